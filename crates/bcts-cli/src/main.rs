@@ -22,11 +22,21 @@ struct Cli {
 #[derive(clap::Subcommand)]
 enum Command {
     Run(RunCommand),
+    Check(CheckCommand),
+    Fmt(FmtCommand),
+    Explain(ExplainCommand),
+    Test(TestCommand),
+    ExportGrammar(ExportGrammarCommand),
+    Lex(LexCommand),
+    Stats(StatsCommand),
+    GraphMetrics(GraphMetricsCommand),
+    List(ListCommand),
+    Migrate(MigrateCommand),
 }
 
 #[derive(clap::Args)]
 struct Args {
-    #[arg(default_value = "config.toml")]
+    #[arg(default_value = bcts::project_config::CONFIG_FILE_NAME)]
     config_path: PathBuf,
 }
 
@@ -34,10 +44,131 @@ struct Args {
 struct RunCommand {
 }
 
+/// Report brace errors, confusable words and whitespace lints in a source file.
+#[derive(clap::Args)]
+struct CheckCommand {
+    path: PathBuf,
+    /// Apply fixes to the file instead of printing them as a diff,
+    /// failing if denied diagnostics remain after them.
+    #[arg(long)]
+    fix: bool,
+}
+
+/// Format source files in place.
+#[derive(clap::Args)]
+struct FmtCommand {
+    /// Source files, or directories to search for `.bct` files.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// Print a diff of unformatted files and fail instead of writing them.
+    #[arg(long)]
+    check: bool,
+}
+
+/// Print the long explanation of a diagnostic code.
+#[derive(clap::Args)]
+struct ExplainCommand {
+    code: String,
+}
+
+/// Run the test blocks in `.bct` files.
+#[derive(clap::Args)]
+struct TestCommand {
+    /// Test files, or directories to search for `.bct` files.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+}
+
+/// Print a syntax grammar for editors.
+#[derive(clap::Args)]
+#[group(required = true, multiple = false)]
+struct ExportGrammarCommand {
+    /// A tree-sitter `grammar.js`.
+    #[arg(long)]
+    tree_sitter: bool,
+    /// A TextMate `.tmLanguage.json`.
+    #[arg(long)]
+    textmate: bool,
+}
+
+/// Print the tokens of a source file, one record per token.
+#[derive(clap::Args)]
+struct LexCommand {
+    path: PathBuf,
+    #[arg(long, value_enum, default_value_t = LexFormat::Tsv)]
+    format: LexFormat,
+}
+
+/// Print token counts, nesting depth, lines and errors of a source file.
+#[derive(clap::Args)]
+struct StatsCommand {
+    path: PathBuf,
+    /// Also print the brace depth of each line and the densest branches.
+    #[arg(long)]
+    complexity: bool,
+}
+
+/// Print fan-in, fan-out, the longest import chain
+/// and unresolved imports of a world.
+#[derive(clap::Args)]
+struct GraphMetricsCommand {
+    /// A world fixture file, as described in `bcts::world_fixture`.
+    fixture: PathBuf,
+}
+
+/// List the modules of the project whose paths match a pattern.
+#[derive(clap::Args)]
+struct ListCommand {
+    /// A glob over `<import space>/<package>/<module>` paths, e.g. `sys/*`.
+    #[arg(default_value = "*")]
+    pattern: String,
+    /// Match the pattern as a regular expression instead.
+    #[arg(long)]
+    regex: bool,
+    /// Directories of system packages.
+    #[arg(long = "sys")]
+    sys_paths: Vec<PathBuf>,
+}
+
+/// Rewrite source files written for an older language version.
+#[derive(clap::Args)]
+struct MigrateCommand {
+    /// Source files, or directories to search for `.bct` files.
+    #[arg(required = true)]
+    paths: Vec<PathBuf>,
+    /// The language version the sources are written for, e.g. `1.0`.
+    #[arg(long, value_parser = parse_language_version)]
+    from: bcts::language::LanguageVersion,
+    /// The language version to migrate to, the latest by default.
+    #[arg(long, value_parser = parse_language_version)]
+    to: Option<bcts::language::LanguageVersion>,
+    /// Write the migrated files instead of printing a diff.
+    #[arg(long)]
+    write: bool,
+}
+
+#[derive(Copy, Clone, clap::ValueEnum)]
+enum LexFormat {
+    /// Tab-separated values, with a header line.
+    Tsv,
+    /// A JSON array of objects.
+    Json,
+}
+
 impl Cli {
     fn run(&self) -> AnyResult<()> {
         match &self.cmd {
             Command::Run(cmd) => cmd.run(&self.args),
+            Command::Check(cmd) => cmd.run(&self.args),
+            Command::Fmt(cmd) => cmd.run(&self.args),
+            Command::Explain(cmd) => cmd.run(&self.args),
+            Command::Test(cmd) => cmd.run(&self.args),
+            Command::ExportGrammar(cmd) => cmd.run(&self.args),
+            Command::Lex(cmd) => cmd.run(&self.args),
+            Command::Stats(cmd) => cmd.run(&self.args),
+            Command::GraphMetrics(cmd) => cmd.run(&self.args),
+            Command::List(cmd) => cmd.run(&self.args),
+            Command::Migrate(cmd) => cmd.run(&self.args),
         }
     }
 }
@@ -49,3 +180,363 @@ impl RunCommand {
         Ok(())
     }
 }
+
+impl CheckCommand {
+    fn run(&self, args: &Args) -> AnyResult<()> {
+        let text = std::fs::read_to_string(&self.path)?;
+        let root = project_root(args);
+        let db = &bcts::Database::default();
+        let config_tree = bcts::project_config::ConfigTree::load(db, root)?;
+        let relative_path = self.path.strip_prefix(root).unwrap_or(&self.path);
+        let config_file = bcts::project_config::effective_config(db, config_tree, relative_path).clone();
+        let severity_config = config_file.severity.clone();
+
+        let mut denied: usize = 0;
+        for layer in config_tree.layers(db) {
+            for diagnostic in bcts::project_config::config_layer_diagnostics(db, *layer) {
+                if let Some(rendered) = diagnostic.render(&severity_config) {
+                    println!("{rendered}");
+                }
+                if severity_config.severity(diagnostic.code) == bcts::diagnostics::Severity::Deny {
+                    denied = denied.checked_add(1).X();
+                }
+            }
+        }
+
+        let project_config = bcts::project_config::ProjectConfig::from_file(db, config_file);
+        let (diagnostics, edits) = check_source(db, project_config, &severity_config, &text);
+        let tab_width = project_config.format(db).tab_width;
+        for diagnostic in &diagnostics {
+            if let Some(rendered) = diagnostic.render_in(&severity_config, &text, tab_width) {
+                println!("{rendered}");
+            }
+        }
+
+        if self.fix {
+            let fixed = bcts::edit::apply_edits(&text, &edits)?;
+            if fixed != text {
+                std::fs::write(&self.path, &fixed)?;
+                println!("fixed {}", self.path.display());
+            }
+            // Only what the fixes left is denied.
+            let (remaining, _) = check_source(db, project_config, &severity_config, &fixed);
+            denied = denied.checked_add(denied_count(&severity_config, &remaining)).X();
+        } else {
+            let path = self.path.to_string_lossy();
+            print!("{}", bcts::diff::unified_diff(&path, &text, &edits)?);
+            denied = denied.checked_add(denied_count(&severity_config, &diagnostics)).X();
+        }
+        if denied > 0 {
+            bail!("{denied} error(s) in {}", self.path.display());
+        }
+
+        Ok(())
+    }
+}
+
+impl FmtCommand {
+    fn run(&self, args: &Args) -> AnyResult<()> {
+        let root = project_root(args);
+        let db = &bcts::Database::default();
+        let config_tree = bcts::project_config::ConfigTree::load(db, root)?;
+
+        let mut files = vec![];
+        for path in &self.paths {
+            collect_bct_files(path, &mut files)?;
+        }
+
+        let mut unformatted : usize = 0;
+        for file in files {
+            let text = std::fs::read_to_string(&file)
+                .with_context(|| format!("reading {}", file.display()))?;
+            let relative_path = file.strip_prefix(root).unwrap_or(&file);
+            let config_file = bcts::project_config::effective_config(db, config_tree, relative_path);
+            let source = bcts::input::Source::new(db, text.clone());
+            let edits = bcts::format::format_edits(db, source, &config_file.format);
+            let formatted = bcts::edit::apply_edits(&text, &edits)?;
+            if formatted == text {
+                continue;
+            }
+            if self.check {
+                print!("{}", bcts::diff::unified_diff(&file.to_string_lossy(), &text, &edits)?);
+                unformatted = unformatted.checked_add(1).X();
+            } else {
+                std::fs::write(&file, formatted)
+                    .with_context(|| format!("writing {}", file.display()))?;
+                println!("formatted {}", file.display());
+            }
+        }
+
+        if unformatted > 0 {
+            bail!("{unformatted} file(s) not formatted");
+        }
+
+        Ok(())
+    }
+}
+
+impl ExplainCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        let code = bcts::diagnostics::Code::parse(&self.code)
+            .ok_or_else(|| anyhow!("unknown diagnostic code `{}`", self.code))?;
+        println!("{} {}\n", code, code.name());
+        print!("{}", code.explanation());
+
+        Ok(())
+    }
+}
+
+impl TestCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        let mut files = vec![];
+        for path in &self.paths {
+            collect_bct_files(path, &mut files)?;
+        }
+
+        let mut passed : usize = 0;
+        let mut failed : usize = 0;
+        for file in files {
+            let text = std::fs::read_to_string(&file)
+                .with_context(|| format!("reading {}", file.display()))?;
+            let outcomes = bcts::test_runner::run_test_file(&text)
+                .with_context(|| format!("in {}", file.display()))?;
+            for outcome in outcomes {
+                let name = format!("{}:{} {}", file.display(), outcome.line, outcome.name);
+                if outcome.passed() {
+                    println!("test {name} ... ok");
+                    passed = passed.checked_add(1).X();
+                } else {
+                    println!("test {name} ... FAILED");
+                    for failure in &outcome.failures {
+                        println!("  {}", failure.replace('\n', "\n  ").trim_end());
+                    }
+                    failed = failed.checked_add(1).X();
+                }
+            }
+        }
+
+        println!("\ntest result: {passed} passed; {failed} failed");
+        if failed > 0 {
+            bail!("{failed} test(s) failed");
+        }
+
+        Ok(())
+    }
+}
+
+impl ExportGrammarCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        if self.tree_sitter {
+            print!("{}", bcts::grammar_export::tree_sitter_grammar());
+        }
+        if self.textmate {
+            print!("{}", bcts::grammar_export::textmate_grammar());
+        }
+
+        Ok(())
+    }
+}
+
+impl LexCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        let text = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("reading {}", self.path.display()))?;
+        let db = &bcts::Database::default();
+        let source = bcts::input::Source::new(db, text);
+        let chunk = bcts::source_map::basic_source_map(db, source);
+        let chunk_lex = bcts::lexer::lex_chunk(db, chunk);
+        match self.format {
+            LexFormat::Tsv => print!("{}", bcts::dump::lex_tsv(db, chunk_lex)),
+            LexFormat::Json => print!("{}", bcts::dump::lex_json(db, chunk_lex)),
+        }
+
+        Ok(())
+    }
+}
+
+impl StatsCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        let text = std::fs::read_to_string(&self.path)
+            .with_context(|| format!("reading {}", self.path.display()))?;
+        let db = &bcts::Database::default();
+        let source = bcts::input::Source::new(db, text);
+        let chunk = bcts::source_map::basic_source_map(db, source);
+        let chunk_lex = bcts::lexer::lex_chunk(db, chunk);
+        let stats = bcts::token_stats::token_stats(db, chunk_lex);
+        println!("lines: {}", stats.lines);
+        println!("tokens: {}", stats.tokens());
+        for (kind, count) in &stats.kinds {
+            println!("  {kind}: {count}");
+        }
+        println!("max depth: {}", stats.max_depth);
+        println!("errors: {}", stats.errors());
+        println!("  brace: {}", stats.brace_errors);
+        println!("  literal: {}", stats.literal_errors);
+
+        if self.complexity {
+            let report = bcts::complexity::complexity_report(db, source);
+            let text = chunk.text(db).as_str(db);
+            let line_index = bcts::line_index::LineIndex::new(text, 1);
+            let width = report.max_depth();
+            println!();
+            println!("depth by line:");
+            for (line, depth) in report.line_depths.iter().enumerate() {
+                let bar = "#".repeat(*depth);
+                let row = format!("{:>6} {bar:<width$} | {}", line.checked_add(1).X(), line_index.line_text(line));
+                println!("{}", row.trim_end());
+            }
+            println!();
+            println!("densest branches:");
+            for branch in report.densest_branches(10) {
+                println!(
+                    "  {}: {} tokens, depth {}",
+                    line_index.line_col(branch.span.start),
+                    branch.tokens,
+                    branch.depth,
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
+impl GraphMetricsCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        let text = std::fs::read_to_string(&self.fixture)
+            .with_context(|| format!("reading {}", self.fixture.display()))?;
+        let db = &bcts::Database::default();
+        let fixture = bcts::world_fixture::WorldFixture::new(db, text);
+        let world = bcts::world_fixture::fixture_world(db, fixture)
+            .map_err(|e| anyhow!("in {}: {e}", self.fixture.display()))?;
+        let graph = bcts::package_resolve2::package_world_graph(
+            db,
+            world.package_world_map(db),
+            world.import_demand_map(db),
+        );
+        let metrics = bcts::graph_metrics::graph_metrics(db, graph);
+        print!("{}", bcts::graph_metrics::graph_metrics_table(db, world.package_world_map(db), metrics));
+
+        Ok(())
+    }
+}
+
+impl ListCommand {
+    fn run(&self, args: &Args) -> AnyResult<()> {
+        let pattern = match self.regex {
+            false => bcts::module_pattern::ModulePattern::glob(&self.pattern)?,
+            true => bcts::module_pattern::ModulePattern::regex(&self.pattern)?,
+        };
+        let root = project_root(args);
+        let config = bcts::project_config::ConfigFile::load(root)?;
+        let loader = bcts::loader::PackageLoader::from_config(root, &config, self.sys_paths.clone());
+        let db = &mut bcts::Database::default();
+        let world = loader.load(db)?.world;
+        let package_world_map = bcts::package2::package_world_map(db, world);
+        for record in bcts::module_pattern::find_modules(db, package_world_map, &pattern) {
+            println!("{}", bcts::module_pattern::module_path(db, &record));
+        }
+
+        Ok(())
+    }
+}
+
+impl MigrateCommand {
+    fn run(&self, _args: &Args) -> AnyResult<()> {
+        let to = self.to.unwrap_or(bcts::language::LanguageVersion::LATEST);
+        let rules = bcts::migrate::migration_rules(&bcts::migrate::migrations(), self.from, to)?;
+
+        let mut files = vec![];
+        for path in &self.paths {
+            collect_bct_files(path, &mut files)?;
+        }
+
+        let db = &bcts::Database::default();
+        for file in files {
+            let text = std::fs::read_to_string(&file)
+                .with_context(|| format!("reading {}", file.display()))?;
+            let source = bcts::input::Source::new(db, text.clone());
+            let edits = bcts::migrate::migration_edits(db, source, &rules);
+            if edits.is_empty() {
+                continue;
+            }
+            if self.write {
+                let migrated = bcts::edit::apply_edits(&text, &edits)?;
+                std::fs::write(&file, migrated)
+                    .with_context(|| format!("writing {}", file.display()))?;
+                println!("migrated {}", file.display());
+            } else {
+                print!("{}", bcts::diff::unified_diff(&file.to_string_lossy(), &text, &edits)?);
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn parse_language_version(version: &str) -> AnyResult<bcts::language::LanguageVersion> {
+    bcts::language::LanguageVersion::parse(version)
+        .ok_or_else(|| anyhow!("expected a version like `1.0`"))
+}
+
+/// The config file's directory, which is the project root.
+fn project_root(args: &Args) -> &std::path::Path {
+    match args.config_path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => std::path::Path::new("."),
+    }
+}
+
+/// `path` if it is a file, or every `.bct` file under it, sorted.
+fn collect_bct_files(path: &std::path::Path, files: &mut Vec<PathBuf>) -> AnyResult<()> {
+    if !path.is_dir() {
+        files.push(path.to_path_buf());
+        return Ok(());
+    }
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    entries.sort();
+    for entry in entries {
+        if entry.is_dir() {
+            collect_bct_files(&entry, files)?;
+        } else if entry.extension().is_some_and(|extension| extension == "bct") {
+            files.push(entry);
+        }
+    }
+    Ok(())
+}
+
+/// Brace errors, confusable words and whitespace lints in `text`,
+/// and the fixes for them, less those of allowed lints.
+fn check_source(
+    db: &bcts::Database,
+    project_config: bcts::project_config::ProjectConfig,
+    severity_config: &bcts::diagnostics::SeverityConfig,
+    text: &str,
+) -> (Vec<bcts::diagnostics::Diagnostic>, Vec<bcts::edit::TextEdit>) {
+    let source = bcts::input::Source::new(db, S(text));
+    let source_map_config = bcts::project_config::project_source_map_config(db, project_config);
+    let chunk = bcts::source_map::source_map(db, source, source_map_config);
+    let lexer_config = bcts::project_config::project_lexer_config(db, project_config);
+    let chunk_lex = bcts::lexer::lex_chunk_with_config(db, chunk, lexer_config);
+    let bracer = bcts::bracer::bracer(db, chunk_lex);
+
+    let lints = bcts::lints::whitespace_lints(db, bracer);
+    let diagnostics = bcts::diagnostics::bracer_diagnostics(db, bracer).into_iter()
+        .chain(bcts::confusables::confusable_diagnostics(db, bracer))
+        .chain(lints.iter().map(|lint| lint.diagnostic.clone()))
+        .collect();
+    let mut edits = bcts::fixes::bracer_fixes(db, bracer);
+    edits.extend(bcts::lints::lint_fixes(&lints, severity_config));
+    (diagnostics, edits)
+}
+
+fn denied_count(
+    severity_config: &bcts::diagnostics::SeverityConfig,
+    diagnostics: &[bcts::diagnostics::Diagnostic],
+) -> usize {
+    diagnostics.iter()
+        .filter(|diagnostic| severity_config.severity(diagnostic.code) == bcts::diagnostics::Severity::Deny)
+        .count()
+}
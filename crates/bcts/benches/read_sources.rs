@@ -0,0 +1,42 @@
+//! Compares reading a large data document with `fs::read_to_string`
+//! to `bcts::mmap::read_to_string`, which maps it with the `mmap` feature.
+//!
+//!     cargo bench -p bcts --features mmap --bench read_sources
+//!
+//! The size in megabytes is `BCTS_BENCH_MB`, 256 by default.
+//! The file is written once and read several times,
+//! so it is in the page cache, as for a file read again by an editor.
+
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 5;
+
+fn main() {
+    let megabytes: usize = std::env::var("BCTS_BENCH_MB").ok()
+        .map(|mb| mb.parse().expect("BCTS_BENCH_MB must be a number"))
+        .unwrap_or(256);
+    let dir = rmx::tempfile::tempdir().unwrap();
+    let path = dir.path().join("data.bct");
+    let line = ": map<i64, f32> / map {1 = 2.5, 300 = 1.0}\n";
+    let bytes = megabytes.checked_mul(1024 * 1024).expect("BCTS_BENCH_MB too large");
+    let text = line.repeat(bytes.checked_div(line.len()).unwrap());
+    std::fs::write(&path, &text).unwrap();
+    drop(text);
+
+    println!("{megabytes} MB, mmap feature {}", cfg!(feature = "mmap"));
+    bench("fs::read_to_string", || std::fs::read_to_string(&path).unwrap().len());
+    bench("mmap::read_to_string", || bcts::mmap::read_to_string(&path).unwrap().len());
+    bench("mmap::with_file_bytes", || {
+        bcts::mmap::with_file_bytes(&path, |bytes| std::str::from_utf8(bytes).unwrap().len()).unwrap()
+    });
+}
+
+fn bench(name: &str, mut read: impl FnMut() -> usize) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let start = Instant::now();
+        std::hint::black_box(read());
+        best = best.min(start.elapsed());
+    }
+    println!("{name:<24} best of {RUNS}: {best:?}");
+}
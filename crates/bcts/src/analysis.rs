@@ -0,0 +1,234 @@
+//! The whole analysis of a source, as far as it gets.
+//!
+//! The passes assume the output of the pass before meets its invariants,
+//! and panic, with `X()` or `bug!()`, when it doesn't.
+//! `analyze` runs the checked variant of each pass instead,
+//! `try_basic_source_map`, `try_lex_chunk` and `try_bracer`,
+//! and stops at the first phase whose output is broken,
+//! keeping the phases before it.
+//! A server analyzing many files can then report
+//! an internal error for one file and carry on with the rest.
+//!
+//! This guards each pass against its input, not against itself:
+//! a pass that panics on input meeting its invariants still panics.
+
+use rmx::prelude::*;
+
+use rmx::std::fmt;
+
+use crate::bracer::{Bracer, BracerInvariantError};
+use crate::chunk::{Chunk, ChunkInvariantError};
+use crate::diagnostics::Diagnostic;
+use crate::input::Source;
+use crate::lexer::{ChunkLex, LexInvariantError};
+
+/// A phase of `analyze`, in order.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub enum Phase {
+    SourceMap,
+    Lex,
+    Bracer,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::SourceMap => "source map",
+            Phase::Lex => "lex",
+            Phase::Bracer => "bracer",
+        }
+    }
+}
+
+/// The broken invariant a phase's output was found with.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum PhaseError {
+    SourceMap(ChunkInvariantError),
+    Lex(LexInvariantError),
+    Bracer(BracerInvariantError),
+}
+
+impl PhaseError {
+    pub fn phase(&self) -> Phase {
+        match self {
+            PhaseError::SourceMap(_) => Phase::SourceMap,
+            PhaseError::Lex(_) => Phase::Lex,
+            PhaseError::Bracer(_) => Phase::Bracer,
+        }
+    }
+}
+
+impl fmt::Display for PhaseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = self.phase().as_str();
+        match self {
+            PhaseError::SourceMap(error) => write!(f, "internal error in {phase}: {error:?}"),
+            PhaseError::Lex(error) => write!(f, "internal error in {phase}: {error:?}"),
+            PhaseError::Bracer(error) => write!(f, "internal error in {phase}: {error:?}"),
+        }
+    }
+}
+
+impl rmx::std::error::Error for PhaseError { }
+
+/// The output of each phase that succeeded.
+///
+/// A phase's output is `None` if it or an earlier phase failed.
+#[derive(Clone, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct AnalysisResult<'db> {
+    pub chunk: Option<Chunk<'db>>,
+    pub chunk_lex: Option<ChunkLex<'db>>,
+    pub bracer: Option<Bracer<'db>>,
+    /// Token, brace and confusable diagnostics, in source order,
+    /// if every phase succeeded.
+    pub diagnostics: Vec<Diagnostic>,
+    /// The phase that failed, if any.
+    pub error: Option<PhaseError>,
+}
+
+impl<'db> AnalysisResult<'db> {
+    pub fn failed_phase(&self) -> Option<Phase> {
+        self.error.as_ref().map(PhaseError::phase)
+    }
+}
+
+/// Analyze `source` through every phase, see the module docs.
+#[salsa::tracked(returns(ref))]
+pub fn analyze<'db>(
+    db: &'db dyn crate::Db,
+    source: Source,
+) -> AnalysisResult<'db> {
+    let mut result = AnalysisResult {
+        chunk: None,
+        chunk_lex: None,
+        bracer: None,
+        diagnostics: vec![],
+        error: None,
+    };
+
+    let chunk = match crate::source_map::try_basic_source_map(db, source) {
+        Ok(chunk) => chunk,
+        Err(error) => {
+            result.error = Some(PhaseError::SourceMap(error));
+            return result;
+        }
+    };
+    result.chunk = Some(chunk);
+
+    let chunk_lex = match crate::lexer::try_lex_chunk(db, chunk) {
+        Ok(chunk_lex) => chunk_lex,
+        Err(error) => {
+            result.error = Some(PhaseError::Lex(error));
+            return result;
+        }
+    };
+    result.chunk_lex = Some(chunk_lex);
+
+    let bracer = match crate::bracer::try_bracer(db, chunk_lex) {
+        Ok(bracer) => bracer,
+        Err(error) => {
+            result.error = Some(PhaseError::Bracer(error));
+            return result;
+        }
+    };
+    result.bracer = Some(bracer);
+
+    let mut diagnostics = crate::diagnostics::bracer_diagnostics(db, bracer);
+    diagnostics.extend(crate::confusables::confusable_diagnostics(db, bracer));
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.C().map(|span| (span.start, span.end)));
+    result.diagnostics = diagnostics;
+    result
+}
+
+#[test]
+fn test_analyze() {
+    use crate::diagnostics::Code;
+
+    let ref db = crate::Database::default();
+    let analyzed = |text: &str| {
+        let result = analyze(db, Source::new(db, S(text)));
+        assert_eq!(result.error, None, "{text:?}");
+        assert!(result.chunk.is_some() && result.chunk_lex.is_some() && result.bracer.is_some());
+        result.diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<_>>()
+    };
+
+    assert_eq!(analyzed(""), vec![]);
+    assert_eq!(analyzed("f(a, [b]) = \"s\" // c\n"), vec![]);
+    assert_eq!(analyzed("f(a]"), vec![Code::UnclosedBrace, Code::UnmatchedClose]);
+    assert_eq!(analyzed("s = \"abc\nрaypal"), vec![Code::UnterminatedToken]);
+    assert_eq!(analyzed("x = рaypal"), vec![Code::MixedScriptIdentifier]);
+
+    // Every seed case of the bracer panic tests.
+    for text in [
+        r#": @!@@u64] / @[: @u64 / @19, : @u64 / @19]"#,
+        r#": ##i16) / #(: #i16 / #35)"#,
+        r#": @map<@bool, @!@u64> /} @map {: @bool / @false = : @!@u64 / @254}"#,
+        r#": #data / #data : #(#u)64, #bool) / #(: #u64 / #56, : #bool / #false)"#,
+    ] {
+        analyzed(text);
+    }
+}
+
+#[test]
+fn test_phase_error() {
+    let error = PhaseError::Lex(LexInvariantError::Gap { offset: 3 });
+    assert_eq!(error.phase(), Phase::Lex);
+    assert_eq!(error.to_string(), "internal error in lex: Gap { offset: 3 }");
+}
+
+/// The checks of `text`'s chunk, tokens and brace tree,
+/// each broken on purpose.
+#[cfg(test)]
+#[salsa::tracked]
+fn broken_checks(
+    db: &dyn crate::Db,
+    source: Source,
+) -> (
+    Result<(), ChunkInvariantError>,
+    Result<(), LexInvariantError>,
+    Result<(), BracerInvariantError>,
+) {
+    use crate::lexer::Sigil;
+
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let text = chunk.text(db);
+    let broken_chunk = Chunk::new(
+        db,
+        text,
+        vec![],
+        vec![0..2, 1..3],
+        vec![],
+        vec![],
+        vec![],
+        chunk.kind(db),
+    );
+
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    let mut tokens = chunk_lex.tokens(db).clone();
+    tokens.remove(1);
+    let broken_chunk_lex = ChunkLex::new(db, chunk, tokens, vec![]);
+
+    let bracer = crate::bracer::bracer(db, chunk_lex);
+    let broken_bracer = Bracer::new(
+        db,
+        chunk_lex,
+        bracer.branches(db).clone(),
+        vec![],
+        vec![(0, Sigil::ParenClose)],
+        vec![],
+    );
+
+    (broken_chunk.check(db), broken_chunk_lex.check(db), broken_bracer.check(db))
+}
+
+#[test]
+fn test_invariant_checks() {
+    let ref db = crate::Database::default();
+    let (chunk, chunk_lex, bracer) = broken_checks(db, Source::new(db, S("f(a) b")));
+    assert_eq!(chunk, Err(ChunkInvariantError::InvalidRange { range: 1..3 }));
+    assert_eq!(chunk_lex, Err(LexInvariantError::Gap { offset: 1 }));
+    assert_eq!(bracer, Err(BracerInvariantError::InvalidRemovedClose { index: 0 }));
+}
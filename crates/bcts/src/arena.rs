@@ -0,0 +1,240 @@
+//! Owned copies of the token tree, independent of the database.
+//!
+//! `export_arena` copies a source's tokens and brace tree
+//! into a `TreeArena`: a few flat vectors addressed by `u32` indices,
+//! with no references into salsa storage.
+//! The arena is `Send` and `Sync`, so it can outlive the `Database`
+//! and be shared between threads for post-processing.
+//!
+//! Nodes are stored in preorder.
+//! A branch's descendants follow it directly and end at its `end`,
+//! so walking children skips each child's subtree in one step.
+
+use rmx::prelude::*;
+
+use rmx::std::ops::Range;
+
+use crate::bracer::{BracerIter, TreeToken};
+use crate::input::Source;
+use crate::lexer::{Sigil, TokenKind};
+
+/// Index of a token in `TreeArena::tokens`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct TokenId(pub u32);
+
+/// Index of a node in `TreeArena::nodes`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct NodeId(pub u32);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ArenaToken {
+    pub kind: TokenKind,
+    /// Byte span in `TreeArena::text`.
+    pub span: Range<u32>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ArenaNode {
+    Token(TokenId),
+    Branch {
+        open_sigil: Sigil,
+        /// Byte span from the open sigil through the close sigil,
+        /// or through the last token if the bracer inserted the close.
+        span: Range<u32>,
+        /// One past the last descendant.
+        end: NodeId,
+    },
+}
+
+/// An owned token tree. See the module docs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TreeArena {
+    pub text: String,
+    pub tokens: Vec<ArenaToken>,
+    pub nodes: Vec<ArenaNode>,
+}
+
+/// Copy the tokens and brace tree of `source` into an arena.
+///
+/// Panics if the source is 4 GiB or larger.
+pub fn export_arena(db: &dyn crate::Db, source: Source) -> TreeArena {
+    let bracer = crate::bracer::basic_bracer(db, source);
+    let chunk_lex = bracer.chunk(db);
+
+    let mut arena = TreeArena {
+        text: source.text(db).C(),
+        tokens: Vec::with_capacity(chunk_lex.tokens(db).len()),
+        nodes: vec![],
+    };
+    for token in chunk_lex.tokens(db) {
+        arena.tokens.push(ArenaToken {
+            kind: token.kind(db),
+            span: to_u32_range(token.text(db).range(db)),
+        });
+    }
+
+    add_nodes(db, &mut arena, chunk_lex.tokens(db), bracer.iter(db));
+    arena
+}
+
+fn add_nodes<'db>(
+    db: &'db dyn crate::Db,
+    arena: &mut TreeArena,
+    tokens: &[crate::lexer::Token<'db>],
+    iter: BracerIter<'db>,
+) {
+    for tree_token in iter {
+        match tree_token {
+            TreeToken::Token(token) => {
+                let index = tokens.partition_point(|t| t.text(db).range(db).start < token.text(db).range(db).start);
+                arena.nodes.push(ArenaNode::Token(TokenId(to_u32(index))));
+            }
+            TreeToken::Branch(open_sigil, branch_iter) => {
+                let span = branch_iter.text_span().X().span;
+                let node = arena.nodes.len();
+                arena.nodes.push(ArenaNode::Branch {
+                    open_sigil,
+                    span: to_u32_range(span),
+                    end: NodeId(0),
+                });
+                rmx::extras::recurse(|| {
+                    add_nodes(db, arena, tokens, branch_iter)
+                });
+                let end_id = NodeId(to_u32(arena.nodes.len()));
+                let ArenaNode::Branch { end, .. } = &mut arena.nodes[node] else {
+                    unreachable!();
+                };
+                *end = end_id;
+            }
+        }
+    }
+}
+
+fn to_u32(n: usize) -> u32 {
+    u32::try_from(n).expect("source too large for arena")
+}
+
+fn to_u32_range(range: Range<usize>) -> Range<u32> {
+    to_u32(range.start)..to_u32(range.end)
+}
+
+impl TreeArena {
+    pub fn token(&self, id: TokenId) -> &ArenaToken {
+        &self.tokens[id.0 as usize]
+    }
+
+    pub fn token_text(&self, id: TokenId) -> &str {
+        let span = &self.token(id).span;
+        &self.text[span.start as usize..span.end as usize]
+    }
+
+    pub fn node(&self, id: NodeId) -> &ArenaNode {
+        &self.nodes[id.0 as usize]
+    }
+
+    /// The top-level nodes.
+    pub fn roots(&self) -> Children<'_> {
+        Children {
+            arena: self,
+            next: 0,
+            end: to_u32(self.nodes.len()),
+        }
+    }
+
+    /// The children of a branch; empty for a token.
+    pub fn children(&self, id: NodeId) -> Children<'_> {
+        let end = match self.node(id) {
+            ArenaNode::Token(_) => id.0.checked_add(1).X(),
+            ArenaNode::Branch { end, .. } => end.0,
+        };
+        Children {
+            arena: self,
+            next: id.0.checked_add(1).X(),
+            end,
+        }
+    }
+}
+
+/// Iterator over sibling nodes, created by `TreeArena::roots`
+/// and `TreeArena::children`.
+#[derive(Clone)]
+pub struct Children<'a> {
+    arena: &'a TreeArena,
+    next: u32,
+    end: u32,
+}
+
+impl<'a> Iterator for Children<'a> {
+    type Item = NodeId;
+
+    fn next(&mut self) -> Option<NodeId> {
+        if self.next >= self.end {
+            return None;
+        }
+        let id = NodeId(self.next);
+        self.next = match self.arena.node(id) {
+            ArenaNode::Token(_) => self.next.checked_add(1).X(),
+            ArenaNode::Branch { end, .. } => end.0,
+        };
+        Some(id)
+    }
+}
+
+#[test]
+fn test_export_arena() {
+    fn assert_send_sync<T: Send + Sync>() { }
+    assert_send_sync::<TreeArena>();
+
+    // Render like `dump::tree_dump`.
+    fn render(arena: &TreeArena, nodes: Children<'_>, depth: usize, buf: &mut String) {
+        let indent = "  ".repeat(depth);
+        for id in nodes {
+            match arena.node(id) {
+                ArenaNode::Token(token_id) => {
+                    let token = arena.token(*token_id);
+                    buf.push_str(&format!(
+                        "{indent}{:?} {}..{} {:?}\n",
+                        token.kind, token.span.start, token.span.end, arena.token_text(*token_id),
+                    ));
+                }
+                ArenaNode::Branch { open_sigil, .. } => {
+                    buf.push_str(&format!("{indent}{}\n", open_sigil.as_str()));
+                    render(arena, arena.children(id), depth.checked_add(1).X(), buf);
+                    buf.push_str(&format!("{indent}{}\n", open_sigil.close_sigil().as_str()));
+                }
+            }
+        }
+    }
+
+    for text in ["", "a b", "f(a, [b {c}]) d", "(a}b\n{[}\n)\n(\n", "a § b]"] {
+        let arena = {
+            let ref db = crate::Database::default();
+            let source = Source::new(db, S(text));
+            let arena = export_arena(db, source);
+
+            let bracer = crate::bracer::basic_bracer(db, source);
+            let mut rendered = String::new();
+            render(&arena, arena.roots(), 0, &mut rendered);
+            assert_eq!(rendered, crate::dump::tree_dump(db, bracer), "{text:?}");
+            arena
+        };
+
+        // Still usable with the database gone, on another thread.
+        let handle = rmx::std::thread::spawn(move || arena.roots().count());
+        handle.join().unwrap();
+    }
+
+    let ref db = crate::Database::default();
+    let arena = export_arena(db, Source::new(db, S("f(a)")));
+    let roots: Vec<_> = arena.roots().collect();
+    assert_eq!(roots, vec![NodeId(0), NodeId(1)]);
+    assert_eq!(arena.node(NodeId(1)), &ArenaNode::Branch {
+        open_sigil: Sigil::ParenOpen,
+        span: 1..4,
+        end: NodeId(3),
+    });
+    let children: Vec<_> = arena.children(NodeId(1)).collect();
+    assert_eq!(children, vec![NodeId(2)]);
+    let ArenaNode::Token(token) = arena.node(NodeId(2)) else { panic!() };
+    assert_eq!(arena.token_text(*token), "a");
+}
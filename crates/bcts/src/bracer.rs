@@ -21,6 +21,7 @@ pub struct Bracer<'db> {
 }
 
 #[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
 pub struct Branch {
     real_token_range: Range<usize>,
     branches: usize,
@@ -29,6 +30,9 @@ pub struct Branch {
     errors: usize,
     open_sigil: Sigil,
     close_sigil: Sigil,
+    /// Whether the branch ends with its own close,
+    /// rather than being closed by the bracer.
+    closed: bool,
 }
 
 impl<'db> Bracer<'db> {
@@ -70,11 +74,15 @@ impl<'db> Iterator for BracerIter<'db> {
 
     fn next(&mut self) -> Option<TreeToken<'db>> {
         let res = self.next2();
-        debug!("next: {:?}", match res.as_ref() {
-            None => "none",
-            Some(TreeToken::Token(t)) => t.text(self.db).as_str(self.db),
-            Some(TreeToken::Branch(..)) => "branch",
-        });
+        tracing::trace!(
+            target: crate::VERBOSE_TARGET,
+            next = match res.as_ref() {
+                None => "none",
+                Some(TreeToken::Token(t)) => t.text(self.db).as_str(self.db),
+                Some(TreeToken::Branch(..)) => "branch",
+            },
+            "bracer iter next",
+        );
         res
     }
 }
@@ -83,31 +91,31 @@ impl<'db> BracerIter<'db> {
     /// Get source Text and byte span for this branch, including delimiters.
     ///
     /// Returns None for top-level iterators (which have no enclosing braces).
-    pub fn text_span(&self) -> Option<crate::text::TextSpan<'db>> {
+    pub fn text_span(&self) -> Option<crate::span::TextSpan<'db>> {
         let tokens = self.tree.chunk(self.db).tokens(self.db);
         // real_token_range starts AFTER the open brace, so go back 1 for open brace.
         let open_idx = self.real_token_range.start.checked_sub(1)?;
         let close_idx = self.real_token_range.end.checked_sub(1)?;
         let open_token = tokens.get(open_idx)?;
         let close_token = tokens.get(close_idx)?;
-        let text = open_token.text(self.db).text(self.db);
-        let span = open_token.text(self.db).range(self.db).start
-                 ..close_token.text(self.db).range(self.db).end;
-        Some(crate::text::TextSpan::new(text, span))
+        open_token.text(self.db).text_span(self.db)
+            .merge(&close_token.text(self.db).text_span(self.db))
     }
 
     fn next2(&mut self) -> Option<TreeToken<'db>> {
         loop {
-            debug!("--");
-            debug!("real token range {:?}", self.real_token_range.C());
-            debug!("next branches {:?}", self.branches.C());
-            debug!("inserted closes {:?}", self.inserted_closes.C());
-            debug!("removed closes {:?}", self.removed_closes.C());
-            debug!("next token index {:?}", self.next_token_index);
-            debug!("next branch index {:?}", self.next_branch_index);
-            debug!("next inserted close index {:?}", self.next_inserted_close_index);
-            debug!("next removed close index {:?}", self.next_removed_close_index);
-            debug!("--");
+            tracing::trace!(
+                target: crate::VERBOSE_TARGET,
+                real_token_range = ?self.real_token_range,
+                branches = ?self.branches,
+                inserted_closes = ?self.inserted_closes,
+                removed_closes = ?self.removed_closes,
+                next_token_index = self.next_token_index,
+                next_branch_index = self.next_branch_index,
+                next_inserted_close_index = self.next_inserted_close_index,
+                next_removed_close_index = self.next_removed_close_index,
+                "bracer iter step",
+            );
 
             let tokens = &self.tree.chunk(self.db).tokens(self.db)
                 [self.real_token_range.C()];
@@ -149,10 +157,12 @@ impl<'db> BracerIter<'db> {
                     match self.next_token_index.cmp(&next_removed_close.0) {
                         Ordering::Less => {
                             self.next_token_index = self.next_token_index.checked_add(1).X();
+                            // A close that wasn't removed ends the branch
+                            // being iterated, and is skipped as in the arm above.
                             if !next_token.is_close_sigil(self.db) {
                                 Some(TreeToken::Token(*next_token))
                             } else {
-                                panic!("is this possible?")
+                                continue;
                             }
                         }
                         Ordering::Equal => {
@@ -193,7 +203,11 @@ impl<'db> BracerIter<'db> {
                                 }
                             );
 
-                            debug!("sbi {:#?}", Range::from_start_len(self.next_branch_index, next_branch.branches).X());
+                            tracing::trace!(
+                                target: crate::VERBOSE_TARGET,
+                                branches = ?Range::from_start_len(self.next_branch_index, next_branch.branches).X(),
+                                "bracer iter enter branch",
+                            );
 
                             self.next_token_index = next_branch.real_token_range.end;
                             self.next_branch_index = self.next_branch_index
@@ -242,208 +256,540 @@ pub enum TreeToken<'db> {
     Branch(Sigil, BracerIter<'db>),
 }
 
+/// Pair up the open and close sigils of `chunk` into branches.
+///
+/// A chunk without tokens has an empty tree,
+/// and a branch with nothing between its sigils,
+/// like `()`, iterates nothing.
+/// Whitespace tokens, including whitespace-only chunks,
+/// are kept like any other token.
 #[salsa::tracked]
 pub fn bracer<'db>(
     db: &'db dyn crate::Db,
     chunk: ChunkLex<'db>
 ) -> Bracer<'db> {
-    let tokens = chunk.tokens(db).iter().enumerate();
-
-    #[derive(Default, Debug)]
-    pub struct BraceMap {
-        branches: Vec<Branch>,
-        inserted_closes: Vec<(usize, Sigil)>,
-        removed_closes: Vec<(usize, Sigil)>,
-        errors: Vec<(Range<usize>, Sigil)>,
+    let _span = tracing::debug_span!("bracer", tokens = chunk.tokens(db).len()).entered();
+    crate::metrics::record(|metrics| metrics.query_executed("bracer"));
+    let kinds = chunk.tokens(db).iter().map(|token| token.kind(db)).enumerate();
+    let top_map = BraceMap::brace(kinds, chunk.tokens(db).len());
+
+    tracing::debug!(
+        branches = top_map.branches.len(),
+        inserted_closes = top_map.inserted_closes.len(),
+        removed_closes = top_map.removed_closes.len(),
+        errors = top_map.errors.len(),
+        "braced",
+    );
+
+    Bracer::new(
+        db,
+        chunk,
+        top_map.branches,
+        top_map.inserted_closes,
+        top_map.removed_closes,
+        top_map.errors,
+    )
+}
+
+/// The `bracer` of `source` with `basic_source_map` and `lex_chunk`.
+#[salsa::tracked]
+pub fn basic_bracer<'db>(
+    db: &'db dyn crate::Db,
+    source: crate::input::Source,
+) -> Bracer<'db> {
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    bracer(db, chunk_lex)
+}
+
+/// The `basic_bracer` of a new source of `text`.
+#[cfg(test)]
+pub(crate) fn bracer_for_str<'db>(
+    db: &'db dyn crate::Db,
+    text: &str,
+) -> Bracer<'db> {
+    basic_bracer(db, crate::input::Source::new(db, S(text)))
+}
+
+/// Whether every open sigil of `chunk` is closed by its own close,
+/// that is whether its `bracer` would have no errors.
+pub fn is_balanced<'db>(
+    db: &'db dyn crate::Db,
+    chunk: ChunkLex<'db>
+) -> bool {
+    balance(db, chunk) == Balance::Balanced
+}
+
+/// How the sigils of a chunk pair up.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum Balance {
+    /// Every open is closed by its own close.
+    Balanced,
+    /// Every close matches the innermost open, but some opens are not closed.
+    ///
+    /// More tokens could balance the chunk.
+    Unclosed,
+    /// Some close doesn't match the innermost open.
+    Unmatched,
+}
+
+/// How the sigils of `chunk` pair up.
+///
+/// This only keeps a stack of the open sigils,
+/// for callers that don't need the branches of a `bracer`.
+/// `bracer`, checked by `Bracer::check`.
+#[salsa::tracked]
+pub fn try_bracer<'db>(
+    db: &'db dyn crate::Db,
+    chunk: ChunkLex<'db>,
+) -> Result<Bracer<'db>, BracerInvariantError> {
+    let bracer = bracer(db, chunk);
+    bracer.check(db)?;
+    Ok(bracer)
+}
+
+/// A broken invariant of a `Bracer`, found by `Bracer::check`.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum BracerInvariantError {
+    /// A branch's tokens are out of range, out of order,
+    /// or don't start with its open sigil,
+    /// or its nested branches are not inside it.
+    InvalidBranch { branch: usize },
+    /// An inserted close is out of range or out of order.
+    InvalidInsertedClose { index: usize },
+    /// A removed close is out of range or out of order,
+    /// or not at a close of its sigil.
+    InvalidRemovedClose { index: usize },
+    /// An error's tokens are empty or out of range.
+    InvalidError { index: usize },
+}
+
+impl<'db> Bracer<'db> {
+    /// Check the invariants `BracerIter` relies on.
+    ///
+    /// `bracer` always returns a tree meeting them.
+    pub fn check(&self, db: &'db dyn crate::Db) -> Result<(), BracerInvariantError> {
+        let tokens = self.chunk(db).tokens(db);
+        let branches = self.branches(db);
+        let mut start = None;
+        for (index, branch) in branches.iter().enumerate() {
+            let range = &branch.real_token_range;
+            let nested = index.checked_add(1).X()..index.checked_add(1).X().saturating_add(branch.branches);
+            let valid = start < Some(range.start)
+                && range.start < range.end
+                && range.end <= tokens.len()
+                && tokens[range.start].kind(db) == TokenKind::Sigil(branch.open_sigil)
+                && nested.end <= branches.len()
+                && branches[nested].iter().all(|nested| {
+                    nested.real_token_range.start > range.start && nested.real_token_range.end <= range.end
+                });
+            if !valid {
+                return Err(BracerInvariantError::InvalidBranch { branch: index });
+            }
+            start = Some(range.start);
+        }
+
+        let mut previous = 0;
+        for (index, (token_index, _)) in self.inserted_closes(db).iter().enumerate() {
+            if *token_index < previous || *token_index > tokens.len() {
+                return Err(BracerInvariantError::InvalidInsertedClose { index });
+            }
+            previous = *token_index;
+        }
+
+        let mut next = 0;
+        for (index, (token_index, sigil)) in self.removed_closes(db).iter().enumerate() {
+            let valid = *token_index >= next
+                && *token_index < tokens.len()
+                && tokens[*token_index].kind(db) == TokenKind::Sigil(*sigil);
+            if !valid {
+                return Err(BracerInvariantError::InvalidRemovedClose { index });
+            }
+            next = token_index.checked_add(1).X();
+        }
+
+        for (index, (token_range, _)) in self.errors(db).iter().enumerate() {
+            if token_range.start >= token_range.end || token_range.end > tokens.len() {
+                return Err(BracerInvariantError::InvalidError { index });
+            }
+        }
+        Ok(())
     }
+}
 
-    impl BraceMap {
-        fn append(&mut self, other: BraceMap) {
-            self.branches.extend(other.branches);
-            self.inserted_closes.extend(other.inserted_closes);
-            self.removed_closes.extend(other.removed_closes);
-            self.errors.extend(other.errors);
+#[salsa::tracked]
+pub fn balance<'db>(
+    db: &'db dyn crate::Db,
+    chunk: ChunkLex<'db>
+) -> Balance {
+    crate::metrics::record(|metrics| metrics.query_executed("balance"));
+    let mut open = vec![];
+    for token in chunk.tokens(db) {
+        match token.kind(db) {
+            TokenKind::Sigil(
+                sigil @ (Sigil::ParenOpen | Sigil::BraceOpen | Sigil::BracketOpen | Sigil::AngleOpen)
+            ) => {
+                open.push(sigil.close_sigil());
+            }
+            // A close sigil that matches pops its opener and falls through.
+            TokenKind::Sigil(sigil) if sigil.is_close_sigil() && open.pop() != Some(sigil) => {
+                return Balance::Unmatched;
+            }
+            _ => {}
         }
     }
+    match open.is_empty() {
+        true => Balance::Balanced,
+        false => Balance::Unclosed,
+    }
+}
+
+/// The brace structure of a token sequence, as stored in a `Bracer`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct BraceMap {
+    /// Every branch, in preorder.
+    pub branches: Vec<Branch>,
+    pub inserted_closes: Vec<(usize, Sigil)>,
+    pub removed_closes: Vec<(usize, Sigil)>,
+    pub errors: Vec<(Range<usize>, Sigil)>,
+}
 
-    let mut top_map = BraceMap::default();
-    let mut stack: Vec<(usize, Sigil, BraceMap)> = vec![];
-
-    let mut close_brace =
-        |
-    stack: &mut Vec<(usize, Sigil, BraceMap)>,
-    index: usize,
-    open_s: Sigil,
-    close_s: Sigil
-        | {
-            let seen_open = stack.iter().any(|(_, sigil, _)| *sigil == open_s);
-            if seen_open {
-                loop {
-                    let (open_index, open_sigil, mut brace_map) = stack.pop().X();
+impl BraceMap {
+    pub fn new(kinds: &[TokenKind]) -> BraceMap {
+        BraceMap::brace(kinds.iter().copied().enumerate(), kinds.len())
+    }
+
+    /// Brace the tokens of `kinds`, which are numbered up to `num_tokens`.
+    fn brace(
+        kinds: impl Iterator<Item = (usize, TokenKind)>,
+        num_tokens: usize,
+    ) -> BraceMap {
+        let mut top_map = BraceMap::default();
+        let mut stack: Vec<(usize, Sigil, BraceMap)> = vec![];
+
+        let mut close_brace =
+            |
+        stack: &mut Vec<(usize, Sigil, BraceMap)>,
+        index: usize,
+        open_s: Sigil,
+        close_s: Sigil
+            | {
+                let seen_open = stack.iter().any(|(_, sigil, _)| *sigil == open_s);
+                if seen_open {
+                    loop {
+                        let (open_index, open_sigil, mut brace_map) = stack.pop().X();
+                        let mut parent_brace_map = stack.last_mut()
+                            .map(|(_, _, brace_map)| brace_map)
+                            .unwrap_or(&mut top_map);
+                        if open_sigil == open_s {
+                            parent_brace_map.branches.push(Branch {
+                                real_token_range: open_index..index.checked_add(1).X(),
+                                branches: brace_map.branches.len(),
+                                inserted_closes: brace_map.inserted_closes.len(),
+                                removed_closes: brace_map.removed_closes.len(),
+                                errors: brace_map.errors.len(),
+                                open_sigil: open_s,
+                                close_sigil: close_s,
+                                closed: true,
+                            });
+                            parent_brace_map.append(brace_map);
+                            break;
+                        } else if open_sigil == Sigil::ParenOpen {
+                            brace_map.inserted_closes.push((index, Sigil::ParenClose));
+                            brace_map.errors.push((
+                                open_index..index,
+                                Sigil::ParenOpen,
+                            ));
+                            parent_brace_map.branches.push(Branch {
+                                real_token_range: open_index..index,
+                                branches: brace_map.branches.len(),
+                                inserted_closes: brace_map.inserted_closes.len(),
+                                removed_closes: brace_map.removed_closes.len(),
+                                errors: brace_map.errors.len(),
+                                open_sigil: Sigil::ParenOpen,
+                                close_sigil: Sigil::ParenClose,
+                                closed: false,
+                            });
+                            parent_brace_map.append(brace_map);
+                        } else if open_sigil == Sigil::BraceOpen {
+                            brace_map.inserted_closes.push((index, Sigil::BraceClose));
+                            brace_map.errors.push((
+                                open_index..index,
+                                Sigil::BraceOpen,
+                            ));
+                            parent_brace_map.branches.push(Branch {
+                                real_token_range: open_index..index,
+                                branches: brace_map.branches.len(),
+                                inserted_closes: brace_map.inserted_closes.len(),
+                                removed_closes: brace_map.removed_closes.len(),
+                                errors: brace_map.errors.len(),
+                                open_sigil: Sigil::BraceOpen,
+                                close_sigil: Sigil::BraceClose,
+                                closed: false,
+                            });
+                            parent_brace_map.append(brace_map);
+                        } else if open_sigil == Sigil::BracketOpen {
+                            brace_map.inserted_closes.push((index, Sigil::BracketClose));
+                            brace_map.errors.push((
+                                open_index..index,
+                                Sigil::BracketOpen,
+                            ));
+                            parent_brace_map.branches.push(Branch {
+                                real_token_range: open_index..index,
+                                branches: brace_map.branches.len(),
+                                inserted_closes: brace_map.inserted_closes.len(),
+                                removed_closes: brace_map.removed_closes.len(),
+                                errors: brace_map.errors.len(),
+                                open_sigil: Sigil::BracketOpen,
+                                close_sigil: Sigil::BracketClose,
+                                closed: false,
+                            });
+                            parent_brace_map.append(brace_map);
+                        } else if open_sigil == Sigil::AngleOpen {
+                            brace_map.inserted_closes.push((index, Sigil::AngleClose));
+                            brace_map.errors.push((
+                                open_index..index,
+                                Sigil::AngleOpen,
+                            ));
+                            parent_brace_map.branches.push(Branch {
+                                real_token_range: open_index..index,
+                                branches: brace_map.branches.len(),
+                                inserted_closes: brace_map.inserted_closes.len(),
+                                removed_closes: brace_map.removed_closes.len(),
+                                errors: brace_map.errors.len(),
+                                open_sigil: Sigil::AngleOpen,
+                                close_sigil: Sigil::AngleClose,
+                                closed: false,
+                            });
+                            parent_brace_map.append(brace_map);
+                        } else {
+                            bug!()
+                        }
+                    }
+                } else {
                     let mut parent_brace_map = stack.last_mut()
                         .map(|(_, _, brace_map)| brace_map)
                         .unwrap_or(&mut top_map);
-                    if open_sigil == open_s {
-                        parent_brace_map.branches.push(Branch {
-                            real_token_range: open_index..index.checked_add(1).X(),
-                            branches: brace_map.branches.len(),
-                            inserted_closes: brace_map.inserted_closes.len(),
-                            removed_closes: brace_map.removed_closes.len(),
-                            errors: brace_map.errors.len(),
-                            open_sigil: open_s,
-                            close_sigil: close_s,
-                        });
-                        parent_brace_map.append(brace_map);
-                        break;
-                    } else if open_sigil == Sigil::ParenOpen {
-                        brace_map.inserted_closes.push((index, Sigil::ParenClose));
-                        brace_map.errors.push((
-                            open_index..index,
-                            Sigil::ParenOpen,
-                        ));
-                        parent_brace_map.branches.push(Branch {
-                            real_token_range: open_index..index,
-                            branches: brace_map.branches.len(),
-                            inserted_closes: brace_map.inserted_closes.len(),
-                            removed_closes: brace_map.removed_closes.len(),
-                            errors: brace_map.errors.len(),
-                            open_sigil: Sigil::ParenOpen,
-                            close_sigil: Sigil::ParenClose,
-                        });
-                        parent_brace_map.append(brace_map);
-                    } else if open_sigil == Sigil::BraceOpen {
-                        brace_map.inserted_closes.push((index, Sigil::BraceClose));
-                        brace_map.errors.push((
-                            open_index..index,
-                            Sigil::BraceOpen,
-                        ));
-                        parent_brace_map.branches.push(Branch {
-                            real_token_range: open_index..index,
-                            branches: brace_map.branches.len(),
-                            inserted_closes: brace_map.inserted_closes.len(),
-                            removed_closes: brace_map.removed_closes.len(),
-                            errors: brace_map.errors.len(),
-                            open_sigil: Sigil::BraceOpen,
-                            close_sigil: Sigil::BraceClose,
-                        });
-                        parent_brace_map.append(brace_map);
-                    } else if open_sigil == Sigil::BracketOpen {
-                        brace_map.inserted_closes.push((index, Sigil::BracketClose));
-                        brace_map.errors.push((
-                            open_index..index,
-                            Sigil::BracketOpen,
-                        ));
-                        parent_brace_map.branches.push(Branch {
-                            real_token_range: open_index..index,
-                            branches: brace_map.branches.len(),
-                            inserted_closes: brace_map.inserted_closes.len(),
-                            removed_closes: brace_map.removed_closes.len(),
-                            errors: brace_map.errors.len(),
-                            open_sigil: Sigil::BracketOpen,
-                            close_sigil: Sigil::BracketClose,
-                        });
-                        parent_brace_map.append(brace_map);
-                    } else if open_sigil == Sigil::AngleOpen {
-                        brace_map.inserted_closes.push((index, Sigil::AngleClose));
-                        brace_map.errors.push((
-                            open_index..index,
-                            Sigil::AngleOpen,
-                        ));
-                        parent_brace_map.branches.push(Branch {
-                            real_token_range: open_index..index,
-                            branches: brace_map.branches.len(),
-                            inserted_closes: brace_map.inserted_closes.len(),
-                            removed_closes: brace_map.removed_closes.len(),
-                            errors: brace_map.errors.len(),
-                            open_sigil: Sigil::AngleOpen,
-                            close_sigil: Sigil::AngleClose,
-                        });
-                        parent_brace_map.append(brace_map);
-                    } else {
-                        bug!()
-                    }
+                    parent_brace_map.removed_closes.push((index, close_s));
+                    parent_brace_map.errors.push((index..index.checked_add(1).X(), close_s));
+                }
+            };
+
+        for (index, kind) in kinds {
+            match kind {
+                TokenKind::Sigil(Sigil::ParenOpen) => {
+                    stack.push((index, Sigil::ParenOpen, default()));
                 }
-            } else {
-                let mut parent_brace_map = stack.last_mut()
-                    .map(|(_, _, brace_map)| brace_map)
-                    .unwrap_or(&mut top_map);
-                parent_brace_map.removed_closes.push((index, close_s));
-                parent_brace_map.errors.push((index..index.checked_add(1).X(), close_s));
+                TokenKind::Sigil(Sigil::BraceOpen) => {
+                    stack.push((index, Sigil::BraceOpen, default()));
+                }
+                TokenKind::Sigil(Sigil::BracketOpen) => {
+                    stack.push((index, Sigil::BracketOpen, default()));
+                }
+                TokenKind::Sigil(Sigil::AngleOpen) => {
+                    stack.push((index, Sigil::AngleOpen, default()));
+                }
+                TokenKind::Sigil(Sigil::ParenClose) => {
+                    close_brace(&mut stack, index, Sigil::ParenOpen, Sigil::ParenClose);
+                }
+                TokenKind::Sigil(Sigil::BraceClose) => {
+                    close_brace(&mut stack, index, Sigil::BraceOpen, Sigil::BraceClose);
+                }
+                TokenKind::Sigil(Sigil::BracketClose) => {
+                    close_brace(&mut stack, index, Sigil::BracketOpen, Sigil::BracketClose);
+                }
+                TokenKind::Sigil(Sigil::AngleClose) => {
+                    close_brace(&mut stack, index, Sigil::AngleOpen, Sigil::AngleClose);
+                }
+                _ => {},
             }
+        }
+
+        while let Some((open_index, open_sigil, brace_map)) = stack.pop() {
+            let mut parent_brace_map = stack.last_mut()
+                .map(|(_, _, brace_map)| brace_map)
+                .unwrap_or(&mut top_map);
+            parent_brace_map.branches.push(Branch {
+                real_token_range: open_index..num_tokens,
+                branches: brace_map.branches.len(),
+                inserted_closes: brace_map.inserted_closes.len(),
+                removed_closes: brace_map.removed_closes.len(),
+                errors: brace_map.errors.len(),
+                open_sigil,
+                close_sigil: open_sigil.close_sigil(),
+                closed: false,
+            });
+            parent_brace_map.errors.push((
+                open_index..num_tokens,
+                open_sigil,
+            ));
+            parent_brace_map.append(brace_map);
+        }
+
+    top_map
+    }
+
+    fn append(&mut self, other: BraceMap) {
+        self.branches.extend(other.branches);
+        self.inserted_closes.extend(other.inserted_closes);
+        self.removed_closes.extend(other.removed_closes);
+        self.errors.extend(other.errors);
+    }
+
+    /// The map of the tokens `kinds`, which are the tokens of this map
+    /// with the tokens of `edit.old` replaced by `edit.new_len` others.
+    ///
+    /// Only the innermost branch around the edit whose new contents
+    /// are braced the same on their own as inside the rest of the tokens
+    /// is braced again; the rest of the map is reused.
+    /// That is a branch that ends with its own close,
+    /// and whose new contents close nothing outside the branch
+    /// and leave nothing open at its close.
+    /// If no branch around the edit qualifies, all of `kinds` is braced.
+    pub fn edited(&self, kinds: &[TokenKind], edit: &TokenEdit) -> BraceMap {
+        match self.edited_branch(kinds, edit) {
+            Some((_, map)) => map,
+            None => BraceMap::new(kinds),
+        }
+    }
+
+    /// The map after `edit` and the index of the branch braced again,
+    /// if only one branch needs to be.
+    fn edited_branch(&self, kinds: &[TokenKind], edit: &TokenEdit) -> Option<(usize, BraceMap)> {
+        let shift = |index: usize| match index >= edit.old.end {
+            true => index.checked_sub(edit.old.len()).X().checked_add(edit.new_len).X(),
+            false => index,
         };
+        // Branches around the edit are ancestors of each other,
+        // so in preorder the innermost is last.
+        let around = self.branches.iter().enumerate().filter(|(_, branch)| {
+            let range = &branch.real_token_range;
+            branch.closed && range.start < edit.old.start && edit.old.end < range.end
+        });
 
-    for (index, token) in tokens {
-        match token.kind(db) {
-            TokenKind::Sigil(Sigil::ParenOpen) => {
-                stack.push((index, Sigil::ParenOpen, default()));
-            }
-            TokenKind::Sigil(Sigil::BraceOpen) => {
-                stack.push((index, Sigil::BraceOpen, default()));
-            }
-            TokenKind::Sigil(Sigil::BracketOpen) => {
-                stack.push((index, Sigil::BracketOpen, default()));
-            }
-            TokenKind::Sigil(Sigil::AngleOpen) => {
-                stack.push((index, Sigil::AngleOpen, default()));
-            }
-            TokenKind::Sigil(Sigil::ParenClose) => {
-                close_brace(&mut stack, index, Sigil::ParenOpen, Sigil::ParenClose);
+        for (index, branch) in around.collect::<Vec<_>>().into_iter().rev() {
+            let open = branch.real_token_range.start;
+            let old_close = branch.real_token_range.end.checked_sub(1).X();
+            let close = shift(old_close);
+            let inner_start = open.checked_add(1).X();
+            let inner = BraceMap::brace(
+                kinds[inner_start..close].iter().copied().enumerate()
+                    .map(|(offset, kind)| (inner_start.checked_add(offset).X(), kind)),
+                close,
+            );
+            // Every error but those of inserted closes is an unmatched close
+            // or a brace left open at `close`.
+            if !inner.removed_closes.is_empty() || inner.errors.len() != inner.inserted_closes.len() {
+                continue;
             }
-            TokenKind::Sigil(Sigil::BraceClose) => {
-                close_brace(&mut stack, index, Sigil::BraceOpen, Sigil::BraceClose);
-            }
-            TokenKind::Sigil(Sigil::BracketClose) => {
-                close_brace(&mut stack, index, Sigil::BracketOpen, Sigil::BracketClose);
-            }
-            TokenKind::Sigil(Sigil::AngleClose) => {
-                close_brace(&mut stack, index, Sigil::AngleOpen, Sigil::AngleClose);
-            }
-            _ => {},
+            let num_tokens = kinds.len().checked_sub(edit.new_len).X().checked_add(edit.old.len()).X();
+            return Some((index, self.replace_branch(num_tokens, index, inner, shift)));
         }
+        None
     }
 
-    let num_tokens = chunk.tokens(db).len();
-
-    while let Some((open_index, open_sigil, brace_map)) = stack.pop() {
-        let mut parent_brace_map = stack.last_mut()
-            .map(|(_, _, brace_map)| brace_map)
-            .unwrap_or(&mut top_map);
-        parent_brace_map.branches.push(Branch {
-            real_token_range: open_index..num_tokens,
-            branches: brace_map.branches.len(),
-            inserted_closes: brace_map.inserted_closes.len(),
-            removed_closes: brace_map.removed_closes.len(),
-            errors: brace_map.errors.len(),
-            open_sigil,
-            close_sigil: open_sigil.close_sigil(),
+    /// The map of `num_tokens` tokens with the contents of branch `index`
+    /// replaced by `inner`, and the token indexes after it moved by `shift`.
+    fn replace_branch(
+        &self,
+        num_tokens: usize,
+        index: usize,
+        inner: BraceMap,
+        shift: impl Fn(usize) -> usize,
+    ) -> BraceMap {
+        let branch = &self.branches[index];
+        let open = branch.real_token_range.start;
+        let old_close = branch.real_token_range.end.checked_sub(1).X();
+        let inside = |token_index: usize| open < token_index && token_index <= old_close;
+        let old_branches = index.checked_add(1).X()..index.checked_add(1).X().checked_add(branch.branches).X();
+        // Items are recorded at the token that causes them:
+        // a removed close at itself, an open left unclosed at the token closing it,
+        // and one still open at the end of the tokens before its contents.
+        // Those recorded before the branch opens come before its items,
+        // which are contiguous, and those recorded after it closes come after.
+        let contents = |times: Vec<usize>| -> Range<usize> {
+            let start = times.iter().filter(|time| **time <= open).count();
+            start..start.checked_add(times.iter().filter(|time| inside(**time)).count()).X()
+        };
+        let old_inserted = contents(self.inserted_closes.iter().map(|(at, _)| *at).collect());
+        let old_removed = contents(self.removed_closes.iter().map(|(at, _)| *at).collect());
+        let old_errors = contents(self.errors.iter().map(|(range, sigil)| {
+            match sigil.is_close_sigil() || range.end == num_tokens {
+                true => range.start,
+                false => range.end,
+            }
+        }).collect());
+        let resize = |count: usize, old: usize, new: usize| count.checked_sub(old).X().checked_add(new).X();
+
+        let mut branches = Vec::with_capacity(self.branches.len());
+        for ancestor in &self.branches[..index] {
+            let mut ancestor = ancestor.C();
+            let range = &ancestor.real_token_range;
+            if range.start < open && old_close < range.end {
+                ancestor.real_token_range = range.start..shift(range.end);
+                ancestor.branches = resize(ancestor.branches, branch.branches, inner.branches.len());
+                ancestor.inserted_closes =
+                    resize(ancestor.inserted_closes, old_inserted.len(), inner.inserted_closes.len());
+                ancestor.removed_closes =
+                    resize(ancestor.removed_closes, old_removed.len(), inner.removed_closes.len());
+                ancestor.errors = resize(ancestor.errors, old_errors.len(), inner.errors.len());
+            }
+            branches.push(ancestor);
+        }
+        branches.push(Branch {
+            real_token_range: open..shift(branch.real_token_range.end),
+            branches: inner.branches.len(),
+            inserted_closes: inner.inserted_closes.len(),
+            removed_closes: inner.removed_closes.len(),
+            errors: inner.errors.len(),
+            ..branch.C()
         });
-        parent_brace_map.errors.push((
-            open_index..num_tokens,
-            open_sigil,
-        ));
-        parent_brace_map.append(brace_map);
-    }
+        branches.extend(inner.branches);
+        branches.extend(self.branches[old_branches.end..].iter().map(|branch| Branch {
+            real_token_range: shift(branch.real_token_range.start)..shift(branch.real_token_range.end),
+            ..branch.C()
+        }));
 
-    debug!("bm {top_map:#?}");
+        fn splice<T>(old: &[T], contents: Range<usize>, new: Vec<T>, shift: impl Fn(&T) -> T) -> Vec<T> {
+            old[..contents.start].iter().map(&shift)
+                .chain(new)
+                .chain(old[contents.end..].iter().map(&shift))
+                .collect()
+        }
+        BraceMap {
+            branches,
+            inserted_closes: splice(
+                &self.inserted_closes, old_inserted, inner.inserted_closes,
+                |(at, sigil)| (shift(*at), *sigil),
+            ),
+            removed_closes: splice(
+                &self.removed_closes, old_removed, inner.removed_closes,
+                |(at, sigil)| (shift(*at), *sigil),
+            ),
+            errors: splice(
+                &self.errors, old_errors, inner.errors,
+                |(range, sigil)| (shift(range.start)..shift(range.end), *sigil),
+            ),
+        }
+    }
+}
 
-    Bracer::new(
-        db,
-        chunk,
-        top_map.branches,
-        top_map.inserted_closes,
-        top_map.removed_closes,
-        top_map.errors,
-    )
+/// A replacement of some tokens of a chunk by others.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TokenEdit {
+    /// The replaced tokens.
+    pub old: Range<usize>,
+    /// The number of tokens replacing them.
+    pub new_len: usize,
 }
 
 impl<'db> TreeToken<'db> {
     /// Get source Text and byte span for this token or branch.
-    pub fn text_span(&self, db: &'db dyn crate::Db) -> Option<crate::text::TextSpan<'db>> {
+    pub fn text_span(&self, db: &'db dyn crate::Db) -> Option<crate::span::TextSpan<'db>> {
         match self {
-            TreeToken::Token(tok) => {
-                let subtext = tok.text(db);
-                Some(crate::text::TextSpan::new(subtext.text(db), subtext.range(db)))
-            }
+            TreeToken::Token(tok) => Some(tok.text(db).text_span(db)),
             TreeToken::Branch(_, iter) => iter.text_span(),
         }
     }
@@ -522,12 +868,9 @@ impl<'db> Bracer<'db> {
 
 #[cfg(test)]
 fn dbglex(s: &str) -> String {
-    debug!("dbglex {s}");
+    tracing::debug!(source = s, "dbglex");
     let ref db = crate::Database::default();
-    let source = crate::input::Source::new(db, S(s));
-    let chunk = crate::source_map::basic_source_map(db, source);
-    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
-    let bracer = bracer(db, chunk_lex);
+    let bracer = bracer_for_str(db, s);
     bracer.debug_str(db)
 }
 
@@ -655,10 +998,7 @@ fn test_text_span() {
 
     // Helper to get the span string from input.
     let get_span = |s: &str| -> Option<(usize, usize, String)> {
-        let source = crate::input::Source::new(db, S(s));
-        let chunk = crate::source_map::basic_source_map(db, source);
-        let chunk_lex = crate::lexer::lex_chunk(db, chunk);
-        let bracer = bracer(db, chunk_lex);
+        let bracer = bracer_for_str(db, s);
         // Find the first branch.
         for token in bracer.iter(db) {
             if let TreeToken::Branch(_, _) = &token {
@@ -720,10 +1060,7 @@ fn test_text_span() {
 #[test]
 fn test_without_space() {
     let ref db = crate::Database::default();
-    let source = crate::input::Source::new(db, S("a b (c)"));
-    let chunk = crate::source_map::basic_source_map(db, source);
-    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
-    let bracer = bracer(db, chunk_lex);
+    let bracer = bracer_for_str(db, "a b (c)");
 
     let tokens: Vec<_> = bracer.iter(db).collect();
     // tokens: "a", ws, "b", ws, branch(c)
@@ -760,3 +1097,98 @@ fn test_removed_closes() {
     // Complex nesting with stray closes.
     assert_eq!(dbglex("((a)})"), "( ( a ) )");
 }
+
+#[test]
+fn test_edited() {
+    use Sigil::*;
+    let kind = |choice: usize| match choice {
+        0 => TokenKind::Sigil(ParenOpen),
+        1 => TokenKind::Sigil(ParenClose),
+        2 => TokenKind::Sigil(BracketOpen),
+        3 => TokenKind::Sigil(BracketClose),
+        4 => TokenKind::Sigil(BraceOpen),
+        5 => TokenKind::Sigil(BraceClose),
+        6 => TokenKind::Sigil(AngleOpen),
+        7 => TokenKind::Sigil(AngleClose),
+        _ => TokenKind::Word,
+    };
+
+    // Random edits of mostly balanced tokens.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let mut next = |bound: usize| {
+        state = state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        usize::try_from(state >> 33).X() % bound
+    };
+    for _ in 0..2000 {
+        let mut kinds = vec![];
+        let mut open = vec![];
+        for _ in 0..next(60) {
+            match next(10) {
+                0..=2 => {
+                    let choice = next(4).checked_mul(2).X();
+                    open.push(choice);
+                    kinds.push(kind(choice));
+                }
+                3..=5 if let Some(choice) = open.pop() => {
+                    kinds.push(kind(choice.checked_add(1).X()));
+                }
+                6 => kinds.push(kind(next(8))),
+                _ => kinds.push(TokenKind::Word),
+            }
+        }
+        let start = next(kinds.len().checked_add(1).X());
+        let end = start.checked_add(next(kinds.len().checked_sub(start).X().min(2).checked_add(1).X())).X();
+        let new: Vec<_> = (0..next(3)).map(|_| kind(next(12))).collect();
+        let mut edited_kinds = kinds.C();
+        edited_kinds.splice(start..end, new.iter().copied());
+
+        let edit = TokenEdit { old: start..end, new_len: new.len() };
+        let map = BraceMap::new(&kinds);
+        assert_eq!(map.edited(&edited_kinds, &edit), BraceMap::new(&edited_kinds), "{kinds:?} {edit:?} {new:?}");
+    }
+
+    // An edit deep inside is braced again in its own branch.
+    let mut kinds = vec![];
+    for _ in 0..50 {
+        kinds.extend([TokenKind::Sigil(BraceOpen), TokenKind::Word]);
+    }
+    kinds.push(TokenKind::Word);
+    for _ in 0..50 {
+        kinds.extend([TokenKind::Word, TokenKind::Sigil(BraceClose)]);
+    }
+    let mut edited_kinds = kinds.C();
+    edited_kinds.splice(100..101, [TokenKind::Sigil(ParenOpen), TokenKind::Word, TokenKind::Sigil(ParenClose)]);
+    let edit = TokenEdit { old: 100..101, new_len: 3 };
+    let (index, map) = BraceMap::new(&kinds).edited_branch(&edited_kinds, &edit).X();
+    assert_eq!(index, 49);
+    assert_eq!(map, BraceMap::new(&edited_kinds));
+
+    // An unmatched close can't be braced inside the branch.
+    edited_kinds[101] = TokenKind::Sigil(BraceClose);
+    assert_eq!(BraceMap::new(&kinds).edited_branch(&edited_kinds, &edit), None);
+}
+
+#[test]
+fn test_is_balanced() {
+    let ref db = crate::Database::default();
+    use Balance::*;
+    for (text, expected) in [
+        ("", Balanced),
+        ("a (b [c] {d <e>}) f", Balanced),
+        ("\"(\" // )", Balanced),
+        ("(a", Unclosed),
+        ("{a [b]", Unclosed),
+        ("a)", Unmatched),
+        ("(a]", Unmatched),
+        ("([)]", Unmatched),
+        ("{a} >", Unmatched),
+    ] {
+        let source = crate::input::Source::new(db, S(text));
+        let chunk = crate::source_map::basic_source_map(db, source);
+        let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+        let balanced = expected == Balanced;
+        assert_eq!(balance(db, chunk_lex), expected, "{text}");
+        assert_eq!(is_balanced(db, chunk_lex), balanced, "{text}");
+        assert_eq!(bracer(db, chunk_lex).errors(db).is_empty(), balanced, "{text}");
+    }
+}
@@ -16,6 +16,12 @@ pub struct Chunk<'db> {
     pub strings: Vec<Range<usize>>,
     #[returns(ref)]
     pub errors: Vec<Range<usize>>,
+    /// Why each of `errors` is an error, in the same order.
+    #[returns(ref)]
+    pub error_reasons: Vec<ErrorReason>,
+    #[returns(ref)]
+    pub embedded: Vec<Range<usize>>,
+    pub kind: ChunkKind,
 }
 
 impl<'db> Chunk<'db> {
@@ -26,9 +32,11 @@ impl<'db> Chunk<'db> {
         let comments = self.comments(db).iter().cloned().map(|range| (range, RangeKind::Comment));
         let strings = self.strings(db).iter().cloned().map(|range| (range, RangeKind::String));
         let errors = self.errors(db).iter().cloned().map(|range| (range, RangeKind::Error));
+        let embedded = self.embedded(db).iter().cloned().map(|range| (range, RangeKind::Embedded));
         let mut known_ranges = comments
             .merge_by(strings, |x, y| x.0.start <= y.0.start)
-            .merge_by(errors, |x, y| x.0.start <= y.0.start);
+            .merge_by(errors, |x, y| x.0.start <= y.0.start)
+            .merge_by(embedded, |x, y| x.0.start <= y.0.start);
 
         let next_known_range = known_ranges.next();
         let mut ranges = Ranges {
@@ -41,8 +49,144 @@ impl<'db> Chunk<'db> {
     }
 }
 
+/// A broken invariant of a `Chunk`, found by `Chunk::check`.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum ChunkInvariantError {
+    /// A range is outside the text or not on char boundaries,
+    /// or overlaps or comes before the range before it, of any kind.
+    InvalidRange { range: Range<usize> },
+    /// The errors and error reasons differ in number.
+    ErrorReasonCount,
+}
+
+impl<'db> Chunk<'db> {
+    /// Check the invariants the lexer relies on,
+    /// that every range is within the text and none overlap.
+    ///
+    /// The source map always returns such chunks.
+    pub fn check(&self, db: &'db dyn crate::Db) -> Result<(), ChunkInvariantError> {
+        if self.errors(db).len() != self.error_reasons(db).len() {
+            return Err(ChunkInvariantError::ErrorReasonCount);
+        }
+        let text = self.text(db).as_str(db);
+        let ranges = self.comments(db).iter()
+            .chain(self.strings(db))
+            .chain(self.errors(db))
+            .chain(self.embedded(db))
+            .sorted_by_key(|range| (range.start, range.end));
+        let mut end = 0;
+        for range in ranges {
+            let valid = range.start >= end
+                && range.start <= range.end
+                && range.end <= text.len()
+                && text.is_char_boundary(range.start)
+                && text.is_char_boundary(range.end);
+            if !valid {
+                return Err(ChunkInvariantError::InvalidRange { range: range.C() });
+            }
+            end = range.end;
+        }
+        Ok(())
+    }
+}
+
 #[derive(Eq, PartialEq, Copy, Clone, Debug)]
-pub enum RangeKind { Comment, String, Error, Unknown }
+pub enum RangeKind { Comment, String, Error, Embedded, Unknown }
+
+/// Why a range of a chunk is an error.
+///
+/// Every error runs to the end of the text,
+/// for want of the close its opening delimiter expects.
+/// Spans are relative to the chunk, like the error ranges.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum ErrorReason {
+    /// A string without its closing quote.
+    UnterminatedString { open_quote_span: Range<usize> },
+    /// A block comment with `depth` nested comments left open,
+    /// the outermost opened at `open_span`.
+    UnterminatedBlockComment { open_span: Range<usize>, depth: usize },
+    /// An embedded region without its closing fence.
+    UnterminatedFence { open_span: Range<usize> },
+    /// A heredoc without its terminator line,
+    /// opened by `<<` and the terminator at `open_span`.
+    UnterminatedHeredoc { open_span: Range<usize> },
+}
+
+impl ErrorReason {
+    /// The span of the opening delimiter.
+    pub fn open_span(&self) -> Range<usize> {
+        match self {
+            ErrorReason::UnterminatedString { open_quote_span } => open_quote_span.C(),
+            ErrorReason::UnterminatedBlockComment { open_span, .. } => open_span.C(),
+            ErrorReason::UnterminatedFence { open_span } => open_span.C(),
+            ErrorReason::UnterminatedHeredoc { open_span } => open_span.C(),
+        }
+    }
+
+    /// What's missing, e.g. ``unterminated string, expected a closing `"` ``.
+    ///
+    /// `chunk_text` is the text of the chunk the error is in.
+    pub fn message(&self, chunk_text: &str) -> String {
+        let open = &chunk_text[self.open_span()];
+        match self {
+            ErrorReason::UnterminatedString { .. } => {
+                format!("unterminated string, expected a closing `{open}`")
+            }
+            ErrorReason::UnterminatedBlockComment { depth: 1, .. } => {
+                S("unterminated block comment, expected `*/`")
+            }
+            ErrorReason::UnterminatedBlockComment { depth, .. } => {
+                format!("unterminated block comment, expected {depth} `*/`")
+            }
+            ErrorReason::UnterminatedFence { .. } => {
+                format!("unterminated embedded region, expected a closing `{open}`")
+            }
+            ErrorReason::UnterminatedHeredoc { .. } => {
+                let terminator = &open[2..];
+                format!("unterminated heredoc, expected a line `{terminator}`")
+            }
+        }
+    }
+
+    /// The reason with its spans moved back by `offset`,
+    /// for a chunk starting `offset` bytes later.
+    pub fn checked_sub(&self, offset: usize) -> Option<ErrorReason> {
+        Some(match self {
+            ErrorReason::UnterminatedString { open_quote_span } => {
+                ErrorReason::UnterminatedString { open_quote_span: open_quote_span.C().checked_sub(offset)? }
+            }
+            ErrorReason::UnterminatedBlockComment { open_span, depth } => {
+                ErrorReason::UnterminatedBlockComment { open_span: open_span.C().checked_sub(offset)?, depth: *depth }
+            }
+            ErrorReason::UnterminatedFence { open_span } => {
+                ErrorReason::UnterminatedFence { open_span: open_span.C().checked_sub(offset)? }
+            }
+            ErrorReason::UnterminatedHeredoc { open_span } => {
+                ErrorReason::UnterminatedHeredoc { open_span: open_span.C().checked_sub(offset)? }
+            }
+        })
+    }
+}
+
+/// What a chunk contains.
+///
+/// Decided by `chunks::Config::try_chunk` when the chunk is split off,
+/// or for the last chunk by the split before it or `chunks::Config::last_kind`,
+/// and used to pick per-kind configuration in later passes,
+/// see `source_map::KindConfig` and `lexer::KindConfig`.
+#[derive(Copy, Clone, Debug, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub enum ChunkKind {
+    /// Ordinary source code.
+    #[default]
+    Code,
+    /// A directive to the tool rather than code.
+    Directive,
+    /// An inline data block.
+    Data,
+}
 
 struct Ranges<'db> {
     chunk_len: usize,
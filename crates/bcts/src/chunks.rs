@@ -6,12 +6,19 @@ use rmx::std::iter::Peekable;
 use rmx::std::slice::Iter as SliceIter;
 
 use crate::text::Text;
-use crate::chunk::{Chunk, RangeKind};
+use crate::chunk::{Chunk, ChunkKind, ErrorReason, RangeKind};
 
 #[salsa::tracked]
 pub struct Chunks<'db> {
     #[returns(ref)]
     pub chunks: Vec<Chunk<'db>>,
+    /// The offset of each chunk in the text it was split from,
+    /// the source for chunks of a `basic_source_map` chunk.
+    #[returns(ref)]
+    pub offsets: Vec<usize>,
+    /// Splits moved to keep chunks whole, see `ChunkSplitError`.
+    #[returns(ref)]
+    pub split_errors: Vec<ChunkSplitError>,
 }
 
 #[salsa::tracked]
@@ -30,7 +37,62 @@ pub fn basic_chunks<'db>(
 pub struct Config<'db> {
     #[returns(ref)]
     pub chunk_start_chars: Vec<char>,
-    pub try_chunk: for <'a> fn(&'a str) -> Option<usize>,
+    /// Called at each chunk start char to decide whether
+    /// the current chunk ends there, and what kind it is.
+    pub try_chunk: for <'a> fn(&'a str) -> Option<ChunkSplit>,
+    /// The kind of the last chunk, which no split ends,
+    /// unless the split before it gives a `ChunkSplit::next_kind`.
+    pub last_kind: ChunkKind,
+}
+
+/// The end of a chunk, as found by `Config::try_chunk`.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct ChunkSplit {
+    /// Bytes from the start char to include at the end of the chunk.
+    pub eat_bytes: usize,
+    /// The kind of the chunk that ends here.
+    pub kind: ChunkKind,
+    /// The kind of the chunk that starts here, if the split decides it,
+    /// e.g. a marker opening a data block.
+    ///
+    /// A chunk ended by a later split takes that split's `kind`,
+    /// so this only decides the kind of the last chunk.
+    pub next_kind: Option<ChunkKind>,
+}
+
+/// A `ChunkSplit` that would have cut a chunk badly,
+/// and was moved to the first position that doesn't.
+///
+/// Positions are offsets into the text being chunked.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum ChunkSplitError {
+    /// The split was past the end of the text,
+    /// and is at the end instead.
+    PastEnd { split: usize },
+    /// The split was inside a character, and is after it instead.
+    InsideChar { split: usize },
+    /// The split was inside a comment, string, error or embedded region,
+    /// and is after it instead, merging the chunks on either side.
+    Straddles { split: usize, range: Range<usize> },
+}
+
+/// A broken invariant of `Chunks`, found by `Chunks::check`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ChunksInvariantError {
+    /// The chunks' texts don't make up the text they were split from.
+    TextMismatch,
+    EmptyChunk { chunk: usize },
+    /// A range of a chunk is outside the chunk's text,
+    /// or overlaps or comes before the range before it.
+    InvalidRange { chunk: usize, range: Range<usize> },
+    /// A chunk's errors and error reasons differ in number.
+    ErrorReasonCount { chunk: usize },
+    /// The offsets are not where the chunks start.
+    OffsetsMismatch,
+    /// The chunks' ranges, moved to the chunks' offsets,
+    /// are not those of the chunk they were split from.
+    RangesMismatch,
 }
 
 #[salsa::tracked]
@@ -41,14 +103,37 @@ pub fn basic_config<'db>(
         db,
         vec!['.'],
         basic_try_chunk,
+        ChunkKind::Code,
     )
 }
 
-fn basic_try_chunk(text: &str) -> Option<usize> {
+fn basic_try_chunk(text: &str) -> Option<ChunkSplit> {
     assert_eq!(text.as_bytes()[0], b'.');
-    Some(1)
+    Some(ChunkSplit {
+        eat_bytes: 1,
+        kind: ChunkKind::Code,
+        next_kind: None,
+    })
 }
 
+/// Split a code chunk after every chunk start char.
+///
+/// For configs whose start chars are plain separators.
+pub fn separator_try_chunk(text: &str) -> Option<ChunkSplit> {
+    let separator = text.chars().next().X();
+    Some(ChunkSplit {
+        eat_bytes: separator.len_utf8(),
+        kind: ChunkKind::Code,
+        next_kind: None,
+    })
+}
+
+/// Split `chunk_in` into chunks, as decided by `config`.
+///
+/// Chunks are never empty: an empty text has no chunks,
+/// and whitespace, even a text of only whitespace,
+/// stays in the chunk it is in, so the chunks make up the whole text.
+/// See `Chunks::check`, which holds for the result in debug builds.
 #[salsa::tracked]
 pub fn chunks<'db>(
     db: &'db dyn crate::Db,
@@ -62,17 +147,93 @@ pub fn chunks<'db>(
         comments_iter: chunk_in.comments(db).iter().peekable(),
         strings_iter: chunk_in.strings(db).iter().peekable(),
         errors_iter: chunk_in.errors(db).iter().peekable(),
+        error_reasons_iter: chunk_in.error_reasons(db).iter(),
+        embedded_iter: chunk_in.embedded(db).iter().peekable(),
         position: 0,
+        next_kind: None,
         chunk_wip: ChunkWip {
             chunk_start: 0,
             comments: vec![],
             strings: vec![],
             errors: vec![],
+            error_reasons: vec![],
+            embedded: vec![],
         },
         chunks: vec![],
+        offsets: vec![],
+        split_errors: vec![],
     };
 
-    state.map()
+    let chunks = state.map();
+    debug_assert_eq!(chunks.check(db, chunk_in), Ok(()));
+    chunks
+}
+
+impl<'db> Chunks<'db> {
+    /// The index of the chunk containing the byte at `offset`
+    /// in the text the chunks were split from,
+    /// or of the last chunk for the offset of the end of the text.
+    ///
+    /// The offset within the chunk is `offset - offsets[index]`.
+    pub fn chunk_at_offset(&self, db: &'db dyn crate::Db, offset: usize) -> Option<usize> {
+        let offsets = self.offsets(db);
+        let index = offsets.partition_point(|&start| start <= offset).checked_sub(1)?;
+        let end = offsets[index].checked_add(self.chunks(db)[index].text(db).as_str(db).len()).X();
+        let is_last = index.checked_add(1).X() == offsets.len();
+        (offset < end || (is_last && offset == end)).then_some(index)
+    }
+
+    /// Check that these are chunks of `chunk_in`:
+    /// non-empty chunks whose texts make up its text,
+    /// each with sorted ranges inside its text,
+    /// and a reason for each error,
+    /// which together are the ranges of `chunk_in`.
+    ///
+    /// `chunks` always returns such chunks;
+    /// this is for tests and for checking chunks from elsewhere.
+    pub fn check(&self, db: &'db dyn crate::Db, chunk_in: Chunk<'db>) -> Result<(), ChunksInvariantError> {
+        let chunks = self.chunks(db);
+        let text: String = chunks.iter().map(|chunk| chunk.text(db).as_str(db)).collect();
+        if text != chunk_in.text(db).as_str(db) {
+            return Err(ChunksInvariantError::TextMismatch);
+        }
+
+        let mut offset: usize = 0;
+        let mut moved: [Vec<Range<usize>>; 4] = default();
+        if self.offsets(db).len() != chunks.len() {
+            return Err(ChunksInvariantError::OffsetsMismatch);
+        }
+        for (index, chunk) in chunks.iter().enumerate() {
+            if self.offsets(db)[index] != offset {
+                return Err(ChunksInvariantError::OffsetsMismatch);
+            }
+            let len = chunk.text(db).as_str(db).len();
+            if len == 0 {
+                return Err(ChunksInvariantError::EmptyChunk { chunk: index });
+            }
+            let ranges = [chunk.comments(db), chunk.strings(db), chunk.errors(db), chunk.embedded(db)];
+            for (ranges, moved) in ranges.into_iter().zip(&mut moved) {
+                let mut end = 0;
+                for range in ranges {
+                    if range.start < end || range.start > range.end || range.end > len {
+                        return Err(ChunksInvariantError::InvalidRange { chunk: index, range: range.C() });
+                    }
+                    end = range.end;
+                    moved.push(offset.checked_add(range.start).X()..offset.checked_add(range.end).X());
+                }
+            }
+            if chunk.errors(db).len() != chunk.error_reasons(db).len() {
+                return Err(ChunksInvariantError::ErrorReasonCount { chunk: index });
+            }
+            offset = offset.checked_add(len).X();
+        }
+
+        let expected = [chunk_in.comments(db), chunk_in.strings(db), chunk_in.errors(db), chunk_in.embedded(db)];
+        if expected.into_iter().zip(&moved).any(|(expected, moved)| expected != moved) {
+            return Err(ChunksInvariantError::RangesMismatch);
+        }
+        Ok(())
+    }
 }
 
 struct State<'db> {
@@ -82,9 +243,15 @@ struct State<'db> {
     comments_iter: Peekable<SliceIter<'db, Range<usize>>>,
     strings_iter: Peekable<SliceIter<'db, Range<usize>>>,
     errors_iter: Peekable<SliceIter<'db, Range<usize>>>,
+    error_reasons_iter: SliceIter<'db, ErrorReason>,
+    embedded_iter: Peekable<SliceIter<'db, Range<usize>>>,
     position: usize,
+    /// The `next_kind` of the last split.
+    next_kind: Option<ChunkKind>,
     chunk_wip: ChunkWip,
     chunks: Vec<Chunk<'db>>,
+    offsets: Vec<usize>,
+    split_errors: Vec<ChunkSplitError>,
 }
 
 // ranges are relative to `chunk_start`
@@ -93,6 +260,8 @@ struct ChunkWip {
     comments: Vec<Range<usize>>,
     strings: Vec<Range<usize>>,
     errors: Vec<Range<usize>>,
+    error_reasons: Vec<ErrorReason>,
+    embedded: Vec<Range<usize>>,
 }
 
 impl<'db> State<'db> {
@@ -105,9 +274,10 @@ impl<'db> State<'db> {
                 continue;
             }
 
-            self.position = range.start;
+            // A moved split may have eaten into this range already.
+            self.position = self.position.max(range.start);
 
-            loop {
+            while self.position < range.end {
                 let text_remaining = &text_all[self.position..range.end];
                 let mut start_char_indexes = text_remaining.match_indices(all_start_chars).map(|(i, s)| i);
                 let next_start_char_index = start_char_indexes.next();
@@ -125,36 +295,40 @@ impl<'db> State<'db> {
                     }
                     None => {
                         self.position = range.end;
-
-                        break;
                     }
                 }
             }
         }
 
+        // The last chunk has no split of its own to decide its kind.
         let text_remaining = &text_all[self.position..];
-        self.push_chunk(text_remaining.len());
+        let kind = self.next_kind.unwrap_or(self.config.last_kind(self.db));
+        self.push_chunk(text_remaining.len(), kind);
 
         assert_eq!(self.position, self.chunk_wip.chunk_start);
         assert!(self.chunk_wip.comments.is_empty());
         assert!(self.chunk_wip.strings.is_empty());
         assert!(self.chunk_wip.errors.is_empty());
+        assert!(self.chunk_wip.embedded.is_empty());
 
         Chunks::new(
             self.db,
             self.chunks,
+            self.offsets,
+            self.split_errors,
         )
     }
 
     fn step(
         &mut self,
-        try_chunk: Option<usize>,
+        try_chunk: Option<ChunkSplit>,
     ) {
         let chunk_offset = self.position.checked_sub(self.chunk_wip.chunk_start).X();
 
         match try_chunk {
-            Some(chunk_extra_bytes) => {
-                self.push_chunk(chunk_extra_bytes);
+            Some(split) => {
+                self.push_chunk(split.eat_bytes, split.kind);
+                self.next_kind = split.next_kind;
             }
             None => {
                 self.position = self.position.checked_add(1).X();
@@ -164,13 +338,15 @@ impl<'db> State<'db> {
         }
     }
 
-    fn push_chunk(&mut self, eat_bytes: usize) {
+    fn push_chunk(&mut self, eat_bytes: usize, kind: ChunkKind) {
         self.position = self.position.checked_add(eat_bytes).X();
+        self.fix_split();
         self.collect_ranges();
         let text_all = self.chunk_in.text(self.db).as_str(self.db);
         assert!(self.position <= text_all.len());
         let chunk_text = &text_all[self.chunk_wip.chunk_start..self.position];
         if !chunk_text.is_empty() {
+            self.offsets.push(self.chunk_wip.chunk_start);
             self.chunks.push(
                 Chunk::new(
                     self.db,
@@ -178,13 +354,42 @@ impl<'db> State<'db> {
                     mem::take(&mut self.chunk_wip.comments),
                     mem::take(&mut self.chunk_wip.strings),
                     mem::take(&mut self.chunk_wip.errors),
+                    mem::take(&mut self.chunk_wip.error_reasons),
+                    mem::take(&mut self.chunk_wip.embedded),
+                    kind,
                 )
             );
         }
         self.chunk_wip.chunk_start = self.position;
     }
 
-    fn try_chunk(&self, text: &str) -> Option<usize> {
+    /// Move a split that would cut the text badly, see `ChunkSplitError`.
+    fn fix_split(&mut self) {
+        let text_all = self.chunk_in.text(self.db).as_str(self.db);
+        let split = self.position;
+        if split > text_all.len() {
+            self.split_errors.push(ChunkSplitError::PastEnd { split });
+            self.position = text_all.len();
+            return;
+        }
+        if !text_all.is_char_boundary(split) {
+            self.split_errors.push(ChunkSplitError::InsideChar { split });
+            self.position = (split..text_all.len()).find(|&i| text_all.is_char_boundary(i)).unwrap_or(text_all.len());
+        }
+        // Ranges don't overlap, so one move is enough.
+        let position = self.position;
+        let straddled = [&self.comments_iter, &self.strings_iter, &self.errors_iter, &self.embedded_iter]
+            .into_iter()
+            .filter_map(|iter| iter.clone().find(|range| range.end > position))
+            .find(|range| range.start < position)
+            .cloned();
+        if let Some(range) = straddled {
+            self.position = range.end;
+            self.split_errors.push(ChunkSplitError::Straddles { split, range });
+        }
+    }
+
+    fn try_chunk(&self, text: &str) -> Option<ChunkSplit> {
         let start_char = text.chars().next().X();
         if self.config.chunk_start_chars(self.db).contains(&start_char) {
             self.config.try_chunk(self.db)(text)
@@ -201,30 +406,39 @@ impl<'db> State<'db> {
              &mut self.chunk_wip.strings),
             (&mut self.errors_iter,
              &mut self.chunk_wip.errors),
+            (&mut self.embedded_iter,
+             &mut self.chunk_wip.embedded),
         ];
         for (iter, vec) in configs {
             while let Some(range) = iter.peek() {
                 if range.start >= self.position {
                     break;
                 }
+                // `fix_split` keeps ranges in one chunk.
                 assert!(range.end <= self.position);
                 vec.push(
-                    iter.next().X().clone().checked_sub(self.chunk_wip.chunk_start).expect("poo")
+                    iter.next().X().clone().checked_sub(self.chunk_wip.chunk_start).X()
                 );
             }
         }
+
+        // Each error collected above has its reason next.
+        let reasons_needed = self.chunk_wip.errors.len().checked_sub(self.chunk_wip.error_reasons.len()).X();
+        for reason in self.error_reasons_iter.by_ref().take(reasons_needed) {
+            self.chunk_wip.error_reasons.push(reason.checked_sub(self.chunk_wip.chunk_start).X());
+        }
     }
 }
 
 #[test]
 fn test_source_map() {
-    fn chunk<'db>(db: &'db dyn crate::Db, s: &str) -> Chunks<'db> {
+    fn chunk<'db>(db: &'db dyn crate::Db, s: &str) -> (Chunk<'db>, Chunks<'db>) {
         use crate::input::Source;
         use crate::source_map::basic_source_map;
 
         let source = Source::new(db, S(s));
         let full_chunk = basic_source_map(db, source);
-        basic_chunks(db, full_chunk)
+        (full_chunk, basic_chunks(db, full_chunk))
     }
 
     // "F"ragment
@@ -261,7 +475,9 @@ fn test_source_map() {
         let db = &crate::Database::default();
         let text = source(frags);
         eprintln!("t '{text}'");
-        let map = chunk(db, &text);
+        let (full_chunk, map) = chunk(db, &text);
+        assert_eq!(map.check(db, full_chunk), Ok(()));
+        assert!(map.split_errors(db).is_empty());
 
         // Grouping on dots
         let ex_chunks = frags.iter().peekable()
@@ -401,3 +617,150 @@ fn test_source_map() {
         F::E("/*/**/ab"),
     ]);
 }
+
+#[test]
+fn test_error_reasons() {
+    use crate::input::Source;
+    use crate::source_map::basic_source_map;
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S("a. /* b"));
+    let chunks = basic_chunks(db, basic_source_map(db, source));
+    let [first, second] = chunks.chunks(db).as_slice() else { panic!() };
+    assert!(first.error_reasons(db).is_empty());
+    assert_eq!(second.errors(db).first(), Some(&(1..5)));
+    assert_eq!(second.error_reasons(db), &vec![
+        ErrorReason::UnterminatedBlockComment { open_span: 1..3, depth: 1 },
+    ]);
+}
+
+#[test]
+fn test_chunk_kinds() {
+    // `@` opens a data block that runs to the next split.
+    fn kind_try_chunk(text: &str) -> Option<ChunkSplit> {
+        let (kind, next_kind) = match text.as_bytes()[0] {
+            b'.' => (ChunkKind::Code, None),
+            b'!' => (ChunkKind::Directive, None),
+            b'@' => (ChunkKind::Code, Some(ChunkKind::Data)),
+            _ => unreachable!(),
+        };
+        Some(ChunkSplit { eat_bytes: 1, kind, next_kind })
+    }
+
+    #[salsa::tracked]
+    fn kinds<'db>(
+        db: &'db dyn crate::Db,
+        source: crate::input::Source,
+        last_kind: ChunkKind,
+    ) -> Vec<(String, ChunkKind)> {
+        let full_chunk = crate::source_map::basic_source_map(db, source);
+        assert_eq!(full_chunk.kind(db), ChunkKind::Code);
+        let config = Config::new(db, vec!['.', '!', '@'], kind_try_chunk, last_kind);
+        chunks(db, full_chunk, config).chunks(db).iter().map(|chunk| {
+            (S(chunk.text(db).as_str(db)), chunk.kind(db))
+        }).collect()
+    }
+
+    let ref db = crate::Database::default();
+    let kinds_of = |text: &str, last_kind| kinds(db, crate::input::Source::new(db, S(text)), last_kind);
+    assert_eq!(kinds_of("use x! a. b", ChunkKind::Code), vec![
+        (S("use x!"), ChunkKind::Directive),
+        (S(" a."), ChunkKind::Code),
+        (S(" b"), ChunkKind::Code),
+    ]);
+    assert_eq!(kinds_of("a. b", ChunkKind::Data), vec![
+        (S("a."), ChunkKind::Code),
+        (S(" b"), ChunkKind::Data),
+    ]);
+    // The file ends in a data block.
+    assert_eq!(kinds_of("a@ 1, 2", ChunkKind::Code), vec![
+        (S("a@"), ChunkKind::Code),
+        (S(" 1, 2"), ChunkKind::Data),
+    ]);
+    // A data block ended by a split takes that split's kind.
+    assert_eq!(kinds_of("a@ 1. b", ChunkKind::Code), vec![
+        (S("a@"), ChunkKind::Code),
+        (S(" 1."), ChunkKind::Code),
+        (S(" b"), ChunkKind::Code),
+    ]);
+}
+
+#[test]
+fn test_split_errors() {
+    // Split three bytes after each `;`, whatever is there.
+    fn greedy_try_chunk(text: &str) -> Option<ChunkSplit> {
+        Some(ChunkSplit { eat_bytes: 3, kind: ChunkKind::Code, next_kind: None })
+    }
+
+    #[salsa::tracked]
+    fn split<'db>(
+        db: &'db dyn crate::Db,
+        source: crate::input::Source,
+    ) -> (Vec<String>, Vec<ChunkSplitError>) {
+        let full_chunk = crate::source_map::basic_source_map(db, source);
+        let config = Config::new(db, vec![';'], greedy_try_chunk, ChunkKind::Code);
+        let chunks = chunks(db, full_chunk, config);
+        assert_eq!(chunks.check(db, full_chunk), Ok(()));
+        let texts = chunks.chunks(db).iter()
+            .map(|chunk| S(chunk.text(db).as_str(db)))
+            .collect();
+        (texts, chunks.split_errors(db).C())
+    }
+
+    let ref db = crate::Database::default();
+    let split_of = |text: &str| split(db, crate::input::Source::new(db, S(text)));
+
+    assert_eq!(split_of("a;bcd"), (vec![S("a;bc"), S("d")], vec![]));
+    // The comment stays whole, in the first chunk.
+    assert_eq!(split_of("a;/* ; */b;cd"), (
+        vec![S("a;/* ; */"), S("b;cd")],
+        vec![ChunkSplitError::Straddles { split: 4, range: 2..9 }],
+    ));
+    assert_eq!(split_of("a;\"x\";\"y\""), (
+        vec![S("a;\"x\""), S(";\"y\"")],
+        vec![
+            ChunkSplitError::Straddles { split: 4, range: 2..5 },
+            ChunkSplitError::Straddles { split: 8, range: 6..9 },
+        ],
+    ));
+    assert_eq!(split_of("a;b"), (vec![S("a;b")], vec![ChunkSplitError::PastEnd { split: 4 }]));
+    assert_eq!(split_of("a;bé"), (vec![S("a;bé")], vec![ChunkSplitError::InsideChar { split: 4 }]));
+}
+
+#[test]
+fn test_check() {
+    #[salsa::tracked]
+    fn check_other<'db>(
+        db: &'db dyn crate::Db,
+        source: crate::input::Source,
+        other: crate::input::Source,
+    ) -> Result<(), ChunksInvariantError> {
+        let chunks = basic_chunks(db, crate::source_map::basic_source_map(db, source));
+        chunks.check(db, crate::source_map::basic_source_map(db, other))
+    }
+
+    let ref db = crate::Database::default();
+    let source = |text: &str| crate::input::Source::new(db, S(text));
+    assert_eq!(check_other(db, source("a. b"), source("a. b")), Ok(()));
+    assert_eq!(check_other(db, source(""), source("")), Ok(()));
+    assert_eq!(check_other(db, source("a. b"), source("a. c")), Err(ChunksInvariantError::TextMismatch));
+}
+
+#[test]
+fn test_offsets() {
+    use crate::input::Source;
+    use crate::source_map::basic_source_map;
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S("ab. c. // .\nd"));
+    let chunks = basic_chunks(db, basic_source_map(db, source));
+    assert_eq!(chunks.offsets(db), &vec![0, 3, 6]);
+    let at = |offset| chunks.chunk_at_offset(db, offset);
+    assert_eq!((at(0), at(2), at(3), at(5), at(6)), (Some(0), Some(0), Some(1), Some(1), Some(2)));
+    assert_eq!((at(13), at(14)), (Some(2), None));
+
+    let source = Source::new(db, S(""));
+    let chunks = basic_chunks(db, basic_source_map(db, source));
+    assert_eq!(chunks.offsets(db), &Vec::<usize>::new());
+    assert_eq!(chunks.chunk_at_offset(db, 0), None);
+}
@@ -0,0 +1,190 @@
+//! Association of comments with statements.
+//!
+//! Until there is a parser, a statement is a line of the brace tree
+//! that contains anything other than whitespace and comments.
+//! Lines inside a branch are statements of that branch,
+//! so comments inside braces attach to statements inside the same braces.
+//!
+//! Each comment is attached by these rules, in order:
+//!
+//! - A comment between a statement's first and last tokens
+//!   is an inner comment of that statement.
+//! - A comment after a statement's tokens on the same line
+//!   is a trailing comment of that statement.
+//! - Otherwise it is a leading comment of the next statement
+//!   in the same branch, which may be on the same line
+//!   or any later line.
+//! - Otherwise, when no statement follows, it is detached.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+use rmx::std::mem;
+
+use crate::bracer::{Bracer, BracerIter, TreeToken};
+use crate::lexer::{Token, TokenKind};
+use crate::text::ByteSpan;
+
+/// The comments of a brace tree, by the statement they are attached to.
+#[derive(Clone, Debug, Default, Eq, PartialEq, salsa::Update)]
+pub struct CommentAttachments<'db> {
+    /// The statements with comments, keyed by where the statement starts.
+    pub statements: BTreeMap<usize, StatementComments<'db>>,
+    /// Comments with no statement after them in their branch,
+    /// in source order.
+    pub detached: Vec<Token<'db>>,
+}
+
+/// The comments attached to one statement, each in source order.
+#[derive(Clone, Debug, Default, Eq, PartialEq, salsa::Update)]
+pub struct StatementComments<'db> {
+    /// From the statement's first token to its last.
+    pub span: ByteSpan,
+    pub leading: Vec<Token<'db>>,
+    pub inner: Vec<Token<'db>>,
+    pub trailing: Vec<Token<'db>>,
+}
+
+/// Attach every comment in `bracer` to a statement.
+#[salsa::tracked(returns(ref))]
+pub fn comment_attachments<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> CommentAttachments<'db> {
+    let mut attachments = CommentAttachments::default();
+    attach_in(db, bracer.iter(db), &mut attachments);
+    attachments.detached.sort_by_key(|comment| comment.text(db).range(db).start);
+    attachments
+}
+
+fn attach_in<'db>(
+    db: &'db dyn crate::Db,
+    iter: BracerIter<'db>,
+    attachments: &mut CommentAttachments<'db>,
+) {
+    let mut pending: Vec<Token<'db>> = vec![];
+
+    for line in iter.lines() {
+        let mut statement: Option<ByteSpan> = None;
+        let mut inner: Vec<Token<'db>> = vec![];
+        // Comments after the statement's last token so far.
+        let mut trailing: Vec<Token<'db>> = vec![];
+
+        for tree_token in line {
+            match &tree_token {
+                TreeToken::Token(token) if token.kind(db) == TokenKind::Comment => {
+                    if statement.is_some() {
+                        trailing.push(*token);
+                    } else {
+                        pending.push(*token);
+                    }
+                    continue;
+                }
+                TreeToken::Token(token) if token.kind(db) == TokenKind::Whitespace => {
+                    continue;
+                }
+                TreeToken::Token(_) => {}
+                TreeToken::Branch(_, branch_iter) => {
+                    let branch_iter = branch_iter.C();
+                    rmx::extras::recurse(|| {
+                        attach_in(db, branch_iter, attachments)
+                    });
+                }
+            }
+
+            inner.append(&mut trailing);
+            let span = tree_token.text_span(db).X().span;
+            statement = Some(match statement {
+                Some(statement) => statement.start..span.end,
+                None => span,
+            });
+        }
+
+        let Some(statement) = statement else {
+            continue;
+        };
+        if pending.is_empty() && inner.is_empty() && trailing.is_empty() {
+            continue;
+        }
+        attachments.statements.insert(statement.start, StatementComments {
+            span: statement,
+            leading: mem::take(&mut pending),
+            inner,
+            trailing,
+        });
+    }
+
+    attachments.detached.extend(pending);
+}
+
+#[cfg(test)]
+fn dbgcomments(s: &str) -> Vec<(String, String)> {
+    let ref db = crate::Database::default();
+    let bracer = crate::bracer::bracer_for_str(db, s);
+    let attachments = comment_attachments(db, bracer);
+    let mut comments = vec![];
+    for statement in attachments.statements.values() {
+        let kinds = [
+            ("leading", &statement.leading),
+            ("inner", &statement.inner),
+            ("trailing", &statement.trailing),
+        ];
+        for (kind, tokens) in kinds {
+            for token in tokens {
+                comments.push((*token, format!("{kind} {:?}", &s[statement.span.C()])));
+            }
+        }
+    }
+    comments.extend(attachments.detached.iter().map(|token| (*token, S("detached"))));
+    comments.sort_by_key(|(token, _)| token.text(db).range(db).start);
+    comments.into_iter()
+        .map(|(token, attachment)| (S(token.text(db).as_str(db)), attachment))
+        .collect()
+}
+
+#[test]
+fn test_comment_attachments() {
+    let ca = |comment: &str, attachment: &str| (S(comment), S(attachment));
+
+    assert_eq!(dbgcomments(""), vec![]);
+    assert_eq!(dbgcomments("a b"), vec![]);
+    assert_eq!(
+        dbgcomments("// c\na b"),
+        vec![ca("// c", "leading \"a b\"")],
+    );
+    assert_eq!(
+        dbgcomments("a b // c\n"),
+        vec![ca("// c", "trailing \"a b\"")],
+    );
+    assert_eq!(
+        dbgcomments("// c1\n\n// c2\na // c3\nb\n// c4\n"),
+        vec![
+            ca("// c1", "leading \"a\""),
+            ca("// c2", "leading \"a\""),
+            ca("// c3", "trailing \"a\""),
+            ca("// c4", "detached"),
+        ],
+    );
+    assert_eq!(
+        dbgcomments("/* c1 */ a /* c2 */ b"),
+        vec![
+            ca("/* c1 */", "leading \"a /* c2 */ b\""),
+            ca("/* c2 */", "inner \"a /* c2 */ b\""),
+        ],
+    );
+    assert_eq!(
+        dbgcomments("a /* c1 */ b /* c2 */ c // c3\n"),
+        vec![
+            ca("/* c1 */", "inner \"a /* c1 */ b /* c2 */ c\""),
+            ca("/* c2 */", "inner \"a /* c1 */ b /* c2 */ c\""),
+            ca("// c3", "trailing \"a /* c1 */ b /* c2 */ c\""),
+        ],
+    );
+    assert_eq!(
+        dbgcomments("f {\n  // c1\n  x\n  // c2\n} // c3\n"),
+        vec![
+            ca("// c1", "leading \"x\""),
+            ca("// c2", "detached"),
+            ca("// c3", "trailing \"f {\\n  // c1\\n  x\\n  // c2\\n}\""),
+        ],
+    );
+}
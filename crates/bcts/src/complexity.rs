@@ -0,0 +1,142 @@
+//! Where a source is densest.
+//!
+//! Large data documents are hard to read where braces nest deeply
+//! or a single branch holds a great many tokens.
+//! `complexity_report` finds both, for the CLI `stats --complexity` heatmap
+//! and for editors shading the gutter.
+
+use rmx::prelude::*;
+
+use crate::bracer::{basic_bracer, TreeToken};
+use crate::input::Source;
+use crate::lexer::TokenKind;
+use crate::line_index::LineIndex;
+use crate::text::ByteSpan;
+
+#[derive(Clone, Debug, Default, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct ComplexityReport {
+    /// The deepest brace depth of any token on each line,
+    /// with lines counted like `LineIndex`.
+    ///
+    /// Top-level tokens are at depth 0, and a branch's braces
+    /// are at the depth of the tokens around it.
+    /// Whitespace is left out, so a blank line is at depth 0.
+    pub line_depths: Vec<usize>,
+    /// Every branch, in source order.
+    pub branches: Vec<BranchComplexity>,
+}
+
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct BranchComplexity {
+    /// From the open brace to the close brace,
+    /// or to the last token of a branch without one.
+    pub span: ByteSpan,
+    /// The depth of the tokens in the branch, 1 for a top-level branch.
+    pub depth: usize,
+    /// The tokens between the braces, those of nested branches included,
+    /// not counting whitespace, comments, or the braces themselves.
+    pub tokens: usize,
+}
+
+impl ComplexityReport {
+    pub fn max_depth(&self) -> usize {
+        self.line_depths.iter().copied().max().unwrap_or(0)
+    }
+
+    /// The branches with the most tokens, most first,
+    /// ties in source order.
+    pub fn densest_branches(&self, count: usize) -> Vec<&BranchComplexity> {
+        let mut branches: Vec<_> = self.branches.iter().collect();
+        branches.sort_by_key(|branch| rmx::std::cmp::Reverse(branch.tokens));
+        branches.truncate(count);
+        branches
+    }
+}
+
+#[salsa::tracked(returns(ref))]
+pub fn complexity_report(
+    db: &dyn crate::Db,
+    source: Source,
+) -> ComplexityReport {
+    crate::metrics::record(|metrics| metrics.query_executed("complexity_report"));
+    let bracer = basic_bracer(db, source);
+    let line_index = LineIndex::new(source.text(db), 1);
+
+    let mut line_depths = vec![0; line_index.line_count()];
+    let mut mark = |span: ByteSpan, depth: usize| {
+        let last = span.end.saturating_sub(1).max(span.start);
+        for line_depth in &mut line_depths[line_index.line(span.start)..=line_index.line(last)] {
+            *line_depth = (*line_depth).max(depth);
+        }
+    };
+
+    let mut branches: Vec<BranchComplexity> = vec![];
+    // Each iterator, with the index in `branches` of the branch it iterates.
+    let mut stack: Vec<(_, Option<usize>)> = vec![(bracer.iter(db), None)];
+    while let Some(depth) = stack.len().checked_sub(1) {
+        let (iter, _) = &mut stack[depth];
+        match iter.next() {
+            Some(TreeToken::Token(token)) => {
+                let kind = token.kind(db);
+                if kind == TokenKind::Whitespace {
+                    continue;
+                }
+                mark(token.text(db).range(db), depth);
+                if kind != TokenKind::Comment {
+                    for index in stack.iter().filter_map(|(_, index)| *index) {
+                        let branch = &mut branches[index];
+                        branch.tokens = branch.tokens.checked_add(1).X();
+                    }
+                }
+            }
+            Some(TreeToken::Branch(_, iter)) => {
+                let span = iter.text_span().X();
+                let span = span.start()..span.end();
+                mark(span.start..span.start, depth);
+                mark(span.end..span.end, depth);
+                branches.push(BranchComplexity {
+                    span,
+                    depth: depth.checked_add(1).X(),
+                    tokens: 0,
+                });
+                stack.push((iter, Some(branches.len().checked_sub(1).X())));
+            }
+            None => {
+                stack.pop();
+            }
+        }
+    }
+
+    ComplexityReport { line_depths, branches }
+}
+
+#[test]
+fn test_complexity_report() {
+    let ref db = crate::Database::default();
+    let report = |s: &str| {
+        let source = Source::new(db, S(s));
+        complexity_report(db, source).C()
+    };
+
+    assert_eq!(report(""), ComplexityReport { line_depths: vec![0], branches: vec![] });
+
+    let nested = report("a = {\n  b = [1, (2)] // c\n\n}\nc\n");
+    assert_eq!(nested.line_depths, vec![0, 3, 0, 0, 0, 0]);
+    assert_eq!(nested.max_depth(), 3);
+    assert_eq!(nested.branches, vec![
+        BranchComplexity { span: 4..28, depth: 1, tokens: 5 },
+        BranchComplexity { span: 12..20, depth: 2, tokens: 3 },
+        BranchComplexity { span: 16..19, depth: 3, tokens: 1 },
+    ]);
+    let densest: Vec<_> = nested.densest_branches(2).iter().map(|branch| branch.tokens).collect();
+    assert_eq!(densest, vec![5, 3]);
+
+    // A branch without a close ends at its last token.
+    let unclosed = report("f(a,\n b");
+    assert_eq!(unclosed.line_depths, vec![1, 1]);
+    assert_eq!(unclosed.branches, vec![
+        BranchComplexity { span: 1..7, depth: 1, tokens: 3 },
+    ]);
+}
@@ -0,0 +1,180 @@
+//! Lints for identifiers that could be mistaken for others.
+//!
+//! A word that looks like another word in the same source
+//! but is spelled with different characters, like `nаme`
+//! with a Cyrillic `а`, can make a document say something
+//! other than what a reader sees.
+//! Words are compared by their skeleton,
+//! which replaces each non-ASCII letter that looks like an ASCII letter
+//! with that letter, as in Unicode's confusable detection (UTS #39).
+//!
+//! The first spelling of a skeleton in the source is taken as intended,
+//! except that the keys defined by `key_values` lines come first,
+//! so a use that doesn't match its definition is the one reported.
+//! Words that also mix scripts, like Latin and Cyrillic,
+//! are reported whether or not they are confusable with another word.
+//!
+//! Only the scripts most often mistaken for Latin are told apart;
+//! characters of other scripts are not checked.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+
+use crate::bracer::Bracer;
+use crate::diagnostics::{Code, Diagnostic};
+
+/// The scripts told apart when checking for mixed scripts.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub enum Script {
+    Latin,
+    Greek,
+    Cyrillic,
+    Armenian,
+    Hebrew,
+    Arabic,
+}
+
+impl Script {
+    /// The script of `ch`, if it is a letter of one of the scripts.
+    pub fn of(ch: char) -> Option<Script> {
+        match ch {
+            'a'..='z' | 'A'..='Z' => Some(Script::Latin),
+            '\u{D7}' | '\u{F7}' => None,
+            '\u{C0}'..='\u{24F}' | '\u{1E00}'..='\u{1EFF}' => Some(Script::Latin),
+            '\u{FF21}'..='\u{FF3A}' | '\u{FF41}'..='\u{FF5A}' => Some(Script::Latin),
+            '\u{370}'..='\u{3FF}' | '\u{1F00}'..='\u{1FFF}' => Some(Script::Greek),
+            '\u{400}'..='\u{52F}' => Some(Script::Cyrillic),
+            '\u{530}'..='\u{58F}' => Some(Script::Armenian),
+            '\u{590}'..='\u{5FF}' => Some(Script::Hebrew),
+            '\u{600}'..='\u{6FF}' => Some(Script::Arabic),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Script::Latin => "Latin",
+            Script::Greek => "Greek",
+            Script::Cyrillic => "Cyrillic",
+            Script::Armenian => "Armenian",
+            Script::Hebrew => "Hebrew",
+            Script::Arabic => "Arabic",
+        }
+    }
+}
+
+/// The scripts of the letters of `word`, in `Script` order.
+pub fn scripts(word: &str) -> Vec<Script> {
+    word.chars().filter_map(Script::of).sorted().dedup().collect()
+}
+
+/// `word` with its letters that look like ASCII letters replaced by them.
+///
+/// Two words with the same skeleton look alike.
+pub fn skeleton(word: &str) -> String {
+    word.chars().map(prototype).collect()
+}
+
+/// The ASCII letter `ch` looks like, or `ch`.
+fn prototype(ch: char) -> char {
+    match ch {
+        // Fullwidth forms.
+        '\u{FF21}'..='\u{FF3A}' | '\u{FF41}'..='\u{FF5A}' => {
+            char::from_u32(u32::from(ch).checked_sub(0xFEE0).X()).X()
+        }
+        // Latin.
+        'ı' => 'i',
+        // Cyrillic.
+        'а' => 'a', 'е' => 'e', 'о' => 'o', 'р' => 'p', 'с' => 'c', 'у' => 'y',
+        'х' => 'x', 'ѕ' => 's', 'і' => 'i', 'ј' => 'j', 'һ' => 'h', 'ԁ' => 'd',
+        'ԛ' => 'q', 'ԝ' => 'w', 'ӏ' => 'l',
+        'А' => 'A', 'В' => 'B', 'Е' => 'E', 'К' => 'K', 'М' => 'M', 'Н' => 'H',
+        'О' => 'O', 'Р' => 'P', 'С' => 'C', 'Т' => 'T', 'Х' => 'X', 'Ѕ' => 'S',
+        'І' => 'I', 'Ј' => 'J', 'Ү' => 'Y',
+        // Greek.
+        'α' => 'a', 'ο' => 'o', 'ν' => 'v', 'ι' => 'i', 'ρ' => 'p', 'υ' => 'u',
+        'ϲ' => 'c',
+        'Α' => 'A', 'Β' => 'B', 'Ε' => 'E', 'Ζ' => 'Z', 'Η' => 'H', 'Ι' => 'I',
+        'Κ' => 'K', 'Μ' => 'M', 'Ν' => 'N', 'Ο' => 'O', 'Ρ' => 'P', 'Τ' => 'T',
+        'Υ' => 'Y', 'Χ' => 'X',
+        _ => ch,
+    }
+}
+
+/// Diagnostics for confusable and mixed-script words, in source order.
+pub fn confusable_diagnostics<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> Vec<Diagnostic> {
+    // The intended spelling of each skeleton.
+    let mut spellings: BTreeMap<String, &str> = BTreeMap::new();
+    for key_value in crate::key_values::key_values(db, bracer) {
+        let key = key_value.key.text(db).as_str(db);
+        spellings.entry(skeleton(key)).or_insert(key);
+    }
+
+    let mut diagnostics = vec![];
+    for (_, token) in bracer.chunk(db).words(db) {
+        let word = token.text(db).as_str(db);
+        let span = Some(token.text(db).range(db));
+
+        let scripts = scripts(word);
+        if scripts.len() > 1 {
+            let names = scripts.iter().map(Script::name).join(" and ");
+            diagnostics.push(Diagnostic {
+                code: Code::MixedScriptIdentifier,
+                span: span.C(),
+                message: format!("`{word}` mixes {names} letters"),
+            });
+        }
+
+        let spelling = *spellings.entry(skeleton(word)).or_insert(word);
+        if spelling != word {
+            diagnostics.push(Diagnostic {
+                code: Code::ConfusableIdentifier,
+                span,
+                message: format!("`{word}` looks like `{spelling}` but is spelled differently"),
+            });
+        }
+    }
+
+    crate::metrics::record(|metrics| {
+        for diagnostic in &diagnostics {
+            metrics.diagnostic_emitted(diagnostic.code);
+        }
+    });
+    diagnostics
+}
+
+#[test]
+fn test_confusable_diagnostics() {
+    use crate::input::Source;
+
+    let ref db = crate::Database::default();
+    // `nаme` and `раypal` have Cyrillic letters,
+    // `Νame` a Greek capital nu.
+    let text = "\
+print(nаme)
+name = 1
+Νame = 2
+раypal = [nаme, name, мир]
+";
+    let bracer = crate::bracer::bracer_for_str(db, text);
+
+    let diagnostics: Vec<_> = confusable_diagnostics(db, bracer).into_iter()
+        .map(|diagnostic| (diagnostic.code, &text[diagnostic.span.X()], diagnostic.message))
+        .collect();
+    let d = |code, span, message: &str| (code, span, S(message));
+    use Code::{ConfusableIdentifier as Confusable, MixedScriptIdentifier as Mixed};
+    assert_eq!(diagnostics, vec![
+        d(Mixed, "nаme", "`nаme` mixes Latin and Cyrillic letters"),
+        d(Confusable, "nаme", "`nаme` looks like `name` but is spelled differently"),
+        d(Mixed, "Νame", "`Νame` mixes Latin and Greek letters"),
+        d(Mixed, "раypal", "`раypal` mixes Latin and Cyrillic letters"),
+        d(Mixed, "nаme", "`nаme` mixes Latin and Cyrillic letters"),
+        d(Confusable, "nаme", "`nаme` looks like `name` but is spelled differently"),
+    ]);
+
+    assert_eq!(skeleton("ｐаѕѕ"), "pass");
+    assert_eq!(scripts("мир_1"), vec![Script::Cyrillic]);
+}
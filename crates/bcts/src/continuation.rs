@@ -0,0 +1,49 @@
+//! Whether a source is complete.
+//!
+//! A REPL reads a line at a time,
+//! and must decide after each whether to evaluate what it has
+//! or prompt for another line.
+//! A source is incomplete if it ends inside a string or comment,
+//! or with braces still open.
+//! Errors that more input can't fix, like a stray close,
+//! don't make it incomplete, so they are reported right away.
+
+use crate::bracer::Balance;
+use crate::input::Source;
+
+/// Whether `source` is incomplete and more input could complete it.
+#[salsa::tracked]
+pub fn needs_more_input(
+    db: &dyn crate::Db,
+    source: Source,
+) -> bool {
+    let chunk = crate::source_map::basic_source_map(db, source);
+    // Every error of the source map is a region unterminated at the end.
+    if !chunk.errors(db).is_empty() {
+        return true;
+    }
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    crate::bracer::balance(db, chunk_lex) == Balance::Unclosed
+}
+
+#[test]
+fn test_needs_more_input() {
+    let ref db = crate::Database::default();
+    for (text, expected) in [
+        ("", false),
+        ("a = 1", false),
+        ("a = (1\n", true),
+        ("a = {\n  b = [1, 2]\n", true),
+        ("a = {\n  b = [1, 2]\n}", false),
+        ("a = \"one\n", true),
+        ("a = 1 /* note\n", true),
+        ("a = 1 /* note */ // )", false),
+        // Embedded regions are off in the basic config.
+        ("```sql\nselect\n", false),
+        ("a = (1]", false),
+        ("a = 1)", false),
+    ] {
+        let source = Source::new(db, rmx::prelude::S(text));
+        assert_eq!(needs_more_input(db, source), expected, "{text:?}");
+    }
+}
@@ -0,0 +1,1269 @@
+//! Reading typed data literals into values.
+//!
+//! A data document is a single typed literal: `:`, a type, `/` and a value.
+//!
+//! ```text
+//! : map<i64, f32> / map {: i64 / 0 = : f32 / 209.6, 59 = 12.8}
+//! ```
+//!
+//! The types and their values are:
+//!
+//! - `bool`: `true` or `false`.
+//! - `i8` to `i64`, `u8` to `u64`, and `int`, the same as `i64`:
+//!   ints as `numbers` reads them, like `-12`, `0xff` or `255u8`.
+//! - `f32` and `f64`: floats as `numbers` reads them, like `1.5`,
+//!   or ints without a suffix.
+//! - `string`: a string literal, with the escapes of `escapes`,
+//!   or several concatenated, either adjacent or joined by `++`,
+//!   like `"a" "b"` or `"a" ++ "b"`.
+//!   A heredoc, `<<END` and lines up to a line `END`,
+//!   is a string literal without escapes, for text kept as written.
+//! - `bytes`: a byte string literal, like `b"\x89PNG"`,
+//!   of ASCII and the escapes of `escapes::process_byte_escape_sequences`,
+//!   or several concatenated, like strings.
+//! - `datetime`: an RFC 3339 date or datetime, like `2024-01-01`
+//!   or `2024-01-01T08:30:00Z`, as `time_literals` reads it.
+//! - `duration`: a duration, like `15m30s`, as `time_literals` reads it.
+//! - `[T]`, a list: `[a, b]`.
+//! - `set<T>`: `set {a, b}`.
+//! - `map<K, V>`: `map {k = v, ...}`.
+//! - `(T, U, ...)`, a tuple: `(a, b, ...)`.
+//! - `{name: T, ...}`, a struct: `{name = v, ...}`,
+//!   with every field, in any order.
+//! - `enum {A, B(T, ...), ...}`: `enum A` or `enum B(t, ...)`.
+//!
+//! An element of a list, set, map, tuple or struct is either a value
+//! of the element type or a typed literal of that type,
+//! so `[1, : i64 / 2]` is a valid `[i64]`.
+//! A trailing comma is allowed after the last element.
+//! Any type or value may start with a `#` or `@` marker,
+//! as in `: #map<#i64, #f32> / #map {...}`, which is ignored.
+//!
+//! Values can be read into Rust types with serde,
+//! by `from_str` or by deserializing a `Value`:
+//! lists, sets and tuples are sequences, maps and structs are maps,
+//! enum variants are enum variants, with their fields as a tuple,
+//! and datetimes and durations are strings, as `Display` writes them
+//! and as `time_literals::format_duration` writes them.
+
+use rmx::prelude::*;
+use rmx::std::cell::RefCell;
+use rmx::std::collections::BTreeMap;
+use rmx::std::fmt;
+use rmx::std::iter;
+use serde::de::{self, IntoDeserializer};
+use serde::de::value::{MapAccessDeserializer, MapDeserializer, SeqDeserializer};
+
+use crate::bracer::{BracerIter, TreeToken};
+use crate::diagnostics::{Code, Diagnostic, Severity};
+use crate::input::Source;
+use crate::lexer::{Sigil, Token, TokenKind};
+use crate::numbers::{NumberError, NumberErrorKind};
+use crate::text::ByteSpan;
+use crate::time_literals::{TimeError, TimeErrorKind};
+
+pub use crate::numbers::IntType;
+pub use crate::time_literals::Datetime;
+
+/// The type of a data value.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Type {
+    Bool,
+    Int(IntType),
+    Float { bits: u32 },
+    String,
+    Bytes,
+    Datetime,
+    Duration,
+    List(Box<Type>),
+    Set(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    Tuple(Vec<Type>),
+    Struct(Vec<(String, Type)>),
+    /// Variants, each with the types of its fields.
+    Enum(Vec<(String, Vec<Type>)>),
+}
+
+/// A data value.
+///
+/// Set elements and map entries are in source order,
+/// struct fields in the order of the struct type.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    /// An int, always in the range of its type.
+    Int(i128, IntType),
+    Float(f64),
+    String(String),
+    Bytes(Vec<u8>),
+    Datetime(Datetime),
+    Duration(rmx::std::time::Duration),
+    List(Vec<Value>),
+    Set(Vec<Value>),
+    Map(Vec<(Value, Value)>),
+    Tuple(Vec<Value>),
+    Struct(Vec<(String, Value)>),
+    Enum { variant: String, fields: Vec<Value> },
+}
+
+/// A document's value and where each value inside it was written.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Document {
+    pub value: Value,
+    spans: BTreeMap<ValuePath, ByteSpan>,
+}
+
+impl Document {
+    /// The bytes of the source the value at `path` was read from.
+    pub fn span(&self, path: &ValuePath) -> Option<ByteSpan> {
+        self.spans.get(path).cloned()
+    }
+}
+
+/// Where a value is inside a document's value.
+///
+/// Written like `.servers[0].ports[1]`, or `.` for the document's value.
+#[derive(Clone, Debug, Default, Hash)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub struct ValuePath(pub Vec<PathSegment>);
+
+/// A step from a value into one of its elements.
+#[derive(Clone, Debug, Hash)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub enum PathSegment {
+    /// An element of a list, set or tuple, a field of an enum variant,
+    /// or the value of a map entry, by position.
+    Index(usize),
+    /// The key of a map entry, by position.
+    Key(usize),
+    /// A struct field.
+    Field(String),
+}
+
+impl ValuePath {
+    /// This path followed by `segment`.
+    pub fn join(&self, segment: PathSegment) -> ValuePath {
+        let mut path = self.C();
+        path.0.push(segment);
+        path
+    }
+}
+
+impl fmt::Display for ValuePath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return write!(f, ".");
+        }
+        for segment in &self.0 {
+            match segment {
+                PathSegment::Index(index) => write!(f, "[{index}]")?,
+                PathSegment::Key(index) => write!(f, "[key {index}]")?,
+                PathSegment::Field(name) => write!(f, ".{name}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Bool => write!(f, "bool"),
+            Type::Int(int_type) => write!(f, "{int_type}"),
+            Type::Float { bits } => write!(f, "f{bits}"),
+            Type::String => write!(f, "string"),
+            Type::Bytes => write!(f, "bytes"),
+            Type::Datetime => write!(f, "datetime"),
+            Type::Duration => write!(f, "duration"),
+            Type::List(element) => write!(f, "[{element}]"),
+            Type::Set(element) => write!(f, "set<{element}>"),
+            Type::Map(key, value) => write!(f, "map<{key}, {value}>"),
+            Type::Tuple(elements) => write!(f, "({})", elements.iter().join(", ")),
+            Type::Struct(fields) => {
+                let fields = fields.iter().map(|(name, ty)| format!("{name}: {ty}")).join(", ");
+                write!(f, "{{{fields}}}")
+            }
+            Type::Enum(variants) => {
+                let variants = variants.iter().map(|(name, fields)| match fields.is_empty() {
+                    true => name.C(),
+                    false => format!("{name}({})", fields.iter().join(", ")),
+                }).join(", ");
+                write!(f, "enum {{{variants}}}")
+            }
+        }
+    }
+}
+
+impl Value {
+    /// What kind of value this is, like "a list", for messages.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "a bool",
+            Value::Int(..) => "an int",
+            Value::Float(_) => "a float",
+            Value::String(_) => "a string",
+            Value::Bytes(_) => "a byte string",
+            Value::Datetime(_) => "a datetime",
+            Value::Duration(_) => "a duration",
+            Value::List(_) => "a list",
+            Value::Set(_) => "a set",
+            Value::Map(_) => "a map",
+            Value::Tuple(_) => "a tuple",
+            Value::Struct(_) => "a struct",
+            Value::Enum { .. } => "an enum",
+        }
+    }
+}
+
+/// Read a data document from text.
+///
+/// This is `from_source` with a database of its own,
+/// for programs using the data syntax without the rest of the compiler.
+pub fn read_data(text: &str) -> Result<Value, Vec<Diagnostic>> {
+    let ref db = crate::Database::default();
+    from_source(db, Source::new(db, S(text)))
+}
+
+/// Read a data document from text into any deserializable type.
+pub fn from_str<T: de::DeserializeOwned>(text: &str) -> Result<T, DataError> {
+    let value = read_data(text).map_err(DataError::Read)?;
+    T::deserialize(value)
+}
+
+/// Read the data document in `source`.
+///
+/// Fails with the brace errors of the source if it has any,
+/// and otherwise with every problem found in the literal, in source order.
+/// An element that doesn't match its type is reported at the element,
+/// or at its own annotation if that is the wrong type,
+/// and the elements after it are still checked.
+pub fn from_source(
+    db: &dyn crate::Db,
+    source: Source,
+) -> Result<Value, Vec<Diagnostic>> {
+    read_document(db, source).map(|document| document.value)
+}
+
+/// Read the data document in `source`,
+/// keeping the span of every value for later diagnostics.
+pub fn read_document(
+    db: &dyn crate::Db,
+    source: Source,
+) -> Result<Document, Vec<Diagnostic>> {
+    let reader = Reader { db, path: default(), spans: default(), errors: default() };
+    let tokens = reader.source_tokens(source)?;
+    let whole = 0..source.text(db).len();
+    let result = reader.typed_literal(&tokens, &whole);
+    let mut errors = reader.errors.take();
+    match result {
+        Ok((_, value)) if errors.is_empty() => {
+            return Ok(Document { value, spans: reader.spans.take() });
+        }
+        Ok(_) => {}
+        Err(error) => errors.push(error),
+    }
+    errors.sort_by_key(|error| error.span.as_ref().map(|span| span.start));
+    Err(report(errors))
+}
+
+/// Read a type written on its own, like `{name: string, ports: [u16]}`.
+pub fn read_type(
+    db: &dyn crate::Db,
+    source: Source,
+) -> Result<Type, Vec<Diagnostic>> {
+    let reader = Reader { db, path: default(), spans: default(), errors: default() };
+    let tokens = reader.source_tokens(source)?;
+    let whole = 0..source.text(db).len();
+    reader.ty(&tokens, &whole).map_err(|error| report(vec![error]))
+}
+
+/// The source map config of data documents,
+/// which take heredocs whatever a project's `[source_map]` says.
+#[salsa::tracked]
+fn data_config<'db>(
+    db: &'db dyn crate::Db,
+) -> crate::source_map::Config<'db> {
+    crate::source_map::Config::new(
+        db,
+        vec!['/'],
+        vec!['"'],
+        None,
+        true,
+    )
+}
+
+/// The lexer config of data documents,
+/// which take time literals whatever a project's `[lexer]` says.
+#[salsa::tracked]
+fn data_lexer_config<'db>(
+    db: &'db dyn crate::Db,
+) -> crate::lexer::Config<'db> {
+    crate::lexer::Config::new(
+        db,
+        crate::lexer::ErrorRecovery::default(),
+        crate::lexer::SigilSet::all(),
+        crate::lexer::LiteralRegistry::default(),
+        crate::lexer::WhitespaceMode::Joined,
+        true,
+    )
+}
+
+fn report(errors: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    for error in &errors {
+        crate::metrics::record(|metrics| metrics.diagnostic_emitted(error.code));
+    }
+    errors
+}
+
+struct Reader<'db> {
+    db: &'db dyn crate::Db,
+    /// The path of the value being read.
+    path: RefCell<ValuePath>,
+    spans: RefCell<BTreeMap<ValuePath, ByteSpan>>,
+    /// Errors in elements, which don't stop the rest from being read.
+    errors: RefCell<Vec<Diagnostic>>,
+}
+
+impl<'db> Reader<'db> {
+    /// The tokens of `source`, or its brace errors.
+    fn source_tokens(&self, source: Source) -> Result<Vec<TreeToken<'db>>, Vec<Diagnostic>> {
+        let chunk = crate::source_map::source_map(self.db, source, data_config(self.db));
+        let chunk_lex = crate::lexer::lex_chunk_with_config(self.db, chunk, data_lexer_config(self.db));
+        let bracer = crate::bracer::bracer(self.db, chunk_lex);
+        let errors: Vec<_> = crate::diagnostics::bracer_diagnostics(self.db, bracer).into_iter()
+            .filter(|diagnostic| diagnostic.code.default_severity() == Severity::Deny)
+            .collect();
+        match errors.is_empty() {
+            true => Ok(self.tree_tokens(bracer.iter(self.db))),
+            false => Err(errors),
+        }
+    }
+
+    /// `: type / value`, anywhere a typed literal may appear.
+    ///
+    /// `at` is where to report problems if `tokens` is empty.
+    fn typed_literal(
+        &self,
+        tokens: &[TreeToken<'db>],
+        at: &ByteSpan,
+    ) -> Result<(Type, Value), Diagnostic> {
+        let span = self.span(tokens, at);
+        let Some(rest) = tokens.strip_prefix_sigil(self.db, Sigil::Colon) else {
+            return Err(invalid(span, S("expected `:` and a type")));
+        };
+        let Some(slash) = rest.position_of_sigil(self.db, Sigil::SlashForward) else {
+            return Err(invalid(span, S("expected `/` and a value after the type")));
+        };
+        let ty = self.ty(&rest[..slash], &span)?;
+        let value = self.value(&rest[slash.checked_add(1).X()..], &ty, &span)?;
+        Ok((ty, value))
+    }
+
+    /// An element of a composite value, with or without its own type.
+    fn element(
+        &self,
+        tokens: &[TreeToken<'db>],
+        ty: &Type,
+        at: &ByteSpan,
+    ) -> Result<Value, Diagnostic> {
+        let Some(rest) = tokens.strip_prefix_sigil(self.db, Sigil::Colon) else {
+            return self.value(tokens, ty, at);
+        };
+        let (element_ty, value) = self.typed_literal(tokens, at)?;
+        if element_ty != *ty {
+            let slash = rest.position_of_sigil(self.db, Sigil::SlashForward).X();
+            let span = self.span(&rest[..slash], at);
+            return Err(mismatch(span, format!("expected a `{ty}` element, found a `{element_ty}`")));
+        }
+        Ok(value)
+    }
+
+    /// An element at `segment` inside the value being read.
+    ///
+    /// If the element is not valid, records why and returns `None`,
+    /// so the rest of the enclosing value is still checked.
+    fn element_at(
+        &self,
+        segment: PathSegment,
+        tokens: &[TreeToken<'db>],
+        ty: &Type,
+        at: &ByteSpan,
+    ) -> Option<Value> {
+        self.path.borrow_mut().0.push(segment);
+        let value = self.element(tokens, ty, at);
+        self.path.borrow_mut().0.pop();
+        value.map_err(|error| self.recover(error)).ok()
+    }
+
+    /// Record an error that doesn't stop the enclosing value from being read.
+    fn recover(&self, error: Diagnostic) {
+        self.errors.borrow_mut().push(error);
+    }
+
+    fn ty(
+        &self,
+        tokens: &[TreeToken<'db>],
+        at: &ByteSpan,
+    ) -> Result<Type, Diagnostic> {
+        let tokens = without_marker(self.db, tokens);
+        let span = self.span(tokens, at);
+        let params = |iter: &BracerIter<'db>, count: usize| -> Result<Vec<Type>, Diagnostic> {
+            let tokens = self.tree_tokens(iter.C());
+            let types = split(self.db, &tokens, Sigil::Comma).into_iter()
+                .map(|tokens| self.ty(tokens, &span))
+                .collect::<Result<Vec<_>, _>>()?;
+            if types.len() != count {
+                return Err(invalid(span.C(), format!("expected {count} type parameter(s), found {}", types.len())));
+            }
+            Ok(types)
+        };
+
+        match tokens {
+            [TreeToken::Token(token)] if token.kind(self.db) == TokenKind::Word => {
+                let name = token.text(self.db).as_str(self.db);
+                scalar_type(name).ok_or_else(|| invalid(span, format!("unknown type `{name}`")))
+            }
+            [TreeToken::Token(token), TreeToken::Branch(Sigil::AngleOpen, iter)] => {
+                match token.text(self.db).as_str(self.db) {
+                    "map" => {
+                        let mut params = params(iter, 2)?.into_iter();
+                        let key = params.next().X();
+                        let value = params.next().X();
+                        Ok(Type::Map(Box::new(key), Box::new(value)))
+                    }
+                    "set" => Ok(Type::Set(Box::new(params(iter, 1)?.pop().X()))),
+                    name => Err(invalid(span, format!("unknown type `{name}`"))),
+                }
+            }
+            [TreeToken::Token(token), TreeToken::Branch(Sigil::BraceOpen, iter)]
+                if token.text(self.db).as_str(self.db) == "enum" =>
+            {
+                let tokens = self.tree_tokens(iter.C());
+                let mut variants: Vec<(String, Vec<Type>)> = vec![];
+                for variant in split(self.db, &tokens, Sigil::Comma) {
+                    let variant_span = self.span(variant, &span);
+                    let Some((name, fields)) = self.variant(variant) else {
+                        return Err(invalid(variant_span, S("expected a variant")));
+                    };
+                    let fields = match fields {
+                        Some(iter) => {
+                            let tokens = self.tree_tokens(iter.C());
+                            split(self.db, &tokens, Sigil::Comma).into_iter()
+                                .map(|tokens| self.ty(tokens, &variant_span))
+                                .collect::<Result<Vec<_>, _>>()?
+                        }
+                        None => vec![],
+                    };
+                    if variants.iter().any(|(other, _)| *other == name) {
+                        return Err(invalid(variant_span, format!("duplicate variant `{name}`")));
+                    }
+                    variants.push((name, fields));
+                }
+                Ok(Type::Enum(variants))
+            }
+            [TreeToken::Branch(Sigil::BracketOpen, iter)] => {
+                Ok(Type::List(Box::new(params(iter, 1)?.pop().X())))
+            }
+            [TreeToken::Branch(Sigil::ParenOpen, iter)] => {
+                let tokens = self.tree_tokens(iter.C());
+                let types = split(self.db, &tokens, Sigil::Comma).into_iter()
+                    .map(|tokens| self.ty(tokens, &span))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Type::Tuple(types))
+            }
+            [TreeToken::Branch(Sigil::BraceOpen, iter)] => {
+                let tokens = self.tree_tokens(iter.C());
+                let mut fields: Vec<(String, Type)> = vec![];
+                for field in split(self.db, &tokens, Sigil::Comma) {
+                    let field_span = self.span(field, &span);
+                    let (name, ty) = match field {
+                        [TreeToken::Token(name), TreeToken::Token(colon), ty @ ..]
+                            if name.kind(self.db) == TokenKind::Word
+                            && colon.kind(self.db) == TokenKind::Sigil(Sigil::Colon) =>
+                        {
+                            (S(name.text(self.db).as_str(self.db)), self.ty(ty, &field_span)?)
+                        }
+                        _ => return Err(invalid(field_span, S("expected `name: type`"))),
+                    };
+                    if fields.iter().any(|(field_name, _)| *field_name == name) {
+                        return Err(invalid(field_span, format!("duplicate field `{name}`")));
+                    }
+                    fields.push((name, ty));
+                }
+                Ok(Type::Struct(fields))
+            }
+            _ => Err(invalid(span, S("expected a type"))),
+        }
+    }
+
+    fn value(
+        &self,
+        tokens: &[TreeToken<'db>],
+        ty: &Type,
+        at: &ByteSpan,
+    ) -> Result<Value, Diagnostic> {
+        let tokens = without_marker(self.db, tokens);
+        let span = self.span(tokens, at);
+        let expected = || mismatch(span.C(), format!("expected a `{ty}` value"));
+        self.spans.borrow_mut().insert(self.path.borrow().C(), span.C());
+
+        match ty {
+            Type::Bool | Type::Int(_) | Type::Float { .. } | Type::String | Type::Bytes
+                | Type::Datetime | Type::Duration => {
+                self.scalar(tokens, ty, &span)
+            }
+            Type::List(element) => {
+                let [TreeToken::Branch(Sigil::BracketOpen, iter)] = tokens else {
+                    return Err(expected());
+                };
+                Ok(Value::List(self.elements(iter, element, &span)))
+            }
+            Type::Set(element) => {
+                let Some(iter) = self.keyword_branch(tokens, "set") else {
+                    return Err(expected());
+                };
+                let tokens = self.tree_tokens(iter.C());
+                let mut elements = vec![];
+                for (index, tokens) in split(self.db, &tokens, Sigil::Comma).into_iter().enumerate() {
+                    let Some(value) = self.element_at(PathSegment::Index(index), tokens, element, &span) else {
+                        continue;
+                    };
+                    if elements.contains(&value) {
+                        self.recover(invalid(self.span(tokens, &span), S("duplicate set element")));
+                        continue;
+                    }
+                    elements.push(value);
+                }
+                Ok(Value::Set(elements))
+            }
+            Type::Map(key_ty, value_ty) => {
+                let Some(iter) = self.keyword_branch(tokens, "map") else {
+                    return Err(expected());
+                };
+                let tokens = self.tree_tokens(iter.C());
+                let mut entries: Vec<(Value, Value)> = vec![];
+                for (index, entry) in split(self.db, &tokens, Sigil::Comma).into_iter().enumerate() {
+                    let entry_span = self.span(entry, &span);
+                    let Some(equals) = entry.position_of_sigil(self.db, Sigil::Equals) else {
+                        self.recover(invalid(entry_span, S("expected `key = value`")));
+                        continue;
+                    };
+                    let key = self.element_at(PathSegment::Key(index), &entry[..equals], key_ty, &entry_span);
+                    let value_tokens = &entry[equals.checked_add(1).X()..];
+                    let value = self.element_at(PathSegment::Index(index), value_tokens, value_ty, &entry_span);
+                    let (Some(key), Some(value)) = (key, value) else {
+                        continue;
+                    };
+                    if entries.iter().any(|(other, _)| *other == key) {
+                        self.recover(invalid(self.span(&entry[..equals], &entry_span), S("duplicate map key")));
+                        continue;
+                    }
+                    entries.push((key, value));
+                }
+                Ok(Value::Map(entries))
+            }
+            Type::Tuple(types) => {
+                let [TreeToken::Branch(Sigil::ParenOpen, iter)] = tokens else {
+                    return Err(expected());
+                };
+                let tokens = self.tree_tokens(iter.C());
+                let elements = split(self.db, &tokens, Sigil::Comma);
+                if elements.len() != types.len() {
+                    return Err(mismatch(self.extra_span(&elements, types.len(), &span), format!(
+                        "expected {} tuple element(s), found {}", types.len(), elements.len(),
+                    )));
+                }
+                Ok(Value::Tuple(elements.into_iter().zip(types).enumerate()
+                    .filter_map(|(index, (tokens, ty))| self.element_at(PathSegment::Index(index), tokens, ty, &span))
+                    .collect()))
+            }
+            Type::Struct(fields) => {
+                let [TreeToken::Branch(Sigil::BraceOpen, iter)] = tokens else {
+                    return Err(expected());
+                };
+                let tokens = self.tree_tokens(iter.C());
+                // Fields whose values are not valid are `None`.
+                let mut values: Vec<(String, Option<Value>)> = vec![];
+                for field in split(self.db, &tokens, Sigil::Comma) {
+                    let field_span = self.span(field, &span);
+                    let (name, name_span, value) = match field {
+                        [TreeToken::Token(name), TreeToken::Token(equals), value @ ..]
+                            if name.kind(self.db) == TokenKind::Word
+                            && equals.kind(self.db) == TokenKind::Sigil(Sigil::Equals) =>
+                        {
+                            (name.text(self.db).as_str(self.db), name.text(self.db).range(self.db), value)
+                        }
+                        _ => {
+                            self.recover(invalid(field_span, S("expected `name = value`")));
+                            continue;
+                        }
+                    };
+                    let Some((_, field_ty)) = fields.iter().find(|(field_name, _)| field_name == name) else {
+                        self.recover(mismatch(name_span, format!("`{ty}` has no field `{name}`")));
+                        continue;
+                    };
+                    if values.iter().any(|(value_name, _)| value_name == name) {
+                        self.recover(invalid(name_span, format!("duplicate field `{name}`")));
+                        continue;
+                    }
+                    let value = self.element_at(PathSegment::Field(S(name)), value, field_ty, &field_span);
+                    values.push((S(name), value));
+                }
+                let mut ordered = vec![];
+                for (name, _) in fields {
+                    let Some(index) = values.iter().position(|(value_name, _)| value_name == name) else {
+                        return Err(mismatch(span, format!("missing field `{name}`")));
+                    };
+                    if let (name, Some(value)) = values.swap_remove(index) {
+                        ordered.push((name, value));
+                    }
+                }
+                Ok(Value::Struct(ordered))
+            }
+            Type::Enum(variants) => {
+                let (Some(TreeToken::Token(keyword)), Some((name, fields))) = (tokens.first(), self.variant(&tokens[1..])) else {
+                    return Err(expected());
+                };
+                if keyword.text(self.db).as_str(self.db) != "enum" {
+                    return Err(expected());
+                }
+                let Some((_, field_types)) = variants.iter().find(|(variant, _)| *variant == name) else {
+                    let variant = without_marker(self.db, &tokens[1..]);
+                    return Err(mismatch(self.span(&variant[..1], &span), format!("`{ty}` has no variant `{name}`")));
+                };
+                let tokens = fields.map(|iter| self.tree_tokens(iter.C())).unwrap_or_default();
+                let fields = split(self.db, &tokens, Sigil::Comma);
+                if fields.len() != field_types.len() {
+                    return Err(mismatch(self.extra_span(&fields, field_types.len(), &span), format!(
+                        "expected {} field(s) of variant `{name}`, found {}", field_types.len(), fields.len(),
+                    )));
+                }
+                let fields = fields.into_iter().zip(field_types).enumerate()
+                    .filter_map(|(index, (tokens, ty))| self.element_at(PathSegment::Index(index), tokens, ty, &span))
+                    .collect();
+                Ok(Value::Enum { variant: name, fields })
+            }
+        }
+    }
+
+    /// `Name` or `Name(...)`, in an enum type or value.
+    fn variant<'a>(
+        &self,
+        tokens: &'a [TreeToken<'db>],
+    ) -> Option<(String, Option<&'a BracerIter<'db>>)> {
+        let (name, fields) = match without_marker(self.db, tokens) {
+            [TreeToken::Token(name)] => (name, None),
+            [TreeToken::Token(name), TreeToken::Branch(Sigil::ParenOpen, iter)] => (name, Some(iter)),
+            _ => return None,
+        };
+        match name.kind(self.db) {
+            TokenKind::Word => Some((S(name.text(self.db).as_str(self.db)), fields)),
+            _ => None,
+        }
+    }
+
+    fn elements(
+        &self,
+        iter: &BracerIter<'db>,
+        ty: &Type,
+        at: &ByteSpan,
+    ) -> Vec<Value> {
+        let tokens = self.tree_tokens(iter.C());
+        split(self.db, &tokens, Sigil::Comma).into_iter().enumerate()
+            .filter_map(|(index, tokens)| self.element_at(PathSegment::Index(index), tokens, ty, at))
+            .collect()
+    }
+
+    /// A bool, number or string, written without spaces.
+    fn scalar(
+        &self,
+        tokens: &[TreeToken<'db>],
+        ty: &Type,
+        span: &ByteSpan,
+    ) -> Result<Value, Diagnostic> {
+        if *ty == Type::String && let Some(literals) = self.string_literals(tokens, TokenKind::String) {
+            return self.concat(&literals);
+        }
+        if *ty == Type::Bytes && let Some(literals) = self.string_literals(tokens, TokenKind::ByteString) {
+            return self.concat_bytes(&literals);
+        }
+
+        let mut text = String::new();
+        let mut end = None;
+        for tree_token in tokens {
+            let TreeToken::Token(token) = tree_token else {
+                return Err(mismatch(span.C(), format!("expected a `{ty}` value")));
+            };
+            let range = token.text(self.db).range(self.db);
+            if end.is_some_and(|end| end != range.start) {
+                return Err(invalid(span.C(), S("expected a single value")));
+            }
+            end = Some(range.end);
+            text.push_str(token.text(self.db).as_str(self.db));
+        }
+        if text.is_empty() {
+            return Err(invalid(span.C(), S("expected a value")));
+        }
+        let found = || mismatch(span.C(), format!("expected a `{ty}` value, found `{text}`"));
+
+        match ty {
+            Type::Bool => match text.as_str() {
+                "true" => Ok(Value::Bool(true)),
+                "false" => Ok(Value::Bool(false)),
+                _ => Err(found()),
+            },
+            Type::Int(int_type) => {
+                crate::numbers::parse_int(&text, span, *int_type)
+                    .map(|int| Value::Int(int, *int_type))
+                    .map_err(|error| number_error(error, &text, ty, found))
+            }
+            Type::Float { bits } => {
+                crate::numbers::parse_float(&text, span, *bits)
+                    .map(Value::Float)
+                    .map_err(|error| number_error(error, &text, ty, found))
+            }
+            Type::Datetime => {
+                crate::time_literals::parse_datetime(&text, span)
+                    .map(Value::Datetime)
+                    .map_err(|error| time_error(error, found))
+            }
+            Type::Duration => {
+                crate::time_literals::parse_duration(&text, span)
+                    .map(Value::Duration)
+                    .map_err(|error| time_error(error, found))
+            }
+            Type::String | Type::Bytes => Err(found()),
+            _ => bug!(),
+        }
+    }
+
+    /// The literals of a string or byte string value, of token kind `kind`,
+    /// if it is one or more of them, adjacent or joined by `++`.
+    fn string_literals(&self, tokens: &[TreeToken<'db>], kind: TokenKind) -> Option<Vec<Token<'db>>> {
+        let is_plus = |token: &Token<'db>| token.kind(self.db) == TokenKind::Sigil(Sigil::Plus);
+        let mut literals = vec![];
+        let mut rest = tokens;
+        loop {
+            let [TreeToken::Token(literal), after @ ..] = rest else {
+                return None;
+            };
+            let quoted = literal.text(self.db).as_str(self.db);
+            if literal.kind(self.db) != kind || quoted.len() < 2 {
+                return None;
+            }
+            literals.push(*literal);
+            rest = match after {
+                [] => return Some(literals),
+                [TreeToken::Token(first), TreeToken::Token(second), after @ ..]
+                    if is_plus(first) && is_plus(second)
+                    && first.text(self.db).range(self.db).end == second.text(self.db).range(self.db).start =>
+                {
+                    after
+                }
+                after => after,
+            };
+        }
+    }
+
+    /// The concatenated contents of `literals`,
+    /// reporting an invalid escape at its own literal.
+    fn concat(&self, literals: &[Token<'db>]) -> Result<Value, Diagnostic> {
+        let mut value = String::new();
+        for literal in literals {
+            let quoted = literal.text(self.db).as_str(self.db);
+            if let Some(Ok(heredoc)) = crate::source_map::parse_heredoc(quoted) {
+                value.push_str(&quoted[heredoc.content]);
+                continue;
+            }
+            let contents = &quoted[1..quoted.len().checked_sub(1).X()];
+            let contents = crate::escapes::process_escape_sequences(contents).map_err(|error| {
+                invalid(literal.text(self.db).range(self.db), format!("invalid string escape: {error:?}"))
+            })?;
+            value.push_str(&contents);
+        }
+        Ok(Value::String(value))
+    }
+
+    /// The concatenated bytes of byte string `literals`,
+    /// reporting an invalid escape at its own literal.
+    fn concat_bytes(&self, literals: &[Token<'db>]) -> Result<Value, Diagnostic> {
+        let mut value = vec![];
+        for literal in literals {
+            let quoted = literal.text(self.db).as_str(self.db);
+            let contents = &quoted[2..quoted.len().checked_sub(1).X()];
+            let contents = crate::escapes::process_byte_escape_sequences(contents).map_err(|error| {
+                invalid(literal.text(self.db).range(self.db), format!("invalid byte string escape: {error:?}"))
+            })?;
+            value.extend(contents);
+        }
+        Ok(Value::Bytes(value))
+    }
+
+    /// The branch of `keyword {...}`, as in `map {...}`.
+    fn keyword_branch<'a>(
+        &self,
+        tokens: &'a [TreeToken<'db>],
+        keyword: &str,
+    ) -> Option<&'a BracerIter<'db>> {
+        match tokens {
+            [TreeToken::Token(token), TreeToken::Branch(Sigil::BraceOpen, iter)]
+                if token.kind(self.db) == TokenKind::Word
+                && token.text(self.db).as_str(self.db) == keyword => Some(iter),
+            _ => None,
+        }
+    }
+
+    /// The tokens of a branch, without whitespace and comments.
+    fn tree_tokens(&self, iter: BracerIter<'db>) -> Vec<TreeToken<'db>> {
+        iter.filter_map(|tree_token| tree_token.without_space(self.db)).collect()
+    }
+
+    /// The elements after the first `expected`, if there are more,
+    /// or else `at`, the whole value missing some.
+    fn extra_span(&self, elements: &[&[TreeToken<'db>]], expected: usize, at: &ByteSpan) -> ByteSpan {
+        match (elements.get(expected), elements.last()) {
+            (Some(first), Some(last)) => {
+                self.span(first, at).start..self.span(last, at).end
+            }
+            _ => at.C(),
+        }
+    }
+
+    fn span(&self, tokens: &[TreeToken<'db>], at: &ByteSpan) -> ByteSpan {
+        match (tokens.first(), tokens.last()) {
+            (Some(first), Some(last)) => {
+                let first = first.text_span(self.db).X();
+                first.merge(&last.text_span(self.db).X()).X().span
+            }
+            _ => at.C(),
+        }
+    }
+}
+
+/// The words of data literals with a meaning of their own,
+/// other than type names.
+pub const KEYWORDS: &[&str] = &["true", "false", "set", "map", "enum"];
+
+/// The names of the scalar types.
+pub const SCALAR_TYPES: &[&str] = &[
+    "bool", "string", "bytes", "datetime", "duration", "int",
+    "i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64",
+    "f32", "f64",
+];
+
+fn scalar_type(name: &str) -> Option<Type> {
+    Some(match name {
+        "bool" => Type::Bool,
+        "string" => Type::String,
+        "bytes" => Type::Bytes,
+        "datetime" => Type::Datetime,
+        "duration" => Type::Duration,
+        "int" => Type::Int(IntType { signed: true, bits: 64 }),
+        "f32" => Type::Float { bits: 32 },
+        "f64" => Type::Float { bits: 64 },
+        _ => Type::Int(IntType::from_name(name)?),
+    })
+}
+
+/// The diagnostic for a number that is not a valid `ty`,
+/// with `found` for text that is not a number of its kind at all.
+fn number_error(
+    error: NumberError,
+    text: &str,
+    ty: &Type,
+    found: impl FnOnce() -> Diagnostic,
+) -> Diagnostic {
+    match error.kind {
+        NumberErrorKind::NotANumber | NumberErrorKind::NotAnInt => found(),
+        NumberErrorKind::IntOverflow { .. } | NumberErrorKind::FloatOverflow { .. } => {
+            mismatch(error.span, format!("`{text}` is out of range for `{ty}`"))
+        }
+        NumberErrorKind::SuffixMismatch { .. } => mismatch(error.span.C(), error.to_string()),
+        _ => invalid(error.span.C(), error.to_string()),
+    }
+}
+
+/// The diagnostic for a datetime or duration that is not valid,
+/// with `found` for text that is not one at all.
+fn time_error(error: TimeError, found: impl FnOnce() -> Diagnostic) -> Diagnostic {
+    match error.kind {
+        TimeErrorKind::NotADatetime | TimeErrorKind::NotADuration => found(),
+        _ => invalid(error.span.C(), error.to_string()),
+    }
+}
+
+fn without_marker<'a, 'db>(
+    db: &'db dyn crate::Db,
+    tokens: &'a [TreeToken<'db>],
+) -> &'a [TreeToken<'db>] {
+    tokens.strip_prefix_sigil(db, Sigil::Hash)
+        .or_else(|| tokens.strip_prefix_sigil(db, Sigil::At))
+        .filter(|rest| !rest.is_empty())
+        .unwrap_or(tokens)
+}
+
+/// Split at each `separator`, allowing one after the last piece.
+fn split<'a, 'db>(
+    db: &'db dyn crate::Db,
+    mut tokens: &'a [TreeToken<'db>],
+    separator: Sigil,
+) -> Vec<&'a [TreeToken<'db>]> {
+    let mut pieces = vec![];
+    while !tokens.is_empty() {
+        match tokens.position_of_sigil(db, separator) {
+            Some(index) => {
+                pieces.push(&tokens[..index]);
+                tokens = &tokens[index.checked_add(1).X()..];
+            }
+            None => {
+                pieces.push(tokens);
+                break;
+            }
+        }
+    }
+    pieces
+}
+
+#[extension_trait]
+impl<'db> TreeTokensExt<'db> for [TreeToken<'db>] {
+    fn position_of_sigil(&self, db: &'db dyn crate::Db, sigil: Sigil) -> Option<usize> {
+        self.iter().position(|tree_token| match tree_token {
+            TreeToken::Token(token) => token.kind(db) == TokenKind::Sigil(sigil),
+            TreeToken::Branch(..) => false,
+        })
+    }
+
+    fn strip_prefix_sigil(&self, db: &'db dyn crate::Db, sigil: Sigil) -> Option<&[TreeToken<'db>]> {
+        match self.position_of_sigil(db, sigil) {
+            Some(0) => Some(&self[1..]),
+            _ => None,
+        }
+    }
+}
+
+fn invalid(span: ByteSpan, message: String) -> Diagnostic {
+    Diagnostic { code: Code::InvalidDataLiteral, span: Some(span), message }
+}
+
+fn mismatch(span: ByteSpan, message: String) -> Diagnostic {
+    Diagnostic { code: Code::DataTypeMismatch, span: Some(span), message }
+}
+
+/// A failure to read a document into a Rust type, or to write one.
+#[derive(Clone, Debug, PartialEq)]
+pub enum DataError {
+    /// The document is not a valid data literal.
+    Read(Vec<Diagnostic>),
+    /// The value doesn't fit the Rust type.
+    Deserialize(String),
+    /// The value can't be written as a document, see `data_writer`.
+    Write(String),
+}
+
+impl fmt::Display for DataError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DataError::Read(diagnostics) => {
+                let config = crate::diagnostics::SeverityConfig::default();
+                let rendered = diagnostics.iter()
+                    .filter_map(|diagnostic| diagnostic.render(&config))
+                    .join("\n");
+                write!(f, "{rendered}")
+            }
+            DataError::Deserialize(message) | DataError::Write(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl rmx::std::error::Error for DataError {}
+
+impl de::Error for DataError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        DataError::Deserialize(message.to_string())
+    }
+}
+
+impl<'de> IntoDeserializer<'de, DataError> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Value {
+    type Error = DataError;
+
+    fn deserialize_any<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        match self {
+            Value::Bool(bool) => visitor.visit_bool(bool),
+            Value::Int(int, IntType { signed: true, .. }) => visitor.visit_i64(i64::try_from(int).X()),
+            Value::Int(int, IntType { signed: false, .. }) => visitor.visit_u64(u64::try_from(int).X()),
+            Value::Float(float) => visitor.visit_f64(float),
+            Value::String(string) => visitor.visit_string(string),
+            Value::Bytes(bytes) => visitor.visit_byte_buf(bytes),
+            Value::Datetime(datetime) => visitor.visit_string(datetime.to_string()),
+            Value::Duration(duration) => visitor.visit_string(crate::time_literals::format_duration(duration)),
+            Value::List(elements) | Value::Set(elements) | Value::Tuple(elements) => {
+                visitor.visit_seq(SeqDeserializer::new(elements.into_iter()))
+            }
+            Value::Map(entries) => visitor.visit_map(MapDeserializer::new(entries.into_iter())),
+            Value::Struct(fields) => visitor.visit_map(MapDeserializer::new(fields.into_iter())),
+            Value::Enum { variant, fields } => {
+                // A variant is read as a map of its name to its fields,
+                // and a variant with one field as that field.
+                let fields = match fields.len() {
+                    1 => fields.into_iter().next().X(),
+                    _ => Value::Tuple(fields),
+                };
+                let map = MapDeserializer::new(iter::once((variant, fields)));
+                visitor.visit_enum(MapAccessDeserializer::new(map))
+            }
+        }
+    }
+
+    /// Byte strings are sequences of `u8` too, as for `Vec<u8>`.
+    fn deserialize_seq<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        match self {
+            Value::Bytes(bytes) => visitor.visit_seq(SeqDeserializer::new(bytes.into_iter())),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_unit<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        match self {
+            Value::Tuple(elements) if elements.is_empty() => visitor.visit_unit(),
+            value => value.deserialize_any(visitor),
+        }
+    }
+
+    /// There is no null value, so an option is always `Some`.
+    /// Struct fields of option types may be left out instead.
+    fn deserialize_option<V: de::Visitor<'de>>(self, visitor: V) -> Result<V::Value, DataError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: de::Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, DataError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    serde::forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit_struct tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+#[test]
+fn test_read_data() {
+    let int = |int| Value::Int(int, IntType { signed: true, bits: 64 });
+    let int_of = |int, signed, bits| Value::Int(int, IntType { signed, bits });
+    let string = |s: &str| Value::String(S(s));
+
+    assert_eq!(read_data(": bool / true"), Ok(Value::Bool(true)));
+    assert_eq!(read_data(": i8 / -128"), Ok(int_of(-128, true, 8)));
+    assert_eq!(
+        read_data(": u64 / 18_446_744_073_709_551_615"),
+        Ok(int_of(18_446_744_073_709_551_615, false, 64)),
+    );
+    assert_eq!(read_data(": f32 / -209.5"), Ok(Value::Float(-209.5)));
+    assert_eq!(read_data(r#": string / "a\tb\u{e9}""#), Ok(string("a\tb\u{e9}")));
+    assert_eq!(read_data(r#": string / "a" "b"
+        // Comments between literals are trivia too.
+        ++ "c" ++"d""#), Ok(string("abcd")));
+    assert_eq!(
+        read_data(": string / <<END\n\"a\\q\"\r\n  END\nEND\n++ \"b\""),
+        Ok(string("\"a\\q\"\r\n  END\nb")),
+    );
+    assert_eq!(
+        read_data(r#": bytes / b"\x89PNG" ++ b"\r\n\x1a""#),
+        Ok(Value::Bytes(b"\x89PNG\r\n\x1a".to_vec())),
+    );
+    let datetime = crate::time_literals::parse_datetime("2024-01-01T08:30:00Z", &(0..20)).X();
+    assert_eq!(read_data(": datetime / 2024-01-01T08:30:00Z"), Ok(Value::Datetime(datetime)));
+    assert_eq!(
+        read_data(": duration / 15m30s"),
+        Ok(Value::Duration(rmx::std::time::Duration::from_secs(930))),
+    );
+    assert_eq!(
+        read_data(r#": [string] / ["a" "b", "c"]"#),
+        Ok(Value::List(vec![string("ab"), string("c")])),
+    );
+    assert_eq!(read_data(": [int] / [1, : int / 2, ]"), Ok(Value::List(vec![int(1), int(2)])));
+    assert_eq!(read_data(": [int] / []"), Ok(Value::List(vec![])));
+    assert_eq!(
+        read_data(": (i64, bool) / (: i64 / 99, false)"),
+        Ok(Value::Tuple(vec![int(99), Value::Bool(false)])),
+    );
+    assert_eq!(
+        read_data(r#": {y1: string, y2: [u8]} / {y2 = [180, 202], y1 = : string / "value"}"#),
+        Ok(Value::Struct(vec![
+            (S("y1"), string("value")),
+            (S("y2"), Value::List(vec![int_of(180, false, 8), int_of(202, false, 8)])),
+        ])),
+    );
+    assert_eq!(
+        read_data(": set<i32> / set {127, 63}"),
+        Ok(Value::Set(vec![int_of(127, true, 32), int_of(63, true, 32)])),
+    );
+    assert_eq!(
+        read_data(": #enum {Gen44(#i16), GenType17} / #enum Gen44(: #i16 / #63)"),
+        Ok(Value::Enum { variant: S("Gen44"), fields: vec![int_of(63, true, 16)] }),
+    );
+    assert_eq!(
+        read_data(": enum {A, B(i64)} / enum A"),
+        Ok(Value::Enum { variant: S("A"), fields: vec![] }),
+    );
+
+    // Markers and nested maps, as in the fuzz corpus.
+    assert_eq!(
+        read_data("\
+// Comments and newlines are allowed.
+: #map<#i64, #map<#string, #f32>> / #map {
+    : #i64 / #0 = #map {\"a\" = : #f32 / #209.6},
+    59 = map {},
+}
+"),
+        Ok(Value::Map(vec![
+            (int(0), Value::Map(vec![(string("a"), Value::Float(f64::from(209.6_f32)))])),
+            (int(59), Value::Map(vec![])),
+        ])),
+    );
+}
+
+#[test]
+fn test_read_data_errors() {
+    fn error(text: &str) -> (Code, String, String) {
+        let errors = read_data(text).err().X();
+        assert_eq!(errors.len(), 1);
+        let error = errors.into_iter().next().X();
+        (error.code, S(&text[error.span.X()]), error.message)
+    }
+    let e = |code, span: &str, message: &str| (code, S(span), S(message));
+    use Code::{InvalidDataLiteral as Invalid, DataTypeMismatch as Mismatch};
+
+    assert_eq!(error("true"), e(Invalid, "true", "expected `:` and a type"));
+    assert_eq!(error(": bool true"), e(Invalid, ": bool true", "expected `/` and a value after the type"));
+    assert_eq!(error(": i65 / 1"), e(Invalid, "i65", "unknown type `i65`"));
+    assert_eq!(error(": map<i64> / map {}"), e(Invalid, "map<i64>", "expected 2 type parameter(s), found 1"));
+    assert_eq!(error(": u8 / 256"), e(Mismatch, "256", "`256` is out of range for `u8`"));
+    assert_eq!(error(": i8 / -129"), e(Mismatch, "-129", "`-129` is out of range for `i8`"));
+    assert_eq!(error(": i64 / 1 2"), e(Invalid, "1 2", "expected a single value"));
+    assert_eq!(error(": bool / 1"), e(Mismatch, "1", "expected a `bool` value, found `1`"));
+    assert_eq!(error(": [i64] / [1, : f32 / 2]"), e(Mismatch, "f32", "expected a `i64` element, found a `f32`"));
+    assert_eq!(error(": [i64] / 1"), e(Mismatch, "1", "expected a `[i64]` value"));
+    assert_eq!(error(": map<i64, i64> / map {1 = 2, 1 = 3}"), e(Invalid, "1", "duplicate map key"));
+    assert_eq!(error(": map<i64, i64> / map {1}"), e(Invalid, "1", "expected `key = value`"));
+    assert_eq!(error(": (i64, i64) / (1)"), e(Mismatch, "(1)", "expected 2 tuple element(s), found 1"));
+    assert_eq!(error(": (i64) / (1, 2, 3)"), e(Mismatch, "2, 3", "expected 1 tuple element(s), found 3"));
+    assert_eq!(error(": {a: i64} / {}"), e(Mismatch, "{}", "missing field `a`"));
+    assert_eq!(error(": {a: i64} / {a = 1, b = 1}"), e(Mismatch, "b", "`{a: i64}` has no field `b`"));
+    assert_eq!(error(": enum {A} / enum B"), e(Mismatch, "B", "`enum {A}` has no variant `B`"));
+    assert_eq!(error(": enum {A(i64)} / enum A"), e(Mismatch, "enum A", "expected 1 field(s) of variant `A`, found 0"));
+    assert_eq!(error(r#": string / "\q""#), e(Invalid, r#""\q""#, "invalid string escape: InvalidEscape { position: 0, escape: 'q' }"));
+    assert_eq!(error(r#": string / "a" ++ "\q""#), e(Invalid, r#""\q""#, "invalid string escape: InvalidEscape { position: 0, escape: 'q' }"));
+    assert_eq!(error(r#": bytes / b"a" b"\x1""#), e(Invalid, r#"b"\x1""#, "invalid byte string escape: InvalidHexEscape { position: 1, reason: \"expected 2 hex digits, got \\\"1\\\"\" }"));
+    assert_eq!(error(r#": bytes / "a""#), e(Mismatch, r#""a""#, r#"expected a `bytes` value, found `"a"`"#));
+    assert_eq!(error(r#": string / b"a""#), e(Mismatch, r#"b"a""#, r#"expected a `string` value, found `b"a"`"#));
+    assert_eq!(error(r#": string / "a" ++"#), e(Invalid, r#""a" ++"#, "expected a single value"));
+    assert_eq!(error(r#": string / "a" + "b""#), e(Invalid, r#""a" + "b""#, "expected a single value"));
+    // Invalid datetimes and durations are lexing errors, reported instead.
+    assert_eq!(
+        error(": datetime / 2024-02-30"),
+        e(Code::InvalidDatetime, "30", "invalid datetime `2024-02-30`: day 30 is out of range for 2024-02"),
+    );
+    assert_eq!(error(": datetime / 15m"), e(Mismatch, "15m", "expected a `datetime` value, found `15m`"));
+    assert_eq!(error(": duration / 1s1s"), e(Code::InvalidDuration, "s", "invalid duration `1s1s`: unit `s` is repeated"));
+    assert_eq!(error(": duration / x"), e(Mismatch, "x", "expected a `duration` value, found `x`"));
+
+    // Every invalid element is reported, not only the first.
+    let text = ": map<i64, f32> / map {1 = 2.5, 300 = x, 3 = : f64 / 1.0, : i64 / 4 = 1}";
+    let errors: Vec<_> = read_data(text).err().X().into_iter()
+        .map(|error| (error.code, S(&text[error.span.X()]), error.message))
+        .collect();
+    assert_eq!(errors, vec![
+        e(Mismatch, "x", "expected a `f32` value, found `x`"),
+        e(Mismatch, "f64", "expected a `f32` element, found a `f64`"),
+    ]);
+    let text = ": {a: u8, b: [u8]} / {a = 300, b = [1, -1, 256], c = 0}";
+    let errors: Vec<_> = read_data(text).err().X().into_iter()
+        .map(|error| (S(&text[error.span.X()]), error.message))
+        .collect();
+    assert_eq!(errors, vec![
+        (S("300"), S("`300` is out of range for `u8`")),
+        (S("-1"), S("`-1` is out of range for `u8`")),
+        (S("256"), S("`256` is out of range for `u8`")),
+        (S("c"), S("`{a: u8, b: [u8]}` has no field `c`")),
+    ]);
+
+    // Brace errors are reported instead.
+    let errors = read_data(": [i64] / [1, 2").err().X();
+    assert_eq!(errors[0].code, Code::UnclosedBrace);
+}
+
+#[test]
+fn test_scalar_types() {
+    for name in SCALAR_TYPES {
+        let ty = scalar_type(name).X();
+        assert_eq!(ty.to_string(), if *name == "int" { "i64" } else { name });
+    }
+    assert_eq!(scalar_type("i128"), None);
+}
+
+#[test]
+fn test_deserialize() {
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        ports: Vec<u16>,
+        limits: rmx::std::collections::BTreeMap<String, f64>,
+        mode: Mode,
+        fallback: (Mode, Mode),
+        comment: Option<String>,
+    }
+
+    #[derive(serde::Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Off,
+        Level(u8),
+        Range(i32, i32),
+    }
+
+    let config: Config = from_str(r#"
+: {
+    name: string,
+    ports: [u16],
+    limits: map<string, f64>,
+    mode: enum {Off, Level(u8), Range(i32, i32)},
+    fallback: (enum {Off, Level(u8), Range(i32, i32)}, enum {Off, Level(u8), Range(i32, i32)}),
+} / {
+    name = "server",
+    ports = [80, 443],
+    limits = map {"cpu" = 0.5},
+    mode = enum Level(3),
+    fallback = (enum Off, enum Range(-1, 1)),
+}
+"#).X();
+    assert_eq!(config, Config {
+        name: S("server"),
+        ports: vec![80, 443],
+        limits: [(S("cpu"), 0.5)].into(),
+        mode: Mode::Level(3),
+        fallback: (Mode::Off, Mode::Range(-1, 1)),
+        comment: None,
+    });
+
+    assert!(matches!(from_str::<Config>(": u8 / 300"), Err(DataError::Read(_))));
+    let error = from_str::<u8>(": i64 / 300").err().X();
+    assert_eq!(error.to_string(), "invalid value: integer `300`, expected u8");
+
+    // Byte strings read as bytes or as sequences.
+    assert_eq!(from_str::<Vec<u8>>(r#": bytes / b"a\x00""#), Ok(vec![b'a', 0]));
+
+    // Datetimes and durations read as their text.
+    assert_eq!(from_str::<String>(": datetime / 2024-01-01t08:30:00.50z"), Ok(S("2024-01-01T08:30:00.5Z")));
+    assert_eq!(from_str::<String>(": duration / 90s"), Ok(S("1m30s")));
+}
@@ -0,0 +1,377 @@
+//! Schemas for data documents.
+//!
+//! A schema describes the values a document may hold,
+//! like a `Type` does, with constraints no type can express:
+//! int and float ranges, the strings a string may be,
+//! list lengths, and struct fields that may be left out.
+//!
+//! A schema is written in the type syntax of `data`,
+//! which gives the ranges of the int widths and requires every field,
+//!
+//! ```text
+//! {name: string, ports: [u16]}
+//! ```
+//!
+//! or built in Rust:
+//!
+//! ```
+//! use bcts::data_schema::Schema;
+//!
+//! let schema = Schema::record()
+//!     .field("name", Schema::one_of(["web", "db"]))
+//!     .optional_field("ports", Schema::list_len(Schema::int_range(1..=65535), 1..=4));
+//! ```
+//!
+//! Validation reports every violation in a document,
+//! each addressed by the `ValuePath` of the offending value
+//! and spanning the source it was read from.
+
+use rmx::prelude::*;
+use rmx::std::ops::RangeInclusive;
+
+use crate::data::{Document, IntType, PathSegment, Type, Value, ValuePath};
+use crate::diagnostics::{Code, Diagnostic};
+use crate::input::Source;
+
+/// The values a data document may hold.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Schema {
+    /// Any value.
+    Any,
+    Bool,
+    Int { range: Option<RangeInclusive<i128>> },
+    Float { range: Option<RangeInclusive<f64>> },
+    String { one_of: Option<Vec<String>> },
+    Bytes,
+    Datetime,
+    Duration,
+    List { element: Box<Schema>, len: Option<RangeInclusive<usize>> },
+    Set(Box<Schema>),
+    Map(Box<Schema>, Box<Schema>),
+    Tuple(Vec<Schema>),
+    Struct(Vec<FieldSchema>),
+    Enum(Vec<(String, Vec<Schema>)>),
+}
+
+/// A field of a struct schema.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FieldSchema {
+    pub name: String,
+    pub schema: Schema,
+    /// Whether a struct without the field is a violation.
+    pub required: bool,
+}
+
+impl Schema {
+    pub fn int() -> Schema {
+        Schema::Int { range: None }
+    }
+
+    pub fn int_range(range: RangeInclusive<i128>) -> Schema {
+        Schema::Int { range: Some(range) }
+    }
+
+    pub fn float() -> Schema {
+        Schema::Float { range: None }
+    }
+
+    pub fn float_range(range: RangeInclusive<f64>) -> Schema {
+        Schema::Float { range: Some(range) }
+    }
+
+    pub fn string() -> Schema {
+        Schema::String { one_of: None }
+    }
+
+    /// A string that must be one of `strings`.
+    pub fn one_of<'a>(strings: impl IntoIterator<Item = &'a str>) -> Schema {
+        Schema::String { one_of: Some(strings.into_iter().map(S).collect()) }
+    }
+
+    pub fn list(element: Schema) -> Schema {
+        Schema::List { element: Box::new(element), len: None }
+    }
+
+    pub fn list_len(element: Schema, len: RangeInclusive<usize>) -> Schema {
+        Schema::List { element: Box::new(element), len: Some(len) }
+    }
+
+    pub fn set(element: Schema) -> Schema {
+        Schema::Set(Box::new(element))
+    }
+
+    pub fn map(key: Schema, value: Schema) -> Schema {
+        Schema::Map(Box::new(key), Box::new(value))
+    }
+
+    /// A struct without fields, to add them to with `field`.
+    pub fn record() -> Schema {
+        Schema::Struct(vec![])
+    }
+
+    /// An enum without variants, to add them to with `variant`.
+    pub fn enumeration() -> Schema {
+        Schema::Enum(vec![])
+    }
+
+    /// Add a required field to a struct schema.
+    ///
+    /// Panics if this is not a struct schema.
+    pub fn field(self, name: &str, schema: Schema) -> Schema {
+        self.with_field(name, schema, true)
+    }
+
+    /// Add a field that may be left out to a struct schema.
+    ///
+    /// Panics if this is not a struct schema.
+    pub fn optional_field(self, name: &str, schema: Schema) -> Schema {
+        self.with_field(name, schema, false)
+    }
+
+    fn with_field(self, name: &str, schema: Schema, required: bool) -> Schema {
+        let Schema::Struct(mut fields) = self else {
+            panic!("field `{name}` added to a schema that is not a struct");
+        };
+        fields.push(FieldSchema { name: S(name), schema, required });
+        Schema::Struct(fields)
+    }
+
+    /// Add a variant to an enum schema.
+    ///
+    /// Panics if this is not an enum schema.
+    pub fn variant(self, name: &str, fields: Vec<Schema>) -> Schema {
+        let Schema::Enum(mut variants) = self else {
+            panic!("variant `{name}` added to a schema that is not an enum");
+        };
+        variants.push((S(name), fields));
+        Schema::Enum(variants)
+    }
+
+    /// The schema of the values of `ty`.
+    pub fn from_type(ty: &Type) -> Schema {
+        let all = |types: &[Type]| types.iter().map(Schema::from_type).collect();
+        match ty {
+            Type::Bool => Schema::Bool,
+            Type::Int(int_type) => Schema::int_range(int_type.range()),
+            Type::Float { .. } => Schema::float(),
+            Type::String => Schema::string(),
+            Type::Bytes => Schema::Bytes,
+            Type::Datetime => Schema::Datetime,
+            Type::Duration => Schema::Duration,
+            Type::List(element) => Schema::list(Schema::from_type(element)),
+            Type::Set(element) => Schema::set(Schema::from_type(element)),
+            Type::Map(key, value) => Schema::map(Schema::from_type(key), Schema::from_type(value)),
+            Type::Tuple(types) => Schema::Tuple(all(types)),
+            Type::Struct(fields) => Schema::Struct(fields.iter().map(|(name, ty)| FieldSchema {
+                name: name.C(),
+                schema: Schema::from_type(ty),
+                required: true,
+            }).collect()),
+            Type::Enum(variants) => Schema::Enum(variants.iter().map(|(name, types)| {
+                (name.C(), all(types))
+            }).collect()),
+        }
+    }
+
+    /// Read a schema written as a type, like `{name: string, ports: [u16]}`.
+    pub fn read(text: &str) -> Result<Schema, Vec<Diagnostic>> {
+        let ref db = crate::Database::default();
+        let ty = crate::data::read_type(db, Source::new(db, S(text)))?;
+        Ok(Schema::from_type(&ty))
+    }
+
+    /// Check `document` against the schema.
+    ///
+    /// Returns a diagnostic for every violation,
+    /// with a message starting with the path of the offending value.
+    pub fn validate(&self, document: &Document) -> Vec<Diagnostic> {
+        let mut validator = Validator { document, diagnostics: vec![] };
+        validator.check(self, &document.value, &default());
+        validator.diagnostics
+    }
+
+    /// What kind of value the schema describes, like "a list", for messages.
+    fn kind(&self) -> &'static str {
+        match self {
+            Schema::Any => "any value",
+            Schema::Bool => "a bool",
+            Schema::Int { .. } => "an int",
+            Schema::Float { .. } => "a float",
+            Schema::String { .. } => "a string",
+            Schema::Bytes => "a byte string",
+            Schema::Datetime => "a datetime",
+            Schema::Duration => "a duration",
+            Schema::List { .. } => "a list",
+            Schema::Set(_) => "a set",
+            Schema::Map(..) => "a map",
+            Schema::Tuple(_) => "a tuple",
+            Schema::Struct(_) => "a struct",
+            Schema::Enum(_) => "an enum",
+        }
+    }
+}
+
+struct Validator<'a> {
+    document: &'a Document,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn check(&mut self, schema: &Schema, value: &Value, path: &ValuePath) {
+        match (schema, value) {
+            (Schema::Any, _) | (Schema::Bool, Value::Bool(_)) | (Schema::Bytes, Value::Bytes(_))
+                | (Schema::Datetime, Value::Datetime(_)) | (Schema::Duration, Value::Duration(_)) => {}
+            (Schema::Int { range }, Value::Int(int, _)) => {
+                if let Some(range) = range && !range.contains(int) {
+                    self.violation(path, format!(
+                        "{int} is not in {}..={}", range.start(), range.end(),
+                    ));
+                }
+            }
+            (Schema::Float { range }, Value::Float(float)) => {
+                if let Some(range) = range && !range.contains(float) {
+                    self.violation(path, format!(
+                        "{float} is not in {}..={}", range.start(), range.end(),
+                    ));
+                }
+            }
+            (Schema::String { one_of }, Value::String(string)) => {
+                if let Some(one_of) = one_of && !one_of.contains(string) {
+                    let expected = one_of.iter().map(|s| format!("{s:?}")).join(", ");
+                    self.violation(path, format!("{string:?} is not one of {expected}"));
+                }
+            }
+            (Schema::List { element, len }, Value::List(values)) => {
+                if let Some(len) = len && !len.contains(&values.len()) {
+                    self.violation(path, format!(
+                        "{} elements is not in {}..={}", values.len(), len.start(), len.end(),
+                    ));
+                }
+                for (index, value) in values.iter().enumerate() {
+                    self.check(element, value, &path.join(PathSegment::Index(index)));
+                }
+            }
+            (Schema::Set(element), Value::Set(values)) => {
+                for (index, value) in values.iter().enumerate() {
+                    self.check(element, value, &path.join(PathSegment::Index(index)));
+                }
+            }
+            (Schema::Map(key_schema, value_schema), Value::Map(entries)) => {
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    self.check(key_schema, key, &path.join(PathSegment::Key(index)));
+                    self.check(value_schema, value, &path.join(PathSegment::Index(index)));
+                }
+            }
+            (Schema::Tuple(schemas), Value::Tuple(values)) => {
+                if schemas.len() != values.len() {
+                    self.violation(path, format!(
+                        "expected {} elements, found {}", schemas.len(), values.len(),
+                    ));
+                    return;
+                }
+                for (index, (schema, value)) in schemas.iter().zip(values).enumerate() {
+                    self.check(schema, value, &path.join(PathSegment::Index(index)));
+                }
+            }
+            (Schema::Struct(field_schemas), Value::Struct(fields)) => {
+                for (name, value) in fields {
+                    let field_path = path.join(PathSegment::Field(name.C()));
+                    match field_schemas.iter().find(|field| &field.name == name) {
+                        Some(field) => self.check(&field.schema, value, &field_path),
+                        None => self.violation(&field_path, format!("unknown field `{name}`")),
+                    }
+                }
+                for field in field_schemas {
+                    if field.required && !fields.iter().any(|(name, _)| name == &field.name) {
+                        self.violation(path, format!("missing field `{}`", field.name));
+                    }
+                }
+            }
+            (Schema::Enum(variants), Value::Enum { variant, fields }) => {
+                let Some((_, schemas)) = variants.iter().find(|(name, _)| name == variant) else {
+                    self.violation(path, format!("unknown variant `{variant}`"));
+                    return;
+                };
+                if schemas.len() != fields.len() {
+                    self.violation(path, format!(
+                        "variant `{variant}` expects {} fields, found {}",
+                        schemas.len(), fields.len(),
+                    ));
+                    return;
+                }
+                for (index, (schema, field)) in schemas.iter().zip(fields).enumerate() {
+                    self.check(schema, field, &path.join(PathSegment::Index(index)));
+                }
+            }
+            (schema, value) => {
+                self.violation(path, format!("expected {}, found {}", schema.kind(), value.kind()));
+            }
+        }
+    }
+
+    fn violation(&mut self, path: &ValuePath, problem: String) {
+        crate::metrics::record(|metrics| metrics.diagnostic_emitted(Code::SchemaViolation));
+        self.diagnostics.push(Diagnostic {
+            code: Code::SchemaViolation,
+            span: self.document.span(path),
+            message: format!("{path}: {problem}"),
+        });
+    }
+}
+
+#[test]
+fn test_validate() {
+    let ref db = crate::Database::default();
+    let validate = |schema: &Schema, text: &str| -> Vec<(String, String)> {
+        let source = Source::new(db, S(text));
+        let document = crate::data::read_document(db, source).ok().X();
+        schema.validate(&document).into_iter()
+            .map(|diagnostic| (
+                S(&text[diagnostic.span.X()]),
+                diagnostic.message,
+            ))
+            .collect()
+    };
+
+    let schema = Schema::record()
+        .field("name", Schema::one_of(["web", "db"]))
+        .optional_field("ports", Schema::list_len(Schema::int_range(1..=1024), 1..=2))
+        .field("mode", Schema::enumeration().variant("Off", vec![]).variant("On", vec![Schema::float_range(0.0..=1.0)]));
+
+    let ok = r#": {name: string, mode: enum {On(f64)}} / {name = "db", mode = enum On(0.5)}"#;
+    assert_eq!(validate(&schema, ok), vec![]);
+
+    let bad = r#": {name: string, ports: [u16], mode: enum {On(f64)}, extra: bool} / {
+    name = "mail",
+    ports = [80, 8080, 0],
+    mode = enum On(1.5),
+    extra = true,
+}"#;
+    let s = |span: &str, message: &str| (S(span), S(message));
+    assert_eq!(validate(&schema, bad), vec![
+        s(r#""mail""#, r#".name: "mail" is not one of "web", "db""#),
+        s("[80, 8080, 0]", ".ports: 3 elements is not in 1..=2"),
+        s("8080", ".ports[1]: 8080 is not in 1..=1024"),
+        s("0", ".ports[2]: 0 is not in 1..=1024"),
+        s("1.5", ".mode[0]: 1.5 is not in 0..=1"),
+        s("true", ".extra: unknown field `extra`"),
+    ]);
+
+    let missing = ": {ports: [u16]} / {ports = [1]}";
+    assert_eq!(validate(&schema, missing), vec![
+        s("{ports = [1]}", ".: missing field `name`"),
+        s("{ports = [1]}", ".: missing field `mode`"),
+    ]);
+
+    let schema = Schema::read("map<string, (u8, [bool])>").ok().X();
+    assert_eq!(schema, Schema::map(
+        Schema::string(),
+        Schema::Tuple(vec![Schema::int_range(0..=255), Schema::list(Schema::Bool)]),
+    ));
+    let wrong = r#": map<string, (i64, bool)> / map {"a" = (256, true)}"#;
+    assert_eq!(validate(&schema, wrong), vec![
+        s("256", "[0][0]: 256 is not in 0..=255"),
+        s("true", "[0][1]: expected a list, found a bool"),
+    ]);
+    assert!(Schema::read("map<string").is_err());
+}
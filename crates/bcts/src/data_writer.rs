@@ -0,0 +1,743 @@
+//! Writing values as data documents.
+//!
+//! The writer is the inverse of `data`:
+//! reading the document written for a value gives an equal value.
+//!
+//! The document's type is inferred from the value.
+//! A collection's element type is inferred from all of its elements,
+//! so values that mix types in one collection, like a list of
+//! an `i64` and a `string`, can't be written.
+//! An enum type has the variants the value uses.
+//! Empty collections have `bool` elements,
+//! which read back the same as any other empty collection.
+//!
+//! A collection that fits within `max_width` is written on one line,
+//! and otherwise with one element per line and a trailing comma.
+
+use rmx::prelude::*;
+use serde::ser::{self, Serialize};
+
+use crate::data::{DataError, IntType, Type, Value};
+use crate::project_config::FormatOptions;
+
+/// Write `value` as a data document.
+pub fn to_source(value: &Value, options: &FormatOptions) -> Result<String, DataError> {
+    let ty = infer_type(&[value])?.X();
+    let writer = Writer { options };
+    let value = writer.value(value, &ty, 0)?;
+    Ok(format!(": {ty} / {value}\n"))
+}
+
+/// Write any serializable value as a data document.
+pub fn to_string<T: Serialize + ?Sized>(value: &T, options: &FormatOptions) -> Result<String, DataError> {
+    to_source(&to_value(value)?, options)
+}
+
+/// Convert any serializable value to a `Value`.
+///
+/// Rust values map to data values as `Value`'s `Deserializer` reads them back.
+/// There is no null, so `None` fails,
+/// except as a struct field, which is left out.
+/// Struct variants are variants with one struct field.
+pub fn to_value<T: Serialize + ?Sized>(value: &T) -> Result<Value, DataError> {
+    value.serialize(ValueSerializer)?
+        .ok_or_else(|| error("`None` can only be written as a struct field"))
+}
+
+/// The type of a set of values, or `None` if there are none.
+fn infer_type(values: &[&Value]) -> Result<Option<Type>, DataError> {
+    let Some(first) = values.first() else {
+        return Ok(None);
+    };
+    for value in values {
+        if rmx::std::mem::discriminant(*value) != rmx::std::mem::discriminant(*first) {
+            return Err(error(format!("can't write {} and {} in one collection", first.kind(), value.kind())));
+        }
+    }
+    // The type of the elements of every value,
+    // with the `bool` placeholder if there are none.
+    let elements = |elements: Vec<&Value>| -> Result<Type, DataError> {
+        Ok(infer_type(&elements)?.unwrap_or(Type::Bool))
+    };
+
+    Ok(Some(match first {
+        Value::Bool(_) => Type::Bool,
+        Value::Float(_) => Type::Float { bits: 64 },
+        Value::String(_) => Type::String,
+        Value::Bytes(_) => Type::Bytes,
+        Value::Datetime(_) => Type::Datetime,
+        Value::Duration(_) => Type::Duration,
+        Value::Int(_, int_type) => {
+            for value in values {
+                if let Value::Int(_, other) = value && other != int_type {
+                    return Err(error(format!("can't write `{int_type}` and `{other}` in one collection")));
+                }
+            }
+            Type::Int(*int_type)
+        }
+        Value::List(_) | Value::Set(_) => {
+            let element = elements(values.iter().flat_map(|value| match value {
+                Value::List(elements) | Value::Set(elements) => elements.iter(),
+                _ => bug!(),
+            }).collect())?;
+            match first {
+                Value::List(_) => Type::List(Box::new(element)),
+                _ => Type::Set(Box::new(element)),
+            }
+        }
+        Value::Map(_) => {
+            let entries: Vec<&(Value, Value)> = values.iter().flat_map(|value| match value {
+                Value::Map(entries) => entries.iter(),
+                _ => bug!(),
+            }).collect();
+            let key = elements(entries.iter().map(|(key, _)| key).collect())?;
+            let value = elements(entries.iter().map(|(_, value)| value).collect())?;
+            Type::Map(Box::new(key), Box::new(value))
+        }
+        Value::Tuple(first_elements) => {
+            let tuples: Vec<&Vec<Value>> = values.iter().map(|value| match value {
+                Value::Tuple(elements) => elements,
+                _ => bug!(),
+            }).collect();
+            if tuples.iter().any(|elements| elements.len() != first_elements.len()) {
+                return Err(error("can't write tuples of different lengths in one collection"));
+            }
+            let types = (0..first_elements.len())
+                .map(|index| elements(tuples.iter().map(|elements| &elements[index]).collect()))
+                .collect::<Result<_, _>>()?;
+            Type::Tuple(types)
+        }
+        Value::Struct(first_fields) => {
+            let structs: Vec<&Vec<(String, Value)>> = values.iter().map(|value| match value {
+                Value::Struct(fields) => fields,
+                _ => bug!(),
+            }).collect();
+            let same_fields = |fields: &&Vec<(String, Value)>| {
+                fields.iter().map(|(name, _)| name).eq(first_fields.iter().map(|(name, _)| name))
+            };
+            if !structs.iter().all(same_fields) {
+                return Err(error("can't write structs with different fields in one collection"));
+            }
+            let mut fields = vec![];
+            for (index, (name, _)) in first_fields.iter().enumerate() {
+                check_name(name)?;
+                let ty = elements(structs.iter().map(|fields| &fields[index].1).collect())?;
+                fields.push((name.C(), ty));
+            }
+            Type::Struct(fields)
+        }
+        Value::Enum { .. } => {
+            let mut variants: Vec<(&String, Vec<&Vec<Value>>)> = vec![];
+            for value in values {
+                let Value::Enum { variant, fields } = value else {
+                    bug!();
+                };
+                match variants.iter_mut().find(|(name, _)| *name == variant) {
+                    Some((_, uses)) => uses.push(fields),
+                    None => variants.push((variant, vec![fields])),
+                }
+            }
+            let mut types = vec![];
+            for (name, uses) in variants {
+                check_name(name)?;
+                let count = uses[0].len();
+                if uses.iter().any(|fields| fields.len() != count) {
+                    return Err(error(format!("variant `{name}` has different numbers of fields")));
+                }
+                let fields = (0..count)
+                    .map(|index| elements(uses.iter().map(|fields| &fields[index]).collect()))
+                    .collect::<Result<_, _>>()?;
+                types.push((name.C(), fields));
+            }
+            Type::Enum(types)
+        }
+    }))
+}
+
+/// Field and variant names are single words.
+fn check_name(name: &str) -> Result<(), DataError> {
+    let is_word = !name.is_empty() && name.chars().all(|ch| ch.is_alphanumeric() || ch == '_');
+    match is_word {
+        true => Ok(()),
+        false => Err(error(format!("`{name}` is not a valid field or variant name"))),
+    }
+}
+
+fn error(message: impl Into<String>) -> DataError {
+    DataError::Write(message.into())
+}
+
+impl ser::Error for DataError {
+    fn custom<T: rmx::std::fmt::Display>(message: T) -> Self {
+        DataError::Write(message.to_string())
+    }
+}
+
+struct Writer<'a> {
+    options: &'a FormatOptions,
+}
+
+impl<'a> Writer<'a> {
+    /// Write a value of type `ty`, starting on a line at `depth`.
+    fn value(&self, value: &Value, ty: &Type, depth: usize) -> Result<String, DataError> {
+        let inner = depth.checked_add(1).X();
+        let elements = |elements: &[Value], ty: &Type| -> Result<Vec<String>, DataError> {
+            elements.iter().map(|element| self.value(element, ty, inner)).collect()
+        };
+
+        Ok(match (value, ty) {
+            (Value::Bool(bool), Type::Bool) => bool.to_string(),
+            (Value::Int(int, int_type), Type::Int(_)) => {
+                if !int_type.range().contains(int) {
+                    return Err(error(format!("`{int}` is out of range for `{int_type}`")));
+                }
+                int.to_string()
+            }
+            // Debug formatting reads back as the same float.
+            (Value::Float(float), Type::Float { .. }) => format!("{float:?}"),
+            (Value::String(string), Type::String) => quote(string),
+            (Value::Bytes(bytes), Type::Bytes) => quote_bytes(bytes),
+            (Value::Datetime(datetime), Type::Datetime) => datetime.to_string(),
+            (Value::Duration(duration), Type::Duration) => crate::time_literals::format_duration(*duration),
+            (Value::List(values), Type::List(element)) => {
+                self.block("[", "]", elements(values, element)?, depth)
+            }
+            (Value::Set(values), Type::Set(element)) => {
+                if values.iter().enumerate().any(|(index, value)| values[..index].contains(value)) {
+                    return Err(error("duplicate set element"));
+                }
+                self.block("set {", "}", elements(values, element)?, depth)
+            }
+            (Value::Map(entries), Type::Map(key_ty, value_ty)) => {
+                let mut items = vec![];
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if entries[..index].iter().any(|(other, _)| other == key) {
+                        return Err(error("duplicate map key"));
+                    }
+                    let key = self.value(key, key_ty, inner)?;
+                    let value = self.value(value, value_ty, inner)?;
+                    items.push(format!("{key} = {value}"));
+                }
+                self.block("map {", "}", items, depth)
+            }
+            (Value::Tuple(values), Type::Tuple(types)) => {
+                let items = values.iter().zip(types)
+                    .map(|(value, ty)| self.value(value, ty, inner))
+                    .collect::<Result<_, _>>()?;
+                self.block("(", ")", items, depth)
+            }
+            (Value::Struct(fields), Type::Struct(types)) => {
+                let items = fields.iter().zip(types)
+                    .map(|((name, value), (_, ty))| Ok(format!("{name} = {}", self.value(value, ty, inner)?)))
+                    .collect::<Result<_, DataError>>()?;
+                self.block("{", "}", items, depth)
+            }
+            (Value::Enum { variant, fields }, Type::Enum(variants)) => {
+                let (_, types) = variants.iter().find(|(name, _)| name == variant).X();
+                if fields.is_empty() {
+                    return Ok(format!("enum {variant}"));
+                }
+                let items = fields.iter().zip(types)
+                    .map(|(value, ty)| self.value(value, ty, inner))
+                    .collect::<Result<_, _>>()?;
+                self.block(&format!("enum {variant}("), ")", items, depth)
+            }
+            _ => bug!(),
+        })
+    }
+
+    /// Items between `open` and `close`, on one line if they fit.
+    fn block(&self, open: &str, close: &str, items: Vec<String>, depth: usize) -> String {
+        let flat = format!("{open}{}{close}", items.join(", "));
+        let width = depth.checked_mul(self.options.indent_width).X().checked_add(flat.len()).X();
+        if items.is_empty() || (width <= self.options.max_width && !flat.contains('\n')) {
+            return flat;
+        }
+        let indent = |depth: usize| " ".repeat(depth.checked_mul(self.options.indent_width).X());
+        let inner = indent(depth.checked_add(1).X());
+        let mut block = format!("{open}\n");
+        for item in items {
+            block.push_str(&format!("{inner}{item},\n"));
+        }
+        block.push_str(&indent(depth));
+        block.push_str(close);
+        block
+    }
+}
+
+
+/// A string literal, escaped as `escapes` reads it.
+fn quote(string: &str) -> String {
+    let mut quoted = String::from('"');
+    for ch in string.chars() {
+        match ch {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            '\0' => quoted.push_str("\\0"),
+            _ if ch.is_control() => quoted.push_str(&format!("\\u{{{:x}}}", u32::from(ch))),
+            _ => quoted.push(ch),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// A byte string literal, escaped as `escapes` reads it.
+fn quote_bytes(bytes: &[u8]) -> String {
+    let mut quoted = String::from("b\"");
+    for byte in bytes {
+        match byte {
+            b'"' => quoted.push_str("\\\""),
+            b'\\' => quoted.push_str("\\\\"),
+            b'\n' => quoted.push_str("\\n"),
+            b'\r' => quoted.push_str("\\r"),
+            b'\t' => quoted.push_str("\\t"),
+            b' '..=b'~' => quoted.push(char::from(*byte)),
+            _ => quoted.push_str(&format!("\\x{byte:02x}")),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Serializes to a `Value`, or to `None` for `None`.
+struct ValueSerializer;
+
+fn int(int: impl Into<i128>, signed: bool, bits: u32) -> Result<Option<Value>, DataError> {
+    Ok(Some(Value::Int(int.into(), IntType { signed, bits })))
+}
+
+/// A value that must be present, in a collection.
+fn present(value: Option<Value>) -> Result<Value, DataError> {
+    value.ok_or_else(|| error("`None` can only be written as a struct field"))
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = SeqSerializer;
+    type SerializeMap = MapSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = StructSerializer;
+
+    fn serialize_bool(self, v: bool) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Bool(v)))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Option<Value>, DataError> {
+        int(v, true, 8)
+    }
+
+    fn serialize_i16(self, v: i16) -> Result<Option<Value>, DataError> {
+        int(v, true, 16)
+    }
+
+    fn serialize_i32(self, v: i32) -> Result<Option<Value>, DataError> {
+        int(v, true, 32)
+    }
+
+    fn serialize_i64(self, v: i64) -> Result<Option<Value>, DataError> {
+        int(v, true, 64)
+    }
+
+    fn serialize_i128(self, v: i128) -> Result<Option<Value>, DataError> {
+        let v = i64::try_from(v).map_err(|_| error(format!("`{v}` is out of range for `i64`")))?;
+        int(v, true, 64)
+    }
+
+    fn serialize_u8(self, v: u8) -> Result<Option<Value>, DataError> {
+        int(v, false, 8)
+    }
+
+    fn serialize_u16(self, v: u16) -> Result<Option<Value>, DataError> {
+        int(v, false, 16)
+    }
+
+    fn serialize_u32(self, v: u32) -> Result<Option<Value>, DataError> {
+        int(v, false, 32)
+    }
+
+    fn serialize_u64(self, v: u64) -> Result<Option<Value>, DataError> {
+        int(v, false, 64)
+    }
+
+    fn serialize_u128(self, v: u128) -> Result<Option<Value>, DataError> {
+        let v = u64::try_from(v).map_err(|_| error(format!("`{v}` is out of range for `u64`")))?;
+        int(v, false, 64)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Float(f64::from(v))))
+    }
+
+    fn serialize_f64(self, v: f64) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Float(v)))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::String(v.to_string())))
+    }
+
+    fn serialize_str(self, v: &str) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::String(S(v))))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Bytes(v.to_vec())))
+    }
+
+    fn serialize_none(self) -> Result<Option<Value>, DataError> {
+        Ok(None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Option<Value>, DataError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Tuple(vec![])))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Option<Value>, DataError> {
+        self.serialize_unit()
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Enum { variant: S(variant), fields: vec![] }))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Option<Value>, DataError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Option<Value>, DataError> {
+        let field = present(value.serialize(ValueSerializer)?)?;
+        Ok(Some(Value::Enum { variant: S(variant), fields: vec![field] }))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, DataError> {
+        Ok(SeqSerializer { kind: SeqKind::List, elements: Vec::with_capacity(len.unwrap_or(0)) })
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, DataError> {
+        Ok(SeqSerializer { kind: SeqKind::Tuple, elements: Vec::with_capacity(len) })
+    }
+
+    fn serialize_tuple_struct(self, _name: &'static str, len: usize) -> Result<SeqSerializer, DataError> {
+        self.serialize_tuple(len)
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, DataError> {
+        Ok(SeqSerializer { kind: SeqKind::Variant(variant), elements: Vec::with_capacity(len) })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer, DataError> {
+        Ok(MapSerializer { entries: Vec::with_capacity(len.unwrap_or(0)), key: None })
+    }
+
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<StructSerializer, DataError> {
+        Ok(StructSerializer { variant: None, fields: Vec::with_capacity(len) })
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<StructSerializer, DataError> {
+        Ok(StructSerializer { variant: Some(variant), fields: Vec::with_capacity(len) })
+    }
+}
+
+enum SeqKind {
+    List,
+    Tuple,
+    Variant(&'static str),
+}
+
+struct SeqSerializer {
+    kind: SeqKind,
+    elements: Vec<Value>,
+}
+
+impl SeqSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.elements.push(present(value.serialize(ValueSerializer)?)?);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, DataError> {
+        Ok(Some(match self.kind {
+            SeqKind::List => Value::List(self.elements),
+            SeqKind::Tuple => Value::Tuple(self.elements),
+            SeqKind::Variant(variant) => Value::Enum { variant: S(variant), fields: self.elements },
+        }))
+    }
+}
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeTupleVariant for SeqSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        self.push(value)
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        self.finish()
+    }
+}
+
+struct MapSerializer {
+    entries: Vec<(Value, Value)>,
+    key: Option<Value>,
+}
+
+impl ser::SerializeMap for MapSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), DataError> {
+        self.key = Some(present(key.serialize(ValueSerializer)?)?);
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), DataError> {
+        let key = self.key.take().expect("map value before its key");
+        self.entries.push((key, present(value.serialize(ValueSerializer)?)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        Ok(Some(Value::Map(self.entries)))
+    }
+}
+
+struct StructSerializer {
+    variant: Option<&'static str>,
+    fields: Vec<(String, Value)>,
+}
+
+impl StructSerializer {
+    fn push<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), DataError> {
+        if let Some(value) = value.serialize(ValueSerializer)? {
+            self.fields.push((S(key), value));
+        }
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Option<Value>, DataError> {
+        let value = Value::Struct(self.fields);
+        Ok(Some(match self.variant {
+            Some(variant) => Value::Enum { variant: S(variant), fields: vec![value] },
+            None => value,
+        }))
+    }
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), DataError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        self.finish()
+    }
+}
+
+impl ser::SerializeStructVariant for StructSerializer {
+    type Ok = Option<Value>;
+    type Error = DataError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, key: &'static str, value: &T) -> Result<(), DataError> {
+        self.push(key, value)
+    }
+
+    fn end(self) -> Result<Option<Value>, DataError> {
+        self.finish()
+    }
+}
+
+#[test]
+fn test_to_source() {
+    use crate::data::read_data;
+
+    let i64 = |int| Value::Int(int, IntType { signed: true, bits: 64 });
+    let u8 = |int| Value::Int(int, IntType { signed: false, bits: 8 });
+    let string = |s: &str| Value::String(S(s));
+    let options = FormatOptions { max_width: 40, ..FormatOptions::default() };
+    let write = |value: &Value| to_source(value, &options).X();
+
+    assert_eq!(write(&i64(-5)), ": i64 / -5\n");
+    assert_eq!(write(&Value::Float(1e-7)), ": f64 / 1e-7\n");
+    assert_eq!(write(&string("a \"b\"\n\u{7}")), ": string / \"a \\\"b\\\"\\n\\u{7}\"\n");
+    assert_eq!(write(&Value::Bytes(vec![b'a', 0, 0xff])), ": bytes / b\"a\\x00\\xff\"\n");
+    assert_eq!(write(&Value::List(vec![])), ": [bool] / []\n");
+    assert_eq!(
+        write(&Value::List(vec![Value::List(vec![]), Value::List(vec![u8(1)])])),
+        ": [[u8]] / [[], [1]]\n",
+    );
+    assert_eq!(
+        write(&Value::List(vec![
+            Value::Enum { variant: S("A"), fields: vec![] },
+            Value::Enum { variant: S("B"), fields: vec![string("x")] },
+        ])),
+        ": [enum {A, B(string)}] / [enum A, enum B(\"x\")]\n",
+    );
+    assert_eq!(
+        write(&Value::Struct(vec![
+            (S("name"), string("a long enough name")),
+            (S("sizes"), Value::Map(vec![(string("small"), u8(1)), (string("large"), u8(200))])),
+        ])),
+        "\
+: {name: string, sizes: map<string, u8>} / {
+    name = \"a long enough name\",
+    sizes = map {\"small\" = 1, \"large\" = 200},
+}
+",
+    );
+
+    // Everything written reads back the same.
+    let values = [
+        Value::Bool(false),
+        Value::Float(-0.5),
+        Value::Float(f64::INFINITY),
+        Value::Float(1e300),
+        string("tab\tquote\"backslash\\ // not a comment"),
+        Value::Bytes(vec![0, b'"', b'\\', b'a', 0xff, b'\n']),
+        Value::Datetime(crate::time_literals::parse_datetime("2024-02-29T23:59:60.001-05:30", &(0..29)).X()),
+        Value::List(vec![Value::Duration(rmx::std::time::Duration::new(93_784, 5)); 2]),
+        Value::Set(vec![u8(1), u8(2)]),
+        Value::Tuple(vec![i64(1), Value::Tuple(vec![]), string("")]),
+        Value::Map(vec![(
+            Value::Tuple(vec![u8(0), u8(1)]),
+            Value::List(vec![Value::Struct(vec![(S("a"), Value::Float(2.0))]); 3]),
+        )]),
+        Value::Enum { variant: S("V"), fields: vec![Value::Map(vec![]), Value::Set(vec![])] },
+    ];
+    for value in values {
+        let written = write(&value);
+        assert_eq!(read_data(&written), Ok(value), "{written}");
+    }
+
+    let error = |value: &Value| to_source(value, &options).err().X().to_string();
+    assert_eq!(error(&Value::List(vec![i64(1), string("a")])), "can't write an int and a string in one collection");
+    assert_eq!(error(&Value::List(vec![i64(1), u8(1)])), "can't write `i64` and `u8` in one collection");
+    assert_eq!(error(&Value::Struct(vec![(S("a b"), i64(1))])), "`a b` is not a valid field or variant name");
+    assert_eq!(error(&Value::Set(vec![i64(1), i64(1)])), "duplicate set element");
+    assert_eq!(error(&u8(256)), "`256` is out of range for `u8`");
+}
+
+#[test]
+fn test_serialize() {
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    struct Config {
+        name: String,
+        ports: Vec<u16>,
+        limits: rmx::std::collections::BTreeMap<String, f32>,
+        modes: Vec<Mode>,
+        comment: Option<String>,
+        unit: (),
+    }
+
+    #[derive(serde::Serialize, serde::Deserialize, Debug, PartialEq)]
+    enum Mode {
+        Off,
+        Level(u8),
+        Range(i32, i32),
+        Named { name: char, level: u8 },
+    }
+
+    let config = Config {
+        name: S("server"),
+        ports: vec![80, 443],
+        limits: [(S("cpu"), 0.5), (S("memory"), 1e9)].into(),
+        modes: vec![Mode::Off, Mode::Level(3), Mode::Range(-1, 1), Mode::Named { name: 'x', level: 2 }],
+        comment: None,
+        unit: (),
+    };
+    let written = to_string(&config, &FormatOptions::default()).X();
+    assert_eq!(written, "\
+: {name: string, ports: [u16], limits: map<string, f64>, modes: [enum {Off, Level(u8), Range(i32, i32), Named({name: string, level: u8})}], unit: ()} / {
+    name = \"server\",
+    ports = [80, 443],
+    limits = map {\"cpu\" = 0.5, \"memory\" = 1000000000.0},
+    modes = [enum Off, enum Level(3), enum Range(-1, 1), enum Named({name = \"x\", level = 2})],
+    unit = (),
+}
+");
+    assert_eq!(crate::data::from_str::<Config>(&written), Ok(config));
+
+    assert!(to_value(&None::<u8>).is_err());
+    assert!(to_value(&vec![Some(1), None]).is_err());
+}
@@ -0,0 +1,1001 @@
+//! Diagnostic codes, severities and the explanation registry.
+//!
+//! Every diagnostic has a stable code like `E0101`.
+//! Codes are never reused or renumbered;
+//! the `E00xx` range is for lexing and braces,
+//! `E01xx` for module and package resolution,
+//! `E02xx` for configuration files,
+//! `E03xx` for data documents.
+//!
+//! Each code has a default severity,
+//! which a `SeverityConfig` can override per code,
+//! usually from the `[severity]` table of a config file:
+//!
+//! ```toml
+//! [severity]
+//! E0101 = "warn"
+//! E0104 = "allow"
+//! ```
+
+use rmx::prelude::*;
+
+use rmx::std::collections::BTreeMap;
+use rmx::std::fmt;
+
+use crate::bracer::Bracer;
+use crate::chunk::ErrorReason;
+use crate::line_index::LineIndex;
+use crate::lexer::TokenKind;
+use crate::import_lines::import_demand_span;
+use crate::module_attrs::{DEPRECATED, EXPERIMENTAL, module_attrs};
+use crate::package2::{EntryPointError, Package, PackageModule, PackageWorld};
+use crate::package_resolve2::{PackageWorldMap, PackageWorldModuleGraph, ResolvedPackageModule, ForbiddenReason};
+use crate::text::ByteSpan;
+
+/// A stable diagnostic code.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(enum_iterator::Sequence)]
+pub enum Code {
+    UnclosedBrace,
+    UnmatchedClose,
+    InvalidToken,
+    ConfusableIdentifier,
+    MixedScriptIdentifier,
+    UnterminatedToken,
+    InvalidDatetime,
+    InvalidDuration,
+    InvalidLiteral,
+    UnsupportedLanguageVersion,
+    FeatureNotEnabled,
+    TrailingWhitespace,
+    MissingFinalNewline,
+    ExtraFinalNewlines,
+    UnresolvedImport,
+    AmbiguousImport,
+    ForbiddenImport,
+    ImportCycle,
+    MissingEntryPoint,
+    PrivateEntryPoint,
+    UnreadableModule,
+    DuplicateModulePath,
+    InvalidModuleName,
+    ShadowedImport,
+    DeprecatedImport,
+    ExperimentalImport,
+    UnknownConfigKey,
+    InvalidConfigValue,
+    InvalidDataLiteral,
+    DataTypeMismatch,
+    SchemaViolation,
+}
+
+/// How a diagnostic is reported.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub enum Severity {
+    /// Reported as an error, failing the check.
+    Deny,
+    /// Reported as a warning.
+    Warn,
+    /// Not reported.
+    Allow,
+}
+
+impl Code {
+    /// All codes, in code order.
+    pub fn all() -> impl Iterator<Item = Code> {
+        enum_iterator::all::<Code>()
+    }
+
+    /// The code as written, e.g. `"E0101"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Code::UnclosedBrace => "E0001",
+            Code::UnmatchedClose => "E0002",
+            Code::InvalidToken => "E0003",
+            Code::ConfusableIdentifier => "E0004",
+            Code::MixedScriptIdentifier => "E0005",
+            Code::UnterminatedToken => "E0006",
+            Code::InvalidDatetime => "E0007",
+            Code::InvalidDuration => "E0008",
+            Code::InvalidLiteral => "E0009",
+            Code::UnsupportedLanguageVersion => "E0010",
+            Code::FeatureNotEnabled => "E0011",
+            Code::TrailingWhitespace => "E0012",
+            Code::MissingFinalNewline => "E0013",
+            Code::ExtraFinalNewlines => "E0014",
+            Code::UnresolvedImport => "E0101",
+            Code::AmbiguousImport => "E0102",
+            Code::ForbiddenImport => "E0103",
+            Code::ImportCycle => "E0104",
+            Code::MissingEntryPoint => "E0105",
+            Code::PrivateEntryPoint => "E0106",
+            Code::UnreadableModule => "E0107",
+            Code::DuplicateModulePath => "E0108",
+            Code::InvalidModuleName => "E0109",
+            Code::ShadowedImport => "E0110",
+            Code::DeprecatedImport => "E0111",
+            Code::ExperimentalImport => "E0112",
+            Code::UnknownConfigKey => "E0201",
+            Code::InvalidConfigValue => "E0202",
+            Code::InvalidDataLiteral => "E0301",
+            Code::DataTypeMismatch => "E0302",
+            Code::SchemaViolation => "E0303",
+        }
+    }
+
+    /// Look up a code by its written form.
+    pub fn parse(code: &str) -> Option<Code> {
+        Code::all().find(|c| c.as_str() == code)
+    }
+
+    /// A short name, e.g. `"UnresolvedImport"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Code::UnclosedBrace => "UnclosedBrace",
+            Code::UnmatchedClose => "UnmatchedClose",
+            Code::InvalidToken => "InvalidToken",
+            Code::ConfusableIdentifier => "ConfusableIdentifier",
+            Code::MixedScriptIdentifier => "MixedScriptIdentifier",
+            Code::UnterminatedToken => "UnterminatedToken",
+            Code::InvalidDatetime => "InvalidDatetime",
+            Code::InvalidDuration => "InvalidDuration",
+            Code::InvalidLiteral => "InvalidLiteral",
+            Code::UnsupportedLanguageVersion => "UnsupportedLanguageVersion",
+            Code::FeatureNotEnabled => "FeatureNotEnabled",
+            Code::TrailingWhitespace => "TrailingWhitespace",
+            Code::MissingFinalNewline => "MissingFinalNewline",
+            Code::ExtraFinalNewlines => "ExtraFinalNewlines",
+            Code::UnresolvedImport => "UnresolvedImport",
+            Code::AmbiguousImport => "AmbiguousImport",
+            Code::ForbiddenImport => "ForbiddenImport",
+            Code::ImportCycle => "ImportCycle",
+            Code::MissingEntryPoint => "MissingEntryPoint",
+            Code::PrivateEntryPoint => "PrivateEntryPoint",
+            Code::UnreadableModule => "UnreadableModule",
+            Code::DuplicateModulePath => "DuplicateModulePath",
+            Code::InvalidModuleName => "InvalidModuleName",
+            Code::ShadowedImport => "ShadowedImport",
+            Code::DeprecatedImport => "DeprecatedImport",
+            Code::ExperimentalImport => "ExperimentalImport",
+            Code::UnknownConfigKey => "UnknownConfigKey",
+            Code::InvalidConfigValue => "InvalidConfigValue",
+            Code::InvalidDataLiteral => "InvalidDataLiteral",
+            Code::DataTypeMismatch => "DataTypeMismatch",
+            Code::SchemaViolation => "SchemaViolation",
+        }
+    }
+
+    pub fn default_severity(&self) -> Severity {
+        match self {
+            Code::UnclosedBrace => Severity::Deny,
+            Code::UnmatchedClose => Severity::Deny,
+            Code::InvalidToken => Severity::Deny,
+            Code::ConfusableIdentifier => Severity::Warn,
+            Code::MixedScriptIdentifier => Severity::Warn,
+            Code::UnterminatedToken => Severity::Deny,
+            Code::InvalidDatetime => Severity::Deny,
+            Code::InvalidDuration => Severity::Deny,
+            Code::InvalidLiteral => Severity::Deny,
+            Code::UnsupportedLanguageVersion => Severity::Deny,
+            Code::FeatureNotEnabled => Severity::Deny,
+            Code::TrailingWhitespace => Severity::Warn,
+            Code::MissingFinalNewline => Severity::Warn,
+            Code::ExtraFinalNewlines => Severity::Warn,
+            Code::UnresolvedImport => Severity::Deny,
+            Code::AmbiguousImport => Severity::Deny,
+            Code::ForbiddenImport => Severity::Deny,
+            Code::ImportCycle => Severity::Warn,
+            Code::MissingEntryPoint => Severity::Deny,
+            Code::PrivateEntryPoint => Severity::Deny,
+            Code::UnreadableModule => Severity::Deny,
+            Code::DuplicateModulePath => Severity::Warn,
+            Code::InvalidModuleName => Severity::Deny,
+            Code::ShadowedImport => Severity::Warn,
+            Code::DeprecatedImport => Severity::Warn,
+            Code::ExperimentalImport => Severity::Warn,
+            Code::UnknownConfigKey => Severity::Warn,
+            Code::InvalidConfigValue => Severity::Deny,
+            Code::InvalidDataLiteral => Severity::Deny,
+            Code::DataTypeMismatch => Severity::Deny,
+            Code::SchemaViolation => Severity::Deny,
+        }
+    }
+
+    /// The long-form explanation, as shown by `bcts explain`.
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            Code::UnclosedBrace => "\
+An open brace has no matching close.
+
+    f(a, b
+
+The brace tree is repaired by closing the brace
+at the next close of an enclosing brace, or at the end of the file.
+Add the missing close where it belongs.
+",
+            Code::UnmatchedClose => "\
+A close brace has no matching open.
+
+    f(a, b))
+
+The stray close is ignored when building the brace tree.
+Remove it, or add the open it was meant to match.
+",
+            Code::InvalidToken => "\
+The text contains characters that do not begin any token.
+
+    a § b
+
+They are kept as an error token and otherwise ignored.
+Remove them, or put them in a string or comment.
+",
+            Code::ConfusableIdentifier => "\
+A word looks like another word of the source but is spelled differently.
+
+    name = 1
+    print(nаme)
+
+Here the second `nаme` is written with a Cyrillic `а`,
+so it is not the `name` defined above, though it looks the same.
+The spelling used by a definition, or else the first spelling in the source,
+is taken as intended.
+Retype the word, or rename one of the two so they look different.
+",
+            Code::MixedScriptIdentifier => "\
+A word mixes letters of different scripts, like Latin and Cyrillic.
+
+    раypal = 1
+
+Such words are rarely intended
+and can pass for a word spelled entirely in one script.
+Retype the word in a single script.
+",
+            Code::UnterminatedToken => "\
+A string, block comment or embedded region is not closed.
+
+    a = \"hello
+
+Everything from the opening delimiter to the end of the file
+is kept as an error token, and the diagnostic points at the delimiter.
+Block comments nest, so each `/*` inside one needs its own `*/`.
+Add the missing close.
+",
+            Code::InvalidDatetime => "\
+A datetime literal is not a valid RFC 3339 date or datetime.
+
+    start = 2024-02-30T25:00:00Z
+
+Anything starting like a date, `2024-02-30`, is a datetime literal.
+It is a date, optionally followed by `T` and a time,
+and then optionally by `Z` or an offset like `+05:30`.
+The month, day, hour, minute, second and offset must be in range,
+so this day and hour are invalid.
+",
+            Code::InvalidDuration => "\
+A duration literal has its units out of order or is too large.
+
+    timeout = 30s15m
+
+A duration is ints each followed by a unit,
+`d`, `h`, `m`, `s`, `ms`, `us` or `ns`.
+The units must go from largest to smallest, each at most once,
+so this is written `15m30s`.
+",
+            Code::InvalidLiteral => "\
+A custom literal's contents are rejected by its validation.
+
+    pattern = re\"(a+\"
+
+Applications embedding the language can register literal prefixes,
+like `re` here, each with a validation of the text between the quotes.
+The message says what is wrong; it comes from the application,
+which here expects a regular expression.
+",
+            Code::UnsupportedLanguageVersion => "\
+A module requires a newer language version than the world is checked at.
+
+    attr language \"1.1\"
+
+The world's version is the `version` of the `[language]` section
+of the project config, the latest version if there is none.
+Raise the world's version, or stop using the newer syntax
+and lower the module's.
+The version must be written `major.minor`.
+",
+            Code::FeatureNotEnabled => "\
+Syntax is used whose feature is not enabled at the world's language version.
+
+    [language]
+    version = \"1.0\"
+
+    timeout = 15m
+
+Duration literals are the `time-literals` feature, stable since 1.1.
+Raise the world's version, or enable the feature alone
+by adding it to `features` in the `[language]` section.
+",
+            Code::TrailingWhitespace => "\
+A line ends in spaces or tabs.
+
+Trailing whitespace is invisible in most editors
+and shows up as noise in diffs.
+Line comments are checked too, but not strings or block comments,
+where the whitespace may be meant.
+`bcts check --fix` removes it, as does `bcts fmt` outside comments.
+",
+            Code::MissingFinalNewline => "\
+The file does not end with a newline.
+
+Many tools expect every line, including the last, to end in a newline,
+and diffs of the file show the missing one.
+`bcts check --fix` and `bcts fmt` add it.
+",
+            Code::ExtraFinalNewlines => "\
+The file ends with blank lines, or whitespace after its final newline.
+
+A file should end with exactly one newline after its last line.
+`bcts check --fix` and `bcts fmt` remove the rest.
+",
+            Code::UnresolvedImport => "\
+An import names a module that does not exist.
+
+    import module sys/core/missing
+
+Check the import space, package and module names
+against the packages in the world.
+",
+            Code::AmbiguousImport => "\
+An import matches more than one module.
+
+This happens when different packages and modules
+produce the same import path.
+Rename one of the modules or packages so the path is unique.
+",
+            Code::ForbiddenImport => "\
+An import names a module that the importer may not use.
+
+Modules with package visibility can only be imported
+from within their own package.
+Make the module public, or import it from inside its package.
+",
+            Code::ImportCycle => "\
+A set of modules import each other.
+
+Cycles are allowed, but every module in one is compiled together,
+which limits incremental reuse.
+Break the cycle by moving shared items into a new module.
+",
+            Code::MissingEntryPoint => "\
+A package's main module does not exist.
+
+The package names a main module to run as a program,
+but has no module of that name.
+Fix the name, add the module, or remove the main module
+if the package is a library.
+",
+            Code::PrivateEntryPoint => "\
+A package's main module has package visibility.
+
+A program is started from outside its package,
+so its main module must be public.
+Make the module public.
+",
+            Code::UnreadableModule => "\
+An import names a module whose file could not be read.
+
+The file may have been deleted after its package was listed,
+be a symlink to nothing, lack read permission, or not be UTF-8.
+The module is treated as empty until the file can be read
+and the package is loaded again.
+",
+            Code::DuplicateModulePath => "\
+Two paths in the package paths lead to the same module.
+
+    lib/app/util.bct
+    lib/app/helpers.bct -> util.bct
+
+This happens with symlinks to files or directories,
+and with module names that differ only in case,
+like `Util.bct` and `util.bct`,
+which are one file on case-insensitive file systems.
+Only the first path is loaded. Remove or rename the others.
+",
+            Code::InvalidModuleName => "\
+A package or module file or directory has a name
+that can't be written in an import.
+
+    lib/my-app/main.bct
+    lib/app/self.bct
+
+Each package name and each part of a module path
+must be a single word: letters, digits and `_`.
+`pkg`, `self` and `super` are reserved for relative imports.
+The file or directory is not loaded. Rename it.
+",
+            Code::ShadowedImport => "\
+An import resolved in an overriding import space
+hides a module of the same path in the import space it names.
+
+    [packages]
+    overrides = [{ import_space = \"local\", overrides = \"sys\" }]
+
+    import module sys/core/io
+
+With a package `core` in both `local` and `sys`,
+the import uses `local`'s `core/io`.
+This is how a system package is patched with a local copy;
+allow this warning where the override is intended,
+or remove the override or the local copy.
+",
+            Code::DeprecatedImport => "\
+An import names a module marked deprecated.
+
+    attr deprecated \"use sys/core/fs\"
+
+The module may be removed in a later version of its package.
+The attribute's message, if any, says what to use instead.
+",
+            Code::ExperimentalImport => "\
+An import names a module marked experimental.
+
+    attr experimental
+
+The module may change or be removed without notice.
+Allow this warning where depending on it is intended.
+",
+            Code::UnknownConfigKey => "\
+A `bct.toml` file contains a section or key that is not recognized.
+
+    [format]
+    indent = 2
+
+The key is ignored. Check its spelling against the documented keys.
+",
+            Code::InvalidConfigValue => "\
+A `bct.toml` key has a value of the wrong type or out of range.
+
+    [format]
+    indent_width = \"two\"
+
+The key is ignored, and the value from the enclosing directory's
+config file, or the default, is used instead.
+",
+            Code::InvalidDataLiteral => "\
+A data literal is not written in the data syntax.
+
+    : map<i64, f32> / {1 = 2.5}
+
+A typed literal is `:`, a type, `/` and a value.
+Map and set values are written `map {...}` and `set {...}`,
+map entries `key = value`, and struct fields `name = value`.
+",
+            Code::DataTypeMismatch => "\
+The value of a data literal does not match its type.
+
+    : u8 / 300
+    : [i64] / [: f32 / 1.5]
+
+Numbers must be in the range of their type,
+and an element with its own annotation must have
+the element type of the enclosing literal.
+",
+            Code::SchemaViolation => "\
+A data document does not match the schema it is checked against.
+
+    : {port: u16} / {port = 0}
+
+The message starts with the path of the offending value,
+like `.servers[0].port`.
+Schemas constrain documents beyond their types,
+e.g. with int ranges, allowed strings and optional fields.
+",
+        }
+    }
+}
+
+impl fmt::Display for Code {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Deny => "deny",
+            Severity::Warn => "warn",
+            Severity::Allow => "allow",
+        }
+    }
+
+    pub fn parse(severity: &str) -> Option<Severity> {
+        match severity {
+            "deny" => Some(Severity::Deny),
+            "warn" => Some(Severity::Warn),
+            "allow" => Some(Severity::Allow),
+            _ => None,
+        }
+    }
+}
+
+/// Per-code severity overrides.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, salsa::Update)]
+pub struct SeverityConfig {
+    pub overrides: BTreeMap<Code, Severity>,
+}
+
+impl SeverityConfig {
+    pub fn severity(&self, code: Code) -> Severity {
+        self.overrides.get(&code).copied().unwrap_or(code.default_severity())
+    }
+
+    /// Read overrides from the `[severity]` table of a TOML document.
+    ///
+    /// A document without the table has no overrides.
+    pub fn from_toml(toml: &str) -> AnyResult<SeverityConfig> {
+        let table: rmx::toml::Table = rmx::toml::from_str(toml)?;
+        let Some(severities) = table.get("severity") else {
+            return Ok(SeverityConfig::default());
+        };
+        let severities = severities.as_table()
+            .ok_or_else(|| anyhow!("`severity` must be a table"))?;
+        SeverityConfig::from_table(severities)
+    }
+
+    /// Read overrides from the contents of a `[severity]` table.
+    pub fn from_table(severities: &rmx::toml::Table) -> AnyResult<SeverityConfig> {
+        let mut overrides = BTreeMap::new();
+        for (code_str, severity) in severities {
+            let code = Code::parse(code_str)
+                .ok_or_else(|| anyhow!("unknown diagnostic code `{code_str}`"))?;
+            let severity = severity.as_str()
+                .and_then(Severity::parse)
+                .ok_or_else(|| anyhow!("severity of {code_str} must be \"deny\", \"warn\" or \"allow\""))?;
+            overrides.insert(code, severity);
+        }
+        Ok(SeverityConfig { overrides })
+    }
+}
+
+/// A problem found in a source.
+#[derive(Clone, Debug, Eq, PartialEq, salsa::Update)]
+pub struct Diagnostic {
+    pub code: Code,
+    /// Bytes of the source the problem is in, if it has a location.
+    pub span: Option<ByteSpan>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    /// Render as e.g. `error[E0002] 5..6: unmatched `]``.
+    ///
+    /// Returns `None` if the severity is `Allow`.
+    pub fn render(&self, config: &SeverityConfig) -> Option<String> {
+        let level = self.level(config)?;
+        Some(match &self.span {
+            Some(span) => format!("{level}[{}] {}..{}: {}", self.code, span.start, span.end, self.message),
+            None => format!("{level}[{}]: {}", self.code, self.message),
+        })
+    }
+
+    /// Render with the line of `text` the diagnostic is on
+    /// and carets under its span, as in
+    ///
+    /// ```text
+    /// error[E0002] 2:7: unmatched `]`
+    ///   |
+    /// 2 |     f(a]
+    ///   |        ^
+    /// ```
+    ///
+    /// Tabs in the line are expanded to `tab_width` columns,
+    /// which are also the columns of the position,
+    /// so the carets stay under the span.
+    /// A span over several lines is underlined to the end of its first line.
+    /// A diagnostic without a span renders as by `render`.
+    ///
+    /// Returns `None` if the severity is `Allow`.
+    pub fn render_in(&self, config: &SeverityConfig, text: &str, tab_width: usize) -> Option<String> {
+        let Some(span) = &self.span else {
+            return self.render(config);
+        };
+        let level = self.level(config)?;
+        let index = LineIndex::new(text, tab_width);
+        let start = index.line_col(span.start);
+        let line_text = index.line_text(start.line);
+        let end_column = match index.line(span.end) == start.line {
+            true => index.line_col(span.end).column,
+            false => index.width_from(0, line_text),
+        };
+        let carets = end_column.saturating_sub(start.column).max(1);
+
+        let number = start.line.checked_add(1).X().to_string();
+        let gutter = " ".repeat(number.len());
+        let lines = [
+            format!("{level}[{}] {start}: {}", self.code, self.message),
+            format!("{gutter} |"),
+            format!("{number} | {}", index.expand_tabs(line_text)),
+            format!("{gutter} | {}{}", " ".repeat(start.column), "^".repeat(carets)),
+        ];
+        Some(lines.iter().map(|line| line.trim_end()).join("\n"))
+    }
+
+    fn level(&self, config: &SeverityConfig) -> Option<&'static str> {
+        match config.severity(self.code) {
+            Severity::Deny => Some("error"),
+            Severity::Warn => Some("warning"),
+            Severity::Allow => None,
+        }
+    }
+}
+
+/// Diagnostics for invalid and unterminated tokens and brace errors, in source order.
+pub fn bracer_diagnostics<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> Vec<Diagnostic> {
+    let tokens = bracer.chunk(db).tokens(db);
+    let chunk = bracer.chunk(db).chunk(db);
+    let chunk_text = chunk.text(db).as_str(db);
+    let error_reasons: BTreeMap<usize, &ErrorReason> = chunk.errors(db).iter()
+        .map(|range| range.start)
+        .zip(chunk.error_reasons(db))
+        .collect();
+    let mut diagnostics = vec![];
+
+    for token in tokens {
+        let kind = token.kind(db);
+        if matches!(kind, TokenKind::Datetime | TokenKind::Duration) {
+            let subtext = token.text(db);
+            let (text, range) = (subtext.as_str(db), subtext.range(db));
+            let error = match kind {
+                TokenKind::Datetime => crate::time_literals::parse_datetime(text, &range)
+                    .err().map(|error| (Code::InvalidDatetime, "datetime", error)),
+                _ => crate::time_literals::parse_duration(text, &range)
+                    .err().map(|error| (Code::InvalidDuration, "duration", error)),
+            };
+            if let Some((code, name, error)) = error {
+                let message = format!("invalid {name} `{text}`: {error}");
+                diagnostics.push(Diagnostic { code, span: Some(error.span.C()), message });
+            }
+        }
+        if kind == TokenKind::Error {
+            let subtext = token.text(db);
+            let range = subtext.range(db);
+            diagnostics.push(match error_reasons.get(&range.start) {
+                Some(reason) => Diagnostic {
+                    code: Code::UnterminatedToken,
+                    span: Some(reason.open_span()),
+                    message: reason.message(chunk_text),
+                },
+                None => Diagnostic {
+                    code: Code::InvalidToken,
+                    span: Some(range),
+                    message: format!("invalid token {:?}", subtext.as_str(db)),
+                },
+            });
+        }
+    }
+
+    for (token_range, sigil) in bracer.errors(db) {
+        let first = &tokens[token_range.start];
+        let last = &tokens[token_range.end.checked_sub(1).X()];
+        let span = first.text(db).text_span(db).merge(&last.text(db).text_span(db)).X().span;
+        let (code, message) = if sigil.is_close_sigil() {
+            (Code::UnmatchedClose, format!("unmatched `{}`", sigil.as_str()))
+        } else {
+            (Code::UnclosedBrace, format!("unclosed `{}`", sigil.as_str()))
+        };
+        diagnostics.push(Diagnostic { code, span: Some(span), message });
+    }
+
+    for (span, message) in bracer.chunk(db).literal_errors(db) {
+        let prefix = chunk_text[span.C()].split('"').next().X();
+        diagnostics.push(Diagnostic {
+            code: Code::InvalidLiteral,
+            span: Some(span.C()),
+            message: format!("invalid `{prefix}` literal: {message}"),
+        });
+    }
+
+    diagnostics.sort_by_key(|diagnostic| diagnostic.span.C().map(|span| (span.start, span.end)));
+    crate::metrics::record(|metrics| {
+        for diagnostic in &diagnostics {
+            metrics.diagnostic_emitted(diagnostic.code);
+        }
+    });
+    diagnostics
+}
+
+/// Diagnostics for failed imports and import cycles.
+///
+/// Import demands have no source location,
+/// so each diagnostic is paired with the importing module instead.
+/// Cycle diagnostics are paired with every module in the cycle.
+/// Imports of modules whose file couldn't be read
+/// are reported too, since the importer can't see what it imports.
+/// Ambiguous imports name each module they match and its package,
+/// found in `package_world_map`, the world `graph` was resolved in.
+/// Imports of modules with a `deprecated` or `experimental` attribute
+/// are reported too, see `module_attrs`,
+/// at the line of the importing module that writes the import, if any.
+pub fn import_diagnostics<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    graph: PackageWorldModuleGraph<'db>,
+) -> Vec<(PackageModule, Diagnostic)> {
+    let mut diagnostics = vec![];
+    let no_span = |code, message| Diagnostic { code, span: None, message };
+    let origins: BTreeMap<PackageModule, String> = package_world_map.flatten_iter(db)
+        .map(|record| {
+            let module_name = record.package_module.name(db);
+            let origin = format!("`{module_name}` of package `{}/{}`", record.import_space, record.package_name);
+            (record.package_module, origin)
+        })
+        .collect();
+
+    for (package_module, imports) in graph.map(db) {
+        for (import_demand, resolved) in imports {
+            let (import_space, package_alias, module_alias) = import_demand;
+            let path = format!("{import_space}/{package_alias}/{module_alias}");
+            let diagnostic = match resolved {
+                ResolvedPackageModule::Resolved(module) => {
+                    let attrs = module_attrs(db, *module);
+                    let attr_lints = [
+                        (DEPRECATED, Code::DeprecatedImport, "a deprecated"),
+                        (EXPERIMENTAL, Code::ExperimentalImport, "an experimental"),
+                    ];
+                    for (attr, code, description) in attr_lints {
+                        let Some(value) = attrs.get(attr) else {
+                            continue;
+                        };
+                        let message = match value {
+                            Some(value) => format!("import `{path}` is of {description} module: {value}"),
+                            None => format!("import `{path}` is of {description} module"),
+                        };
+                        let span = import_demand_span(db, *package_module, import_demand);
+                        diagnostics.push((*package_module, Diagnostic { code, span, message }));
+                    }
+                    let Some(error) = module.text(db).load_error(db) else {
+                        continue;
+                    };
+                    no_span(
+                        Code::UnreadableModule,
+                        format!("import `{path}` is of a module that could not be read: {error}"),
+                    )
+                }
+                ResolvedPackageModule::Unresolved => {
+                    no_span(Code::UnresolvedImport, format!("unresolved import `{path}`"))
+                }
+                ResolvedPackageModule::Ambiguous(candidates, span) => {
+                    let origins = candidates.iter().map(|module| origins[module].as_str()).join(" and ");
+                    Diagnostic {
+                        code: Code::AmbiguousImport,
+                        span: span.map(|span| span.byte_span()),
+                        message: format!("import `{path}` matches {} modules: {origins}", candidates.len()),
+                    }
+                }
+                ResolvedPackageModule::Forbidden(_, ForbiddenReason::Private, span) => {
+                    Diagnostic {
+                        code: Code::ForbiddenImport,
+                        span: span.map(|span| span.byte_span()),
+                        message: format!("import `{path}` is of a package-private module"),
+                    }
+                }
+            };
+            diagnostics.push((*package_module, diagnostic));
+        }
+    }
+
+    for (package_module, shadowed_imports) in graph.shadowed(db) {
+        for shadowed_import in shadowed_imports {
+            let (import_space, package_alias, module_alias) = &shadowed_import.import_demand;
+            let path = format!("{import_space}/{package_alias}/{module_alias}");
+            let describe = |modules: &[PackageModule]| {
+                modules.iter().map(|module| origins[module].as_str()).join(" and ")
+            };
+            diagnostics.push((*package_module, no_span(
+                Code::ShadowedImport,
+                format!(
+                    "import `{path}` uses {}, shadowing {}",
+                    describe(&shadowed_import.used),
+                    describe(&shadowed_import.shadowed),
+                ),
+            )));
+        }
+    }
+
+    for group in graph.cycle_groups(db) {
+        let names = group.iter().map(|module| module.name(db).as_str()).sorted().join(", ");
+        for module in group {
+            diagnostics.push((module, no_span(
+                Code::ImportCycle,
+                format!("import cycle between modules {names}"),
+            )));
+        }
+    }
+
+    crate::metrics::record(|metrics| {
+        for (_, diagnostic) in &diagnostics {
+            metrics.diagnostic_emitted(diagnostic.code);
+        }
+    });
+    diagnostics
+}
+
+/// Diagnostics for packages whose main module is not a valid entry point,
+/// paired with the package.
+pub fn entry_point_diagnostics(
+    db: &dyn crate::Db,
+    world: PackageWorld,
+) -> Vec<(Package, Diagnostic)> {
+    let packages = world.pkglib_system(db).values()
+        .chain(world.pkglib_local(db).values());
+    let diagnostics: Vec<_> = packages
+        .filter_map(|package| {
+            let error = package.entry_point(db)?.err()?;
+            let name = package.name(db);
+            let main_module = package.main_module(db).as_deref().X();
+            let (code, message) = match error {
+                EntryPointError::Missing => (
+                    Code::MissingEntryPoint,
+                    format!("package `{name}` has no main module `{main_module}`"),
+                ),
+                EntryPointError::Private => (
+                    Code::PrivateEntryPoint,
+                    format!("main module `{main_module}` of package `{name}` is not public"),
+                ),
+            };
+            Some((*package, Diagnostic { code, span: None, message }))
+        })
+        .collect();
+
+    crate::metrics::record(|metrics| {
+        for (_, diagnostic) in &diagnostics {
+            metrics.diagnostic_emitted(diagnostic.code);
+        }
+    });
+    diagnostics
+}
+
+#[test]
+fn test_codes() {
+    let codes: Vec<_> = Code::all().map(|code| code.as_str()).collect();
+    assert!(codes.iter().tuple_windows().all(|(a, b)| a < b));
+    for code in Code::all() {
+        assert_eq!(Code::parse(code.as_str()), Some(code));
+        assert!(!code.explanation().is_empty());
+    }
+    assert_eq!(Code::parse("E0101"), Some(Code::UnresolvedImport));
+    assert_eq!(Code::parse("E9999"), None);
+}
+
+#[test]
+fn test_severity_config() {
+    let config = SeverityConfig::from_toml("[severity]\nE0101 = \"warn\"\nE0104 = \"allow\"\n").X();
+    assert_eq!(config.severity(Code::UnresolvedImport), Severity::Warn);
+    assert_eq!(config.severity(Code::ImportCycle), Severity::Allow);
+    assert_eq!(config.severity(Code::UnclosedBrace), Severity::Deny);
+
+    assert_eq!(SeverityConfig::from_toml("").X(), SeverityConfig::default());
+    assert!(SeverityConfig::from_toml("[severity]\nE9999 = \"warn\"\n").is_err());
+    assert!(SeverityConfig::from_toml("[severity]\nE0101 = \"loud\"\n").is_err());
+}
+
+#[test]
+fn test_bracer_diagnostics() {
+    let ref db = crate::Database::default();
+    let bracer = crate::bracer::bracer_for_str(db, "(a § b]");
+
+    let mut config = SeverityConfig::default();
+    config.overrides.insert(Code::UnmatchedClose, Severity::Warn);
+    config.overrides.insert(Code::InvalidToken, Severity::Allow);
+
+    let rendered: Vec<_> = bracer_diagnostics(db, bracer).iter()
+        .filter_map(|diagnostic| diagnostic.render(&config))
+        .collect();
+    assert_eq!(rendered, vec![
+        "error[E0001] 0..8: unclosed `(`",
+        "warning[E0002] 7..8: unmatched `]`",
+    ]);
+}
+
+#[test]
+fn test_unterminated_diagnostics() {
+    let diagnostics = |text: &str| {
+        let ref db = crate::Database::default();
+        let bracer = crate::bracer::bracer_for_str(db, text);
+        bracer_diagnostics(db, bracer).into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.X(), diagnostic.message))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(diagnostics("a = \"b"), vec![
+        (Code::UnterminatedToken, 4..5, S("unterminated string, expected a closing `\"`")),
+    ]);
+    assert_eq!(diagnostics("/* /* a */"), vec![
+        (Code::UnterminatedToken, 0..2, S("unterminated block comment, expected `*/`")),
+    ]);
+    assert_eq!(diagnostics("/* /* a"), vec![
+        (Code::UnterminatedToken, 0..2, S("unterminated block comment, expected 2 `*/`")),
+    ]);
+    assert_eq!(diagnostics("§"), vec![
+        (Code::InvalidToken, 0..2, S("invalid token \"§\"")),
+    ]);
+
+    let ref db = crate::Database::default();
+    let source = crate::input::Source::new(db, S("```x"));
+    let chunk = crate::source_map::source_map(db, source, crate::source_map::extended_config(db));
+    let bracer = crate::bracer::bracer(db, crate::lexer::lex_chunk(db, chunk));
+    assert_eq!(bracer_diagnostics(db, bracer).into_iter().map(|diagnostic| diagnostic.message).collect::<Vec<_>>(), vec![
+        S("unterminated embedded region, expected a closing `````"),
+    ]);
+}
+
+#[test]
+fn test_time_literal_diagnostics() {
+    let diagnostics = |text: &str| {
+        let ref db = crate::Database::default();
+        let source = crate::input::Source::new(db, S(text));
+        let chunk = crate::source_map::basic_source_map(db, source);
+        let config = crate::lexer::extended_config(db);
+        let bracer = crate::bracer::bracer(db, crate::lexer::lex_chunk_with_config(db, chunk, config));
+        bracer_diagnostics(db, bracer).into_iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.X(), diagnostic.message))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(diagnostics("a = 2024-01-01T00:00:00Z, b = 15m30s"), vec![]);
+    assert_eq!(diagnostics("a = 2024-02-30"), vec![
+        (Code::InvalidDatetime, 12..14, S("invalid datetime `2024-02-30`: day 30 is out of range for 2024-02")),
+    ]);
+    assert_eq!(diagnostics("a = 2024-01-01T00:00:00Q"), vec![
+        (Code::InvalidDatetime, 23..24, S("invalid datetime `2024-01-01T00:00:00Q`: \
+            expected `Z`, an offset like `+05:30`, or the end of the datetime")),
+    ]);
+    assert_eq!(diagnostics("a = 30s15m"), vec![
+        (Code::InvalidDuration, 9..10, S("invalid duration `30s15m`: unit `m` must come before `s`")),
+    ]);
+}
+
+#[test]
+fn test_render_in() {
+    let text = "a = 1\n\tb = f(\n  \tc])\n";
+    let config = SeverityConfig::default();
+    let render = |span: ByteSpan, tab_width| {
+        let diagnostic = Diagnostic { code: Code::UnmatchedClose, span: Some(span), message: S("unmatched `]`") };
+        diagnostic.render_in(&config, text, tab_width).X()
+    };
+    let close = text.find(']').X();
+
+    // Spaces before a tab don't move its stop.
+    assert_eq!(render(close..close + 1, 4), "\
+error[E0002] 3:6: unmatched `]`
+  |
+3 |     c])
+  |      ^");
+    assert_eq!(render(close..close + 1, 8), "\
+error[E0002] 3:10: unmatched `]`
+  |
+3 |         c])
+  |          ^");
+
+    // A span over several lines is underlined to the end of its first.
+    let open = text.find('(').X();
+    assert_eq!(render(open..close, 4), "\
+error[E0002] 2:10: unmatched `]`
+  |
+2 |     b = f(
+  |          ^");
+    let b = text.find('b').X();
+    assert_eq!(render(b..text.len(), 2), "\
+error[E0002] 2:3: unmatched `]`
+  |
+2 |   b = f(
+  |   ^^^^^^");
+
+    let diagnostic = Diagnostic { code: Code::InvalidToken, span: None, message: S("no span") };
+    assert_eq!(diagnostic.render_in(&config, text, 4).X(), "error[E0003]: no span");
+}
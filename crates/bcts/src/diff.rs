@@ -0,0 +1,303 @@
+//! Unified diff rendering of text edits.
+//!
+//! Hunks are built directly from the edited ranges,
+//! so no line diff algorithm is involved:
+//! every line touched by an edit is shown as removed and re-added,
+//! with `CONTEXT_LINES` of unchanged context around each hunk.
+
+use rmx::prelude::*;
+
+use rmx::std::fmt::Write as _;
+
+use crate::edit::{EditConflict, TextEdit, apply_edits};
+
+/// Unchanged lines shown before and after each change.
+pub const CONTEXT_LINES: usize = 3;
+
+/// Render `edits` to `text` as a unified diff of the file at `path`.
+///
+/// Returns an empty string if the edits change nothing.
+pub fn unified_diff(
+    path: &str,
+    text: &str,
+    edits: &[TextEdit],
+) -> Result<String, EditConflict> {
+    // Validates the edits.
+    apply_edits(text, edits)?;
+
+    let lines: Vec<(usize, &str)> = text.split_inclusive('\n')
+        .scan(0_usize, |offset, line| {
+            let start = *offset;
+            *offset = start.checked_add(line.len()).X();
+            Some((start, line))
+        })
+        .collect();
+
+    let changes = changed_line_groups(text, &lines, edits);
+    if changes.is_empty() {
+        return Ok(S(""));
+    }
+
+    let mut buf = String::new();
+    writeln!(buf, "--- a/{path}").X();
+    writeln!(buf, "+++ b/{path}").X();
+
+    // Added minus removed lines in hunks already written.
+    let mut line_delta: isize = 0;
+
+    for hunk in hunks(&changes, lines.len()) {
+        let mut body = String::new();
+        let mut old_count: usize = 0;
+        let mut new_count: usize = 0;
+        let mut line = hunk.lines.start;
+
+        for change in hunk.changes {
+            for (_, context) in &lines[line..change.lines.start] {
+                write_line(&mut body, ' ', context);
+            }
+            let context_count = change.lines.start.checked_sub(line).X();
+            old_count = old_count.checked_add(context_count).X();
+            new_count = new_count.checked_add(context_count).X();
+
+            for (_, old) in &lines[change.lines.C()] {
+                write_line(&mut body, '-', old);
+                old_count = old_count.checked_add(1).X();
+            }
+            for new in change.new_text.split_inclusive('\n') {
+                write_line(&mut body, '+', new);
+                new_count = new_count.checked_add(1).X();
+            }
+
+            line = change.lines.end;
+        }
+        for (_, context) in &lines[line..hunk.lines.end] {
+            write_line(&mut body, ' ', context);
+        }
+        let context_count = hunk.lines.end.checked_sub(line).X();
+        old_count = old_count.checked_add(context_count).X();
+        new_count = new_count.checked_add(context_count).X();
+
+        let old_start = hunk.lines.start;
+        let new_start = old_start.checked_add_signed(line_delta).X();
+        writeln!(
+            buf,
+            "@@ -{} +{} @@",
+            hunk_range(old_start, old_count),
+            hunk_range(new_start, new_count),
+        ).X();
+        buf.push_str(&body);
+
+        line_delta = line_delta
+            .checked_add_unsigned(new_count).X()
+            .checked_sub_unsigned(old_count).X();
+    }
+
+    Ok(buf)
+}
+
+/// A run of lines replaced by new text.
+struct Change {
+    /// Indexes into the original lines.
+    lines: rmx::std::ops::Range<usize>,
+    new_text: String,
+}
+
+struct Hunk<'c> {
+    lines: rmx::std::ops::Range<usize>,
+    changes: &'c [Change],
+}
+
+/// Group edits by the lines they touch,
+/// merging edits that touch the same lines.
+fn changed_line_groups(
+    text: &str,
+    lines: &[(usize, &str)],
+    edits: &[TextEdit],
+) -> Vec<Change> {
+    let line_of = |offset: usize| {
+        lines.partition_point(|(start, _)| *start <= offset).saturating_sub(1)
+    };
+
+    let mut groups: Vec<(rmx::std::ops::Range<usize>, Vec<&TextEdit>)> = vec![];
+    let sorted = edits.iter().sorted_by_key(|edit| (edit.range.start, edit.range.end));
+    for edit in sorted {
+        let first = line_of(edit.range.start);
+        let last = if edit.range.end > edit.range.start {
+            line_of(edit.range.end.checked_sub(1).X())
+        } else {
+            first
+        };
+        let edit_lines = first..last.checked_add(1).X().min(lines.len().max(1));
+        match groups.last_mut() {
+            Some((group_lines, group_edits)) if edit_lines.start < group_lines.end => {
+                group_lines.end = group_lines.end.max(edit_lines.end);
+                group_edits.push(edit);
+            }
+            _ => groups.push((edit_lines, vec![edit])),
+        }
+    }
+
+    groups.into_iter().filter_map(|(group_lines, group_edits)| {
+        let start = lines.get(group_lines.start).map(|(start, _)| *start).unwrap_or(0);
+        let end = lines.get(group_lines.end).map(|(start, _)| *start).unwrap_or(text.len());
+        let local_edits: Vec<TextEdit> = group_edits.into_iter().map(|edit| {
+            TextEdit {
+                range: edit.range.start.checked_sub(start).X()
+                    ..edit.range.end.checked_sub(start).X(),
+                new_text: edit.new_text.C(),
+            }
+        }).collect();
+        let old_text = &text[start..end];
+        let new_text = apply_edits(old_text, &local_edits).X();
+        (new_text != old_text).then(|| {
+            // Lines of an empty text are an empty range.
+            let lines = if lines.is_empty() { 0..0 } else { group_lines };
+            Change { lines, new_text }
+        })
+    }).collect()
+}
+
+/// Merge changes whose context would overlap into hunks.
+fn hunks(changes: &[Change], line_count: usize) -> Vec<Hunk<'_>> {
+    let with_context = |change: &Change| {
+        change.lines.start.saturating_sub(CONTEXT_LINES)
+            ..change.lines.end.saturating_add(CONTEXT_LINES).min(line_count)
+    };
+
+    let mut hunks: Vec<Hunk<'_>> = vec![];
+    let mut first = 0;
+    for (index, change) in changes.iter().enumerate() {
+        let lines = with_context(change);
+        match hunks.last_mut() {
+            Some(hunk) if lines.start <= hunk.lines.end => {
+                hunk.lines.end = lines.end;
+                hunk.changes = &changes[first..=index];
+            }
+            _ => {
+                first = index;
+                hunks.push(Hunk {
+                    lines,
+                    changes: &changes[index..=index],
+                });
+            }
+        }
+    }
+    hunks
+}
+
+/// Format a hunk header range from a 0-based start line.
+fn hunk_range(start: usize, count: usize) -> String {
+    if count == 0 {
+        // An empty range names the line before it.
+        format!("{start},0")
+    } else {
+        format!("{},{count}", start.checked_add(1).X())
+    }
+}
+
+fn write_line(buf: &mut String, prefix: char, line: &str) {
+    buf.push(prefix);
+    buf.push_str(line);
+    if !line.ends_with('\n') {
+        buf.push_str("\n\\ No newline at end of file\n");
+    }
+}
+
+#[test]
+fn test_unified_diff() {
+    let text = "a\nb\nc\nd\ne\nf\ng\nh\ni\nj\n";
+
+    assert_eq!(unified_diff("x.bct", text, &[]), Ok(S("")));
+    assert_eq!(
+        unified_diff("x.bct", text, &[TextEdit::replace(2..3, "b")]),
+        Ok(S("")),
+    );
+
+    assert_eq!(
+        unified_diff("x.bct", text, &[TextEdit::replace(8..9, "E")]).X(),
+        "\
+--- a/x.bct
++++ b/x.bct
+@@ -2,7 +2,7 @@
+ b
+ c
+ d
+-e
++E
+ f
+ g
+ h
+",
+    );
+
+    // Two changes far apart make two hunks,
+    // the second offset by the line added in the first.
+    assert_eq!(
+        unified_diff("x.bct", text, &[
+            TextEdit::insert(0, "z\n"),
+            TextEdit::delete(18..20),
+        ]).X(),
+        "\
+--- a/x.bct
++++ b/x.bct
+@@ -1,4 +1,5 @@
+-a
++z
++a
+ b
+ c
+ d
+@@ -7,4 +8,3 @@
+ g
+ h
+ i
+-j
+",
+    );
+
+    // Nearby changes share a hunk.
+    assert_eq!(
+        unified_diff("x.bct", text, &[
+            TextEdit::replace(0..1, "A"),
+            TextEdit::replace(4..5, "C"),
+        ]).X(),
+        "\
+--- a/x.bct
++++ b/x.bct
+@@ -1,6 +1,6 @@
+-a
++A
+ b
+-c
++C
+ d
+ e
+ f
+",
+    );
+}
+
+#[test]
+fn test_unified_diff_no_trailing_newline() {
+    assert_eq!(
+        unified_diff("x.bct", "(a", &[TextEdit::insert(2, ")")]).X(),
+        "\
+--- a/x.bct
++++ b/x.bct
+@@ -1,1 +1,1 @@
+-(a
+\\ No newline at end of file
++(a)
+\\ No newline at end of file
+",
+    );
+    assert_eq!(
+        unified_diff("x.bct", "", &[TextEdit::insert(0, "a\n")]).X(),
+        "\
+--- a/x.bct
++++ b/x.bct
+@@ -0,0 +1,1 @@
++a
+",
+    );
+}
@@ -0,0 +1,351 @@
+//! Human-readable dumps of pipeline outputs.
+//!
+//! These are stable text renderings of the lexer, bracer and lines
+//! outputs, and of resolved package worlds,
+//! meant for golden tests and debugging.
+//! Every dump ends each entry with a newline.
+
+use rmx::prelude::*;
+
+use rmx::std::collections::BTreeMap;
+use rmx::std::fmt::Write as _;
+use rmx::std::ops::Range;
+
+use crate::bracer::{Bracer, TreeToken};
+use crate::lexer::{ChunkLex, Token, TokenKind};
+use crate::line_index::LineIndex;
+use crate::package2::PackageModule;
+use crate::package_resolve2::{
+    ForbiddenReason,
+    ImportDemand,
+    PackageWorldMap,
+    PackageWorldModuleGraph,
+    ResolvedPackageModule,
+};
+use crate::text::ByteSpan;
+
+/// One line per token: kind, byte span, and text.
+pub fn lex_dump<'db>(
+    db: &'db dyn crate::Db,
+    chunk_lex: ChunkLex<'db>,
+) -> String {
+    let mut buf = String::new();
+    for token in chunk_lex.tokens(db) {
+        writeln!(buf, "{}", token_str(db, *token)).X();
+    }
+    buf
+}
+
+/// A header line, then one line per token, with tab-separated fields:
+/// kind, start and end byte offsets, line, column, and text.
+///
+/// Lines and columns are one-based,
+/// and columns count characters, a tab being one.
+/// In the text, tab, carriage return, newline and backslash
+/// are escaped as `\t`, `\r`, `\n` and `\\`.
+pub fn lex_tsv<'db>(
+    db: &'db dyn crate::Db,
+    chunk_lex: ChunkLex<'db>,
+) -> String {
+    let mut buf = String::from("kind\tstart\tend\tline\tcol\ttext\n");
+    for record in token_records(db, chunk_lex) {
+        let mut text = String::with_capacity(record.text.len());
+        for ch in record.text.chars() {
+            match ch {
+                '\t' => text.push_str("\\t"),
+                '\r' => text.push_str("\\r"),
+                '\n' => text.push_str("\\n"),
+                '\\' => text.push_str("\\\\"),
+                _ => text.push(ch),
+            }
+        }
+        writeln!(
+            buf, "{}\t{}\t{}\t{}\t{}\t{text}",
+            record.kind, record.span.start, record.span.end, record.line, record.column,
+        ).X();
+    }
+    buf
+}
+
+/// A JSON array of tokens, one per line, with the fields of `lex_tsv`:
+/// `kind`, `start`, `end`, `line`, `col` and `text`.
+pub fn lex_json<'db>(
+    db: &'db dyn crate::Db,
+    chunk_lex: ChunkLex<'db>,
+) -> String {
+    let records: Vec<_> = token_records(db, chunk_lex).into_iter().map(|record| {
+        rmx::serde_json::json!({
+            "kind": record.kind,
+            "start": record.span.start,
+            "end": record.span.end,
+            "line": record.line,
+            "col": record.column,
+            "text": record.text,
+        }).to_string()
+    }).collect();
+    match records.is_empty() {
+        true => S("[]\n"),
+        false => format!("[\n{}\n]\n", records.join(",\n")),
+    }
+}
+
+/// A token as written by `lex_tsv` and `lex_json`.
+struct TokenRecord<'db> {
+    kind: &'static str,
+    span: ByteSpan,
+    /// One-based.
+    line: usize,
+    /// One-based, in characters.
+    column: usize,
+    text: &'db str,
+}
+
+fn token_records<'db>(
+    db: &'db dyn crate::Db,
+    chunk_lex: ChunkLex<'db>,
+) -> Vec<TokenRecord<'db>> {
+    let line_index = LineIndex::new(chunk_lex.chunk(db).text(db).as_str(db), 1);
+    chunk_lex.tokens(db).iter().map(|token| {
+        let subtext = token.text(db);
+        let span = subtext.range(db);
+        let line_col = line_index.line_col(span.start);
+        TokenRecord {
+            kind: kind_name(token.kind(db)),
+            span,
+            line: line_col.line.checked_add(1).X(),
+            column: line_col.column.checked_add(1).X(),
+            text: subtext.as_str(db),
+        }
+    }).collect()
+}
+
+/// The name of a kind of token, as written in dumps and stats.
+pub fn kind_name(kind: TokenKind) -> &'static str {
+    match kind {
+        TokenKind::Word => "word",
+        TokenKind::Sigil(_) => "sigil",
+        TokenKind::String => "string",
+        TokenKind::ByteString => "bytes",
+        TokenKind::Datetime => "datetime",
+        TokenKind::Duration => "duration",
+        TokenKind::Literal(_) => "literal",
+        TokenKind::Whitespace => "whitespace",
+        TokenKind::Comment => "comment",
+        TokenKind::Embedded => "embedded",
+        TokenKind::Error => "error",
+    }
+}
+
+/// The brace tree, one token per line, indented by depth.
+///
+/// Branches print their open sigil, their contents
+/// one level deeper, then their close sigil.
+/// Closes inserted by the bracer are printed like real ones.
+pub fn tree_dump<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> String {
+    let mut buf = String::new();
+    write_tree(db, bracer.iter(db), 0, &mut buf);
+    buf
+}
+
+fn write_tree<'db>(
+    db: &'db dyn crate::Db,
+    iter: impl Iterator<Item = TreeToken<'db>>,
+    depth: usize,
+    buf: &mut String,
+) {
+    let indent = "  ".repeat(depth);
+    for tree_token in iter {
+        match tree_token {
+            TreeToken::Token(token) => {
+                writeln!(buf, "{indent}{}", token_str(db, token)).X();
+            }
+            TreeToken::Branch(sigil, branch_iter) => {
+                writeln!(buf, "{indent}{}", sigil.as_str()).X();
+                let depth = depth.checked_add(1).X();
+                rmx::extras::recurse(|| {
+                    write_tree(db, branch_iter, depth, buf)
+                });
+                writeln!(buf, "{indent}{}", sigil.close_sigil().as_str()).X();
+            }
+        }
+    }
+}
+
+/// One entry per line: byte span and source text of the line.
+pub fn lines_dump<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> String {
+    let mut buf = String::new();
+    let text = bracer.chunk(db).chunk(db).text(db).as_str(db);
+    for line in bracer.iter(db).lines() {
+        let span = line
+            .filter_map(|tree_token| tree_token.text_span(db))
+            .map(|text_span| text_span.span)
+            .reduce(|a, b| a.start.min(b.start)..a.end.max(b.end));
+        if let Some(span) = span {
+            writeln!(buf, "{} {:?}", span_str(&span), &text[span]).X();
+        }
+    }
+    buf
+}
+
+/// One line per error found by the lexer or bracer.
+///
+/// Lexer errors come first, in source order, then bracer errors.
+pub fn diagnostics_dump<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> String {
+    let mut buf = String::new();
+    let tokens = bracer.chunk(db).tokens(db);
+    let chunk = bracer.chunk(db).chunk(db);
+    let chunk_text = chunk.text(db).as_str(db);
+    for token in tokens {
+        if token.kind(db) == TokenKind::Error {
+            let subtext = token.text(db);
+            let range = subtext.range(db);
+            let reason = chunk.errors(db).iter().zip(chunk.error_reasons(db))
+                .find(|(error, _)| error.start == range.start);
+            match reason {
+                Some((_, reason)) => writeln!(
+                    buf, "error {}: {}",
+                    span_str(&reason.open_span()), reason.message(chunk_text),
+                ).X(),
+                None => writeln!(
+                    buf, "error {}: invalid token {:?}",
+                    span_str(&range), subtext.as_str(db),
+                ).X(),
+            }
+        }
+    }
+    for (token_range, sigil) in bracer.errors(db) {
+        let span = token_range_span(db, tokens, token_range);
+        if sigil.is_close_sigil() {
+            writeln!(buf, "error {}: unmatched `{}`", span_str(&span), sigil.as_str()).X();
+        } else {
+            writeln!(buf, "error {}: unclosed `{}`", span_str(&span), sigil.as_str()).X();
+        }
+    }
+    buf
+}
+
+/// The resolved imports of every module, in world order,
+/// then the import cycles.
+///
+/// Modules are written as `<import space>/<package>:<module>`,
+/// which tells apart modules with colliding import paths,
+/// and imports as demanded, leaving out an empty package alias.
+pub fn resolve_dump<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    graph: PackageWorldModuleGraph<'db>,
+) -> String {
+    let paths: BTreeMap<PackageModule, String> = package_world_map.flatten_iter(db)
+        .map(|record| {
+            let path = format!(
+                "{}/{}:{}",
+                record.import_space, record.package_name, record.package_module.name(db),
+            );
+            (record.package_module, path)
+        })
+        .collect();
+
+    let mut buf = String::new();
+    for (record, imports) in graph.imports_in_world_order(db, package_world_map) {
+        writeln!(buf, "{}", paths[&record.package_module]).X();
+        for (import_demand, resolved) in imports {
+            let import = import_demand_str(import_demand);
+            match resolved {
+                ResolvedPackageModule::Resolved(module) => {
+                    writeln!(buf, "  {import} -> {}", paths[module]).X();
+                }
+                ResolvedPackageModule::Unresolved => {
+                    writeln!(buf, "  {import} unresolved").X();
+                }
+                ResolvedPackageModule::Ambiguous(candidates, _) => {
+                    let candidates = candidates.iter().map(|module| paths[module].as_str()).join(" ");
+                    writeln!(buf, "  {import} ambiguous {candidates}").X();
+                }
+                ResolvedPackageModule::Forbidden(module, ForbiddenReason::Private, _) => {
+                    writeln!(buf, "  {import} forbidden private {}", paths[module]).X();
+                }
+            }
+        }
+    }
+    for group in graph.cycle_groups(db) {
+        let modules = group.iter().map(|module| paths[module].as_str()).sorted().join(" ");
+        writeln!(buf, "cycle {modules}").X();
+    }
+    buf
+}
+
+fn import_demand_str(import_demand: &ImportDemand) -> String {
+    let (import_space, package_alias, module_alias) = import_demand;
+    if package_alias.is_empty() {
+        format!("{import_space}/{module_alias}")
+    } else {
+        format!("{import_space}/{package_alias}/{module_alias}")
+    }
+}
+
+fn token_range_span<'db>(
+    db: &'db dyn crate::Db,
+    tokens: &[Token<'db>],
+    token_range: &Range<usize>,
+) -> ByteSpan {
+    let first = &tokens[token_range.start];
+    let last = &tokens[token_range.end.checked_sub(1).X()];
+    first.text(db).text_span(db).merge(&last.text(db).text_span(db)).X().span
+}
+
+fn token_str<'db>(
+    db: &'db dyn crate::Db,
+    token: Token<'db>,
+) -> String {
+    let subtext = token.text(db);
+    format!(
+        "{:?} {} {:?}",
+        token.kind(db),
+        span_str(&subtext.range(db)),
+        subtext.as_str(db),
+    )
+}
+
+fn span_str(span: &ByteSpan) -> String {
+    format!("{}..{}", span.start, span.end)
+}
+
+#[test]
+fn test_lex_records() {
+    let ref db = crate::Database::default();
+    let chunk_lex = |text: &str| {
+        let source = crate::input::Source::new(db, S(text));
+        crate::lexer::lex_chunk(db, crate::source_map::basic_source_map(db, source))
+    };
+
+    let tokens = chunk_lex("a =\t\"\\x\"\n  é.b");
+    assert_eq!(lex_tsv(db, tokens), "\
+kind\tstart\tend\tline\tcol\ttext
+word\t0\t1\t1\t1\ta
+whitespace\t1\t2\t1\t2\t 
+sigil\t2\t3\t1\t3\t=
+whitespace\t3\t4\t1\t4\t\\t
+string\t4\t8\t1\t5\t\"\\\\x\"
+whitespace\t8\t11\t1\t9\t\\n  
+word\t11\t13\t2\t3\té
+sigil\t13\t14\t2\t4\t.
+word\t14\t15\t2\t5\tb
+");
+
+    assert_eq!(lex_json(db, chunk_lex("x;")), "\
+[
+{\"col\":1,\"end\":1,\"kind\":\"word\",\"line\":1,\"start\":0,\"text\":\"x\"},
+{\"col\":2,\"end\":2,\"kind\":\"sigil\",\"line\":1,\"start\":1,\"text\":\";\"}
+]
+");
+    assert_eq!(lex_json(db, chunk_lex("")), "[]\n");
+}
@@ -0,0 +1,113 @@
+//! Empty and whitespace-only sources through every pass.
+//!
+//! The guarantees tested here are documented on
+//! `chunks::chunks`, `lexer::lex_chunk_with_config`,
+//! `bracer::bracer` and `lines::iter_lines`.
+
+use rmx::prelude::*;
+
+use crate::bracer::{IteratorOfTreeTokenExt as _, TreeToken};
+use crate::lexer::TokenKind;
+
+struct Passes {
+    chunks: Vec<String>,
+    tokens: Vec<(TokenKind, String)>,
+    bracer: String,
+    lines: Vec<String>,
+    /// The lines of each top-level branch.
+    branch_lines: Vec<Vec<String>>,
+}
+
+fn passes(s: &str) -> Passes {
+    let ref db = crate::Database::default();
+    let source = crate::input::Source::new(db, S(s));
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let chunks = crate::chunks::basic_chunks(db, chunk);
+    assert_eq!(chunks.check(db, chunk), Ok(()));
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    let bracer = crate::bracer::bracer(db, chunk_lex);
+    Passes {
+        chunks: chunks.chunks(db).iter()
+            .map(|chunk| S(chunk.text(db).as_str(db)))
+            .collect(),
+        tokens: chunk_lex.tokens(db).iter()
+            .map(|token| (token.kind(db), S(token.text(db).as_str(db))))
+            .collect(),
+        bracer: bracer.iter(db).debug_str(db),
+        lines: bracer.iter(db).lines()
+            .map(|line| line.debug_str(db))
+            .collect(),
+        branch_lines: bracer.iter(db)
+            .filter_map(|token| match token {
+                TreeToken::Branch(_, iter) => Some(iter.lines().map(|line| line.debug_str(db)).collect()),
+                TreeToken::Token(_) => None,
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_empty_source() {
+    let p = passes("");
+    assert_eq!(p.chunks, Vec::<String>::new());
+    assert_eq!(p.tokens, vec![]);
+    assert_eq!(p.bracer, "");
+    assert_eq!(p.lines, Vec::<String>::new());
+}
+
+#[test]
+fn test_whitespace_source() {
+    for s in [" ", "\n", "\t\t", " \n\t\n ", "\r\n"] {
+        let p = passes(s);
+        assert_eq!(p.chunks, vec![S(s)], "{s:?}");
+        assert_eq!(p.tokens, vec![(TokenKind::Whitespace, S(s))], "{s:?}");
+        assert_eq!(p.bracer, "ws", "{s:?}");
+        assert_eq!(p.lines, vec![S("ws")], "{s:?}");
+    }
+}
+
+#[test]
+fn test_whitespace_chunks() {
+    let p = passes("\n.\n");
+    assert_eq!(p.chunks, vec![S("\n."), S("\n")]);
+    assert_eq!(p.lines, vec![S("ws"), S(". ws")]);
+
+    let p = passes(" . ");
+    assert_eq!(p.chunks, vec![S(" ."), S(" ")]);
+    assert_eq!(p.lines, vec![S("ws . ws")]);
+}
+
+#[test]
+fn test_empty_branches() {
+    for (s, bracer) in [("()", "( )"), ("{}", "{ }"), ("[]", "[ ]"), ("(", "( )")] {
+        let p = passes(s);
+        assert_eq!(p.bracer, bracer, "{s:?}");
+        assert_eq!(p.lines, vec![S(bracer)], "{s:?}");
+        assert_eq!(p.branch_lines, vec![Vec::<String>::new()], "{s:?}");
+    }
+
+    let p = passes("(())");
+    assert_eq!(p.bracer, "( ( ) )");
+    assert_eq!(p.branch_lines, vec![vec![S("( )")]]);
+}
+
+#[test]
+fn test_whitespace_branches() {
+    let p = passes("( )");
+    assert_eq!(p.lines, vec![S("( ws )")]);
+    assert_eq!(p.branch_lines, vec![vec![S("ws")]]);
+
+    let p = passes("a(\n\n)b\n");
+    assert_eq!(p.lines, vec![S("a ( ws ) b ws")]);
+    assert_eq!(p.branch_lines, vec![vec![S("ws")]]);
+}
+
+#[test]
+fn test_empty_string() {
+    let p = passes("\"\"");
+    assert_eq!(p.tokens, vec![(TokenKind::String, S("\"\""))]);
+    assert_eq!(p.lines, vec![S("\"\"")]);
+
+    let p = passes("(\"\")");
+    assert_eq!(p.branch_lines, vec![vec![S("\"\"")]]);
+}
@@ -0,0 +1,169 @@
+//! Text edits and their application.
+//!
+//! Fix-its, renames and the formatter all describe their changes
+//! as `TextEdit`s against the original text.
+//! `apply_edits` applies a batch of them at once,
+//! refusing batches whose edits overlap.
+
+use rmx::prelude::*;
+
+use rmx::std::fmt;
+
+use crate::text::ByteSpan;
+
+/// Replace a byte range of the original text.
+///
+/// An empty range is an insertion,
+/// empty `new_text` is a deletion.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TextEdit {
+    pub range: ByteSpan,
+    pub new_text: String,
+}
+
+impl TextEdit {
+    pub fn replace(range: ByteSpan, new_text: &str) -> TextEdit {
+        TextEdit { range, new_text: S(new_text) }
+    }
+
+    pub fn insert(offset: usize, new_text: &str) -> TextEdit {
+        TextEdit { range: offset..offset, new_text: S(new_text) }
+    }
+
+    pub fn delete(range: ByteSpan) -> TextEdit {
+        TextEdit { range, new_text: S("") }
+    }
+}
+
+/// Why a batch of edits could not be applied.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum EditConflict {
+    /// Two edits change overlapping ranges.
+    Overlap(TextEdit, TextEdit),
+    /// An edit's range is reversed or extends past the end of the text.
+    OutOfBounds(TextEdit),
+    /// An edit's range splits a UTF-8 character.
+    NotCharBoundary(TextEdit),
+}
+
+impl fmt::Display for EditConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditConflict::Overlap(a, b) => {
+                write!(f, "edits at {:?} and {:?} overlap", a.range, b.range)
+            }
+            EditConflict::OutOfBounds(edit) => {
+                write!(f, "edit at {:?} is out of bounds", edit.range)
+            }
+            EditConflict::NotCharBoundary(edit) => {
+                write!(f, "edit at {:?} is not on a char boundary", edit.range)
+            }
+        }
+    }
+}
+
+impl rmx::std::error::Error for EditConflict { }
+
+/// Apply `edits` to `text`.
+///
+/// All edit ranges refer to the original `text`, in any order.
+/// Edits may touch, but not overlap.
+/// Insertions at the same offset are applied in the order given,
+/// and an insertion at the start of a replaced range goes before it.
+pub fn apply_edits(text: &str, edits: &[TextEdit]) -> Result<String, EditConflict> {
+    for edit in edits {
+        if edit.range.start > edit.range.end || edit.range.end > text.len() {
+            return Err(EditConflict::OutOfBounds(edit.C()));
+        }
+        if !text.is_char_boundary(edit.range.start) || !text.is_char_boundary(edit.range.end) {
+            return Err(EditConflict::NotCharBoundary(edit.C()));
+        }
+    }
+
+    let sorted: Vec<&TextEdit> = edits.iter()
+        .sorted_by_key(|edit| (edit.range.start, edit.range.end))
+        .collect();
+
+    for (a, b) in sorted.iter().tuple_windows() {
+        if b.range.start < a.range.end {
+            return Err(EditConflict::Overlap((*a).C(), (*b).C()));
+        }
+    }
+
+    let mut buf = String::with_capacity(text.len());
+    let mut position = 0;
+    for edit in sorted {
+        buf.push_str(&text[position..edit.range.start]);
+        buf.push_str(&edit.new_text);
+        position = edit.range.end;
+    }
+    buf.push_str(&text[position..]);
+
+    Ok(buf)
+}
+
+#[test]
+fn test_apply_edits() {
+    let text = "let x = (a + b];";
+
+    assert_eq!(apply_edits(text, &[]), Ok(S(text)));
+    assert_eq!(
+        apply_edits(text, &[TextEdit::replace(14..15, ")")]),
+        Ok(S("let x = (a + b);")),
+    );
+    assert_eq!(
+        apply_edits(text, &[
+            TextEdit::replace(14..15, ")"),
+            TextEdit::replace(4..5, "y"),
+            TextEdit::delete(15..16),
+        ]),
+        Ok(S("let y = (a + b)")),
+    );
+    assert_eq!(
+        apply_edits(text, &[
+            TextEdit::insert(0, "a"),
+            TextEdit::insert(0, "b"),
+            TextEdit::replace(0..3, "var"),
+            TextEdit::insert(3, "!"),
+        ]),
+        Ok(S("abvar! x = (a + b];")),
+    );
+    assert_eq!(apply_edits("", &[TextEdit::insert(0, "x")]), Ok(S("x")));
+}
+
+#[test]
+fn test_apply_edits_conflicts() {
+    let text = "a bé";
+
+    let a = TextEdit::replace(0..2, "x");
+    let b = TextEdit::delete(1..3);
+    assert_eq!(
+        apply_edits(text, &[b.C(), a.C()]),
+        Err(EditConflict::Overlap(a.C(), b.C())),
+    );
+
+    let inside = TextEdit::insert(1, "y");
+    assert_eq!(
+        apply_edits(text, &[a.C(), inside.C()]),
+        Err(EditConflict::Overlap(a.C(), inside.C())),
+    );
+
+    let past_end = TextEdit::insert(6, "z");
+    assert_eq!(
+        apply_edits(text, &[past_end.C()]),
+        Err(EditConflict::OutOfBounds(past_end)),
+    );
+
+    #[allow(clippy::reversed_empty_ranges)]
+    let reversed = TextEdit::delete(2..1);
+    assert_eq!(
+        apply_edits(text, &[reversed.C()]),
+        Err(EditConflict::OutOfBounds(reversed)),
+    );
+
+    let split_char = TextEdit::delete(3..4);
+    assert_eq!(
+        apply_edits(text, &[split_char.C()]),
+        Err(EditConflict::NotCharBoundary(split_char)),
+    );
+}
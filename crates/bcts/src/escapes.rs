@@ -5,6 +5,8 @@ pub enum EscapeError {
     InvalidEscape { position: usize, escape: char },
     InvalidUnicodeEscape { position: usize, reason: String },
     UnterminatedUnicodeEscape { position: usize },
+    InvalidHexEscape { position: usize, reason: String },
+    NonAsciiByte { position: usize, ch: char },
 }
 
 /// Process escape sequences in a string literal.
@@ -88,6 +90,54 @@ pub fn process_escape_sequences(s: &str) -> Result<String, EscapeError> {
     Ok(result)
 }
 
+/// Process escape sequences in a byte string literal.
+///
+/// The input is the content between the quotes, as for
+/// `process_escape_sequences`, which must be ASCII.
+/// The escapes are those of strings but `\u`,
+/// plus `\xNN`, a byte as two hex digits.
+pub fn process_byte_escape_sequences(s: &str) -> Result<Vec<u8>, EscapeError> {
+    let mut result = Vec::with_capacity(s.len());
+    let mut chars = s.char_indices();
+
+    while let Some((i, ch)) = chars.next() {
+        if !ch.is_ascii() {
+            return Err(EscapeError::NonAsciiByte { position: i, ch });
+        }
+        if ch == '\\' {
+            match chars.next() {
+                Some((_, '"')) => result.push(b'"'),
+                Some((_, '\\')) => result.push(b'\\'),
+                Some((_, 'n')) => result.push(b'\n'),
+                Some((_, 'r')) => result.push(b'\r'),
+                Some((_, 't')) => result.push(b'\t'),
+                Some((_, '0')) => result.push(b'\0'),
+                Some((pos, 'x')) => {
+                    let hex_str: String = chars.by_ref().take(2).map(|(_, ch)| ch).collect();
+                    if hex_str.len() != 2 || !hex_str.chars().all(|ch| ch.is_ascii_hexdigit()) {
+                        return Err(EscapeError::InvalidHexEscape {
+                            position: pos,
+                            reason: format!("expected 2 hex digits, got {hex_str:?}"),
+                        });
+                    }
+                    result.push(u8::from_str_radix(&hex_str, 16).X());
+                }
+                Some((_, esc)) => {
+                    return Err(EscapeError::InvalidEscape { position: i, escape: esc });
+                }
+                None => {
+                    // Trailing backslash.
+                    return Err(EscapeError::InvalidEscape { position: i, escape: '\\' });
+                }
+            }
+        } else {
+            result.push(u8::try_from(ch).X());
+        }
+    }
+
+    Ok(result)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -164,4 +214,26 @@ mod tests {
             Err(EscapeError::InvalidEscape { .. })
         ));
     }
+
+    #[test]
+    fn test_byte_escapes() {
+        assert_eq!(process_byte_escape_sequences(r#"a\"\\\n\r\t\0"#).unwrap(), b"a\"\\\n\r\t\0");
+        assert_eq!(process_byte_escape_sequences(r#"\x00\xfF\x7f"#).unwrap(), b"\x00\xff\x7f");
+        assert!(matches!(
+            process_byte_escape_sequences(r#"\x4"#),
+            Err(EscapeError::InvalidHexEscape { position: 1, .. })
+        ));
+        assert!(matches!(
+            process_byte_escape_sequences(r#"\x+1"#),
+            Err(EscapeError::InvalidHexEscape { .. })
+        ));
+        assert!(matches!(
+            process_byte_escape_sequences(r#"\u{41}"#),
+            Err(EscapeError::InvalidEscape { escape: 'u', .. })
+        ));
+        assert!(matches!(
+            process_byte_escape_sequences("a\u{e9}"),
+            Err(EscapeError::NonAsciiByte { position: 1, ch: '\u{e9}' })
+        ));
+    }
 }
@@ -0,0 +1,180 @@
+//! Automatic fixes for diagnostics.
+//!
+//! Fixes are `TextEdit`s against the source text,
+//! to be shown with `diff::unified_diff` or applied with `edit::apply_edits`.
+
+use rmx::prelude::*;
+
+use crate::bracer::Bracer;
+use crate::edit::TextEdit;
+use crate::import_lines::import_demand_span;
+use crate::lexer::TokenKind;
+use crate::module_attrs::{DEPRECATED, deprecated_replacement, module_attrs};
+use crate::package2::PackageModule;
+use crate::package_resolve2::{PackageWorldModuleGraph, ResolvedPackageModule};
+
+/// Fixes for the brace errors repaired by the bracer.
+///
+/// These make the source say what the bracer already assumed:
+/// unmatched closes are deleted,
+/// closes the bracer inserted are written in,
+/// and braces still open at the end are closed there.
+pub fn bracer_fixes<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> Vec<TextEdit> {
+    let tokens = bracer.chunk(db).tokens(db);
+    let text = bracer.chunk(db).chunk(db).text(db).as_str(db);
+    let text_len = text.len();
+    let token_start = |index: usize| {
+        tokens.get(index).map(|token| token.text(db).range(db).start).unwrap_or(text_len)
+    };
+
+    let mut edits = vec![];
+
+    for (index, _) in bracer.removed_closes(db) {
+        edits.push(TextEdit::delete(tokens[*index].text(db).range(db)));
+    }
+
+    for (index, sigil) in bracer.inserted_closes(db) {
+        edits.push(TextEdit::insert(token_start(*index), sigil.as_str()));
+    }
+
+    // Unclosed braces are reported with a range to the end of the tokens.
+    // Close the innermost first.
+    let closes: String = bracer.errors(db).iter()
+        .filter(|(range, sigil)| !sigil.is_close_sigil() && range.end == tokens.len())
+        .sorted_by_key(|(range, _)| rmx::std::cmp::Reverse(range.start))
+        .map(|(_, sigil)| sigil.close_sigil().as_str())
+        .collect();
+    // The closes go at the end of the text, on a line of their own
+    // if the text ends in a newline, so it still does,
+    // or if it ends in a line comment, which would take them in.
+    let last = tokens.iter().rev().find(|token| token.kind(db) != TokenKind::Whitespace);
+    if let Some(last) = last.filter(|_| !closes.is_empty()) {
+        let range = last.text(db).range(db);
+        let line_ending = line_ending(text);
+        let closes = if text[range.end..].contains('\n') {
+            format!("{closes}{line_ending}")
+        } else if last.kind(db) == TokenKind::Comment && text[range].starts_with("//") {
+            format!("{line_ending}{closes}")
+        } else {
+            closes
+        };
+        edits.push(TextEdit::insert(text_len, &closes));
+    }
+
+    edits
+}
+
+/// The line ending of the first line of `text`, `\n` if it has one line.
+pub fn line_ending(text: &str) -> &'static str {
+    match text.find('\n') {
+        Some(newline) if text[..newline].ends_with('\r') => "\r\n",
+        _ => "\n",
+    }
+}
+
+/// Fixes for the imports of `package_module` of deprecated modules
+/// whose `deprecated` attribute names a replacement, see `module_attrs`.
+///
+/// The import's path is replaced where `import_lines` finds it written.
+/// A replacement in the `pkg` import space is relative to the
+/// deprecated module's package, so from another package it is written
+/// in the import space and package the import named.
+/// Replacements in the `self` and `super` import spaces,
+/// relative to the deprecated module itself, are not fixed.
+pub fn deprecation_fixes<'db>(
+    db: &'db dyn crate::Db,
+    graph: PackageWorldModuleGraph<'db>,
+    package_module: PackageModule,
+) -> Vec<TextEdit> {
+    let Some(imports) = graph.map(db).get(&package_module) else {
+        return vec![];
+    };
+    imports.iter()
+        .filter_map(|(import_demand, resolved)| {
+            let ResolvedPackageModule::Resolved(module) = resolved else {
+                return None;
+            };
+            let value = module_attrs(db, *module).get(DEPRECATED)?.as_ref()?;
+            let replacement = deprecated_replacement(value)?;
+            let (import_space, package_alias, _) = import_demand;
+            let replacement = match replacement.split_once('/') {
+                Some(("self" | "super", _)) => return None,
+                Some(("pkg", path)) if import_space != "pkg" => format!("{import_space}/{package_alias}/{path}"),
+                _ => S(replacement),
+            };
+            let span = import_demand_span(db, package_module, import_demand)?;
+            Some(TextEdit::replace(span, &replacement))
+        })
+        .collect()
+}
+
+#[test]
+fn test_bracer_fixes() {
+    fn fix(s: &str) -> String {
+        let ref db = crate::Database::default();
+        let bracer = crate::bracer::bracer_for_str(db, s);
+        crate::edit::apply_edits(s, &bracer_fixes(db, bracer)).X()
+    }
+
+    assert_eq!(fix(""), "");
+    assert_eq!(fix("(a)"), "(a)");
+    assert_eq!(fix("a)"), "a");
+    assert_eq!(fix("(a"), "(a)");
+    assert_eq!(fix("([{a"), "([{a}])");
+    assert_eq!(fix("{(a}"), "{(a)}");
+    assert_eq!(fix("[a)] b]"), "[a] b");
+    assert_eq!(fix("(a\n"), "(a\n)\n");
+    assert_eq!(fix("(a\r\n  "), "(a\r\n  )\r\n");
+    assert_eq!(fix("(a // b"), "(a // b\n)");
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn fixed_main(db: &dyn crate::Db, fixture: crate::world_fixture::WorldFixture) -> String {
+    let world = crate::world_fixture::fixture_world(db, fixture).X();
+    let package_world_map = world.package_world_map(db);
+    let graph = crate::package_resolve2::package_world_graph(db, package_world_map, world.import_demand_map(db));
+    let main = package_world_map.map(db)["local"]["app"].modules(db)["main"];
+    let text = main.text(db).text(db);
+    crate::edit::apply_edits(text, &deprecation_fixes(db, graph, main)).X()
+}
+
+#[test]
+fn test_deprecation_fixes() {
+    let ref db = crate::Database::default();
+    let fixture = crate::world_fixture::WorldFixture::new(db, S("\
+//@ package sys/core
+//@ module old
+attr deprecated \"use pkg/new\"
+//@ module legacy
+attr deprecated \"use sys/alloc/vec\"
+//@ module gone
+attr deprecated \"removed in 2.0\"
+//@ module near
+attr deprecated \"use self/new\"
+//@ module new
+
+//@ package local/app
+//@ module main
+//@ import sys core old
+//@ import sys core legacy
+//@ import sys core gone
+//@ import sys core near
+//@ import pkg util
+import module sys/core/old
+import module sys/core/legacy
+import module sys/core/gone
+import module sys/core/near
+//@ module util
+attr deprecated \"use pkg/helpers\"
+"));
+    assert_eq!(fixed_main(db, fixture), "\
+import module sys/core/new
+import module sys/alloc/vec
+import module sys/core/gone
+import module sys/core/near
+");
+}
@@ -0,0 +1,473 @@
+//! The source formatter.
+//!
+//! Formatting only changes whitespace between lines,
+//! so the formatted source is always equal modulo trivia
+//! to the original, see `tree_eq`:
+//!
+//! - Each line is indented by `indent_width` spaces
+//!   per brace it is inside,
+//!   one less if it starts with a close brace.
+//! - Trailing whitespace is removed.
+//! - Runs of more than `max_blank_lines` blank lines are cut to that many.
+//! - Leading whitespace of the file is removed,
+//!   and a non-empty file ends with exactly one newline,
+//!   unless it ends in an error token, like an unterminated comment.
+//!
+//! Whitespace within a line is left as written,
+//! as is everything inside comments, strings and embedded regions,
+//! even when they span lines.
+//! Line endings are kept, `\r\n` or `\n`.
+//!
+//! With `wrap_comments`, line comments alone on a line
+//! that run past `max_width` are wrapped onto more line comments
+//! with the same prefix and indentation.
+//! Block comments are never wrapped.
+//!
+//! Formatting is idempotent: formatting formatted source changes nothing.
+
+use rmx::prelude::*;
+
+use rmx::std::collections::BTreeMap;
+use rmx::std::iter;
+
+use crate::bracer::Bracer;
+use crate::edit::{TextEdit, apply_edits};
+use crate::input::Source;
+use crate::lexer::{Sigil, TokenKind};
+use crate::project_config::FormatOptions;
+
+/// Edits formatting `source`.
+pub fn format_edits(
+    db: &dyn crate::Db,
+    source: Source,
+    options: &FormatOptions,
+) -> Vec<TextEdit> {
+    bracer_format_edits(db, crate::bracer::basic_bracer(db, source), options)
+}
+
+/// `source` formatted.
+pub fn format_source(
+    db: &dyn crate::Db,
+    source: Source,
+    options: &FormatOptions,
+) -> String {
+    apply_edits(source.text(db), &format_edits(db, source, options)).X()
+}
+
+/// Edits formatting the source of `bracer`.
+pub fn bracer_format_edits<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+    options: &FormatOptions,
+) -> Vec<TextEdit> {
+    let _span = tracing::debug_span!("format").entered();
+    let tokens = bracer.chunk(db).tokens(db);
+    let text = bracer.chunk(db).chunk(db).text(db).as_str(db);
+    let depths = BraceDepths::new(db, bracer);
+
+    // The indentation of a line starting with the token at `index`.
+    let indent = |index: usize| {
+        " ".repeat(depths.line_depth(index).checked_mul(options.indent_width).X())
+    };
+
+    // Whether the token at `index` is the first on its line.
+    let starts_line = |index: usize| {
+        let Some(previous) = index.checked_sub(1) else {
+            return true;
+        };
+        tokens[previous].kind(db) == TokenKind::Whitespace
+            && (previous == 0 || tokens[previous].text(db).as_str(db).contains('\n'))
+    };
+
+    let mut edits = vec![];
+    let last = tokens.len().checked_sub(1);
+    for (index, token) in tokens.iter().enumerate() {
+        if token.kind(db) == TokenKind::Comment && options.wrap_comments && starts_line(index) {
+            let range = token.text(db).range(db);
+            // A line comment ends before `\n`, so includes the `\r` of `\r\n`.
+            let comment = &text[range.C()];
+            let (comment, newline) = match comment.strip_suffix('\r') {
+                Some(comment) => (comment, "\r\n"),
+                None => (comment, "\n"),
+            };
+            if let Some(wrapped) = wrap_comment(comment, &indent(index), newline, options.max_width) {
+                let cr = &newline[..newline.len().checked_sub(1).X()];
+                edits.push(TextEdit::replace(range, &format!("{wrapped}{cr}")));
+            }
+            continue;
+        }
+        if token.kind(db) != TokenKind::Whitespace {
+            continue;
+        }
+        let range = token.text(db).range(db);
+        let whitespace = &text[range.C()];
+        let is_first = index == 0;
+        if Some(index) == last {
+            // Trailing whitespace of the file, kept to one line ending.
+            let new_text = match (is_first, whitespace.find('\n')) {
+                // A file of only whitespace.
+                (true, _) => "",
+                (false, Some(newline)) => line_ending(&whitespace[..=newline]),
+                (false, None) => "\n",
+            };
+            if new_text != whitespace {
+                edits.push(TextEdit::replace(range, new_text));
+            }
+            continue;
+        }
+        let indent = indent(index.checked_add(1).X());
+        if is_first {
+            // Leading whitespace of the file.
+            if indent != whitespace {
+                edits.push(TextEdit::replace(range, &indent));
+            }
+            continue;
+        }
+        if !whitespace.contains('\n') {
+            continue;
+        }
+
+        // Edit each line separately, for readable diffs.
+        // The first line ends the line before, the others ending
+        // in a newline are blank lines.
+        let mut line_start = range.start;
+        for (line_number, line) in whitespace.split_inclusive('\n').enumerate() {
+            let line_range = line_start..line_start.checked_add(line.len()).X();
+            line_start = line_range.end;
+            if line.ends_with('\n') && line_number > options.max_blank_lines {
+                edits.push(TextEdit::delete(line_range));
+            } else if line.ends_with('\n') {
+                // Trailing whitespace before the line ending.
+                let end = line_range.end.checked_sub(line_ending(line).len()).X();
+                if line_range.start < end {
+                    edits.push(TextEdit::delete(line_range.start..end));
+                }
+            } else if line != indent {
+                // Indentation of the next line.
+                edits.push(TextEdit::replace(line_range, &indent));
+            }
+        }
+        if whitespace.ends_with('\n') && !indent.is_empty() {
+            edits.push(TextEdit::insert(range.end, &indent));
+        }
+    }
+
+    // An error token at the end, like an unterminated comment,
+    // would take in an added newline.
+    let ends_open = tokens.last()
+        .is_some_and(|token| matches!(token.kind(db), TokenKind::Whitespace | TokenKind::Error));
+    if !text.is_empty() && !ends_open {
+        edits.push(TextEdit::insert(text.len(), "\n"));
+    }
+
+    edits
+}
+
+/// Edits reindenting the lines affected by typing `typed_char`,
+/// for an editor to apply as the user types.
+///
+/// `source` already contains the typed character,
+/// and `offset` is just after it.
+///
+/// - After a close brace, every line of the branch it closes,
+///   from the line after the open to the line of the close.
+/// - After `;`, the line of the `;`.
+/// - After a newline, the new line, even if it is still empty.
+///
+/// Other characters, and characters typed inside comments or strings,
+/// produce no edits.
+/// Only indentation is changed, unlike `format_edits`.
+pub fn on_type_format(
+    db: &dyn crate::Db,
+    source: Source,
+    offset: usize,
+    typed_char: char,
+    options: &FormatOptions,
+) -> Vec<TextEdit> {
+    let bracer = crate::bracer::basic_bracer(db, source);
+    let tokens = bracer.chunk(db).tokens(db);
+    let text = source.text(db).as_str();
+    let ranges: Vec<_> = tokens.iter().map(|token| token.text(db).range(db)).collect();
+    let depths = BraceDepths::new(db, bracer);
+    let Some(typed_start) = offset.checked_sub(typed_char.len_utf8()) else {
+        return vec![];
+    };
+    // The token that is just the typed character.
+    let typed_token = ranges.iter().position(|range| *range == (typed_start..offset));
+
+    let line_start = |offset: usize| text[..offset].rfind('\n').map_or(0, |newline| newline.checked_add(1).X());
+    let (lines, include_blank) = match typed_char {
+        '\n' => (offset..=offset, true),
+        ';' => {
+            let Some(index) = typed_token else {
+                return vec![];
+            };
+            if tokens[index].kind(db) != TokenKind::Sigil(Sigil::Semicolon) {
+                return vec![];
+            }
+            let start = line_start(typed_start);
+            (start..=start, false)
+        }
+        ')' | ']' | '}' | '>' => {
+            let Some(close) = typed_token.filter(|index| depths.closes[*index]) else {
+                return vec![];
+            };
+            let outer_depth = depths.line_depth(close);
+            let open = (0..close).rev().find(|index| {
+                depths.depths[*index] == outer_depth
+                    && matches!(tokens[*index].kind(db), TokenKind::Sigil(sigil) if is_open(sigil))
+            });
+            let Some(open) = open else {
+                return vec![];
+            };
+            (ranges[open].end..=line_start(typed_start), false)
+        }
+        _ => return vec![],
+    };
+
+    let mut edits = vec![];
+    let line_starts = iter::once(0)
+        .chain(text.match_indices('\n').map(|(newline, _)| newline.checked_add(1).X()))
+        .filter(|start| lines.contains(start));
+    for start in line_starts {
+        // Skip lines continuing a comment or string.
+        let containing = ranges.partition_point(|range| range.end <= start);
+        if containing < tokens.len()
+            && ranges[containing].start < start
+            && tokens[containing].kind(db) != TokenKind::Whitespace
+        {
+            continue;
+        }
+        let indent_len = text[start..].len()
+            .checked_sub(text[start..].trim_start_matches([' ', '\t']).len()).X();
+        let indent_end = start.checked_add(indent_len).X();
+        let first = ranges.partition_point(|range| range.start < indent_end);
+        let is_blank = text[indent_end..].starts_with(['\n', '\r']) || indent_end == text.len();
+        let depth = match is_blank {
+            true if !include_blank => continue,
+            true => depths.depths[first],
+            false => depths.line_depth(first),
+        };
+        let indent = " ".repeat(depth.checked_mul(options.indent_width).X());
+        if text[start..indent_end] != indent {
+            edits.push(TextEdit::replace(start..indent_end, &indent));
+        }
+    }
+    edits
+}
+
+/// The brace depth at each token, as the bracer sees the braces:
+/// stray closes don't close anything,
+/// and closes the bracer inserted do.
+struct BraceDepths {
+    /// The depth before each token, and at the end.
+    depths: Vec<usize>,
+    /// Whether each token closes a brace.
+    closes: Vec<bool>,
+}
+
+impl BraceDepths {
+    fn new<'db>(db: &'db dyn crate::Db, bracer: Bracer<'db>) -> BraceDepths {
+        let tokens = bracer.chunk(db).tokens(db);
+        let removed_closes: Vec<usize> = bracer.removed_closes(db).iter()
+            .map(|(index, _)| *index)
+            .collect();
+        let mut inserted_closes: BTreeMap<usize, usize> = BTreeMap::new();
+        for (index, _) in bracer.inserted_closes(db) {
+            let count = inserted_closes.entry(*index).or_default();
+            *count = count.checked_add(1).X();
+        }
+
+        let mut depths = Vec::with_capacity(tokens.len().checked_add(1).X());
+        let mut closes = Vec::with_capacity(tokens.len());
+        let mut depth = 0_usize;
+        for (index, token) in tokens.iter().enumerate() {
+            let inserted = inserted_closes.get(&index).copied().unwrap_or(0);
+            depth = depth.checked_sub(inserted).X();
+            depths.push(depth);
+            let (opens, closes_brace) = match token.kind(db) {
+                TokenKind::Sigil(sigil) => (
+                    is_open(sigil),
+                    sigil.is_close_sigil() && !removed_closes.contains(&index),
+                ),
+                _ => (false, false),
+            };
+            if opens {
+                depth = depth.checked_add(1).X();
+            } else if closes_brace {
+                depth = depth.checked_sub(1).X();
+            }
+            closes.push(closes_brace);
+        }
+        depths.push(depth);
+        BraceDepths { depths, closes }
+    }
+
+    /// The depth of a line starting with the token at `index`.
+    /// A line starting with a close is at the depth outside it.
+    fn line_depth(&self, index: usize) -> usize {
+        match self.closes[index] {
+            true => self.depths[index].checked_sub(1).X(),
+            false => self.depths[index],
+        }
+    }
+}
+
+/// `comment` wrapped to `max_width` as consecutive line comments,
+/// if it is a line comment longer than that.
+///
+/// Each line keeps the comment's prefix, e.g. `//` or `///`,
+/// and continuation lines get `indent`.
+/// Words are never split, so a word too long for a line gets a line to itself.
+fn wrap_comment(comment: &str, indent: &str, newline: &str, max_width: usize) -> Option<String> {
+    if !comment.starts_with("//") {
+        return None;
+    }
+    let width = |s: &str| s.chars().count();
+    if width(indent).checked_add(width(comment)).X() <= max_width {
+        return None;
+    }
+    let markers = comment.len().checked_sub(comment.trim_start_matches(['/', '!']).len()).X();
+    let prefix = format!("{} ", &comment[..markers]);
+    let mut words = comment[markers..].split_whitespace();
+    let first_word = words.next()?;
+
+    let mut wrapped = format!("{prefix}{first_word}");
+    let mut line_width = width(indent).checked_add(width(&wrapped)).X();
+    for word in words {
+        let with_word = line_width.checked_add(1).X().checked_add(width(word)).X();
+        if with_word <= max_width {
+            wrapped.push(' ');
+            line_width = with_word;
+        } else {
+            wrapped.push_str(newline);
+            wrapped.push_str(indent);
+            wrapped.push_str(&prefix);
+            line_width = width(indent).checked_add(width(&prefix)).X();
+            line_width = line_width.checked_add(width(word)).X();
+        }
+        wrapped.push_str(word);
+    }
+    (wrapped != comment).then_some(wrapped)
+}
+
+/// The line ending of `line`, `\r\n` or `\n`.
+fn line_ending(line: &str) -> &'static str {
+    match line.ends_with("\r\n") {
+        true => "\r\n",
+        false => "\n",
+    }
+}
+
+fn is_open(sigil: Sigil) -> bool {
+    matches!(sigil, Sigil::ParenOpen | Sigil::BraceOpen | Sigil::BracketOpen | Sigil::AngleOpen)
+}
+
+#[test]
+fn test_format_source() {
+    fn format(s: &str) -> String {
+        let ref db = crate::Database::default();
+        let options = FormatOptions { indent_width: 2, ..FormatOptions::default() };
+        format_source(db, Source::new(db, S(s)), &options)
+    }
+
+    assert_eq!(format(""), "");
+    assert_eq!(format(" \n\n"), "");
+    assert_eq!(format("a"), "a\n");
+    assert_eq!(format("\n\n  a  \n\n\n"), "a\n");
+    assert_eq!(format("a  b \nc"), "a  b\nc\n");
+    assert_eq!(format("f {\na\n    b(\nc\n)\n}\n"), "f {\n  a\n  b(\n    c\n  )\n}\n");
+    assert_eq!(format("{\n\n\na\n}\n"), "{\n\n\n  a\n}\n");
+    assert_eq!(format("a\r\n  b\r\n"), "a\r\nb\r\n");
+    // Comments and strings spanning lines are kept as written.
+    assert_eq!(format("{\n/* a\n      b */\n\"c\n  d\"\n}"), "{\n  /* a\n      b */\n  \"c\n  d\"\n}\n");
+    // Stray closes don't dedent, unclosed braces indent to the end.
+    assert_eq!(format("a\n)\nb\n"), "a\n)\nb\n");
+    assert_eq!(format("(\na\n"), "(\n  a\n");
+    assert_eq!(format("{ ( }\na\n"), "{ ( }\na\n");
+    assert_eq!(format("a\n/* open"), "a\n/* open");
+}
+
+#[test]
+fn test_max_blank_lines() {
+    fn format(s: &str, max_blank_lines: usize) -> String {
+        let ref db = crate::Database::default();
+        let options = FormatOptions { indent_width: 2, max_blank_lines, ..FormatOptions::default() };
+        format_source(db, Source::new(db, S(s)), &options)
+    }
+
+    let text = "a\n\n\n\nb\n{\n  \n\t\n\n  c\n}\n";
+    assert_eq!(format(text, 0), "a\nb\n{\n  c\n}\n");
+    assert_eq!(format(text, 1), "a\n\nb\n{\n\n  c\n}\n");
+    assert_eq!(format(text, 2), "a\n\n\nb\n{\n\n\n  c\n}\n");
+    assert_eq!(format(text, 3), "a\n\n\n\nb\n{\n\n\n\n  c\n}\n");
+    // Comments break a run.
+    assert_eq!(format("a\n\n// b\n\nc\n", 1), "a\n\n// b\n\nc\n");
+    assert_eq!(format("a\r\n\r\n\r\nb\r\n", 1), "a\r\n\r\nb\r\n");
+    let formatted = format(text, 1);
+    assert_eq!(format(&formatted, 1), formatted);
+}
+
+#[test]
+fn test_wrap_comments() {
+    fn format(s: &str) -> String {
+        let ref db = crate::Database::default();
+        let options = FormatOptions { indent_width: 2, max_width: 20, wrap_comments: true, ..FormatOptions::default() };
+        format_source(db, Source::new(db, S(s)), &options)
+    }
+
+    assert_eq!(format("// one two three four five\n"), "// one two three\n// four five\n");
+    assert_eq!(format("{\n/// one two  three four\n}\n"), "{\n  /// one two three\n  /// four\n}\n");
+    assert_eq!(format("//! one\r\n"), "//! one\r\n");
+    assert_eq!(format("//! one two three four\r\n"), "//! one two three\r\n//! four\r\n");
+    // Long words get their own line.
+    assert_eq!(format("// a https://example.com/long\n"), "// a\n// https://example.com/long\n");
+    // Trailing and block comments are left alone.
+    assert_eq!(format("a // one two three four\n"), "a // one two three four\n");
+    assert_eq!(format("/* one two three four five */\n"), "/* one two three four five */\n");
+    assert_eq!(format("/* /* one two */ three four five */\n"), "/* /* one two */ three four five */\n");
+
+    let wrapped = format("{\n// one two three four five six seven\n}\n");
+    assert_eq!(format(&wrapped), wrapped);
+}
+
+#[test]
+fn test_on_type_format() {
+    fn typed(before: &str, typed_char: char, after: &str) -> String {
+        let ref db = crate::Database::default();
+        let text = format!("{before}{typed_char}{after}");
+        let source = Source::new(db, text.C());
+        let offset = before.len().checked_add(typed_char.len_utf8()).X();
+        let options = FormatOptions { indent_width: 2, ..FormatOptions::default() };
+        apply_edits(&text, &on_type_format(db, source, offset, typed_char, &options)).X()
+    }
+
+    // Closing a brace reindents its branch, but not the lines around it.
+    assert_eq!(typed("x
+    f {
+a
+      b(
+c)
+", '}', "
+y"), "x
+    f {
+  a
+  b(
+    c)
+}
+y");
+    assert_eq!(typed("{ a", '}', ""), "{ a}");
+    // A newline indents the new line.
+    assert_eq!(typed("f {", '\n', ""), "f {\n  ");
+    assert_eq!(typed("f {", '\n', "}"), "f {\n}");
+    assert_eq!(typed("f {\n  a", '\n', "        b\n}"), "f {\n  a\n  b\n}");
+    // A separator reindents its line.
+    assert_eq!(typed("{\n      a", ';', " b\n}"), "{\n  a; b\n}");
+
+    // Nothing happens in strings and comments, or for other characters.
+    assert_eq!(typed("{\n      \"a", '}', "\""), "{\n      \"a}\"");
+    assert_eq!(typed("{\n      // a", ';', ""), "{\n      // a;");
+    assert_eq!(typed("{\n      a", 'b', ""), "{\n      ab");
+    // A stray close closes nothing.
+    assert_eq!(typed("\n    a", ')', ""), "\n    a)");
+}
@@ -0,0 +1,198 @@
+//! Syntax grammars for editors, generated from the lexer's tables.
+//!
+//! Editors without a language server highlight with a grammar of their own.
+//! Generating it from the `Sigil` table keeps its tokens
+//! the same as the lexer's, sigil for sigil.
+//!
+//! The grammars approximate the source map and bracer:
+//!
+//! - Comments are `//` to the end of the line and `/* */`,
+//!   which only the TextMate grammar nests.
+//! - Strings are `"` with backslash escapes.
+//! - Embedded regions are fenced by ```` ``` ````,
+//!   the usual `embedded_fence` of projects enabling them.
+//! - Words are runs of letters, digits and `_`.
+//! - Brace sigils nest as groups, and other sigils are single tokens.
+//! - Keywords and scalar type names are those of `data` literals.
+//!
+//! Every sigil set is included, since an editor can't tell
+//! which sets a project enables.
+
+use rmx::prelude::*;
+use rmx::serde_json::{self, json};
+
+use crate::lexer::Sigil;
+
+/// The name of the language in generated grammars.
+pub const LANGUAGE_NAME: &str = "bct";
+
+/// The brace groups, with their open sigil and a rule name.
+const GROUPS: [(Sigil, &str); 4] = [
+    (Sigil::ParenOpen, "parens"),
+    (Sigil::BracketOpen, "brackets"),
+    (Sigil::BraceOpen, "braces"),
+    (Sigil::AngleOpen, "angles"),
+];
+
+/// The sigils that are not braces, longest first.
+fn plain_sigils() -> Vec<Sigil> {
+    let braces: Vec<Sigil> = GROUPS.iter()
+        .flat_map(|(open, _)| [*open, open.close_sigil()])
+        .collect();
+    enum_iterator::all::<Sigil>()
+        .filter(|sigil| !braces.contains(sigil))
+        .collect()
+}
+
+/// A tree-sitter `grammar.js`.
+pub fn tree_sitter_grammar() -> String {
+    // JSON strings are JavaScript strings.
+    let quote = |text: &str| json!(text).to_string();
+
+    let mut grammar = String::new();
+    grammar.push_str("// Generated by `bcts export-grammar --tree-sitter`.\n\n");
+    grammar.push_str("module.exports = grammar({\n");
+    grammar.push_str(&format!("  name: {},\n\n", quote(LANGUAGE_NAME)));
+    grammar.push_str("  extras: $ => [/\\s/, $.comment],\n\n");
+    grammar.push_str("  rules: {\n");
+    grammar.push_str("    source_file: $ => repeat($._item),\n\n");
+
+    let items = GROUPS.iter().map(|(_, name)| format!("$.{name}"))
+        .chain(["$.word", "$.string", "$.embedded", "$.sigil"].map(S))
+        .join(", ");
+    grammar.push_str(&format!("    _item: $ => choice({items}),\n\n"));
+
+    for (open, name) in GROUPS {
+        grammar.push_str(&format!(
+            "    {name}: $ => seq({}, repeat($._item), {}),\n",
+            quote(open.as_str()),
+            quote(open.close_sigil().as_str()),
+        ));
+    }
+    grammar.push('\n');
+
+    grammar.push_str("    sigil: $ => choice(\n");
+    for sigil in plain_sigils() {
+        grammar.push_str(&format!("      {},\n", quote(sigil.as_str())));
+    }
+    grammar.push_str("    ),\n\n");
+
+    grammar.push_str("    word: $ => /[\\p{L}\\p{N}_]+/,\n\n");
+    grammar.push_str("    string: $ => /\"([^\"\\\\]|\\\\(.|\\n))*\"/,\n\n");
+    grammar.push_str("    embedded: $ => token(seq('```', /([^`]|`[^`]|``[^`])*/, '```')),\n\n");
+    grammar.push_str("    comment: $ => token(choice(\n");
+    grammar.push_str("      seq('//', /[^\\n]*/),\n");
+    grammar.push_str("      seq('/*', /[^*]*\\*+([^/*][^*]*\\*+)*/, '/'),\n");
+    grammar.push_str("    )),\n");
+    grammar.push_str("  },\n");
+    grammar.push_str("});\n");
+    grammar
+}
+
+/// A TextMate `.tmLanguage.json`, as used by VS Code.
+pub fn textmate_grammar() -> String {
+    let scope = |name: &str| format!("{name}.{LANGUAGE_NAME}");
+    let words = |words: &[&str]| format!("\\b({})\\b", words.join("|"));
+    let sigils = |sigils: Vec<Sigil>| sigils.iter().map(|sigil| regex_escape(sigil.as_str())).join("|");
+    let braces = GROUPS.iter().flat_map(|(open, _)| [*open, open.close_sigil()]).collect();
+
+    let grammar = json!({
+        "name": LANGUAGE_NAME,
+        "scopeName": format!("source.{LANGUAGE_NAME}"),
+        "fileTypes": [LANGUAGE_NAME],
+        "patterns": [
+            { "include": "#comments" },
+            { "include": "#embedded" },
+            { "include": "#strings" },
+            { "include": "#keywords" },
+            { "include": "#types" },
+            // Sigils like `<=` before the braces they start with.
+            { "include": "#sigils" },
+            { "include": "#braces" },
+        ],
+        "repository": {
+            "comments": {
+                "patterns": [
+                    { "name": scope("comment.line.double-slash"), "match": "//.*$" },
+                    { "include": "#block-comment" },
+                ],
+            },
+            "block-comment": {
+                "name": scope("comment.block"),
+                "begin": "/\\*",
+                "end": "\\*/",
+                "patterns": [{ "include": "#block-comment" }],
+            },
+            "embedded": {
+                "name": scope("markup.raw.block"),
+                "begin": "```",
+                "end": "```",
+            },
+            "strings": {
+                "name": scope("string.quoted.double"),
+                "begin": "\"",
+                "end": "\"",
+                "patterns": [{ "name": scope("constant.character.escape"), "match": "\\\\." }],
+            },
+            "keywords": {
+                "name": scope("keyword.other"),
+                "match": words(crate::data::KEYWORDS),
+            },
+            "types": {
+                "name": scope("support.type"),
+                "match": words(crate::data::SCALAR_TYPES),
+            },
+            "braces": {
+                "name": scope("punctuation.section.group"),
+                "match": sigils(braces),
+            },
+            "sigils": {
+                "name": scope("keyword.operator"),
+                "match": sigils(plain_sigils()),
+            },
+        },
+    });
+    let mut grammar = serde_json::to_string_pretty(&grammar).X();
+    grammar.push('\n');
+    grammar
+}
+
+/// `text` with the characters special in regexes escaped.
+fn regex_escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for ch in text.chars() {
+        if "\\^$.|?*+()[]{}".contains(ch) {
+            escaped.push('\\');
+        }
+        escaped.push(ch);
+    }
+    escaped
+}
+
+#[test]
+fn test_tree_sitter_grammar() {
+    let grammar = tree_sitter_grammar();
+    assert!(grammar.contains("  name: \"bct\",\n"));
+    assert!(grammar.contains("    angles: $ => seq(\"<\", repeat($._item), \">\"),\n"));
+    for sigil in plain_sigils() {
+        let line = format!("      {},\n", json!(sigil.as_str()));
+        assert!(grammar.contains(&line), "{line}");
+    }
+    assert!(grammar.contains("      \"\\\\\",\n"));
+    assert!(!grammar.contains("      \"(\",\n"));
+    assert_eq!(grammar.matches('(').count(), grammar.matches(')').count());
+}
+
+#[test]
+fn test_textmate_grammar() {
+    let grammar: serde_json::Value = serde_json::from_str(&textmate_grammar()).X();
+    assert_eq!(grammar["scopeName"], "source.bct");
+    let repository = &grammar["repository"];
+    assert_eq!(repository["keywords"]["match"], "\\b(true|false|set|map|enum)\\b");
+    assert_eq!(repository["braces"]["match"], "\\(|\\)|\\[|\\]|\\{|\\}|<|>");
+    let sigils = repository["sigils"]["match"].as_str().X();
+    assert!(sigils.starts_with("\\+\\?=|-\\?=|"));
+    assert!(sigils.contains("|\\\\|`"));
+    assert_eq!(regex_escape("+|="), "\\+\\|=");
+    assert_eq!(sigils, plain_sigils().iter().map(|sigil| regex_escape(sigil.as_str())).join("|"));
+}
@@ -0,0 +1,488 @@
+//! Compact binary export of a resolved package world.
+//!
+//! The same graph as `graph_export`, plus the import diagnostics,
+//! for distributed build systems that read worlds too large for JSON.
+//! Enabled by the `binary-export` feature.
+//!
+//! # Format
+//!
+//! Integers are unsigned LEB128 varints.
+//! A string is a varint index into the string table,
+//! so names repeated across modules and imports are stored once.
+//! A module is a varint index into the modules of all packages,
+//! in package order, as in `GraphRecord::modules`.
+//!
+//! - Magic bytes `BCTG`, then the format version, currently `1`.
+//!   Incremented on any incompatible change to the layout below.
+//! - The string table: a count, then each string
+//!   as a byte length and UTF-8 bytes.
+//! - Packages, ordered by import space then name: a count, then each
+//!   as import space, name, and a count of module names, ordered by name.
+//! - Imports, ordered by importing module then import demand:
+//!   a count, then each as the importing module, the import demand
+//!   as import space, package and module strings, and a resolution:
+//!   - `0`, the imported module;
+//!   - `1`, unresolved;
+//!   - `2`, ambiguous, a count of the candidate modules, then each;
+//!   - `3`, forbidden, the matching module, and `0` for private.
+//! - Cycle groups: a count, then each as a count of sorted modules.
+//! - Diagnostics from `diagnostics::import_diagnostics`,
+//!   ordered by module: a count, then each as the module,
+//!   the code string, e.g. `E0101`, `0` for no span
+//!   or `1` and the span's start and end, and the message.
+//!
+//! Nothing follows the diagnostics.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+
+use crate::diagnostics::{Code, Diagnostic, import_diagnostics};
+use crate::package2::PackageModule;
+use crate::package_resolve2::{
+    ForbiddenReason,
+    ImportDemand,
+    ImportDemandMap,
+    PackageWorldMap,
+    ResolvedPackageModule,
+    package_world_graph,
+};
+
+/// The version of the format described in the module docs.
+pub const BINARY_VERSION: u32 = 1;
+
+const MAGIC: &[u8; 4] = b"BCTG";
+
+/// A resolved package world, detached from the database.
+///
+/// Modules are referred to by their index in `GraphRecord::modules`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct GraphRecord {
+    pub packages: Vec<RecordedPackage>,
+    pub imports: Vec<RecordedImport>,
+    pub cycle_groups: Vec<Vec<usize>>,
+    pub diagnostics: Vec<(usize, Diagnostic)>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedPackage {
+    pub import_space: String,
+    pub name: String,
+    pub modules: Vec<String>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct RecordedImport {
+    pub from: usize,
+    pub import_demand: ImportDemand,
+    pub resolution: RecordedResolution,
+}
+
+/// A `ResolvedPackageModule` with modules as indices.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum RecordedResolution {
+    Resolved(usize),
+    Unresolved,
+    Ambiguous(Vec<usize>),
+    Forbidden(usize, ForbiddenReason),
+}
+
+impl GraphRecord {
+    /// Record the resolved package world and its import diagnostics.
+    ///
+    /// Like `graph_export`, cyclic worlds are recorded,
+    /// with the cycles in `cycle_groups`.
+    pub fn new<'db>(
+        db: &'db dyn crate::Db,
+        package_world_map: PackageWorldMap<'db>,
+        import_demand_map: ImportDemandMap<'db>,
+    ) -> GraphRecord {
+        let graph = package_world_graph(db, package_world_map, import_demand_map);
+
+        let mut module_indices: BTreeMap<PackageModule, usize> = BTreeMap::new();
+        let mut packages = vec![];
+        for (import_space, package_map) in package_world_map.map(db) {
+            for (package_name, package) in package_map {
+                let mut modules = vec![];
+                for (module_name, package_module) in package.modules(db) {
+                    module_indices.insert(*package_module, module_indices.len());
+                    modules.push(module_name.C());
+                }
+                packages.push(RecordedPackage {
+                    import_space: import_space.C(),
+                    name: package_name.C(),
+                    modules,
+                });
+            }
+        }
+        let index = |module: &PackageModule| module_indices[module];
+
+        let mut imports = vec![];
+        for (package_module, module_imports) in graph.map(db) {
+            for (import_demand, resolved) in module_imports {
+                let resolution = match resolved {
+                    ResolvedPackageModule::Resolved(to) => RecordedResolution::Resolved(index(to)),
+                    ResolvedPackageModule::Unresolved => RecordedResolution::Unresolved,
+                    ResolvedPackageModule::Ambiguous(candidates, _) => {
+                        RecordedResolution::Ambiguous(candidates.iter().map(index).sorted().collect())
+                    }
+                    ResolvedPackageModule::Forbidden(to, reason, _) => {
+                        RecordedResolution::Forbidden(index(to), *reason)
+                    }
+                };
+                imports.push(RecordedImport {
+                    from: index(package_module),
+                    import_demand: import_demand.C(),
+                    resolution,
+                });
+            }
+        }
+        imports.sort_by(|a, b| (a.from, &a.import_demand).cmp(&(b.from, &b.import_demand)));
+
+        let mut cycle_groups: Vec<Vec<usize>> = graph.cycle_groups(db).iter()
+            .map(|group| group.iter().map(index).sorted().collect())
+            .collect();
+        cycle_groups.sort();
+
+        let mut diagnostics: Vec<(usize, Diagnostic)> = import_diagnostics(db, package_world_map, graph).into_iter()
+            .map(|(module, diagnostic)| (index(&module), diagnostic))
+            .collect();
+        diagnostics.sort_by_key(|(module, _)| *module);
+
+        GraphRecord { packages, imports, cycle_groups, diagnostics }
+    }
+
+    /// Every module as import space, package name and module name,
+    /// in index order.
+    pub fn modules(&self) -> impl Iterator<Item = (&str, &str, &str)> {
+        self.packages.iter().flat_map(|package| {
+            package.modules.iter().map(|module| {
+                (package.import_space.as_str(), package.name.as_str(), module.as_str())
+            })
+        })
+    }
+
+    /// Encode in the format described in the module docs.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut strings = Strings::default();
+        let mut body = vec![];
+
+        write_usize(&mut body, self.packages.len());
+        for package in &self.packages {
+            strings.write(&mut body, &package.import_space);
+            strings.write(&mut body, &package.name);
+            write_usize(&mut body, package.modules.len());
+            for module in &package.modules {
+                strings.write(&mut body, module);
+            }
+        }
+
+        write_usize(&mut body, self.imports.len());
+        for import in &self.imports {
+            write_usize(&mut body, import.from);
+            let (import_space, package_alias, module_alias) = &import.import_demand;
+            strings.write(&mut body, import_space);
+            strings.write(&mut body, package_alias);
+            strings.write(&mut body, module_alias);
+            match &import.resolution {
+                RecordedResolution::Resolved(to) => {
+                    body.push(0);
+                    write_usize(&mut body, *to);
+                }
+                RecordedResolution::Unresolved => {
+                    body.push(1);
+                }
+                RecordedResolution::Ambiguous(candidates) => {
+                    body.push(2);
+                    write_modules(&mut body, candidates);
+                }
+                RecordedResolution::Forbidden(to, reason) => {
+                    body.push(3);
+                    write_usize(&mut body, *to);
+                    body.push(match reason {
+                        ForbiddenReason::Private => 0,
+                    });
+                }
+            }
+        }
+
+        write_usize(&mut body, self.cycle_groups.len());
+        for group in &self.cycle_groups {
+            write_modules(&mut body, group);
+        }
+
+        write_usize(&mut body, self.diagnostics.len());
+        for (module, diagnostic) in &self.diagnostics {
+            write_usize(&mut body, *module);
+            strings.write(&mut body, diagnostic.code.as_str());
+            match &diagnostic.span {
+                Some(span) => {
+                    body.push(1);
+                    write_usize(&mut body, span.start);
+                    write_usize(&mut body, span.end);
+                }
+                None => body.push(0),
+            }
+            strings.write(&mut body, &diagnostic.message);
+        }
+
+        let mut buf = MAGIC.to_vec();
+        write_usize(&mut buf, BINARY_VERSION as usize);
+        write_usize(&mut buf, strings.list.len());
+        for string in &strings.list {
+            write_usize(&mut buf, string.len());
+            buf.extend_from_slice(string.as_bytes());
+        }
+        buf.extend(body);
+        buf
+    }
+
+    /// Decode from the format described in the module docs.
+    ///
+    /// Fails on other versions of the format and on malformed input,
+    /// including module indices out of range.
+    pub fn decode(bytes: &[u8]) -> AnyResult<GraphRecord> {
+        let Some(bytes) = bytes.strip_prefix(MAGIC) else {
+            bail!("not a binary graph export");
+        };
+        let mut reader = Reader { bytes, strings: vec![], module_count: 0 };
+        let version = reader.usize()?;
+        if version != BINARY_VERSION as usize {
+            bail!("binary graph export version {version} is not supported, expected {BINARY_VERSION}");
+        }
+
+        for _ in 0..reader.usize()? {
+            let len = reader.usize()?;
+            let string = S(rmx::std::str::from_utf8(reader.take(len)?)
+                .context("string is not UTF-8")?);
+            reader.strings.push(string);
+        }
+
+        let mut packages = vec![];
+        for _ in 0..reader.usize()? {
+            let import_space = reader.string()?;
+            let name = reader.string()?;
+            let modules = (0..reader.usize()?).map(|_| reader.string()).collect::<AnyResult<_>>()?;
+            packages.push(RecordedPackage { import_space, name, modules });
+        }
+        let module_count = packages.iter().map(|package| package.modules.len()).sum();
+        reader.module_count = module_count;
+
+        let mut imports = vec![];
+        for _ in 0..reader.usize()? {
+            let from = reader.module()?;
+            let import_demand = (reader.string()?, reader.string()?, reader.string()?);
+            let resolution = match reader.byte()? {
+                0 => RecordedResolution::Resolved(reader.module()?),
+                1 => RecordedResolution::Unresolved,
+                2 => RecordedResolution::Ambiguous(reader.modules()?),
+                3 => {
+                    let to = reader.module()?;
+                    let reason = match reader.byte()? {
+                        0 => ForbiddenReason::Private,
+                        reason => bail!("unknown forbidden reason {reason}"),
+                    };
+                    RecordedResolution::Forbidden(to, reason)
+                }
+                tag => bail!("unknown import resolution {tag}"),
+            };
+            imports.push(RecordedImport { from, import_demand, resolution });
+        }
+
+        let cycle_groups = (0..reader.usize()?).map(|_| reader.modules()).collect::<AnyResult<_>>()?;
+
+        let mut diagnostics = vec![];
+        for _ in 0..reader.usize()? {
+            let module = reader.module()?;
+            let code = reader.string()?;
+            let code = Code::parse(&code)
+                .ok_or_else(|| anyhow!("unknown diagnostic code `{code}`"))?;
+            let span = match reader.byte()? {
+                0 => None,
+                1 => Some(reader.usize()?..reader.usize()?),
+                tag => bail!("unknown span tag {tag}"),
+            };
+            let message = reader.string()?;
+            diagnostics.push((module, Diagnostic { code, span, message }));
+        }
+
+        if !reader.bytes.is_empty() {
+            bail!("{} bytes after the end of the binary graph export", reader.bytes.len());
+        }
+        Ok(GraphRecord { packages, imports, cycle_groups, diagnostics })
+    }
+}
+
+/// Export the resolved package world and its import diagnostics
+/// in the format described in the module docs.
+pub fn export_package_world_binary<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+) -> Vec<u8> {
+    GraphRecord::new(db, package_world_map, import_demand_map).encode()
+}
+
+/// The string table, in order of first use.
+#[derive(Default)]
+struct Strings {
+    list: Vec<String>,
+    indices: BTreeMap<String, usize>,
+}
+
+impl Strings {
+    fn write(&mut self, buf: &mut Vec<u8>, string: &str) {
+        let index = match self.indices.get(string) {
+            Some(index) => *index,
+            None => {
+                let index = self.list.len();
+                self.list.push(S(string));
+                self.indices.insert(S(string), index);
+                index
+            }
+        };
+        write_usize(buf, index);
+    }
+}
+
+fn write_usize(buf: &mut Vec<u8>, mut value: usize) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte);
+            return;
+        }
+        buf.push(byte | 0x80);
+    }
+}
+
+fn write_modules(buf: &mut Vec<u8>, modules: &[usize]) {
+    write_usize(buf, modules.len());
+    for module in modules {
+        write_usize(buf, *module);
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    strings: Vec<String>,
+    module_count: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, len: usize) -> AnyResult<&[u8]> {
+        if len > self.bytes.len() {
+            bail!("binary graph export is truncated");
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn byte(&mut self) -> AnyResult<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn usize(&mut self) -> AnyResult<usize> {
+        let mut value: usize = 0;
+        for shift in (0..usize::BITS).step_by(7) {
+            let byte = self.byte()?;
+            let bits = usize::from(byte & 0x7f);
+            if bits.checked_shl(shift).map(|shifted| shifted >> shift) != Some(bits) {
+                bail!("integer in binary graph export overflows");
+            }
+            value |= bits << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("integer in binary graph export overflows");
+    }
+
+    fn string(&mut self) -> AnyResult<String> {
+        let index = self.usize()?;
+        self.strings.get(index).cloned()
+            .ok_or_else(|| anyhow!("string index {index} is out of range"))
+    }
+
+    fn module(&mut self) -> AnyResult<usize> {
+        let module = self.usize()?;
+        if module >= self.module_count {
+            bail!("module index {module} is out of range");
+        }
+        Ok(module)
+    }
+
+    fn modules(&mut self) -> AnyResult<Vec<usize>> {
+        (0..self.usize()?).map(|_| self.module()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_fixture::{WorldFixture, fixture_world};
+
+    const FIXTURE: &str = "\
+//@ package local/app
+//@ module main
+//@ import sys core a
+//@ import sys missing x
+//@ import sys core hidden
+
+//@ package sys/core
+//@ module a
+//@ import pkg b
+//@ module b
+//@ import pkg a
+//@ module hidden
+//@ private
+";
+
+    #[salsa::tracked]
+    fn record<'db>(db: &'db dyn crate::Db, fixture: WorldFixture) -> GraphRecord {
+        let world = fixture_world(db, fixture).X();
+        GraphRecord::new(db, world.package_world_map(db), world.import_demand_map(db))
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let ref db = crate::Database::default();
+        let fixture = WorldFixture::new(db, S(FIXTURE));
+        let record = record(db, fixture);
+
+        let modules: Vec<_> = record.modules().collect();
+        assert_eq!(modules, vec![
+            ("local", "app", "main"),
+            ("sys", "core", "a"),
+            ("sys", "core", "b"),
+            ("sys", "core", "hidden"),
+        ]);
+        assert_eq!(record.imports.len(), 5);
+        assert_eq!(record.cycle_groups, vec![vec![1, 2]]);
+        assert!(record.imports.iter().any(|import| {
+            import.resolution == RecordedResolution::Forbidden(3, ForbiddenReason::Private)
+        }));
+        assert!(!record.diagnostics.is_empty());
+
+        let bytes = record.encode();
+        assert!(bytes.starts_with(b"BCTG\x01"));
+        assert_eq!(GraphRecord::decode(&bytes).X(), record);
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let ref db = crate::Database::default();
+        let fixture = WorldFixture::new(db, S(FIXTURE));
+        let bytes = record(db, fixture).encode();
+
+        let error = |bytes: &[u8]| GraphRecord::decode(bytes).unwrap_err().to_string();
+        assert_eq!(error(b"{}"), "not a binary graph export");
+        assert_eq!(
+            error(b"BCTG\x02"),
+            "binary graph export version 2 is not supported, expected 1",
+        );
+        assert_eq!(error(&bytes[..bytes.len() - 1]), "binary graph export is truncated");
+        assert_eq!(error(&[&bytes[..], b"\0"].concat()), "1 bytes after the end of the binary graph export");
+    }
+}
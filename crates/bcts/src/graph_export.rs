@@ -0,0 +1,458 @@
+//! JSON export of a resolved package world.
+//!
+//! This is the integration point for external build systems
+//! that want to consume the module graph without linking bcts.
+//!
+//! # Schema
+//!
+//! The top-level object has these fields:
+//!
+//! - `schema_version`: integer, currently `2`.
+//!   Incremented on any incompatible change to the layout below.
+//! - `packages`: array of packages, ordered by import space then name.
+//!   - `import_space`: string, e.g. `"sys"`.
+//!   - `name`: string, the package name.
+//!   - `modules`: array of module ids belonging to the package.
+//! - `modules`: array of modules, in the same order as `packages`.
+//!   - `id`: string, `"<import_space>/<package>/<module>"`.
+//!   - `import_space`, `package`, `name`: strings.
+//! - `edges`: array of resolved imports.
+//!   - `from`: module id of the importing module.
+//!   - `to`: module id of the imported module.
+//!   - `import`: the import demand, see below.
+//! - `unresolved_imports`: array of imports that did not resolve.
+//!   - `from`: module id of the importing module.
+//!   - `import`: the import demand.
+//! - `ambiguous_imports`: array of imports matching more than one module.
+//!   - `from`: module id of the importing module.
+//!   - `import`: the import demand.
+//!   - `candidates`: array of matching module ids, sorted.
+//!   - `span`: the import's span in the importing module, or `null`.
+//! - `forbidden_imports`: array of imports of modules the importer may not import.
+//!   - `from`: module id of the importing module.
+//!   - `to`: module id of the matching module.
+//!   - `import`: the import demand.
+//!   - `reason`: string, currently always `"private"`.
+//!   - `span`: the import's span in the importing module, or `null`.
+//! - `cycle_groups`: array of arrays of module ids.
+//!   Each inner array is a set of modules that import each other,
+//!   sorted by id.
+//!
+//! An import demand is an object with `import_space`,
+//! `package` and `module` string fields,
+//! exactly as they were demanded by the importing module.
+//! A span is an object with `start` and `end` byte offset fields,
+//! of the path of the import's `import module` line,
+//! and is `null` for prelude imports and imports without such a line.
+//!
+//! Edges and failed imports are ordered by the id
+//! of the importing module, then by import demand.
+//! Strings are compared bytewise, so the output depends only
+//! on names, not on platform or the order inputs were created in.
+
+use rmx::prelude::*;
+use rmx::serde_json::{self, json, Value};
+use rmx::std::collections::BTreeMap;
+
+use crate::package2::PackageModule;
+use crate::package_resolve2::{
+    ImportDemand,
+    ImportDemandMap,
+    PackageWorldMap,
+    ResolvedPackageModule,
+    ForbiddenReason,
+    ImportSpan,
+    package_world_graph,
+};
+
+/// The version of the export schema described in the module docs.
+pub const SCHEMA_VERSION: u32 = 2;
+
+/// Export the resolved package world as a JSON value.
+///
+/// Cyclic worlds are still exported, with the cycles
+/// listed in `cycle_groups`.
+pub fn export_package_world<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+) -> Value {
+    let graph = package_world_graph(db, package_world_map, import_demand_map);
+
+    let mut module_ids: BTreeMap<PackageModule, String> = BTreeMap::new();
+    let mut packages: Vec<Value> = vec![];
+    let mut modules: Vec<Value> = vec![];
+
+    for (import_space, package_map) in package_world_map.map(db) {
+        for (package_name, package) in package_map {
+            let mut package_modules = vec![];
+            for (module_name, package_module) in package.modules(db) {
+                let id = format!("{import_space}/{package_name}/{module_name}");
+                modules.push(json!({
+                    "id": id,
+                    "import_space": import_space,
+                    "package": package_name,
+                    "name": module_name,
+                }));
+                package_modules.push(Value::from(id.C()));
+                module_ids.insert(*package_module, id);
+            }
+            packages.push(json!({
+                "import_space": import_space,
+                "name": package_name,
+                "modules": package_modules,
+            }));
+        }
+    }
+
+    let mut edges: Vec<(&str, &ImportDemand, &str)> = vec![];
+    let mut unresolved: Vec<(&str, &ImportDemand)> = vec![];
+    let mut ambiguous: Vec<(&str, &ImportDemand, Vec<&str>, Option<ImportSpan>)> = vec![];
+    let mut forbidden: Vec<(&str, &ImportDemand, &str, ForbiddenReason, Option<ImportSpan>)> = vec![];
+
+    for (package_module, imports) in graph.map(db) {
+        let from = module_ids[package_module].as_str();
+        for (import_demand, resolved) in imports {
+            match resolved {
+                ResolvedPackageModule::Resolved(to) => {
+                    edges.push((from, import_demand, module_ids[to].as_str()));
+                }
+                ResolvedPackageModule::Unresolved => {
+                    unresolved.push((from, import_demand));
+                }
+                ResolvedPackageModule::Ambiguous(candidates, span) => {
+                    let candidates = candidates.iter()
+                        .map(|module| module_ids[module].as_str())
+                        .sorted().collect();
+                    ambiguous.push((from, import_demand, candidates, *span));
+                }
+                ResolvedPackageModule::Forbidden(to, reason, span) => {
+                    forbidden.push((from, import_demand, module_ids[to].as_str(), *reason, *span));
+                }
+            }
+        }
+    }
+
+    edges.sort();
+    unresolved.sort();
+    ambiguous.sort();
+    forbidden.sort();
+
+    let edges: Vec<Value> = edges.into_iter().map(|(from, import_demand, to)| {
+        json!({
+            "from": from,
+            "to": to,
+            "import": import_demand_json(import_demand),
+        })
+    }).collect();
+    let unresolved: Vec<Value> = unresolved.into_iter().map(|(from, import_demand)| {
+        json!({
+            "from": from,
+            "import": import_demand_json(import_demand),
+        })
+    }).collect();
+    let ambiguous: Vec<Value> = ambiguous.into_iter().map(|(from, import_demand, candidates, span)| {
+        json!({
+            "from": from,
+            "import": import_demand_json(import_demand),
+            "candidates": candidates,
+            "span": span_json(span),
+        })
+    }).collect();
+    let forbidden: Vec<Value> = forbidden.into_iter().map(|(from, import_demand, to, reason, span)| {
+        json!({
+            "from": from,
+            "to": to,
+            "import": import_demand_json(import_demand),
+            "reason": match reason {
+                ForbiddenReason::Private => "private",
+            },
+            "span": span_json(span),
+        })
+    }).collect();
+    let mut cycle_groups: Vec<Vec<&str>> = graph.cycle_groups(db).iter().map(|group| {
+        group.iter().map(|module| module_ids[module].as_str()).sorted().collect()
+    }).collect();
+    cycle_groups.sort();
+
+    json!({
+        "schema_version": SCHEMA_VERSION,
+        "packages": packages,
+        "modules": modules,
+        "edges": edges,
+        "unresolved_imports": unresolved,
+        "ambiguous_imports": ambiguous,
+        "forbidden_imports": forbidden,
+        "cycle_groups": cycle_groups,
+    })
+}
+
+/// Export the resolved package world as pretty-printed JSON.
+pub fn export_package_world_string<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+) -> String {
+    let value = export_package_world(db, package_world_map, import_demand_map);
+    serde_json::to_string_pretty(&value).expect("json")
+}
+
+fn import_demand_json(import_demand: &ImportDemand) -> Value {
+    let (import_space, package_alias, module_alias) = import_demand;
+    json!({
+        "import_space": import_space,
+        "package": package_alias,
+        "module": module_alias,
+    })
+}
+
+fn span_json(span: Option<ImportSpan>) -> Value {
+    match span {
+        Some(span) => json!({ "start": span.start, "end": span.end }),
+        None => Value::Null,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::Source;
+    use crate::package2::Package;
+
+    #[salsa::tracked]
+    struct TestInput<'db> {
+        package_world_map: PackageWorldMap<'db>,
+        import_demand_map: ImportDemandMap<'db>,
+    }
+
+    #[salsa::tracked]
+    fn test_input<'db>(
+        db: &'db dyn crate::Db,
+    ) -> TestInput<'db> {
+        build_test_input(db, false)
+    }
+
+    #[salsa::tracked]
+    fn test_input_reversed<'db>(
+        db: &'db dyn crate::Db,
+    ) -> TestInput<'db> {
+        build_test_input(db, true)
+    }
+
+    #[rustfmt::skip]
+    fn build_test_input<'db>(
+        db: &'db dyn crate::Db,
+        reverse_creation_order: bool,
+    ) -> TestInput<'db> {
+        let mut names = vec!["main", "a", "b"];
+        if reverse_creation_order {
+            names.reverse();
+        }
+        let modules: BTreeMap<&str, PackageModule> = names.into_iter().map(|name| {
+            (name, PackageModule::new(db, S(name), Source::new(db, S(""))))
+        }).collect();
+        let module = |name: &str| modules[name];
+        let package_world_map = PackageWorldMap::new(
+            db,
+            BTreeMap::from([
+                (S("local"), BTreeMap::from([
+                    (S("app"), Package::new(
+                        db,
+                        S("app"),
+                        BTreeMap::from([
+                            (S("main"), module("main")),
+                        ]),
+                    )),
+                ])),
+                (S("sys"), BTreeMap::from([
+                    (S("core"), Package::new(
+                        db,
+                        S("core"),
+                        BTreeMap::from([
+                            (S("a"), module("a")),
+                            (S("b"), module("b")),
+                        ]),
+                    )),
+                ])),
+            ]),
+        );
+        let app = package_world_map.map(db)["local"]["app"];
+        let core = package_world_map.map(db)["sys"]["core"];
+        let import_demand_map = ImportDemandMap::new(
+            db,
+            BTreeMap::from([
+                (app.modules(db)["main"], vec![
+                    (S("sys"), S("core"), S("a")),
+                    (S("sys"), S("missing"), S("x")),
+                ]),
+                (core.modules(db)["a"], vec![
+                    (S("pkg"), S("core"), S("b")),
+                ]),
+                (core.modules(db)["b"], vec![
+                    (S("pkg"), S("core"), S("a")),
+                ]),
+            ]),
+            vec![],
+            vec![],
+        );
+        TestInput::new(db, package_world_map, import_demand_map)
+    }
+
+    const GOLDEN: &str = r#"{
+  "ambiguous_imports": [],
+  "cycle_groups": [
+    [
+      "sys/core/a",
+      "sys/core/b"
+    ]
+  ],
+  "edges": [
+    {
+      "from": "local/app/main",
+      "import": {
+        "import_space": "sys",
+        "module": "a",
+        "package": "core"
+      },
+      "to": "sys/core/a"
+    },
+    {
+      "from": "sys/core/a",
+      "import": {
+        "import_space": "pkg",
+        "module": "b",
+        "package": "core"
+      },
+      "to": "sys/core/b"
+    },
+    {
+      "from": "sys/core/b",
+      "import": {
+        "import_space": "pkg",
+        "module": "a",
+        "package": "core"
+      },
+      "to": "sys/core/a"
+    }
+  ],
+  "forbidden_imports": [],
+  "modules": [
+    {
+      "id": "local/app/main",
+      "import_space": "local",
+      "name": "main",
+      "package": "app"
+    },
+    {
+      "id": "sys/core/a",
+      "import_space": "sys",
+      "name": "a",
+      "package": "core"
+    },
+    {
+      "id": "sys/core/b",
+      "import_space": "sys",
+      "name": "b",
+      "package": "core"
+    }
+  ],
+  "packages": [
+    {
+      "import_space": "local",
+      "modules": [
+        "local/app/main"
+      ],
+      "name": "app"
+    },
+    {
+      "import_space": "sys",
+      "modules": [
+        "sys/core/a",
+        "sys/core/b"
+      ],
+      "name": "core"
+    }
+  ],
+  "schema_version": 2,
+  "unresolved_imports": [
+    {
+      "from": "local/app/main",
+      "import": {
+        "import_space": "sys",
+        "module": "x",
+        "package": "missing"
+      }
+    }
+  ]
+}"#;
+
+    #[test]
+    fn test_export_golden() {
+        let ref db = crate::Database::default();
+        let input = test_input(db);
+        let actual = export_package_world_string(
+            db,
+            input.package_world_map(db),
+            input.import_demand_map(db),
+        );
+        assert_eq!(actual, GOLDEN);
+    }
+
+    #[test]
+    fn test_export_schema_version() {
+        let ref db = crate::Database::default();
+        let input = test_input(db);
+        let value = export_package_world(
+            db,
+            input.package_world_map(db),
+            input.import_demand_map(db),
+        );
+        assert_eq!(value["schema_version"], SCHEMA_VERSION);
+    }
+
+    #[salsa::tracked]
+    fn test_input_forbidden<'db>(
+        db: &'db dyn crate::Db,
+    ) -> TestInput<'db> {
+        let builder = crate::world_builder::WorldBuilder::new()
+            .package("sys/core")
+            .module("detail", "")
+            .visibility(crate::package2::Visibility::Package)
+            .package("sys/main")
+            .module("main", "import module sys/core/detail")
+            .import("sys", "core", "detail");
+        let package_world_map = builder.build_map(db);
+        let import_demand_map = builder.build_import_demands(db, package_world_map);
+        TestInput::new(db, package_world_map, import_demand_map)
+    }
+
+    #[test]
+    fn test_export_forbidden() {
+        let ref db = crate::Database::default();
+        let input = test_input_forbidden(db);
+        let value = export_package_world(
+            db,
+            input.package_world_map(db),
+            input.import_demand_map(db),
+        );
+        assert_eq!(value["forbidden_imports"], json!([{
+            "from": "sys/main/main",
+            "to": "sys/core/detail",
+            "import": { "import_space": "sys", "package": "core", "module": "detail" },
+            "reason": "private",
+            "span": { "start": 14, "end": 29 },
+        }]));
+    }
+
+    #[test]
+    fn test_export_independent_of_creation_order() {
+        let ref db = crate::Database::default();
+        let input = test_input_reversed(db);
+        let actual = export_package_world_string(
+            db,
+            input.package_world_map(db),
+            input.import_demand_map(db),
+        );
+        assert_eq!(actual, GOLDEN);
+    }
+}
@@ -0,0 +1,214 @@
+//! Architectural metrics of a resolved import graph.
+//!
+//! Fan-in and fan-out count modules, not imports,
+//! so a module importing another twice counts it once.
+//! Like cycle detection, they only see imports that resolved;
+//! imports that resolved to no module are counted separately.
+//! Ambiguous and forbidden imports are in neither.
+
+use rmx::prelude::*;
+
+use rmx::std::collections::BTreeMap;
+use rmx::std::fmt::Write as _;
+
+use crate::package2::PackageModule;
+use crate::package_resolve2::{
+    PackageWorldMap,
+    PackageWorldModuleGraph,
+    ResolvedPackageModule,
+};
+
+#[derive(Clone, Debug, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct ModuleMetrics {
+    /// Modules that import this one.
+    pub fan_in: usize,
+    /// Modules this one imports.
+    pub fan_out: usize,
+    /// Imports of this module that resolved to no module.
+    pub unresolved: usize,
+}
+
+#[derive(Clone, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct GraphMetrics {
+    pub modules: BTreeMap<PackageModule, ModuleMetrics>,
+    /// The longest chain of modules each importing the next,
+    /// importer first.
+    ///
+    /// Imports between modules of one cycle group are left out,
+    /// so the chain is finite; cycles are reported by `cycle_groups`.
+    /// Of chains of equal length the one starting
+    /// with the first module in `PackageModule` order is taken.
+    pub longest_chain: Vec<PackageModule>,
+    /// Imports that resolved to no module, in the whole graph.
+    pub unresolved_edges: usize,
+}
+
+#[salsa::tracked(returns(ref))]
+pub fn graph_metrics<'db>(
+    db: &'db dyn crate::Db,
+    graph: PackageWorldModuleGraph<'db>,
+) -> GraphMetrics {
+    let edges = graph.edges(db);
+
+    let mut modules: BTreeMap<PackageModule, ModuleMetrics> = graph.map(db).iter()
+        .map(|(module, imports)| {
+            let unresolved = imports.iter()
+                .filter(|(_, resolved)| *resolved == ResolvedPackageModule::Unresolved)
+                .count();
+            (*module, ModuleMetrics { fan_out: edges[module].len(), unresolved, .. default() })
+        })
+        .collect();
+    for imported in edges.values().flatten() {
+        let metrics = modules.entry(*imported).or_default();
+        metrics.fan_in = metrics.fan_in.checked_add(1).X();
+    }
+    let unresolved_edges = modules.values().map(|metrics| metrics.unresolved).sum();
+
+    // Drop imports within cycle groups, leaving a DAG.
+    let mut group_of: BTreeMap<PackageModule, usize> = BTreeMap::new();
+    for (index, group) in graph.cycle_groups(db).iter().enumerate() {
+        group_of.extend(group.iter().map(|module| (*module, index)));
+    }
+    let (edges, group_of) = (&edges, &group_of);
+    let dag_imports = move |module: PackageModule| {
+        let group = group_of.get(&module).copied();
+        edges.get(&module).into_iter().flatten().copied()
+            .filter(move |imported| *imported != module)
+            .filter(move |imported| group.is_none() || group_of.get(imported).copied() != group)
+    };
+
+    // The length of the longest chain from each module,
+    // and the module after it on that chain.
+    let mut chains: BTreeMap<PackageModule, (usize, Option<PackageModule>)> = BTreeMap::new();
+    for &start in edges.keys() {
+        let mut stack = vec![start];
+        while let Some(&module) = stack.last() {
+            if chains.contains_key(&module) {
+                stack.pop();
+                continue;
+            }
+            let pending: Vec<_> = dag_imports(module)
+                .filter(|imported| !chains.contains_key(imported))
+                .collect();
+            if !pending.is_empty() {
+                stack.extend(pending);
+                continue;
+            }
+            let mut chain = (1, None);
+            for imported in dag_imports(module) {
+                let length = chains[&imported].0.checked_add(1).X();
+                if length > chain.0 {
+                    chain = (length, Some(imported));
+                }
+            }
+            chains.insert(module, chain);
+            stack.pop();
+        }
+    }
+
+    let mut longest_chain = vec![];
+    let mut next = chains.iter()
+        .rev()
+        .max_by_key(|(_, (length, _))| *length)
+        .map(|(module, _)| *module);
+    while let Some(module) = next {
+        longest_chain.push(module);
+        next = chains[&module].1;
+    }
+
+    GraphMetrics { modules, longest_chain, unresolved_edges }
+}
+
+/// The metrics as a table of modules, in `flatten_iter` order,
+/// followed by the longest chain and the unresolved import count.
+///
+/// Modules are named `<import_space>/<package>/<module>`,
+/// as in `graph_export`.
+pub fn graph_metrics_table<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    metrics: &GraphMetrics,
+) -> String {
+    let mut ids: BTreeMap<PackageModule, String> = BTreeMap::new();
+    let mut rows = vec![];
+    for record in package_world_map.flatten_iter(db) {
+        let module = record.package_module;
+        let id = format!("{}/{}/{}", record.import_space, record.package_name, module.name(db));
+        let module_metrics = metrics.modules.get(&module).cloned().unwrap_or_default();
+        rows.push((id.C(), module_metrics));
+        ids.insert(module, id);
+    }
+
+    let width = rows.iter().map(|(id, _)| id.len()).chain(["module".len()]).max().X();
+    let mut buf = String::new();
+    writeln!(buf, "{:<width$}  {:>6}  {:>7}  {:>10}", "module", "fan-in", "fan-out", "unresolved").X();
+    for (id, module_metrics) in rows {
+        let ModuleMetrics { fan_in, fan_out, unresolved } = module_metrics;
+        writeln!(buf, "{id:<width$}  {fan_in:>6}  {fan_out:>7}  {unresolved:>10}").X();
+    }
+    let chain = metrics.longest_chain.iter().map(|module| ids[module].as_str()).join(" -> ");
+    writeln!(buf).X();
+    writeln!(buf, "longest chain: {} module(s): {chain}", metrics.longest_chain.len()).X();
+    writeln!(buf, "unresolved imports: {}", metrics.unresolved_edges).X();
+    buf
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_fixture::{WorldFixture, fixture_world};
+
+    const FIXTURE: &str = "\
+//@ package local/app
+//@ module main
+//@ import sys core a
+//@ import sys core c
+//@ import sys missing x
+
+//@ package sys/core
+//@ module a
+//@ import pkg b
+//@ module b
+//@ import pkg a
+//@ import pkg c
+//@ module c
+//@ import pkg d
+//@ module d
+//@ import pkg gone
+";
+
+    #[salsa::tracked]
+    fn metrics_table<'db>(db: &'db dyn crate::Db, fixture: WorldFixture) -> (GraphMetrics, String) {
+        let world = fixture_world(db, fixture).X();
+        let graph = crate::package_resolve2::package_world_graph(
+            db,
+            world.package_world_map(db),
+            world.import_demand_map(db),
+        );
+        let metrics = graph_metrics(db, graph);
+        (metrics.C(), graph_metrics_table(db, world.package_world_map(db), metrics))
+    }
+
+    #[test]
+    fn test_graph_metrics() {
+        let ref db = crate::Database::default();
+        let fixture = WorldFixture::new(db, S(FIXTURE));
+        let (metrics, table) = metrics_table(db, fixture);
+
+        assert_eq!(metrics.unresolved_edges, 2);
+        assert_eq!(metrics.longest_chain.len(), 3);
+        assert_eq!(table, "\
+module          fan-in  fan-out  unresolved
+local/app/main       0        2           1
+sys/core/a           2        1           0
+sys/core/b           1        2           0
+sys/core/c           2        1           0
+sys/core/d           1        0           1
+
+longest chain: 3 module(s): local/app/main -> sys/core/c -> sys/core/d
+unresolved imports: 2
+");
+    }
+}
@@ -0,0 +1,202 @@
+//! Which files the package loader skips.
+//!
+//! Any directory below a package path may contain a `.gitignore`
+//! and a `.bctignore` file, whose patterns apply to the paths below it.
+//! Both have the `.gitignore` syntax:
+//!
+//! - Blank lines and lines starting with `#` are skipped.
+//!   A leading `\#` or `\!` is a literal `#` or `!`.
+//! - A pattern is a glob, where `*` and `?` don't match `/`
+//!   and `**` matches any number of directories.
+//! - A pattern ending in `/` only matches directories.
+//! - A pattern with a `/` elsewhere is relative to the ignore file's directory;
+//!   otherwise it matches a name at any depth.
+//! - A pattern starting with `!` re-includes what earlier patterns ignored.
+//!
+//! The last matching pattern wins, with patterns of deeper directories
+//! after those of shallower ones, and `.bctignore` after `.gitignore`,
+//! so `.bctignore` can re-include what git ignores.
+//! As with git, nothing below an ignored directory is loaded,
+//! whatever later patterns say.
+
+use rmx::prelude::*;
+use rmx::glob::{MatchOptions, Pattern};
+use rmx::std::path::{Path, PathBuf};
+
+/// The names of ignore files, in the order they are applied.
+pub const IGNORE_FILES: [&str; 2] = [".gitignore", ".bctignore"];
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// The ignore patterns that apply in a directory.
+#[derive(Clone, Debug, Default)]
+pub struct IgnoreRules {
+    rules: Vec<IgnoreRule>,
+}
+
+#[derive(Clone, Debug)]
+struct IgnoreRule {
+    /// The directory of the ignore file.
+    base: PathBuf,
+    pattern: Pattern,
+    negated: bool,
+    dirs_only: bool,
+    /// Whether the pattern is matched against the path from `base`
+    /// rather than the file name.
+    anchored: bool,
+}
+
+impl IgnoreRules {
+    /// The rules for `dir`: these rules and those of the ignore files in `dir`.
+    pub fn with_dir(&self, dir: &Path) -> AnyResult<IgnoreRules> {
+        let mut rules = self.C();
+        for file_name in IGNORE_FILES {
+            let path = dir.join(file_name);
+            let text = match rmx::std::fs::read_to_string(&path) {
+                Ok(text) => text,
+                Err(e) if e.kind() == rmx::std::io::ErrorKind::NotFound => continue,
+                Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+            };
+            rules.add(dir, &text)
+                .with_context(|| format!("parsing {}", path.display()))?;
+        }
+        Ok(rules)
+    }
+
+    /// The rules for `path`, below `root`: those of the ignore files
+    /// in `root` and every directory between it and `path`.
+    ///
+    /// Returns `None` if `path` or a directory above it is ignored,
+    /// or is not below `root`.
+    /// This is for checking a single path, e.g. one reported by a file watcher,
+    /// with the rules the loader uses while walking the tree.
+    pub fn for_path(root: &Path, path: &Path) -> AnyResult<Option<IgnoreRules>> {
+        let Ok(relative) = path.strip_prefix(root) else {
+            return Ok(None);
+        };
+        let mut rules = IgnoreRules::default().with_dir(root)?;
+        let mut dir = root.to_path_buf();
+        let mut components = relative.components().peekable();
+        while let Some(component) = components.next() {
+            dir.push(component);
+            let is_last = components.peek().is_none();
+            let is_dir = !is_last || dir.is_dir();
+            if rules.is_ignored(&dir, is_dir) {
+                return Ok(None);
+            }
+            if is_dir {
+                rules = rules.with_dir(&dir)?;
+            }
+        }
+        Ok(Some(rules))
+    }
+
+    /// Add the patterns of an ignore file in `base`.
+    pub fn add(&mut self, base: &Path, text: &str) -> AnyResult<()> {
+        for (index, line) in text.lines().enumerate() {
+            let line_number = index.checked_add(1).X();
+            let line = line.trim_end();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (negated, line) = match line.strip_prefix('!') {
+                Some(line) => (true, line),
+                None => (false, line.strip_prefix('\\').unwrap_or(line)),
+            };
+            let (dirs_only, line) = match line.strip_suffix('/') {
+                Some(line) => (true, line),
+                None => (false, line),
+            };
+            let anchored = line.contains('/');
+            let line = line.strip_prefix('/').unwrap_or(line);
+            let pattern = Pattern::new(line)
+                .with_context(|| format!("line {line_number}: invalid pattern `{line}`"))?;
+            self.rules.push(IgnoreRule {
+                base: base.to_path_buf(),
+                pattern,
+                negated,
+                dirs_only,
+                anchored,
+            });
+        }
+        Ok(())
+    }
+
+    /// Whether `path` is ignored by the last pattern matching it.
+    ///
+    /// Only `path` itself is matched, not the directories above it.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        self.rules.iter().rev()
+            .find(|rule| rule.matches(path, is_dir))
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+impl IgnoreRule {
+    fn matches(&self, path: &Path, is_dir: bool) -> bool {
+        if self.dirs_only && !is_dir {
+            return false;
+        }
+        let Ok(relative) = path.strip_prefix(&self.base) else {
+            return false;
+        };
+        let subject = match self.anchored {
+            true => relative.to_str(),
+            false => relative.file_name().and_then(|name| name.to_str()),
+        };
+        subject.is_some_and(|subject| self.pattern.matches_with(subject, MATCH_OPTIONS))
+    }
+}
+
+#[test]
+fn test_ignore_rules() {
+    let root = Path::new("/project");
+    let mut rules = IgnoreRules::default();
+    rules.add(root, "\
+# Build output.
+target/
+*.tmp
+/generated.bct
+docs/**/*.bct
+\\#notes.bct
+").X();
+    assert!(rules.is_ignored(&root.join("target"), true));
+    assert!(!rules.is_ignored(&root.join("target"), false));
+    assert!(rules.is_ignored(&root.join("app/target"), true));
+    assert!(rules.is_ignored(&root.join("app/x.tmp"), false));
+    assert!(rules.is_ignored(&root.join("generated.bct"), false));
+    assert!(!rules.is_ignored(&root.join("app/generated.bct"), false));
+    assert!(rules.is_ignored(&root.join("docs/a.bct"), false));
+    assert!(rules.is_ignored(&root.join("docs/a/b.bct"), false));
+    assert!(!rules.is_ignored(&root.join("app/docs/a.bct"), false));
+    assert!(rules.is_ignored(&root.join("#notes.bct"), false));
+    assert!(!rules.is_ignored(&root.join("main.bct"), false));
+    assert!(!rules.is_ignored(Path::new("/elsewhere/x.tmp"), false));
+
+    // Later patterns win, including those of deeper ignore files.
+    rules.add(&root.join("app"), "!keep.tmp\n").X();
+    assert!(!rules.is_ignored(&root.join("app/keep.tmp"), false));
+    assert!(rules.is_ignored(&root.join("keep.tmp"), false));
+
+    assert!(IgnoreRules::default().add(root, "[\n").is_err());
+}
+
+#[test]
+fn test_ignore_rules_for_path() {
+    let dir = rmx::tempfile::tempdir().X();
+    let root = dir.path();
+    rmx::std::fs::create_dir_all(root.join("app/out")).X();
+    rmx::std::fs::write(root.join(".gitignore"), "out/\n*.bct~\n").X();
+    rmx::std::fs::write(root.join("app/.bctignore"), "skip.bct\n").X();
+
+    let rules = IgnoreRules::for_path(root, &root.join("app/main.bct")).X().X();
+    assert!(rules.is_ignored(&root.join("app/skip.bct"), false));
+    assert!(rules.is_ignored(&root.join("app/main.bct~"), false));
+    assert!(IgnoreRules::for_path(root, &root.join("app/skip.bct")).X().is_none());
+    assert!(IgnoreRules::for_path(root, &root.join("app/out/main.bct")).X().is_none());
+    assert!(IgnoreRules::for_path(root, Path::new("/elsewhere")).X().is_none());
+}
@@ -0,0 +1,112 @@
+//! Recognition of `import module` lines.
+//!
+//! Import demands are not parsed from module text;
+//! they come from the `ImportDemandMap` the world is resolved with.
+//! This shallow analysis, like `key_values`, finds the import lines
+//! a module's text does have, so that diagnostics and fixes
+//! about an import can point at where it is written.
+//! A top-level line is an import line if, ignoring whitespace and comments,
+//! it is the words `import module` followed by a path,
+//! any non-empty run of tokens but not branches,
+//! e.g. `import module sys/core/io` or `import module pkg/util`.
+
+use rmx::prelude::*;
+
+use crate::bracer::TreeToken;
+use crate::package2::PackageModule;
+use crate::package_resolve2::ImportDemand;
+use crate::text::ByteSpan;
+
+/// A recognized import line.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct ImportLine {
+    /// The path as written.
+    pub path: String,
+    /// The bytes of the path in the module's text.
+    pub span: ByteSpan,
+}
+
+/// Find every import line at the top level of a module.
+#[salsa::tracked(returns(ref))]
+pub fn import_lines(
+    db: &dyn crate::Db,
+    package_module: PackageModule,
+) -> Vec<ImportLine> {
+    let bracer = crate::bracer::basic_bracer(db, package_module.text(db));
+    bracer.iter(db).lines()
+        .filter_map(|line| {
+            let tokens = line
+                .filter_map(|tree_token| tree_token.without_space(db))
+                .map(|tree_token| match tree_token {
+                    TreeToken::Token(token) => Some(token),
+                    TreeToken::Branch(..) => None,
+                })
+                .collect::<Option<Vec<_>>>()?;
+            let [import, module, path @ ..] = tokens.as_slice() else {
+                return None;
+            };
+            if import.word_str(db)? != "import" || module.word_str(db)? != "module" {
+                return None;
+            }
+            let start = path.first()?.text(db).range(db).start;
+            let end = path.last()?.text(db).range(db).end;
+            let text = package_module.text(db).text(db);
+            Some(ImportLine { path: S(&text[start..end]), span: start..end })
+        })
+        .collect()
+}
+
+/// The path an import demand is written as in an import line,
+/// e.g. `sys/core/io`, or `pkg/util` in the import spaces without packages.
+pub fn import_demand_path(import_demand: &ImportDemand) -> String {
+    let (import_space, package_alias, module_alias) = import_demand;
+    match package_alias.is_empty() {
+        true => format!("{import_space}/{module_alias}"),
+        false => format!("{import_space}/{package_alias}/{module_alias}"),
+    }
+}
+
+/// The span of the first import line of `package_module`
+/// writing `import_demand`, if any.
+pub fn import_demand_span(
+    db: &dyn crate::Db,
+    package_module: PackageModule,
+    import_demand: &ImportDemand,
+) -> Option<ByteSpan> {
+    let path = import_demand_path(import_demand);
+    import_lines(db, package_module).iter()
+        .find(|line| line.path == path)
+        .map(|line| line.span.C())
+}
+
+#[test]
+fn test_import_lines() {
+    let ref db = crate::Database::default();
+    let lines = |s: &str| {
+        let source = crate::input::Source::new(db, S(s));
+        let module = PackageModule::new(db, S("m"), source);
+        import_lines(db, module).iter()
+            .map(|line| (line.path.C(), line.span.C()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(lines(""), vec![]);
+    assert_eq!(lines("import module sys/core/io"), vec![(S("sys/core/io"), 14..25)]);
+    assert_eq!(
+        lines("// a\nimport module pkg/util // b\nx = 1\n  import  module a/b/c\n"),
+        vec![(S("pkg/util"), 19..27), (S("a/b/c"), 56..61)],
+    );
+
+    // Not import lines.
+    assert_eq!(lines("import module"), vec![]);
+    assert_eq!(lines("import sys/core"), vec![]);
+    assert_eq!(lines("import module (sys/core)"), vec![]);
+    assert_eq!(lines("(import module sys/core)"), vec![]);
+
+    let demand = |import_space: &str, package_alias: &str, module_alias: &str| {
+        (S(import_space), S(package_alias), S(module_alias))
+    };
+    assert_eq!(import_demand_path(&demand("sys", "core", "io")), "sys/core/io");
+    assert_eq!(import_demand_path(&demand("pkg", "", "util")), "pkg/util");
+}
@@ -4,4 +4,10 @@ use rmx::prelude::*;
 pub struct Source {
     #[returns(ref)]
     pub text: String,
+    /// Why the source's file couldn't be read, if it couldn't.
+    ///
+    /// The text of a source with a load error is empty.
+    #[default]
+    #[returns(ref)]
+    pub load_error: Option<String>,
 }
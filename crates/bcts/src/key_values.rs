@@ -0,0 +1,181 @@
+//! Recognition of `key = value` and `key : type / value` lines.
+//!
+//! This is a shallow analysis over the lines of the brace tree,
+//! usable before a real parser exists.
+//! A line is a key/value record if, ignoring whitespace and comments,
+//! it has one of these shapes:
+//!
+//! - `key = value`
+//! - `key : type`
+//! - `key : type / value`
+//!
+//! where `key` is a single word and `type` and `value`
+//! are any non-empty run of tokens and branches.
+//! The type and value are written as in a typed data literal, see `data`,
+//! and only the first `/` at the top level of the line separates them.
+//! Lines with any other shape are ignored.
+
+use rmx::prelude::*;
+
+use crate::bracer::{Bracer, TreeToken};
+use crate::lexer::{Token, TokenKind, Sigil};
+use crate::span::TextSpan;
+
+/// A recognized key/value line.
+#[derive(Clone)]
+pub struct KeyValue<'db> {
+    /// The key word.
+    pub key: Token<'db>,
+    /// The type following `:`, if any.
+    pub ty: Option<TextSpan<'db>>,
+    /// The value following `=`, or `/` after a type, if any.
+    pub value: Option<TextSpan<'db>>,
+    /// The whole record, from key to the end of the type or value.
+    pub span: TextSpan<'db>,
+}
+
+/// Find every key/value line at the top level of `bracer`.
+pub fn key_values<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> Vec<KeyValue<'db>> {
+    bracer.iter(db).lines()
+        .filter_map(|line| line_key_value(db, line))
+        .collect()
+}
+
+/// Recognize a single line as a key/value record.
+pub fn line_key_value<'db>(
+    db: &'db dyn crate::Db,
+    line: impl Iterator<Item = TreeToken<'db>>,
+) -> Option<KeyValue<'db>> {
+    let tokens: Vec<TreeToken<'db>> = line
+        .filter_map(|tree_token| tree_token.without_space(db))
+        .collect();
+
+    let key = match tokens.first()? {
+        TreeToken::Token(token) if token.kind(db) == TokenKind::Word => *token,
+        _ => return None,
+    };
+    let separator = tokens.get(1).and_then(|tree_token| sigil(db, tree_token))?;
+    let rest = &tokens[2..];
+
+    let (ty, value) = match separator {
+        Sigil::Equals => (None, rest),
+        Sigil::Colon => {
+            let slash = rest.iter().position(|tree_token| {
+                sigil(db, tree_token) == Some(Sigil::SlashForward)
+            });
+            match slash {
+                Some(slash) => {
+                    let value = &rest[slash.checked_add(1).X()..];
+                    if value.is_empty() {
+                        return None;
+                    }
+                    (Some(&rest[..slash]), value)
+                }
+                None => (Some(rest), &rest[rest.len()..]),
+            }
+        }
+        _ => return None,
+    };
+
+    let ty = match ty {
+        Some(ty) => Some(span_of(db, ty)?),
+        None => None,
+    };
+    let value = span_of(db, value);
+    if ty.is_none() && value.is_none() {
+        return None;
+    }
+
+    let last = value.as_ref().or(ty.as_ref()).X();
+    let span = key.text(db).text_span(db).merge(last).X();
+
+    Some(KeyValue { key, ty, value, span })
+}
+
+fn sigil<'db>(
+    db: &'db dyn crate::Db,
+    tree_token: &TreeToken<'db>,
+) -> Option<Sigil> {
+    match tree_token {
+        TreeToken::Token(token) => match token.kind(db) {
+            TokenKind::Sigil(sigil) => Some(sigil),
+            _ => None,
+        },
+        TreeToken::Branch(..) => None,
+    }
+}
+
+/// The span covering a run of tree tokens, or `None` if it is empty.
+fn span_of<'db>(
+    db: &'db dyn crate::Db,
+    tree_tokens: &[TreeToken<'db>],
+) -> Option<TextSpan<'db>> {
+    let first = tree_tokens.first()?.text_span(db)?;
+    let last = tree_tokens.last()?.text_span(db)?;
+    first.merge(&last)
+}
+
+#[cfg(test)]
+fn dbgkv(s: &str) -> Vec<(String, Option<String>, Option<String>)> {
+    let ref db = crate::Database::default();
+    let bracer = crate::bracer::bracer_for_str(db, s);
+    let text = |span: &TextSpan<'_>| S(span.as_str(db));
+    key_values(db, bracer).iter().map(|kv| {
+        (
+            S(kv.key.text(db).as_str(db)),
+            kv.ty.as_ref().map(text),
+            kv.value.as_ref().map(text),
+        )
+    }).collect()
+}
+
+#[test]
+fn test_key_values() {
+    let kv = |key: &str, ty: Option<&str>, value: Option<&str>| {
+        (S(key), ty.map(S), value.map(S))
+    };
+
+    assert_eq!(dbgkv(""), vec![]);
+    assert_eq!(dbgkv("x = 1"), vec![kv("x", None, Some("1"))]);
+    assert_eq!(dbgkv("x : u32"), vec![kv("x", Some("u32"), None)]);
+    assert_eq!(
+        dbgkv("x : u32 / 1"),
+        vec![kv("x", Some("u32"), Some("1"))],
+    );
+    assert_eq!(
+        dbgkv("name = \"bob\" // comment\nlist : [u8] / [1, 2]\n"),
+        vec![
+            kv("name", None, Some("\"bob\"")),
+            kv("list", Some("[u8]"), Some("[1, 2]")),
+        ],
+    );
+    assert_eq!(
+        dbgkv("m : map<i64, f32> / map {1 = 2.5}"),
+        vec![kv("m", Some("map<i64, f32>"), Some("map {1 = 2.5}"))],
+    );
+    assert_eq!(
+        dbgkv("x: u32 / 1 / 2"),
+        vec![kv("x", Some("u32"), Some("1 / 2"))],
+    );
+    assert_eq!(
+        dbgkv("f = (a = b)"),
+        vec![kv("f", None, Some("(a = b)"))],
+    );
+    assert_eq!(
+        dbgkv("x = {\n  y = 1\n}"),
+        vec![kv("x", None, Some("{\n  y = 1\n}"))],
+    );
+
+    // Not key/value shapes.
+    assert_eq!(dbgkv("x ="), vec![]);
+    assert_eq!(dbgkv("x :"), vec![]);
+    assert_eq!(dbgkv("x : / 1"), vec![]);
+    assert_eq!(dbgkv("x : u32 /"), vec![]);
+    assert_eq!(dbgkv("x == 1"), vec![]);
+    assert_eq!(dbgkv("a b = 1"), vec![]);
+    assert_eq!(dbgkv("\"x\" = 1"), vec![]);
+    assert_eq!(dbgkv("(x) = 1"), vec![]);
+}
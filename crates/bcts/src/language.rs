@@ -0,0 +1,292 @@
+//! Language versions and feature gates.
+//!
+//! A world is checked against one language version,
+//! the `[language]` section of the project config:
+//!
+//! ```toml
+//! [language]
+//! version = "1.0"
+//! features = ["time-literals"]
+//! ```
+//!
+//! Syntax added after 1.0 is gated behind a `Feature`,
+//! enabled by the version it became stable in or by naming it in `features`.
+//! A module can declare the version it needs with an attribute,
+//! see `module_attrs`:
+//!
+//! ```text
+//! attr language "1.1"
+//! ```
+//!
+//! `language_diagnostics` reports modules needing a newer version
+//! than the world's, and gated syntax whose feature is not enabled.
+//! Without a `[language]` section the world is at `LanguageVersion::LATEST`,
+//! where every feature is enabled.
+
+use rmx::prelude::*;
+
+use rmx::std::collections::BTreeSet;
+use rmx::std::fmt;
+
+use crate::bracer::TreeToken;
+use crate::diagnostics::{Code, Diagnostic};
+use crate::lexer::TokenKind;
+use crate::package2::PackageModule;
+use crate::project_config::ProjectConfig;
+use crate::text::ByteSpan;
+
+/// The attribute declaring the language version a module needs.
+pub const LANGUAGE: &str = "language";
+
+/// A language version, written `major.minor`, e.g. `1.1`.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub struct LanguageVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl LanguageVersion {
+    /// The language before heredocs and time literals.
+    pub const V1_0: LanguageVersion = LanguageVersion { major: 1, minor: 0 };
+    pub const V1_1: LanguageVersion = LanguageVersion { major: 1, minor: 1 };
+    /// The version this crate implements.
+    pub const LATEST: LanguageVersion = LanguageVersion::V1_1;
+
+    pub fn parse(version: &str) -> Option<LanguageVersion> {
+        let (major, minor) = version.split_once('.')?;
+        let number = |s: &str| match s.starts_with(|ch: char| ch.is_ascii_digit()) {
+            true => s.parse().ok(),
+            false => None,
+        };
+        Some(LanguageVersion { major: number(major)?, minor: number(minor)? })
+    }
+}
+
+impl fmt::Display for LanguageVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
+
+/// Syntax that is only accepted from some language version on.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(enum_iterator::Sequence)]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Feature {
+    /// `<<TERMINATOR` strings, see `source_map::parse_heredoc`.
+    Heredocs,
+    /// Datetime and duration literals, see `time_literals`.
+    TimeLiterals,
+}
+
+impl Feature {
+    pub fn all() -> impl Iterator<Item = Feature> {
+        enum_iterator::all::<Feature>()
+    }
+
+    /// The name as written in `features`, e.g. `"time-literals"`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Feature::Heredocs => "heredocs",
+            Feature::TimeLiterals => "time-literals",
+        }
+    }
+
+    pub fn parse(name: &str) -> Option<Feature> {
+        Feature::all().find(|feature| feature.as_str() == name)
+    }
+
+    /// The first version enabling the feature without naming it.
+    pub fn stable_since(&self) -> LanguageVersion {
+        match self {
+            Feature::Heredocs => LanguageVersion::V1_1,
+            Feature::TimeLiterals => LanguageVersion::V1_1,
+        }
+    }
+}
+
+/// The language a world is checked against, the `[language]` section.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, salsa::Update)]
+pub struct LanguageOptions {
+    pub version: LanguageVersion,
+    /// Features enabled ahead of their version.
+    pub features: BTreeSet<Feature>,
+}
+
+impl Default for LanguageOptions {
+    fn default() -> LanguageOptions {
+        LanguageOptions {
+            version: LanguageVersion::LATEST,
+            features: BTreeSet::new(),
+        }
+    }
+}
+
+impl LanguageOptions {
+    pub fn enabled(&self, feature: Feature) -> bool {
+        self.features.contains(&feature) || self.version >= feature.stable_since()
+    }
+}
+
+/// Version and feature gate diagnostics for a module,
+/// checked against the project's language options.
+#[salsa::tracked(returns(ref))]
+pub fn language_diagnostics(
+    db: &dyn crate::Db,
+    project_config: ProjectConfig,
+    package_module: PackageModule,
+) -> Vec<Diagnostic> {
+    let options = project_config.language(db);
+    let mut diagnostics = vec![];
+
+    if let Some((value, span)) = language_attr(db, package_module) {
+        let message = match (value.as_deref().and_then(LanguageVersion::parse), value) {
+            (Some(version), _) if version <= options.version => None,
+            (Some(version), _) => Some(format!(
+                "module requires language version {version}, but the world is at {}",
+                options.version,
+            )),
+            (None, Some(value)) => Some(format!("invalid language version `{value}`")),
+            (None, None) => Some(S("language attribute without a version")),
+        };
+        if let Some(message) = message {
+            diagnostics.push(Diagnostic {
+                code: Code::UnsupportedLanguageVersion,
+                span: Some(span),
+                message,
+            });
+        }
+    }
+
+    let source_map_config = crate::project_config::project_source_map_config(db, project_config);
+    let chunk = crate::source_map::source_map(db, package_module.text(db), source_map_config);
+    let lexer_config = crate::project_config::project_lexer_config(db, project_config);
+    let chunk_lex = crate::lexer::lex_chunk_with_config(db, chunk, lexer_config);
+    for token in chunk_lex.tokens(db) {
+        let feature = match token.kind(db) {
+            TokenKind::String => {
+                match crate::source_map::parse_heredoc(token.text(db).as_str(db)) {
+                    Some(Ok(_)) => Feature::Heredocs,
+                    _ => continue,
+                }
+            }
+            TokenKind::Datetime | TokenKind::Duration => Feature::TimeLiterals,
+            _ => continue,
+        };
+        if !options.enabled(feature) {
+            diagnostics.push(Diagnostic {
+                code: Code::FeatureNotEnabled,
+                span: Some(token.text(db).range(db)),
+                message: format!(
+                    "feature `{}` is not enabled, it is stable since language version {}",
+                    feature.as_str(),
+                    feature.stable_since(),
+                ),
+            });
+        }
+    }
+
+    diagnostics
+}
+
+/// The value of the last `language` attribute of a module,
+/// and the span of its line.
+fn language_attr(
+    db: &dyn crate::Db,
+    package_module: PackageModule,
+) -> Option<(Option<String>, ByteSpan)> {
+    let bracer = crate::bracer::basic_bracer(db, package_module.text(db));
+    bracer.iter(db).lines()
+        .filter_map(|line| {
+            let tokens: Vec<_> = line.filter_map(|tree_token| tree_token.without_space(db)).collect();
+            let (name, value) = crate::module_attrs::line_attr(db, tokens.iter().cloned())?;
+            if name != LANGUAGE {
+                return None;
+            }
+            let span = |tree_token: &TreeToken<'_>| match tree_token {
+                TreeToken::Token(token) => Some(token.text(db).range(db)),
+                TreeToken::Branch(..) => None,
+            };
+            let start = span(tokens.first()?)?.start;
+            let end = span(tokens.last()?)?.end;
+            Some((value, start..end))
+        })
+        .last()
+}
+
+#[test]
+fn test_language_version() {
+    assert_eq!(LanguageVersion::parse("1.0"), Some(LanguageVersion::V1_0));
+    assert_eq!(LanguageVersion::parse("2.13"), Some(LanguageVersion { major: 2, minor: 13 }));
+    assert_eq!(LanguageVersion::parse("1"), None);
+    assert_eq!(LanguageVersion::parse("1.1.0"), None);
+    assert_eq!(LanguageVersion::parse("1.+1"), None);
+    assert_eq!(LanguageVersion::parse("v1.0"), None);
+    assert!(LanguageVersion::V1_0 < LanguageVersion::V1_1);
+    assert!(LanguageVersion { major: 1, minor: 10 } > LanguageVersion { major: 1, minor: 9 });
+    assert_eq!(LanguageVersion::LATEST.to_string(), "1.1");
+
+    assert_eq!(Feature::parse("time-literals"), Some(Feature::TimeLiterals));
+    assert_eq!(Feature::parse("heredoc"), None);
+
+    let mut options = LanguageOptions { version: LanguageVersion::V1_0, .. default() };
+    assert!(!options.enabled(Feature::Heredocs));
+    options.features.insert(Feature::Heredocs);
+    assert!(options.enabled(Feature::Heredocs));
+    assert!(!options.enabled(Feature::TimeLiterals));
+    assert!(LanguageOptions::default().enabled(Feature::TimeLiterals));
+}
+
+#[test]
+fn test_language_diagnostics() {
+    use crate::project_config::ConfigFile;
+
+    let ref db = crate::Database::default();
+    let diagnostics = |toml: &str, text: &str| {
+        let project_config = ProjectConfig::from_file(db, ConfigFile::parse(toml).X());
+        let source = crate::input::Source::new(db, S(text));
+        let module = PackageModule::new(db, S("m"), source);
+        language_diagnostics(db, project_config, module).iter()
+            .map(|diagnostic| (diagnostic.code, diagnostic.span.C().X(), diagnostic.message.C()))
+            .collect::<Vec<_>>()
+    };
+    let text = "attr language \"1.1\"\nat = 2024-01-01T00:00:00Z\ns = <<END\nx\nEND\nt = 15m\n";
+
+    assert_eq!(diagnostics("", text), vec![]);
+    assert_eq!(diagnostics("[language]\nversion = \"1.0\"\n", "x = \"a\"\nattr language \"1.0\"\n"), vec![]);
+    let toml = "[lexer]\ntime_literals = true\n[language]\nversion = \"1.0\"\nfeatures = [\"heredocs\"]\n";
+    assert_eq!(diagnostics(toml, text), vec![
+        (
+            Code::UnsupportedLanguageVersion,
+            0..19,
+            S("module requires language version 1.1, but the world is at 1.0"),
+        ),
+        (
+            Code::FeatureNotEnabled,
+            25..45,
+            S("feature `time-literals` is not enabled, it is stable since language version 1.1"),
+        ),
+        (
+            Code::FeatureNotEnabled,
+            66..69,
+            S("feature `time-literals` is not enabled, it is stable since language version 1.1"),
+        ),
+    ]);
+    // Without time literals lexed there are none to gate.
+    assert_eq!(diagnostics("[language]\nversion = \"1.0\"\n", "t = 15m\n"), vec![]);
+    let heredoc = "s = <<END\nx\nEND\n";
+    assert_eq!(diagnostics("[language]\nversion = \"1.0\"\n", heredoc), vec![]);
+    assert_eq!(diagnostics("[source_map]\nheredocs = true\n[language]\nversion = \"1.0\"\n", heredoc), vec![
+        (
+            Code::FeatureNotEnabled,
+            4..15,
+            S("feature `heredocs` is not enabled, it is stable since language version 1.1"),
+        ),
+    ]);
+    assert_eq!(diagnostics("", "attr language \"one\"\n"), vec![
+        (Code::UnsupportedLanguageVersion, 0..19, S("invalid language version `one`")),
+    ]);
+}
@@ -7,16 +7,22 @@ use rmx::std::collections::BTreeMap;
 
 use crate::input::Source;
 use crate::text::{Text, SubText};
-use crate::chunk::{Chunk, RangeKind};
+use crate::chunk::{Chunk, ChunkKind, RangeKind};
 use crate::source_map::{
     basic_source_map,
 };
+use crate::text::ByteSpan;
+use crate::time_literals;
 
 #[salsa::tracked]
 pub struct ChunkLex<'db> {
     pub chunk: Chunk<'db>,
     #[returns(ref)]
     pub tokens: Vec<Token<'db>>,
+    /// The span of each custom literal its validation rejected,
+    /// with the message it returned. See `LiteralRegistry`.
+    #[returns(ref)]
+    pub literal_errors: Vec<(ByteSpan, String)>,
 }
 
 #[salsa::tracked]
@@ -32,8 +38,21 @@ pub enum TokenKind {
     Word,
     Sigil(Sigil),
     String,
+    /// A string prefixed by `b`, as in `b"\x00"`.
+    ByteString,
+    /// An RFC 3339 datetime, like `2024-01-01T00:00:00Z`.
+    /// See `time_literals`.
+    Datetime,
+    /// A duration, like `15m30s`.
+    Duration,
+    /// A string with a registered prefix, like `re"a+"`.
+    /// See `LiteralRegistry`.
+    Literal(LiteralTag),
     Whitespace,
     Comment,
+    /// A fenced region in another language, not lexed.
+    /// See `source_map::embedded_regions`.
+    Embedded,
     Error,
 }
 
@@ -97,6 +116,13 @@ pub enum Sigil {
     Colon,
     Hash,
     At,
+    Dollar,
+    Tilde,
+    Ampersand,
+    Caret,
+    Percent,
+    Backslash,
+    Backtick,
     ParenOpen,
     ParenClose,
     BraceOpen,
@@ -105,13 +131,267 @@ pub enum Sigil {
     BracketClose,
 }
 
+#[salsa::tracked]
+pub struct Config<'db> {
+    pub error_recovery: ErrorRecovery,
+    /// The optional sigil sets that are lexed as sigils.
+    ///
+    /// Sigils of other sets are split into shorter sigils,
+    /// or become errors if there are none.
+    #[returns(ref)]
+    pub sigil_sets: Vec<SigilSet>,
+    /// The prefixes of custom literals.
+    #[returns(ref)]
+    pub literals: LiteralRegistry,
+    pub whitespace: WhitespaceMode,
+    /// Whether datetimes and durations are lexed as their own tokens,
+    /// see `time_literals`, rather than as words and sigils.
+    pub time_literals: bool,
+}
+
+/// How runs of whitespace are split into tokens.
+#[derive(Copy, Clone, Debug, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum WhitespaceMode {
+    /// All consecutive whitespace is one token.
+    #[default]
+    Joined,
+    /// A whitespace token ends after each newline,
+    /// so `"\n\n  "` is the tokens `"\n"`, `"\n"` and `"  "`,
+    /// and each blank line is a token of its own.
+    SplitLines,
+}
+
+/// Custom literal forms, for embedders to extend the syntax with.
+///
+/// A custom literal is a registered prefix word directly before a string,
+/// like `re"a+"` or `path"lib/a.bct"`,
+/// and is lexed as one `TokenKind::Literal` token with the prefix's tag.
+/// The prefix's validation is called with the text between the quotes,
+/// as written, and the literals it rejects are
+/// `ChunkLex::literal_errors`, reported as `InvalidLiteral`.
+/// An unregistered prefix is a word followed by a string.
+#[derive(Clone, Debug, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct LiteralRegistry {
+    extensions: Vec<LiteralExtension>,
+}
+
+/// A registered custom literal form.
+///
+/// Functions are compared by address, which may differ for one function
+/// and only costs a relex, or be shared by functions that do the same.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+#[allow(unpredictable_function_pointer_comparisons)]
+pub struct LiteralExtension {
+    pub prefix: String,
+    pub tag: LiteralTag,
+    /// Returns a message if the text between the quotes is invalid.
+    pub validate: fn(&str) -> Result<(), String>,
+}
+
+/// Which registered literal form a token is, by order of registration.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub struct LiteralTag(pub usize);
+
+impl LiteralRegistry {
+    /// Register literals written `prefix"..."`, returning their tag.
+    ///
+    /// Panics if `prefix` is not a word, is already registered,
+    /// or is `b`, which is for byte strings.
+    pub fn register(&mut self, prefix: &str, validate: fn(&str) -> Result<(), String>) -> LiteralTag {
+        let is_word = !prefix.is_empty() && prefix.chars().all(|ch| ch.is_alphanumeric() || ch == '_');
+        assert!(is_word && prefix != "b", "invalid literal prefix `{prefix}`");
+        assert!(self.get(prefix).is_none(), "literal prefix `{prefix}` registered twice");
+        let tag = LiteralTag(self.extensions.len());
+        self.extensions.push(LiteralExtension { prefix: S(prefix), tag, validate });
+        tag
+    }
+
+    /// The literal form written with `prefix`.
+    pub fn get(&self, prefix: &str) -> Option<&LiteralExtension> {
+        self.extensions.iter().find(|extension| extension.prefix == prefix)
+    }
+
+    /// The literal form of tokens tagged `tag`.
+    pub fn extension(&self, tag: LiteralTag) -> Option<&LiteralExtension> {
+        self.extensions.get(tag.0)
+    }
+}
+
+/// Optional families of sigils.
+///
+/// Sigils not in any set are always enabled.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(enum_iterator::Sequence)]
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SigilSet {
+    /// `+?`, `-?`, `*?`, `/?` and their `=` forms.
+    QuestionOps,
+    /// `+|`, `-|`, `*|`, `/|` and their `=` forms.
+    BarOps,
+    /// `+!`, `-!`, `*!`, `/!`.
+    ExclamationOps,
+    /// `+%`, `-%`, `*%`, `/%`.
+    PercentOps,
+    /// `$`, `~`, `&`, `^`, `%`, `\` and `` ` ``.
+    Prefix,
+}
+
+impl SigilSet {
+    pub fn all() -> Vec<SigilSet> {
+        enum_iterator::all::<SigilSet>().collect()
+    }
+}
+
+/// Where the lexer ends a run of unrecognized characters.
+///
+/// An error token always contains at least its first character.
+/// It then extends over following characters until one
+/// of the enabled stop conditions is met.
+/// Unrecognized characters never stop an error token,
+/// except by `max_chars`.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct ErrorRecovery {
+    /// End the error token before whitespace.
+    pub stop_at_whitespace: bool,
+    /// End the error token before a word character.
+    pub stop_at_word: bool,
+    /// End the error token before the start of a sigil.
+    pub stop_at_sigil: bool,
+    /// The maximum number of characters in one error token.
+    pub max_chars: Option<usize>,
+}
+
+impl Default for ErrorRecovery {
+    /// Stop at any recognized token, with no length limit.
+    fn default() -> ErrorRecovery {
+        ErrorRecovery {
+            stop_at_whitespace: true,
+            stop_at_word: true,
+            stop_at_sigil: true,
+            max_chars: None,
+        }
+    }
+}
+
+#[salsa::tracked]
+pub fn basic_config<'db>(
+    db: &'db dyn crate::Db,
+) -> Config<'db> {
+    Config::new(
+        db,
+        ErrorRecovery::default(),
+        SigilSet::all(),
+        LiteralRegistry::default(),
+        WhitespaceMode::Joined,
+        false,
+    )
+}
+
+/// `basic_config` with time literals.
+#[cfg(test)]
+#[salsa::tracked]
+pub(crate) fn extended_config<'db>(
+    db: &'db dyn crate::Db,
+) -> Config<'db> {
+    Config::new(
+        db,
+        ErrorRecovery::default(),
+        SigilSet::all(),
+        LiteralRegistry::default(),
+        WhitespaceMode::Joined,
+        true,
+    )
+}
+
 #[salsa::tracked]
 pub fn lex_chunk<'db>(
     db: &'db dyn crate::Db,
     chunk: Chunk<'db>,
 ) -> ChunkLex<'db> {
-    let mut tokens = Vec::new();
+    lex_chunk_with_config(
+        db,
+        chunk,
+        basic_config(db),
+    )
+}
+
+/// `lex_chunk`, checked by `ChunkLex::check`.
+///
+/// The lexer assumes `chunk` meets `Chunk::check`.
+#[salsa::tracked]
+pub fn try_lex_chunk<'db>(
+    db: &'db dyn crate::Db,
+    chunk: Chunk<'db>,
+) -> Result<ChunkLex<'db>, LexInvariantError> {
+    let chunk_lex = lex_chunk(db, chunk);
+    chunk_lex.check(db)?;
+    Ok(chunk_lex)
+}
+
+/// A broken invariant of a `ChunkLex`, found by `ChunkLex::check`.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub enum LexInvariantError {
+    /// A token is of another text than its chunk's.
+    ForeignToken { token: usize },
+    EmptyToken { token: usize },
+    /// The tokens don't cover the chunk's text end to end, in order;
+    /// the first gap or overlap is at `offset`.
+    Gap { offset: usize },
+}
+
+/// Lexer configuration chosen by the kind of each chunk.
+///
+/// Chunks of kinds without an entry use `basic_config`.
+#[salsa::tracked]
+pub struct KindConfig<'db> {
+    #[returns(ref)]
+    pub configs: BTreeMap<ChunkKind, Config<'db>>,
+}
+
+#[salsa::tracked]
+pub fn lex_chunk_by_kind<'db>(
+    db: &'db dyn crate::Db,
+    chunk: Chunk<'db>,
+    kind_config: KindConfig<'db>,
+) -> ChunkLex<'db> {
+    let config = kind_config.configs(db).get(&chunk.kind(db)).copied()
+        .unwrap_or_else(|| basic_config(db));
+    lex_chunk_with_config(
+        db,
+        chunk,
+        config,
+    )
+}
+
+/// Lex `chunk` with `config`.
+///
+/// The tokens are never empty and make up the chunk text, in order,
+/// so an empty chunk has no tokens,
+/// and a chunk of only whitespace has one `Whitespace` token.
+/// This is asserted in debug builds.
+#[salsa::tracked]
+pub fn lex_chunk_with_config<'db>(
+    db: &'db dyn crate::Db,
+    chunk: Chunk<'db>,
+    config: Config<'db>,
+) -> ChunkLex<'db> {
     let chunk_text = chunk.text(db);
+    let _span = tracing::debug_span!(
+        "lex",
+        kind = ?chunk.kind(db),
+        text_len = chunk_text.as_str(db).len(),
+    ).entered();
+    crate::metrics::record(|metrics| metrics.query_executed("lex"));
+    let mut tokens: Vec<Token<'db>> = Vec::new();
+    let mut literal_errors = Vec::new();
 
     for range in chunk.ranges(db) {
         match range {
@@ -123,10 +403,35 @@ pub fn lex_chunk<'db>(
                 ));
             }
             (range, RangeKind::String) => {
+                let text = &chunk_text.as_str(db)[range.C()];
+                let prefix = tokens.last().copied()
+                    .filter(|last| last.kind(db) == TokenKind::Word && last.text(db).range(db).end == range.start)
+                    .filter(|_| text.starts_with('"'))
+                    .and_then(|last| {
+                        let extension = config.literals(db).get(last.text(db).as_str(db))?;
+                        Some((last.text(db).range(db).start, extension))
+                    });
+                if let Some((start, extension)) = prefix {
+                    tokens.pop();
+                    let contents = &text[1..text.len().checked_sub(1).X()];
+                    if let Err(message) = (extension.validate)(contents) {
+                        literal_errors.push((start..range.end, message));
+                    }
+                    tokens.push(Token::new(
+                        db,
+                        chunk_text.sub(db, start..range.end),
+                        TokenKind::Literal(extension.tag),
+                    ));
+                    continue;
+                }
+                let kind = match chunk_text.as_str(db)[range.C()].starts_with('b') {
+                    true => TokenKind::ByteString,
+                    false => TokenKind::String,
+                };
                 tokens.push(Token::new(
                     db,
                     chunk_text.sub(db, range),
-                    TokenKind::String,
+                    kind,
                 ));
             }
             (range, RangeKind::Error) => {
@@ -136,12 +441,23 @@ pub fn lex_chunk<'db>(
                     TokenKind::Error,
                 ));
             }
+            (range, RangeKind::Embedded) => {
+                tokens.push(Token::new(
+                    db,
+                    chunk_text.sub(db, range),
+                    TokenKind::Embedded,
+                ));
+            }
             (range, RangeKind::Unknown) => {
                 let mut tokenizer = Tokenizer {
                     db,
                     chunk,
                     range,
                     chunk_text: chunk_text.C(),
+                    error_recovery: config.error_recovery(db),
+                    sigil_sets: config.sigil_sets(db),
+                    whitespace: config.whitespace(db),
+                    time_literals: config.time_literals(db),
                 };
 
                 tokens.extend(
@@ -151,13 +467,35 @@ pub fn lex_chunk<'db>(
         }
     }
 
-    return ChunkLex::new(db, chunk, tokens);
+    debug_assert!(
+        tokens_cover(db, chunk_text.as_str(db).len(), &tokens),
+        "tokens don't make up the chunk text",
+    );
+    tracing::debug!(tokens = tokens.len(), "lexed");
+    crate::metrics::record(|metrics| metrics.tokens_lexed(tokens.len()));
+    return ChunkLex::new(db, chunk, tokens, literal_errors);
+
+    fn tokens_cover<'db>(db: &'db dyn crate::Db, text_len: usize, tokens: &[Token<'db>]) -> bool {
+        let mut end = 0;
+        for token in tokens {
+            let range = token.text(db).range(db);
+            if range.start != end || range.is_empty() {
+                return false;
+            }
+            end = range.end;
+        }
+        end == text_len
+    }
 
     struct Tokenizer<'db> {
         db: &'db dyn crate::Db,
         chunk: Chunk<'db>,
         chunk_text: Text<'db>,
         range: Range<usize>,
+        error_recovery: ErrorRecovery,
+        sigil_sets: &'db [SigilSet],
+        whitespace: WhitespaceMode,
+        time_literals: bool,
     }
 
     #[derive(Eq, PartialEq, Debug, Copy, Clone)]
@@ -202,6 +540,20 @@ pub fn lex_chunk<'db>(
             let is_word_char = Self::is_word_start;
 
             let start = self.range.start;
+            let text = &self.chunk.text(self.db).as_str(self.db)[self.range.C()];
+            let datetime_len = match self.time_literals {
+                true => time_literals::datetime_len(text),
+                false => None,
+            };
+            if let Some(len) = datetime_len {
+                self.range.start = start.checked_add(len).X();
+                return Token::new(
+                    self.db,
+                    self.chunk_text.sub(self.db, start .. self.range.start),
+                    TokenKind::Datetime,
+                );
+            }
+
             while let Some(ch) = self.peek() {
                 if is_word_char(ch) {
                     self.eat_char(ch);
@@ -210,10 +562,15 @@ pub fn lex_chunk<'db>(
                 }
             }
             assert!(start < self.range.start);
+            let word = &text[..self.range.start.checked_sub(start).X()];
+            let kind = match self.time_literals && time_literals::is_duration(word) {
+                true => TokenKind::Duration,
+                false => TokenKind::Word,
+            };
             Token::new(
                 self.db,
                 self.chunk_text.sub(self.db, start .. self.range.start),
-                TokenKind::Word,
+                kind,
             )
         }
 
@@ -227,7 +584,11 @@ pub fn lex_chunk<'db>(
             let all_sigils = enum_iterator::all::<Sigil>();
             let text = &self.chunk.text(self.db).as_str(self.db)[self.range.C()];
 
-            for sigil in all_sigils {
+            let enabled = |sigil: &Sigil| {
+                sigil.set().is_none_or(|set| self.sigil_sets.contains(&set))
+            };
+
+            for sigil in all_sigils.filter(enabled) {
                 let sigil_str = sigil.as_str();
                 if text.starts_with(sigil_str) {
                     let range_start = self.range.start;
@@ -241,34 +602,31 @@ pub fn lex_chunk<'db>(
                 }
             }
 
-            self.eat_error_from(self.peek().X())
+            // Every sigil start char is itself a sigil,
+            // so this is only reached if that sigil is disabled.
+            self.eat_error()
         }
 
         fn eat_error(&mut self) -> Token<'db> {
-            self.eat_error_from(self.peek().X())
-        }
+            let recovery = self.error_recovery;
+            let start = self.range.start;
 
-        fn eat_error_from(&mut self, start_ch: char) -> Token<'db> {
-            assert_eq!(self.peek_token(), Some(NextToken::Whitespace));
+            self.eat_char(self.peek().X());
+            let mut chars: usize = 1;
 
-            let token_start = Self::token_start(start_ch);
-            let start = self.range.start.checked_sub(1).X();
             while let Some(ch) = self.peek() {
-                let next_token_start = Self::token_start(ch);
-                let recover = match (token_start, next_token_start) {
-                    (NextToken::Whitespace, _) => unreachable!(),
-                    (NextToken::Word, _) => unreachable!(),
-                    (NextToken::Sigil, NextToken::Error) => false,
-                    (NextToken::Sigil, NextToken::Whitespace) => true,
-                    (NextToken::Sigil, NextToken::Word) => true,
-                    (NextToken::Sigil, NextToken::Sigil) => true,
-                    (NextToken::Error, NextToken::Error) => false,
-                    (NextToken::Error, NextToken::Whitespace) => true,
-                    (NextToken::Error, NextToken::Word) => true,
-                    (NextToken::Error, NextToken::Sigil) => true,
+                if recovery.max_chars.is_some_and(|max| chars >= max) {
+                    break;
+                }
+                let recover = match Self::token_start(ch) {
+                    NextToken::Error => false,
+                    NextToken::Whitespace => recovery.stop_at_whitespace,
+                    NextToken::Word => recovery.stop_at_word,
+                    NextToken::Sigil => recovery.stop_at_sigil,
                 };
                 if !recover {
                     self.eat_char(ch);
+                    chars = chars.checked_add(1).X();
                 } else {
                     break;
                 }
@@ -288,6 +646,9 @@ pub fn lex_chunk<'db>(
             while let Some(ch) = self.peek() {
                 if ch.is_whitespace() {
                     self.eat_char(ch);
+                    if ch == '\n' && self.whitespace == WhitespaceMode::SplitLines {
+                        break;
+                    }
                 } else {
                     break;
                 }
@@ -313,6 +674,86 @@ pub fn lex_chunk<'db>(
 }
 
 impl<'db> ChunkLex<'db> {
+    /// Check the invariants the bracer relies on,
+    /// that the tokens are non-empty and cover the chunk's text in order.
+    ///
+    /// The lexer always returns such tokens.
+    pub fn check(&self, db: &'db dyn crate::Db) -> Result<(), LexInvariantError> {
+        let chunk_text = self.chunk(db).text(db);
+        let mut offset = 0;
+        for (index, token) in self.tokens(db).iter().enumerate() {
+            let text = token.text(db);
+            if text.text(db) != chunk_text {
+                return Err(LexInvariantError::ForeignToken { token: index });
+            }
+            let range = text.range(db);
+            if range.start != offset {
+                return Err(LexInvariantError::Gap { offset });
+            }
+            if range.start >= range.end {
+                return Err(LexInvariantError::EmptyToken { token: index });
+            }
+            offset = range.end;
+        }
+        if offset != chunk_text.as_str(db).len() {
+            return Err(LexInvariantError::Gap { offset });
+        }
+        Ok(())
+    }
+
+    /// Whether the token at `index` is directly followed by
+    /// the next token, with no whitespace or comment between.
+    ///
+    /// Whitespace and comment tokens are never glued,
+    /// and neither is the last token.
+    /// This distinguishes `a<b` from `a < b`.
+    pub fn glued_with_next(&self, db: &'db dyn crate::Db, index: usize) -> bool {
+        let tokens = self.tokens(db);
+        let (Some(token), Some(next)) = (tokens.get(index), tokens.get(index.checked_add(1).X())) else {
+            return false;
+        };
+        token.without_space(db).is_some() && next.without_space(db).is_some()
+    }
+
+    /// The index of `token` in this chunk's tokens.
+    pub fn token_index(&self, db: &'db dyn crate::Db, token: Token<'db>) -> Option<usize> {
+        let start = token.text(db).range(db).start;
+        let tokens = self.tokens(db);
+        let index = tokens
+            .binary_search_by_key(&start, |token| token.text(db).range(db).start)
+            .ok()?;
+        (tokens[index] == token).then_some(index)
+    }
+
+    /// The `Word` tokens, with their indices.
+    pub fn words(&self, db: &'db dyn crate::Db) -> impl Iterator<Item = (usize, Token<'db>)> + 'db {
+        self.tokens_at(db, &token_indices(db, *self).words)
+    }
+
+    /// The `Sigil` tokens, with their indices.
+    pub fn sigils(&self, db: &'db dyn crate::Db) -> impl Iterator<Item = (usize, Token<'db>)> + 'db {
+        self.tokens_at(db, &token_indices(db, *self).sigils)
+    }
+
+    /// The `String` and `ByteString` tokens, with their indices.
+    pub fn strings(&self, db: &'db dyn crate::Db) -> impl Iterator<Item = (usize, Token<'db>)> + 'db {
+        self.tokens_at(db, &token_indices(db, *self).strings)
+    }
+
+    /// The `Error` tokens, with their indices.
+    pub fn errors(&self, db: &'db dyn crate::Db) -> impl Iterator<Item = (usize, Token<'db>)> + 'db {
+        self.tokens_at(db, &token_indices(db, *self).errors)
+    }
+
+    fn tokens_at(
+        &self,
+        db: &'db dyn crate::Db,
+        indices: &'db [usize],
+    ) -> impl Iterator<Item = (usize, Token<'db>)> + 'db {
+        let tokens = self.tokens(db);
+        indices.iter().map(move |&index| (index, tokens[index]))
+    }
+
     #[cfg(test)]
     fn debug_str(&self, db: &'db dyn crate::Db) -> String {
         #[allow(unstable_name_collisions)] // intersperse
@@ -322,6 +763,36 @@ impl<'db> ChunkLex<'db> {
     }
 }
 
+/// The indices of a chunk's tokens of the kinds
+/// analyses look for, found in one pass and memoized.
+/// See `ChunkLex::words` and the methods after it.
+#[derive(Clone, Debug, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct TokenIndices {
+    pub words: Vec<usize>,
+    pub sigils: Vec<usize>,
+    pub strings: Vec<usize>,
+    pub errors: Vec<usize>,
+}
+
+#[salsa::tracked(returns(ref))]
+pub fn token_indices<'db>(
+    db: &'db dyn crate::Db,
+    chunk: ChunkLex<'db>,
+) -> TokenIndices {
+    let mut indices = TokenIndices::default();
+    for (index, token) in chunk.tokens(db).iter().enumerate() {
+        match token.kind(db) {
+            TokenKind::Word => indices.words.push(index),
+            TokenKind::Sigil(_) => indices.sigils.push(index),
+            TokenKind::String | TokenKind::ByteString => indices.strings.push(index),
+            TokenKind::Error => indices.errors.push(index),
+            _ => {}
+        }
+    }
+    indices
+}
+
 impl<'db> Token<'db> {
     pub fn without_space(self, db: &'db dyn crate::Db) -> Option<Self> {
         match self.kind(db) {
@@ -334,12 +805,14 @@ impl<'db> Token<'db> {
     #[cfg(test)]
     pub fn debug_str(&self, db: &'db dyn crate::Db) -> &'db str {
         match self.kind(db) {
-            TokenKind::Word | TokenKind::String => {
+            TokenKind::Word | TokenKind::String | TokenKind::ByteString
+                | TokenKind::Datetime | TokenKind::Duration | TokenKind::Literal(_) => {
                 self.text(db).as_str(db)
             }
             TokenKind::Sigil(s) => s.as_str(),
             TokenKind::Whitespace => "ws",
             TokenKind::Comment => "cmt",
+            TokenKind::Embedded => "emb",
             TokenKind::Error => "err",
         }
     }
@@ -357,9 +830,58 @@ impl<'db> Token<'db> {
         }
         Some(self.text(db).as_str(db))
     }
+
+    /// Whether this token is directly followed by the next token in `chunk_lex`.
+    ///
+    /// See `ChunkLex::glued_with_next`.
+    /// Returns false if this token is not from `chunk_lex`.
+    pub fn glued_with_next(&self, db: &'db dyn crate::Db, chunk_lex: ChunkLex<'db>) -> bool {
+        match chunk_lex.token_index(db, *self) {
+            Some(index) => chunk_lex.glued_with_next(db, index),
+            None => false,
+        }
+    }
 }
 
 impl Sigil {
+    /// The optional set this sigil belongs to, if any.
+    pub fn set(&self) -> Option<SigilSet> {
+        match self {
+            Sigil::PlusQuestionEquals
+                | Sigil::MinusQuestionEquals
+                | Sigil::StarQuestionEquals
+                | Sigil::SlashQuestionEquals
+                | Sigil::PlusQuestion
+                | Sigil::MinusQuestion
+                | Sigil::StarQuestion
+                | Sigil::SlashQuestion => Some(SigilSet::QuestionOps),
+            Sigil::PlusBarEquals
+                | Sigil::MinusBarEquals
+                | Sigil::StarBarEquals
+                | Sigil::SlashBarEquals
+                | Sigil::PlusBar
+                | Sigil::MinusBar
+                | Sigil::StarBar
+                | Sigil::SlashBar => Some(SigilSet::BarOps),
+            Sigil::PlusExclamation
+                | Sigil::MinusExclamation
+                | Sigil::StarExclamation
+                | Sigil::SlashExclamation => Some(SigilSet::ExclamationOps),
+            Sigil::PlusPercent
+                | Sigil::MinusPercent
+                | Sigil::StarPercent
+                | Sigil::SlashPercent => Some(SigilSet::PercentOps),
+            Sigil::Dollar
+                | Sigil::Tilde
+                | Sigil::Ampersand
+                | Sigil::Caret
+                | Sigil::Percent
+                | Sigil::Backslash
+                | Sigil::Backtick => Some(SigilSet::Prefix),
+            _ => None,
+        }
+    }
+
     pub fn as_str(&self) -> &'static str {
         match self {
             // Three-character sigils.
@@ -418,6 +940,13 @@ impl Sigil {
             Sigil::Colon => ":",
             Sigil::Hash => "#",
             Sigil::At => "@",
+            Sigil::Dollar => "$",
+            Sigil::Tilde => "~",
+            Sigil::Ampersand => "&",
+            Sigil::Caret => "^",
+            Sigil::Percent => "%",
+            Sigil::Backslash => "\\",
+            Sigil::Backtick => "`",
             Sigil::ParenOpen => "(",
             Sigil::ParenClose => ")",
             Sigil::BraceOpen => "{",
@@ -441,7 +970,7 @@ impl Sigil {
         }
     }
 
-    fn is_close_sigil(&self) -> bool {
+    pub fn is_close_sigil(&self) -> bool {
         matches!(self, Sigil::ParenClose | Sigil::BraceClose | Sigil::BracketClose | Sigil::AngleClose)
     }
 }
@@ -517,6 +1046,20 @@ fn test_lex_chunk() {
         "a # b",
     );
 
+    // Prefix markers.
+    assert_eq!(
+        dbglex("#map@u64!x"),
+        "# map @ u64 ! x",
+    );
+    assert_eq!(
+        dbglex("$a ~b &c ^d %e \\f `g`"),
+        "$ a ws ~ b ws & c ws ^ d ws % e ws \\ f ws ` g `",
+    );
+    assert_eq!(
+        dbglex("a+%b%c"),
+        "a +% b % c",
+    );
+
     // Basic arithmetic operators.
     assert_eq!(
         dbglex("a+b-c*d/e"),
@@ -622,4 +1165,288 @@ fn test_lex_chunk() {
     );
 }
 
+#[test]
+fn test_lex_time_literals() {
+    fn kinds(s: &str) -> Vec<(String, TokenKind)> {
+        let ref db = crate::Database::default();
+        let source = Source::new(db, S(s));
+        let chunk = basic_source_map(db, source);
+        let chunk_lex = lex_chunk_with_config(db, chunk, extended_config(db));
+        chunk_lex.tokens(db).iter()
+            .filter_map(|token| token.without_space(db))
+            .map(|token| (S(token.text(db).as_str(db)), token.kind(db)))
+            .collect()
+    }
+    let datetime = |s: &str| (S(s), TokenKind::Datetime);
+    let duration = |s: &str| (S(s), TokenKind::Duration);
+    let word = |s: &str| (S(s), TokenKind::Word);
+    let sigil = |s: &str, sigil| (S(s), TokenKind::Sigil(sigil));
+
+    assert_eq!(
+        kinds("[2024-01-01T00:00:00Z, 2024-01-01T00:00:00.5-05:00]"),
+        vec![
+            sigil("[", Sigil::BracketOpen),
+            datetime("2024-01-01T00:00:00Z"),
+            sigil(",", Sigil::Comma),
+            datetime("2024-01-01T00:00:00.5-05:00"),
+            sigil("]", Sigil::BracketClose),
+        ],
+    );
+    assert_eq!(kinds("2024-01-01"), vec![datetime("2024-01-01")]);
+    // Invalid datetimes are still one token.
+    assert_eq!(kinds("2024-13-01T99"), vec![datetime("2024-13-01T99")]);
+    assert_eq!(kinds("15m30s 250ms"), vec![duration("15m30s"), duration("250ms")]);
+    assert_eq!(kinds("30s15m"), vec![duration("30s15m")]);
+    assert_eq!(kinds("2024 255u8 5y"), vec![word("2024"), word("255u8"), word("5y")]);
 
+    // Off by default.
+    let ref db = crate::Database::default();
+    let chunk_lex = lex_chunk(db, basic_source_map(db, Source::new(db, S("30s 2024-01-01"))));
+    assert!(chunk_lex.tokens(db).iter().all(|token| {
+        !matches!(token.kind(db), TokenKind::Datetime | TokenKind::Duration)
+    }));
+}
+
+#[test]
+fn test_glued_with_next() {
+    fn dbgglue(s: &str) -> Vec<bool> {
+        let ref db = crate::Database::default();
+        let source = Source::new(db, S(s));
+        let chunk = basic_source_map(db, source);
+        let chunk_lex = lex_chunk(db, chunk);
+        chunk_lex.tokens(db).iter().enumerate().map(|(index, token)| {
+            let glued = chunk_lex.glued_with_next(db, index);
+            assert_eq!(chunk_lex.token_index(db, *token), Some(index));
+            assert_eq!(token.glued_with_next(db, chunk_lex), glued);
+            glued
+        }).collect()
+    }
+
+    assert_eq!(dbgglue(""), Vec::<bool>::new());
+    assert_eq!(dbgglue("a"), vec![false]);
+    assert_eq!(dbgglue("a<b"), vec![true, true, false]);
+    assert_eq!(dbgglue("a < b"), vec![false, false, false, false, false]);
+    assert_eq!(dbgglue("a< b"), vec![true, false, false, false]);
+    assert_eq!(dbgglue("f(x)"), vec![true, true, true, false]);
+    assert_eq!(dbgglue("a// c\nb"), vec![false, false, false, false]);
+}
+
+#[test]
+fn test_lex_error_recovery() {
+    #[salsa::tracked]
+    fn dbglex_with<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+        error_recovery: ErrorRecovery,
+    ) -> String {
+        let chunk = basic_source_map(db, source);
+        let config = Config::new(db, error_recovery, SigilSet::all(), LiteralRegistry::default(), WhitespaceMode::Joined, false);
+        let chunk_lex = lex_chunk_with_config(db, chunk, config);
+        chunk_lex.debug_str(db)
+    }
+
+    fn dbglex(s: &str, error_recovery: ErrorRecovery) -> String {
+        let ref db = crate::Database::default();
+        let source = Source::new(db, S(s));
+        dbglex_with(db, source, error_recovery)
+    }
+
+    let default = ErrorRecovery::default();
+    assert_eq!(dbglex("§", default), "err");
+    assert_eq!(dbglex("a§b", default), "a err b");
+    assert_eq!(dbglex("§§¤ b", default), "err ws b");
+    assert_eq!(dbglex("a §+ b", default), "a ws err + ws b");
+    assert_eq!(dbglex("(§)", default), "( err )");
+    assert_eq!(dbglex("§é", default), "err é");
+
+    let through_whitespace = ErrorRecovery {
+        stop_at_whitespace: false,
+        ..default
+    };
+    assert_eq!(dbglex("§ § b", through_whitespace), "err b");
+
+    let through_sigils = ErrorRecovery {
+        stop_at_sigil: false,
+        ..default
+    };
+    assert_eq!(dbglex("§+§ b", through_sigils), "err ws b");
+
+    let through_words = ErrorRecovery {
+        stop_at_word: false,
+        ..default
+    };
+    assert_eq!(dbglex("§ab+", through_words), "err +");
+
+    let max_two = ErrorRecovery {
+        max_chars: Some(2),
+        ..default
+    };
+    assert_eq!(dbglex("§§§§§", max_two), "err err err");
+
+    // Fuzz corpus inputs containing `@`, `#` and `!` lex cleanly.
+    let corpus = [
+        r#": @!@@u64] / @[: @u64 / @19, : @u64 / @19]"#,
+        r#": #map#int, #bool> / #map {: #int / #119 = : #bool / #false}"#,
+        r#": !seti32> / set {: i32 / 127, : i32 / 63}"#,
+    ];
+    for input in corpus {
+        let lexed = dbglex(input, default);
+        assert!(!lexed.split(' ').contains(&"err"), "{input}: {lexed}");
+    }
+
+    assert_eq!(
+        dbglex(r#"§#map¤int, ¦bool¦"#, default),
+        "err # map err int , ws err bool err",
+    );
+}
+
+#[test]
+fn test_lex_chunk_by_kind() {
+    fn data_try_chunk(text: &str) -> Option<crate::chunks::ChunkSplit> {
+        Some(crate::chunks::ChunkSplit {
+            eat_bytes: 1,
+            kind: ChunkKind::Data,
+            next_kind: None,
+        })
+    }
+
+    #[salsa::tracked]
+    fn dbglex_kinds<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+    ) -> Vec<String> {
+        let full_chunk = basic_source_map(db, source);
+        let chunks_config = crate::chunks::Config::new(db, vec![';'], data_try_chunk, ChunkKind::Code);
+        let chunks = crate::chunks::chunks(db, full_chunk, chunks_config);
+        let data_config = Config::new(db, ErrorRecovery {
+            max_chars: Some(1),
+            .. ErrorRecovery::default()
+        }, SigilSet::all(), LiteralRegistry::default(), WhitespaceMode::Joined, false);
+        let kind_config = KindConfig::new(db, BTreeMap::from([
+            (ChunkKind::Data, data_config),
+        ]));
+        chunks.chunks(db).iter().map(|chunk| {
+            lex_chunk_by_kind(db, *chunk, kind_config).debug_str(db)
+        }).collect()
+    }
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S("§§;§§"));
+    assert_eq!(dbglex_kinds(db, source), vec![
+        S("err err ;"),
+        S("err"),
+    ]);
+}
+
+#[test]
+fn test_lex_sigil_sets() {
+    #[salsa::tracked]
+    fn dbglex_with<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+        sigil_sets: Vec<SigilSet>,
+    ) -> String {
+        let chunk = basic_source_map(db, source);
+        let config = Config::new(db, ErrorRecovery::default(), sigil_sets, LiteralRegistry::default(), WhitespaceMode::Joined, false);
+        lex_chunk_with_config(db, chunk, config).debug_str(db)
+    }
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S("a+?=b+|c $d+e"));
+    assert_eq!(
+        dbglex_with(db, source, SigilSet::all()),
+        "a +?= b +| c ws $ d + e",
+    );
+    assert_eq!(
+        dbglex_with(db, source, vec![SigilSet::BarOps]),
+        "a + ? = b +| c ws err d + e",
+    );
+    assert_eq!(
+        dbglex_with(db, source, vec![]),
+        "a + ? = b + | c ws err d + e",
+    );
+}
+
+#[test]
+fn test_lex_whitespace_mode() {
+    #[salsa::tracked]
+    fn lex_texts<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+        whitespace: WhitespaceMode,
+    ) -> Vec<String> {
+        let chunk = basic_source_map(db, source);
+        let config = Config::new(db, ErrorRecovery::default(), SigilSet::all(), LiteralRegistry::default(), whitespace, false);
+        lex_chunk_with_config(db, chunk, config).tokens(db).iter()
+            .map(|token| S(token.text(db).as_str(db)))
+            .collect()
+    }
+
+    let ref db = crate::Database::default();
+    let texts = |s: &str, whitespace| lex_texts(db, Source::new(db, S(s)), whitespace);
+
+    assert_eq!(texts("a \n\n  b", WhitespaceMode::Joined), vec!["a", " \n\n  ", "b"]);
+    assert_eq!(texts("a \n\n  b", WhitespaceMode::SplitLines), vec!["a", " \n", "\n", "  ", "b"]);
+    assert_eq!(texts("a\r\n\r\n", WhitespaceMode::SplitLines), vec!["a", "\r\n", "\r\n"]);
+    assert_eq!(texts("a  b", WhitespaceMode::SplitLines), vec!["a", "  ", "b"]);
+    // Whitespace in comments and strings is not lexed.
+    assert_eq!(texts("// a\n\n\"\n\n\"", WhitespaceMode::SplitLines), vec!["// a", "\n", "\n", "\"\n\n\""]);
+}
+
+#[test]
+fn test_lex_literals() {
+    #[salsa::tracked]
+    fn lex_with<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+        literals: LiteralRegistry,
+    ) -> ChunkLex<'db> {
+        let chunk = basic_source_map(db, source);
+        let config = Config::new(db, ErrorRecovery::default(), SigilSet::all(), literals, WhitespaceMode::Joined, false);
+        lex_chunk_with_config(db, chunk, config)
+    }
+
+    fn balanced(text: &str) -> Result<(), String> {
+        match text.matches('(').count() == text.matches(')').count() {
+            true => Ok(()),
+            false => Err(S("unbalanced parentheses")),
+        }
+    }
+
+    let mut literals = LiteralRegistry::default();
+    let re = literals.register("re", balanced);
+    let path = literals.register("path", |_| Ok(()));
+    assert_eq!(literals.extension(path).X().prefix, "path");
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S(r#"re"(a+)" path"a/b" x"c" re "d" re"(""#));
+    let chunk_lex = lex_with(db, source, literals);
+    let tokens: Vec<_> = chunk_lex.tokens(db).iter()
+        .filter_map(|token| token.without_space(db))
+        .map(|token| (S(token.text(db).as_str(db)), token.kind(db)))
+        .collect();
+    assert_eq!(tokens, vec![
+        (S(r#"re"(a+)""#), TokenKind::Literal(re)),
+        (S(r#"path"a/b""#), TokenKind::Literal(path)),
+        (S("x"), TokenKind::Word),
+        (S(r#""c""#), TokenKind::String),
+        (S("re"), TokenKind::Word),
+        (S(r#""d""#), TokenKind::String),
+        (S(r#"re"(""#), TokenKind::Literal(re)),
+    ]);
+    assert_eq!(chunk_lex.literal_errors(db), &vec![(31..36, S("unbalanced parentheses"))]);
+}
+
+#[test]
+fn test_token_kind_views() {
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S(r#"a = "x" + b"y" § b"#));
+    let chunk_lex = lex_chunk(db, basic_source_map(db, source));
+    let texts = |tokens: Vec<(usize, Token<'_>)>| -> Vec<(usize, String)> {
+        tokens.into_iter().map(|(index, token)| (index, S(token.text(db).as_str(db)))).collect()
+    };
+    assert_eq!(texts(chunk_lex.words(db).collect()), vec![(0, S("a")), (12, S("b"))]);
+    assert_eq!(texts(chunk_lex.sigils(db).collect()), vec![(2, S("=")), (6, S("+"))]);
+    assert_eq!(texts(chunk_lex.strings(db).collect()), vec![(4, S(r#""x""#)), (8, S(r#"b"y""#))]);
+    assert_eq!(texts(chunk_lex.errors(db).collect()), vec![(10, S("§"))]);
+}
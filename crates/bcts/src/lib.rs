@@ -5,13 +5,41 @@ use rmx::prelude::*;
 
 pub mod input;
 pub mod text;
+pub mod span;
 pub mod escapes;
+pub mod numbers;
+pub mod time_literals;
 pub mod chunk;
 pub mod source_map;
 pub mod chunks;
+pub mod statement_cache;
 pub mod lexer;
 pub mod bracer;
 pub mod lines;
+pub mod line_index;
+pub mod key_values;
+pub mod comments;
+pub mod confusables;
+pub mod text_objects;
+pub mod continuation;
+pub mod tree_eq;
+pub mod arena;
+pub mod edit;
+pub mod diff;
+pub mod fixes;
+pub mod lints;
+pub mod format;
+pub mod migrate;
+pub mod mutate;
+pub mod diagnostics;
+pub mod analysis;
+pub mod project_config;
+pub mod progress;
+pub mod metrics;
+pub mod dump;
+pub mod token_stats;
+pub mod complexity;
+pub mod test_runner;
 
 pub mod modules;
 pub mod module_resolve;
@@ -21,11 +49,44 @@ pub mod package_resolve;
 
 pub mod package2;
 pub mod package_resolve2;
+pub mod world_iter;
+pub mod module_attrs;
+pub mod import_lines;
+pub mod language;
 
 pub mod module_graph;
+pub mod lower;
+pub mod data;
+pub mod data_writer;
+pub mod data_schema;
+pub mod graph_export;
+#[cfg(feature = "binary-export")]
+pub mod graph_binary;
+pub mod graph_metrics;
+pub mod module_pattern;
+pub mod grammar_export;
+pub mod world_builder;
+pub mod session;
+pub mod world_fixture;
+pub mod state_archive;
+pub mod replay;
+pub mod loader;
+pub mod mmap;
+pub mod ignore_rules;
+
+#[cfg(test)]
+mod edge_case_tests;
 
 use salsa::Database as Db;
 
+/// The `tracing` target of per-token logging inside the passes,
+/// e.g. each step of the bracer iterator.
+///
+/// These events are too frequent to leave on with the rest
+/// of the debug logging, so enable them separately,
+/// e.g. with `RUST_LOG=bcts::verbose=trace`.
+pub const VERBOSE_TARGET: &str = "bcts::verbose";
+
 #[salsa::db]
 #[derive(Default, Clone)]
 pub struct Database {
@@ -35,3 +96,33 @@ pub struct Database {
 #[salsa::db]
 impl salsa::Database for Database {
 }
+
+/// A database that logs the salsa events `record` turns into strings,
+/// for tests of what queries run and what is revalidated.
+#[cfg(test)]
+pub(crate) fn event_recording_database(
+    record: fn(&salsa::Event) -> Option<String>,
+) -> (Database, rmx::std::sync::Arc<rmx::std::sync::Mutex<Vec<String>>>) {
+    let events = rmx::std::sync::Arc::new(rmx::std::sync::Mutex::new(vec![]));
+    let db = Database {
+        storage: salsa::Storage::new(Some(Box::new({
+            let events = events.C();
+            move |event: salsa::Event| {
+                if let Some(entry) = record(&event) {
+                    events.lock().X().push(entry);
+                }
+            }
+        }))),
+    };
+    (db, events)
+}
+
+/// The query an event executes, like `parse_statement(Id(400))`,
+/// for `event_recording_database`.
+#[cfg(test)]
+pub(crate) fn executed_query(event: &salsa::Event) -> Option<String> {
+    match event.kind {
+        salsa::EventKind::WillExecute { database_key } => Some(format!("{database_key:?}")),
+        _ => None,
+    }
+}
@@ -0,0 +1,133 @@
+//! Conversion of byte offsets to lines and columns.
+//!
+//! Spans are byte ranges, but people count columns on screen,
+//! where a tab advances to the next multiple of the tab width
+//! and a multi-byte character is one column.
+//! Lines and columns are zero-based, and displayed one-based.
+
+use rmx::prelude::*;
+use rmx::std::fmt;
+use rmx::std::iter;
+
+/// The lines of a text.
+#[derive(Clone, Debug)]
+pub struct LineIndex<'a> {
+    text: &'a str,
+    /// The byte offset of each line's first character.
+    line_starts: Vec<usize>,
+    tab_width: usize,
+}
+
+/// A position as a line and a column.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
+pub struct LineCol {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl fmt::Display for LineCol {
+    /// Written one-based, like `3:14`.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.line.checked_add(1).X(), self.column.checked_add(1).X())
+    }
+}
+
+impl<'a> LineIndex<'a> {
+    /// Index `text`, with tab stops every `tab_width` columns.
+    ///
+    /// A `tab_width` of 0 is taken as 1.
+    pub fn new(text: &'a str, tab_width: usize) -> LineIndex<'a> {
+        let newlines = memchr::memchr_iter(b'\n', text.as_bytes())
+            .map(|newline| newline.checked_add(1).X());
+        LineIndex {
+            text,
+            line_starts: iter::once(0).chain(newlines).collect(),
+            tab_width: tab_width.max(1),
+        }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+
+    /// The line containing the byte at `offset`.
+    ///
+    /// Panics if `offset` is past the end of the text.
+    pub fn line(&self, offset: usize) -> usize {
+        assert!(offset <= self.text.len());
+        self.line_starts.partition_point(|start| *start <= offset).checked_sub(1).X()
+    }
+
+    /// The text of `line`, without its line ending.
+    pub fn line_text(&self, line: usize) -> &'a str {
+        let start = self.line_starts[line];
+        let end = self.line_starts.get(line.checked_add(1).X())
+            .map_or(self.text.len(), |next| next.checked_sub(1).X());
+        let text = &self.text[start..end];
+        text.strip_suffix('\r').unwrap_or(text)
+    }
+
+    /// The line and column of the byte at `offset`.
+    ///
+    /// Panics if `offset` is past the end of the text
+    /// or not at a character boundary.
+    pub fn line_col(&self, offset: usize) -> LineCol {
+        let line = self.line(offset);
+        let before = &self.text[self.line_starts[line]..offset];
+        LineCol { line, column: self.width_from(0, before) }
+    }
+
+    /// The column reached by writing `text` from column `column`.
+    pub fn width_from(&self, column: usize, text: &str) -> usize {
+        text.chars().fold(column, |column, ch| match ch {
+            '\t' => column.checked_div(self.tab_width).X()
+                .checked_add(1).X()
+                .checked_mul(self.tab_width).X(),
+            _ => column.checked_add(1).X(),
+        })
+    }
+
+    /// `text` with its tabs replaced by spaces to the same columns,
+    /// starting at column 0.
+    pub fn expand_tabs(&self, text: &str) -> String {
+        let mut expanded = String::with_capacity(text.len());
+        let mut column = 0;
+        for ch in text.chars() {
+            let next = self.width_from(column, ch.encode_utf8(&mut [0; 4]));
+            match ch {
+                '\t' => expanded.extend(iter::repeat_n(' ', next.checked_sub(column).X())),
+                _ => expanded.push(ch),
+            }
+            column = next;
+        }
+        expanded
+    }
+}
+
+#[test]
+fn test_line_index() {
+    let text = "a\tb\n\t\tx = 1\r\n  \ty\n";
+    let index = LineIndex::new(text, 4);
+    assert_eq!(index.line_count(), 4);
+    assert_eq!(index.line_text(1), "\t\tx = 1");
+    assert_eq!(index.line_text(3), "");
+
+    let col = |offset| {
+        let LineCol { line, column } = index.line_col(offset);
+        (line, column)
+    };
+    assert_eq!(col(0), (0, 0));
+    assert_eq!(col(2), (0, 4));
+    assert_eq!(col(3), (0, 5));
+    assert_eq!(col(4), (1, 0));
+    assert_eq!(col(6), (1, 8));
+    // Spaces before a tab don't move its stop.
+    assert_eq!(col(text.find('y').X()), (2, 4));
+    assert_eq!(col(text.len()), (3, 0));
+    assert_eq!(index.line_col(6).to_string(), "2:9");
+
+    assert_eq!(index.expand_tabs("a\tb"), "a   b");
+    assert_eq!(index.expand_tabs("  \ty"), "    y");
+    assert_eq!(LineIndex::new(text, 2).line_col(6), LineCol { line: 1, column: 4 });
+    assert_eq!(LineIndex::new("é\tx", 8).line_col(3), LineCol { line: 0, column: 8 });
+}
@@ -6,6 +6,16 @@ use rmx::core::ops::Range;
 use crate::bracer::{Bracer, TreeToken, BracerIter};
 use crate::lexer::{Token, TokenKind};
 
+/// Split tree tokens into lines,
+/// each ending after a whitespace token containing a newline.
+///
+/// Lines are never empty, so no tokens, as of an empty source
+/// or an empty branch, make no lines.
+/// A whitespace token is one line however many newlines it holds,
+/// so a whitespace-only source is one line.
+/// Lexed with `WhitespaceMode::SplitLines`, whitespace tokens
+/// hold at most one newline, so each blank line is a line of its own,
+/// and the indentation of a line is on that line, not the one before.
 pub fn iter_lines<'db>(
     db: &'db dyn crate::Db,
     iter: impl Iterator<Item = TreeToken<'db>> + Clone,
@@ -34,6 +44,49 @@ pub fn iter_lines<'db>(
     })
 }
 
+/// What a line of `iter_lines` holds.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum LineKind {
+    /// A token other than whitespace.
+    Content,
+    /// Only whitespace, ending in a newline,
+    /// making up `newlines` blank lines of the source.
+    ///
+    /// With `WhitespaceMode::SplitLines` each blank line is a line of its own.
+    /// Otherwise blank lines after a content line end that line,
+    /// and only those starting a source or branch make a line,
+    /// all of them together.
+    /// The newline ending the line of an open brace starts the branch,
+    /// so is a blank line of the branch's lines.
+    Blank { newlines: usize },
+    /// Only whitespace without a newline, continuing to the end
+    /// of the source or branch, like indentation before a close brace
+    /// or spaces at the end of a file.
+    Whitespace,
+}
+
+/// The kind of `line`, a line of `iter_lines`.
+pub fn line_kind<'db>(
+    db: &'db dyn crate::Db,
+    line: impl Iterator<Item = TreeToken<'db>>,
+) -> LineKind {
+    let mut newlines: usize = 0;
+    for tree_token in line {
+        let TreeToken::Token(token) = tree_token else {
+            return LineKind::Content;
+        };
+        if token.kind(db) != TokenKind::Whitespace {
+            return LineKind::Content;
+        }
+        let count = token.text(db).as_str(db).matches('\n').count();
+        newlines = newlines.checked_add(count).X();
+    }
+    match newlines {
+        0 => LineKind::Whitespace,
+        newlines => LineKind::Blank { newlines },
+    }
+}
+
 impl<'db> BracerIter<'db> {
     pub fn lines(self) -> impl Iterator<Item = impl Iterator<Item = TreeToken<'db>>> {
         iter_lines(self.db, self)
@@ -66,14 +119,79 @@ impl<'db> TreeToken<'db> {
 
 #[cfg(test)]
 fn dbglex(s: &str) -> Vec<String> {
-    debug!("dbglex {s}");
+    tracing::debug!(source = s, "dbglex");
     use crate::bracer::IteratorOfTreeTokenExt as _;
     let ref db = crate::Database::default();
-    let source = crate::input::Source::new(db, S(s));
+    let bracer = crate::bracer::bracer_for_str(db, s);
+    bracer.iter(db).lines().map(|mut line| line.debug_str(db)).collect()
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn dbglex_split_lines(db: &dyn crate::Db, source: crate::input::Source) -> Vec<String> {
+    use crate::bracer::IteratorOfTreeTokenExt as _;
+    use crate::lexer::{Config, ErrorRecovery, LiteralRegistry, SigilSet, WhitespaceMode};
     let chunk = crate::source_map::basic_source_map(db, source);
-    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    let config = Config::new(db, ErrorRecovery::default(), SigilSet::all(), LiteralRegistry::default(), WhitespaceMode::SplitLines, false);
+    let chunk_lex = crate::lexer::lex_chunk_with_config(db, chunk, config);
     let bracer = crate::bracer::bracer(db, chunk_lex);
-    bracer.iter(db).lines().map(|mut line| line.debug_str(db)).collect()
+    bracer.iter(db).lines().map(|line| line.debug_str(db)).collect()
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn line_kinds(
+    db: &dyn crate::Db,
+    source: crate::input::Source,
+    whitespace: crate::lexer::WhitespaceMode,
+) -> Vec<LineKind> {
+    use crate::lexer::{Config, ErrorRecovery, LiteralRegistry, SigilSet};
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let config = Config::new(db, ErrorRecovery::default(), SigilSet::all(), LiteralRegistry::default(), whitespace, false);
+    let chunk_lex = crate::lexer::lex_chunk_with_config(db, chunk, config);
+    let bracer = crate::bracer::bracer(db, chunk_lex);
+    let mut kinds = vec![];
+    for line in bracer.iter(db).lines() {
+        let line: Vec<_> = line.collect();
+        kinds.push(line_kind(db, line.iter().cloned()));
+        // And the lines of a branch alone on its line.
+        if let [TreeToken::Branch(_, iter)] = &line[..] {
+            kinds.extend(iter.C().lines().map(|line| line_kind(db, line)));
+        }
+    }
+    kinds
+}
+
+#[test]
+fn test_line_kind() {
+    use crate::lexer::WhitespaceMode;
+
+    let ref db = crate::Database::default();
+    let kinds = |s: &str, whitespace| line_kinds(db, crate::input::Source::new(db, S(s)), whitespace);
+    let blank = |newlines| LineKind::Blank { newlines };
+
+    let text = "\n\na\n\n\nb  ";
+    assert_eq!(kinds(text, WhitespaceMode::Joined), vec![
+        blank(2), LineKind::Content, LineKind::Content,
+    ]);
+    assert_eq!(kinds(text, WhitespaceMode::SplitLines), vec![
+        blank(1), blank(1), LineKind::Content, blank(1), blank(1), LineKind::Content,
+    ]);
+    assert_eq!(kinds("  ", WhitespaceMode::SplitLines), vec![LineKind::Whitespace]);
+    // The newline after the open brace, and the indentation before the close.
+    assert_eq!(kinds("(\n\na\n  )", WhitespaceMode::SplitLines), vec![
+        LineKind::Content, blank(1), blank(1), LineKind::Content, LineKind::Whitespace,
+    ]);
+}
+
+#[test]
+fn test_split_lines() {
+    let ref db = crate::Database::default();
+    let lines = |s: &str| dbglex_split_lines(db, crate::input::Source::new(db, S(s)));
+    assert_eq!(lines("\n\n"), vec![S("ws"), S("ws")]);
+    assert_eq!(lines("a\n\n\nb"), vec![S("a ws"), S("ws"), S("ws"), S("b")]);
+    assert_eq!(lines("a\n  b\n"), vec![S("a ws"), S("ws b ws")]);
+    assert_eq!(lines("a(b\n\nc)d"), vec![S("a ( b ws ws c ) d")]);
 }
 
 #[test]
@@ -0,0 +1,210 @@
+//! Built-in style lints.
+//!
+//! A lint is a diagnostic that comes with its fix,
+//! unlike brace errors, whose fixes are worked out separately
+//! by `fixes::bracer_fixes`.
+//! Lints are warnings by default; `lint_fixes` leaves out
+//! the fixes of lints a `SeverityConfig` allows,
+//! so `bcts check --fix` doesn't undo what a project chose to allow.
+//!
+//! `whitespace_lints` checks the whitespace the formatter also fixes,
+//! for projects that don't run the formatter:
+//!
+//! - `TrailingWhitespace`, spaces or tabs at the end of a line,
+//!   including the end of a line comment.
+//! - `MissingFinalNewline`, a file whose last line has no line ending.
+//! - `ExtraFinalNewlines`, blank lines or whitespace after the last line.
+
+use rmx::prelude::*;
+
+use crate::bracer::Bracer;
+use crate::diagnostics::{Code, Diagnostic, Severity, SeverityConfig};
+use crate::edit::TextEdit;
+use crate::lexer::TokenKind;
+use crate::text::ByteSpan;
+
+/// A diagnostic and the edit fixing it.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Lint {
+    pub diagnostic: Diagnostic,
+    pub fix: TextEdit,
+}
+
+/// Lints for trailing whitespace and the final newline, in source order.
+///
+/// Whitespace in strings and block comments is left alone,
+/// as is the end of a file ending in an error token,
+/// like an unterminated comment, which would take in an added newline.
+/// A file of only whitespace has no lints.
+pub fn whitespace_lints<'db>(
+    db: &'db dyn crate::Db,
+    bracer: Bracer<'db>,
+) -> Vec<Lint> {
+    let tokens = bracer.chunk(db).tokens(db);
+    let text = bracer.chunk(db).chunk(db).text(db).as_str(db);
+    let mut lints = vec![];
+    let trailing = |lints: &mut Vec<Lint>, span: ByteSpan| {
+        lints.push(Lint {
+            diagnostic: Diagnostic {
+                code: Code::TrailingWhitespace,
+                span: Some(span.C()),
+                message: S("trailing whitespace"),
+            },
+            fix: TextEdit::delete(span),
+        });
+    };
+
+    // The whitespace after the last other token is checked
+    // with the final newline.
+    let Some(last) = tokens.iter().rev().find(|token| token.kind(db) != TokenKind::Whitespace) else {
+        return lints;
+    };
+    let content_end = last.text(db).range(db).end;
+
+    for token in tokens {
+        let range = token.text(db).range(db);
+        if range.start >= content_end {
+            break;
+        }
+        match token.kind(db) {
+            TokenKind::Whitespace => {
+                let mut line_start = range.start;
+                for line in text[range.C()].split_inclusive('\n') {
+                    if let Some(line) = line.strip_suffix('\n') {
+                        let line = line.strip_suffix('\r').unwrap_or(line);
+                        if !line.is_empty() {
+                            trailing(&mut lints, line_start..line_start.checked_add(line.len()).X());
+                        }
+                    }
+                    line_start = line_start.checked_add(line.len()).X();
+                }
+            }
+            // A line comment ends before `\n`, so includes the `\r` of `\r\n`.
+            TokenKind::Comment if text[range.C()].starts_with("//") => {
+                let comment = &text[range.C()];
+                let comment = comment.strip_suffix('\r').unwrap_or(comment);
+                let trimmed = comment.trim_end();
+                if trimmed.len() < comment.len() {
+                    let start = range.start.checked_add(trimmed.len()).X();
+                    trailing(&mut lints, start..range.start.checked_add(comment.len()).X());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if last.kind(db) == TokenKind::Error {
+        return lints;
+    }
+    let rest = &text[content_end..];
+    match rest.find('\n') {
+        None => {
+            if !rest.is_empty() {
+                trailing(&mut lints, content_end..text.len());
+            }
+            let line_ending = crate::fixes::line_ending(text);
+            lints.push(Lint {
+                diagnostic: Diagnostic {
+                    code: Code::MissingFinalNewline,
+                    span: Some(text.len()..text.len()),
+                    message: S("missing newline at the end of the file"),
+                },
+                fix: TextEdit::insert(text.len(), line_ending),
+            });
+        }
+        Some(newline) => {
+            let line = &rest[..newline];
+            let line = line.strip_suffix('\r').unwrap_or(line);
+            if !line.is_empty() {
+                trailing(&mut lints, content_end..content_end.checked_add(line.len()).X());
+            }
+            let end = content_end.checked_add(newline).X().checked_add(1).X();
+            if end < text.len() {
+                let message = match text[end..].contains('\n') {
+                    true => "blank lines at the end of the file",
+                    false => "whitespace after the final newline",
+                };
+                lints.push(Lint {
+                    diagnostic: Diagnostic {
+                        code: Code::ExtraFinalNewlines,
+                        span: Some(end..text.len()),
+                        message: S(message),
+                    },
+                    fix: TextEdit::delete(end..text.len()),
+                });
+            }
+        }
+    }
+
+    lints
+}
+
+/// The fixes of `lints`, except those of lints `config` allows.
+pub fn lint_fixes(lints: &[Lint], config: &SeverityConfig) -> Vec<TextEdit> {
+    lints.iter()
+        .filter(|lint| config.severity(lint.diagnostic.code) != Severity::Allow)
+        .map(|lint| lint.fix.C())
+        .collect()
+}
+
+#[test]
+fn test_whitespace_lints() {
+    let ref db = crate::Database::default();
+    let lints = |s: &str| {
+        let bracer = crate::bracer::bracer_for_str(db, s);
+        whitespace_lints(db, bracer)
+    };
+    let codes = |s: &str| {
+        lints(s).iter()
+            .map(|lint| (lint.diagnostic.code, lint.diagnostic.span.C().X()))
+            .collect::<Vec<_>>()
+    };
+    let fixed = |s: &str| {
+        let fixes = lint_fixes(&lints(s), &SeverityConfig::default());
+        crate::edit::apply_edits(s, &fixes).X()
+    };
+
+    assert_eq!(codes(""), vec![]);
+    assert_eq!(codes(" \n\n"), vec![]);
+    assert_eq!(codes("a\n"), vec![]);
+    assert_eq!(codes("a \nb\t\t\n"), vec![
+        (Code::TrailingWhitespace, 1..2),
+        (Code::TrailingWhitespace, 4..6),
+    ]);
+    assert_eq!(codes("a"), vec![(Code::MissingFinalNewline, 1..1)]);
+    assert_eq!(codes("a  "), vec![
+        (Code::TrailingWhitespace, 1..3),
+        (Code::MissingFinalNewline, 3..3),
+    ]);
+    assert_eq!(codes("a\n\n \n"), vec![(Code::ExtraFinalNewlines, 2..5)]);
+    assert_eq!(lints("a\n  ")[0].diagnostic.message, "whitespace after the final newline");
+
+    assert_eq!(fixed("a \n  \n{\n  b  // c  \n}  \n\n"), "a\n\n{\n  b  // c\n}\n");
+    assert_eq!(fixed("a \r\n// b \r\nc"), "a\r\n// b\r\nc\r\n");
+    // Strings, block comments and unterminated tokens are left alone.
+    assert_eq!(fixed("\"a  \n\" /* b  \n*/\n"), "\"a  \n\" /* b  \n*/\n");
+    assert_eq!(fixed("a  \n/* b  "), "a\n/* b  ");
+
+    // Fixes of allowed lints are left out.
+    let config = SeverityConfig::from_toml("[severity]\nE0012 = \"allow\"\n").X();
+    let fixes = lint_fixes(&lints("a  "), &config);
+    assert_eq!(fixes, vec![TextEdit::insert(3, "\n")]);
+}
+
+#[test]
+fn test_fixes_converge() {
+    // Brace and lint fixes applied together, as by `bcts check --fix`.
+    fn fix(s: &str) -> String {
+        let ref db = crate::Database::default();
+        let bracer = crate::bracer::bracer_for_str(db, s);
+        let mut fixes = crate::fixes::bracer_fixes(db, bracer);
+        fixes.extend(lint_fixes(&whitespace_lints(db, bracer), &SeverityConfig::default()));
+        crate::edit::apply_edits(s, &fixes).X()
+    }
+
+    for s in ["a = (b\nc ] d  \n", "(a  ", "(a\n\n  ", "{a // b  \n\n", "(a // b"] {
+        let fixed = fix(s);
+        assert_eq!(fix(&fixed), fixed, "fixing {s:?}");
+    }
+    assert_eq!(fix("a = (b\nc ] d  \n"), "a = (b\nc  d\n)\n");
+}
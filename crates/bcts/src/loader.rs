@@ -0,0 +1,702 @@
+//! Loading package worlds from directories on disk.
+//!
+//! A package path is a directory of packages.
+//! Each subdirectory is a package of the same name,
+//! and each `.bct` file below it is a module,
+//! named by its path within the package without the extension,
+//! e.g. `ui/window` for `ui/window.bct`.
+//! Hidden files and directories are skipped,
+//! as are those matched by `.gitignore` and `.bctignore` files,
+//! as described in `ignore_rules`.
+//!
+//! Package names and the segments of module names
+//! must each be a single word to the lexer, and not reserved;
+//! files and directories with other names are skipped
+//! and reported as `InvalidModuleName`.
+//!
+//! A module file that is missing or unreadable when it is read,
+//! e.g. a dangling symlink or a file that is not UTF-8,
+//! still becomes a module, with an empty source
+//! carrying the error in `Source::load_error`,
+//! which `diagnostics::import_diagnostics` reports at its importers.
+//! Once the file is readable, the next load clears the error.
+//! Files are read by `mmap::read_to_string`,
+//! which maps large files with the `mmap` feature.
+//! Failing to read a package path or package directory
+//! fails the whole load.
+//!
+//! Every loaded file is registered by path in the database,
+//! so an editor can shadow it with the text of an unsaved buffer
+//! using `Database::set_overlay`, without writing to disk.
+//! Overlays may be set before the file is loaded,
+//! and are kept when the world is reloaded.
+//!
+//! Files are identified by their canonical path,
+//! with symlinks resolved and `..` folded,
+//! so an overlay may be set through any path to its file.
+//! When two paths in a load lead to the same file or directory,
+//! e.g. through a symlink, only the first is loaded
+//! and the others are reported as `DuplicateModulePath`.
+//! So are modules of a package whose names differ only in case,
+//! which could not coexist on a case-insensitive file system.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+use rmx::std::path::{Path, PathBuf};
+use salsa::Setter as _;
+
+use crate::chunk::{Chunk, ChunkKind};
+use crate::diagnostics::{Code, Diagnostic};
+use crate::ignore_rules::IgnoreRules;
+use crate::input::Source;
+use crate::lexer::{TokenKind, lex_chunk};
+use crate::package2::{Package, PackageModule, PackageName, PackageWorld, ModuleName};
+use crate::package2::import_space_durability;
+use crate::project_config::ConfigFile;
+use crate::text::{InternedText, Text};
+
+/// The file extension of modules.
+pub const MODULE_EXTENSION: &str = "bct";
+
+/// A file read by the loader.
+#[salsa::input]
+pub struct LoadedFile {
+    #[returns(ref)]
+    pub path: PathBuf,
+    /// The module source, with the file's text or its overlay.
+    pub source: Source,
+    /// The text last read from disk, empty if it couldn't be read.
+    #[returns(ref)]
+    pub disk_text: String,
+    /// Why the file couldn't be read, as of the last load.
+    #[returns(ref)]
+    pub load_error: Option<String>,
+    /// The durability of `source`, that of its import space.
+    pub durability: salsa::Durability,
+}
+
+/// The files loaded into a database, and the overlays shadowing them.
+#[salsa::input(singleton)]
+pub struct FileRegistry {
+    #[returns(ref)]
+    pub files: BTreeMap<PathBuf, LoadedFile>,
+    /// Editor buffer text by path, used instead of the file's text.
+    #[returns(ref)]
+    pub overlays: BTreeMap<PathBuf, String>,
+}
+
+/// Where to find the packages of each import space.
+#[derive(Clone, Debug, Default)]
+pub struct PackageLoader {
+    pub sys_paths: Vec<PathBuf>,
+    pub local_paths: Vec<PathBuf>,
+}
+
+/// A world read by `PackageLoader::load`.
+pub struct LoadedWorld {
+    pub world: PackageWorld,
+    /// Problems with the files of the world, paired with the file's path.
+    pub diagnostics: Vec<(PathBuf, Diagnostic)>,
+}
+
+impl PackageLoader {
+    /// Load local packages from the project's `[packages] paths`,
+    /// relative to `root`, and system packages from `sys_paths`.
+    pub fn from_config(root: &Path, config: &ConfigFile, sys_paths: Vec<PathBuf>) -> PackageLoader {
+        PackageLoader {
+            sys_paths,
+            local_paths: config.package_paths.iter().map(|path| root.join(path)).collect(),
+        }
+    }
+
+    /// Whether `path` would be loaded as a module:
+    /// a visible `.bct` file in a package, not ignored.
+    ///
+    /// This is for deciding whether a changed file,
+    /// e.g. one reported by a file watcher, needs a reload.
+    /// The file itself need not exist.
+    pub fn is_module_path(&self, path: &Path) -> AnyResult<bool> {
+        let package_path = self.sys_paths.iter().chain(&self.local_paths)
+            .find(|package_path| path.starts_with(package_path));
+        let Some(package_path) = package_path else {
+            return Ok(false);
+        };
+        let relative = path.strip_prefix(package_path).X();
+        let visible = relative.iter()
+            .all(|name| name.to_str().is_some_and(|name| !name.starts_with('.')));
+        let is_module = visible
+            && relative.iter().count() >= 2
+            && path.extension().is_some_and(|extension| extension == MODULE_EXTENSION);
+        Ok(is_module && IgnoreRules::for_path(package_path, path)?.is_some())
+    }
+
+    /// Read every package into a new `PackageWorld`.
+    ///
+    /// Files loaded before keep their `Source`,
+    /// which is only updated if its text changed,
+    /// so reloading reuses the analysis of unchanged modules.
+    /// A package found in more than one package path of an import space
+    /// is taken from the first.
+    pub fn load(&self, db: &mut crate::Database) -> AnyResult<LoadedWorld> {
+        let mut walk = Walk::default();
+        let pkglib_system = self.load_import_space(db, &mut walk, "sys", &self.sys_paths)?;
+        let pkglib_local = self.load_import_space(db, &mut walk, "local", &self.local_paths)?;
+        let world = PackageWorld::builder(pkglib_system, pkglib_local)
+            .pkglib_system_durability(import_space_durability("sys"))
+            .pkglib_local_durability(import_space_durability("local"))
+            .new(db);
+        crate::metrics::record(|metrics| {
+            for (_, diagnostic) in &walk.diagnostics {
+                metrics.diagnostic_emitted(diagnostic.code);
+            }
+        });
+        Ok(LoadedWorld { world, diagnostics: walk.diagnostics })
+    }
+
+    fn load_import_space(
+        &self,
+        db: &mut crate::Database,
+        walk: &mut Walk,
+        import_space: &str,
+        package_paths: &[PathBuf],
+    ) -> AnyResult<BTreeMap<PackageName, Package>> {
+        let durability = import_space_durability(import_space);
+        let mut packages = BTreeMap::new();
+        for package_path in package_paths {
+            let rules = IgnoreRules::default().with_dir(package_path)?;
+            for (package_name, package_dir) in visible_entries(package_path, true)? {
+                if packages.contains_key(&package_name)
+                    || rules.is_ignored(&package_dir, true)
+                    || !walk.valid_name(&*db, &package_dir, "package", &package_name)
+                    || !walk.visit(&package_dir)
+                {
+                    continue;
+                }
+                let rules = rules.with_dir(&package_dir)?;
+                let mut modules = BTreeMap::new();
+                for (module_name, path) in module_files(&*db, walk, &package_dir, rules)? {
+                    let source = db.load_file(&path, durability);
+                    let package_module = PackageModule::builder(module_name.C(), source)
+                        .durability(durability)
+                        .new(db);
+                    modules.insert(module_name, package_module);
+                }
+                let package = Package::builder(package_name.C(), modules)
+                    .durability(durability)
+                    .new(db);
+                packages.insert(package_name, package);
+            }
+        }
+        Ok(packages)
+    }
+}
+
+/// The paths visited by one load.
+#[derive(Default)]
+struct Walk {
+    /// The first path visited for each canonical path.
+    seen: BTreeMap<PathBuf, PathBuf>,
+    diagnostics: Vec<(PathBuf, Diagnostic)>,
+}
+
+impl Walk {
+    /// Whether `path` is the first path to the file or directory it names.
+    ///
+    /// Later paths, through symlinks or `..`, are reported and skipped,
+    /// which also stops symlink loops.
+    fn visit(&mut self, path: &Path) -> bool {
+        let canonical = canonical_path(path);
+        match self.seen.get(&canonical) {
+            Some(first) => {
+                let message = format!("`{}` is the same file as `{}`", path.display(), first.display());
+                self.duplicate(path, message);
+                false
+            }
+            None => {
+                self.seen.insert(canonical, path.to_path_buf());
+                true
+            }
+        }
+    }
+
+    /// Whether `name`, of the file or directory at `path`,
+    /// is a valid package or module name.
+    /// Invalid names are reported.
+    fn valid_name(&mut self, db: &dyn crate::Db, path: &Path, what: &str, name: &str) -> bool {
+        match name_error(db, InternedText::new(db, S(name))) {
+            Some(error) => {
+                let message = format!("{what} name `{name}` {error}");
+                self.report(path, Code::InvalidModuleName, message);
+                false
+            }
+            None => true,
+        }
+    }
+
+    fn duplicate(&mut self, path: &Path, message: String) {
+        self.report(path, Code::DuplicateModulePath, message);
+    }
+
+    fn report(&mut self, path: &Path, code: Code, message: String) {
+        let diagnostic = Diagnostic { code, span: None, message };
+        self.diagnostics.push((path.to_path_buf(), diagnostic));
+    }
+}
+
+/// The `.bct` files under `package_dir`, by module name,
+/// with their canonical paths.
+///
+/// Of modules whose names differ only in case,
+/// which would be one file on a case-insensitive file system,
+/// only the first in name order is kept.
+fn module_files(
+    db: &dyn crate::Db,
+    walk: &mut Walk,
+    package_dir: &Path,
+    rules: IgnoreRules,
+) -> AnyResult<BTreeMap<ModuleName, PathBuf>> {
+    let mut files = vec![];
+    let mut dirs = vec![(String::new(), package_dir.to_path_buf(), rules)];
+    while let Some((prefix, dir, rules)) = dirs.pop() {
+        for (name, path) in visible_entries(&dir, false)? {
+            let is_dir = path.is_dir();
+            if rules.is_ignored(&path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                if walk.valid_name(db, &path, "module", &name) && walk.visit(&path) {
+                    let rules = rules.with_dir(&path)?;
+                    dirs.push((format!("{prefix}{name}/"), path, rules));
+                }
+            } else if let Some(stem) = name.strip_suffix(&format!(".{MODULE_EXTENSION}")) {
+                if !walk.valid_name(db, &path, "module", stem) {
+                    continue;
+                }
+                files.push((format!("{prefix}{stem}"), path));
+            }
+        }
+    }
+    files.sort();
+
+    let mut modules = BTreeMap::new();
+    let mut folded_names: BTreeMap<String, PathBuf> = BTreeMap::new();
+    for (module_name, path) in files {
+        if let Some(first) = folded_names.get(&module_name.to_lowercase()) {
+            let message = format!(
+                "module `{module_name}` at `{}` differs only in case from the module at `{}`",
+                path.display(), first.display(),
+            );
+            walk.duplicate(&path, message);
+            continue;
+        }
+        if walk.visit(&path) {
+            folded_names.insert(module_name.to_lowercase(), path.C());
+            modules.insert(module_name, canonical_path(&path));
+        }
+    }
+    Ok(modules)
+}
+
+/// Names that can't be package names or module path segments,
+/// because they are import spaces with special meaning.
+pub const RESERVED_NAMES: [&str; 3] = ["pkg", "self", "super"];
+
+/// Why `name` can't be a package name or a segment of a module path,
+/// if it can't.
+///
+/// A name must lex as a single word, and not be one of `RESERVED_NAMES`.
+#[salsa::tracked]
+pub fn name_error<'db>(db: &'db dyn crate::Db, name: InternedText<'db>) -> Option<String> {
+    let name = name.text(db);
+    if RESERVED_NAMES.contains(&name.as_str()) {
+        return Some(S("is reserved"));
+    }
+    let text = Text::new(db, name.C());
+    let chunk = Chunk::new(db, text, vec![], vec![], vec![], vec![], vec![], ChunkKind::Code);
+    match lex_chunk(db, chunk).tokens(db).as_slice() {
+        [token] if token.kind(db) == TokenKind::Word => None,
+        _ => Some(S("is not a single word")),
+    }
+}
+
+/// `path` with symlinks resolved and `.` and `..` folded,
+/// or as given if it doesn't exist.
+fn canonical_path(path: &Path) -> PathBuf {
+    rmx::std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// The non-hidden entries of `dir`, optionally only directories,
+/// following symlinks, sorted by name.
+/// Names that are not UTF-8 are skipped.
+fn visible_entries(dir: &Path, dirs_only: bool) -> AnyResult<Vec<(String, PathBuf)>> {
+    let mut entries = vec![];
+    let read_dir = rmx::std::fs::read_dir(dir)
+        .with_context(|| format!("reading {}", dir.display()))?;
+    for entry in read_dir {
+        let entry = entry?;
+        let Ok(name) = entry.file_name().into_string() else {
+            continue;
+        };
+        let path = entry.path();
+        if name.starts_with('.') || (dirs_only && !path.is_dir()) {
+            continue;
+        }
+        entries.push((name, path));
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+impl crate::Database {
+    /// Shadow the file at `path` with `text`, e.g. an unsaved editor buffer.
+    ///
+    /// If the file is loaded its module sees `text` immediately;
+    /// otherwise it will when it is loaded.
+    /// An overlay also hides a load error,
+    /// since the buffer's text is readable even if the file is not.
+    pub fn set_overlay(&mut self, path: &Path, text: String) {
+        let path = &canonical_path(path);
+        let registry = self.file_registry();
+        let mut overlays = registry.overlays(self).C();
+        overlays.insert(path.to_path_buf(), text);
+        registry.set_overlays(self).to(overlays);
+        if let Some(file) = registry.files(self).get(path).copied() {
+            self.update_source(file);
+        }
+    }
+
+    /// Remove the overlay of `path`, going back to the file on disk
+    /// as of the last load.
+    pub fn clear_overlay(&mut self, path: &Path) {
+        let path = &canonical_path(path);
+        let registry = self.file_registry();
+        let mut overlays = registry.overlays(self).C();
+        if overlays.remove(path).is_none() {
+            return;
+        }
+        registry.set_overlays(self).to(overlays);
+        if let Some(file) = registry.files(self).get(path).copied() {
+            self.update_source(file);
+        }
+    }
+
+    /// The overlay of `path`, if any.
+    pub fn overlay(&self, path: &Path) -> Option<&str> {
+        let path = &canonical_path(path);
+        let registry = FileRegistry::try_get(self)?;
+        registry.overlays(self).get(path).map(String::as_str)
+    }
+
+    fn file_registry(&self) -> FileRegistry {
+        FileRegistry::try_get(self)
+            .unwrap_or_else(|| FileRegistry::new(self, BTreeMap::new(), BTreeMap::new()))
+    }
+
+    /// Read the file at `path`, returning its source,
+    /// the same one as last time if it was loaded before.
+    ///
+    /// A file that can't be read still gets a source,
+    /// empty and with `Source::load_error` set,
+    /// so its module exists and imports of it resolve.
+    fn load_file(&mut self, path: &Path, durability: salsa::Durability) -> Source {
+        let (disk_text, load_error) = match crate::mmap::read_to_string(path) {
+            Ok(text) => (text, None),
+            Err(e) => {
+                tracing::debug!(path = %path.display(), error = %e, "unreadable module");
+                (String::new(), Some(e.to_string()))
+            }
+        };
+        let registry = self.file_registry();
+
+        let file = match registry.files(self).get(path).copied() {
+            Some(file) => {
+                if *file.disk_text(self) != disk_text {
+                    file.set_disk_text(self).to(disk_text);
+                }
+                if *file.load_error(self) != load_error {
+                    file.set_load_error(self).to(load_error);
+                }
+                file
+            }
+            None => {
+                let source = Source::builder(String::new()).durability(durability).new(self);
+                let file = LoadedFile::new(self, path.to_path_buf(), source, disk_text, load_error, durability);
+                let mut files = registry.files(self).C();
+                files.insert(path.to_path_buf(), file);
+                registry.set_files(self).to(files);
+                file
+            }
+        };
+        self.update_source(file);
+        file.source(self)
+    }
+
+    // Give the file's source the overlay, or else what was read from disk.
+    // Fields are set only if changed, so unchanged sources keep their revisions.
+    fn update_source(&mut self, file: LoadedFile) {
+        let (text, load_error) = match self.file_registry().overlays(self).get(file.path(self)) {
+            Some(text) => (text.C(), None),
+            None => (file.disk_text(self).C(), file.load_error(self).C()),
+        };
+        let source = file.source(self);
+        let durability = file.durability(self);
+        if *source.text(self) != text {
+            source.set_text(self).with_durability(durability).to(text);
+        }
+        if *source.load_error(self) != load_error {
+            source.set_load_error(self).with_durability(durability).to(load_error);
+        }
+    }
+}
+
+#[test]
+fn test_load_and_overlay() {
+    let dir = rmx::tempfile::tempdir().X();
+    let write = |path: &str, text: &str| {
+        let path = dir.path().join(path);
+        rmx::std::fs::create_dir_all(path.parent().X()).X();
+        rmx::std::fs::write(path, text).X();
+    };
+    write("sys/core/core.bct", "core");
+    write("lib/app/main.bct", "main");
+    write("lib/app/ui/window.bct", "window");
+    write("lib/app/notes.txt", "not a module");
+    write("lib/app/.hidden.bct", "hidden");
+    write("lib/.hidden/main.bct", "hidden");
+
+    let config = ConfigFile::parse("[packages]\npaths = [\"lib\"]\n").X();
+    let loader = PackageLoader::from_config(dir.path(), &config, vec![dir.path().join("sys")]);
+    let mut db = crate::Database::default();
+    let world = loader.load(&mut db).X().world;
+
+    let core = world.pkglib_system(&db)["core"];
+    assert_eq!(core.modules(&db)["core"].text(&db).text(&db), "core");
+    let app = world.pkglib_local(&db)["app"];
+    let modules: Vec<_> = app.modules(&db).keys().map(String::as_str).collect();
+    assert_eq!(modules, vec!["main", "ui/window"]);
+    assert_eq!(world.pkglib_local(&db).len(), 1);
+
+    let main_path = dir.path().join("lib/app/main.bct");
+    let main_source = app.modules(&db)["main"].text(&db);
+    db.set_overlay(&main_path, S("edited"));
+    assert_eq!(main_source.text(&db), "edited");
+    assert_eq!(db.overlay(&main_path), Some("edited"));
+
+    // Overlays survive reloading, and sources are reused.
+    write("lib/app/main.bct", "saved");
+    let world = loader.load(&mut db).X().world;
+    let app = world.pkglib_local(&db)["app"];
+    assert!(app.modules(&db)["main"].text(&db) == main_source);
+    assert_eq!(main_source.text(&db), "edited");
+
+    db.clear_overlay(&main_path);
+    assert_eq!(main_source.text(&db), "saved");
+    assert_eq!(db.overlay(&main_path), None);
+
+    // An overlay set before loading is used by the load.
+    let window_path = dir.path().join("lib/app/ui/window.bct");
+    let mut db = crate::Database::default();
+    db.set_overlay(&window_path, S("unsaved"));
+    let world = loader.load(&mut db).X().world;
+    let window = world.pkglib_local(&db)["app"].modules(&db)["ui/window"];
+    assert_eq!(window.text(&db).text(&db), "unsaved");
+}
+
+#[test]
+fn test_unreadable_module() {
+    use crate::diagnostics::{Code, import_diagnostics};
+    use crate::package2::package_world_map;
+    use crate::package_resolve2::{ImportDemandMap, package_world_graph};
+
+    #[salsa::input]
+    struct Input {
+        world: PackageWorld,
+    }
+
+    // The codes of the diagnostics of `app/main`, which imports `app/broken`.
+    #[salsa::tracked]
+    fn main_diagnostics(db: &dyn crate::Db, input: Input) -> Vec<(Code, String)> {
+        let map = package_world_map(db, input.world(db));
+        let app = map.map(db)["local"]["app"];
+        let mut demands: BTreeMap<_, _> = app.modules(db).values()
+            .map(|module| (*module, vec![]))
+            .collect();
+        demands.insert(app.modules(db)["main"], vec![(S("pkg"), S(""), S("broken"))]);
+        let demands = ImportDemandMap::new(db, demands, vec![], vec![]);
+        let graph = package_world_graph(db, map, demands);
+        import_diagnostics(db, map, graph).into_iter()
+            .map(|(_, diagnostic)| (diagnostic.code, diagnostic.message))
+            .collect()
+    }
+
+    let dir = rmx::tempfile::tempdir().X();
+    let app_dir = dir.path().join("lib/app");
+    rmx::std::fs::create_dir_all(&app_dir).X();
+    rmx::std::fs::write(app_dir.join("main.bct"), "main").X();
+    rmx::std::os::unix::fs::symlink(app_dir.join("missing"), app_dir.join("broken.bct")).X();
+    rmx::std::fs::write(app_dir.join("binary.bct"), b"\xff\xfe").X();
+
+    let loader = PackageLoader { sys_paths: vec![], local_paths: vec![dir.path().join("lib")] };
+    let mut db = crate::Database::default();
+    let world = loader.load(&mut db).X().world;
+    let app = world.pkglib_local(&db)["app"];
+    let broken = app.modules(&db)["broken"].text(&db);
+    assert_eq!(broken.text(&db), "");
+    assert!(broken.load_error(&db).is_some());
+    assert!(app.modules(&db)["binary"].text(&db).load_error(&db).is_some());
+    assert_eq!(app.modules(&db)["main"].text(&db).load_error(&db), &None);
+
+    let input = Input::new(&db, world);
+    let diagnostics = main_diagnostics(&db, input);
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].0, Code::UnreadableModule);
+    assert!(diagnostics[0].1.starts_with("import `pkg//broken` is of a module that could not be read: "));
+
+    // An overlay hides the error.
+    let broken_path = app_dir.join("broken.bct");
+    db.set_overlay(&broken_path, S("unsaved"));
+    assert_eq!(broken.load_error(&db), &None);
+    assert!(main_diagnostics(&db, input).is_empty());
+    db.clear_overlay(&broken_path);
+    assert!(broken.load_error(&db).is_some());
+
+    // Once the file is readable the next load recovers.
+    // The symlink now leads to a file, which is a new source.
+    let binary = app.modules(&db)["binary"].text(&db);
+    rmx::std::fs::write(app_dir.join("missing"), "fixed").X();
+    rmx::std::fs::write(app_dir.join("binary.bct"), "text").X();
+    let world = loader.load(&mut db).X().world;
+    let app = world.pkglib_local(&db)["app"];
+    let broken = app.modules(&db)["broken"].text(&db);
+    assert_eq!(broken.text(&db), "fixed");
+    assert_eq!(broken.load_error(&db), &None);
+    assert!(app.modules(&db)["binary"].text(&db) == binary);
+    assert_eq!(binary.text(&db), "text");
+    assert_eq!(binary.load_error(&db), &None);
+    input.set_world(&mut db).to(world);
+    assert!(main_diagnostics(&db, input).is_empty());
+}
+
+#[test]
+fn test_duplicate_paths() {
+    use rmx::std::os::unix::fs::symlink;
+
+    let dir = rmx::tempfile::tempdir().X();
+    let lib = dir.path().join("lib");
+    rmx::std::fs::create_dir_all(lib.join("app/ui")).X();
+    rmx::std::fs::write(lib.join("app/util.bct"), "util").X();
+    rmx::std::fs::write(lib.join("app/Main.bct"), "Main").X();
+    rmx::std::fs::write(lib.join("app/main.bct"), "main").X();
+    symlink(lib.join("app/util.bct"), lib.join("app/helpers.bct")).X();
+    // A loop back to the package.
+    symlink(lib.join("app"), lib.join("app/ui/app")).X();
+    // A second name for the package.
+    symlink(lib.join("app"), lib.join("alias")).X();
+
+    let loader = PackageLoader { sys_paths: vec![], local_paths: vec![lib.C()] };
+    let mut db = crate::Database::default();
+    let loaded = loader.load(&mut db).X();
+
+    let packages: Vec<_> = loaded.world.pkglib_local(&db).keys().map(String::as_str).collect();
+    assert_eq!(packages, vec!["alias"]);
+    let alias = loaded.world.pkglib_local(&db)["alias"];
+    let modules: Vec<_> = alias.modules(&db).keys().map(String::as_str).collect();
+    assert_eq!(modules, vec!["Main", "helpers"]);
+
+    let diagnostics: Vec<_> = loaded.diagnostics.iter()
+        .map(|(path, diagnostic)| {
+            assert_eq!(diagnostic.code, Code::DuplicateModulePath);
+            path.strip_prefix(&lib).X().to_str().X()
+        })
+        .collect();
+    assert_eq!(diagnostics, vec!["alias/ui/app", "alias/main.bct", "alias/util.bct", "app"]);
+
+    // An overlay through the symlink shadows the file.
+    let helpers = alias.modules(&db)["helpers"].text(&db);
+    db.set_overlay(&lib.join("app/util.bct"), S("edited"));
+    assert_eq!(helpers.text(&db), "edited");
+    assert_eq!(db.overlay(&lib.join("alias/../app/helpers.bct")), Some("edited"));
+}
+
+#[test]
+fn test_ignore_files() {
+    let dir = rmx::tempfile::tempdir().X();
+    let lib = dir.path().join("lib");
+    let write = |path: &str, text: &str| {
+        let path = lib.join(path);
+        rmx::std::fs::create_dir_all(path.parent().X()).X();
+        rmx::std::fs::write(path, text).X();
+    };
+    write(".gitignore", "scratch/\n");
+    write("scratch/main.bct", "");
+    write("app/.gitignore", "target/\n*.bct~\ngenerated/\n");
+    write("app/.bctignore", "!generated/\n");
+    write("app/main.bct", "");
+    write("app/main.bct~", "");
+    write("app/target/out.bct", "");
+    write("app/generated/api.bct", "");
+
+    let loader = PackageLoader { sys_paths: vec![], local_paths: vec![lib.C()] };
+    let mut db = crate::Database::default();
+    let world = loader.load(&mut db).X().world;
+    let packages: Vec<_> = world.pkglib_local(&db).keys().map(String::as_str).collect();
+    assert_eq!(packages, vec!["app"]);
+    let app = world.pkglib_local(&db)["app"];
+    let modules: Vec<_> = app.modules(&db).keys().map(String::as_str).collect();
+    assert_eq!(modules, vec!["generated/api", "main"]);
+
+    assert!(loader.is_module_path(&lib.join("app/main.bct")).X());
+    assert!(loader.is_module_path(&lib.join("app/new.bct")).X());
+    assert!(loader.is_module_path(&lib.join("app/generated/api.bct")).X());
+    assert!(!loader.is_module_path(&lib.join("app/target/out.bct")).X());
+    assert!(!loader.is_module_path(&lib.join("scratch/main.bct")).X());
+    assert!(!loader.is_module_path(&lib.join("app/.hidden.bct")).X());
+    assert!(!loader.is_module_path(&lib.join("app/notes.txt")).X());
+    assert!(!loader.is_module_path(&lib.join("main.bct")).X());
+    assert!(!loader.is_module_path(&dir.path().join("other/app/main.bct")).X());
+}
+
+#[test]
+fn test_invalid_names() {
+    let dir = rmx::tempfile::tempdir().X();
+    let lib = dir.path().join("lib");
+    for path in [
+        "app/main.bct",
+        "app/ui_2/window.bct",
+        "app/30s.bct",
+        "1h/main.bct",
+        "app/self.bct",
+        "app/my-module.bct",
+        "app/bad dir/x.bct",
+        "my-app/main.bct",
+        "super/main.bct",
+    ] {
+        let path = lib.join(path);
+        rmx::std::fs::create_dir_all(path.parent().X()).X();
+        rmx::std::fs::write(path, "").X();
+    }
+
+    let loader = PackageLoader { sys_paths: vec![], local_paths: vec![lib.C()] };
+    let mut db = crate::Database::default();
+    let loaded = loader.load(&mut db).X();
+    let packages: Vec<_> = loaded.world.pkglib_local(&db).keys().map(String::as_str).collect();
+    // Names shaped like durations are words, time literals being off.
+    assert_eq!(packages, vec!["1h", "app"]);
+    let app = loaded.world.pkglib_local(&db)["app"];
+    let modules: Vec<_> = app.modules(&db).keys().map(String::as_str).collect();
+    assert_eq!(modules, vec!["30s", "main", "ui_2/window"]);
+
+    let diagnostics: Vec<_> = loaded.diagnostics.iter()
+        .map(|(path, diagnostic)| {
+            assert_eq!(diagnostic.code, Code::InvalidModuleName);
+            (path.strip_prefix(&lib).X().to_str().X(), diagnostic.message.as_str())
+        })
+        .collect();
+    assert_eq!(diagnostics, vec![
+        ("app/bad dir", "module name `bad dir` is not a single word"),
+        ("app/my-module.bct", "module name `my-module` is not a single word"),
+        ("app/self.bct", "module name `self` is reserved"),
+        ("my-app", "package name `my-app` is not a single word"),
+        ("super", "package name `super` is reserved"),
+    ]);
+}
@@ -0,0 +1,258 @@
+//! Lowering of a `ModuleGraph` to the intermediate representation
+//! that backends compile from.
+//!
+//! Until there is a parser, the IR is the statements of each module
+//! with their words resolved to the statements defining them.
+//! As in `comments`, a statement is a top-level line of the brace tree
+//! that contains anything other than whitespace and comments.
+//! A statement recognized by `key_values` defines its key.
+//!
+//! Each word of a statement, including words inside its branches,
+//! resolves to, in order:
+//!
+//! - the statement itself, if the word is its key,
+//! - the last earlier statement of the module defining it,
+//! - the statement defining it in a direct dependency
+//!   of the module, if exactly one dependency defines it.
+//!
+//! Other words are free. They are not errors:
+//! they include the language's keywords, which nothing defines.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+
+use crate::bracer::TreeToken;
+use crate::diagnostics::{Diagnostic, Severity};
+use crate::lexer::{Token, TokenKind};
+use crate::module_graph::{Module, ModuleGraph, ModuleId};
+use crate::text::ByteSpan;
+
+/// The lowered modules of a world.
+#[derive(Clone, Eq, PartialEq)]
+pub struct Ir {
+    /// Modules in the graph's dependency order.
+    pub modules: Vec<ModuleIr>,
+}
+
+/// A lowered module.
+#[derive(Clone, Eq, PartialEq)]
+pub struct ModuleIr {
+    pub id: ModuleId,
+    pub statements: Vec<Statement>,
+    /// The statement defining each key the module defines.
+    ///
+    /// When a key is defined more than once, the last definition.
+    pub definitions: BTreeMap<String, usize>,
+}
+
+/// A statement of a module.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Statement {
+    /// Bytes of the module source, from the first token to the last.
+    pub span: ByteSpan,
+    /// The key the statement defines, if any.
+    pub definition: Option<String>,
+    /// Every word of the statement, in source order.
+    pub words: Vec<Word>,
+}
+
+/// A word of a statement and what it resolves to.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Word {
+    pub span: ByteSpan,
+    pub symbol: Symbol,
+}
+
+/// The statement a word resolves to.
+#[derive(Clone, Eq, PartialEq)]
+pub enum Symbol {
+    /// A statement of the same module, by index.
+    Local(usize),
+    /// A statement of another module, by index.
+    Imported(ModuleId, usize),
+    /// Nothing in scope defines the word.
+    Free,
+}
+
+impl rmx::std::fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut rmx::std::fmt::Formatter<'_>) -> rmx::std::fmt::Result {
+        match self {
+            Symbol::Local(statement) => write!(f, "Local({statement})"),
+            Symbol::Imported(_, statement) => write!(f, "Imported(_, {statement})"),
+            Symbol::Free => write!(f, "Free"),
+        }
+    }
+}
+
+/// Lower every module of `world`, in dependency order.
+///
+/// Fails with the error diagnostics of every module,
+/// i.e. those whose default severity is `Severity::Deny`,
+/// if there are any.
+/// Diagnostic spans are relative to their module's source,
+/// so messages are prefixed with the module path.
+///
+/// Panics if a module is lowered before one of its dependencies,
+/// which `ModuleGraph` rules out.
+pub fn compile_world(
+    db: &dyn crate::Db,
+    world: ModuleGraph,
+) -> Result<Ir, Vec<Diagnostic>> {
+    let _span = tracing::debug_span!("compile_world").entered();
+    let mut modules: Vec<ModuleIr> = vec![];
+    let mut module_indexes: BTreeMap<ModuleId, usize> = BTreeMap::new();
+    let mut errors = vec![];
+
+    for module in world.iter_modules(db) {
+        db.unwind_if_revision_cancelled();
+        let id = module.id(db);
+        let dependencies: Vec<&ModuleIr> = world.dependencies(db).get(&id)
+            .into_iter().flatten()
+            .map(|dependency| {
+                let index = module_indexes.get(dependency)
+                    .expect("module lowered before its dependency");
+                &modules[*index]
+            })
+            .collect();
+        let (module_ir, diagnostics) = lower_module(db, module, &dependencies);
+        errors.extend(diagnostics.into_iter().map(|diagnostic| Diagnostic {
+            message: format!("{}: {}", id.path(db), diagnostic.message),
+            ..diagnostic
+        }));
+        module_indexes.insert(id, modules.len());
+        modules.push(module_ir);
+    }
+
+    tracing::debug!(modules = modules.len(), errors = errors.len(), "compiled world");
+    match errors.is_empty() {
+        true => Ok(Ir { modules }),
+        false => Err(errors),
+    }
+}
+
+fn lower_module(
+    db: &dyn crate::Db,
+    module: Module,
+    dependencies: &[&ModuleIr],
+) -> (ModuleIr, Vec<Diagnostic>) {
+    let bracer = crate::bracer::basic_bracer(db, module.source(db));
+    let diagnostics = crate::diagnostics::bracer_diagnostics(db, bracer).into_iter()
+        .filter(|diagnostic| diagnostic.code.default_severity() == Severity::Deny)
+        .collect();
+
+    let mut statements = vec![];
+    let mut definitions: BTreeMap<String, usize> = BTreeMap::new();
+    for line in bracer.iter(db).lines() {
+        let tree_tokens: Vec<TreeToken<'_>> = line
+            .filter_map(|tree_token| tree_token.without_space(db))
+            .collect();
+        let (Some(first), Some(last)) = (tree_tokens.first(), tree_tokens.last()) else {
+            continue;
+        };
+        let span = first.text_span(db).X().merge(&last.text_span(db).X()).X().span;
+        let index = statements.len();
+        let definition = crate::key_values::line_key_value(db, tree_tokens.iter().cloned())
+            .map(|key_value| S(key_value.key.text(db).as_str(db)));
+
+        let mut tokens = vec![];
+        collect_words(db, tree_tokens, &mut tokens);
+        let words = tokens.into_iter().enumerate().map(|(position, token)| {
+            let name = token.text(db).as_str(db);
+            let symbol = match definitions.get(name) {
+                _ if position == 0 && definition.as_deref() == Some(name) => Symbol::Local(index),
+                Some(statement) => Symbol::Local(*statement),
+                None => resolve_import(dependencies, name),
+            };
+            Word { span: token.text(db).range(db), symbol }
+        }).collect();
+
+        if let Some(definition) = &definition {
+            definitions.insert(definition.C(), index);
+        }
+        statements.push(Statement { span, definition, words });
+    }
+
+    (ModuleIr { id: module.id(db), statements, definitions }, diagnostics)
+}
+
+/// The symbol of a word that the module doesn't define itself.
+fn resolve_import(
+    dependencies: &[&ModuleIr],
+    name: &str,
+) -> Symbol {
+    let mut candidates = dependencies.iter().filter_map(|dependency| {
+        let statement = dependency.definitions.get(name)?;
+        Some(Symbol::Imported(dependency.id, *statement))
+    });
+    match (candidates.next(), candidates.next()) {
+        (Some(symbol), None) => symbol,
+        _ => Symbol::Free,
+    }
+}
+
+fn collect_words<'db>(
+    db: &'db dyn crate::Db,
+    tree_tokens: impl IntoIterator<Item = TreeToken<'db>>,
+    words: &mut Vec<Token<'db>>,
+) {
+    for tree_token in tree_tokens {
+        match tree_token {
+            TreeToken::Token(token) if token.kind(db) == TokenKind::Word => words.push(token),
+            TreeToken::Token(_) => {}
+            TreeToken::Branch(_, branch_iter) => {
+                rmx::extras::recurse(|| collect_words(db, branch_iter, words));
+            }
+        }
+    }
+}
+
+#[test]
+fn test_compile_world() {
+    use crate::input::Source;
+    use crate::module_graph::ModuleGraphBuilder;
+
+    let ref db = crate::Database::default();
+    let mut builder = ModuleGraphBuilder::new(db);
+    let base = builder.add_module("sys/std/base", Source::new(db, S("one = 1\ntwo = one\n")));
+    let other = builder.add_module("sys/std/other", Source::new(db, S("two = 2\n")));
+    let app_text = "\
+// Comments are not statements.
+x = f(one, [two])
+one = x
+print { one } // trailing
+";
+    let app = builder.add_module("app/main", Source::new(db, S(app_text)));
+    builder.add_dependency(app, base);
+    builder.add_dependency(app, other);
+    let graph = builder.build();
+
+    let ir = compile_world(db, graph).ok().X();
+    let ids: Vec<_> = ir.modules.iter().map(|module| module.id).collect();
+    assert!(ids == vec![base, other, app]);
+
+    let base_ir = &ir.modules[0];
+    assert_eq!(base_ir.definitions, BTreeMap::from([(S("one"), 0), (S("two"), 1)]));
+    assert_eq!(base_ir.statements[1].words[1].symbol, Symbol::Local(0));
+
+    let app_ir = &ir.modules[2];
+    let words: Vec<Vec<_>> = app_ir.statements.iter()
+        .map(|statement| statement.words.iter().map(|word| word.symbol.C()).collect())
+        .collect();
+    assert_eq!(words, vec![
+        // `two` is defined by both dependencies, so it is free.
+        vec![Symbol::Local(0), Symbol::Free, Symbol::Imported(base, 0), Symbol::Free],
+        vec![Symbol::Local(1), Symbol::Local(0)],
+        vec![Symbol::Free, Symbol::Local(1)],
+    ]);
+    assert_eq!(app_ir.statements[0].definition.as_deref(), Some("x"));
+    assert_eq!(app_ir.statements[2].definition, None);
+    assert_eq!(&app_text[app_ir.statements[2].span.C()], "print { one }");
+
+    // Errors are collected from every module.
+    let mut builder = ModuleGraphBuilder::new(db);
+    builder.add_module("a", Source::new(db, S("f(")));
+    builder.add_module("b", Source::new(db, S("g)")));
+    let errors = compile_world(db, builder.build()).err().X();
+    let messages: Vec<_> = errors.iter().map(|error| error.message.as_str()).collect();
+    assert_eq!(messages, vec!["a: unclosed `(`", "b: unmatched `)`"]);
+}
@@ -0,0 +1,65 @@
+//! Metrics hooks for applications embedding bcts.
+//!
+//! Implement `Metrics` to forward measurements to Prometheus,
+//! OpenTelemetry, or similar, and install it with `set_metrics`.
+//! Like a logger it is global to the process.
+//! Without one installed the hooks cost an atomic load.
+//!
+//! The passes report from inside their queries,
+//! so a query reused from salsa's cache reports nothing:
+//! the measurements are of work done, not of results returned.
+//! Diagnostics are not cached and are reported each time they are collected,
+//! by `diagnostics::bracer_diagnostics` and `diagnostics::import_diagnostics`.
+
+use rmx::prelude::*;
+
+use rmx::std::sync::{Arc, RwLock};
+use rmx::std::sync::atomic::{AtomicBool, Ordering};
+use rmx::std::time::Duration;
+
+use crate::diagnostics::Code;
+
+/// Receives measurements from the passes.
+///
+/// Every method has an empty default,
+/// so implementations need only handle what they record.
+pub trait Metrics: Send + Sync {
+    /// A query ran instead of being reused, e.g. `"lex"`.
+    fn query_executed(&self, query: &'static str) { }
+
+    /// A chunk was lexed into `count` tokens.
+    fn tokens_lexed(&self, count: usize) { }
+
+    /// A diagnostic with `code` was produced.
+    fn diagnostic_emitted(&self, code: Code) { }
+
+    /// Resolving the imports of a whole world took `duration`.
+    fn world_resolved(&self, duration: Duration) { }
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static METRICS: RwLock<Option<Arc<dyn Metrics>>> = RwLock::new(None);
+
+/// Install `metrics` for the whole process, or remove it with `None`.
+pub fn set_metrics(metrics: Option<Arc<dyn Metrics>>) {
+    let enabled = metrics.is_some();
+    *METRICS.write().X() = metrics;
+    ENABLED.store(enabled, Ordering::Release);
+}
+
+/// Whether metrics are installed,
+/// for skipping work that only feeds them, like reading the clock.
+pub(crate) fn enabled() -> bool {
+    ENABLED.load(Ordering::Acquire)
+}
+
+/// Call `f` with the installed metrics, if any.
+pub(crate) fn record(f: impl FnOnce(&dyn Metrics)) {
+    if !enabled() {
+        return;
+    }
+    let metrics = METRICS.read().X().C();
+    if let Some(metrics) = metrics {
+        f(&*metrics);
+    }
+}
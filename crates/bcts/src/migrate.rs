@@ -0,0 +1,170 @@
+//! Migrating sources between language versions.
+//!
+//! Each `Migration` upgrades sources one step,
+//! from one language version to the next, see `language`,
+//! with token-level `RewriteRule`s like a renamed sigil or keyword.
+//! Migrating across several versions applies every step in order.
+//! Rewrites are `TextEdit`s, like fixes and formatting,
+//! so they can be shown with `diff::unified_diff`.
+//!
+//! Only tokens are rewritten,
+//! so words inside comments and strings are left alone.
+//! Sources are lexed with every sigil set enabled.
+
+use rmx::prelude::*;
+
+use crate::edit::TextEdit;
+use crate::input::Source;
+use crate::language::LanguageVersion;
+use crate::lexer::TokenKind;
+
+/// A token-level rewrite.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum RewriteRule {
+    /// Replace a sigil, e.g. `:-` with `<-`.
+    Sigil { from: String, to: String },
+    /// Replace a word, e.g. the keyword `import` with `use`.
+    Word { from: String, to: String },
+}
+
+impl RewriteRule {
+    pub fn sigil(from: &str, to: &str) -> RewriteRule {
+        RewriteRule::Sigil { from: S(from), to: S(to) }
+    }
+
+    pub fn word(from: &str, to: &str) -> RewriteRule {
+        RewriteRule::Word { from: S(from), to: S(to) }
+    }
+
+    /// The rewritten text of a token, if this rule applies to it.
+    fn rewrite(&self, kind: TokenKind, text: &str) -> Option<&str> {
+        match (self, kind) {
+            (RewriteRule::Sigil { from, to }, TokenKind::Sigil(_)) if from == text => Some(to),
+            (RewriteRule::Word { from, to }, TokenKind::Word) if from == text => Some(to),
+            _ => None,
+        }
+    }
+}
+
+/// The rules upgrading sources from one version to the next.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Migration {
+    pub from: LanguageVersion,
+    pub to: LanguageVersion,
+    pub rules: Vec<RewriteRule>,
+}
+
+/// Every migration step of the language, oldest first.
+pub fn migrations() -> Vec<Migration> {
+    vec![
+        // Heredocs and time literals only added syntax.
+        Migration {
+            from: LanguageVersion::V1_0,
+            to: LanguageVersion::V1_1,
+            rules: vec![],
+        },
+    ]
+}
+
+/// The rules of the steps of `registry` from `from` to `to`, in order.
+///
+/// Migrating to the same version has no rules.
+/// It is an error if the steps don't connect the versions,
+/// which includes migrating to an older version.
+pub fn migration_rules(
+    registry: &[Migration],
+    from: LanguageVersion,
+    to: LanguageVersion,
+) -> AnyResult<Vec<RewriteRule>> {
+    let mut rules = vec![];
+    let mut version = from;
+    while version != to {
+        let Some(step) = registry.iter().find(|step| step.from == version && step.to <= to) else {
+            bail!("no migration from language version {from} to {to}");
+        };
+        rules.extend(step.rules.iter().cloned());
+        version = step.to;
+    }
+    Ok(rules)
+}
+
+/// Edits rewriting the tokens of `source` with `rules`.
+///
+/// Each token is rewritten by every rule in turn,
+/// so a rule sees the text left by the rules before it.
+pub fn migration_edits(
+    db: &dyn crate::Db,
+    source: Source,
+    rules: &[RewriteRule],
+) -> Vec<TextEdit> {
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    chunk_lex.tokens(db).iter()
+        .filter_map(|token| {
+            let original = token.text(db).as_str(db);
+            let kind = token.kind(db);
+            let rewritten = rules.iter().fold(original, |text, rule| {
+                rule.rewrite(kind, text).unwrap_or(text)
+            });
+            (rewritten != original).then(|| TextEdit::replace(token.text(db).range(db), rewritten))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const V1_2: LanguageVersion = LanguageVersion { major: 1, minor: 2 };
+    const V1_3: LanguageVersion = LanguageVersion { major: 1, minor: 3 };
+
+    fn registry() -> Vec<Migration> {
+        let mut registry = migrations();
+        registry.push(Migration {
+            from: LanguageVersion::V1_1,
+            to: V1_2,
+            rules: vec![RewriteRule::sigil(":-", "<-"), RewriteRule::word("import", "use")],
+        });
+        registry.push(Migration {
+            from: V1_2,
+            to: V1_3,
+            rules: vec![RewriteRule::word("use", "include")],
+        });
+        registry
+    }
+
+    #[test]
+    fn test_migration_rules() {
+        let registry = registry();
+        let rules = |from, to| migration_rules(&registry, from, to);
+
+        assert_eq!(rules(LanguageVersion::V1_0, LanguageVersion::V1_1).X(), vec![]);
+        assert_eq!(rules(V1_2, V1_2).X(), vec![]);
+        assert_eq!(rules(LanguageVersion::V1_0, V1_3).X(), vec![
+            RewriteRule::sigil(":-", "<-"),
+            RewriteRule::word("import", "use"),
+            RewriteRule::word("use", "include"),
+        ]);
+        assert_eq!(
+            rules(V1_3, LanguageVersion::V1_0).unwrap_err().to_string(),
+            "no migration from language version 1.3 to 1.0",
+        );
+        assert!(rules(V1_2, LanguageVersion { major: 2, minor: 0 }).is_err());
+    }
+
+    #[test]
+    fn test_migration_edits() {
+        let ref db = crate::Database::default();
+        let registry = registry();
+        let migrate = |text: &str, to| {
+            let rules = migration_rules(&registry, LanguageVersion::V1_1, to).X();
+            let source = Source::new(db, S(text));
+            crate::edit::apply_edits(text, &migration_edits(db, source, &rules)).X()
+        };
+
+        let text = "import module a // import\nx :- \"import\"\nimported = y:-z\n";
+        assert_eq!(migrate(text, LanguageVersion::V1_1), text);
+        assert_eq!(migrate(text, V1_2), "use module a // import\nx <- \"import\"\nimported = y<-z\n");
+        assert_eq!(migrate(text, V1_3), "include module a // import\nx <- \"import\"\nimported = y<-z\n");
+    }
+}
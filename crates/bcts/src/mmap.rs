@@ -0,0 +1,141 @@
+//! Reading files, memory-mapped if large.
+//!
+//! With the `mmap` feature, on Unix, files of at least `MMAP_THRESHOLD`
+//! bytes are memory-mapped instead of read into a buffer,
+//! for multi-hundred-megabyte data documents.
+//! Otherwise files are read with `fs::read` or `fs::read_to_string`.
+//!
+//! A map is only lent out, to the closure passed to `with_file_bytes`,
+//! and unmapped when it returns,
+//! so no slice of it can outlive the map.
+//! Sources own their text, so whatever is kept is copied out of the map,
+//! once, while the closure runs.
+//! Copying the text out of a map is no faster than reading it,
+//! as `benches/read_sources.rs` shows;
+//! mapping pays off for callers that only look at the bytes,
+//! like checking they are UTF-8.
+//!
+//! A mapped file that is truncated by another process
+//! while the closure runs may crash the process with `SIGBUS`,
+//! and other changes to it may be seen part way.
+//! That is the price of mapping; leave the feature off
+//! where files are rewritten in place while being read.
+
+use rmx::prelude::*;
+use rmx::std::io;
+use rmx::std::path::Path;
+
+/// The size from which files are memory-mapped, with the `mmap` feature.
+pub const MMAP_THRESHOLD: u64 = 1 << 20;
+
+/// Call `f` with the bytes of the file at `path`.
+pub fn with_file_bytes<R>(path: &Path, f: impl FnOnce(&[u8]) -> R) -> io::Result<R> {
+    #[cfg(all(feature = "mmap", unix))]
+    if let Some(map) = unix::Mmap::map_large(path)? {
+        return Ok(f(map.bytes()));
+    }
+    let bytes = rmx::std::fs::read(path)?;
+    Ok(f(&bytes))
+}
+
+/// Read the file at `path` as UTF-8 text, like `fs::read_to_string`.
+pub fn read_to_string(path: &Path) -> io::Result<String> {
+    #[cfg(all(feature = "mmap", unix))]
+    if let Some(map) = unix::Mmap::map_large(path)? {
+        return match rmx::std::str::from_utf8(map.bytes()) {
+            Ok(text) => Ok(S(text)),
+            Err(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "stream did not contain valid UTF-8")),
+        };
+    }
+    rmx::std::fs::read_to_string(path)
+}
+
+#[cfg(all(feature = "mmap", unix))]
+mod unix {
+    use rmx::std::fs::File;
+    use rmx::std::io;
+    use rmx::std::os::fd::AsRawFd;
+    use rmx::std::path::Path;
+    use rmx::std::ptr;
+
+    use super::MMAP_THRESHOLD;
+
+    /// A read-only, private map of a whole file.
+    pub struct Mmap {
+        ptr: *mut libc::c_void,
+        len: usize,
+    }
+
+    impl Mmap {
+        /// Map the file at `path` if it has at least `MMAP_THRESHOLD` bytes.
+        pub fn map_large(path: &Path) -> io::Result<Option<Mmap>> {
+            let file = File::open(path)?;
+            if file.metadata()?.len() < MMAP_THRESHOLD {
+                return Ok(None);
+            }
+            Mmap::map(&file).map(Some)
+        }
+
+        /// Map all of `file`, as long as it is now.
+        pub fn map(file: &File) -> io::Result<Mmap> {
+            let len = usize::try_from(file.metadata()?.len())
+                .map_err(|_| io::Error::new(io::ErrorKind::OutOfMemory, "file too large to map"))?;
+            if len == 0 {
+                // Empty maps are invalid.
+                return Ok(Mmap { ptr: ptr::null_mut(), len });
+            }
+            // SAFETY: a fresh private mapping aliases no Rust memory,
+            // and the file descriptor is valid for the call.
+            // The map stays valid after the file is closed.
+            let ptr = unsafe {
+                libc::mmap(ptr::null_mut(), len, libc::PROT_READ, libc::MAP_PRIVATE, file.as_raw_fd(), 0)
+            };
+            if ptr == libc::MAP_FAILED {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(Mmap { ptr, len })
+        }
+
+        pub fn bytes(&self) -> &[u8] {
+            if self.len == 0 {
+                return &[];
+            }
+            // SAFETY: the map is `len` readable bytes until dropped,
+            // which the borrow of `self` outlives.
+            // See the module docs for files changed while mapped.
+            unsafe { rmx::std::slice::from_raw_parts(self.ptr.cast::<u8>(), self.len) }
+        }
+    }
+
+    impl Drop for Mmap {
+        fn drop(&mut self) {
+            if self.len != 0 {
+                // SAFETY: `ptr` and `len` are a map made by `map`,
+                // and no borrow of it outlives `self`.
+                unsafe {
+                    libc::munmap(self.ptr, self.len);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_with_file_bytes() {
+    let dir = rmx::tempfile::tempdir().X();
+    let small = dir.path().join("small.bct");
+    let large = dir.path().join("large.bct");
+    let empty = dir.path().join("empty.bct");
+    let invalid = dir.path().join("invalid.bct");
+    let large_text = "a = 1\n".repeat(usize::try_from(MMAP_THRESHOLD).X() / 6 + 1);
+    rmx::std::fs::write(&small, "a = 1").X();
+    rmx::std::fs::write(&large, &large_text).X();
+    rmx::std::fs::write(&empty, "").X();
+    rmx::std::fs::write(&invalid, b"a = \xff").X();
+
+    assert_eq!(with_file_bytes(&small, <[u8]>::len).X(), 5);
+    assert_eq!(read_to_string(&large).X(), large_text);
+    assert_eq!(read_to_string(&empty).X(), "");
+    assert_eq!(read_to_string(&invalid).unwrap_err().kind(), io::ErrorKind::InvalidData);
+    assert_eq!(read_to_string(&dir.path().join("missing.bct")).unwrap_err().kind(), io::ErrorKind::NotFound);
+}
@@ -0,0 +1,186 @@
+//! Recognition of module attribute lines.
+//!
+//! Like `key_values`, this is a shallow analysis over the lines
+//! of a module's brace tree, usable before a real parser exists.
+//! A top-level line is an attribute if, ignoring whitespace and comments,
+//! it has one of these shapes:
+//!
+//! - `attr name`
+//! - `attr name "value"`
+//!
+//! where `name` is a single word and `value` a string literal.
+//! Lines with any other shape are ignored,
+//! as are values with invalid escapes.
+//!
+//! Some attributes are reported where the module is imported,
+//! by `diagnostics::import_diagnostics`:
+//!
+//! - `attr deprecated "use pkg/new"`, as `DeprecatedImport`.
+//! - `attr experimental`, as `ExperimentalImport`.
+//!
+//! The value of either, if any, is added to the message,
+//! and the diagnostic points at the import's line, see `import_lines`.
+//! A `deprecated` value of the form `use <path>` names a replacement,
+//! which `fixes::deprecation_fixes` writes in place of the import.
+//!
+//! `attr language "1.1"` declares the language version a module needs,
+//! checked by `language::language_diagnostics`.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+
+use crate::bracer::TreeToken;
+use crate::lexer::TokenKind;
+use crate::package2::PackageModule;
+
+/// The attribute marking a module as deprecated.
+pub const DEPRECATED: &str = "deprecated";
+/// The attribute marking a module as experimental.
+pub const EXPERIMENTAL: &str = "experimental";
+
+/// The attributes of a module, by name, with their values.
+///
+/// If a name appears twice the later line wins.
+#[salsa::tracked(returns(ref))]
+pub fn module_attrs(
+    db: &dyn crate::Db,
+    package_module: PackageModule,
+) -> BTreeMap<String, Option<String>> {
+    let bracer = crate::bracer::basic_bracer(db, package_module.text(db));
+    bracer.iter(db).lines()
+        .filter_map(|line| line_attr(db, line))
+        .collect()
+}
+
+/// The replacement path named by a `deprecated` attribute's value,
+/// e.g. `sys/core/new` in `use sys/core/new`.
+pub fn deprecated_replacement(value: &str) -> Option<&str> {
+    let path = value.strip_prefix("use ")?.trim();
+    if path.is_empty() || path.contains(char::is_whitespace) {
+        return None;
+    }
+    Some(path)
+}
+
+/// Recognize a single line as an attribute.
+pub fn line_attr<'db>(
+    db: &'db dyn crate::Db,
+    line: impl Iterator<Item = TreeToken<'db>>,
+) -> Option<(String, Option<String>)> {
+    let tokens: Vec<TreeToken<'db>> = line
+        .filter_map(|tree_token| tree_token.without_space(db))
+        .collect();
+    let token = |index: usize| match tokens.get(index) {
+        Some(TreeToken::Token(token)) => Some(*token),
+        _ => None,
+    };
+
+    if token(0)?.word_str(db)? != "attr" {
+        return None;
+    }
+    let name = token(1)?.word_str(db)?;
+    let value = match tokens.len() {
+        2 => None,
+        3 => {
+            let literal = token(2)?;
+            if literal.kind(db) != TokenKind::String {
+                return None;
+            }
+            let quoted = literal.text(db).as_str(db);
+            let value = match crate::source_map::parse_heredoc(quoted) {
+                Some(Ok(heredoc)) => S(&quoted[heredoc.content]),
+                _ => {
+                    let contents = quoted.get(1..quoted.len().checked_sub(1)?)?;
+                    crate::escapes::process_escape_sequences(contents).ok()?
+                }
+            };
+            Some(value)
+        }
+        _ => return None,
+    };
+    Some((S(name), value))
+}
+
+#[cfg(test)]
+fn dbgattrs(s: &str) -> Vec<(String, Option<String>)> {
+    let ref db = crate::Database::default();
+    let source = crate::input::Source::new(db, S(s));
+    let module = PackageModule::new(db, S("m"), source);
+    module_attrs(db, module).iter()
+        .map(|(name, value)| (name.C(), value.C()))
+        .collect()
+}
+
+#[test]
+fn test_module_attrs() {
+    let attr = |name: &str, value: Option<&str>| (S(name), value.map(S));
+
+    assert_eq!(dbgattrs(""), vec![]);
+    assert_eq!(dbgattrs("attr experimental"), vec![attr("experimental", None)]);
+    assert_eq!(
+        dbgattrs("// old\nattr deprecated \"use \\\"pkg/new\\\"\" // why\nx = 1\n"),
+        vec![attr("deprecated", Some("use \"pkg/new\""))],
+    );
+    assert_eq!(
+        dbgattrs("attr a \"1\"\nattr b\nattr a \"2\"\n"),
+        vec![attr("a", Some("2")), attr("b", None)],
+    );
+
+    // Not attribute shapes.
+    assert_eq!(dbgattrs("attr"), vec![]);
+    assert_eq!(dbgattrs("attr \"x\""), vec![]);
+    assert_eq!(dbgattrs("attr x y"), vec![]);
+    assert_eq!(dbgattrs("attr x \"y\" \"z\""), vec![]);
+    assert_eq!(dbgattrs("attr x \"\\q\""), vec![]);
+    assert_eq!(dbgattrs("(attr x)"), vec![]);
+    assert_eq!(dbgattrs("y attr x"), vec![]);
+
+    assert_eq!(deprecated_replacement("use sys/core/new"), Some("sys/core/new"));
+    assert_eq!(deprecated_replacement("use  pkg/new "), Some("pkg/new"));
+    assert_eq!(deprecated_replacement("use"), None);
+    assert_eq!(deprecated_replacement("use pkg/a or pkg/b"), None);
+    assert_eq!(deprecated_replacement("going away"), None);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn attr_diagnostics(
+    db: &dyn crate::Db,
+    fixture: crate::world_fixture::WorldFixture,
+) -> Vec<(crate::diagnostics::Code, String)> {
+    let world = crate::world_fixture::fixture_world(db, fixture).X();
+    let graph = crate::package_resolve2::package_world_graph(
+        db,
+        world.package_world_map(db),
+        world.import_demand_map(db),
+    );
+    crate::diagnostics::import_diagnostics(db, world.package_world_map(db), graph).into_iter()
+        .map(|(_, diagnostic)| (diagnostic.code, diagnostic.message))
+        .collect()
+}
+
+#[test]
+fn test_attr_diagnostics() {
+    use crate::diagnostics::Code;
+
+    let ref db = crate::Database::default();
+    let fixture = crate::world_fixture::WorldFixture::new(db, S("\
+//@ package sys/core
+//@ module old
+attr deprecated \"use sys/core/new\"
+//@ module new
+attr experimental
+//@ module plain
+attr other
+
+//@ package local/app
+//@ module main
+//@ import sys core old
+//@ import sys core new
+//@ import sys core plain
+"));
+    assert_eq!(attr_diagnostics(db, fixture), vec![
+        (Code::ExperimentalImport, S("import `sys/core/new` is of an experimental module")),
+        (Code::DeprecatedImport, S("import `sys/core/old` is of a deprecated module: use sys/core/new")),
+    ]);
+}
@@ -5,6 +5,8 @@
 
 use rmx::prelude::*;
 use rmx::std::collections::{BTreeMap, BTreeSet};
+use rmx::std::iter::Copied;
+use rmx::std::slice;
 use crate::input::Source;
 
 /// Opaque module identifier.
@@ -53,11 +55,41 @@ impl ModuleGraph {
     }
 
     /// Iterate modules in dependency order.
-    pub fn iter_modules<'db>(&self, db: &'db dyn salsa::Database) -> impl Iterator<Item = Module> + 'db {
-        self.modules(db).iter().copied()
+    pub fn iter_modules<'db>(&self, db: &'db dyn salsa::Database) -> ModuleIter<'db> {
+        ModuleIter {
+            inner: self.modules(db).iter().copied(),
+        }
+    }
+}
+
+/// Iterator over the modules of a `ModuleGraph` in dependency order.
+///
+/// Created by `ModuleGraph::iter_modules`.
+#[derive(Clone)]
+pub struct ModuleIter<'db> {
+    inner: Copied<slice::Iter<'db, Module>>,
+}
+
+impl<'db> Iterator for ModuleIter<'db> {
+    type Item = Module;
+
+    fn next(&mut self) -> Option<Module> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
     }
 }
 
+impl<'db> DoubleEndedIterator for ModuleIter<'db> {
+    fn next_back(&mut self) -> Option<Module> {
+        self.inner.next_back()
+    }
+}
+
+impl<'db> ExactSizeIterator for ModuleIter<'db> {}
+
 /// Builder for constructing a ModuleGraph.
 pub struct ModuleGraphBuilder<'db> {
     db: &'db dyn salsa::Database,
@@ -138,4 +170,22 @@ mod tests {
         let math_deps = graph.dependencies(&db).get(&math).unwrap();
         assert!(math_deps.contains(&base));
     }
+
+    #[test]
+    fn test_iter_modules() {
+        let db = crate::Database::default();
+        let mut builder = ModuleGraphBuilder::new(&db);
+        let base = builder.add_module("sys/std/base", Source::new(&db, S("")));
+        let math = builder.add_module("sys/std/math", Source::new(&db, S("")));
+        let graph = builder.build();
+
+        let iter = graph.iter_modules(&db);
+        assert_eq!(iter.len(), 2);
+
+        let ids: Vec<_> = iter.C().map(|module| module.id(&db)).collect();
+        assert!(ids == vec![base, math]);
+
+        let ids: Vec<_> = iter.rev().map(|module| module.id(&db)).collect();
+        assert!(ids == vec![math, base]);
+    }
 }
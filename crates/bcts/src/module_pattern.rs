@@ -0,0 +1,121 @@
+//! Finding modules by their paths.
+//!
+//! A module's path is `<import_space>/<package>/<module>`,
+//! as in `graph_export`, e.g. `sys/core/io` or `local/app/ui/window`.
+//! Patterns match the whole path, either as a glob,
+//! where `*` matches across `/` so `sys/*` is every system module,
+//! or as a regular expression.
+
+use rmx::prelude::*;
+use rmx::glob::{MatchOptions, Pattern};
+use rmx::regex::Regex;
+
+use crate::package_resolve2::{PackageWorldMap, PackageWorldRecord};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: false,
+    require_literal_leading_dot: false,
+};
+
+/// A pattern over module paths, see the module docs.
+#[derive(Clone, Debug)]
+pub enum ModulePattern {
+    Glob(Pattern),
+    Regex(Regex),
+}
+
+impl ModulePattern {
+    pub fn glob(pattern: &str) -> AnyResult<ModulePattern> {
+        let pattern = Pattern::new(pattern)
+            .with_context(|| format!("invalid module glob `{pattern}`"))?;
+        Ok(ModulePattern::Glob(pattern))
+    }
+
+    /// The regex must match the whole path,
+    /// so `sys/` alone matches nothing.
+    pub fn regex(pattern: &str) -> AnyResult<ModulePattern> {
+        let regex = Regex::new(&format!("^(?:{pattern})$"))
+            .with_context(|| format!("invalid module regex `{pattern}`"))?;
+        Ok(ModulePattern::Regex(regex))
+    }
+
+    pub fn matches(&self, path: &str) -> bool {
+        match self {
+            ModulePattern::Glob(pattern) => pattern.matches_with(path, MATCH_OPTIONS),
+            ModulePattern::Regex(regex) => regex.is_match(path),
+        }
+    }
+}
+
+/// The path of a module, as matched by `ModulePattern`.
+pub fn module_path(db: &dyn crate::Db, record: &PackageWorldRecord<'_>) -> String {
+    let module_name = record.package_module.name(db);
+    format!("{}/{}/{module_name}", record.import_space, record.package_name)
+}
+
+/// Every module in the world whose path matches `pattern`,
+/// in `flatten_iter` order.
+pub fn find_modules<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    pattern: &ModulePattern,
+) -> Vec<PackageWorldRecord<'db>> {
+    package_world_map.flatten_iter(db)
+        .filter(|record| pattern.matches(&module_path(db, record)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world_builder::WorldBuilder;
+
+    #[salsa::tracked]
+    fn found(db: &dyn crate::Db, pattern: String, regex: bool) -> Vec<String> {
+        let map = WorldBuilder::new()
+            .package("sys/core")
+            .module("io", "")
+            .module("io/file", "")
+            .package("sys/alloc")
+            .module("vec", "")
+            .package("local/app")
+            .module("main", "")
+            .module("ui/window", "")
+            .build_map(db);
+        let pattern = match regex {
+            false => ModulePattern::glob(&pattern),
+            true => ModulePattern::regex(&pattern),
+        };
+        find_modules(db, map, &pattern.X()).iter()
+            .map(|record| module_path(db, record))
+            .collect()
+    }
+
+    #[test]
+    fn test_find_modules() {
+        let ref db = crate::Database::default();
+        let glob = |pattern: &str| found(db, S(pattern), false);
+        let regex = |pattern: &str| found(db, S(pattern), true);
+
+        assert_eq!(glob("sys/core/*"), vec![S("sys/core/io"), S("sys/core/io/file")]);
+        assert_eq!(glob("sys/*"), vec![S("sys/alloc/vec"), S("sys/core/io"), S("sys/core/io/file")]);
+        assert_eq!(glob("*/main"), vec![S("local/app/main")]);
+        assert_eq!(glob("local/app/ui/?indow"), vec![S("local/app/ui/window")]);
+        assert_eq!(glob("sys/core"), Vec::<String>::new());
+
+        assert_eq!(regex("sys/core/[^/]+"), vec![S("sys/core/io")]);
+        assert_eq!(regex("local/.*|sys/alloc/vec"), vec![
+            S("local/app/main"),
+            S("local/app/ui/window"),
+            S("sys/alloc/vec"),
+        ]);
+        assert_eq!(regex("sys/"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        assert!(ModulePattern::glob("sys/[").is_err());
+        assert!(ModulePattern::regex("sys/(").is_err());
+    }
+}
@@ -0,0 +1,169 @@
+//! Mutating valid sources into broken ones.
+//!
+//! The seed cases in `tests/bracer_panic_tests.rs` were made
+//! by breaking valid sources one token at a time.
+//! This makes more the same way, for panic-regression tests
+//! and for fuzzers outside the crate.
+//!
+//! Each `Mutation` makes one edit, chosen by a seed,
+//! so a broken source is reproduced from its original, mutation and seed.
+//! Mutations find their targets by lexing the original with `lexer::lex_chunk`.
+
+use rmx::prelude::*;
+use rmx::rand::{Rng as _, SeedableRng as _};
+use rmx::rand_pcg::Pcg64Mcg;
+
+use crate::edit::{TextEdit, apply_edits};
+use crate::input::Source;
+use crate::lexer::{Sigil, TokenKind};
+use crate::text::ByteSpan;
+
+/// A way to break a source.
+#[derive(Copy, Clone, Debug, Hash)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(enum_iterator::Sequence)]
+pub enum Mutation {
+    /// Delete an open brace, `(`, `[`, `{` or `<`.
+    DeleteOpeningBracket,
+    /// Insert a copy of a close brace anywhere.
+    ExtraClosingBracket,
+    /// Cut the end off a string literal, including its close quote.
+    TruncateString,
+}
+
+impl Mutation {
+    pub fn all() -> impl Iterator<Item = Mutation> {
+        enum_iterator::all::<Mutation>()
+    }
+
+    /// The name, e.g. `"DeleteOpeningBracket"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mutation::DeleteOpeningBracket => "DeleteOpeningBracket",
+            Mutation::ExtraClosingBracket => "ExtraClosingBracket",
+            Mutation::TruncateString => "TruncateString",
+        }
+    }
+}
+
+/// A broken source, and how it was made.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Mutant {
+    pub mutation: Mutation,
+    pub seed: u64,
+    pub text: String,
+}
+
+/// Break `text` with `mutation`, choosing where by `seed`.
+///
+/// Returns `None` if `text` has nothing to mutate,
+/// e.g. no strings for `TruncateString`.
+pub fn mutate(
+    db: &dyn crate::Db,
+    text: &str,
+    mutation: Mutation,
+    seed: u64,
+) -> Option<String> {
+    let mut rng = Pcg64Mcg::seed_from_u64(seed);
+    let source = Source::new(db, S(text));
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    let tokens: Vec<(TokenKind, ByteSpan)> = chunk_lex.tokens(db).iter()
+        .map(|token| (token.kind(db), token.text(db).range(db)))
+        .collect();
+    let mut choose = |targets: Vec<(TokenKind, ByteSpan)>| match targets.len() {
+        0 => None,
+        len => Some(targets[rng.random_range(0..len)].C()),
+    };
+
+    let edit = match mutation {
+        Mutation::DeleteOpeningBracket => {
+            let (_, range) = choose(tokens.into_iter()
+                .filter(|(kind, _)| matches!(kind, TokenKind::Sigil(
+                    Sigil::ParenOpen | Sigil::BracketOpen | Sigil::BraceOpen | Sigil::AngleOpen
+                )))
+                .collect())?;
+            TextEdit::delete(range)
+        }
+        Mutation::ExtraClosingBracket => {
+            let (kind, _) = choose(tokens.into_iter()
+                .filter(|(kind, _)| matches!(kind, TokenKind::Sigil(sigil) if sigil.is_close_sigil()))
+                .collect())?;
+            let TokenKind::Sigil(sigil) = kind else { bug!() };
+            let boundaries: Vec<usize> = text.char_indices()
+                .map(|(offset, _)| offset)
+                .chain([text.len()])
+                .collect();
+            let offset = boundaries[rng.random_range(0..boundaries.len())];
+            TextEdit::insert(offset, sigil.as_str())
+        }
+        Mutation::TruncateString => {
+            let (_, range) = choose(tokens.into_iter()
+                .filter(|(kind, _)| matches!(kind, TokenKind::String | TokenKind::ByteString))
+                .collect())?;
+            // Keep at least the first char, the quote or heredoc `<`.
+            let cuts: Vec<usize> = text[range.C()].char_indices()
+                .skip(1)
+                .map(|(offset, _)| range.start.checked_add(offset).X())
+                .collect();
+            let cut = cuts[rng.random_range(0..cuts.len())];
+            TextEdit::delete(cut..range.end)
+        }
+    };
+    Some(apply_edits(text, &[edit]).X())
+}
+
+/// Every mutation of `text` with each seed in `seeds`,
+/// skipping mutations with nothing to mutate.
+pub fn mutants(
+    db: &dyn crate::Db,
+    text: &str,
+    seeds: rmx::std::ops::Range<u64>,
+) -> Vec<Mutant> {
+    seeds
+        .flat_map(|seed| Mutation::all().map(move |mutation| (mutation, seed)))
+        .filter_map(|(mutation, seed)| {
+            let text = mutate(db, text, mutation, seed)?;
+            Some(Mutant { mutation, seed, text })
+        })
+        .collect()
+}
+
+#[test]
+fn test_mutate() {
+    let ref db = crate::Database::default();
+    let text = "f(a, [b]) = \"str\" // (\n";
+
+    for seed in 0..20 {
+        let deleted = mutate(db, text, Mutation::DeleteOpeningBracket, seed).X();
+        assert!(deleted == "fa, [b]) = \"str\" // (\n" || deleted == "f(a, b]) = \"str\" // (\n", "{deleted:?}");
+
+        let extra = mutate(db, text, Mutation::ExtraClosingBracket, seed).X();
+        assert_eq!(extra.len(), text.len() + 1);
+        let removed = extra.char_indices()
+            .any(|(offset, ch)| matches!(ch, ')' | ']') && format!("{}{}", &extra[..offset], &extra[offset + 1..]) == text);
+        assert!(removed, "{extra:?}");
+
+        let truncated = mutate(db, text, Mutation::TruncateString, seed).X();
+        assert!(truncated.starts_with("f(a, [b]) = \""), "{truncated:?}");
+        assert!(truncated.ends_with(" // (\n"), "{truncated:?}");
+        assert!(truncated.len() < text.len(), "{truncated:?}");
+        assert!(!truncated.contains("str\""), "{truncated:?}");
+    }
+
+    // Seeded, so reproducible.
+    assert_eq!(
+        mutate(db, text, Mutation::ExtraClosingBracket, 7),
+        mutate(db, text, Mutation::ExtraClosingBracket, 7),
+    );
+
+    assert_eq!(mutate(db, "a = 1", Mutation::DeleteOpeningBracket, 0), None);
+    assert_eq!(mutate(db, "a = 1", Mutation::ExtraClosingBracket, 0), None);
+    assert_eq!(mutate(db, "a = 1", Mutation::TruncateString, 0), None);
+    // Heredocs are off in the basic config.
+    assert_eq!(mutate(db, "a = <<END\nx\nEND\n", Mutation::TruncateString, 0), None);
+
+    let mutants = mutants(db, "(\"a\")", 0..3);
+    assert_eq!(mutants.len(), 9);
+    assert_eq!(mutants[0], Mutant { mutation: Mutation::DeleteOpeningBracket, seed: 0, text: S("\"a\")") });
+}
@@ -0,0 +1,360 @@
+//! Parsing of number literals.
+//!
+//! An int is decimal digits, or hexadecimal, octal or binary digits
+//! after `0x`, `0o` or `0b`, with an optional `-`.
+//! A float is decimal digits with an optional fraction and exponent.
+//! Either may contain `_` separators and end with a type suffix,
+//! like `255u8`, `-0x80_i8` or `1.5f32`.
+//! Decimal digits with a float suffix, like `1f32`, are a float.
+//! The floats without digits are written `inf`, `-inf` and `NaN`.
+//!
+//! The text parsed is the source text of a span,
+//! which may be several tokens, as the lexer splits `1.5` at the `.`.
+//! Errors are reported at the part of the span at fault,
+//! e.g. the invalid digit or the suffix.
+
+use rmx::prelude::*;
+use rmx::std::fmt;
+use rmx::std::ops::RangeInclusive;
+
+use crate::text::ByteSpan;
+
+/// The signedness and width of an int type.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct IntType {
+    pub signed: bool,
+    pub bits: u32,
+}
+
+impl IntType {
+    /// The int type named like `i8` or `u64`.
+    pub fn from_name(name: &str) -> Option<IntType> {
+        let (signed, bits) = match name.split_at_checked(1)? {
+            ("i", bits) => (true, bits),
+            ("u", bits) => (false, bits),
+            _ => return None,
+        };
+        let bits = match bits {
+            "8" => 8,
+            "16" => 16,
+            "32" => 32,
+            "64" => 64,
+            _ => return None,
+        };
+        Some(IntType { signed, bits })
+    }
+
+    /// The values of the type.
+    pub fn range(&self) -> RangeInclusive<i128> {
+        match self.signed {
+            true => {
+                let half = 1_i128 << self.bits.checked_sub(1).X();
+                half.checked_neg().X()..=half.checked_sub(1).X()
+            }
+            false => 0..=(1_i128 << self.bits).checked_sub(1).X(),
+        }
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let prefix = match self.signed {
+            true => 'i',
+            false => 'u',
+        };
+        write!(f, "{prefix}{}", self.bits)
+    }
+}
+
+/// The value of a number literal, with the type of its suffix.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Number {
+    Int { value: i128, suffix: Option<IntType> },
+    /// A float, with the bits of its suffix.
+    Float { value: f64, suffix: Option<u32> },
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct NumberError {
+    pub span: ByteSpan,
+    pub kind: NumberErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum NumberErrorKind {
+    /// The text doesn't start with a digit, after any `-`.
+    NotANumber,
+    /// Nothing but separators after a radix prefix, like `0x_`.
+    NoDigits,
+    InvalidDigit { digit: char, radix: u32 },
+    InvalidFloat,
+    /// The int doesn't fit its type.
+    ///
+    /// An int without a suffix must fit `i64` if negative,
+    /// and `u64` otherwise.
+    IntOverflow { ty: IntType },
+    FloatOverflow { bits: u32 },
+    /// The suffix is not the type the number must have.
+    SuffixMismatch { suffix: String, expected: String },
+    /// A float where an int must be.
+    NotAnInt,
+}
+
+impl fmt::Display for NumberError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            NumberErrorKind::NotANumber => write!(f, "expected a number"),
+            NumberErrorKind::NoDigits => write!(f, "expected digits"),
+            NumberErrorKind::InvalidDigit { digit, radix } => {
+                write!(f, "invalid digit `{digit}` for a base {radix} number")
+            }
+            NumberErrorKind::InvalidFloat => write!(f, "invalid float"),
+            NumberErrorKind::IntOverflow { ty } => write!(f, "out of range for `{ty}`"),
+            NumberErrorKind::FloatOverflow { bits } => write!(f, "out of range for `f{bits}`"),
+            NumberErrorKind::SuffixMismatch { suffix, expected } => {
+                write!(f, "suffix `{suffix}` doesn't match the expected `{expected}`")
+            }
+            NumberErrorKind::NotAnInt => write!(f, "expected an int, found a float"),
+        }
+    }
+}
+
+impl rmx::std::error::Error for NumberError {}
+
+const SUFFIXES: &[&str] = &["i8", "i16", "i32", "i64", "u8", "u16", "u32", "u64", "f32", "f64"];
+
+/// Parse the number written at `span` as `text`.
+pub fn parse_number(text: &str, span: &ByteSpan) -> Result<Number, NumberError> {
+    let error = |range: ByteSpan, kind| NumberError {
+        span: span.start.checked_add(range.start).X()..span.start.checked_add(range.end).X(),
+        kind,
+    };
+    let negative = text.starts_with('-');
+    let unsigned = &text[usize::from(negative)..];
+    match unsigned {
+        "inf" => {
+            let value = if negative { f64::NEG_INFINITY } else { f64::INFINITY };
+            return Ok(Number::Float { value, suffix: None });
+        }
+        "NaN" if !negative => return Ok(Number::Float { value: f64::NAN, suffix: None }),
+        _ => {}
+    }
+    if !unsigned.starts_with(|ch: char| ch.is_ascii_digit()) {
+        return Err(error(0..text.len(), NumberErrorKind::NotANumber));
+    }
+    let (radix, prefix) = match unsigned.get(..2) {
+        Some("0x") => (16, 2),
+        Some("0o") => (8, 2),
+        Some("0b") => (2, 2),
+        _ => (10, 0),
+    };
+    let digits_start = usize::from(negative).checked_add(prefix).X();
+    let rest = &text[digits_start..];
+    // A hex digit can't start an int suffix, but can end with `f32`.
+    let suffix = SUFFIXES.iter().copied()
+        .filter(|suffix| radix == 10 || !suffix.starts_with('f'))
+        .find(|suffix| rest.ends_with(suffix));
+    let digits_end = text.len().checked_sub(suffix.map_or(0, str::len)).X();
+    let digits = &text[digits_start..digits_end];
+    if digits.chars().all(|ch| ch == '_') {
+        return Err(error(0..text.len(), NumberErrorKind::NoDigits));
+    }
+
+    let is_float = suffix.is_some_and(|suffix| suffix.starts_with('f'))
+        || (radix == 10 && digits.contains(['.', 'e', 'E']));
+    if is_float {
+        let bits = match suffix {
+            Some("f32") => 32,
+            _ => 64,
+        };
+        let value = parse_float_digits(digits.replace('_', ""), bits)
+            .ok_or_else(|| error(digits_start..digits_end, NumberErrorKind::InvalidFloat))?;
+        if value.is_infinite() {
+            return Err(error(0..text.len(), NumberErrorKind::FloatOverflow { bits }));
+        }
+        let value = if negative { -value } else { value };
+        return Ok(Number::Float { value, suffix: suffix.map(|_| bits) });
+    }
+
+    let suffix = suffix.map(|suffix| IntType::from_name(suffix).X());
+    let ty = suffix.unwrap_or(IntType { signed: negative, bits: 64 });
+    let overflow = || error(0..text.len(), NumberErrorKind::IntOverflow { ty });
+    let mut value: i128 = 0;
+    for (offset, ch) in digits.char_indices() {
+        if ch == '_' {
+            continue;
+        }
+        let Some(digit) = ch.to_digit(radix) else {
+            let start = digits_start.checked_add(offset).X();
+            let end = start.checked_add(ch.len_utf8()).X();
+            return Err(error(start..end, NumberErrorKind::InvalidDigit { digit: ch, radix }));
+        };
+        value = value.checked_mul(radix.into())
+            .and_then(|value| value.checked_add(digit.into()))
+            .ok_or_else(overflow)?;
+    }
+    let value = if negative { value.checked_neg().X() } else { value };
+    if !ty.range().contains(&value) {
+        return Err(overflow());
+    }
+    Ok(Number::Int { value, suffix })
+}
+
+fn parse_float_digits(digits: String, bits: u32) -> Option<f64> {
+    match bits {
+        32 => digits.parse::<f32>().ok().map(f64::from),
+        _ => digits.parse::<f64>().ok(),
+    }
+}
+
+/// Parse the number written at `span` as `text` as an int of type `ty`.
+pub fn parse_int(text: &str, span: &ByteSpan, ty: IntType) -> Result<i128, NumberError> {
+    let (value, suffix) = match parse_number(text, span)? {
+        Number::Int { value, suffix } => (value, suffix),
+        Number::Float { .. } => {
+            return Err(NumberError { span: span.C(), kind: NumberErrorKind::NotAnInt });
+        }
+    };
+    if let Some(suffix) = suffix && suffix != ty {
+        return Err(suffix_mismatch(text, span, suffix.to_string(), ty.to_string()));
+    }
+    if !ty.range().contains(&value) {
+        return Err(NumberError { span: span.C(), kind: NumberErrorKind::IntOverflow { ty } });
+    }
+    Ok(value)
+}
+
+/// Parse the number written at `span` as `text` as a float of `bits` bits.
+///
+/// An int without a suffix is a float too.
+pub fn parse_float(text: &str, span: &ByteSpan, bits: u32) -> Result<f64, NumberError> {
+    match parse_number(text, span)? {
+        Number::Int { value, suffix: None } => {
+            // Rounds to the nearest float, as a decimal literal would.
+            match bits {
+                32 => Ok(f64::from(value as f32)),
+                _ => Ok(value as f64),
+            }
+        }
+        Number::Int { suffix: Some(suffix), .. } => {
+            Err(suffix_mismatch(text, span, suffix.to_string(), format!("f{bits}")))
+        }
+        Number::Float { suffix: Some(suffix), .. } if suffix != bits => {
+            Err(suffix_mismatch(text, span, format!("f{suffix}"), format!("f{bits}")))
+        }
+        // A float parsed as an `f64` must be rounded to an `f32`.
+        Number::Float { value, suffix: None } if bits == 32 && value.is_finite() => {
+            let unsigned = text.strip_prefix('-').unwrap_or(text);
+            let value = parse_float_digits(unsigned.replace('_', ""), 32).X();
+            if value.is_infinite() {
+                return Err(NumberError { span: span.C(), kind: NumberErrorKind::FloatOverflow { bits } });
+            }
+            Ok(if text.starts_with('-') { -value } else { value })
+        }
+        Number::Float { value, .. } => Ok(value),
+    }
+}
+
+fn suffix_mismatch(text: &str, span: &ByteSpan, suffix: String, expected: String) -> NumberError {
+    let start = span.start.checked_add(text.len()).X().checked_sub(suffix.len()).X();
+    NumberError {
+        span: start..span.end,
+        kind: NumberErrorKind::SuffixMismatch { suffix, expected },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(text: &str) -> ByteSpan {
+        10..text.len().checked_add(10).X()
+    }
+
+    fn number(text: &str) -> Result<Number, NumberError> {
+        parse_number(text, &at(text))
+    }
+
+    fn int(value: i128, suffix: Option<&str>) -> Number {
+        Number::Int { value, suffix: suffix.map(|suffix| IntType::from_name(suffix).unwrap()) }
+    }
+
+    fn error(span: ByteSpan, kind: NumberErrorKind) -> NumberError {
+        NumberError { span, kind }
+    }
+
+    #[test]
+    fn test_ints() {
+        assert_eq!(number("0"), Ok(int(0, None)));
+        assert_eq!(number("1_000"), Ok(int(1000, None)));
+        assert_eq!(number("-12"), Ok(int(-12, None)));
+        assert_eq!(number("0xff"), Ok(int(255, None)));
+        assert_eq!(number("0xFF_u8"), Ok(int(255, Some("u8"))));
+        assert_eq!(number("-0x80i8"), Ok(int(-128, Some("i8"))));
+        assert_eq!(number("0o17"), Ok(int(15, None)));
+        assert_eq!(number("0b1010_1010"), Ok(int(170, None)));
+        assert_eq!(number("255u8"), Ok(int(255, Some("u8"))));
+        assert_eq!(number("18446744073709551615"), Ok(int(u64::MAX.into(), None)));
+        assert_eq!(number("-9223372036854775808"), Ok(int(i64::MIN.into(), None)));
+        // `f32` is hex digits, not a suffix.
+        assert_eq!(number("0x1f32"), Ok(int(0x1f32, None)));
+    }
+
+    #[test]
+    fn test_floats() {
+        assert_eq!(number("1.5"), Ok(Number::Float { value: 1.5, suffix: None }));
+        assert_eq!(number("-2_0.25"), Ok(Number::Float { value: -20.25, suffix: None }));
+        assert_eq!(number("1e3"), Ok(Number::Float { value: 1000.0, suffix: None }));
+        assert_eq!(number("1.5f32"), Ok(Number::Float { value: 1.5, suffix: Some(32) }));
+        assert_eq!(number("7f64"), Ok(Number::Float { value: 7.0, suffix: Some(64) }));
+        assert_eq!(number("-inf"), Ok(Number::Float { value: f64::NEG_INFINITY, suffix: None }));
+        assert!(matches!(number("NaN"), Ok(Number::Float { value, .. }) if value.is_nan()));
+    }
+
+    #[test]
+    fn test_errors() {
+        use NumberErrorKind::*;
+
+        assert_eq!(number("x"), Err(error(10..11, NotANumber)));
+        assert_eq!(number("-"), Err(error(10..11, NotANumber)));
+        assert_eq!(number("0x_"), Err(error(10..13, NoDigits)));
+        assert_eq!(number("0b102"), Err(error(14..15, InvalidDigit { digit: '2', radix: 2 })));
+        assert_eq!(number("12q4"), Err(error(12..13, InvalidDigit { digit: 'q', radix: 10 })));
+        assert_eq!(number("0x1.5"), Err(error(13..14, InvalidDigit { digit: '.', radix: 16 })));
+        assert_eq!(number("1.2.3"), Err(error(10..15, InvalidFloat)));
+        assert_eq!(number("256u8"), Err(error(10..15, IntOverflow { ty: IntType { signed: false, bits: 8 } })));
+        assert_eq!(number("-1u8"), Err(error(10..14, IntOverflow { ty: IntType { signed: false, bits: 8 } })));
+        assert_eq!(
+            number("18446744073709551616"),
+            Err(error(10..30, IntOverflow { ty: IntType { signed: false, bits: 64 } })),
+        );
+        assert_eq!(
+            number(&"9".repeat(50)),
+            Err(error(10..60, IntOverflow { ty: IntType { signed: false, bits: 64 } })),
+        );
+        assert_eq!(number("1e39f32"), Err(error(10..17, FloatOverflow { bits: 32 })));
+    }
+
+    #[test]
+    fn test_typed() {
+        use NumberErrorKind::*;
+        let u8 = IntType { signed: false, bits: 8 };
+
+        assert_eq!(parse_int("255", &at("255"), u8), Ok(255));
+        assert_eq!(parse_int("256", &at("256"), u8), Err(error(10..13, IntOverflow { ty: u8 })));
+        assert_eq!(parse_int("0xffu8", &at("0xffu8"), u8), Ok(255));
+        let mismatch = SuffixMismatch { suffix: S("i8"), expected: S("u8") };
+        assert_eq!(parse_int("1i8", &at("1i8"), u8), Err(error(11..13, mismatch)));
+        assert_eq!(parse_int("1.0", &at("1.0"), u8), Err(error(10..13, NotAnInt)));
+
+        assert_eq!(parse_float("2", &at("2"), 32), Ok(2.0));
+        assert_eq!(parse_float("0.1", &at("0.1"), 32), Ok(f64::from(0.1_f32)));
+        assert_eq!(parse_float("1e39", &at("1e39"), 32), Err(error(10..14, FloatOverflow { bits: 32 })));
+        assert_eq!(parse_float("1e39", &at("1e39"), 64), Ok(1e39));
+        assert_eq!(parse_float("inf", &at("inf"), 32), Ok(f64::INFINITY));
+        let mismatch = SuffixMismatch { suffix: S("f64"), expected: S("f32") };
+        assert_eq!(parse_float("1.5f64", &at("1.5f64"), 32), Err(error(13..16, mismatch)));
+        let mismatch = SuffixMismatch { suffix: S("u8"), expected: S("f64") };
+        assert_eq!(parse_float("1u8", &at("1u8"), 64), Err(error(11..13, mismatch)));
+    }
+}
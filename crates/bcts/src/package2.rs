@@ -1,8 +1,9 @@
 use rmx::prelude::*;
 use rmx::std::collections::BTreeMap;
+use salsa::Setter as _;
 
 use crate::input::Source;
-use crate::package_resolve2::PackageWorldMap;
+use crate::package_resolve2::{ImportSpace, PackageWorldMap};
 
 pub type PackageName = String;
 pub type ModuleName = String;
@@ -13,6 +14,22 @@ pub struct Package {
     pub name: PackageName,
     #[returns(ref)]
     pub modules: BTreeMap<ModuleName, PackageModule>,
+    /// Import spaces this package sees under another name.
+    ///
+    /// Maps the import space named in the package's imports
+    /// to the import space searched instead,
+    /// e.g. `sys` to `vendor` to build against vendored replacements
+    /// of system packages without editing the package's modules.
+    ///
+    /// Aliases named by one of `loader::RESERVED_NAMES` are ignored.
+    #[default]
+    #[returns(ref)]
+    pub import_space_aliases: BTreeMap<ImportSpace, ImportSpace>,
+    /// The module run when the package is run as a program,
+    /// or `None` for a library.
+    #[default]
+    #[returns(ref)]
+    pub main_module: Option<ModuleName>,
 }
 
 #[salsa::input]
@@ -21,6 +38,24 @@ pub struct PackageModule {
     #[returns(ref)]
     pub name: ModuleName,
     pub text: Source,
+    #[default]
+    pub visibility: Visibility,
+    /// Don't import the world's prelude, see `ImportDemandMap::prelude`.
+    #[default]
+    pub no_prelude: bool,
+}
+
+/// Who may import a module.
+#[derive(Copy, Clone, Debug, Default, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Visibility {
+    /// Importable from any package.
+    #[default]
+    Public,
+    /// Importable only from modules in the same package.
+    Package,
 }
 
 /// A package world containing system and local package libraries.
@@ -32,6 +67,101 @@ pub struct PackageWorld {
     pub pkglib_local: BTreeMap<PackageName, Package>,
 }
 
+/// The durability to give inputs in `import_space`.
+///
+/// System packages change rarely, e.g. when the toolchain is upgraded,
+/// while user code changes on every keystroke.
+/// With system inputs at high durability salsa can reuse queries
+/// that read only system packages without revalidating them
+/// after a low durability input changes.
+pub fn import_space_durability(import_space: &str) -> salsa::Durability {
+    match import_space {
+        "sys" => salsa::Durability::HIGH,
+        _ => salsa::Durability::LOW,
+    }
+}
+
+impl PackageWorld {
+    /// Put `package` in place of the package named `name` in `import_space`,
+    /// or add it if there is none.
+    ///
+    /// An existing package is kept and given the fields of `package`,
+    /// and its modules of the same names are kept and given their texts,
+    /// setting only what changed.
+    /// So the world's package maps are untouched
+    /// and only queries reading the replaced package are invalidated.
+    /// Modules only in `package` are added as they are.
+    pub fn replace_package(
+        self,
+        db: &mut dyn crate::Db,
+        import_space: &str,
+        name: &str,
+        package: Package,
+    ) -> AnyResult<()> {
+        let packages = match import_space {
+            "sys" => self.pkglib_system(db),
+            "local" => self.pkglib_local(db),
+            _ => bail!("unknown import space `{import_space}`"),
+        };
+        let durability = import_space_durability(import_space);
+
+        let Some(existing) = packages.get(name).copied() else {
+            let mut packages = packages.C();
+            packages.insert(S(name), package);
+            match import_space {
+                "sys" => self.set_pkglib_system(db).with_durability(durability).to(packages),
+                _ => self.set_pkglib_local(db).with_durability(durability).to(packages),
+            };
+            return Ok(());
+        };
+        if existing == package {
+            return Ok(());
+        }
+
+        let mut modules = BTreeMap::new();
+        for (module_name, module) in package.modules(db).C() {
+            let Some(kept) = existing.modules(db).get(&module_name).copied() else {
+                modules.insert(module_name, module);
+                continue;
+            };
+            let (source, kept_source) = (module.text(db), kept.text(db));
+            if source.text(db) != kept_source.text(db) {
+                let text = source.text(db).C();
+                kept_source.set_text(db).with_durability(durability).to(text);
+            }
+            if source.load_error(db) != kept_source.load_error(db) {
+                let load_error = source.load_error(db).C();
+                kept_source.set_load_error(db).with_durability(durability).to(load_error);
+            }
+            let visibility = module.visibility(db);
+            if visibility != kept.visibility(db) {
+                kept.set_visibility(db).with_durability(durability).to(visibility);
+            }
+            let no_prelude = module.no_prelude(db);
+            if no_prelude != kept.no_prelude(db) {
+                kept.set_no_prelude(db).with_durability(durability).to(no_prelude);
+            }
+            modules.insert(module_name, kept);
+        }
+        if *existing.modules(db) != modules {
+            existing.set_modules(db).with_durability(durability).to(modules);
+        }
+        if existing.name(db) != package.name(db) {
+            let name = package.name(db).C();
+            existing.set_name(db).with_durability(durability).to(name);
+        }
+        if existing.import_space_aliases(db) != package.import_space_aliases(db) {
+            let aliases = package.import_space_aliases(db).C();
+            existing.set_import_space_aliases(db).with_durability(durability).to(aliases);
+        }
+        if existing.main_module(db) != package.main_module(db) {
+            let main_module = package.main_module(db).C();
+            existing.set_main_module(db).with_durability(durability).to(main_module);
+        }
+        Ok(())
+    }
+}
+
 /// Create a PackageWorldMap from a PackageWorld.
 #[salsa::tracked]
 pub fn package_world_map(
@@ -49,3 +179,175 @@ pub fn package_world_map(
     )
 }
 
+
+/// A module that can be run as a program.
+#[derive(Clone, Eq, PartialEq, Hash, salsa::Update)]
+pub struct EntryPoint {
+    pub import_space: ImportSpace,
+    pub package: Package,
+    pub module: PackageModule,
+}
+
+/// Why a package's `main_module` is not a valid entry point.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, salsa::Update)]
+pub enum EntryPointError {
+    /// The package has no module with that name.
+    Missing,
+    /// The module has `Visibility::Package`,
+    /// so it cannot be reached from outside the package.
+    Private,
+}
+
+impl Package {
+    /// The package's entry point module, if it names one.
+    pub fn entry_point(self, db: &dyn crate::Db) -> Option<Result<PackageModule, EntryPointError>> {
+        let name = self.main_module(db).as_ref()?;
+        let Some(module) = self.modules(db).get(name).copied() else {
+            return Some(Err(EntryPointError::Missing));
+        };
+        Some(match module.visibility(db) {
+            Visibility::Public => Ok(module),
+            Visibility::Package => Err(EntryPointError::Private),
+        })
+    }
+}
+
+/// The valid entry points of every package in the world,
+/// system packages first, then by package name.
+///
+/// Invalid ones are reported by `diagnostics::entry_point_diagnostics`.
+#[salsa::tracked(returns(ref))]
+pub fn entry_points(
+    db: &dyn crate::Db,
+    package_world: PackageWorld,
+) -> Vec<EntryPoint> {
+    let pkglibs = [
+        ("sys", package_world.pkglib_system(db)),
+        ("local", package_world.pkglib_local(db)),
+    ];
+    pkglibs.into_iter()
+        .flat_map(|(import_space, packages)| {
+            packages.values().filter_map(move |package| {
+                let module = package.entry_point(db)?.ok()?;
+                Some(EntryPoint {
+                    import_space: S(import_space),
+                    package: *package,
+                    module,
+                })
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_entry_points() {
+    use crate::diagnostics::{Code, entry_point_diagnostics};
+    use crate::world_builder::WorldBuilder;
+
+    let ref db = crate::Database::default();
+    let world = WorldBuilder::new()
+        .package("sys/core")
+        .module("core", "")
+        .package("local/app")
+        .main_module("main")
+        .module("main", "")
+        .package("local/typo")
+        .main_module("mian")
+        .module("main", "")
+        .package("local/hidden")
+        .main_module("main")
+        .module("main", "")
+        .visibility(Visibility::Package)
+        .build(db);
+
+    let app = world.pkglib_local(db)["app"];
+    let entry_points = entry_points(db, world);
+    assert!(entry_points == &vec![EntryPoint {
+        import_space: S("local"),
+        package: app,
+        module: app.modules(db)["main"],
+    }]);
+    assert!(world.pkglib_system(db)["core"].entry_point(db).is_none());
+
+    let codes: Vec<_> = entry_point_diagnostics(db, world).iter()
+        .map(|(package, diagnostic)| (package.name(db).as_str(), diagnostic.code))
+        .collect();
+    assert_eq!(codes, vec![
+        ("hidden", Code::PrivateEntryPoint),
+        ("typo", Code::MissingEntryPoint),
+    ]);
+}
+
+#[test]
+fn test_replace_package() {
+    use crate::package_resolve2::{ImportDemandMap, ResolvedPackageModule, package_world_graph};
+    use crate::world_builder::WorldBuilder;
+
+    // The modules whose import of `sys/core/core` is unresolved.
+    // Every module outside `sys` has one.
+    #[salsa::tracked]
+    fn unresolved_imports(db: &dyn crate::Db, world: PackageWorld) -> Vec<String> {
+        let map = package_world_map(db, world);
+        let demands = map.flatten_iter(db)
+            .map(|record| {
+                let imports = match record.import_space {
+                    "sys" => vec![],
+                    _ => vec![(S("sys"), S("core"), S("core"))],
+                };
+                (record.package_module, imports)
+            })
+            .collect();
+        let demands = ImportDemandMap::new(db, demands, vec![], vec![]);
+        let graph = package_world_graph(db, map, demands);
+        graph.map(db).iter()
+            .filter(|(_, imports)| {
+                imports.iter().any(|(_, resolved)| *resolved == ResolvedPackageModule::Unresolved)
+            })
+            .map(|(module, _)| module.name(db).C())
+            .sorted()
+            .collect()
+    }
+
+    let (mut db, executed) = crate::event_recording_database(crate::executed_query);
+    let world = WorldBuilder::new()
+        .package("sys/core")
+        .module("core", "")
+        .package("local/app")
+        .module("main", "main")
+        .package("local/lib")
+        .module("lib", "")
+        .module("util", "")
+        .build(&db);
+    assert!(unresolved_imports(&db, world).is_empty());
+    let main = world.pkglib_local(&db)["app"].modules(&db)["main"];
+
+    // A new `app` that looks for system packages in a missing import space.
+    let source = Source::new(&db, S("changed"));
+    let package = Package::builder(S("app"), BTreeMap::from([(S("main"), PackageModule::new(&db, S("main"), source))]))
+        .import_space_aliases(BTreeMap::from([(S("sys"), S("vendor"))]))
+        .new(&db);
+    executed.lock().X().clear();
+    world.replace_package(&mut db, "local", "app", package).X();
+    assert_eq!(unresolved_imports(&db, world), vec![S("main")]);
+
+    // The old package and module were updated in place.
+    let app = world.pkglib_local(&db)["app"];
+    assert!(app.modules(&db)["main"] == main);
+    assert_eq!(main.text(&db).text(&db), "changed");
+    assert_eq!(app.import_space_aliases(&db).len(), 1);
+
+    // Only `app` was resolved again.
+    let executed = executed.lock().X().C();
+    let count = |query: &str| executed.iter().filter(|key| key.starts_with(query)).count();
+    assert_eq!(count("package_world_map("), 0);
+    assert_eq!(count("module_imports("), 1);
+
+    // A package not in the world is added.
+    let source = Source::new(&db, S(""));
+    let package = Package::new(&db, S("extra"), BTreeMap::from([(S("extra"), PackageModule::new(&db, S("extra"), source))]));
+    world.replace_package(&mut db, "local", "extra", package).X();
+    assert!(world.pkglib_local(&db)["extra"] == package);
+    assert_eq!(unresolved_imports(&db, world), vec![S("main")]);
+
+    assert!(world.replace_package(&mut db, "vendor", "app", package).is_err());
+}
@@ -44,6 +44,8 @@ pub struct PackageWorldModuleGraphWithErrors<'db> {
 #[derive(Eq, PartialEq, Ord, PartialOrd)]
 pub enum ValidationError {
     CycleDetected,
+    /// A module of the world has no entry in the `ImportDemandMap`.
+    MissingImportDemands,
 }
 
 #[salsa::tracked]
@@ -61,7 +63,9 @@ pub fn resolve_package_world<'db>(
             package_module,
         } = package_world_record;
         let mut module_deps = BTreeSet::new();
-        let import_demands = &import_demand_map.map(db)[&package_module];
+        let Some(import_demands) = import_demand_map.map(db).get(&package_module) else {
+            return PackageWorldModuleGraphWithErrors::new(db, Err(ValidationError::MissingImportDemands));
+        };
         for import_demand in import_demands.iter() {
             let module_world_map = module_world_map(db, package_world_map, package);
             match lookup_import(
@@ -206,12 +210,8 @@ struct ModuleWorldMap<'db> {
     map: BTreeMap<ImportSpace, BTreeMap<ModuleAlias, PackageModule>>,
 }
 
-pub struct PackageWorldRecord<'db> {
-    pub import_space: &'db str,
-    pub package_name: &'db str,
-    pub package: Package,
-    pub package_module: PackageModule,
-}
+pub type PackageWorldRecord<'db> = crate::world_iter::PackageWorldRecord<'db, Package>;
+pub type PackageWorldIter<'db> = crate::world_iter::PackageWorldIter<'db, Package>;
 
 impl<'db> PackageWorldMap<'db> {
     fn module_map(
@@ -232,25 +232,15 @@ impl<'db> PackageWorldMap<'db> {
             }).collect()
     }
 
+    /// Iterate every module of every package in the world.
+    ///
+    /// Records are ordered by import space, then package name,
+    /// then module name, comparing names bytewise.
     pub fn flatten_iter(
         &self,
         db: &'db dyn crate::Db,
-    ) -> impl Iterator<Item = PackageWorldRecord<'db>> {
-        let map = self.map(db);
-        map.iter()
-            .flat_map(move |(import_space, packages)| {
-                packages.iter().flat_map(move |(package_name, package)| {
-                    let modules = package.modules(db);
-                    modules.iter().map(move |(module_name, package_module)| {
-                        PackageWorldRecord {
-                            import_space,
-                            package_name,
-                            package: *package,
-                            package_module: *package_module,
-                        }
-                    })
-                })
-            })
+    ) -> PackageWorldIter<'db> {
+        PackageWorldIter::new(db, self.map(db))
     }
 }
 
@@ -507,3 +497,34 @@ fn test_cycles() {
 
     assert!(resolved.result(db).is_err());
 }
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_missing_demands<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let test_input = test_input_cycle(db);
+    let package_world_map = test_input.package_world_map(db);
+    let module_a = package_world_map.map(db)["sys"]["core"].modules(db)["a"];
+    // Demands for `a` only, as if `b` were added to the world after.
+    let import_demand_map = ImportDemandMap::new(
+        db,
+        BTreeMap::from([
+            (module_a, vec![(S("pkg"), S("b"))]),
+        ]),
+    );
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_missing_import_demands() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_missing_demands(db);
+    let resolved = resolve_package_world(
+        db,
+        test_input.package_world_map(db),
+        test_input.import_demand_map(db),
+    );
+
+    assert!(matches!(resolved.result(db), Err(ValidationError::MissingImportDemands)));
+}
@@ -1,9 +1,10 @@
 use rmx::prelude::*;
 use rmx::std::collections::{BTreeSet, BTreeMap};
+use rmx::std::fmt;
 use rmx::std::path::PathBuf;
 
-use crate::text::SubText;
-use crate::package2::{self as package, PackageName, Package, PackageModule, ModuleName};
+use crate::text::{ByteSpan, SubText};
+use crate::package2::{self as package, PackageName, Package, PackageModule, ModuleName, Visibility};
 
 pub type ImportSpace = String;
 pub type PackageAlias = String;
@@ -21,19 +22,108 @@ pub type ImportDemand = (ImportSpace, PackageAlias, ModuleAlias);
 pub struct ImportDemandMap<'db> {
     #[returns(ref)]
     pub map: BTreeMap<PackageModule, Vec<ImportDemand>>,
+    /// Imports added to every module, resolved as if each module
+    /// had demanded them itself.
+    ///
+    /// Modules with `PackageModule::no_prelude` set don't get them,
+    /// and a prelude module doesn't import itself.
+    #[returns(ref)]
+    pub prelude: Vec<ImportDemand>,
+    /// Import spaces searched before others, in order,
+    /// see `ImportSpaceOverride`.
+    #[returns(ref)]
+    pub overrides: Vec<ImportSpaceOverride>,
+}
+
+/// A rule that an import of a path in `overrides`
+/// resolves in `import_space` instead, if the path is there,
+/// e.g. `local` over `sys`, to patch a system package with a local copy.
+///
+/// Rules are tried in order and the first whose import space
+/// has the path wins; they don't chain.
+/// Where the path is also in `overrides` the import shadows it,
+/// which `import_diagnostics` reports as `ShadowedImport`.
+/// The `pkg`, `self` and `super` import spaces are never overridden
+/// and never override.
+#[derive(Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub struct ImportSpaceOverride {
+    pub import_space: ImportSpace,
+    pub overrides: ImportSpace,
 }
 
+/// An import resolved in an overriding import space,
+/// see `ImportSpaceOverride`.
+#[derive(Clone, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub struct ShadowedImport {
+    pub import_demand: ImportDemand,
+    /// The modules the import matched in the overriding import space.
+    pub used: Vec<PackageModule>,
+    /// The modules it would have matched without the override.
+    pub shadowed: Vec<PackageModule>,
+}
+
+/// The resolved imports of every module in a world.
+///
+/// The map is ordered by `PackageModule`, whose order is the order
+/// the modules were created in, not their names.
+/// The imports of each module are ordered by import demand,
+/// comparing strings bytewise.
+/// Use `imports_in_world_order` for an order that depends only on names.
 #[salsa::tracked]
 pub struct PackageWorldModuleGraph<'db> {
     #[returns(ref)]
     pub map: BTreeMap<PackageModule, BTreeSet<(ImportDemand, ResolvedPackageModule)>>,
+    /// The imports of each module that shadowed another module,
+    /// for modules with any.
+    #[returns(ref)]
+    pub shadowed: BTreeMap<PackageModule, Vec<ShadowedImport>>,
+    /// Modules with no entry in the `ImportDemandMap`,
+    /// e.g. added to the world without updating the demands.
+    /// They are resolved as demanding nothing, getting only the prelude.
+    #[returns(ref)]
+    pub missing_demands: BTreeSet<PackageModule>,
 }
 
-#[derive(Copy, Clone, Hash, salsa::Update)]
+#[derive(Clone, Hash, salsa::Update)]
 #[derive(Eq, PartialEq, Ord, PartialOrd)]
 pub enum ResolvedPackageModule {
     Resolved(PackageModule),
     Unresolved,
+    /// More than one module matches the import.
+    ///
+    /// The candidates are sorted and there are always at least two.
+    Ambiguous(Vec<PackageModule>, Option<ImportSpan>),
+    /// The import matches a module that the importer may not import.
+    Forbidden(PackageModule, ForbiddenReason, Option<ImportSpan>),
+}
+
+/// Why a resolved import is not allowed.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub enum ForbiddenReason {
+    /// The module has `Visibility::Package` and the importer
+    /// is in a different package.
+    Private,
+}
+
+/// Where a failed import is written in the importing module,
+/// the path of its import line, see `import_lines`.
+///
+/// `None` in a resolution for prelude imports,
+/// and for demands the module's text has no import line for.
+#[derive(Copy, Clone, Debug, Hash, salsa::Update)]
+#[derive(Eq, PartialEq, Ord, PartialOrd)]
+pub struct ImportSpan {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl ImportSpan {
+    pub fn byte_span(&self) -> ByteSpan {
+        self.start..self.end
+    }
 }
 
 #[salsa::tracked]
@@ -45,76 +135,312 @@ pub struct PackageWorldModuleGraphWithErrors<'db> {
 #[derive(Eq, PartialEq, Ord, PartialOrd)]
 pub enum ValidationError {
     CycleDetected,
+    /// Some modules have no import demands,
+    /// see `PackageWorldModuleGraph::missing_demands`.
+    MissingImportDemands,
 }
 
+/// How `PackageWorldMap::merge` settles a package name
+/// in the same import space of both worlds.
+#[derive(Copy, Clone, Debug, Hash)]
+#[derive(Eq, PartialEq)]
+pub enum MergePolicy {
+    /// Keep the package of the world merged into.
+    PreferSelf,
+    /// Take the package of the other world,
+    /// e.g. a project's copy of an SDK package.
+    PreferOther,
+    /// Fail with a `MergeConflict`.
+    Reject,
+}
+
+/// The packages both worlds of a `MergePolicy::Reject` merge have,
+/// as import space and package name, in `flatten_iter` order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MergeConflict {
+    pub packages: Vec<(ImportSpace, PackageName)>,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let packages = self.packages.iter()
+            .map(|(import_space, package_name)| format!("`{import_space}/{package_name}`"))
+            .join(", ");
+        write!(f, "both worlds have packages {packages}")
+    }
+}
+
+impl rmx::std::error::Error for MergeConflict { }
+
 #[salsa::tracked]
 pub fn resolve_package_world<'db>(
     db: &'db dyn crate::Db,
     package_world_map: PackageWorldMap<'db>,
     import_demand_map: ImportDemandMap<'db>,
 ) -> PackageWorldModuleGraphWithErrors<'db> {
+    let graph = package_world_graph(db, package_world_map, import_demand_map);
+    let result = validate_graph(db, graph).map(|()| graph);
+    PackageWorldModuleGraphWithErrors::new(
+        db,
+        result,
+    )
+}
+
+/// Resolve every import demand without validating the resulting graph.
+///
+/// Unlike `resolve_package_world` this returns the graph even when
+/// it contains cycles, for consumers that want to report on them.
+#[salsa::tracked]
+pub fn package_world_graph<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+) -> PackageWorldModuleGraph<'db> {
+    let _span = tracing::debug_span!("resolve").entered();
+    crate::metrics::record(|metrics| metrics.query_executed("resolve"));
+    let start = crate::metrics::enabled().then(rmx::std::time::Instant::now);
     let mut module_edges: BTreeMap<PackageModule, BTreeSet<(ImportDemand, ResolvedPackageModule)>> = default();
+    let mut shadowed: BTreeMap<PackageModule, Vec<ShadowedImport>> = default();
+    let mut missing_demands: BTreeSet<PackageModule> = default();
     for package_world_record in package_world_map.flatten_iter(db) {
+        db.unwind_if_revision_cancelled();
         let PackageWorldRecord {
             import_space,
             package_name,
             package,
             package_module,
         } = package_world_record;
-        let mut module_deps = BTreeSet::new();
-        let import_demands = &import_demand_map.map(db)[&package_module];
-        for import_demand in import_demands.iter() {
-            let module_world_map = module_world_map(db, package_world_map, package);
-            match lookup_import(
-                db,
-                module_world_map,
-                import_demand,
-            ) {
-                Some(import_package_module) => {
-                    module_deps.insert((
-                        import_demand.C(), ResolvedPackageModule::Resolved(import_package_module),
-                    ));
-                },
-                None => {
-                    module_deps.insert((
-                        import_demand.C(), ResolvedPackageModule::Unresolved,
-                    ));
-                }
-            }
+        let module_deps = module_imports(
+            db,
+            package_world_map,
+            import_demand_map,
+            package,
+            package_module,
+        );
+        module_edges.insert(package_module, module_deps.imports.C());
+        if !module_deps.shadowed.is_empty() {
+            shadowed.insert(package_module, module_deps.shadowed.C());
+        }
+        if module_deps.missing_demands {
+            missing_demands.insert(package_module);
         }
-        module_edges.insert(package_module, module_deps);
     }
-    let graph = PackageWorldModuleGraph::new(db, module_edges);
-    let result = validate_graph(db, graph).map(|()| graph);
-    PackageWorldModuleGraphWithErrors::new(
-        db,
-        result,
-    )
+    tracing::debug!(modules = module_edges.len(), "resolved world");
+    if let Some(start) = start {
+        crate::metrics::record(|metrics| metrics.world_resolved(start.elapsed()));
+    }
+    PackageWorldModuleGraph::new(db, module_edges, shadowed, missing_demands)
 }
 
+/// Resolve the import demands of one module, without resolving the world.
+///
+/// This is for callers interested in a few modules,
+/// like a language server with one file open:
+/// it resolves only `package_module`'s imports,
+/// against an index of module names shared by the whole world.
+/// `package_world_graph` is built from the same per-module queries,
+/// so resolving modules one at a time first,
+/// e.g. to report progress, does the graph's work in advance.
+///
+/// The imports are ordered as in `PackageWorldModuleGraph`.
+///
+/// Returns `None` if `package_module` is not in `package_world_map`.
+pub fn resolve_module_imports<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+    package_module: PackageModule,
+) -> Option<Vec<(ImportDemand, ResolvedPackageModule)>> {
+    let package = *module_packages(db, package_world_map).get(&package_module)?;
+    let imports = module_imports(db, package_world_map, import_demand_map, package, package_module)
+        .imports.iter().cloned().collect();
+    Some(imports)
+}
+
+/// The resolved imports of one module.
+#[derive(Clone, Hash, salsa::Update)]
+#[derive(Eq, PartialEq)]
+struct ModuleImports {
+    imports: BTreeSet<(ImportDemand, ResolvedPackageModule)>,
+    shadowed: Vec<ShadowedImport>,
+    /// Whether the module has no entry in the `ImportDemandMap`.
+    missing_demands: bool,
+}
+
+#[salsa::tracked(returns(ref))]
+fn module_imports<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+    package: Package,
+    package_module: PackageModule,
+) -> ModuleImports {
+    crate::metrics::record(|metrics| metrics.query_executed("resolve_module"));
+    let mut module_deps = BTreeSet::new();
+    let mut shadowed = vec![];
+    let demanded = import_demand_map.map(db).get(&package_module);
+    let import_demands = demanded.into_iter().flatten()
+        .map(|import_demand| (import_demand, false));
+    let prelude = match package_module.no_prelude(db) {
+        true => &[][..],
+        false => import_demand_map.prelude(db).as_slice(),
+    };
+    let prelude = prelude.iter().map(|import_demand| (import_demand, true));
+    for (import_demand, from_prelude) in import_demands.chain(prelude) {
+        db.unwind_if_revision_cancelled();
+        let module_world_map = module_world_map(db, package_world_map, package);
+        let (candidates, shadowed_candidates) = lookup_import(
+            db,
+            module_world_map,
+            import_demand_map.overrides(db),
+            package_module,
+            import_demand,
+        );
+        if from_prelude && candidates == [package_module] {
+            continue;
+        }
+        if !shadowed_candidates.is_empty() {
+            shadowed.push(ShadowedImport {
+                import_demand: import_demand.C(),
+                used: candidates.C(),
+                shadowed: shadowed_candidates,
+            });
+        }
+        let span = || match from_prelude {
+            true => None,
+            false => crate::import_lines::import_demand_span(db, package_module, import_demand)
+                .map(|span| ImportSpan { start: span.start, end: span.end }),
+        };
+        let resolved = match candidates.as_slice() {
+            [] => ResolvedPackageModule::Unresolved,
+            [import_package_module] => {
+                check_visibility(db, package, *import_package_module, span)
+            }
+            _ => ResolvedPackageModule::Ambiguous(candidates, span()),
+        };
+        module_deps.insert((import_demand.C(), resolved));
+    }
+    tracing::trace!(
+        module = package_module.name(db).as_str(),
+        imports = module_deps.len(),
+        "resolved module",
+    );
+    ModuleImports { imports: module_deps, shadowed, missing_demands: demanded.is_none() }
+}
+
+/// Find every module matching an import demand, sorted,
+/// and those it shadows through `overrides`.
+///
+/// The module alias is canonicalized first.
+/// In the `self` and `super` import spaces it is relative to
+/// the directory of `importer`, or that directory's parent,
+/// and is looked up among the modules of the importer's package.
 fn lookup_import<'db>(
     db: &'db dyn crate::Db,
     module_world_map: ModuleWorldMap,
+    overrides: &[ImportSpaceOverride],
+    importer: PackageModule,
     import_demand: &ImportDemand,
-) -> Option<PackageModule> {
+) -> (Vec<PackageModule>, Vec<PackageModule>) {
     let import_space = &import_demand.0;
     let package_alias = &import_demand.1;
     let module_alias = &import_demand.2;
-    module_world_map.map(db).get(import_space)
-        .and_then(|modules| {
-            if import_space == "pkg" {
-                modules.get(module_alias).copied()
+    let relative_to = match import_space.as_str() {
+        "self" => Some("."),
+        "super" => Some(".."),
+        _ => None,
+    };
+    let (import_space, module_alias) = match relative_to {
+        Some(relative_to) => {
+            let importer_dir = importer.name(db).rsplit_once('/')
+                .map(|(dir, _)| dir)
+                .unwrap_or("");
+            ("pkg", format!("{importer_dir}/{relative_to}/{module_alias}"))
+        }
+        None => (import_space.as_str(), module_alias.C()),
+    };
+    let Some(module_alias) = canonical_module_path(&module_alias) else {
+        return (vec![], vec![]);
+    };
+    let lookup = |import_space: &str| {
+        module_world_map.map(db).get(import_space)
+            .and_then(|modules| {
+                if import_space == "pkg" {
+                    modules.get(&module_alias).cloned()
+                } else {
+                    let full_path = format!("{}/{}", package_alias, module_alias);
+                    modules.get(&full_path).cloned()
+                }
+            })
+            .unwrap_or_default()
+    };
+    let candidates = lookup(import_space);
+    let is_package_space = |import_space: &str| ["pkg", "self", "super"].contains(&import_space);
+    if is_package_space(import_space) {
+        return (candidates, vec![]);
+    }
+    let overriding = overrides.iter()
+        .filter(|rule| rule.overrides == import_space && !is_package_space(&rule.import_space))
+        .map(|rule| lookup(&rule.import_space))
+        .find(|overriding| !overriding.is_empty());
+    match overriding {
+        Some(overriding) => (overriding, candidates),
+        None => (candidates, vec![]),
+    }
+}
+
+/// Normalize a `/`-separated module path.
+///
+/// Empty and `.` segments are removed,
+/// and `..` removes the segment before it.
+/// Returns `None` if `..` would leave the root,
+/// or if no segments remain.
+pub fn canonical_module_path(path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = vec![];
+    for segment in path.split('/') {
+        match segment {
+            "" | "." => { }
+            ".." => {
+                segments.pop()?;
+            }
+            segment => segments.push(segment),
+        }
+    }
+    if segments.is_empty() {
+        return None;
+    }
+    Some(segments.join("/"))
+}
+
+fn check_visibility<'db>(
+    db: &'db dyn crate::Db,
+    importer_package: Package,
+    import_package_module: PackageModule,
+    span: impl FnOnce() -> Option<ImportSpan>,
+) -> ResolvedPackageModule {
+    match import_package_module.visibility(db) {
+        Visibility::Public => {
+            ResolvedPackageModule::Resolved(import_package_module)
+        }
+        Visibility::Package => {
+            let same_package = importer_package.modules(db).values()
+                .any(|module| *module == import_package_module);
+            if same_package {
+                ResolvedPackageModule::Resolved(import_package_module)
             } else {
-                let full_path = format!("{}/{}", package_alias, module_alias);
-                modules.get(&full_path).copied()
+                ResolvedPackageModule::Forbidden(import_package_module, ForbiddenReason::Private, span())
             }
-        })
+        }
+    }
 }
 
 fn validate_graph<'db>(
     db: &'db dyn crate::Db,
     graph: PackageWorldModuleGraph<'db>,
 ) -> Result<(), ValidationError> {
+    if !graph.missing_demands(db).is_empty() {
+        return Err(ValidationError::MissingImportDemands);
+    }
     let edges: BTreeMap<PackageModule, BTreeSet<PackageModule>> = graph.edges(db);
     detect_cycles(&edges)
 }
@@ -189,17 +515,144 @@ fn dfs_detect_cycle(
     false
 }
 
+/// Find the strongly connected components of the graph that form cycles.
+///
+/// A group contains every module that can reach every other module
+/// in the group. Modules that import themselves form a group of one.
+/// Modules within a group and the groups themselves
+/// are in `PackageModule` order.
+fn cycle_groups(edges: &BTreeMap<PackageModule, BTreeSet<PackageModule>>) -> Vec<BTreeSet<PackageModule>> {
+    let mut state = SccState {
+        edges,
+        next_index: 0,
+        index: BTreeMap::new(),
+        lowlink: BTreeMap::new(),
+        stack: vec![],
+        on_stack: BTreeSet::new(),
+        groups: vec![],
+    };
+
+    for &node in edges.keys() {
+        if !state.index.contains_key(&node) {
+            state.visit(node);
+        }
+    }
+
+    let mut groups: Vec<BTreeSet<PackageModule>> = state.groups.into_iter()
+        .filter(|group| {
+            match group.len() {
+                0 => unreachable!(),
+                1 => {
+                    let node = group.first().X();
+                    edges.get(node).map(|deps| deps.contains(node)).unwrap_or(false)
+                }
+                _ => true,
+            }
+        }).collect();
+    groups.sort();
+    groups
+}
+
+// Tarjan's strongly connected components algorithm.
+struct SccState<'e> {
+    edges: &'e BTreeMap<PackageModule, BTreeSet<PackageModule>>,
+    next_index: usize,
+    index: BTreeMap<PackageModule, usize>,
+    lowlink: BTreeMap<PackageModule, usize>,
+    stack: Vec<PackageModule>,
+    on_stack: BTreeSet<PackageModule>,
+    groups: Vec<BTreeSet<PackageModule>>,
+}
+
+impl<'e> SccState<'e> {
+    fn visit(&mut self, node: PackageModule) {
+        self.index.insert(node, self.next_index);
+        self.lowlink.insert(node, self.next_index);
+        self.next_index = self.next_index.checked_add(1).X();
+        self.stack.push(node);
+        self.on_stack.insert(node);
+
+        if let Some(deps) = self.edges.get(&node) {
+            for &dep in deps {
+                if !self.index.contains_key(&dep) {
+                    rmx::extras::recurse(|| {
+                        self.visit(dep)
+                    });
+                    let lowlink = self.lowlink[&node].min(self.lowlink[&dep]);
+                    self.lowlink.insert(node, lowlink);
+                } else if self.on_stack.contains(&dep) {
+                    let lowlink = self.lowlink[&node].min(self.index[&dep]);
+                    self.lowlink.insert(node, lowlink);
+                }
+            }
+        }
+
+        if self.lowlink[&node] == self.index[&node] {
+            let mut group = BTreeSet::new();
+            loop {
+                let member = self.stack.pop().X();
+                self.on_stack.remove(&member);
+                group.insert(member);
+                if member == node {
+                    break;
+                }
+            }
+            self.groups.push(group);
+        }
+    }
+}
+
+#[salsa::tracked(returns(ref))]
+fn graph_cycle_groups<'db>(
+    db: &'db dyn crate::Db,
+    graph: PackageWorldModuleGraph<'db>,
+) -> Vec<BTreeSet<PackageModule>> {
+    db.unwind_if_revision_cancelled();
+    cycle_groups(&graph.edges(db))
+}
+
+/// The package of every module in the world.
+#[salsa::tracked(returns(ref))]
+fn module_packages<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+) -> BTreeMap<PackageModule, Package> {
+    package_world_map.flatten_iter(db)
+        .map(|record| (record.package_module, record.package))
+        .collect()
+}
+
+/// The `package/module` paths of every import space,
+/// computed once and shared by the `module_world_map` of each package.
+#[salsa::tracked(returns(ref))]
+fn world_module_map<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+) -> BTreeMap<ImportSpace, BTreeMap<ModuleName, Vec<PackageModule>>> {
+    package_world_map.module_map(db)
+}
+
 #[salsa::tracked]
 pub fn module_world_map<'db>(
     db: &'db dyn crate::Db,
     package_world_map: PackageWorldMap<'db>,
     package: Package,
 ) -> ModuleWorldMap<'db> {
-    let mut module_map = package_world_map.module_map(db);
-    assert!(module_map.get("pkg").is_none());
+    let world_module_map = world_module_map(db, package_world_map);
+    let mut module_map = world_module_map.C();
+    for (alias, import_space) in package.import_space_aliases(db) {
+        if crate::loader::RESERVED_NAMES.contains(&alias.as_str()) {
+            continue;
+        }
+        let modules = world_module_map.get(import_space).cloned().unwrap_or_default();
+        module_map.insert(alias.C(), modules);
+    }
+    assert!(!module_map.contains_key("pkg"));
     module_map.insert(
         S("pkg"),
-        package.modules(db).C(),
+        package.modules(db).iter().map(|(module_name, package_module)| {
+            (module_name.C(), vec![*package_module])
+        }).collect(),
     );
     ModuleWorldMap::new(
         db,
@@ -207,72 +660,160 @@ pub fn module_world_map<'db>(
     )
 }
 
+/// The modules visible to one package, by import space and alias.
+///
+/// An alias maps to more than one module when the
+/// `package/module` paths of different packages collide,
+/// e.g. module `b/c` of package `a` and module `c` of package `a/b`.
 #[salsa::tracked]
 struct ModuleWorldMap<'db> {
     #[returns(ref)]
-    map: BTreeMap<ImportSpace, BTreeMap<ModuleAlias, PackageModule>>,
+    map: BTreeMap<ImportSpace, BTreeMap<ModuleAlias, Vec<PackageModule>>>,
 }
 
-pub struct PackageWorldRecord<'db> {
-    pub import_space: &'db str,
-    pub package_name: &'db str,
-    pub package: Package,
-    pub package_module: PackageModule,
+pub type PackageWorldRecord<'db> = crate::world_iter::PackageWorldRecord<'db, Package>;
+pub type PackageWorldIter<'db> = crate::world_iter::PackageWorldIter<'db, Package>;
+
+/// Iterator over the resolved imports of each module in world order.
+///
+/// Created by `PackageWorldModuleGraph::imports_in_world_order`.
+#[derive(Clone)]
+pub struct WorldImportsIter<'db> {
+    records: PackageWorldIter<'db>,
+    map: &'db BTreeMap<PackageModule, BTreeSet<(ImportDemand, ResolvedPackageModule)>>,
+}
+
+impl<'db> Iterator for WorldImportsIter<'db> {
+    type Item = (PackageWorldRecord<'db>, &'db BTreeSet<(ImportDemand, ResolvedPackageModule)>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let map = self.map;
+        self.records.by_ref().find_map(|record| {
+            map.get(&record.package_module).map(|imports| (record, imports))
+        })
+    }
+}
+
+impl<'db> DoubleEndedIterator for WorldImportsIter<'db> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let map = self.map;
+        self.records.by_ref().rev().find_map(|record| {
+            map.get(&record.package_module).map(|imports| (record, imports))
+        })
+    }
 }
 
 impl<'db> PackageWorldMap<'db> {
     fn module_map(
         &self,
         db: &'db dyn crate::Db,
-    ) -> BTreeMap<ImportSpace, BTreeMap<ModuleName, PackageModule>> {
+    ) -> BTreeMap<ImportSpace, BTreeMap<ModuleName, Vec<PackageModule>>> {
         self.map(db).iter()
             .map(|(import_space, packages)| -> (ImportSpace, BTreeMap<_, _>) {
-                (
-                    import_space.S(),
-                    packages.iter().flat_map(|(package_name, package)| {
-                        package.modules(db).iter().map(move |(module_name, package_module)| {
-                            (
-                                format!("{}/{}", package_name, module_name),
-                                *package_module,
-                            )
-                        })
-                    }).collect()
-                )
+                let mut modules: BTreeMap<ModuleName, Vec<PackageModule>> = BTreeMap::new();
+                for (package_name, package) in packages {
+                    for (module_name, package_module) in package.modules(db) {
+                        let path = format!("{}/{}", package_name, module_name);
+                        modules.entry(path).or_default().push(*package_module);
+                    }
+                }
+                for candidates in modules.values_mut() {
+                    candidates.sort();
+                }
+                (import_space.S(), modules)
             }).collect()
     }
 
+    /// Combine this world with `other` into a new map,
+    /// e.g. a base SDK world with a project's world.
+    ///
+    /// Every import space and package of either world is kept,
+    /// except that a package name in the same import space of both
+    /// is settled by `policy`. Packages are kept or replaced whole,
+    /// never combined module by module.
+    /// A package that is the same `Package` in both is no collision.
+    ///
+    /// `PackageWorldMap` is tracked, so this must be called
+    /// from within a tracked function.
+    pub fn merge(
+        self,
+        db: &'db dyn crate::Db,
+        other: PackageWorldMap<'db>,
+        policy: MergePolicy,
+    ) -> Result<PackageWorldMap<'db>, MergeConflict> {
+        let mut map = self.map(db).C();
+        let mut collisions = vec![];
+        for (import_space, other_packages) in other.map(db) {
+            let packages = map.entry(import_space.C()).or_default();
+            for (package_name, other_package) in other_packages {
+                match packages.get(package_name) {
+                    Some(package) if package != other_package => {
+                        collisions.push((import_space.C(), package_name.C()));
+                        if policy == MergePolicy::PreferOther {
+                            packages.insert(package_name.C(), *other_package);
+                        }
+                    }
+                    Some(_) => { }
+                    None => {
+                        packages.insert(package_name.C(), *other_package);
+                    }
+                }
+            }
+        }
+        if policy == MergePolicy::Reject && !collisions.is_empty() {
+            return Err(MergeConflict { packages: collisions });
+        }
+        Ok(PackageWorldMap::new(db, map))
+    }
+
+    /// Iterate every module of every package in the world.
+    ///
+    /// Records are ordered by import space, then package name,
+    /// then module name. Names are compared bytewise,
+    /// so the order does not depend on platform, locale,
+    /// or the order the packages and modules were created in.
     pub fn flatten_iter(
         &self,
         db: &'db dyn crate::Db,
-    ) -> impl Iterator<Item = PackageWorldRecord<'db>> {
-        let map = self.map(db);
-        map.iter()
-            .flat_map(move |(import_space, packages)| {
-                packages.iter().flat_map(move |(package_name, package)| {
-                    let modules = package.modules(db);
-                    modules.iter().map(move |(module_name, package_module)| {
-                        PackageWorldRecord {
-                            import_space,
-                            package_name,
-                            package: *package,
-                            package_module: *package_module,
-                        }
-                    })
-                })
-            })
+    ) -> PackageWorldIter<'db> {
+        PackageWorldIter::new(db, self.map(db))
     }
 }
 
 impl<'db> PackageWorldModuleGraph<'db> {
-    fn edges(
+    /// Iterate the resolved imports of each module in `flatten_iter` order.
+    pub fn imports_in_world_order(
+        &self,
+        db: &'db dyn crate::Db,
+        package_world_map: PackageWorldMap<'db>,
+    ) -> WorldImportsIter<'db> {
+        WorldImportsIter {
+            records: package_world_map.flatten_iter(db),
+            map: self.map(db),
+        }
+    }
+
+    /// Groups of modules that import each other in a cycle.
+    pub fn cycle_groups(
+        &self,
+        db: &'db dyn crate::Db,
+    ) -> Vec<BTreeSet<PackageModule>> {
+        graph_cycle_groups(db, *self).C()
+    }
+
+    pub(crate) fn edges(
         &self,
         db: &'db dyn crate::Db,
     ) -> BTreeMap<PackageModule, BTreeSet<PackageModule>> {
         self.map(db).iter().map(|(module, modules)| {
+            // Only successful imports are edges.
+            // Failed imports are reported per import, not as graph errors.
             let modules: BTreeSet<_> = modules.iter()
                 .filter_map(|(_, module)| match module {
                     ResolvedPackageModule::Resolved(module) => Some(module),
                     ResolvedPackageModule::Unresolved => None,
+                    ResolvedPackageModule::Ambiguous(..) => None,
+                    ResolvedPackageModule::Forbidden(..) => None,
                 }).copied().collect();
             (*module, modules)
         }).collect()
@@ -367,6 +908,23 @@ fn package_world_map_iter_lazy() {
         assert_eq!(expected.1, actual.package.name(db));
         assert_eq!(expected.2, actual.package_module.name(db));
     }
+
+    let reversed: Vec<_> = map.flatten_iter(db).rev()
+        .map(|record| record.package_module)
+        .collect();
+    let mut forward: Vec<_> = map.flatten_iter(db)
+        .map(|record| record.package_module)
+        .collect();
+    forward.reverse();
+    assert!(reversed == forward);
+
+    // Iterate from both ends at once.
+    let mut iter = map.flatten_iter(db);
+    assert_eq!(iter.next().X().package_module.name(db), "main");
+    assert_eq!(iter.next_back().X().package_module.name(db), "u32");
+    let rest = iter.C().map(|record| record.package_module.name(db).as_str()).collect::<Vec<_>>();
+    assert_eq!(rest, vec!["alloc", "core"]);
+    assert_eq!(iter.count(), 2);
 }
 
 #[salsa::tracked]
@@ -406,10 +964,12 @@ fn test_input_unresolvable<'db>(
     let import_demand_map = ImportDemandMap::new(
         db,
         BTreeMap::from([
-            (module_map["main"]["main/main"], vec![
+            (module_map["main"]["main/main"][0], vec![
                 (S("sys"), S("core"), S("core"))
             ]),
         ]),
+        vec![],
+        vec![],
     );
     TestInput::new(db, package_world_map, import_demand_map)
 }
@@ -507,6 +1067,8 @@ fn test_input_cycle<'db>(
                 (S("pkg"), S("core"), S("a"))
             ]),
         ]),
+        vec![],
+        vec![],
     );
     TestInput::new(db, package_world_map, import_demand_map)
 }
@@ -522,4 +1084,540 @@ fn test_cycles() {
     );
 
     assert!(resolved.result(db).is_err());
+
+    let graph = package_world_graph(
+        db,
+        test_input.package_world_map(db),
+        test_input.import_demand_map(db),
+    );
+    let groups: Vec<Vec<&str>> = graph.cycle_groups(db).iter().map(|group| {
+        group.iter().map(|module| module.name(db).as_str()).sorted().collect()
+    }).collect();
+    assert_eq!(groups, vec![vec!["a", "b"]]);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_ambiguous<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    // Module `b/c` of package `a` and module `c` of package `a/b`
+    // are both `sys/a/b/c`.
+    let builder = crate::world_builder::WorldBuilder::new()
+        .package("sys/a")
+        .module("b/c", "")
+        .package("sys/a/b")
+        .module("c", "")
+        .package("sys/main")
+        .module("main", "import module sys/a/b/c")
+        .import("sys", "a", "b/c");
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_ambiguous_import() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_ambiguous(db);
+    let resolved = resolve_package_world(
+        db,
+        test_input.package_world_map(db),
+        test_input.import_demand_map(db),
+    );
+    let graph = resolved.result(db).expect(".");
+    let packages = &test_input.package_world_map(db).map(db)["sys"];
+    let main = packages["main"].modules(db)["main"];
+    let (_, resolved_package) = graph.map(db)[&main].first().X();
+    let ResolvedPackageModule::Ambiguous(candidates, span) = resolved_package else {
+        panic!("expected ambiguous import");
+    };
+    assert_eq!(span.X().byte_span(), 14..23);
+    let mut expected = vec![
+        packages["a"].modules(db)["b/c"],
+        packages["a/b"].modules(db)["c"],
+    ];
+    expected.sort();
+    assert!(*candidates == expected);
+
+    let diagnostics = crate::diagnostics::import_diagnostics(db, test_input.package_world_map(db), graph);
+    let messages: Vec<_> = diagnostics.iter().map(|(_, diagnostic)| diagnostic.message.as_str()).collect();
+    let origins = ["`b/c` of package `sys/a`", "`c` of package `sys/a/b`"];
+    let origins = match expected[0] == packages["a"].modules(db)["b/c"] {
+        true => origins.join(" and "),
+        false => origins.iter().rev().join(" and "),
+    };
+    assert_eq!(messages, vec![format!("import `sys/a/b/c` matches 2 modules: {origins}")]);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_private<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let builder = crate::world_builder::WorldBuilder::new()
+        .package("sys/core")
+        .module("core", "import module pkg/detail")
+        .import("pkg", "core", "detail")
+        .module("detail", "")
+        .visibility(Visibility::Package)
+        .package("sys/main")
+        .module("main", "import module sys/core/detail")
+        .import("sys", "core", "detail");
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_forbidden_import() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_private(db);
+    let resolved = resolve_package_world(
+        db,
+        test_input.package_world_map(db),
+        test_input.import_demand_map(db),
+    );
+    let graph = resolved.result(db).expect(".");
+    let packages = &test_input.package_world_map(db).map(db)["sys"];
+    let core = packages["core"].modules(db)["core"];
+    let detail = packages["core"].modules(db)["detail"];
+    let main = packages["main"].modules(db)["main"];
+
+    // Same package.
+    let (_, resolved_package) = graph.map(db)[&core].first().X();
+    assert!(*resolved_package == ResolvedPackageModule::Resolved(detail));
+
+    // Different package.
+    let (_, resolved_package) = graph.map(db)[&main].first().X();
+    let span = ImportSpan { start: 14, end: 29 };
+    assert!(*resolved_package == ResolvedPackageModule::Forbidden(detail, ForbiddenReason::Private, Some(span)));
+
+    // Forbidden imports are not edges.
+    assert!(graph.edges(db)[&main].is_empty());
+}
+
+#[cfg(test)]
+#[rustfmt::skip]
+#[salsa::tracked]
+fn test_input_unordered<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    // Modules are created in the order z, é, b, Z,
+    // which is neither name order, Z, b, z, é, nor its reverse.
+    let module_z = PackageModule::new(db, S("z"), Source::new(db, S("")));
+    let module_e = PackageModule::new(db, S("é"), Source::new(db, S("")));
+    let module_b = PackageModule::new(db, S("b"), Source::new(db, S("")));
+    let module_upper = PackageModule::new(db, S("Z"), Source::new(db, S("")));
+    let package_world_map = PackageWorldMap::new(
+        db,
+        BTreeMap::from([
+            (S("sys"), BTreeMap::from([
+                (S("core"), Package::new(
+                    db,
+                    S("core"),
+                    BTreeMap::from([
+                        (S("z"), module_z),
+                        (S("é"), module_e),
+                        (S("b"), module_b),
+                        (S("Z"), module_upper),
+                    ]),
+                )),
+            ])),
+        ]),
+    );
+    let import_demand_map = ImportDemandMap::new(
+        db,
+        BTreeMap::from([
+            (module_z, vec![
+                (S("pkg"), S("core"), S("é")),
+                (S("pkg"), S("core"), S("b")),
+            ]),
+            (module_e, vec![]),
+            (module_b, vec![]),
+            (module_upper, vec![]),
+        ]),
+        vec![],
+        vec![],
+    );
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_world_order() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_unordered(db);
+    let package_world_map = test_input.package_world_map(db);
+
+    // Bytewise, so uppercase before lowercase before non-ASCII.
+    let names: Vec<_> = package_world_map.flatten_iter(db)
+        .map(|record| record.package_module.name(db).as_str())
+        .collect();
+    assert_eq!(names, vec!["Z", "b", "z", "é"]);
+
+    let graph = package_world_graph(
+        db,
+        package_world_map,
+        test_input.import_demand_map(db),
+    );
+    let names: Vec<_> = graph.imports_in_world_order(db, package_world_map)
+        .map(|(record, _)| record.package_module.name(db).as_str())
+        .collect();
+    assert_eq!(names, vec!["Z", "b", "z", "é"]);
+
+    let (_, imports) = graph.imports_in_world_order(db, package_world_map)
+        .find(|(record, _)| record.package_module.name(db) == "z").X();
+    let imports: Vec<_> = imports.iter()
+        .map(|((_, _, module_alias), _)| module_alias.as_str())
+        .collect();
+    assert_eq!(imports, vec!["b", "é"]);
+}
+
+#[test]
+fn test_resolve_module_imports_lazily() {
+    let (ref db, executed) = crate::event_recording_database(crate::executed_query);
+    let test_input = test_input_unordered(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    let packages = &package_world_map.map(db)["sys"];
+    let module_z = packages["core"].modules(db)["z"];
+    let module_b = packages["core"].modules(db)["b"];
+
+    executed.lock().X().clear();
+    let imports = resolve_module_imports(db, package_world_map, import_demand_map, module_z).X();
+    let imports: Vec<_> = imports.iter()
+        .map(|((_, _, module_alias), resolved)| (module_alias.as_str(), resolved.C()))
+        .collect();
+    assert_eq!(imports.len(), 2);
+    assert!(imports[0] == ("b", ResolvedPackageModule::Resolved(module_b)));
+
+    // Only the one module was resolved.
+    let module_queries = executed.lock().X().iter()
+        .filter(|key| key.starts_with("module_imports("))
+        .count();
+    assert_eq!(module_queries, 1);
+
+    // The world graph aggregates the same queries.
+    let graph = package_world_graph(db, package_world_map, import_demand_map);
+    let from_graph: Vec<_> = graph.map(db)[&module_z].iter().cloned().collect();
+    assert!(from_graph == resolve_module_imports(db, package_world_map, import_demand_map, module_z).X());
+    let module_queries = executed.lock().X().iter()
+        .filter(|key| key.starts_with("module_imports("))
+        .count();
+    assert_eq!(module_queries, 4);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_aliased<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let builder = crate::world_builder::WorldBuilder::new()
+        .package("sys/core")
+        .module("core", "")
+        .package("vendor/core")
+        .module("core", "")
+        .package("local/forked")
+        .import_space_alias("sys", "vendor")
+        .module("main", "import module sys/core")
+        .import("sys", "core", "core")
+        .package("local/plain")
+        .module("main", "import module sys/core")
+        .import("sys", "core", "core")
+        .package("local/missing")
+        .import_space_alias("sys", "nowhere")
+        .module("main", "import module sys/core")
+        .import("sys", "core", "core");
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_import_space_aliases() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_aliased(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    let map = package_world_map.map(db);
+    let sys_core = map["sys"]["core"].modules(db)["core"];
+    let vendor_core = map["vendor"]["core"].modules(db)["core"];
+
+    let resolve = |package: &str| {
+        let main = map["local"][package].modules(db)["main"];
+        let imports = resolve_module_imports(db, package_world_map, import_demand_map, main).X();
+        assert_eq!(imports.len(), 1);
+        imports[0].1.C()
+    };
+    assert!(resolve("forked") == ResolvedPackageModule::Resolved(vendor_core));
+    assert!(resolve("plain") == ResolvedPackageModule::Resolved(sys_core));
+    assert!(resolve("missing") == ResolvedPackageModule::Unresolved);
+}
+
+#[test]
+fn test_canonical_module_path() {
+    assert_eq!(canonical_module_path("a/b").as_deref(), Some("a/b"));
+    assert_eq!(canonical_module_path("./a//b/").as_deref(), Some("a/b"));
+    assert_eq!(canonical_module_path("a/../b/./c").as_deref(), Some("b/c"));
+    assert_eq!(canonical_module_path("a/.."), None);
+    assert_eq!(canonical_module_path("../a"), None);
+    assert_eq!(canonical_module_path(""), None);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_relative<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let builder = crate::world_builder::WorldBuilder::new()
+        .package("sys/core")
+        .module("a/b/x", "")
+        .import("self", "", "y")
+        .import("super", "", "z")
+        .import("pkg", "", "a/./b//y")
+        .import("self", "", "../../top")
+        .module("a/b/y", "")
+        .module("a/z", "")
+        .module("top", "")
+        .import("self", "", "a/z")
+        .import("super", "", "top");
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_relative_imports() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_relative(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    let modules = package_world_map.map(db)["sys"]["core"].modules(db);
+
+    let resolve = |module: &str| -> Vec<(String, Option<&str>)> {
+        let imports = resolve_module_imports(db, package_world_map, import_demand_map, modules[module]).X();
+        imports.iter().map(|((import_space, _, module_alias), resolved)| {
+            let resolved = match resolved {
+                ResolvedPackageModule::Resolved(module) => Some(module.name(db).as_str()),
+                _ => None,
+            };
+            (format!("{import_space}/{module_alias}"), resolved)
+        }).collect()
+    };
+    assert_eq!(resolve("a/b/x"), vec![
+        (S("pkg/a/./b//y"), Some("a/b/y")),
+        (S("self/../../top"), Some("top")),
+        (S("self/y"), Some("a/b/y")),
+        (S("super/z"), Some("a/z")),
+    ]);
+    // Top-level modules have no parent directory.
+    assert_eq!(resolve("top"), vec![
+        (S("self/a/z"), Some("a/z")),
+        (S("super/top"), None),
+    ]);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_prelude<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let builder = crate::world_builder::WorldBuilder::new()
+        .prelude("sys", "core", "prelude")
+        .package("sys/core")
+        .module("prelude", "")
+        .module("io", "")
+        .import("pkg", "", "prelude")
+        .package("local/app")
+        .module("main", "")
+        .module("raw", "")
+        .no_prelude();
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_prelude() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_prelude(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    let map = package_world_map.map(db);
+    let prelude = map["sys"]["core"].modules(db)["prelude"];
+
+    let imports = |module: PackageModule| {
+        resolve_module_imports(db, package_world_map, import_demand_map, module).X()
+    };
+    let prelude_import = ((S("sys"), S("core"), S("prelude")), ResolvedPackageModule::Resolved(prelude));
+    assert!(imports(map["local"]["app"].modules(db)["main"]) == vec![prelude_import.C()]);
+    assert!(imports(map["local"]["app"].modules(db)["raw"]).is_empty());
+    // The prelude doesn't import itself.
+    assert!(imports(prelude).is_empty());
+    // Explicit imports of the prelude are kept alongside it.
+    let io = imports(map["sys"]["core"].modules(db)["io"]);
+    assert_eq!(io.len(), 2);
+    assert!(io.contains(&prelude_import));
+
+    let graph = resolve_package_world(db, package_world_map, import_demand_map);
+    assert!(graph.result(db).is_ok());
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_missing_demands<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let test_input = test_input_prelude(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    // Drop the demands of `io`, as if it were added to the world after.
+    let io = package_world_map.map(db)["sys"]["core"].modules(db)["io"];
+    let mut map = import_demand_map.map(db).clone();
+    map.remove(&io);
+    let import_demand_map = ImportDemandMap::new(
+        db,
+        map,
+        import_demand_map.prelude(db).clone(),
+        import_demand_map.overrides(db).clone(),
+    );
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_missing_import_demands() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_missing_demands(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    let sys_core = package_world_map.map(db)["sys"]["core"].modules(db);
+    let io = sys_core["io"];
+
+    // A module without demands gets only the prelude.
+    let graph = package_world_graph(db, package_world_map, import_demand_map);
+    assert!(graph.missing_demands(db).iter().eq([&io]));
+    let prelude_import = ((S("sys"), S("core"), S("prelude")), ResolvedPackageModule::Resolved(sys_core["prelude"]));
+    assert!(graph.map(db)[&io].iter().eq([&prelude_import]));
+    assert!(resolve_module_imports(db, package_world_map, import_demand_map, io).X() == vec![prelude_import]);
+
+    // The world doesn't validate.
+    let resolved = resolve_package_world(db, package_world_map, import_demand_map);
+    assert!(matches!(resolved.result(db), Err(ValidationError::MissingImportDemands)));
+
+    // A module outside the world has no imports to resolve.
+    let stray = PackageModule::new(db, S("stray"), Source::new(db, S("")));
+    assert!(resolve_module_imports(db, package_world_map, import_demand_map, stray).is_none());
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_input_overrides<'db>(
+    db: &'db dyn crate::Db,
+) -> TestInput<'db> {
+    let builder = crate::world_builder::WorldBuilder::new()
+        .import_space_override("local", "sys")
+        .package("sys/core")
+        .module("io", "")
+        .module("fs", "")
+        .package("local/core")
+        .module("io", "")
+        .import("pkg", "", "fs")
+        .package("local/app")
+        .module("main", "")
+        .import("sys", "core", "io")
+        .import("sys", "core", "fs");
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    TestInput::new(db, package_world_map, import_demand_map)
+}
+
+#[test]
+fn test_import_space_overrides() {
+    let ref db = crate::Database::default();
+    let test_input = test_input_overrides(db);
+    let package_world_map = test_input.package_world_map(db);
+    let import_demand_map = test_input.import_demand_map(db);
+    let map = package_world_map.map(db);
+    let sys_core = map["sys"]["core"].modules(db);
+    let local_core = map["local"]["core"].modules(db);
+    let main = map["local"]["app"].modules(db)["main"];
+
+    let imports = |module: PackageModule| {
+        resolve_module_imports(db, package_world_map, import_demand_map, module).X()
+    };
+    assert!(imports(main) == vec![
+        ((S("sys"), S("core"), S("fs")), ResolvedPackageModule::Resolved(sys_core["fs"])),
+        ((S("sys"), S("core"), S("io")), ResolvedPackageModule::Resolved(local_core["io"])),
+    ]);
+    // `pkg` imports are never overridden, and missing there stay missing.
+    assert!(imports(local_core["io"]) == vec![
+        ((S("pkg"), S(""), S("fs")), ResolvedPackageModule::Unresolved),
+    ]);
+
+    let graph = resolve_package_world(db, package_world_map, import_demand_map);
+    let graph = graph.result(db).expect(".");
+    let shadowed = &graph.shadowed(db)[&main];
+    assert_eq!(shadowed.len(), 1);
+    assert_eq!(shadowed[0].import_demand, (S("sys"), S("core"), S("io")));
+    assert!(shadowed[0].used == vec![local_core["io"]]);
+    assert!(shadowed[0].shadowed == vec![sys_core["io"]]);
+    assert_eq!(graph.shadowed(db).len(), 1);
+
+    let diagnostics = crate::diagnostics::import_diagnostics(db, package_world_map, graph);
+    let messages: Vec<_> = diagnostics.iter()
+        .filter(|(_, diagnostic)| diagnostic.code == crate::diagnostics::Code::ShadowedImport)
+        .map(|(_, diagnostic)| diagnostic.message.as_str())
+        .collect();
+    assert_eq!(messages, vec![
+        "import `sys/core/io` uses `io` of package `local/core`, shadowing `io` of package `sys/core`",
+    ]);
+}
+
+#[cfg(test)]
+#[salsa::tracked]
+fn test_merged_worlds<'db>(
+    db: &'db dyn crate::Db,
+) -> Vec<Result<Vec<String>, String>> {
+    let sdk = crate::world_builder::WorldBuilder::new()
+        .package("sys/core")
+        .module("core", "sdk")
+        .package("sys/io")
+        .module("io", "sdk")
+        .build_map(db);
+    let project = crate::world_builder::WorldBuilder::new()
+        .package("sys/io")
+        .module("io", "project")
+        .module("fs", "project")
+        .package("local/app")
+        .module("main", "project")
+        .build_map(db);
+    [MergePolicy::PreferSelf, MergePolicy::PreferOther, MergePolicy::Reject].into_iter()
+        .map(|policy| {
+            let merged = sdk.merge(db, project, policy).map_err(|e| e.to_string())?;
+            Ok(merged.flatten_iter(db)
+                .map(|record| {
+                    let text = record.package_module.text(db).text(db);
+                    format!("{}/{}/{} {text}", record.import_space, record.package_name, record.package_module.name(db))
+                })
+                .collect())
+        })
+        .collect()
+}
+
+#[test]
+fn test_merge_worlds() {
+    let ref db = crate::Database::default();
+    let merged = test_merged_worlds(db);
+    assert_eq!(merged[0], Ok(vec![
+        S("local/app/main project"),
+        S("sys/core/core sdk"),
+        S("sys/io/io sdk"),
+    ]));
+    assert_eq!(merged[1], Ok(vec![
+        S("local/app/main project"),
+        S("sys/core/core sdk"),
+        S("sys/io/fs project"),
+        S("sys/io/io project"),
+    ]));
+    assert_eq!(merged[2], Err(S("both worlds have packages `sys/io`")));
 }
@@ -0,0 +1,228 @@
+//! Progress reporting and cancellation for resolving large worlds.
+//!
+//! `resolve_world_with_progress` resolves a world one module at a time,
+//! calling back after each step so a GUI or language server can show
+//! how far along it is, and stop early by returning `ControlFlow::Break`.
+//!
+//! The resolver queries also check for salsa cancellation as they go.
+//! A caller resolving on a clone of the database is cancelled
+//! when the original is written to, e.g. because a file changed,
+//! and gets `Interrupted::Cancelled` instead of a panic.
+
+use rmx::prelude::*;
+
+use rmx::std::fmt;
+use rmx::std::ops::ControlFlow;
+use rmx::std::panic::AssertUnwindSafe;
+
+use crate::package_resolve2::{
+    ImportDemandMap,
+    PackageWorldMap,
+    PackageWorldModuleGraph,
+    package_world_graph,
+    resolve_module_imports,
+};
+
+/// A step of resolving a world, in the order they run.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Phase {
+    /// Resolving the imports of each module.
+    ResolveImports,
+    /// Finding import cycles in the resolved graph.
+    FindCycles,
+}
+
+impl Phase {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Phase::ResolveImports => "resolving imports",
+            Phase::FindCycles => "finding cycles",
+        }
+    }
+}
+
+/// How far resolution has got within a phase.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Progress {
+    pub phase: Phase,
+    /// Work items finished, up to `total`.
+    pub done: usize,
+    pub total: usize,
+}
+
+/// Why resolution stopped before finishing.
+#[derive(Debug)]
+pub enum Interrupted {
+    /// The progress callback returned `ControlFlow::Break`.
+    Aborted,
+    /// The database has a pending write.
+    Cancelled(salsa::Cancelled),
+}
+
+impl fmt::Display for Interrupted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Interrupted::Aborted => f.write_str("aborted"),
+            Interrupted::Cancelled(cancelled) => write!(f, "{cancelled}"),
+        }
+    }
+}
+
+impl rmx::std::error::Error for Interrupted { }
+
+/// Resolve every import in a world, reporting progress.
+///
+/// `on_progress` is called at the start of each phase with `done` zero,
+/// then after each work item.
+/// The result is the same graph `package_world_graph` returns,
+/// with its cycle groups already computed.
+pub fn resolve_world_with_progress<'db>(
+    db: &'db dyn crate::Db,
+    package_world_map: PackageWorldMap<'db>,
+    import_demand_map: ImportDemandMap<'db>,
+    on_progress: &mut dyn FnMut(Progress) -> ControlFlow<()>,
+) -> Result<PackageWorldModuleGraph<'db>, Interrupted> {
+    let mut on_progress = AssertUnwindSafe(on_progress);
+    let resolve = AssertUnwindSafe(|| {
+        let mut report = |phase, done, total| {
+            match on_progress(Progress { phase, done, total }) {
+                ControlFlow::Continue(()) => Ok(()),
+                ControlFlow::Break(()) => Err(Interrupted::Aborted),
+            }
+        };
+
+        let total = package_world_map.flatten_iter(db).count();
+        report(Phase::ResolveImports, 0, total)?;
+        for (index, record) in package_world_map.flatten_iter(db).enumerate() {
+            resolve_module_imports(db, package_world_map, import_demand_map, record.package_module).X();
+            report(Phase::ResolveImports, index.checked_add(1).X(), total)?;
+        }
+
+        let graph = package_world_graph(db, package_world_map, import_demand_map);
+        report(Phase::FindCycles, 0, 1)?;
+        graph.cycle_groups(db);
+        report(Phase::FindCycles, 1, 1)?;
+
+        Ok(graph)
+    });
+
+    salsa::Cancelled::catch(resolve)
+        .unwrap_or_else(|cancelled| Err(Interrupted::Cancelled(cancelled)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package_resolve2::ResolvedPackageModule;
+    use crate::world_builder::WorldBuilder;
+
+    #[salsa::tracked]
+    struct TestWorld<'db> {
+        package_world_map: PackageWorldMap<'db>,
+        import_demand_map: ImportDemandMap<'db>,
+    }
+
+    #[salsa::tracked]
+    fn test_world<'db>(db: &'db dyn crate::Db) -> TestWorld<'db> {
+        let builder = WorldBuilder::new()
+            .package("sys/core")
+            .module("core", "")
+            .package("local/app")
+            .module("main", "import module sys/core")
+            .import("sys", "core", "core")
+            .module("util", "");
+        let package_world_map = builder.build_map(db);
+        let import_demand_map = builder.build_import_demands(db, package_world_map);
+        TestWorld::new(db, package_world_map, import_demand_map)
+    }
+
+    #[test]
+    fn test_resolve_world_with_progress() {
+        let ref db = crate::Database::default();
+        let world = test_world(db);
+
+        let mut reports = vec![];
+        let graph = resolve_world_with_progress(
+            db,
+            world.package_world_map(db),
+            world.import_demand_map(db),
+            &mut |progress| {
+                reports.push((progress.phase, progress.done, progress.total));
+                ControlFlow::Continue(())
+            },
+        ).X();
+
+        assert_eq!(reports, vec![
+            (Phase::ResolveImports, 0, 3),
+            (Phase::ResolveImports, 1, 3),
+            (Phase::ResolveImports, 2, 3),
+            (Phase::ResolveImports, 3, 3),
+            (Phase::FindCycles, 0, 1),
+            (Phase::FindCycles, 1, 1),
+        ]);
+        assert!(graph == package_world_graph(db, world.package_world_map(db), world.import_demand_map(db)));
+        let resolved = graph.map(db).values().flatten()
+            .filter(|(_, module)| matches!(module, ResolvedPackageModule::Resolved(_)))
+            .count();
+        assert_eq!(resolved, 1);
+    }
+
+    #[test]
+    fn test_resolve_world_aborted() {
+        let ref db = crate::Database::default();
+        let world = test_world(db);
+
+        let mut calls = 0;
+        let result = resolve_world_with_progress(
+            db,
+            world.package_world_map(db),
+            world.import_demand_map(db),
+            &mut |progress| {
+                calls += 1;
+                if progress.done == 1 {
+                    ControlFlow::Break(())
+                } else {
+                    ControlFlow::Continue(())
+                }
+            },
+        );
+
+        assert!(matches!(result, Err(Interrupted::Aborted)));
+        assert_eq!(calls, 2);
+    }
+
+    #[test]
+    fn test_resolve_world_cancelled() {
+        use rmx::std::sync::mpsc;
+        use rmx::std::time::{Duration, Instant};
+
+        let mut db = crate::Database::default();
+        let (started_tx, started_rx) = mpsc::channel();
+
+        let worker_db = db.C();
+        let worker = rmx::std::thread::spawn(move || {
+            let db = &worker_db;
+            let world = test_world(db);
+            let result = resolve_world_with_progress(
+                db,
+                world.package_world_map(db),
+                world.import_demand_map(db),
+                &mut |_| {
+                    started_tx.send(()).X();
+                    // Wait for the write to cancel us.
+                    let deadline = Instant::now() + Duration::from_secs(10);
+                    while Instant::now() < deadline {
+                        salsa::Database::unwind_if_revision_cancelled(db);
+                        rmx::std::thread::sleep(Duration::from_millis(1));
+                    }
+                    ControlFlow::Continue(())
+                },
+            );
+            matches!(result, Err(Interrupted::Cancelled(_)))
+        });
+
+        started_rx.recv().X();
+        salsa::Database::trigger_cancellation(&mut db);
+        assert!(worker.join().unwrap());
+    }
+}
@@ -0,0 +1,755 @@
+//! The `bct.toml` project configuration file.
+//!
+//! All sections and keys are optional:
+//!
+//! ```toml
+//! [format]
+//! indent_width = 4
+//! max_width = 100
+//! wrap_comments = false
+//! tab_width = 4
+//! max_blank_lines = 2
+//!
+//! [severity]
+//! E0104 = "allow"
+//!
+//! [chunks]
+//! separators = ["."]
+//!
+//! [source_map]
+//! embedded_fence = "```"
+//! heredocs = true
+//!
+//! [lexer]
+//! sigil_sets = ["question-ops", "bar-ops", "exclamation-ops", "percent-ops", "prefix"]
+//! time_literals = true
+//!
+//! [packages]
+//! paths = ["lib", "vendor"]
+//! prelude = [{ import_space = "sys", package = "core", module = "prelude" }]
+//! overrides = [{ import_space = "local", overrides = "sys" }]
+//!
+//! [language]
+//! version = "1.0"
+//! features = ["time-literals"]
+//! ```
+//!
+//! The loaded file becomes a `ProjectConfig` input with one field per section.
+//! Reloading sets only the fields that changed,
+//! so queries that read only unchanged sections are not re-run.
+//!
+//! A project may also have `bct.toml` files in subdirectories,
+//! collected into a `ConfigTree`.
+//! The effective config for a source file merges every file
+//! from the project root down to the source's directory,
+//! deeper files overriding shallower ones key by key.
+//! Unknown keys and invalid values in a layered file are reported
+//! as diagnostics and otherwise ignored.
+
+use rmx::prelude::*;
+
+use rmx::std::path::{Path, PathBuf};
+use salsa::Setter as _;
+
+use crate::diagnostics::{Code, Diagnostic, SeverityConfig};
+use crate::language::{LanguageOptions, LanguageVersion};
+use crate::lexer::{ErrorRecovery, LiteralRegistry, SigilSet, WhitespaceMode};
+use crate::source_map::SourceMapOptions;
+use crate::package_resolve2::{ImportDemand, ImportSpaceOverride};
+
+/// The name of the project configuration file.
+pub const CONFIG_FILE_NAME: &str = "bct.toml";
+
+#[salsa::input]
+pub struct ProjectConfig {
+    #[returns(ref)]
+    pub format: FormatOptions,
+    #[returns(ref)]
+    pub severity: SeverityConfig,
+    #[returns(ref)]
+    pub chunk_separators: Vec<char>,
+    #[returns(ref)]
+    pub source_map: SourceMapOptions,
+    #[returns(ref)]
+    pub sigil_sets: Vec<SigilSet>,
+    /// Whether datetimes and durations are lexed, see `lexer::Config::time_literals`.
+    pub time_literals: bool,
+    /// Directories containing packages, relative to the project root.
+    #[returns(ref)]
+    pub package_paths: Vec<PathBuf>,
+    /// Modules imported by every module, see `ImportDemandMap::prelude`.
+    #[returns(ref)]
+    pub prelude: Vec<ImportDemand>,
+    /// Import spaces searched first, see `ImportDemandMap::overrides`.
+    #[returns(ref)]
+    pub overrides: Vec<ImportSpaceOverride>,
+    /// The language version and features, see `language`.
+    #[returns(ref)]
+    pub language: LanguageOptions,
+    /// Custom literals registered by the embedder,
+    /// which a config file can't set.
+    #[returns(ref)]
+    pub literals: LiteralRegistry,
+}
+
+/// Formatter options, the `[format]` section.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, salsa::Update)]
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FormatOptions {
+    /// Spaces per indentation level.
+    pub indent_width: usize,
+    /// The line width the formatter tries to stay within.
+    pub max_width: usize,
+    /// Whether to wrap line comments longer than `max_width`.
+    pub wrap_comments: bool,
+    /// The columns between tab stops, for reporting columns
+    /// and aligning diagnostics in sources indented with tabs.
+    pub tab_width: usize,
+    /// The most consecutive blank lines the formatter keeps,
+    /// removing any more.
+    pub max_blank_lines: usize,
+}
+
+impl Default for FormatOptions {
+    fn default() -> FormatOptions {
+        FormatOptions {
+            indent_width: 4,
+            max_width: 100,
+            wrap_comments: false,
+            tab_width: 4,
+            max_blank_lines: 2,
+        }
+    }
+}
+
+/// The contents of a configuration file.
+#[derive(Clone, Debug, Eq, PartialEq, salsa::Update)]
+pub struct ConfigFile {
+    pub format: FormatOptions,
+    pub severity: SeverityConfig,
+    pub chunk_separators: Vec<char>,
+    pub source_map: SourceMapOptions,
+    pub sigil_sets: Vec<SigilSet>,
+    pub time_literals: bool,
+    pub package_paths: Vec<PathBuf>,
+    pub prelude: Vec<ImportDemand>,
+    pub overrides: Vec<ImportSpaceOverride>,
+    pub language: LanguageOptions,
+}
+
+impl Default for ConfigFile {
+    fn default() -> ConfigFile {
+        ConfigFile {
+            format: FormatOptions::default(),
+            severity: SeverityConfig::default(),
+            chunk_separators: vec!['.'],
+            source_map: SourceMapOptions::default(),
+            sigil_sets: SigilSet::all(),
+            time_literals: false,
+            package_paths: vec![],
+            prelude: vec![],
+            overrides: vec![],
+            language: LanguageOptions::default(),
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ChunksSection {
+    separators: Vec<char>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LexerSection {
+    sigil_sets: Vec<SigilSet>,
+    time_literals: bool,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PackagesSection {
+    paths: Vec<PathBuf>,
+    prelude: Vec<PreludeEntry>,
+    overrides: Vec<OverrideEntry>,
+}
+
+#[derive(Default)]
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LanguageSection {
+    version: Option<String>,
+    features: Vec<crate::language::Feature>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct PreludeEntry {
+    import_space: String,
+    package: String,
+    module: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct OverrideEntry {
+    import_space: String,
+    overrides: String,
+}
+
+impl Default for ChunksSection {
+    fn default() -> ChunksSection {
+        ChunksSection { separators: ConfigFile::default().chunk_separators }
+    }
+}
+
+impl Default for LexerSection {
+    fn default() -> LexerSection {
+        LexerSection { sigil_sets: ConfigFile::default().sigil_sets, time_literals: false }
+    }
+}
+
+impl Default for PackagesSection {
+    fn default() -> PackagesSection {
+        PackagesSection { paths: ConfigFile::default().package_paths, prelude: vec![], overrides: vec![] }
+    }
+}
+
+impl ConfigFile {
+    pub fn parse(toml: &str) -> AnyResult<ConfigFile> {
+        ConfigFile::from_table(rmx::toml::from_str(toml)?)
+    }
+
+    pub fn from_table(mut table: rmx::toml::Table) -> AnyResult<ConfigFile> {
+        let mut config = ConfigFile::default();
+        if let Some(severity) = table.remove("severity") {
+            let severity = severity.as_table()
+                .ok_or_else(|| anyhow!("`severity` must be a table"))?;
+            config.severity = SeverityConfig::from_table(severity)?;
+        }
+        for (key, value) in table {
+            match key.as_str() {
+                "format" => {
+                    config.format = value.try_into().context("in [format]")?;
+                }
+                "chunks" => {
+                    let section: ChunksSection = value.try_into().context("in [chunks]")?;
+                    config.chunk_separators = section.separators;
+                }
+                "source_map" => {
+                    let section: SourceMapOptions = value.try_into().context("in [source_map]")?;
+                    let fence = section.embedded_fence.as_deref();
+                    if fence.is_some_and(|fence| !fence.starts_with(|ch: char| ch.is_ascii())) {
+                        bail!("in [source_map]: `embedded_fence` must start with an ASCII char");
+                    }
+                    config.source_map = section;
+                }
+                "lexer" => {
+                    let section: LexerSection = value.try_into().context("in [lexer]")?;
+                    config.sigil_sets = section.sigil_sets;
+                    config.time_literals = section.time_literals;
+                }
+                "packages" => {
+                    let section: PackagesSection = value.try_into().context("in [packages]")?;
+                    config.package_paths = section.paths;
+                    config.prelude = section.prelude.into_iter()
+                        .map(|entry| (entry.import_space, entry.package, entry.module))
+                        .collect();
+                    config.overrides = section.overrides.into_iter()
+                        .map(|entry| ImportSpaceOverride { import_space: entry.import_space, overrides: entry.overrides })
+                        .collect();
+                }
+                "language" => {
+                    let section: LanguageSection = value.try_into().context("in [language]")?;
+                    if let Some(version) = section.version {
+                        config.language.version = LanguageVersion::parse(&version)
+                            .ok_or_else(|| anyhow!("in [language]: invalid version `{version}`"))?;
+                    }
+                    config.language.features = section.features.into_iter().collect();
+                }
+                _ => bail!("unknown section `{key}`"),
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Read `bct.toml` from `root`, or the defaults if there is none.
+    pub fn load(root: &Path) -> AnyResult<ConfigFile> {
+        let path = root.join(CONFIG_FILE_NAME);
+        match rmx::std::fs::read_to_string(&path) {
+            Ok(toml) => ConfigFile::parse(&toml).with_context(|| format!("reading {}", path.display())),
+            Err(e) if e.kind() == rmx::std::io::ErrorKind::NotFound => Ok(ConfigFile::default()),
+            Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+        }
+    }
+}
+
+impl ProjectConfig {
+    pub fn from_file(db: &dyn crate::Db, file: ConfigFile) -> ProjectConfig {
+        ProjectConfig::new(
+            db,
+            file.format,
+            file.severity,
+            file.chunk_separators,
+            file.source_map,
+            file.sigil_sets,
+            file.time_literals,
+            file.package_paths,
+            file.prelude,
+            file.overrides,
+            file.language,
+            LiteralRegistry::default(),
+        )
+    }
+
+    /// Update to the contents of `file`, setting only fields that changed.
+    ///
+    /// The registered literals are kept.
+    pub fn update(self, db: &mut dyn crate::Db, file: ConfigFile) {
+        if *self.format(db) != file.format {
+            self.set_format(db).to(file.format);
+        }
+        if *self.severity(db) != file.severity {
+            self.set_severity(db).to(file.severity);
+        }
+        if *self.chunk_separators(db) != file.chunk_separators {
+            self.set_chunk_separators(db).to(file.chunk_separators);
+        }
+        if *self.source_map(db) != file.source_map {
+            self.set_source_map(db).to(file.source_map);
+        }
+        if *self.sigil_sets(db) != file.sigil_sets {
+            self.set_sigil_sets(db).to(file.sigil_sets);
+        }
+        if self.time_literals(db) != file.time_literals {
+            self.set_time_literals(db).to(file.time_literals);
+        }
+        if *self.package_paths(db) != file.package_paths {
+            self.set_package_paths(db).to(file.package_paths);
+        }
+        if *self.prelude(db) != file.prelude {
+            self.set_prelude(db).to(file.prelude);
+        }
+        if *self.overrides(db) != file.overrides {
+            self.set_overrides(db).to(file.overrides);
+        }
+        if *self.language(db) != file.language {
+            self.set_language(db).to(file.language);
+        }
+    }
+}
+
+/// One `bct.toml` in a project, before merging with the others.
+#[salsa::input]
+pub struct ConfigLayer {
+    /// The directory containing the file, relative to the project root.
+    #[returns(ref)]
+    pub dir: PathBuf,
+    #[returns(ref)]
+    pub table: rmx::toml::Table,
+}
+
+/// Every `bct.toml` in a project.
+#[salsa::input]
+pub struct ConfigTree {
+    /// Sorted by directory, so each layer comes after its parents.
+    #[returns(ref)]
+    pub layers: Vec<ConfigLayer>,
+}
+
+impl ConfigTree {
+    /// Find every `bct.toml` under `root`.
+    ///
+    /// Hidden directories are skipped.
+    /// Files that are not valid TOML are an error;
+    /// unknown keys and invalid values are left to `config_layer_diagnostics`.
+    pub fn load(db: &dyn crate::Db, root: &Path) -> AnyResult<ConfigTree> {
+        let mut layers = vec![];
+        let mut dirs = vec![PathBuf::new()];
+        while let Some(dir) = dirs.pop() {
+            let path = root.join(&dir).join(CONFIG_FILE_NAME);
+            match rmx::std::fs::read_to_string(&path) {
+                Ok(toml) => {
+                    let table = rmx::toml::from_str(&toml)
+                        .with_context(|| format!("reading {}", path.display()))?;
+                    layers.push((dir.C(), table));
+                }
+                Err(e) if e.kind() == rmx::std::io::ErrorKind::NotFound => { }
+                Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+            }
+
+            for entry in rmx::std::fs::read_dir(root.join(&dir))? {
+                let entry = entry?;
+                let hidden = entry.file_name().to_string_lossy().starts_with('.');
+                if entry.file_type()?.is_dir() && !hidden {
+                    dirs.push(dir.join(entry.file_name()));
+                }
+            }
+        }
+
+        Ok(ConfigTree::from_tables(db, layers))
+    }
+
+    /// Build from `(directory, table)` pairs in any order.
+    pub fn from_tables(
+        db: &dyn crate::Db,
+        tables: Vec<(PathBuf, rmx::toml::Table)>,
+    ) -> ConfigTree {
+        let layers = tables.into_iter()
+            .sorted_by(|(a, _), (b, _)| a.cmp(b))
+            .map(|(dir, table)| ConfigLayer::new(db, dir, table))
+            .collect();
+        ConfigTree::new(db, layers)
+    }
+}
+
+/// The effective config for the source file at `path`,
+/// relative to the project root.
+pub fn effective_config<'db>(
+    db: &'db dyn crate::Db,
+    tree: ConfigTree,
+    path: &Path,
+) -> &'db ConfigFile {
+    let dir = path.parent().unwrap_or(Path::new(""));
+    directory_config(db, tree, dir.to_path_buf())
+}
+
+/// The effective config for files in `dir`, relative to the project root.
+///
+/// Layers from the root down to `dir` are merged in order.
+/// Within a section, a deeper layer's key replaces the shallower one's;
+/// arrays are replaced whole, not appended to.
+/// Keys with problems are skipped.
+#[salsa::tracked(returns(ref))]
+pub fn directory_config(
+    db: &dyn crate::Db,
+    tree: ConfigTree,
+    dir: PathBuf,
+) -> ConfigFile {
+    let mut merged = rmx::toml::Table::new();
+    let layers = tree.layers(db).iter()
+        .filter(|layer| dir.starts_with(layer.dir(db)));
+    for layer in layers {
+        for entry in layer_entries(layer.table(db)) {
+            let (Some(key), None) = (entry.key, entry.problem) else {
+                continue;
+            };
+            let section = merged.entry(entry.section)
+                .or_insert_with(|| rmx::toml::Value::Table(rmx::toml::Table::new()));
+            section.as_table_mut().X().insert(S(key), entry.value.C());
+        }
+    }
+
+    // Every merged key was checked on its own.
+    ConfigFile::from_table(merged).X()
+}
+
+/// Unknown sections and keys, and invalid values, in a config file.
+#[salsa::tracked(returns(ref))]
+pub fn config_layer_diagnostics(
+    db: &dyn crate::Db,
+    layer: ConfigLayer,
+) -> Vec<Diagnostic> {
+    let path = layer.dir(db).join(CONFIG_FILE_NAME);
+    layer_entries(layer.table(db)).into_iter()
+        .filter_map(|entry| {
+            let (code, message) = entry.problem?;
+            Some(Diagnostic {
+                code,
+                span: None,
+                message: format!("{}: {message}", path.display()),
+            })
+        })
+        .collect()
+}
+
+/// A key of a config file, or a whole section if it is not a table.
+struct Entry<'t> {
+    section: &'t str,
+    key: Option<&'t str>,
+    value: &'t rmx::toml::Value,
+    problem: Option<(Code, String)>,
+}
+
+fn layer_entries(table: &rmx::toml::Table) -> Vec<Entry<'_>> {
+    let mut entries = vec![];
+    for (section, value) in table {
+        let Some(section_table) = value.as_table() else {
+            let problem = if known_key(section, None) {
+                (Code::InvalidConfigValue, format!("`{section}` must be a table"))
+            } else {
+                (Code::UnknownConfigKey, format!("unknown section `{section}`"))
+            };
+            entries.push(Entry { section, key: None, value, problem: Some(problem) });
+            continue;
+        };
+        if !known_key(section, None) {
+            let problem = (Code::UnknownConfigKey, format!("unknown section `{section}`"));
+            entries.push(Entry { section, key: None, value, problem: Some(problem) });
+            continue;
+        }
+        for (key, value) in section_table {
+            let problem = if !known_key(section, Some(key)) {
+                Some((Code::UnknownConfigKey, format!("unknown key `{section}.{key}`")))
+            } else {
+                let mut single = rmx::toml::Table::new();
+                single.insert(S(key), value.C());
+                let mut table = rmx::toml::Table::new();
+                table.insert(S(section), rmx::toml::Value::Table(single));
+                ConfigFile::from_table(table).err().map(|e| {
+                    (Code::InvalidConfigValue, format!("invalid value for `{section}.{key}`: {:#}", e))
+                })
+            };
+            entries.push(Entry { section, key: Some(key), value, problem });
+        }
+    }
+    entries
+}
+
+/// Whether `section`, or `key` in `section`, is part of the config format.
+fn known_key(section: &str, key: Option<&str>) -> bool {
+    let keys: &[&str] = match section {
+        "format" => &["indent_width", "max_width", "wrap_comments", "tab_width", "max_blank_lines"],
+        "severity" => return key.is_none_or(|key| Code::parse(key).is_some()),
+        "chunks" => &["separators"],
+        "source_map" => &["embedded_fence", "heredocs"],
+        "lexer" => &["sigil_sets", "time_literals"],
+        "packages" => &["paths", "prelude", "overrides"],
+        "language" => &["version", "features"],
+        _ => return false,
+    };
+    key.is_none_or(|key| keys.contains(&key))
+}
+
+/// The chunker config for the project's chunk separators.
+#[salsa::tracked]
+pub fn project_chunks_config<'db>(
+    db: &'db dyn crate::Db,
+    project_config: ProjectConfig,
+) -> crate::chunks::Config<'db> {
+    crate::chunks::Config::new(
+        db,
+        project_config.chunk_separators(db).C(),
+        crate::chunks::separator_try_chunk,
+        crate::chunk::ChunkKind::Code,
+    )
+}
+
+/// The source map config for the project's `[source_map]` options.
+#[salsa::tracked]
+pub fn project_source_map_config<'db>(
+    db: &'db dyn crate::Db,
+    project_config: ProjectConfig,
+) -> crate::source_map::Config<'db> {
+    crate::source_map::Config::new(
+        db,
+        vec!['/'],
+        vec!['"'],
+        project_config.source_map(db).embedded_fence.C(),
+        project_config.source_map(db).heredocs,
+    )
+}
+
+/// The lexer config for the project's `[lexer]` options.
+#[salsa::tracked]
+pub fn project_lexer_config<'db>(
+    db: &'db dyn crate::Db,
+    project_config: ProjectConfig,
+) -> crate::lexer::Config<'db> {
+    crate::lexer::Config::new(
+        db,
+        ErrorRecovery::default(),
+        project_config.sigil_sets(db).C(),
+        project_config.literals(db).C(),
+        WhitespaceMode::Joined,
+        project_config.time_literals(db),
+    )
+}
+
+#[test]
+fn test_parse_config_file() {
+    assert_eq!(ConfigFile::parse("").X(), ConfigFile::default());
+
+    let config = ConfigFile::parse(r#"
+[format]
+indent_width = 2
+
+[severity]
+E0104 = "allow"
+
+[chunks]
+separators = [".", ";"]
+
+[source_map]
+embedded_fence = "~~~"
+heredocs = true
+
+[lexer]
+sigil_sets = ["prefix"]
+time_literals = true
+
+[packages]
+paths = ["lib"]
+prelude = [{ import_space = "sys", package = "core", module = "prelude" }]
+overrides = [{ import_space = "local", overrides = "sys" }]
+
+[language]
+version = "1.0"
+features = ["heredocs"]
+"#).X();
+    assert_eq!(config.format, FormatOptions { indent_width: 2, max_width: 100, ..FormatOptions::default() });
+    assert_eq!(
+        config.severity.severity(crate::diagnostics::Code::ImportCycle),
+        crate::diagnostics::Severity::Allow,
+    );
+    assert_eq!(config.chunk_separators, vec!['.', ';']);
+    assert_eq!(config.source_map.embedded_fence.as_deref(), Some("~~~"));
+    assert!(config.source_map.heredocs);
+    assert_eq!(config.sigil_sets, vec![SigilSet::Prefix]);
+    assert!(config.time_literals);
+    assert_eq!(config.package_paths, vec![PathBuf::from("lib")]);
+    assert_eq!(config.prelude, vec![(S("sys"), S("core"), S("prelude"))]);
+    assert_eq!(config.overrides, vec![ImportSpaceOverride { import_space: S("local"), overrides: S("sys") }]);
+    assert_eq!(config.language.version, LanguageVersion::V1_0);
+    assert_eq!(config.language.features.into_iter().collect::<Vec<_>>(), vec![crate::language::Feature::Heredocs]);
+
+    assert!(ConfigFile::parse("[fmt]\n").is_err());
+    assert!(ConfigFile::parse("[format]\nwidth = 1\n").is_err());
+    assert!(ConfigFile::parse("[source_map]\nembedded_fence = \"\"\n").is_err());
+    assert!(ConfigFile::parse("[lexer]\nsigil_sets = [\"nope\"]\n").is_err());
+    assert!(ConfigFile::parse("[packages]\nprelude = [{ module = \"prelude\" }]\n").is_err());
+    assert!(ConfigFile::parse("[packages]\noverrides = [{ import_space = \"local\" }]\n").is_err());
+    assert!(ConfigFile::parse("[language]\nversion = \"1\"\n").is_err());
+    assert!(ConfigFile::parse("[language]\nfeatures = [\"nope\"]\n").is_err());
+}
+
+#[test]
+fn test_load_config_file() {
+    let dir = rmx::tempfile::tempdir().X();
+    assert_eq!(ConfigFile::load(dir.path()).X(), ConfigFile::default());
+
+    rmx::std::fs::write(dir.path().join(CONFIG_FILE_NAME), "[format]\nmax_width = 80\n").X();
+    assert_eq!(ConfigFile::load(dir.path()).X().format.max_width, 80);
+}
+
+#[test]
+fn test_project_config_incremental() {
+    use rmx::std::sync::atomic::{AtomicUsize, Ordering};
+
+    static LEXER_CONFIG_RUNS: AtomicUsize = AtomicUsize::new(0);
+
+    #[salsa::tracked]
+    fn lex_with_project<'db>(
+        db: &'db dyn crate::Db,
+        source: crate::input::Source,
+        project_config: ProjectConfig,
+    ) -> usize {
+        LEXER_CONFIG_RUNS.fetch_add(1, Ordering::SeqCst);
+        let chunk = crate::source_map::basic_source_map(db, source);
+        let config = project_lexer_config(db, project_config);
+        crate::lexer::lex_chunk_with_config(db, chunk, config).tokens(db).len()
+    }
+
+    let mut db = crate::Database::default();
+    let source = crate::input::Source::new(&db, S("a+?b"));
+    let project_config = ProjectConfig::from_file(&db, ConfigFile::default());
+
+    assert_eq!(lex_with_project(&db, source, project_config), 3);
+    assert_eq!(LEXER_CONFIG_RUNS.load(Ordering::SeqCst), 1);
+
+    // Changing an unrelated section doesn't re-lex.
+    let mut file = ConfigFile::default();
+    file.format.max_width = 80;
+    file.severity = SeverityConfig::from_toml("[severity]\nE0104 = \"allow\"\n").X();
+    project_config.update(&mut db, file.C());
+    assert_eq!(lex_with_project(&db, source, project_config), 3);
+    assert_eq!(LEXER_CONFIG_RUNS.load(Ordering::SeqCst), 1);
+
+    file.sigil_sets = vec![];
+    project_config.update(&mut db, file);
+    assert_eq!(lex_with_project(&db, source, project_config), 4);
+    assert_eq!(LEXER_CONFIG_RUNS.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn test_directory_config_layering() {
+    let ref db = crate::Database::default();
+    let table = |toml: &str| -> rmx::toml::Table { rmx::toml::from_str(toml).X() };
+    let tree = ConfigTree::from_tables(db, vec![
+        (PathBuf::from("pkg/inner"), table("[format]\nmax_width = 60\n")),
+        (PathBuf::new(), table("[format]\nindent_width = 2\n[severity]\nE0101 = \"warn\"\n")),
+        (PathBuf::from("pkg"), table("[format]\nmax_width = 80\n[severity]\nE0104 = \"allow\"\n")),
+    ]);
+
+    let root = effective_config(db, tree, Path::new("main.bct"));
+    assert_eq!(root.format, FormatOptions { indent_width: 2, max_width: 100, ..FormatOptions::default() });
+
+    let pkg = effective_config(db, tree, Path::new("pkg/lib.bct"));
+    assert_eq!(pkg.format, FormatOptions { indent_width: 2, max_width: 80, ..FormatOptions::default() });
+    assert_eq!(pkg.severity.severity(Code::UnresolvedImport), crate::diagnostics::Severity::Warn);
+    assert_eq!(pkg.severity.severity(Code::ImportCycle), crate::diagnostics::Severity::Allow);
+
+    let inner = effective_config(db, tree, Path::new("pkg/inner/a/b.bct"));
+    assert_eq!(inner.format, FormatOptions { indent_width: 2, max_width: 60, ..FormatOptions::default() });
+
+    // `pkgx` is not inside `pkg`.
+    let sibling = effective_config(db, tree, Path::new("pkgx/lib.bct"));
+    assert_eq!(sibling.format.max_width, 100);
+}
+
+#[test]
+fn test_config_layer_diagnostics() {
+    let ref db = crate::Database::default();
+    let table: rmx::toml::Table = rmx::toml::from_str(r#"
+chunks = 1
+
+[format]
+indent = 2
+max_width = "wide"
+indent_width = 8
+
+[severity]
+E9999 = "allow"
+
+[fmt]
+max_width = 80
+"#).X();
+    let tree = ConfigTree::from_tables(db, vec![(PathBuf::from("pkg"), table)]);
+    let layer = tree.layers(db)[0];
+
+    let diagnostics: Vec<_> = config_layer_diagnostics(db, layer).iter()
+        .map(|diagnostic| (diagnostic.code, diagnostic.message.as_str()))
+        .collect();
+    assert_eq!(diagnostics.len(), 5);
+    assert_eq!(diagnostics[0], (Code::InvalidConfigValue, "pkg/bct.toml: `chunks` must be a table"));
+    assert_eq!(diagnostics[1], (Code::UnknownConfigKey, "pkg/bct.toml: unknown section `fmt`"));
+    assert_eq!(diagnostics[2], (Code::UnknownConfigKey, "pkg/bct.toml: unknown key `format.indent`"));
+    assert_eq!(diagnostics[3].0, Code::InvalidConfigValue);
+    assert!(diagnostics[3].1.starts_with("pkg/bct.toml: invalid value for `format.max_width`"));
+    assert_eq!(diagnostics[4], (Code::UnknownConfigKey, "pkg/bct.toml: unknown key `severity.E9999`"));
+
+    // The valid key still applies.
+    let config = effective_config(db, tree, Path::new("pkg/lib.bct"));
+    assert_eq!(config.format, FormatOptions { indent_width: 8, max_width: 100, ..FormatOptions::default() });
+}
+
+#[test]
+fn test_load_config_tree() {
+    let dir = rmx::tempfile::tempdir().X();
+    let ref db = crate::Database::default();
+    rmx::std::fs::create_dir_all(dir.path().join("pkg/src")).X();
+    rmx::std::fs::create_dir_all(dir.path().join(".hidden")).X();
+    rmx::std::fs::write(dir.path().join(CONFIG_FILE_NAME), "[format]\nindent_width = 2\n").X();
+    rmx::std::fs::write(dir.path().join("pkg").join(CONFIG_FILE_NAME), "[format]\nmax_width = 80\n").X();
+    rmx::std::fs::write(dir.path().join(".hidden").join(CONFIG_FILE_NAME), "[format]\nmax_width = 1\n").X();
+
+    let tree = ConfigTree::load(db, dir.path()).X();
+    let dirs: Vec<_> = tree.layers(db).iter().map(|layer| layer.dir(db).C()).collect();
+    assert_eq!(dirs, vec![PathBuf::new(), PathBuf::from("pkg")]);
+
+    let config = effective_config(db, tree, Path::new("pkg/src/lib.bct"));
+    assert_eq!(config.format, FormatOptions { indent_width: 2, max_width: 80, ..FormatOptions::default() });
+}
@@ -0,0 +1,324 @@
+//! Recording and replay of input mutations.
+//!
+//! A `Recorder` makes every change to the inputs it owns,
+//! logging each one as a line of JSON, optionally also written to a file.
+//! `Recorder::replay` applies a log to a fresh database,
+//! reproducing every revision of the original, not just the last one,
+//! which is what is needed to debug incremental invalidation.
+//!
+//! Each line is an object with an `op` field naming the mutation:
+//!
+//! - `new-source`: `source` (integer id, counting from 0), `text`.
+//! - `set-source-text`: `source`, `text`.
+//! - `add-package`: the fields of a package in a `state_archive`.
+//! - `set-module-text`: `import_space`, `package`, `module`, `text`.
+//! - `set-config-file`: `dir`, `toml`.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+use rmx::std::io::Write;
+use rmx::std::path::PathBuf;
+use salsa::Setter as _;
+
+use crate::input::Source;
+use crate::package2::{Package, PackageModule, PackageWorld, import_space_durability};
+use crate::project_config::{ConfigLayer, ConfigTree};
+use crate::loader::RESERVED_NAMES;
+use crate::state_archive::{ArchivedModule, ArchivedPackage};
+
+/// One change to the inputs.
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(tag = "op", rename_all = "kebab-case")]
+pub enum Mutation {
+    NewSource {
+        source: usize,
+        text: String,
+    },
+    SetSourceText {
+        source: usize,
+        text: String,
+    },
+    AddPackage(ArchivedPackage),
+    SetModuleText {
+        import_space: String,
+        package: String,
+        module: String,
+        text: String,
+    },
+    SetConfigFile {
+        dir: PathBuf,
+        toml: String,
+    },
+}
+
+/// Owns a set of inputs and logs every change made to them.
+pub struct Recorder {
+    sources: Vec<Source>,
+    world: PackageWorld,
+    config_tree: ConfigTree,
+    log: Vec<Mutation>,
+    sink: Option<Box<dyn Write + Send>>,
+}
+
+impl Recorder {
+    /// Create an empty world and config tree, logging only in memory.
+    pub fn new(db: &dyn crate::Db) -> Recorder {
+        Recorder {
+            sources: vec![],
+            world: PackageWorld::builder(BTreeMap::new(), BTreeMap::new())
+                .pkglib_system_durability(import_space_durability("sys"))
+                .pkglib_local_durability(import_space_durability("local"))
+                .new(db),
+            config_tree: ConfigTree::new(db, vec![]),
+            log: vec![],
+            sink: None,
+        }
+    }
+
+    /// Like `new`, also writing each mutation to `sink` as it happens.
+    pub fn with_sink(db: &dyn crate::Db, sink: Box<dyn Write + Send>) -> Recorder {
+        Recorder {
+            sink: Some(sink),
+            ..Recorder::new(db)
+        }
+    }
+
+    /// Apply `log`, as written by a recorder, to a fresh set of inputs.
+    ///
+    /// The returned recorder owns inputs equivalent to the original's
+    /// and has the same log.
+    pub fn replay(db: &mut dyn crate::Db, log: &str) -> AnyResult<Recorder> {
+        let mut recorder = Recorder::new(db);
+        for (index, line) in log.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_number = index.checked_add(1).X();
+            let mutation: Mutation = rmx::serde_json::from_str(line)
+                .with_context(|| format!("line {line_number}"))?;
+            recorder.apply(db, mutation)
+                .with_context(|| format!("line {line_number}"))?;
+        }
+        Ok(recorder)
+    }
+
+    pub fn world(&self) -> PackageWorld {
+        self.world
+    }
+
+    pub fn config_tree(&self) -> ConfigTree {
+        self.config_tree
+    }
+
+    /// A source created by `new_source`, by id.
+    pub fn source(&self, id: usize) -> Option<Source> {
+        self.sources.get(id).copied()
+    }
+
+    pub fn log(&self) -> &[Mutation] {
+        &self.log
+    }
+
+    /// The log as written to the sink, one JSON object per line.
+    pub fn log_text(&self) -> String {
+        self.log.iter()
+            .map(|mutation| format!("{}\n", rmx::serde_json::to_string(mutation).X()))
+            .collect()
+    }
+
+    /// Create a standalone source, returning its id.
+    pub fn new_source(&mut self, db: &mut dyn crate::Db, text: &str) -> AnyResult<usize> {
+        let source = self.sources.len();
+        self.apply(db, Mutation::NewSource { source, text: S(text) })?;
+        Ok(source)
+    }
+
+    pub fn set_source_text(&mut self, db: &mut dyn crate::Db, source: usize, text: &str) -> AnyResult<()> {
+        self.apply(db, Mutation::SetSourceText { source, text: S(text) })
+    }
+
+    /// Add a package to the world, replacing any with the same name.
+    pub fn add_package(&mut self, db: &mut dyn crate::Db, package: ArchivedPackage) -> AnyResult<()> {
+        self.apply(db, Mutation::AddPackage(package))
+    }
+
+    pub fn set_module_text(
+        &mut self,
+        db: &mut dyn crate::Db,
+        import_space: &str,
+        package: &str,
+        module: &str,
+        text: &str,
+    ) -> AnyResult<()> {
+        self.apply(db, Mutation::SetModuleText {
+            import_space: S(import_space),
+            package: S(package),
+            module: S(module),
+            text: S(text),
+        })
+    }
+
+    /// Add or replace the `bct.toml` in `dir`.
+    pub fn set_config_file(&mut self, db: &mut dyn crate::Db, dir: PathBuf, toml: &str) -> AnyResult<()> {
+        self.apply(db, Mutation::SetConfigFile { dir, toml: S(toml) })
+    }
+
+    /// Apply a mutation, then log it.
+    ///
+    /// Nothing is logged if the mutation fails.
+    pub fn apply(&mut self, db: &mut dyn crate::Db, mutation: Mutation) -> AnyResult<()> {
+        match &mutation {
+            Mutation::NewSource { source, text } => {
+                if *source != self.sources.len() {
+                    bail!("new source has id {source}, expected {}", self.sources.len());
+                }
+                self.sources.push(Source::new(db, text.C()));
+            }
+            Mutation::SetSourceText { source, text } => {
+                let source = self.source(*source)
+                    .ok_or_else(|| anyhow!("no source with id {source}"))?;
+                source.set_text(db).to(text.C());
+            }
+            Mutation::AddPackage(package) => {
+                let ArchivedPackage { import_space, name, import_space_aliases, main_module, modules } = package;
+                let mut packages = self.pkglib(db, import_space)?.C();
+                if let Some(alias) = import_space_aliases.keys().find(|alias| RESERVED_NAMES.contains(&alias.as_str())) {
+                    bail!("package {name} aliases reserved import space `{alias}`");
+                }
+                let durability = import_space_durability(import_space);
+                let modules = modules.iter().map(|module| {
+                    let source = Source::builder(module.text.C())
+                        .load_error(module.load_error.C())
+                        .durability(durability)
+                        .new(db);
+                    let package_module = PackageModule::builder(module.name.C(), source)
+                        .visibility(module.visibility)
+                        .no_prelude(module.no_prelude)
+                        .durability(durability)
+                        .new(db);
+                    (module.name.C(), package_module)
+                }).collect();
+                let package = Package::builder(name.C(), modules)
+                    .import_space_aliases(import_space_aliases.C())
+                    .main_module(main_module.C())
+                    .durability(durability)
+                    .new(db);
+                packages.insert(name.C(), package);
+                match import_space.as_str() {
+                    "sys" => self.world.set_pkglib_system(db).with_durability(durability).to(packages),
+                    _ => self.world.set_pkglib_local(db).with_durability(durability).to(packages),
+                };
+            }
+            Mutation::SetModuleText { import_space, package, module, text } => {
+                let package_module = self.pkglib(db, import_space)?.get(package)
+                    .and_then(|package| package.modules(db).get(module).copied())
+                    .ok_or_else(|| anyhow!("no module {import_space}/{package}/{module}"))?;
+                let durability = import_space_durability(import_space);
+                package_module.text(db).set_text(db).with_durability(durability).to(text.C());
+            }
+            Mutation::SetConfigFile { dir, toml } => {
+                let table: rmx::toml::Table = rmx::toml::from_str(toml)
+                    .with_context(|| format!("config file in {dir:?}"))?;
+                let existing = self.config_tree.layers(db).iter()
+                    .find(|layer| layer.dir(db) == dir)
+                    .copied();
+                match existing {
+                    Some(layer) => {
+                        layer.set_table(db).to(table);
+                    }
+                    None => {
+                        let mut layers = self.config_tree.layers(db).C();
+                        layers.push(ConfigLayer::new(db, dir.C(), table));
+                        layers.sort_by(|a, b| a.dir(db).cmp(b.dir(db)));
+                        self.config_tree.set_layers(db).to(layers);
+                    }
+                }
+            }
+        }
+
+        if let Some(sink) = &mut self.sink {
+            writeln!(sink, "{}", rmx::serde_json::to_string(&mutation).X())?;
+            sink.flush()?;
+        }
+        self.log.push(mutation);
+        Ok(())
+    }
+
+    fn pkglib<'db>(
+        &self,
+        db: &'db dyn crate::Db,
+        import_space: &str,
+    ) -> AnyResult<&'db BTreeMap<String, Package>> {
+        match import_space {
+            "sys" => Ok(self.world.pkglib_system(db)),
+            "local" => Ok(self.world.pkglib_local(db)),
+            _ => bail!("unknown import space `{import_space}`"),
+        }
+    }
+}
+
+#[test]
+fn test_record_and_replay() {
+    use crate::package2::Visibility;
+
+    let dir = rmx::tempfile::tempdir().X();
+    let log_path = dir.path().join("mutations.jsonl");
+
+    let mut db = crate::Database::default();
+    let mut recorder = Recorder::with_sink(&db, Box::new(rmx::std::fs::File::create(&log_path).X()));
+    let module = |name: &str, text: &str| ArchivedModule {
+        name: S(name),
+        visibility: Visibility::Public,
+        no_prelude: false,
+        text: S(text),
+        load_error: None,
+    };
+    let package = |import_space: &str, name: &str, modules| ArchivedPackage {
+        import_space: S(import_space),
+        name: S(name),
+        import_space_aliases: BTreeMap::new(),
+        main_module: None,
+        modules,
+    };
+    recorder.add_package(&mut db, package("sys", "core", vec![module("core", "")])).X();
+    recorder.add_package(&mut db, ArchivedPackage {
+        import_space_aliases: BTreeMap::from([(S("vendor"), S("sys"))]),
+        main_module: Some(S("main")),
+        ..package("local", "app", vec![
+            module("main", "import module sys/core"),
+            ArchivedModule { load_error: Some(S("stream did not contain valid UTF-8")), ..module("broken", "") },
+        ])
+    }).X();
+    recorder.set_module_text(&mut db, "local", "app", "main", "import module sys/alloc").X();
+    let scratch = recorder.new_source(&mut db, "(a").X();
+    recorder.set_source_text(&mut db, scratch, "(a)").X();
+    recorder.set_config_file(&mut db, PathBuf::new(), "[format]\nindent_width = 2\n").X();
+    recorder.set_config_file(&mut db, PathBuf::new(), "[format]\nindent_width = 8\n").X();
+
+    // Failed mutations are not logged.
+    assert!(recorder.set_module_text(&mut db, "local", "app", "nope", "").is_err());
+    assert!(recorder.add_package(&mut db, ArchivedPackage {
+        import_space_aliases: BTreeMap::from([(S("super"), S("sys"))]),
+        ..package("local", "bad", vec![])
+    }).is_err());
+    assert_eq!(recorder.log().len(), 7);
+
+    let log = rmx::std::fs::read_to_string(&log_path).X();
+    assert_eq!(log, recorder.log_text());
+    assert!(log.lines().next().X().starts_with(r#"{"op":"add-package""#));
+
+    let mut db2 = crate::Database::default();
+    let replayed = Recorder::replay(&mut db2, &log).X();
+    assert_eq!(replayed.log(), recorder.log());
+    assert_eq!(
+        db2.export_state(replayed.world(), replayed.config_tree()),
+        db.export_state(recorder.world(), recorder.config_tree()),
+    );
+    assert_eq!(replayed.source(scratch).X().text(&db2), "(a)");
+    let config = crate::project_config::effective_config(&db2, replayed.config_tree(), rmx::std::path::Path::new("a.bct"));
+    assert_eq!(config.format.indent_width, 8);
+
+    assert!(Recorder::replay(&mut crate::Database::default(), "{\"op\":\"set-source-text\",\"source\":0,\"text\":\"\"}\n").is_err());
+    assert!(Recorder::replay(&mut crate::Database::default(), "not json\n").is_err());
+}
@@ -0,0 +1,516 @@
+//! Analysis without handling the database directly.
+//!
+//! The passes of this crate are salsa queries over a `Database`,
+//! and using them means creating inputs, keeping them to reuse
+//! earlier results, and threading the database through each call.
+//! A `Session` does that for applications that just want diagnostics:
+//!
+//! ```ignore
+//! let mut session = Session::builder()
+//!     .project_root("path/to/project")
+//!     .build()?;
+//! let diagnostics = session.analyze_string("scratch", "a = (1");
+//! let workspace = session.resolve_workspace()?;
+//! ```
+//!
+//! A session keeps one `Source` per name, or per path for files,
+//! so analyzing changed text again only redoes the work that changed.
+//! Sessions are `Send`, to be moved to a worker thread.
+//!
+//! To use a session from several threads at once,
+//! e.g. in a language server or to check files in parallel,
+//! put it in a `SharedSession`.
+//! Writes, which change the session's inputs, take turns,
+//! and reads go through snapshots of the database,
+//! which run queries in parallel with each other.
+//! A write waits until every snapshot is dropped,
+//! and cancels the queries running on them,
+//! so snapshots should be dropped as soon as they are done.
+//!
+//! The `_within` methods analyze with a time budget,
+//! e.g. for diagnostics as the user types.
+//! Lexing and bracing always finish,
+//! but later passes are skipped once the budget runs out,
+//! and the `Analysis` says it was truncated.
+//! The budget is checked between passes,
+//! so a slow pass can overrun it.
+
+use rmx::prelude::*;
+use rmx::std::collections::{BTreeMap, HashSet};
+use rmx::std::path::{Path, PathBuf};
+use rmx::std::str::Utf8Error;
+use rmx::std::sync::Mutex;
+use rmx::std::time::{Duration, Instant};
+use salsa::{Cancelled, Setter as _};
+
+use crate::diagnostics::Diagnostic;
+use crate::input::Source;
+use crate::lexer::{LiteralRegistry, TokenKind};
+use crate::loader::{FileRegistry, PackageLoader};
+use crate::package2::PackageWorld;
+use crate::project_config::{ConfigFile, ProjectConfig};
+use crate::text::ByteSpan;
+
+/// The UTF-8 byte order mark, skipped by `Session::analyze_str`.
+const BOM: &str = "\u{feff}";
+
+/// Describes a `Session` to create.
+#[derive(Clone, Default, Debug)]
+pub struct SessionBuilder {
+    project_root: Option<PathBuf>,
+    sys_paths: Vec<PathBuf>,
+    literals: LiteralRegistry,
+}
+
+impl SessionBuilder {
+    /// Use the project whose `bct.toml` is in `root`,
+    /// for its configuration and its local packages.
+    pub fn project_root(mut self, root: impl Into<PathBuf>) -> SessionBuilder {
+        self.project_root = Some(root.into());
+        self
+    }
+
+    /// Add a directory of system packages.
+    pub fn sys_path(mut self, path: impl Into<PathBuf>) -> SessionBuilder {
+        self.sys_paths.push(path.into());
+        self
+    }
+
+    /// Lex the custom literals of `literals`, see `LiteralRegistry`.
+    pub fn literals(mut self, literals: LiteralRegistry) -> SessionBuilder {
+        self.literals = literals;
+        self
+    }
+
+    /// Read the project configuration, if any, and create the session.
+    pub fn build(self) -> AnyResult<Session> {
+        let mut db = crate::Database::default();
+        let (config, loader) = match &self.project_root {
+            Some(root) => {
+                let config = ConfigFile::load(root)?;
+                let loader = PackageLoader::from_config(root, &config, self.sys_paths);
+                (config, loader)
+            }
+            None => {
+                let loader = PackageLoader { sys_paths: self.sys_paths, local_paths: vec![] };
+                (ConfigFile::default(), loader)
+            }
+        };
+        let project_config = ProjectConfig::from_file(&db, config);
+        project_config.set_literals(&mut db).to(self.literals);
+        Ok(Session { db, project_config, loader, sources: default() })
+    }
+}
+
+/// A database and the inputs created in it.
+pub struct Session {
+    db: crate::Database,
+    project_config: ProjectConfig,
+    loader: PackageLoader,
+    /// Sources analyzed by name.
+    sources: BTreeMap<String, Source>,
+}
+
+/// A workspace read by `Session::resolve_workspace`.
+pub struct Workspace {
+    pub world: PackageWorld,
+    /// Problems loading the world's files and in their sources,
+    /// paired with the file's path.
+    pub diagnostics: Vec<(PathBuf, Diagnostic)>,
+}
+
+/// The result of analyzing a source with a time budget.
+#[derive(Clone, Debug)]
+pub struct Analysis {
+    /// The kind and span of each token.
+    pub tokens: Vec<(TokenKind, ByteSpan)>,
+    pub diagnostics: Vec<Diagnostic>,
+    /// Whether the budget ran out,
+    /// leaving only the diagnostics of lexing and bracing.
+    pub truncated: bool,
+}
+
+impl Default for Session {
+    /// A session with the default configuration and no packages.
+    fn default() -> Session {
+        SessionBuilder::default().build().X()
+    }
+}
+
+impl Session {
+    pub fn builder() -> SessionBuilder {
+        SessionBuilder::default()
+    }
+
+    /// The database, for queries the session has no method for.
+    pub fn db(&self) -> &crate::Database {
+        &self.db
+    }
+
+    /// The diagnostics of `text`, in source order of each kind.
+    ///
+    /// `name` identifies the text between calls,
+    /// e.g. an editor buffer's name,
+    /// so that analyzing new text for it reuses what didn't change.
+    pub fn analyze_string(&mut self, name: &str, text: &str) -> Vec<Diagnostic> {
+        let source = self.set_text(name, text);
+        source_diagnostics(&self.db, self.project_config, source)
+    }
+
+    /// Like `analyze_string`, but stop after `budget`.
+    pub fn analyze_string_within(&mut self, name: &str, text: &str, budget: Duration) -> Analysis {
+        let deadline = Instant::now().checked_add(budget);
+        let source = self.set_text(name, text);
+        analyze_source(&self.db, self.project_config, source, deadline)
+    }
+
+    /// Like `analyze_string`, but skipping a leading byte order mark.
+    ///
+    /// Spans are still offsets into `text`, byte order mark included.
+    pub fn analyze_str(&mut self, name: &str, text: &str) -> Vec<Diagnostic> {
+        let Some(text) = text.strip_prefix(BOM) else {
+            return self.analyze_string(name, text);
+        };
+        let mut diagnostics = self.analyze_string(name, text);
+        for span in diagnostics.iter_mut().filter_map(|diagnostic| diagnostic.span.as_mut()) {
+            *span = span.start.checked_add(BOM.len()).X()..span.end.checked_add(BOM.len()).X();
+        }
+        diagnostics
+    }
+
+    /// Like `analyze_str`, for text that may not be UTF-8,
+    /// e.g. a memory-mapped file.
+    pub fn analyze_bytes(&mut self, name: &str, bytes: &[u8]) -> Result<Vec<Diagnostic>, Utf8Error> {
+        Ok(self.analyze_str(name, rmx::std::str::from_utf8(bytes)?))
+    }
+
+    /// The diagnostics of the file at `path`,
+    /// or of its overlay if `Database::set_overlay` gave it one.
+    pub fn analyze_file(&mut self, path: &Path) -> AnyResult<Vec<Diagnostic>> {
+        let name = path.to_string_lossy();
+        if let Some(text) = self.db.overlay(path) {
+            let text = S(text);
+            return Ok(self.analyze_str(&name, &text));
+        }
+        crate::mmap::with_file_bytes(path, |bytes| self.analyze_bytes(&name, bytes))
+            .with_context(|| format!("reading {}", path.display()))?
+            .with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Shadow the file at `path` with `text`, e.g. an unsaved editor buffer.
+    ///
+    /// See `Database::set_overlay`.
+    pub fn set_overlay(&mut self, path: &Path, text: String) {
+        self.db.set_overlay(path, text);
+    }
+
+    /// Go back to the file on disk at `path`.
+    pub fn clear_overlay(&mut self, path: &Path) {
+        self.db.clear_overlay(path);
+    }
+
+    /// Load the project's packages and analyze each module.
+    ///
+    /// Loading again reuses the analysis of unchanged modules.
+    pub fn resolve_workspace(&mut self) -> AnyResult<Workspace> {
+        let loaded = self.loader.load(&mut self.db)?;
+        let world = loaded.world;
+        let mut diagnostics = loaded.diagnostics;
+
+        let module_sources: HashSet<Source> = world.pkglib_system(&self.db).values()
+            .chain(world.pkglib_local(&self.db).values())
+            .flat_map(|package| package.modules(&self.db).values())
+            .map(|module| module.text(&self.db))
+            .collect();
+        let files = FileRegistry::try_get(&self.db)
+            .map(|registry| registry.files(&self.db).C())
+            .unwrap_or_default();
+        for (path, file) in files {
+            let source = file.source(&self.db);
+            if module_sources.contains(&source) {
+                for diagnostic in source_diagnostics(&self.db, self.project_config, source) {
+                    diagnostics.push((path.C(), diagnostic));
+                }
+            }
+        }
+
+        Ok(Workspace { world, diagnostics })
+    }
+
+    /// Set the text named `name` to `text`, returning its source.
+    pub fn set_text(&mut self, name: &str, text: &str) -> Source {
+        match self.sources.get(name).copied() {
+            Some(source) => {
+                if source.text(&self.db) != text {
+                    source.set_text(&mut self.db).to(S(text));
+                }
+                source
+            }
+            None => {
+                let source = Source::new(&self.db, S(text));
+                self.sources.insert(S(name), source);
+                source
+            }
+        }
+    }
+}
+
+/// A `Session` for several threads.
+pub struct SharedSession {
+    session: Mutex<Session>,
+}
+
+/// A read-only view of a `SharedSession`, as of when it was taken.
+///
+/// Queries on a snapshot don't block, but are cancelled by writes.
+pub struct SessionSnapshot {
+    db: crate::Database,
+    project_config: ProjectConfig,
+    sources: BTreeMap<String, Source>,
+}
+
+impl SharedSession {
+    pub fn new(session: Session) -> SharedSession {
+        SharedSession { session: Mutex::new(session) }
+    }
+
+    /// Change the session, after the writes of other threads.
+    ///
+    /// Blocks while another thread writes or takes a snapshot.
+    /// Changing an input, e.g. by `Session::set_text`,
+    /// also blocks until every snapshot is dropped,
+    /// cancelling the queries running on them.
+    pub fn write<R>(&self, f: impl FnOnce(&mut Session) -> R) -> R {
+        f(&mut self.session.lock().X())
+    }
+
+    /// A snapshot of the session as of now.
+    ///
+    /// Blocks while another thread writes.
+    pub fn snapshot(&self) -> SessionSnapshot {
+        let session = self.session.lock().X();
+        SessionSnapshot {
+            db: session.db.clone(),
+            project_config: session.project_config,
+            sources: session.sources.C(),
+        }
+    }
+}
+
+impl SessionSnapshot {
+    pub fn db(&self) -> &crate::Database {
+        &self.db
+    }
+
+    /// The source named `name` by `Session::set_text` or `analyze_string`.
+    pub fn source(&self, name: &str) -> Option<Source> {
+        self.sources.get(name).copied()
+    }
+
+    /// The diagnostics of `source`, as of the snapshot.
+    ///
+    /// Fails if a write cancels the analysis,
+    /// in which case a new snapshot sees the write.
+    pub fn analyze(&self, source: Source) -> Result<Vec<Diagnostic>, Cancelled> {
+        Cancelled::catch(|| source_diagnostics(&self.db, self.project_config, source))
+    }
+
+    /// Like `analyze`, but stop after `budget`.
+    pub fn analyze_within(&self, source: Source, budget: Duration) -> Result<Analysis, Cancelled> {
+        let deadline = Instant::now().checked_add(budget);
+        Cancelled::catch(|| analyze_source(&self.db, self.project_config, source, deadline))
+    }
+}
+
+/// Brace errors, then confusable words, as `bcts check` reports them.
+fn source_diagnostics(
+    db: &dyn crate::Db,
+    project_config: ProjectConfig,
+    source: Source,
+) -> Vec<Diagnostic> {
+    analyze_source(db, project_config, source, None).diagnostics
+}
+
+/// The tokens and diagnostics of `source`,
+/// skipping the passes after bracing if `deadline` has passed.
+fn analyze_source(
+    db: &dyn crate::Db,
+    project_config: ProjectConfig,
+    source: Source,
+    deadline: Option<Instant>,
+) -> Analysis {
+    let chunk = crate::source_map::basic_source_map(db, source);
+    let lexer_config = crate::project_config::project_lexer_config(db, project_config);
+    let chunk_lex = crate::lexer::lex_chunk_with_config(db, chunk, lexer_config);
+    let bracer = crate::bracer::bracer(db, chunk_lex);
+    let tokens = chunk_lex.tokens(db).iter()
+        .map(|token| (token.kind(db), token.text(db).range(db)))
+        .collect();
+    let mut diagnostics = crate::diagnostics::bracer_diagnostics(db, bracer);
+
+    let truncated = deadline.is_some_and(|deadline| Instant::now() >= deadline);
+    if !truncated {
+        diagnostics.extend(crate::confusables::confusable_diagnostics(db, bracer));
+    }
+    Analysis { tokens, diagnostics, truncated }
+}
+
+#[test]
+fn test_session() {
+    use crate::diagnostics::Code;
+
+    fn assert_send<T: Send>() {}
+    assert_send::<Session>();
+
+    let codes = |diagnostics: Vec<Diagnostic>| -> Vec<Code> {
+        diagnostics.into_iter().map(|diagnostic| diagnostic.code).collect()
+    };
+    let mut session = Session::default();
+    assert_eq!(codes(session.analyze_string("buffer", "a = (1")), vec![Code::UnclosedBrace]);
+    let source = session.sources["buffer"];
+    assert_eq!(codes(session.analyze_string("buffer", "a = (1)")), vec![]);
+    assert!(session.sources["buffer"] == source);
+    assert_eq!(source.text(session.db()), "a = (1)");
+
+    let dir = rmx::tempfile::tempdir().X();
+    let write = |path: &str, text: &str| {
+        let path = dir.path().join(path);
+        rmx::std::fs::create_dir_all(path.parent().X()).X();
+        rmx::std::fs::write(path, text).X();
+    };
+    write("bct.toml", "[packages]\npaths = [\"lib\"]\n");
+    write("sys/core/core.bct", "core");
+    write("lib/app/main.bct", "main = [1");
+    write("lib/app/ui/window.bct", "window");
+
+    let mut session = Session::builder()
+        .project_root(dir.path())
+        .sys_path(dir.path().join("sys"))
+        .build().X();
+    let workspace = session.resolve_workspace().X();
+    assert_eq!(workspace.world.pkglib_local(session.db())["app"].modules(session.db()).len(), 2);
+    let diagnostics: Vec<_> = workspace.diagnostics.iter()
+        .map(|(path, diagnostic)| (path.ends_with("lib/app/main.bct"), diagnostic.code))
+        .collect();
+    assert_eq!(diagnostics, vec![(true, Code::UnclosedBrace)]);
+
+    let main_path = dir.path().join("lib/app/main.bct");
+    session.set_overlay(&main_path, S("main = [1]"));
+    assert_eq!(codes(session.analyze_file(&main_path).X()), vec![]);
+    assert!(session.resolve_workspace().X().diagnostics.is_empty());
+}
+
+#[test]
+fn test_shared_session() {
+    use crate::diagnostics::Code;
+    use rmx::std::{thread, time::Duration};
+
+    fn assert_sync<T: Send + Sync>() {}
+    assert_sync::<SharedSession>();
+
+    let shared = SharedSession::new(Session::default());
+    shared.write(|session| {
+        session.set_text("a", "(1");
+        session.set_text("b", "[2]");
+    });
+
+    // Snapshots analyze in parallel.
+    let codes: Vec<Vec<Code>> = thread::scope(|scope| {
+        let threads: Vec<_> = ["a", "b"].into_iter().map(|name| {
+            let snapshot = shared.snapshot();
+            scope.spawn(move || {
+                let source = snapshot.source(name).X();
+                snapshot.analyze(source).X().into_iter().map(|diagnostic| diagnostic.code).collect()
+            })
+        }).collect();
+        threads.into_iter().map(|thread| thread.join().unwrap()).collect()
+    });
+    assert_eq!(codes, vec![vec![Code::UnclosedBrace], vec![]]);
+
+    // A write waits for snapshots to be dropped.
+    let snapshot = shared.snapshot();
+    thread::scope(|scope| {
+        let writer = scope.spawn(|| shared.write(|session| session.set_text("a", "(1)")));
+        thread::sleep(Duration::from_millis(50));
+        assert!(!writer.is_finished());
+        drop(snapshot);
+        writer.join().unwrap();
+    });
+    let snapshot = shared.snapshot();
+    assert_eq!(snapshot.analyze(snapshot.source("a").X()).X(), vec![]);
+    assert!(snapshot.source("c").is_none());
+}
+
+#[test]
+fn test_analyze_within() {
+    use crate::diagnostics::Code;
+
+    let mut session = Session::default();
+    let text = "nаme = (1";
+    let codes = |analysis: &Analysis| {
+        analysis.diagnostics.iter().map(|diagnostic| diagnostic.code).collect::<Vec<_>>()
+    };
+
+    let analysis = session.analyze_string_within("a", text, Duration::ZERO);
+    assert!(analysis.truncated);
+    assert_eq!(codes(&analysis), vec![Code::UnclosedBrace]);
+    assert_eq!(analysis.tokens.len(), 6);
+    assert_eq!(analysis.tokens[0], (TokenKind::Word, 0..5));
+
+    let analysis = session.analyze_string_within("a", text, Duration::MAX);
+    assert!(!analysis.truncated);
+    assert_eq!(analysis.diagnostics, session.analyze_string("a", text));
+    assert_eq!(codes(&analysis), vec![Code::UnclosedBrace, Code::MixedScriptIdentifier]);
+
+    let shared = SharedSession::new(session);
+    let snapshot = shared.snapshot();
+    let analysis = snapshot.analyze_within(snapshot.source("a").X(), Duration::ZERO).X();
+    assert!(analysis.truncated);
+    assert_eq!(codes(&analysis), vec![Code::UnclosedBrace]);
+}
+
+#[test]
+fn test_session_literals() {
+    use crate::diagnostics::Code;
+
+    let mut literals = LiteralRegistry::default();
+    let tag = literals.register("path", |text| match text.starts_with('/') {
+        true => Err(S("expected a relative path")),
+        false => Ok(()),
+    });
+    let mut session = Session::builder().literals(literals).build().X();
+    let analysis = session.analyze_string_within("buffer", r#"a = path"lib/a.bct""#, Duration::MAX);
+    assert_eq!(analysis.tokens.last(), Some(&(TokenKind::Literal(tag), 4..19)));
+    assert_eq!(analysis.diagnostics, vec![]);
+
+    let diagnostics = session.analyze_string("buffer", r#"a = path"/a.bct""#);
+    assert_eq!(diagnostics, vec![Diagnostic {
+        code: Code::InvalidLiteral,
+        span: Some(4..16),
+        message: S("invalid `path` literal: expected a relative path"),
+    }]);
+}
+
+#[test]
+fn test_analyze_bytes() {
+    use crate::diagnostics::Code;
+
+    let mut session = Session::default();
+    let spans = |diagnostics: Vec<Diagnostic>| -> Vec<ByteSpan> {
+        diagnostics.into_iter().map(|diagnostic| diagnostic.span.X()).collect()
+    };
+    assert_eq!(spans(session.analyze_bytes("buffer", b"a = (1").X()), vec![4..6]);
+    // Spans count the byte order mark.
+    assert_eq!(spans(session.analyze_bytes("buffer", b"\xef\xbb\xbfa = (1").X()), vec![7..9]);
+    assert_eq!(spans(session.analyze_str("buffer", "\u{feff}a = (1")), vec![7..9]);
+    assert_eq!(session.sources["buffer"].text(session.db()), "a = (1");
+
+    let error = session.analyze_bytes("buffer", b"a = \xff").unwrap_err();
+    assert_eq!(error.valid_up_to(), 4);
+
+    // Without skipping, the byte order mark is an invalid token.
+    let codes: Vec<_> = session.analyze_string("buffer", "\u{feff}a").into_iter()
+        .map(|diagnostic| diagnostic.code)
+        .collect();
+    assert_eq!(codes, vec![Code::InvalidToken]);
+}
@@ -1,11 +1,12 @@
 use rmx::prelude::*;
 
+use std::collections::BTreeMap;
 use std::ops::Range;
 use std::{iter, mem};
 
 use crate::input::Source;
 use crate::text::{Text, SubText};
-use crate::chunk::Chunk;
+use crate::chunk::{Chunk, ChunkInvariantError, ChunkKind, ErrorReason};
 
 #[salsa::tracked]
 pub struct Config<'db> {
@@ -13,12 +14,31 @@ pub struct Config<'db> {
     comment_start_chars: Vec<char>,
     #[returns(ref)]
     string_start_chars: Vec<char>,
+    /// Delimiter opening and closing embedded regions, e.g. "```".
+    ///
+    /// Must start with an ASCII char.
+    #[returns(ref)]
+    embedded_fence: Option<String>,
+    /// Whether `<<TERMINATOR` starts a heredoc string.
+    heredocs: bool,
     // fixme had to remove configurability in salsa upgrade
     // fixme why does chunks::Config work? - because one field derives correctly, but two doesn't
     //parse_comment: fn(&str) -> Option<Result<usize, usize>>,
     //parse_string: fn(&str) -> Option<Result<usize, usize>>,
 }
 
+/// Source map options a project can set, the `[source_map]` section.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, salsa::Update)]
+#[derive(serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SourceMapOptions {
+    /// Delimiter opening and closing embedded regions, e.g. "```",
+    /// or `None` for no embedded regions.
+    pub embedded_fence: Option<String>,
+    /// Whether `<<TERMINATOR` starts a heredoc string.
+    pub heredocs: bool,
+}
+
 #[salsa::tracked]
 pub fn basic_source_map<'db>(
     db: &'db dyn crate::Db,
@@ -31,26 +51,84 @@ pub fn basic_source_map<'db>(
     )
 }
 
+/// `basic_source_map`, checked by `Chunk::check`.
+#[salsa::tracked]
+pub fn try_basic_source_map<'db>(
+    db: &'db dyn crate::Db,
+    source: Source,
+) -> Result<Chunk<'db>, ChunkInvariantError> {
+    let chunk = basic_source_map(db, source);
+    chunk.check(db)?;
+    Ok(chunk)
+}
+
 #[salsa::tracked]
 pub fn source_map<'db>(
     db: &'db dyn crate::Db,
     source: Source,
     config: Config<'db>,
 ) -> Chunk<'db> {
-    let mut state = State {
+    let _span = tracing::debug_span!("source_map", text_len = source.text(db).len()).entered();
+    crate::metrics::record(|metrics| metrics.query_executed("source_map"));
+    let state = State {
         db,
         config,
-        source,
+        text: source.text(db),
         position: 0,
         chunk_wip: ChunkWip {
             chunk_start: 0,
             comments: vec![],
             strings: vec![],
             errors: vec![],
+            error_reasons: vec![],
+            embedded: vec![],
         },
     };
 
-    state.map()
+    // fixme bad clone of full source
+    state.map(Text::new(db, S(source.text(db))), ChunkKind::Code)
+}
+
+/// Source map configuration chosen by the kind of each chunk.
+///
+/// Chunks of kinds without an entry keep the ranges
+/// of the source map they were split from.
+#[salsa::tracked]
+pub struct KindConfig<'db> {
+    #[returns(ref)]
+    pub configs: BTreeMap<ChunkKind, Config<'db>>,
+}
+
+/// Map `chunk` again with the config for its kind, see `KindConfig`.
+///
+/// The chunk is split from a source mapped as a whole,
+/// so it is not split inside the ranges of that mapping,
+/// but its own ranges are those of its kind's config.
+#[salsa::tracked]
+pub fn source_map_chunk_by_kind<'db>(
+    db: &'db dyn crate::Db,
+    chunk: Chunk<'db>,
+    kind_config: KindConfig<'db>,
+) -> Chunk<'db> {
+    let Some(config) = kind_config.configs(db).get(&chunk.kind(db)).copied() else {
+        return chunk;
+    };
+    let state = State {
+        db,
+        config,
+        text: chunk.text(db).as_str(db),
+        position: 0,
+        chunk_wip: ChunkWip {
+            chunk_start: 0,
+            comments: vec![],
+            strings: vec![],
+            errors: vec![],
+            error_reasons: vec![],
+            embedded: vec![],
+        },
+    };
+
+    state.map(chunk.text(db), chunk.kind(db))
 }
 
 #[salsa::tracked]
@@ -61,6 +139,8 @@ pub fn basic_config<'db>(
         db,
         vec!['/'],
         vec!['"'],
+        None,
+        false,
         //basic_parse_comment,
         //basic_parse_string,
     )
@@ -69,7 +149,7 @@ pub fn basic_config<'db>(
 struct State<'db> {
     db: &'db dyn crate::Db,
     config: Config<'db>,
-    source: Source,
+    text: &'db str,
     position: usize,
     chunk_wip: ChunkWip,
 }
@@ -80,16 +160,22 @@ struct ChunkWip {
     comments: Vec<Range<usize>>,
     strings: Vec<Range<usize>>,
     errors: Vec<Range<usize>>,
+    error_reasons: Vec<ErrorReason>,
+    embedded: Vec<Range<usize>>,
 }
 
 impl<'db> State<'db> {
-    fn map(mut self) -> Chunk<'db> {
+    fn map(mut self, text: Text<'db>, kind: ChunkKind) -> Chunk<'db> {
         let all_start_chars =
             self.config.comment_start_chars(self.db).iter().copied().chain(
                 self.config.string_start_chars(self.db).iter().copied()
+            ).chain(
+                self.config.embedded_fence(self.db).iter().map(|fence| fence.chars().next().X())
+            ).chain(
+                self.config.heredocs(self.db).then_some('<')
             ).collect::<Vec<_>>();
 
-        let text_all = self.source.text(self.db);
+        let text_all = self.text;
 
         loop {
             let text_remaining = &text_all[self.position..];
@@ -101,6 +187,16 @@ impl<'db> State<'db> {
                     self.position = self.position.checked_add(start_char_index).X();
                     let text_remaining = &text_remaining[start_char_index..];
 
+                    if let Some(parse_fence_res) = self.parse_fence(text_remaining) {
+                        self.step_fence(parse_fence_res);
+                        continue;
+                    }
+
+                    if let Some(parse_heredoc_res) = self.parse_heredoc(text_remaining) {
+                        self.step_heredoc(parse_heredoc_res);
+                        continue;
+                    }
+
                     let parse_comment_res = self.parse_comment(text_remaining);
                     let parse_string_res = self.parse_string(text_remaining);
 
@@ -117,11 +213,13 @@ impl<'db> State<'db> {
 
         Chunk::new(
             self.db,
-            // fixme bad clone of full source
-            Text::new(self.db, S(text_all)),
+            text,
             mem::take(&mut self.chunk_wip.comments),
             mem::take(&mut self.chunk_wip.strings),
             mem::take(&mut self.chunk_wip.errors),
+            mem::take(&mut self.chunk_wip.error_reasons),
+            mem::take(&mut self.chunk_wip.embedded),
+            kind,
         )
     }
 
@@ -140,28 +238,117 @@ impl<'db> State<'db> {
             }
             (Some(Err(comment_bytes)), None) => {
                 let chunk_end = chunk_offset.checked_add(comment_bytes).X();
+                let text_all = self.text;
+                let depth = nested_comment_end(&text_all[self.position..]).err().X();
                 self.chunk_wip.errors.push(chunk_offset..chunk_end);
+                self.chunk_wip.error_reasons.push(ErrorReason::UnterminatedBlockComment {
+                    open_span: chunk_offset..chunk_offset.checked_add(2).X(),
+                    depth,
+                });
                 self.position = self.position.checked_add(comment_bytes).X();
             }
             (None, Some(Ok(string_bytes))) => {
                 let chunk_end = chunk_offset.checked_add(string_bytes).X();
-                self.chunk_wip.strings.push(chunk_offset..chunk_end);
+                let chunk_start = chunk_offset.checked_sub(self.byte_string_prefix()).X();
+                self.chunk_wip.strings.push(chunk_start..chunk_end);
                 self.position = self.position.checked_add(string_bytes).X();
             }
             (None, Some(Err(string_bytes))) => {
                 let chunk_end = chunk_offset.checked_add(string_bytes).X();
-                self.chunk_wip.errors.push(chunk_offset..chunk_end);
+                let text_all = self.text;
+                let quote = text_all[self.position..].chars().next().X();
+                let chunk_start = chunk_offset.checked_sub(self.byte_string_prefix()).X();
+                self.chunk_wip.errors.push(chunk_start..chunk_end);
+                self.chunk_wip.error_reasons.push(ErrorReason::UnterminatedString {
+                    open_quote_span: chunk_offset..chunk_offset.checked_add(quote.len_utf8()).X(),
+                });
                 self.position = self.position.checked_add(string_bytes).X();
             }
             (None, None) => {
                 self.position = self.position.checked_add(1).X();
-                let text_all = self.source.text(self.db);
+                let text_all = self.text;
                 assert!(self.position <= text_all.len());
             }
             (_, _) => unreachable!(),
         }
     }
 
+    /// The length of the `b` before the string at the current position,
+    /// making it a byte string, or 0.
+    ///
+    /// The `b` must not end a longer word, as in `ab"c"`.
+    fn byte_string_prefix(&self) -> usize {
+        let before = &self.text[..self.position];
+        let Some(before_b) = before.strip_suffix('b') else {
+            return 0;
+        };
+        match before_b.chars().next_back() {
+            Some(ch) if ch.is_alphanumeric() || ch == '_' => 0,
+            _ => 1,
+        }
+    }
+
+    fn step_fence(
+        &mut self,
+        parse_fence: Result<usize, usize>,
+    ) {
+        let chunk_offset = self.position.checked_sub(self.chunk_wip.chunk_start).X();
+
+        match parse_fence {
+            Ok(fence_bytes) => {
+                let chunk_end = chunk_offset.checked_add(fence_bytes).X();
+                self.chunk_wip.embedded.push(chunk_offset..chunk_end);
+                self.position = self.position.checked_add(fence_bytes).X();
+            }
+            Err(fence_bytes) => {
+                let chunk_end = chunk_offset.checked_add(fence_bytes).X();
+                let fence = self.config.embedded_fence(self.db).as_ref().X();
+                self.chunk_wip.errors.push(chunk_offset..chunk_end);
+                self.chunk_wip.error_reasons.push(ErrorReason::UnterminatedFence {
+                    open_span: chunk_offset..chunk_offset.checked_add(fence.len()).X(),
+                });
+                self.position = self.position.checked_add(fence_bytes).X();
+            }
+        }
+    }
+
+    fn step_heredoc(
+        &mut self,
+        parse_heredoc: Result<Heredoc, usize>,
+    ) {
+        let chunk_offset = self.position.checked_sub(self.chunk_wip.chunk_start).X();
+
+        match parse_heredoc {
+            Ok(heredoc) => {
+                let chunk_end = chunk_offset.checked_add(heredoc.range.end).X();
+                self.chunk_wip.strings.push(chunk_offset..chunk_end);
+                self.position = self.position.checked_add(heredoc.range.end).X();
+            }
+            Err(heredoc_bytes) => {
+                let chunk_end = chunk_offset.checked_add(heredoc_bytes).X();
+                let text_all = self.text;
+                let open_len = text_all[self.position..].find(['\r', '\n']).X();
+                self.chunk_wip.errors.push(chunk_offset..chunk_end);
+                self.chunk_wip.error_reasons.push(ErrorReason::UnterminatedHeredoc {
+                    open_span: chunk_offset..chunk_offset.checked_add(open_len).X(),
+                });
+                self.position = self.position.checked_add(heredoc_bytes).X();
+            }
+        }
+    }
+
+    fn parse_heredoc(&self, text: &str) -> Option<Result<Heredoc, usize>> {
+        match self.config.heredocs(self.db) {
+            true => parse_heredoc(text),
+            false => None,
+        }
+    }
+
+    fn parse_fence(&self, text: &str) -> Option<Result<usize, usize>> {
+        let fence = self.config.embedded_fence(self.db).as_ref()?;
+        parse_embedded(text, fence).map(|res| res.map(|region| region.range.end))
+    }
+
     fn parse_comment(&self, text: &str) -> Option<Result<usize, usize>> {
         let start_char = text.chars().next().X();
         if self.config.comment_start_chars(self.db).contains(&start_char) {
@@ -183,6 +370,137 @@ impl<'db> State<'db> {
     }
 }
 
+/// A fenced region of text in another language.
+///
+/// Written as the fence, a language tag running to the end of the line,
+/// the content, then the fence again:
+///
+/// ~~~text
+/// ```sql
+/// select * from t
+/// ```
+/// ~~~
+///
+/// The content is passed through untouched,
+/// for the host application to route to other tooling.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EmbeddedRegion {
+    /// The whole region, including both fences.
+    pub range: Range<usize>,
+    /// The language tag, trimmed, possibly empty.
+    pub language: String,
+    /// The content between the tag line and the closing fence.
+    pub content: Range<usize>,
+}
+
+/// The embedded regions of `chunk`, found with `config`.
+///
+/// `config` must be the config the chunk was source mapped with.
+/// Ranges are relative to the chunk.
+pub fn embedded_regions<'db>(
+    db: &'db dyn crate::Db,
+    chunk: Chunk<'db>,
+    config: Config<'db>,
+) -> Vec<EmbeddedRegion> {
+    let Some(fence) = config.embedded_fence(db) else {
+        return vec![];
+    };
+    let text = chunk.text(db).as_str(db);
+    chunk.embedded(db).iter().map(|range| {
+        let region = parse_embedded(&text[range.C()], fence).X().X();
+        let start = range.start;
+        EmbeddedRegion {
+            range: range.C(),
+            language: region.language,
+            content: start.checked_add(region.content.start).X()
+                ..start.checked_add(region.content.end).X(),
+        }
+    }).collect()
+}
+
+/// Parse an embedded region at the start of `text`.
+///
+/// Returns `None` if `text` does not start with `fence`,
+/// and the byte length to the end of `text` if the region is unclosed.
+fn parse_embedded(text: &str, fence: &str) -> Option<Result<EmbeddedRegion, usize>> {
+    let after_open = text.strip_prefix(fence)?;
+    let Some(newline) = memchr::memchr(b'\n', after_open.as_bytes()) else {
+        return Some(Err(text.len()));
+    };
+    let language = after_open[..newline].trim();
+    let content_start = fence.len().checked_add(newline).X().checked_add(1).X();
+    let Some(content_len) = text[content_start..].find(fence) else {
+        return Some(Err(text.len()));
+    };
+    let content_end = content_start.checked_add(content_len).X();
+    Some(Ok(EmbeddedRegion {
+        range: 0..content_end.checked_add(fence.len()).X(),
+        language: S(language),
+        content: content_start..content_end,
+    }))
+}
+
+/// A string of lines ended by a terminator of the writer's choice.
+///
+/// Written as `<<`, the terminator, a word of ASCII letters, digits and `_`,
+/// ending the line, then the content, then a line of just the terminator:
+///
+/// ~~~text
+/// <<END
+/// "quotes" and \backslashes\ are kept as written
+/// END
+/// ~~~
+///
+/// The content has no escapes, so blobs can be embedded as they are.
+/// `<<` followed by anything else, like `<< END` or `<<END x`,
+/// is not a heredoc.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Heredoc {
+    /// The whole heredoc, from `<<` to the end of the closing terminator.
+    pub range: Range<usize>,
+    /// The terminator word in the opening line.
+    pub terminator: Range<usize>,
+    /// The lines between the opening line and the closing terminator,
+    /// including the last line's line ending.
+    pub content: Range<usize>,
+}
+
+/// Parse a heredoc at the start of `text`.
+///
+/// Returns `None` if `text` does not start with one,
+/// and the byte length to the end of `text` if it is unterminated.
+pub fn parse_heredoc(text: &str) -> Option<Result<Heredoc, usize>> {
+    let after_open = text.strip_prefix("<<")?;
+    let terminator_len = after_open
+        .find(|ch: char| !(ch.is_ascii_alphanumeric() || ch == '_'))
+        .unwrap_or(after_open.len());
+    let terminator = &after_open[..terminator_len];
+    if terminator.is_empty() || terminator.starts_with(|ch: char| ch.is_ascii_digit()) {
+        return None;
+    }
+    let after_terminator = &after_open[terminator_len..];
+    let line_end = after_terminator.strip_prefix('\n')
+        .or_else(|| after_terminator.strip_prefix("\r\n"))?;
+    let content_start = text.len().checked_sub(line_end.len()).X();
+
+    let mut line_start = content_start;
+    while line_start < text.len() {
+        let line_len = memchr::memchr(b'\n', &text.as_bytes()[line_start..])
+            .unwrap_or(text.len().checked_sub(line_start).X());
+        let line = &text[line_start..][..line_len];
+        if line.strip_suffix('\r').unwrap_or(line) == terminator {
+            let terminator_end = line_start.checked_add(terminator.len()).X();
+            return Some(Ok(Heredoc {
+                range: 0..terminator_end,
+                terminator: 2..terminator_len.checked_add(2).X(),
+                content: content_start..line_start,
+            }));
+        }
+        line_start = line_start.checked_add(line_len).X().checked_add(1).X();
+    }
+    Some(Err(text.len()))
+}
+
 fn basic_parse_comment(text: &str) -> Option<Result<usize, usize>> {
     let bytes = text.as_bytes();
     match *bytes {
@@ -237,7 +555,12 @@ fn basic_parse_string(text: &str) -> Option<Result<usize, usize>> {
 }
 
 fn parse_nested_comment(text: &str) -> Option<Result<usize, usize>> {
+    Some(nested_comment_end(text).map_err(|_| text.len()))
+}
 
+/// The byte length of the nested comment at the start of `text`,
+/// or if it is unclosed, how many comments are left open.
+fn nested_comment_end(text: &str) -> Result<usize, usize> {
     assert!(text.starts_with("/*"));
 
     #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
@@ -273,7 +596,7 @@ fn parse_nested_comment(text: &str) -> Option<Result<usize, usize>> {
             Kind::Close => {
                 stack.pop().X();
                 if stack.is_empty() {
-                    return Some(Ok(index.checked_add(2).X()));
+                    return Ok(index.checked_add(2).X());
                 }
             }
         }
@@ -281,19 +604,33 @@ fn parse_nested_comment(text: &str) -> Option<Result<usize, usize>> {
 
     if !stack.is_empty() {
         // Unclosed open braces
-        Some(Err(text.len()))
+        Err(stack.len())
     } else {
         // No open braces
         unreachable!()
     }
 }
 
+/// `basic_config` with "```" fences and heredocs.
+#[cfg(test)]
+#[salsa::tracked]
+pub(crate) fn extended_config<'db>(
+    db: &'db dyn crate::Db,
+) -> Config<'db> {
+    Config::new(
+        db,
+        vec!['/'],
+        vec!['"'],
+        Some(S("```")),
+        true,
+    )
+}
 
 #[test]
 fn test_source_map() {
     fn chunk<'db>(db: &'db dyn crate::Db, s: &str) -> Chunk<'db> {
         let source = Source::new(db, S(s));
-        source_map(db, source, basic_config(db))
+        source_map(db, source, extended_config(db))
     }
 
     // "F"ragment
@@ -303,11 +640,12 @@ fn test_source_map() {
         C(&'s str), // comment
         S(&'s str), // string
         E(&'s str), // error
+        M(&'s str), // embedded
     }
 
     fn strs<'s, 'ss>(frags: &'ss [F<'s>]) -> impl Iterator<Item = &'s str> + 'ss {
         frags.iter().map(|f| match f {
-            F::T(s) | F::C(s) | F::S(s) | F::E(s) => *s,
+            F::T(s) | F::C(s) | F::S(s) | F::E(s) | F::M(s) => *s,
         })
     }
 
@@ -343,6 +681,7 @@ fn test_source_map() {
         let mut comments = a_chunk.comments(db).C();
         let mut strings = a_chunk.strings(db).C();
         let mut errors = a_chunk.errors(db).C();
+        let mut embedded = a_chunk.embedded(db).C();
         for (frag, pos) in ex_chunk.iter().rev() {
             //eprintln!("f {frag:?} {pos}");
             match frag {
@@ -364,14 +703,42 @@ fn test_source_map() {
                     let range = errors.pop().X();
                     assert_eq!(range, *pos .. (*pos + s.len()));
                 }
+                F::M(s) => {
+                    assert_eq!(&a_text[*pos..][..s.len()], *s);
+                    let range = embedded.pop().X();
+                    assert_eq!(range, *pos .. (*pos + s.len()));
+                }
             }
         }
 
         assert!(comments.is_empty());
         assert!(strings.is_empty());
         assert!(errors.is_empty());
+        assert!(embedded.is_empty());
     }
 
+    run(&[
+        F::T("a "),
+        F::M("```sql\nselect \"x\" // y\n```"),
+        F::T(" b"),
+    ]);
+    run(&[
+        F::M("```\n```"),
+        F::T(" "),
+        F::E("```x\n``"),
+    ]);
+    run(&[
+        F::S("\"```\""),
+        F::C("// ```"),
+        F::T("\n`` `"),
+    ]);
+    run(&[
+        F::E("```sql"),
+    ]);
+    run(&[
+        F::E("```sql\nselect"),
+    ]);
+
     run(&[
         F::T("ab"),
         F::T("bdd"),
@@ -442,6 +809,44 @@ fn test_source_map() {
         F::E("/*/**/ab"),
     ]);
 
+    // Byte strings.
+    run(&[
+        F::T("a = "),
+        F::S("b\"x\\\"\""),
+        F::T(" ab"),
+        F::S("\"y\""),
+        F::T(" "),
+        F::S("b\"\""),
+        F::S("b\"\""),
+    ]);
+    run(&[
+        F::T("_b"),
+        F::S("\"\""),
+        F::T(" "),
+        F::E("b\"x"),
+    ]);
+
+    // Heredocs.
+    run(&[
+        F::T("a = "),
+        F::S("<<END\n\"x\" /* y\nEND"),
+        F::T("\n"),
+    ]);
+    run(&[
+        F::S("<<A_1\r\n ```\nA_1 \nA_1"),
+        F::T("\r\n<<"),
+    ]);
+    run(&[
+        F::T("map<<A>> << B <<1\n"),
+        F::S("\"x\""),
+    ]);
+    run(&[
+        F::T("<<END x\n"),
+    ]);
+    run(&[
+        F::E("<<END\nENDING\n"),
+    ]);
+
     // Escape sequence tests.
     run(&[
         F::S("\"foo\\\"bar\""),
@@ -467,3 +872,123 @@ fn test_source_map() {
         F::T("y"),
     ]);
 }
+
+#[test]
+fn test_embedded_regions() {
+    #[salsa::tracked]
+    fn regions<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+    ) -> Vec<(String, String)> {
+        let config = extended_config(db);
+        let chunk = source_map(db, source, config);
+        let text = chunk.text(db).as_str(db);
+        embedded_regions(db, chunk, config).into_iter().map(|region| {
+            assert!(text[region.range.C()].starts_with("```"));
+            (region.language, S(&text[region.content]))
+        }).collect()
+    }
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S("a\n```sql \nselect 1\n```\nb ```\n.```"));
+    assert_eq!(regions(db, source), vec![
+        (S("sql"), S("select 1\n")),
+        (S(""), S(".")),
+    ]);
+
+    // Without a fence, backticks are left to the lexer.
+    let chunk = basic_source_map(db, source);
+    assert!(chunk.embedded(db).is_empty());
+    assert!(chunk.errors(db).is_empty());
+}
+
+#[test]
+fn test_error_reasons() {
+    fn reasons(text: &str) -> Vec<ErrorReason> {
+        let ref db = crate::Database::default();
+        let source = Source::new(db, S(text));
+        let chunk = source_map(db, source, extended_config(db));
+        assert_eq!(chunk.errors(db).len(), chunk.error_reasons(db).len());
+        chunk.error_reasons(db).C()
+    }
+
+    assert_eq!(reasons("a \"b"), vec![
+        ErrorReason::UnterminatedString { open_quote_span: 2..3 },
+    ]);
+    assert_eq!(reasons("a /* /* */ /* b"), vec![
+        ErrorReason::UnterminatedBlockComment { open_span: 2..4, depth: 2 },
+    ]);
+    assert_eq!(reasons("```sql\nselect"), vec![
+        ErrorReason::UnterminatedFence { open_span: 0..3 },
+    ]);
+    assert_eq!(reasons("b\"a"), vec![
+        ErrorReason::UnterminatedString { open_quote_span: 1..2 },
+    ]);
+    assert_eq!(reasons("a <<EOF\r\nb"), vec![
+        ErrorReason::UnterminatedHeredoc { open_span: 2..7 },
+    ]);
+    assert_eq!(reasons("\"a\" /* b */"), vec![]);
+}
+
+#[test]
+fn test_source_map_chunk_by_kind() {
+    use crate::chunks::ChunkSplit;
+
+    // `;` ends a code chunk and opens a data block.
+    fn data_try_chunk(text: &str) -> Option<ChunkSplit> {
+        Some(ChunkSplit { eat_bytes: 1, kind: ChunkKind::Code, next_kind: Some(ChunkKind::Data) })
+    }
+
+    #[salsa::tracked]
+    fn comments<'db>(
+        db: &'db dyn crate::Db,
+        source: Source,
+    ) -> Vec<(String, ChunkKind, Vec<String>)> {
+        let full_chunk = basic_source_map(db, source);
+        let chunks_config = crate::chunks::Config::new(db, vec![';'], data_try_chunk, ChunkKind::Code);
+        let chunks = crate::chunks::chunks(db, full_chunk, chunks_config);
+        // Data has no comments.
+        let data_config = Config::new(db, vec![], vec!['"'], None, false);
+        let kind_config = KindConfig::new(db, BTreeMap::from([
+            (ChunkKind::Data, data_config),
+        ]));
+        chunks.chunks(db).iter().map(|chunk| {
+            let chunk = source_map_chunk_by_kind(db, *chunk, kind_config);
+            assert_eq!(chunk.check(db), Ok(()));
+            let text = chunk.text(db).as_str(db);
+            let comments = chunk.comments(db).iter().map(|range| S(&text[range.C()])).collect();
+            (S(text), chunk.kind(db), comments)
+        }).collect()
+    }
+
+    let ref db = crate::Database::default();
+    let source = Source::new(db, S("a // b\n; \"c\" // d"));
+    assert_eq!(comments(db, source), vec![
+        (S("a // b\n;"), ChunkKind::Code, vec![S("// b")]),
+        (S(" \"c\" // d"), ChunkKind::Data, vec![]),
+    ]);
+}
+
+#[test]
+fn test_parse_heredoc() {
+    let text = "<<END\nab\nEND\nc";
+    assert_eq!(parse_heredoc(text), Some(Ok(Heredoc {
+        range: 0..12,
+        terminator: 2..5,
+        content: 6..9,
+    })));
+    assert_eq!(parse_heredoc("<<END\nEND"), Some(Ok(Heredoc {
+        range: 0..9,
+        terminator: 2..5,
+        content: 6..6,
+    })));
+    assert_eq!(parse_heredoc("<<END\nab"), Some(Err(8)));
+    assert_eq!(parse_heredoc("<<END"), None);
+    assert_eq!(parse_heredoc("<<\n"), None);
+    assert_eq!(parse_heredoc("<<9\n9"), None);
+
+    // Without heredocs, `<<` is left to the lexer.
+    let ref db = crate::Database::default();
+    let chunk = basic_source_map(db, Source::new(db, S(text)));
+    assert!(chunk.strings(db).is_empty());
+}
@@ -0,0 +1,235 @@
+//! Byte spans of a `Text`, and combining them.
+//!
+//! A `TextSpan` is a plain value, unlike the salsa-tracked `SubText`,
+//! for temporary use during parsing and error reporting.
+//! Spans are only comparable, mergeable and nestable
+//! when they are spans of the same `Text`;
+//! those operations return `None` otherwise.
+//!
+//! For display, e.g. in diagnostics and hovers,
+//! a span can be widened to the lines it covers,
+//! clipped to its first line, and trimmed of leading whitespace.
+
+use rmx::prelude::*;
+
+use rmx::std::cmp::Ordering;
+
+use crate::text::{ByteSpan, InternedSubText, SubText, Text};
+
+/// Lightweight text + span pair for passing around without salsa overhead.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct TextSpan<'db> {
+    pub text: Text<'db>,
+    pub span: ByteSpan,
+}
+
+impl<'db> TextSpan<'db> {
+    pub fn new(text: Text<'db>, span: ByteSpan) -> Self {
+        TextSpan { text, span }
+    }
+
+    pub fn start(&self) -> usize {
+        self.span.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.span.end
+    }
+
+    pub fn len(&self) -> usize {
+        self.span.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.span.is_empty()
+    }
+
+    pub fn with_end(&self, end: usize) -> Self {
+        TextSpan { text: self.text, span: self.span.start..end }
+    }
+
+    pub fn with_span(&self, span: ByteSpan) -> Self {
+        TextSpan { text: self.text, span }
+    }
+
+    pub fn as_str(&self, db: &'db dyn crate::Db) -> &'db str {
+        &self.text.as_str(db)[self.span.C()]
+    }
+
+    /// The smallest span covering both spans and everything between them.
+    pub fn merge(&self, other: &TextSpan<'db>) -> Option<TextSpan<'db>> {
+        if self.text != other.text {
+            return None;
+        }
+        let start = self.start().min(other.start());
+        let end = self.end().max(other.end());
+        Some(self.with_span(start..end))
+    }
+
+    /// Whether `other` lies within this span.
+    pub fn contains(&self, other: &TextSpan<'db>) -> bool {
+        self.text == other.text
+            && self.start() <= other.start()
+            && other.end() <= self.end()
+    }
+
+    /// This span as offsets from the start of `parent`,
+    /// if it lies within `parent`.
+    pub fn relative_to(&self, parent: &TextSpan<'db>) -> Option<ByteSpan> {
+        if !parent.contains(self) {
+            return None;
+        }
+        let start = self.start().checked_sub(parent.start()).X();
+        let end = self.end().checked_sub(parent.start()).X();
+        Some(start..end)
+    }
+
+    /// The span widened to the whole lines it touches,
+    /// without the final line's line ending.
+    pub fn covering_lines(&self, db: &'db dyn crate::Db) -> TextSpan<'db> {
+        let text = self.text.as_str(db);
+        let start = text[..self.start()].rfind('\n')
+            .map_or(0, |newline| newline.checked_add(1).X());
+        // A span ending in a newline doesn't touch the next line.
+        let last = match self.as_str(db).ends_with('\n') {
+            true => self.end().checked_sub(1).X(),
+            false => self.end(),
+        };
+        let mut end = text[last..].find('\n')
+            .map_or(text.len(), |newline| last.checked_add(newline).X());
+        if end > start && text[..end].ends_with('\r') {
+            end = end.checked_sub(1).X();
+        }
+        self.with_span(start..end)
+    }
+
+    /// The part of the span on its first line.
+    pub fn clip_to_line(&self, db: &'db dyn crate::Db) -> LineClip<'db> {
+        let text = self.as_str(db);
+        match text.find('\n') {
+            Some(newline) => {
+                let line = text[..newline].strip_suffix('\r').unwrap_or(&text[..newline]);
+                LineClip {
+                    span: self.with_end(self.start().checked_add(line.len()).X()),
+                    clipped: true,
+                }
+            }
+            None => LineClip { span: self.C(), clipped: false },
+        }
+    }
+
+    /// The span without its leading whitespace.
+    pub fn trim_start(&self, db: &'db dyn crate::Db) -> TextSpan<'db> {
+        let text = self.as_str(db);
+        let trimmed = text.len().checked_sub(text.trim_start().len()).X();
+        self.with_span(self.start().checked_add(trimmed).X()..self.end())
+    }
+
+    /// Intern the span's text and range,
+    /// for use as a key that is equal for equal text.
+    pub fn interned(&self, db: &'db dyn crate::Db) -> InternedSubText<'db> {
+        self.text.interned(db).sub(db, self.span.C())
+    }
+}
+
+/// A span clipped to one line by `TextSpan::clip_to_line`.
+#[derive(Clone, Eq, PartialEq, Hash)]
+pub struct LineClip<'db> {
+    pub span: TextSpan<'db>,
+    /// Whether the original span continued onto later lines.
+    pub clipped: bool,
+}
+
+impl<'db> LineClip<'db> {
+    /// The clipped text, ending in `…` if it was clipped.
+    pub fn display(&self, db: &'db dyn crate::Db) -> String {
+        match self.clipped {
+            true => format!("{}…", self.span.as_str(db)),
+            false => S(self.span.as_str(db)),
+        }
+    }
+}
+
+/// Spans of the same text are ordered by start, then end.
+impl<'db> PartialOrd for TextSpan<'db> {
+    fn partial_cmp(&self, other: &TextSpan<'db>) -> Option<Ordering> {
+        if self.text != other.text {
+            return None;
+        }
+        Some((self.start(), self.end()).cmp(&(other.start(), other.end())))
+    }
+}
+
+impl<'db> SubText<'db> {
+    pub fn text_span(&self, db: &'db dyn crate::Db) -> TextSpan<'db> {
+        TextSpan::new(self.text(db), self.range(db))
+    }
+}
+
+#[test]
+fn test_text_span() {
+    #[salsa::tracked]
+    fn check(db: &dyn crate::Db) {
+        let text = Text::new(db, S("hello, world"));
+        let other_text = Text::new(db, S("hello, world"));
+        let hello = TextSpan::new(text, 0..5);
+        let world = TextSpan::new(text, 7..12);
+        let all = TextSpan::new(text, 0..12);
+
+        assert_eq!(hello.as_str(db), "hello");
+        assert!(hello.merge(&world) == Some(all.C()));
+        assert!(world.merge(&hello) == Some(all.C()));
+        assert!(hello.merge(&TextSpan::new(other_text, 0..5)).is_none());
+
+        assert!(all.contains(&world));
+        assert!(!world.contains(&all));
+        assert_eq!(world.relative_to(&all), Some(7..12));
+        assert_eq!(TextSpan::new(text, 8..10).relative_to(&world), Some(1..3));
+        assert_eq!(all.relative_to(&world), None);
+
+        assert!(hello < world);
+        assert!(hello < all);
+        assert!(hello.partial_cmp(&TextSpan::new(other_text, 0..5)).is_none());
+
+        // Equal text interns equally, wherever it came from.
+        assert_eq!(hello.interned(db), TextSpan::new(other_text, 0..5).interned(db));
+        assert_eq!(hello.interned(db).as_str(db), "hello");
+        assert!(text.sub(db, 7..12).text_span(db) == world);
+    }
+
+    check(&crate::Database::default());
+}
+
+#[test]
+fn test_line_display() {
+    #[salsa::tracked]
+    fn check(db: &dyn crate::Db) {
+        let text = Text::new(db, S("fn f() {\r\n    let x = (1,\n        2);\n}\n"));
+        let span = |s: &str| {
+            let start = text.as_str(db).find(s).X();
+            TextSpan::new(text, start..start.checked_add(s.len()).X())
+        };
+
+        let x = span("x");
+        assert_eq!(x.covering_lines(db).as_str(db), "    let x = (1,");
+        assert_eq!(x.covering_lines(db).trim_start(db).as_str(db), "let x = (1,");
+        assert_eq!(span("(1,\n        2)").covering_lines(db).as_str(db), "    let x = (1,\n        2);");
+        // A trailing newline stays on its line.
+        assert_eq!(span("}\n").covering_lines(db).as_str(db), "}");
+        assert_eq!(span("fn").covering_lines(db).as_str(db), "fn f() {");
+
+        let tuple = span("(1,\n        2)").clip_to_line(db);
+        assert!(tuple.clipped);
+        assert_eq!(tuple.display(db), "(1,…");
+        let braces = span("{\r\n    let").clip_to_line(db);
+        assert_eq!(braces.display(db), "{…");
+        let single = x.clip_to_line(db);
+        assert!(!single.clipped);
+        assert_eq!(single.display(db), "x");
+
+        assert_eq!(span("\n        2").trim_start(db).as_str(db), "2");
+        assert!(span("    ").trim_start(db).is_empty());
+    }
+
+    check(&crate::Database::default());
+}
@@ -0,0 +1,289 @@
+//! Export and import of database inputs, for bug reports.
+//!
+//! A `StateArchive` holds the inputs of a package world and its
+//! configuration files, but none of the data derived from them,
+//! so importing it into a fresh database reproduces the analysis
+//! exactly. Archives are written as JSON.
+//!
+//! # Schema
+//!
+//! - `version`: integer, currently `1`.
+//! - `packages`: array of packages, ordered by import space then name.
+//!   - `import_space`: string, `"sys"` or `"local"`.
+//!   - `name`: string, the package name.
+//!   - `import_space_aliases`: optional object mapping the import spaces
+//!     the package imports from to the ones searched instead.
+//!   - `main_module`: optional string, the module run as a program.
+//!   - `modules`: array of modules, ordered by name.
+//!     - `name`: string.
+//!     - `visibility`: `"public"` or `"package"`.
+//!     - `no_prelude`: optional boolean, default `false`.
+//!     - `text`: string, the module source.
+//!     - `load_error`: optional string, why the module's file
+//!       couldn't be read, see `Source::load_error`.
+//! - `config_files`: array of `bct.toml` files, ordered by directory.
+//!   - `dir`: string, the directory relative to the project root.
+//!   - `toml`: string, the file contents.
+//!
+//! Import demands are not inputs and are not archived.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+use rmx::std::path::PathBuf;
+
+use crate::loader::RESERVED_NAMES;
+use crate::package2::{PackageWorld, Visibility};
+use crate::project_config::ConfigTree;
+use crate::world_builder::WorldBuilder;
+
+/// The version of the archive schema described in the module docs.
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct StateArchive {
+    pub version: u32,
+    pub packages: Vec<ArchivedPackage>,
+    pub config_files: Vec<ArchivedConfigFile>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchivedPackage {
+    pub import_space: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub import_space_aliases: BTreeMap<String, String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub main_module: Option<String>,
+    pub modules: Vec<ArchivedModule>,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchivedModule {
+    pub name: String,
+    pub visibility: Visibility,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub no_prelude: bool,
+    pub text: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub load_error: Option<String>,
+}
+
+fn is_false(value: &bool) -> bool {
+    !value
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct ArchivedConfigFile {
+    pub dir: PathBuf,
+    pub toml: String,
+}
+
+impl StateArchive {
+    pub fn to_json(&self) -> String {
+        rmx::serde_json::to_string_pretty(self).X()
+    }
+
+    pub fn from_json(json: &str) -> AnyResult<StateArchive> {
+        let archive: StateArchive = rmx::serde_json::from_str(json)?;
+        if archive.version != ARCHIVE_VERSION {
+            bail!(
+                "archive version {} is not supported, expected {ARCHIVE_VERSION}",
+                archive.version,
+            );
+        }
+        Ok(archive)
+    }
+}
+
+impl crate::Database {
+    /// Archive the inputs of `world` and `config_tree`.
+    pub fn export_state(
+        &self,
+        world: PackageWorld,
+        config_tree: ConfigTree,
+    ) -> StateArchive {
+        let db = self;
+        let pkglibs = [
+            ("sys", world.pkglib_system(db)),
+            ("local", world.pkglib_local(db)),
+        ];
+        let packages = pkglibs.into_iter()
+            .flat_map(|(import_space, packages)| {
+                packages.iter().map(move |(name, package)| (import_space, name, package))
+            })
+            .map(|(import_space, name, package)| ArchivedPackage {
+                import_space: S(import_space),
+                name: name.C(),
+                import_space_aliases: package.import_space_aliases(db).C(),
+                main_module: package.main_module(db).C(),
+                modules: package.modules(db).iter().map(|(name, module)| {
+                    ArchivedModule {
+                        name: name.C(),
+                        visibility: module.visibility(db),
+                        no_prelude: module.no_prelude(db),
+                        text: module.text(db).text(db).C(),
+                        load_error: module.text(db).load_error(db).C(),
+                    }
+                }).collect(),
+            })
+            .collect();
+
+        let config_files = config_tree.layers(db).iter().map(|layer| {
+            ArchivedConfigFile {
+                dir: layer.dir(db).C(),
+                toml: rmx::toml::to_string(layer.table(db)).X(),
+            }
+        }).collect();
+
+        StateArchive {
+            version: ARCHIVE_VERSION,
+            packages,
+            config_files,
+        }
+    }
+
+    /// Create new inputs from an archive made by `export_state`.
+    pub fn import_state(
+        &self,
+        archive: &StateArchive,
+    ) -> AnyResult<(PackageWorld, ConfigTree)> {
+        let db = self;
+        let mut builder = WorldBuilder::new();
+        for package in &archive.packages {
+            if package.import_space != "sys" && package.import_space != "local" {
+                bail!("package {} has unknown import space `{}`", package.name, package.import_space);
+            }
+            builder = builder.package(&format!("{}/{}", package.import_space, package.name));
+            for (alias, import_space) in &package.import_space_aliases {
+                if RESERVED_NAMES.contains(&alias.as_str()) {
+                    bail!("package {} aliases reserved import space `{alias}`", package.name);
+                }
+                builder = builder.import_space_alias(alias, import_space);
+            }
+            if let Some(main_module) = &package.main_module {
+                builder = builder.main_module(main_module);
+            }
+            for module in &package.modules {
+                builder = builder.module(&module.name, &module.text)
+                    .visibility(module.visibility);
+                if module.no_prelude {
+                    builder = builder.no_prelude();
+                }
+                if let Some(load_error) = &module.load_error {
+                    builder = builder.load_error(load_error);
+                }
+            }
+        }
+        let world = builder.build(db);
+
+        let tables = archive.config_files.iter().map(|file| {
+            let table = rmx::toml::from_str(&file.toml)
+                .with_context(|| format!("reading config file in {:?}", file.dir))?;
+            Ok((file.dir.C(), table))
+        }).collect::<AnyResult<Vec<_>>>()?;
+        let config_tree = ConfigTree::from_tables(db, tables);
+
+        Ok((world, config_tree))
+    }
+}
+
+#[test]
+fn test_state_archive_round_trip() {
+    let ref db = crate::Database::default();
+    let world = WorldBuilder::new()
+        .package("sys/core")
+        .module("core", "import module pkg/u32")
+        .module("u32", "")
+        .visibility(Visibility::Package)
+        .no_prelude()
+        .package("local/app")
+        .import_space_alias("vendor", "local")
+        .main_module("main")
+        .module("main", "import module sys/core\n")
+        .build(db);
+    let config_tree = ConfigTree::from_tables(db, vec![
+        (PathBuf::new(), rmx::toml::from_str("[format]\nindent_width = 2\n").X()),
+        (PathBuf::from("app"), rmx::toml::from_str("[severity]\nE0104 = \"allow\"\n").X()),
+    ]);
+
+    let archive = db.export_state(world, config_tree);
+    assert_eq!(archive.packages.len(), 2);
+    assert_eq!(archive.packages[0].import_space, "sys");
+    assert_eq!(archive.packages[0].modules[1].visibility, Visibility::Package);
+    assert!(archive.packages[0].modules[1].no_prelude);
+    assert!(archive.packages[0].import_space_aliases.is_empty());
+    assert_eq!(archive.packages[1].import_space_aliases["vendor"], "local");
+    assert_eq!(archive.packages[1].main_module.as_deref(), Some("main"));
+    assert_eq!(archive.config_files[1].dir, PathBuf::from("app"));
+
+    let json = archive.to_json();
+    let imported = StateArchive::from_json(&json).X();
+    assert_eq!(imported, archive);
+
+    // A fresh database gets the same inputs.
+    let ref db2 = crate::Database::default();
+    let (world2, config_tree2) = db2.import_state(&imported).X();
+    assert_eq!(db2.export_state(world2, config_tree2), archive);
+    let config = crate::project_config::effective_config(db2, config_tree2, rmx::std::path::Path::new("app/main.bct"));
+    assert_eq!(config.format.indent_width, 2);
+}
+
+#[test]
+fn test_state_archive_errors() {
+    let archive = StateArchive {
+        version: ARCHIVE_VERSION + 1,
+        packages: vec![],
+        config_files: vec![],
+    };
+    assert!(StateArchive::from_json(&archive.to_json()).is_err());
+    assert!(StateArchive::from_json("{}").is_err());
+
+    let ref db = crate::Database::default();
+    let archive = StateArchive {
+        version: ARCHIVE_VERSION,
+        packages: vec![ArchivedPackage {
+            import_space: S("pkg"),
+            name: S("a"),
+            import_space_aliases: BTreeMap::new(),
+            main_module: None,
+            modules: vec![],
+        }],
+        config_files: vec![],
+    };
+    assert!(db.import_state(&archive).is_err());
+
+    let mut archive = archive;
+    archive.packages[0].import_space = S("local");
+    archive.packages[0].import_space_aliases.insert(S("pkg"), S("sys"));
+    let Err(error) = db.import_state(&archive) else { panic!() };
+    assert!(error.to_string().contains("reserved import space `pkg`"));
+}
+
+#[test]
+fn test_state_archive_load_error() {
+    let ref db = crate::Database::default();
+    let world = WorldBuilder::new()
+        .package("local/app")
+        .module("main", "")
+        .module("broken", "")
+        .load_error("stream did not contain valid UTF-8")
+        .build(db);
+    let config_tree = ConfigTree::from_tables(db, vec![]);
+
+    let archive = db.export_state(world, config_tree);
+    let modules = &archive.packages[0].modules;
+    assert_eq!(modules[0].load_error.as_deref(), Some("stream did not contain valid UTF-8"));
+    assert_eq!(modules[1].load_error, None);
+
+    let imported = StateArchive::from_json(&archive.to_json()).X();
+    let ref db2 = crate::Database::default();
+    let (world2, _) = db2.import_state(&imported).X();
+    let app = world2.pkglib_local(db2)["app"];
+    let broken = app.modules(db2)["broken"].text(db2);
+    assert_eq!(broken.load_error(db2).as_deref(), Some("stream did not contain valid UTF-8"));
+    assert_eq!(app.modules(db2)["main"].text(db2).load_error(db2), &None);
+}
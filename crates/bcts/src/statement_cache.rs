@@ -0,0 +1,159 @@
+//! Parsing repeated statements once.
+//!
+//! Generated data often repeats statements word for word,
+//! within a module and across modules.
+//! `chunks` splits a source into statements at `.` separators,
+//! but each chunk is its own tracked struct, so identical statements
+//! are lexed and braced again wherever they appear.
+//! `parse_statement` instead takes a `StatementKey`,
+//! the statement's text and ranges interned by content,
+//! so every identical statement shares one parse.
+//!
+//! The parse is of the statement alone:
+//! its offsets are relative to the statement,
+//! to be moved by `Chunks::offsets` to the source's.
+//! Whitespace after a separator starts the next chunk,
+//! so `"a.\na."` has two different statements, `a.` and `\na.`.
+//!
+//! `statement_stats` counts how many statements were shared.
+//! Salsa doesn't report cache hits, so they are counted by content:
+//! a statement is a hit if an identical one was counted before it.
+
+use rmx::prelude::*;
+
+use rmx::std::collections::HashSet;
+use rmx::std::ops::Range;
+
+use crate::bracer::Bracer;
+use crate::chunk::{Chunk, ChunkKind, ErrorReason};
+use crate::chunks::Chunks;
+use crate::text::Text;
+
+/// The content of a statement chunk, everything its parse depends on.
+#[salsa::interned]
+#[derive(Debug)]
+pub struct StatementKey<'db> {
+    #[returns(ref)]
+    pub text: String,
+    #[returns(ref)]
+    pub comments: Vec<Range<usize>>,
+    #[returns(ref)]
+    pub strings: Vec<Range<usize>>,
+    #[returns(ref)]
+    pub errors: Vec<Range<usize>>,
+    #[returns(ref)]
+    pub error_reasons: Vec<ErrorReason>,
+    #[returns(ref)]
+    pub embedded: Vec<Range<usize>>,
+    pub kind: ChunkKind,
+}
+
+impl<'db> StatementKey<'db> {
+    pub fn of(db: &'db dyn crate::Db, chunk: Chunk<'db>) -> StatementKey<'db> {
+        StatementKey::new(
+            db,
+            chunk.text(db).text(db).C(),
+            chunk.comments(db).C(),
+            chunk.strings(db).C(),
+            chunk.errors(db).C(),
+            chunk.error_reasons(db).C(),
+            chunk.embedded(db).C(),
+            chunk.kind(db),
+        )
+    }
+}
+
+/// Lex and brace a statement with the basic lexer config.
+#[salsa::tracked]
+pub fn parse_statement<'db>(
+    db: &'db dyn crate::Db,
+    key: StatementKey<'db>,
+) -> Bracer<'db> {
+    crate::metrics::record(|metrics| metrics.query_executed("parse_statement"));
+    let chunk = Chunk::new(
+        db,
+        Text::new(db, key.text(db).C()),
+        key.comments(db).C(),
+        key.strings(db).C(),
+        key.errors(db).C(),
+        key.error_reasons(db).C(),
+        key.embedded(db).C(),
+        key.kind(db),
+    );
+    let chunk_lex = crate::lexer::lex_chunk(db, chunk);
+    crate::bracer::bracer(db, chunk_lex)
+}
+
+/// Each statement of `chunks`, parsed by `parse_statement`.
+#[salsa::tracked(returns(ref))]
+pub fn parse_statements<'db>(
+    db: &'db dyn crate::Db,
+    chunks: Chunks<'db>,
+) -> Vec<Bracer<'db>> {
+    chunks.chunks(db).iter()
+        .map(|chunk| parse_statement(db, StatementKey::of(db, *chunk)))
+        .collect()
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+#[derive(Eq, PartialEq)]
+pub struct StatementStats {
+    pub statements: usize,
+    /// Statements unlike any counted before them.
+    pub distinct: usize,
+}
+
+impl StatementStats {
+    /// Statements sharing the parse of an identical one.
+    pub fn hits(&self) -> usize {
+        self.statements.checked_sub(self.distinct).X()
+    }
+}
+
+/// Count the statements of `modules`, the chunks of each module,
+/// and how many are shared.
+pub fn statement_stats<'db>(
+    db: &'db dyn crate::Db,
+    modules: impl IntoIterator<Item = Chunks<'db>>,
+) -> StatementStats {
+    let mut seen = HashSet::new();
+    let mut stats = StatementStats::default();
+    for chunks in modules {
+        for chunk in chunks.chunks(db) {
+            stats.statements = stats.statements.checked_add(1).X();
+            if seen.insert(StatementKey::of(db, *chunk)) {
+                stats.distinct = stats.distinct.checked_add(1).X();
+            }
+        }
+    }
+    stats
+}
+
+#[test]
+fn test_statement_cache() {
+    let (ref db, executed) = crate::event_recording_database(crate::executed_query);
+    let module = |text: &str| {
+        let source = crate::input::Source::new(db, S(text));
+        crate::chunks::basic_chunks(db, crate::source_map::basic_source_map(db, source))
+    };
+
+    let a = module("a.\nb = (1).\nb = (1).\n");
+    let b = module("\nb = (1).\n");
+    let parsed_a = parse_statements(db, a);
+    let parsed_b = parse_statements(db, b);
+    assert_eq!(parsed_a.len(), 4);
+    assert!(parsed_a[1] == parsed_a[2]);
+    assert!(parsed_a[1] == parsed_b[0]);
+    assert!(parsed_a[3] == parsed_b[1]);
+    assert!(parsed_a[0] != parsed_a[1]);
+    assert_eq!(parsed_b[0].chunk(db).chunk(db).text(db).as_str(db), "\nb = (1).");
+    let parse_statement_runs = executed.lock().X().iter()
+        .filter(|key| key.starts_with("parse_statement("))
+        .count();
+    assert_eq!(parse_statement_runs, 3);
+
+    assert_eq!(statement_stats(db, [a]), StatementStats { statements: 4, distinct: 3 });
+    let stats = statement_stats(db, [a, b]);
+    assert_eq!(stats, StatementStats { statements: 6, distinct: 3 });
+    assert_eq!(stats.hits(), 3);
+}
@@ -0,0 +1,366 @@
+//! Language-level tests written inside `.bct` sources.
+//!
+//! A test file is divided into test blocks by `//@ test <name>` lines.
+//! Each block's source is the text up to the next block,
+//! without its directive lines,
+//! and is run through the lexer and bracer on its own:
+//!
+//! ```text
+//! //@ test unclosed
+//! (a
+//! //@ expect E0001
+//! //@ tree
+//! //| (
+//! //|   Word 1..2 "a"
+//! //|   Whitespace 2..3 "\n"
+//! //| )
+//! ```
+//!
+//! Directives are:
+//!
+//! - `//@ expect <code>`: the block produces a diagnostic with `code`.
+//!   Diagnostics are compared by code as a multiset,
+//!   so a block without `expect` directives must produce none.
+//! - `//@ tree`: the following `//|` lines are the expected
+//!   `dump::tree_dump` of the block.
+//!
+//! Text before the first block is not part of any test.
+//!
+//! Diagnostics can also be expected on a particular line
+//! with a `//~` comment, optionally followed by text
+//! the diagnostic's message must contain:
+//!
+//! ```text
+//! //@ test annotated
+//! (a b] //~ E0002 unmatched
+//! [c
+//! //~^ E0001
+//! ```
+//!
+//! `//~` refers to its own line, and each `^` after it
+//! to one line further up.
+//! Unlike directives, annotations stay in the block's source.
+//! Every diagnostic must be matched by an annotation or an `expect`
+//! directive; annotations are tried first.
+
+use rmx::prelude::*;
+
+use crate::diagnostics::{Code, bracer_diagnostics};
+
+const DIRECTIVE: &str = "//@";
+const TREE_LINE: &str = "//|";
+const ANNOTATION: &str = "//~";
+
+/// One test parsed from a test file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestBlock {
+    pub name: String,
+    /// The 1-based line of the `//@ test` header.
+    pub line: usize,
+    /// The block text without directive lines.
+    pub source: String,
+    pub expected_codes: Vec<Code>,
+    pub expected_tree: Option<String>,
+    pub annotations: Vec<Annotation>,
+}
+
+/// A `//~` comment expecting a diagnostic on a line.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Annotation {
+    /// The 1-based line of the block source the diagnostic is expected on.
+    pub line: usize,
+    pub code: Code,
+    /// Text the diagnostic message must contain.
+    pub message: Option<String>,
+}
+
+/// The result of running one `TestBlock`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct TestOutcome {
+    pub name: String,
+    pub line: usize,
+    /// Descriptions of each mismatch; empty if the test passed.
+    pub failures: Vec<String>,
+}
+
+impl TestOutcome {
+    pub fn passed(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Split a test file into its test blocks.
+///
+/// Unknown directives and directives outside a block are errors.
+pub fn parse_test_blocks(text: &str) -> AnyResult<Vec<TestBlock>> {
+    let mut blocks: Vec<TestBlock> = vec![];
+    let mut in_tree = false;
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let line_number = index.checked_add(1).X();
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+
+        if let Some(tree_line) = trimmed.strip_prefix(TREE_LINE) {
+            let Some(block) = blocks.last_mut().filter(|_| in_tree) else {
+                bail!("line {line_number}: `{TREE_LINE}` line outside a `//@ tree` directive");
+            };
+            let tree = block.expected_tree.get_or_insert_with(String::new);
+            tree.push_str(tree_line.strip_prefix(' ').unwrap_or(tree_line));
+            tree.push('\n');
+            continue;
+        }
+        in_tree = false;
+
+        let Some(directive) = trimmed.strip_prefix(DIRECTIVE) else {
+            if let Some(block) = blocks.last_mut() {
+                block.source.push_str(line);
+                let source_line = block.source.lines().count();
+                if let Some(annotation) = parse_annotation(trimmed, source_line)
+                    .with_context(|| format!("line {line_number}"))?
+                {
+                    block.annotations.push(annotation);
+                }
+            }
+            continue;
+        };
+
+        let mut words = directive.split_whitespace();
+        let command = words.next().unwrap_or("");
+        let argument = words.next();
+        if words.next().is_some() {
+            bail!("line {line_number}: too many arguments to `{command}`");
+        }
+
+        if command == "test" {
+            let name = argument
+                .ok_or_else(|| anyhow!("line {line_number}: `test` needs a name"))?;
+            blocks.push(TestBlock {
+                name: S(name),
+                line: line_number,
+                source: S(""),
+                expected_codes: vec![],
+                expected_tree: None,
+                annotations: vec![],
+            });
+            continue;
+        }
+
+        let Some(block) = blocks.last_mut() else {
+            bail!("line {line_number}: `{command}` before the first `//@ test`");
+        };
+        match (command, argument) {
+            ("expect", Some(code)) => {
+                let code = Code::parse(code)
+                    .ok_or_else(|| anyhow!("line {line_number}: unknown diagnostic code `{code}`"))?;
+                block.expected_codes.push(code);
+            }
+            ("tree", None) => {
+                if block.expected_tree.is_some() {
+                    bail!("line {line_number}: test `{}` has two trees", block.name);
+                }
+                block.expected_tree = Some(S(""));
+                in_tree = true;
+            }
+            _ => bail!("line {line_number}: unknown directive `{}`", directive.trim()),
+        }
+    }
+
+    Ok(blocks)
+}
+
+/// Parse the `//~` annotation in `line`, if any.
+///
+/// `source_line` is the 1-based line of the block source `line` is on.
+fn parse_annotation(line: &str, source_line: usize) -> AnyResult<Option<Annotation>> {
+    let Some(start) = line.find(ANNOTATION) else {
+        return Ok(None);
+    };
+    let rest = &line[start.checked_add(ANNOTATION.len()).X()..];
+    let carets = rest.chars().take_while(|c| *c == '^').count();
+    let rest = &rest[carets..];
+
+    let line = source_line.checked_sub(carets)
+        .filter(|line| *line > 0)
+        .ok_or_else(|| anyhow!("annotation points above the test block"))?;
+    let rest = rest.trim();
+    let (code, message) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let code = Code::parse(code)
+        .ok_or_else(|| anyhow!("unknown diagnostic code `{code}` in annotation"))?;
+    let message = message.trim();
+
+    Ok(Some(Annotation {
+        line,
+        code,
+        message: (!message.is_empty()).then(|| S(message)),
+    }))
+}
+
+/// Run one test block in a fresh database.
+pub fn run_test_block(block: &TestBlock) -> TestOutcome {
+    let ref db = crate::Database::default();
+    let source = crate::input::Source::new(db, block.source.C());
+    let bracer = crate::bracer::basic_bracer(db, source);
+
+    let mut failures = vec![];
+
+    // Diagnostics not matched by an annotation, with their lines.
+    let mut unannotated = vec![];
+    let mut annotations: Vec<Option<&Annotation>> = block.annotations.iter().map(Some).collect();
+    for diagnostic in bracer_diagnostics(db, bracer) {
+        let line = diagnostic.span.as_ref().map(|span| {
+            block.source[..span.start].matches('\n').count().checked_add(1).X()
+        });
+        let matched = annotations.iter_mut().find(|annotation| {
+            annotation.is_some_and(|annotation| {
+                Some(annotation.line) == line
+                    && annotation.code == diagnostic.code
+                    && annotation.message.as_ref()
+                        .is_none_or(|message| diagnostic.message.contains(message.as_str()))
+            })
+        });
+        match matched {
+            Some(annotation) => *annotation = None,
+            None => unannotated.push((line, diagnostic)),
+        }
+    }
+
+    for annotation in annotations.into_iter().flatten() {
+        let message = annotation.message.as_ref()
+            .map(|message| format!(" containing {message:?}"))
+            .unwrap_or_default();
+        failures.push(format!(
+            "line {}: expected {}{message}, not found",
+            annotation.line, annotation.code,
+        ));
+    }
+
+    let actual_codes: Vec<Code> = unannotated.iter()
+        .map(|(_, diagnostic)| diagnostic.code)
+        .sorted()
+        .collect();
+    let expected_codes: Vec<Code> = block.expected_codes.iter().copied().sorted().collect();
+    if actual_codes != expected_codes {
+        failures.push(format!(
+            "expected diagnostics [{}], found [{}]",
+            expected_codes.iter().join(", "),
+            actual_codes.iter().join(", "),
+        ));
+        for (line, diagnostic) in &unannotated {
+            let line = line.map(|line| format!("line {line}: ")).unwrap_or_default();
+            failures.push(format!("{line}{} {}", diagnostic.code, diagnostic.message));
+        }
+    }
+
+    if let Some(expected_tree) = &block.expected_tree {
+        let actual_tree = crate::dump::tree_dump(db, bracer);
+        if actual_tree != *expected_tree {
+            failures.push(format!(
+                "tree mismatch\n--- expected\n{expected_tree}--- actual\n{actual_tree}",
+            ));
+        }
+    }
+
+    TestOutcome {
+        name: block.name.C(),
+        line: block.line,
+        failures,
+    }
+}
+
+/// Parse and run every test block in a test file.
+pub fn run_test_file(text: &str) -> AnyResult<Vec<TestOutcome>> {
+    Ok(parse_test_blocks(text)?.iter().map(run_test_block).collect())
+}
+
+#[test]
+fn test_parse_test_blocks() {
+    let blocks = parse_test_blocks("\
+Notes before the tests.
+//@ test first
+(a
+//@ expect E0001
+//@ tree
+//| (
+//|   Word 1..2 \"a\"
+//| )
+b
+//@ test second
+").X();
+
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[0].name, "first");
+    assert_eq!(blocks[0].line, 2);
+    assert_eq!(blocks[0].source, "(a\nb\n");
+    assert_eq!(blocks[0].expected_codes, vec![Code::UnclosedBrace]);
+    assert_eq!(blocks[0].expected_tree.as_deref(), Some("(\n  Word 1..2 \"a\"\n)\n"));
+    assert_eq!(blocks[1].name, "second");
+    assert_eq!(blocks[1].source, "");
+    assert_eq!(blocks[1].expected_tree, None);
+
+    assert!(parse_test_blocks("//@ expect E0001\n").is_err());
+    assert!(parse_test_blocks("//@ test a\n//@ expect E9999\n").is_err());
+    assert!(parse_test_blocks("//@ test a\n//@ nope\n").is_err());
+    assert!(parse_test_blocks("//@ test a\n//| (\n").is_err());
+    assert!(parse_test_blocks("//@ test\n").is_err());
+}
+
+#[test]
+fn test_run_test_file() {
+    let outcomes = run_test_file("\
+//@ test balanced
+(a)
+//@ tree
+//| (
+//|   Word 1..2 \"a\"
+//| )
+//| Whitespace 3..4 \"\\n\"
+//@ test unmatched
+a)
+//@ expect E0002
+//@ test wrong
+a)
+//@ expect E0001
+").X();
+
+    assert!(outcomes[0].passed(), "{:?}", outcomes[0].failures);
+    assert!(outcomes[1].passed(), "{:?}", outcomes[1].failures);
+    assert!(!outcomes[2].passed());
+    assert_eq!(outcomes[2].failures, vec![
+        S("expected diagnostics [E0001], found [E0002]"),
+        S("line 1: E0002 unmatched `)`"),
+    ]);
+}
+
+#[test]
+fn test_annotations() {
+    let blocks = parse_test_blocks("\
+//@ test annotated
+(a b] //~ E0002 unmatched
+[c
+//~^ E0001
+//~^^^ E0001
+").X();
+    assert_eq!(blocks[0].annotations, vec![
+        Annotation { line: 1, code: Code::UnmatchedClose, message: Some(S("unmatched")) },
+        Annotation { line: 2, code: Code::UnclosedBrace, message: None },
+        Annotation { line: 1, code: Code::UnclosedBrace, message: None },
+    ]);
+    assert!(run_test_block(&blocks[0]).passed(), "{:?}", run_test_block(&blocks[0]).failures);
+
+    assert!(parse_test_blocks("//@ test a\n//~^ E0001\n").is_err());
+    assert!(parse_test_blocks("//@ test a\nb //~ E9999\n").is_err());
+
+    let outcomes = run_test_file("\
+//@ test wrong_line
+(a
+b //~^ E0002
+//@ test wrong_message
+a) //~ E0002 unclosed
+").X();
+    assert_eq!(outcomes[0].failures, vec![
+        S("line 1: expected E0002, not found"),
+        S("expected diagnostics [], found [E0001]"),
+        S("line 1: E0001 unclosed `(`"),
+    ]);
+    assert_eq!(outcomes[1].failures[0], "line 1: expected E0002 containing \"unclosed\", not found");
+}
@@ -6,38 +6,6 @@ use std::{iter, mem};
 /// Byte span type alias.
 pub type ByteSpan = Range<usize>;
 
-/// Lightweight text + span pair for passing around without salsa overhead.
-///
-/// Unlike `SubText` which is salsa-tracked, this is a simple struct
-/// for temporary use during parsing and error reporting.
-#[derive(Clone)]
-pub struct TextSpan<'db> {
-    pub text: Text<'db>,
-    pub span: ByteSpan,
-}
-
-impl<'db> TextSpan<'db> {
-    pub fn new(text: Text<'db>, span: ByteSpan) -> Self {
-        TextSpan { text, span }
-    }
-
-    pub fn start(&self) -> usize {
-        self.span.start
-    }
-
-    pub fn end(&self) -> usize {
-        self.span.end
-    }
-
-    pub fn with_end(&self, end: usize) -> Self {
-        TextSpan { text: self.text, span: self.span.start..end }
-    }
-
-    pub fn with_span(&self, span: ByteSpan) -> Self {
-        TextSpan { text: self.text, span }
-    }
-}
-
 #[salsa::tracked]
 pub struct Text<'db> {
     #[returns(ref)]
@@ -150,12 +118,25 @@ impl<'db> Text<'db> {
     pub fn as_str(&self, db: &'db dyn crate::Db) -> &'db str {
         self.text(db).as_str()
     }
+
+    /// The interned copy of this text.
+    ///
+    /// Interning hashes the whole text, so this goes through
+    /// `interned_of` to do it once per `Text`.
+    pub fn interned(&self, db: &'db dyn crate::Db) -> InternedText<'db> {
+        interned_of(db, *self)
+    }
 }
 
 impl<'db> SubText<'db> {
     pub fn as_str(&self, db: &'db dyn crate::Db) -> &'db str {
         &self.text(db).text(db)[self.range(db)]
     }
+
+    /// The interned copy of this subtext.
+    pub fn interned(&self, db: &'db dyn crate::Db) -> InternedSubText<'db> {
+        self.text(db).interned(db).sub(db, self.range(db))
+    }
 }
 
 impl<'db> InternedText<'db> {
@@ -170,6 +151,35 @@ impl<'db> InternedSubText<'db> {
     }
 }
 
+/// The interned copy of `text`, memoized.
+#[salsa::tracked]
+pub fn interned_of<'db>(
+    db: &'db dyn crate::Db,
+    text: Text<'db>,
+) -> InternedText<'db> {
+    InternedText::new(db, text.text(db).C())
+}
+
+#[test]
+fn interned_of_memoized() {
+    let (ref db, executed) = crate::event_recording_database(crate::executed_query);
+
+    #[salsa::tracked]
+    fn check(db: &dyn crate::Db) {
+        let text = Text::new(db, S("abcdef"));
+        let span = crate::span::TextSpan::new(text, 1..3);
+        assert_eq!(span.interned(db), InternedText::new(db, S("abcdef")).sub(db, 1..3));
+        assert_eq!(text.sub(db, 2..4).interned(db).as_str(db), "cd");
+        assert_eq!(text.interned(db).as_str(db), "abcdef");
+    }
+
+    check(db);
+    let interned_of_runs = executed.lock().X().iter()
+        .filter(|key| key.starts_with("interned_of("))
+        .count();
+    assert_eq!(interned_of_runs, 1);
+}
+
 #[test]
 fn interned_text_eq() {
     let ref db = crate::Database::default();
@@ -0,0 +1,165 @@
+//! Structural selection queries for editors.
+//!
+//! These answer "what bracket group is the cursor in"
+//! and "what comes before and after it" from the brace tree,
+//! so an editor can expand a selection to the enclosing group
+//! or move between siblings.
+//!
+//! All offsets and spans are bytes into the source text.
+//! Siblings are the tokens and branches at the same depth,
+//! skipping whitespace and comments.
+//! An offset on a branch's open or close sigil
+//! is on the branch itself, not inside it.
+
+use rmx::prelude::*;
+
+use crate::bracer::{basic_bracer, BracerIter, TreeToken};
+use crate::input::Source;
+use crate::text::ByteSpan;
+
+/// The span of the innermost branch containing `offset`, including its sigils.
+#[salsa::tracked]
+pub fn enclosing_branch_span<'db>(
+    db: &'db dyn crate::Db,
+    source: Source,
+    offset: usize,
+) -> Option<ByteSpan> {
+    let mut enclosing = None;
+    let mut iter = basic_bracer(db, source).iter(db);
+    loop {
+        let level = Level::new(db, iter);
+        match level.descend(offset) {
+            Some((span, branch_iter)) => {
+                enclosing = Some(span);
+                iter = branch_iter;
+            }
+            None => break,
+        }
+    }
+    enclosing
+}
+
+/// The span of the sibling after the token or branch at `offset`.
+///
+/// If `offset` is between siblings, this is the first sibling after it.
+#[salsa::tracked]
+pub fn next_sibling_span<'db>(
+    db: &'db dyn crate::Db,
+    source: Source,
+    offset: usize,
+) -> Option<ByteSpan> {
+    let level = innermost_level(db, source, offset);
+    level.elements.iter()
+        .map(|(span, _)| span)
+        .find(|span| span.start > offset)
+        .cloned()
+}
+
+/// The span of the sibling before the token or branch at `offset`.
+///
+/// If `offset` is between siblings, this is the last sibling before it.
+#[salsa::tracked]
+pub fn prev_sibling_span<'db>(
+    db: &'db dyn crate::Db,
+    source: Source,
+    offset: usize,
+) -> Option<ByteSpan> {
+    let level = innermost_level(db, source, offset);
+    level.elements.iter()
+        .map(|(span, _)| span)
+        .rfind(|span| span.end <= offset)
+        .cloned()
+}
+
+fn innermost_level<'db>(
+    db: &'db dyn crate::Db,
+    source: Source,
+    offset: usize,
+) -> Level<'db> {
+    let mut level = Level::new(db, basic_bracer(db, source).iter(db));
+    while let Some((_, branch_iter)) = level.descend(offset) {
+        level = Level::new(db, branch_iter);
+    }
+    level
+}
+
+/// The non-trivia tokens and branches at one depth of the tree.
+struct Level<'db> {
+    elements: Vec<(ByteSpan, Option<BracerIter<'db>>)>,
+}
+
+impl<'db> Level<'db> {
+    fn new(db: &'db dyn crate::Db, iter: BracerIter<'db>) -> Level<'db> {
+        let elements = iter
+            .filter_map(|tree_token| tree_token.without_space(db))
+            .filter_map(|tree_token| {
+                let span = tree_token.text_span(db)?.span;
+                let branch_iter = match tree_token {
+                    TreeToken::Token(_) => None,
+                    TreeToken::Branch(_, branch_iter) => Some(branch_iter),
+                };
+                Some((span, branch_iter))
+            })
+            .collect();
+        Level { elements }
+    }
+
+    /// The branch whose contents contain `offset`, if any.
+    fn descend(&self, offset: usize) -> Option<(ByteSpan, BracerIter<'db>)> {
+        self.elements.iter().find_map(|(span, branch_iter)| {
+            let branch_iter = branch_iter.as_ref()?;
+            // Brace sigils are all one byte.
+            let inside = span.start < offset && offset < span.end.checked_sub(1).X();
+            inside.then(|| (span.C(), branch_iter.C()))
+        })
+    }
+}
+
+#[test]
+fn test_enclosing_branch_span() {
+    let ref db = crate::Database::default();
+    let text = "a (b [c d] e) f";
+    let source = Source::new(db, S(text));
+    let enclosing = |offset| {
+        enclosing_branch_span(db, source, offset).map(|span| &text[span])
+    };
+
+    assert_eq!(enclosing(0), None);
+    assert_eq!(enclosing(2), None);
+    assert_eq!(enclosing(3), Some("(b [c d] e)"));
+    assert_eq!(enclosing(5), Some("(b [c d] e)"));
+    assert_eq!(enclosing(6), Some("[c d]"));
+    assert_eq!(enclosing(8), Some("[c d]"));
+    assert_eq!(enclosing(9), Some("(b [c d] e)"));
+    assert_eq!(enclosing(12), None);
+    assert_eq!(enclosing(14), None);
+}
+
+#[test]
+fn test_sibling_spans() {
+    let ref db = crate::Database::default();
+    let text = "a (b [c d] e) f";
+    let source = Source::new(db, S(text));
+    let next = |offset| {
+        next_sibling_span(db, source, offset).map(|span| &text[span])
+    };
+    let prev = |offset| {
+        prev_sibling_span(db, source, offset).map(|span| &text[span])
+    };
+
+    assert_eq!(next(0), Some("(b [c d] e)"));
+    assert_eq!(prev(0), None);
+    assert_eq!(next(1), Some("(b [c d] e)"));
+    assert_eq!(prev(1), Some("a"));
+    assert_eq!(next(2), Some("f"));
+    assert_eq!(prev(2), Some("a"));
+    assert_eq!(next(3), Some("[c d]"));
+    assert_eq!(prev(3), None);
+    assert_eq!(next(5), Some("e"));
+    assert_eq!(prev(5), Some("b"));
+    assert_eq!(next(6), Some("d"));
+    assert_eq!(prev(8), Some("c"));
+    assert_eq!(next(8), None);
+    assert_eq!(next(14), None);
+    assert_eq!(prev(14), Some("(b [c d] e)"));
+}
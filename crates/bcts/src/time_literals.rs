@@ -0,0 +1,498 @@
+//! Parsing of datetime and duration literals.
+//!
+//! A datetime is an RFC 3339 date, like `2024-01-01`,
+//! optionally followed by `T` and a time, like `2024-01-01T08:30:00`,
+//! and then optionally by an offset, `Z` or like `+05:30`.
+//! A time may have up to 9 digits of a fraction of a second,
+//! like `08:30:00.25`.
+//! A datetime without an offset is a local one, as in TOML.
+//!
+//! A duration is ints each followed by a unit,
+//! `d`, `h`, `m`, `s`, `ms`, `us` or `ns`, like `15m30s` or `250ms`,
+//! with the units from largest to smallest, each at most once.
+//!
+//! With `lexer::Config::time_literals` on, the lexer makes
+//! datetime and duration tokens by their shape,
+//! with `datetime_len` and `is_duration`,
+//! so `2024-13-01` is a datetime token whose month is invalid.
+//! Errors are reported at the part of the span at fault,
+//! as in `numbers`.
+
+use rmx::prelude::*;
+use rmx::std::fmt;
+use rmx::std::time::Duration;
+
+use crate::text::ByteSpan;
+
+/// A date, with an optional time of day.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Datetime {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    /// The time, with its offset if it isn't local.
+    pub time: Option<(Time, Option<Offset>)>,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Time {
+    pub hour: u8,
+    pub minute: u8,
+    /// Up to 60, for a leap second.
+    pub second: u8,
+    pub nanosecond: u32,
+}
+
+/// The offset of a time from UTC.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Offset {
+    /// `Z`.
+    Utc,
+    /// Minutes east of UTC, written like `+05:30`.
+    Minutes(i16),
+}
+
+impl fmt::Display for Datetime {
+    /// The datetime in RFC 3339 form, with an upper case `T` and `Z`,
+    /// and no trailing zeros in the fraction of a second.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)?;
+        let Some((time, offset)) = self.time else {
+            return Ok(());
+        };
+        write!(f, "T{:02}:{:02}:{:02}", time.hour, time.minute, time.second)?;
+        if time.nanosecond != 0 {
+            let fraction = format!("{:09}", time.nanosecond);
+            write!(f, ".{}", fraction.trim_end_matches('0'))?;
+        }
+        match offset {
+            None => Ok(()),
+            Some(Offset::Utc) => write!(f, "Z"),
+            Some(Offset::Minutes(minutes)) => {
+                let sign = if minutes < 0 { '-' } else { '+' };
+                let minutes = minutes.unsigned_abs();
+                write!(f, "{sign}{:02}:{:02}", minutes / 60, minutes % 60)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TimeError {
+    pub span: ByteSpan,
+    pub kind: TimeErrorKind,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TimeErrorKind {
+    /// The text doesn't start with a date.
+    NotADatetime,
+    /// The text doesn't start with a digit.
+    NotADuration,
+    /// Something else is written where `expected` must be.
+    Expected { expected: &'static str },
+    /// A month, hour, minute, second or offset out of its range.
+    OutOfRange { field: &'static str, value: u32 },
+    DayOutOfRange { day: u8, year: u16, month: u8 },
+    TooManyFractionalDigits,
+    UnknownUnit { unit: String },
+    /// A unit after a smaller or the same unit.
+    UnitOrder { unit: String, previous: String },
+    DurationOverflow,
+}
+
+impl fmt::Display for TimeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.kind {
+            TimeErrorKind::NotADatetime => write!(f, "expected a datetime, like `2024-01-01T00:00:00Z`"),
+            TimeErrorKind::NotADuration => write!(f, "expected a duration, like `15m30s`"),
+            TimeErrorKind::Expected { expected } => write!(f, "expected {expected}"),
+            TimeErrorKind::OutOfRange { field, value } => write!(f, "{field} {value} is out of range"),
+            TimeErrorKind::DayOutOfRange { day, year, month } => {
+                write!(f, "day {day} is out of range for {year:04}-{month:02}")
+            }
+            TimeErrorKind::TooManyFractionalDigits => {
+                write!(f, "more than 9 digits of a fraction of a second")
+            }
+            TimeErrorKind::UnknownUnit { unit } => {
+                write!(f, "unknown unit `{unit}`, expected one of {}", UNITS.iter().map(|(unit, _)| format!("`{unit}`")).join(", "))
+            }
+            TimeErrorKind::UnitOrder { unit, previous } if unit == previous => {
+                write!(f, "unit `{unit}` is repeated")
+            }
+            TimeErrorKind::UnitOrder { unit, previous } => {
+                write!(f, "unit `{unit}` must come before `{previous}`")
+            }
+            TimeErrorKind::DurationOverflow => write!(f, "duration is out of range"),
+        }
+    }
+}
+
+impl rmx::std::error::Error for TimeError {}
+
+/// The duration units, from largest to smallest, with their nanoseconds.
+const UNITS: &[(&str, u128)] = &[
+    ("d", 86_400_000_000_000),
+    ("h", 3_600_000_000_000),
+    ("m", 60_000_000_000),
+    ("s", 1_000_000_000),
+    ("ms", 1_000_000),
+    ("us", 1_000),
+    ("ns", 1),
+];
+
+/// The length of the datetime token at the start of `text`, if any.
+///
+/// A datetime token starts with a date, like `2024-01-01`,
+/// and continues over word characters, and `:`, `.`, `+` and `-`
+/// before a digit, so an invalid datetime is a single token.
+pub fn datetime_len(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    let shape = b"0000-00-00";
+    let is_date = bytes.len() >= shape.len()
+        && bytes.iter().zip(shape).all(|(byte, shape)| match shape {
+            b'0' => byte.is_ascii_digit(),
+            _ => byte == shape,
+        });
+    if !is_date {
+        return None;
+    }
+    let mut len = shape.len();
+    while let Some(&byte) = bytes.get(len) {
+        let next_is_digit = bytes.get(len.checked_add(1).X()).is_some_and(u8::is_ascii_digit);
+        let continues = byte.is_ascii_alphanumeric()
+            || byte == b'_'
+            || (matches!(byte, b':' | b'.' | b'+' | b'-') && next_is_digit);
+        if !continues {
+            break;
+        }
+        len = len.checked_add(1).X();
+    }
+    Some(len)
+}
+
+/// Whether `word` is shaped like a duration:
+/// ints each followed by a unit, like `15m30s`.
+pub fn is_duration(word: &str) -> bool {
+    let mut rest = word;
+    while !rest.is_empty() {
+        let digits = rest.len().checked_sub(rest.trim_start_matches(|ch: char| ch.is_ascii_digit()).len()).X();
+        let after = &rest[digits..];
+        let letters = after.len().checked_sub(after.trim_start_matches(|ch: char| ch.is_ascii_lowercase()).len()).X();
+        if digits == 0 || !UNITS.iter().any(|(unit, _)| *unit == &after[..letters]) {
+            return false;
+        }
+        rest = &after[letters..];
+    }
+    !word.is_empty()
+}
+
+/// Reads a datetime a byte at a time, making errors in `span`.
+struct Cursor<'a> {
+    text: &'a str,
+    span: &'a ByteSpan,
+    position: usize,
+}
+
+impl Cursor<'_> {
+    fn error(&self, range: ByteSpan, kind: TimeErrorKind) -> TimeError {
+        TimeError {
+            span: self.span.start.checked_add(range.start).X()..self.span.start.checked_add(range.end).X(),
+            kind,
+        }
+    }
+
+    /// An error at the next character, or at the whole text at its end.
+    fn expected(&self, expected: &'static str) -> TimeError {
+        let range = match self.text[self.position..].chars().next() {
+            Some(ch) => self.position..self.position.checked_add(ch.len_utf8()).X(),
+            None => 0..self.text.len(),
+        };
+        self.error(range, TimeErrorKind::Expected { expected })
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.text.as_bytes().get(self.position).copied()
+    }
+
+    fn eat(&mut self, byte: u8, expected: &'static str) -> Result<(), TimeError> {
+        if self.peek() != Some(byte) {
+            return Err(self.expected(expected));
+        }
+        self.position = self.position.checked_add(1).X();
+        Ok(())
+    }
+
+    /// `count` digits, with where they were written.
+    fn digits(&mut self, count: usize, expected: &'static str) -> Result<(u32, ByteSpan), TimeError> {
+        let start = self.position;
+        let mut value: u32 = 0;
+        for _ in 0..count {
+            let digit = self.peek().filter(u8::is_ascii_digit).ok_or_else(|| self.expected(expected))?;
+            value = value.checked_mul(10).X().checked_add(u32::from(digit.checked_sub(b'0').X())).X();
+            self.position = self.position.checked_add(1).X();
+        }
+        Ok((value, start..self.position))
+    }
+
+    /// `count` digits, in `range`, named `field` in errors.
+    fn field(
+        &mut self,
+        count: usize,
+        expected: &'static str,
+        field: &'static str,
+        range: rmx::std::ops::RangeInclusive<u32>,
+    ) -> Result<u32, TimeError> {
+        let (value, at) = self.digits(count, expected)?;
+        if !range.contains(&value) {
+            return Err(self.error(at, TimeErrorKind::OutOfRange { field, value }));
+        }
+        Ok(value)
+    }
+}
+
+/// Parse the datetime written at `span` as `text`.
+pub fn parse_datetime(text: &str, span: &ByteSpan) -> Result<Datetime, TimeError> {
+    let mut cursor = Cursor { text, span, position: 0 };
+    let (year, _) = cursor.digits(4, "4 digits")
+        .and_then(|year| cursor.eat(b'-', "`-`").map(|()| year))
+        .map_err(|_| cursor.error(0..text.len(), TimeErrorKind::NotADatetime))?;
+    let month = cursor.field(2, "2 digits", "month", 1..=12)?;
+    cursor.eat(b'-', "`-`")?;
+    let (day, day_at) = cursor.digits(2, "2 digits")?;
+    let (year, month, day) = (u16::try_from(year).X(), u8::try_from(month).X(), u8::try_from(day).X());
+    if !(1..=days_in_month(year, month)).contains(&day) {
+        return Err(cursor.error(day_at, TimeErrorKind::DayOutOfRange { day, year, month }));
+    }
+
+    let time = match cursor.peek() {
+        None => None,
+        Some(b'T' | b't') => {
+            cursor.position = cursor.position.checked_add(1).X();
+            Some(parse_time(&mut cursor)?)
+        }
+        Some(_) => return Err(cursor.expected("`T`, or the end of the datetime")),
+    };
+    Ok(Datetime { year, month, day, time })
+}
+
+fn parse_time(cursor: &mut Cursor<'_>) -> Result<(Time, Option<Offset>), TimeError> {
+    let hour = cursor.field(2, "2 digits", "hour", 0..=23)?;
+    cursor.eat(b':', "`:`")?;
+    let minute = cursor.field(2, "2 digits", "minute", 0..=59)?;
+    cursor.eat(b':', "`:`")?;
+    let second = cursor.field(2, "2 digits", "second", 0..=60)?;
+
+    let mut nanosecond: u32 = 0;
+    if cursor.peek() == Some(b'.') {
+        cursor.position = cursor.position.checked_add(1).X();
+        let start = cursor.position;
+        while cursor.peek().is_some_and(|byte| byte.is_ascii_digit()) {
+            cursor.position = cursor.position.checked_add(1).X();
+        }
+        let fraction = &cursor.text[start..cursor.position];
+        if fraction.is_empty() {
+            return Err(cursor.expected("digits"));
+        }
+        if fraction.len() > 9 {
+            return Err(cursor.error(start..cursor.position, TimeErrorKind::TooManyFractionalDigits));
+        }
+        nanosecond = format!("{fraction:0<9}").parse().X();
+    }
+
+    let offset = match cursor.peek() {
+        None => None,
+        Some(b'Z' | b'z') => {
+            cursor.position = cursor.position.checked_add(1).X();
+            Some(Offset::Utc)
+        }
+        Some(sign @ (b'+' | b'-')) => {
+            cursor.position = cursor.position.checked_add(1).X();
+            let hours = cursor.field(2, "2 digits", "offset hour", 0..=23)?;
+            cursor.eat(b':', "`:`")?;
+            let minutes = cursor.field(2, "2 digits", "offset minute", 0..=59)?;
+            let minutes = i16::try_from(hours.checked_mul(60).X().checked_add(minutes).X()).X();
+            Some(Offset::Minutes(if sign == b'-' { minutes.checked_neg().X() } else { minutes }))
+        }
+        Some(_) => return Err(cursor.expected("`Z`, an offset like `+05:30`, or the end of the datetime")),
+    };
+    if cursor.peek().is_some() {
+        return Err(cursor.expected("the end of the datetime"));
+    }
+
+    let time = Time {
+        hour: u8::try_from(hour).X(),
+        minute: u8::try_from(minute).X(),
+        second: u8::try_from(second).X(),
+        nanosecond,
+    };
+    Ok((time, offset))
+}
+
+fn days_in_month(year: u16, month: u8) -> u8 {
+    let leap = year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400));
+    match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// Parse the duration written at `span` as `text`.
+pub fn parse_duration(text: &str, span: &ByteSpan) -> Result<Duration, TimeError> {
+    let at = |range: ByteSpan| span.start.checked_add(range.start).X()..span.start.checked_add(range.end).X();
+    if !text.starts_with(|ch: char| ch.is_ascii_digit()) {
+        return Err(TimeError { span: span.C(), kind: TimeErrorKind::NotADuration });
+    }
+    let overflow = || TimeError { span: span.C(), kind: TimeErrorKind::DurationOverflow };
+
+    let mut nanos: u128 = 0;
+    let mut previous: Option<usize> = None;
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        let digits = rest.len().checked_sub(rest.trim_start_matches(|ch: char| ch.is_ascii_digit()).len()).X();
+        let unit_start = position.checked_add(digits).X();
+        let after = &text[unit_start..];
+        let letters = after.len().checked_sub(after.trim_start_matches(|ch: char| ch.is_alphabetic()).len()).X();
+        let unit_end = unit_start.checked_add(letters).X();
+        if digits == 0 {
+            let end = position.checked_add(rest.chars().next().X().len_utf8()).X();
+            return Err(TimeError { span: at(position..end), kind: TimeErrorKind::Expected { expected: "digits" } });
+        }
+        if letters == 0 {
+            let kind = TimeErrorKind::Expected { expected: "a unit, like `s`" };
+            return Err(TimeError { span: at(position..unit_start), kind });
+        }
+        let unit = &text[unit_start..unit_end];
+        let Some(index) = UNITS.iter().position(|(name, _)| *name == unit) else {
+            return Err(TimeError { span: at(unit_start..unit_end), kind: TimeErrorKind::UnknownUnit { unit: S(unit) } });
+        };
+        if let Some(previous) = previous && previous >= index {
+            let kind = TimeErrorKind::UnitOrder { unit: S(unit), previous: S(UNITS[previous].0) };
+            return Err(TimeError { span: at(unit_start..unit_end), kind });
+        }
+        previous = Some(index);
+
+        let value: u128 = text[position..unit_start].parse().map_err(|_| overflow())?;
+        nanos = value.checked_mul(UNITS[index].1)
+            .and_then(|value| nanos.checked_add(value))
+            .ok_or_else(overflow)?;
+        position = unit_end;
+    }
+
+    let secs = u64::try_from(nanos / 1_000_000_000).map_err(|_| overflow())?;
+    Ok(Duration::new(secs, u32::try_from(nanos % 1_000_000_000).X()))
+}
+
+/// A duration as `parse_duration` reads it, like `1h30m`,
+/// with the largest units possible, or `0s`.
+pub fn format_duration(duration: Duration) -> String {
+    let mut nanos = duration.as_nanos();
+    if nanos == 0 {
+        return S("0s");
+    }
+    let mut text = String::new();
+    for (unit, unit_nanos) in UNITS {
+        let count = nanos.checked_div(*unit_nanos).X();
+        if count > 0 {
+            text.push_str(&format!("{count}{unit}"));
+            nanos = nanos.checked_rem(*unit_nanos).X();
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn datetime(text: &str) -> Result<String, TimeError> {
+        parse_datetime(text, &(0..text.len())).map(|datetime| datetime.to_string())
+    }
+
+    fn duration(text: &str) -> Result<Duration, TimeError> {
+        parse_duration(text, &(0..text.len()))
+    }
+
+    fn error(span: ByteSpan, kind: TimeErrorKind) -> TimeError {
+        TimeError { span, kind }
+    }
+
+    #[test]
+    fn test_datetimes() {
+        assert_eq!(datetime("2024-01-01"), Ok(S("2024-01-01")));
+        assert_eq!(datetime("2024-01-01T00:00:00Z"), Ok(S("2024-01-01T00:00:00Z")));
+        assert_eq!(datetime("2024-02-29t08:30:15.250z"), Ok(S("2024-02-29T08:30:15.25Z")));
+        assert_eq!(datetime("1999-12-31T23:59:60-05:30"), Ok(S("1999-12-31T23:59:60-05:30")));
+        assert_eq!(datetime("2024-06-01T12:00:00"), Ok(S("2024-06-01T12:00:00")));
+        assert_eq!(
+            parse_datetime("2024-01-01T00:00:00+01:00", &(0..25)).map(|datetime| datetime.time),
+            Ok(Some((Time { hour: 0, minute: 0, second: 0, nanosecond: 0 }, Some(Offset::Minutes(60))))),
+        );
+    }
+
+    #[test]
+    fn test_datetime_errors() {
+        let out_of_range = |span, field, value| error(span, TimeErrorKind::OutOfRange { field, value });
+        assert_eq!(datetime("2024-13-01"), Err(out_of_range(5..7, "month", 13)));
+        assert_eq!(
+            datetime("2023-02-29"),
+            Err(error(8..10, TimeErrorKind::DayOutOfRange { day: 29, year: 2023, month: 2 })),
+        );
+        assert_eq!(datetime("2024-01-01T24:00:00Z"), Err(out_of_range(11..13, "hour", 24)));
+        assert_eq!(datetime("2024-01-01T00:00:00+24:00"), Err(out_of_range(20..22, "offset hour", 24)));
+        assert_eq!(datetime("2024-01-01T00:00:00.1234567891Z"), Err(error(20..30, TimeErrorKind::TooManyFractionalDigits)));
+        assert_eq!(datetime("2024-01-01T00:00"), Err(error(0..16, TimeErrorKind::Expected { expected: "`:`" })));
+        assert_eq!(datetime("2024-01-01x"), Err(error(10..11, TimeErrorKind::Expected { expected: "`T`, or the end of the datetime" })));
+        assert_eq!(datetime("24-01-01"), Err(error(0..8, TimeErrorKind::NotADatetime)));
+        assert_eq!(
+            datetime("2024-13-01").unwrap_err().to_string(),
+            "month 13 is out of range",
+        );
+    }
+
+    #[test]
+    fn test_durations() {
+        assert_eq!(duration("15m30s"), Ok(Duration::from_secs(930)));
+        assert_eq!(duration("1d2h"), Ok(Duration::from_secs(93_600)));
+        assert_eq!(duration("1s500ms"), Ok(Duration::from_millis(1_500)));
+        assert_eq!(duration("3us7ns"), Ok(Duration::from_nanos(3_007)));
+        assert_eq!(duration("0s"), Ok(Duration::ZERO));
+
+        let order = |span, unit: &str, previous: &str| {
+            error(span, TimeErrorKind::UnitOrder { unit: S(unit), previous: S(previous) })
+        };
+        assert_eq!(duration("30s15m"), Err(order(5..6, "m", "s")));
+        assert_eq!(duration("1s1s"), Err(order(3..4, "s", "s")));
+        assert_eq!(duration("1s1s").unwrap_err().to_string(), "unit `s` is repeated");
+        assert_eq!(duration("5y"), Err(error(1..2, TimeErrorKind::UnknownUnit { unit: S("y") })));
+        assert_eq!(duration("5"), Err(error(0..1, TimeErrorKind::Expected { expected: "a unit, like `s`" })));
+        assert_eq!(duration("m"), Err(error(0..1, TimeErrorKind::NotADuration)));
+        assert_eq!(duration("999999999999999d"), Err(error(0..16, TimeErrorKind::DurationOverflow)));
+    }
+
+    #[test]
+    fn test_shapes() {
+        assert_eq!(datetime_len("2024-01-01T00:00:00Z, b"), Some(20));
+        assert_eq!(datetime_len("2024-01-01T00:00:00-05:00]"), Some(25));
+        assert_eq!(datetime_len("2024-01-01..2024-02-01"), Some(10));
+        assert_eq!(datetime_len("2024-01-01 - 1"), Some(10));
+        assert_eq!(datetime_len("2024-01"), None);
+        assert_eq!(datetime_len("2024-1-01"), None);
+
+        assert!(is_duration("15m30s"));
+        assert!(is_duration("250ms"));
+        assert!(is_duration("30s15m"));
+        assert!(!is_duration("255u8"));
+        assert!(!is_duration("0x1f"));
+        assert!(!is_duration("15"));
+        assert!(!is_duration("ms"));
+        assert!(!is_duration(""));
+
+        assert_eq!(format_duration(Duration::from_secs(930)), "15m30s");
+        assert_eq!(format_duration(Duration::from_millis(90_061_001)), "1d1h1m1s1ms");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+    }
+}
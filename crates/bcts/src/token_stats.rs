@@ -0,0 +1,112 @@
+//! Summary counts of a chunk's tokens.
+//!
+//! For the CLI `stats` command, lint thresholds and editor status bars,
+//! which want a few numbers about a file without walking its tokens.
+
+use rmx::prelude::*;
+
+use rmx::std::collections::BTreeMap;
+
+use crate::bracer::{bracer, TreeToken};
+use crate::dump::kind_name;
+use crate::lexer::ChunkLex;
+use crate::line_index::LineIndex;
+
+#[derive(Clone, Debug, Default, salsa::Update)]
+#[derive(Eq, PartialEq)]
+pub struct TokenStats {
+    /// The number of tokens of each kind, by `dump::kind_name`.
+    /// Kinds with no tokens are left out.
+    pub kinds: BTreeMap<&'static str, usize>,
+    /// The deepest nesting of branches, 0 for none.
+    pub max_depth: usize,
+    /// The number of lines, counted like `LineIndex`,
+    /// so an empty text is one line.
+    pub lines: usize,
+    /// Closes the bracer found mismatched or missing.
+    pub brace_errors: usize,
+    /// Custom literals their validation rejected.
+    pub literal_errors: usize,
+}
+
+impl TokenStats {
+    pub fn tokens(&self) -> usize {
+        self.kinds.values().sum()
+    }
+
+    /// Error tokens, brace errors and literal errors together.
+    pub fn errors(&self) -> usize {
+        let error_tokens = self.kinds.get(kind_name(crate::lexer::TokenKind::Error)).copied().unwrap_or(0);
+        error_tokens
+            .checked_add(self.brace_errors).X()
+            .checked_add(self.literal_errors).X()
+    }
+}
+
+#[salsa::tracked(returns(ref))]
+pub fn token_stats<'db>(
+    db: &'db dyn crate::Db,
+    chunk_lex: ChunkLex<'db>,
+) -> TokenStats {
+    crate::metrics::record(|metrics| metrics.query_executed("token_stats"));
+    let mut kinds = BTreeMap::new();
+    for token in chunk_lex.tokens(db) {
+        let count: &mut usize = kinds.entry(kind_name(token.kind(db))).or_default();
+        *count = count.checked_add(1).X();
+    }
+
+    let bracer = bracer(db, chunk_lex);
+    let mut max_depth = 0;
+    let mut stack = vec![bracer.iter(db)];
+    while let Some(iter) = stack.last_mut() {
+        match iter.next() {
+            Some(TreeToken::Branch(_, branch)) => {
+                stack.push(branch);
+                max_depth = max_depth.max(stack.len().checked_sub(1).X());
+            }
+            Some(TreeToken::Token(_)) => {}
+            None => {
+                stack.pop();
+            }
+        }
+    }
+
+    let text = chunk_lex.chunk(db).text(db).as_str(db);
+    TokenStats {
+        kinds,
+        max_depth,
+        lines: LineIndex::new(text, 1).line_count(),
+        brace_errors: bracer.errors(db).len(),
+        literal_errors: chunk_lex.literal_errors(db).len(),
+    }
+}
+
+#[test]
+fn test_token_stats() {
+    let ref db = crate::Database::default();
+    let stats = |s: &str| {
+        let source = crate::input::Source::new(db, S(s));
+        let chunk = crate::source_map::basic_source_map(db, source);
+        token_stats(db, crate::lexer::lex_chunk(db, chunk)).C()
+    };
+
+    let empty = stats("");
+    assert_eq!(empty, TokenStats { lines: 1, .. TokenStats::default() });
+    assert_eq!((empty.tokens(), empty.errors()), (0, 0));
+
+    let nested = stats("a = {b = [1, (2)]}\n// c\n§\n");
+    assert_eq!(nested.kinds, BTreeMap::from([
+        ("comment", 1),
+        ("error", 1),
+        ("sigil", 9),
+        ("whitespace", 8),
+        ("word", 4),
+    ]));
+    assert_eq!((nested.max_depth, nested.lines), (3, 4));
+    assert_eq!((nested.tokens(), nested.errors()), (23, 1));
+
+    let unbalanced = stats("(a]");
+    assert_eq!(unbalanced.max_depth, 1);
+    assert_eq!(unbalanced.brace_errors, 2);
+    assert_eq!(unbalanced.errors(), 2);
+}
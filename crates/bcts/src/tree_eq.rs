@@ -0,0 +1,75 @@
+//! Comparison of brace trees ignoring trivia.
+//!
+//! Two sources are equal modulo trivia when their brace trees
+//! have the same shape and the same non-trivia tokens,
+//! after dropping all whitespace and comments.
+//! This is the invariant a formatter or codemod must preserve.
+
+use rmx::prelude::*;
+
+use crate::bracer::{basic_bracer, BracerIter, TreeToken};
+use crate::input::Source;
+
+/// Whether `a` and `b` have the same brace tree, ignoring whitespace and comments.
+///
+/// Tokens are compared by kind and text.
+/// Closes inserted by the bracer count like real ones,
+/// so `(a` equals `(a)`.
+#[salsa::tracked]
+pub fn trees_equal_modulo_trivia<'db>(
+    db: &'db dyn crate::Db,
+    a: Source,
+    b: Source,
+) -> bool {
+    iters_equal(db, basic_bracer(db, a).iter(db), basic_bracer(db, b).iter(db))
+}
+
+fn iters_equal<'db>(
+    db: &'db dyn crate::Db,
+    a: BracerIter<'db>,
+    b: BracerIter<'db>,
+) -> bool {
+    let mut a = a.filter_map(|tree_token| tree_token.without_space(db));
+    let mut b = b.filter_map(|tree_token| tree_token.without_space(db));
+    loop {
+        let equal = match (a.next(), b.next()) {
+            (None, None) => return true,
+            (Some(TreeToken::Token(a)), Some(TreeToken::Token(b))) => {
+                a.kind(db) == b.kind(db)
+                    && a.text(db).as_str(db) == b.text(db).as_str(db)
+            }
+            (Some(TreeToken::Branch(a_sigil, a_iter)), Some(TreeToken::Branch(b_sigil, b_iter))) => {
+                a_sigil == b_sigil
+                    && rmx::extras::recurse(|| iters_equal(db, a_iter, b_iter))
+            }
+            _ => false,
+        };
+        if !equal {
+            return false;
+        }
+    }
+}
+
+#[test]
+fn test_trees_equal_modulo_trivia() {
+    fn eq(a: &str, b: &str) -> bool {
+        let ref db = crate::Database::default();
+        let a = Source::new(db, S(a));
+        let b = Source::new(db, S(b));
+        trees_equal_modulo_trivia(db, a, b)
+    }
+
+    assert!(eq("", ""));
+    assert!(eq("", " \n // c\n"));
+    assert!(eq("a b", "a  b"));
+    assert!(eq("f(a, b)", "f( a,\n   b ) // done"));
+    assert!(eq("{ x = 1 }", "{\n  /* one */ x = 1\n}"));
+    assert!(eq("(a", "(a)"));
+
+    assert!(!eq("a", "b"));
+    assert!(!eq("a", "a b"));
+    assert!(!eq("(a)", "[a]"));
+    assert!(!eq("(a) b", "(a b)"));
+    assert!(!eq("\"a b\"", "\"a  b\""));
+    assert!(!eq("ab", "a b"));
+}
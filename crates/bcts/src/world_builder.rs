@@ -0,0 +1,355 @@
+//! Fluent construction of package worlds.
+//!
+//! Building a `PackageWorldMap` by hand takes several levels of
+//! nested `BTreeMap`s. `WorldBuilder` describes the same world
+//! one package and module at a time:
+//!
+//! ```ignore
+//! let world = WorldBuilder::new()
+//!     .package("sys/core")
+//!     .module("core", "import module pkg/u32")
+//!     .import("pkg", "core", "u32")
+//!     .module("u32", "")
+//!     .package("local/app")
+//!     .module("main", "import module sys/core")
+//!     .import("sys", "core", "core");
+//! ```
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+
+use crate::input::Source;
+use crate::loader::RESERVED_NAMES;
+use crate::package2::{Package, PackageModule, PackageName, PackageWorld, ModuleName, Visibility};
+use crate::package2::import_space_durability;
+use crate::package_resolve2::{ImportDemand, ImportDemandMap, ImportSpace, ImportSpaceOverride, PackageWorldMap};
+
+/// Describes a package world to be created in a database.
+///
+/// Modules are added to the most recent package,
+/// and imports and visibility apply to the most recent module.
+#[derive(Clone, Default, Debug)]
+pub struct WorldBuilder {
+    packages: BTreeMap<(ImportSpace, PackageName), BTreeMap<ModuleName, ModuleSpec>>,
+    import_space_aliases: BTreeMap<(ImportSpace, PackageName), BTreeMap<ImportSpace, ImportSpace>>,
+    main_modules: BTreeMap<(ImportSpace, PackageName), ModuleName>,
+    prelude: Vec<ImportDemand>,
+    overrides: Vec<ImportSpaceOverride>,
+    current_package: Option<(ImportSpace, PackageName)>,
+    current_module: Option<ModuleName>,
+}
+
+#[derive(Clone, Debug)]
+struct ModuleSpec {
+    text: String,
+    visibility: Visibility,
+    no_prelude: bool,
+    load_error: Option<String>,
+    imports: Vec<ImportDemand>,
+}
+
+impl WorldBuilder {
+    pub fn new() -> WorldBuilder {
+        WorldBuilder::default()
+    }
+
+    /// Start a package, e.g. `"sys/core"`.
+    ///
+    /// The first path segment is the import space,
+    /// the rest is the package name.
+    pub fn package(mut self, path: &str) -> WorldBuilder {
+        let (import_space, package_name) = path.split_once('/')
+            .expect("package path must be <import_space>/<package>");
+        let key = (S(import_space), S(package_name));
+        self.packages.entry(key.C()).or_default();
+        self.current_package = Some(key);
+        self.current_module = None;
+        self
+    }
+
+    /// Make the current package search `import_space`
+    /// for imports from `alias`, see `Package::import_space_aliases`.
+    ///
+    /// `alias` must not be one of `loader::RESERVED_NAMES`.
+    pub fn import_space_alias(mut self, alias: &str, import_space: &str) -> WorldBuilder {
+        let key = self.current_package.C().expect("import space alias added before any package");
+        assert!(!RESERVED_NAMES.contains(&alias), "import space alias {alias} is reserved");
+        self.import_space_aliases.entry(key).or_default()
+            .insert(S(alias), S(import_space));
+        self
+    }
+
+    /// Set the main module of the current package.
+    pub fn main_module(mut self, name: &str) -> WorldBuilder {
+        let key = self.current_package.C().expect("main module set before any package");
+        self.main_modules.insert(key, S(name));
+        self
+    }
+
+    /// Add a module to the current package.
+    pub fn module(mut self, name: &str, text: &str) -> WorldBuilder {
+        let key = self.current_package.C().expect("module added before any package");
+        self.packages.get_mut(&key).X().insert(S(name), ModuleSpec {
+            text: S(text),
+            visibility: Visibility::Public,
+            no_prelude: false,
+            load_error: None,
+            imports: vec![],
+        });
+        self.current_module = Some(S(name));
+        self
+    }
+
+    /// Replace the text of the current module.
+    pub fn text(mut self, text: &str) -> WorldBuilder {
+        self.current_module_mut().text = S(text);
+        self
+    }
+
+    /// Set the visibility of the current module.
+    pub fn visibility(mut self, visibility: Visibility) -> WorldBuilder {
+        self.current_module_mut().visibility = visibility;
+        self
+    }
+
+    /// Exclude the current module from the world's prelude.
+    pub fn no_prelude(mut self) -> WorldBuilder {
+        self.current_module_mut().no_prelude = true;
+        self
+    }
+
+    /// Mark the current module's file as unreadable,
+    /// see `Source::load_error`.
+    pub fn load_error(mut self, error: &str) -> WorldBuilder {
+        self.current_module_mut().load_error = Some(S(error));
+        self
+    }
+
+    /// Add a module to the world's prelude, imported by every module.
+    pub fn prelude(
+        mut self,
+        import_space: &str,
+        package_alias: &str,
+        module_alias: &str,
+    ) -> WorldBuilder {
+        self.prelude.push((S(import_space), S(package_alias), S(module_alias)));
+        self
+    }
+
+    /// Resolve imports of paths in `overrides` in `import_space`
+    /// where it has them, see `ImportSpaceOverride`.
+    pub fn import_space_override(mut self, import_space: &str, overrides: &str) -> WorldBuilder {
+        self.overrides.push(ImportSpaceOverride {
+            import_space: S(import_space),
+            overrides: S(overrides),
+        });
+        self
+    }
+
+    /// Add an import demand to the current module.
+    pub fn import(
+        mut self,
+        import_space: &str,
+        package_alias: &str,
+        module_alias: &str,
+    ) -> WorldBuilder {
+        self.current_module_mut().imports.push((
+            S(import_space), S(package_alias), S(module_alias),
+        ));
+        self
+    }
+
+    fn current_module_mut(&mut self) -> &mut ModuleSpec {
+        let package_key = self.current_package.as_ref().expect("no current package");
+        let module_name = self.current_module.as_ref().expect("no current module");
+        self.packages.get_mut(package_key).X().get_mut(module_name).X()
+    }
+
+    /// Create the world as a `PackageWorld` input.
+    ///
+    /// Only the `sys` and `local` import spaces are allowed.
+    /// Inputs are given the durability of their import space,
+    /// see `import_space_durability`.
+    pub fn build(&self, db: &dyn crate::Db) -> PackageWorld {
+        let mut pkglib_system = BTreeMap::new();
+        let mut pkglib_local = BTreeMap::new();
+        for (import_space, packages) in self.build_packages(db) {
+            match import_space.as_str() {
+                "sys" => pkglib_system = packages,
+                "local" => pkglib_local = packages,
+                _ => panic!("import space {import_space} is not allowed in a PackageWorld"),
+            }
+        }
+        PackageWorld::builder(pkglib_system, pkglib_local)
+            .pkglib_system_durability(import_space_durability("sys"))
+            .pkglib_local_durability(import_space_durability("local"))
+            .new(db)
+    }
+
+    /// Create the world as a `PackageWorldMap`.
+    ///
+    /// Any import space is allowed.
+    /// `PackageWorldMap` is tracked, so this must be called
+    /// from within a tracked function.
+    pub fn build_map<'db>(&self, db: &'db dyn crate::Db) -> PackageWorldMap<'db> {
+        PackageWorldMap::new(db, self.build_packages(db))
+    }
+
+    /// Create the import demands of every module in `package_world_map`.
+    ///
+    /// `package_world_map` must have been created by `build_map` on this builder.
+    /// Like `build_map`, this must be called from within a tracked function.
+    pub fn build_import_demands<'db>(
+        &self,
+        db: &'db dyn crate::Db,
+        package_world_map: PackageWorldMap<'db>,
+    ) -> ImportDemandMap<'db> {
+        let mut import_demands = BTreeMap::new();
+        for ((import_space, package_name), modules) in &self.packages {
+            let package = package_world_map.map(db)[import_space][package_name];
+            for (module_name, module_spec) in modules {
+                let package_module = package.modules(db)[module_name];
+                import_demands.insert(package_module, module_spec.imports.C());
+            }
+        }
+        ImportDemandMap::new(db, import_demands, self.prelude.C(), self.overrides.C())
+    }
+
+    fn build_packages(
+        &self,
+        db: &dyn crate::Db,
+    ) -> BTreeMap<ImportSpace, BTreeMap<PackageName, Package>> {
+        let mut import_spaces: BTreeMap<ImportSpace, BTreeMap<PackageName, Package>> = BTreeMap::new();
+        for ((import_space, package_name), modules) in &self.packages {
+            let durability = import_space_durability(import_space);
+            let modules = modules.iter().map(|(module_name, module_spec)| {
+                let source = Source::builder(module_spec.text.C())
+                    .load_error(module_spec.load_error.C())
+                    .durability(durability)
+                    .new(db);
+                let package_module = PackageModule::builder(module_name.C(), source)
+                    .visibility(module_spec.visibility)
+                    .no_prelude(module_spec.no_prelude)
+                    .durability(durability)
+                    .new(db);
+                (module_name.C(), package_module)
+            }).collect();
+            let key = (import_space.C(), package_name.C());
+            let import_space_aliases = self.import_space_aliases.get(&key).cloned().unwrap_or_default();
+            let package = Package::builder(package_name.C(), modules)
+                .import_space_aliases(import_space_aliases)
+                .main_module(self.main_modules.get(&key).cloned())
+                .durability(durability)
+                .new(db);
+            import_spaces.entry(import_space.C()).or_default()
+                .insert(package_name.C(), package);
+        }
+        import_spaces
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::package2::package_world_map;
+
+    fn test_builder() -> WorldBuilder {
+        WorldBuilder::new()
+            .package("sys/core")
+            .module("core", "import module pkg/u32")
+            .import("pkg", "core", "u32")
+            .module("u32", "")
+            .visibility(Visibility::Package)
+            .package("local/app")
+            .module("main", "import module sys/core")
+            .import("sys", "core", "core")
+    }
+
+    #[test]
+    fn test_build_world() {
+        let ref db = crate::Database::default();
+        let world = test_builder().build(db);
+
+        let core = world.pkglib_system(db)["core"];
+        assert_eq!(core.name(db), "core");
+        assert_eq!(core.modules(db).len(), 2);
+        let u32_module = core.modules(db)["u32"];
+        assert_eq!(u32_module.visibility(db), Visibility::Package);
+
+        let app = world.pkglib_local(db)["app"];
+        let main = app.modules(db)["main"];
+        assert_eq!(main.text(db).text(db), "import module sys/core");
+
+        let map = package_world_map(db, world);
+        let names: Vec<_> = map.flatten_iter(db)
+            .map(|record| record.package_module.name(db).as_str())
+            .collect();
+        assert_eq!(names, vec!["main", "core", "u32"]);
+    }
+
+    #[salsa::tracked]
+    fn test_import_demands<'db>(db: &'db dyn crate::Db) -> ImportDemandMap<'db> {
+        let builder = test_builder();
+        let map = builder.build_map(db);
+        builder.build_import_demands(db, map)
+    }
+
+    #[test]
+    #[should_panic(expected = "import space alias self is reserved")]
+    fn test_reserved_import_space_alias() {
+        WorldBuilder::new()
+            .package("local/app")
+            .import_space_alias("self", "sys");
+    }
+
+    #[test]
+    fn test_build_import_demands() {
+        let ref db = crate::Database::default();
+        let import_demand_map = test_import_demands(db);
+        let mut demands: Vec<_> = import_demand_map.map(db).iter()
+            .map(|(module, demands)| (module.name(db).as_str(), demands.C()))
+            .collect();
+        demands.sort();
+        assert_eq!(demands, vec![
+            ("core", vec![(S("pkg"), S("core"), S("u32"))]),
+            ("main", vec![(S("sys"), S("core"), S("core"))]),
+            ("u32", vec![]),
+        ]);
+    }
+
+    #[salsa::tracked]
+    fn sys_token_count(db: &dyn crate::Db, world: PackageWorld) -> usize {
+        world.pkglib_system(db).values()
+            .flat_map(|package| package.modules(db).values())
+            .map(|module| {
+                let chunk = crate::source_map::basic_source_map(db, module.text(db));
+                crate::lexer::lex_chunk(db, chunk).tokens(db).len()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_sys_durability_reuse() {
+        use salsa::Setter as _;
+
+        let (mut db, events) = crate::event_recording_database(|event| {
+            match event.kind {
+                salsa::EventKind::DidValidateMemoizedValue { .. } => Some(S("validate")),
+                salsa::EventKind::WillExecute { .. } => Some(S("execute")),
+                _ => None,
+            }
+        });
+        let world = test_builder().build(&db);
+        assert_eq!(sys_token_count(&db, world), 7);
+
+        // Edit user code.
+        let main = world.pkglib_local(&db)["app"].modules(&db)["main"];
+        main.text(&db).set_text(&mut db).to(S("import module sys/alloc"));
+        events.lock().X().clear();
+
+        // Only the outer query is checked; its system package
+        // dependencies are not revalidated one by one.
+        assert_eq!(sys_token_count(&db, world), 7);
+        assert_eq!(*events.lock().X(), vec!["validate"]);
+    }
+}
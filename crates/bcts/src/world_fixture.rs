@@ -0,0 +1,222 @@
+//! Package worlds described by a single text file.
+//!
+//! A fixture lists packages and their modules,
+//! each introduced by a `//@` directive line,
+//! with the module's source text following its header:
+//!
+//! ```text
+//! //@ prelude sys core prelude
+//!
+//! //@ package sys/core
+//! //@ module prelude
+//! //@ no-prelude
+//! //@ module detail
+//! //@ private
+//!
+//! //@ package local/app
+//! //@ main main
+//! //@ alias sys vendor
+//! //@ module main
+//! //@ import sys core core
+//! //@ import self util
+//! import module sys/core
+//! //@ module util
+//! ```
+//!
+//! Package directives apply to the most recent `package`:
+//!
+//! - `//@ package <import space>/<name>` starts a package.
+//! - `//@ main <module>` sets its main module.
+//! - `//@ alias <import space> <import space>` sets an import space alias.
+//!
+//! Module directives apply to the most recent `module`:
+//!
+//! - `//@ module <name>` starts a module in the current package.
+//! - `//@ private` gives it package visibility.
+//! - `//@ no-prelude` excludes it from the prelude.
+//! - `//@ import <import space> <package> <module>` adds an import demand.
+//!   In the `pkg`, `self` and `super` import spaces,
+//!   which have no package, it is `//@ import <import space> <module>`.
+//!
+//! `//@ prelude <import space> <package> <module>` adds to the world's prelude,
+//! and `//@ override <import space> <import space>` makes the first
+//! import space override the second, see `ImportSpaceOverride`.
+//! Both may appear anywhere.
+//!
+//! Other lines are the text of the current module.
+//! Before the first module only blank lines and `//` comments are allowed.
+//! Import demands are not parsed from module text,
+//! so a module's `import` lines and its text are independent.
+
+use rmx::prelude::*;
+
+use crate::package_resolve2::{ImportDemandMap, PackageWorldMap};
+use crate::world_builder::WorldBuilder;
+
+const DIRECTIVE: &str = "//@";
+
+/// The text of a fixture, as a database input.
+#[salsa::input]
+pub struct WorldFixture {
+    #[returns(ref)]
+    pub text: String,
+}
+
+/// The world described by a `WorldFixture`.
+#[salsa::tracked]
+pub struct FixtureWorld<'db> {
+    pub package_world_map: PackageWorldMap<'db>,
+    pub import_demand_map: ImportDemandMap<'db>,
+}
+
+/// Parse a fixture and create its world,
+/// or return the parse error.
+#[salsa::tracked]
+pub fn fixture_world<'db>(
+    db: &'db dyn crate::Db,
+    fixture: WorldFixture,
+) -> Result<FixtureWorld<'db>, String> {
+    let builder = parse_world_fixture(fixture.text(db))
+        .map_err(|e| format!("{e:#}"))?;
+    let package_world_map = builder.build_map(db);
+    let import_demand_map = builder.build_import_demands(db, package_world_map);
+    Ok(FixtureWorld::new(db, package_world_map, import_demand_map))
+}
+
+/// Parse a fixture into a builder for its world.
+pub fn parse_world_fixture(text: &str) -> AnyResult<WorldBuilder> {
+    let mut builder = WorldBuilder::new();
+    let mut in_package = false;
+    // The text of the current module.
+    let mut module: Option<String> = None;
+
+    for (index, line) in text.split_inclusive('\n').enumerate() {
+        let line_number = index.checked_add(1).X();
+        let Some(directive) = line.strip_prefix(DIRECTIVE) else {
+            match &mut module {
+                Some(module_text) => module_text.push_str(line),
+                None => {
+                    let trimmed = line.trim();
+                    if !trimmed.is_empty() && !trimmed.starts_with("//") {
+                        bail!("line {line_number}: text before the first module");
+                    }
+                }
+            }
+            continue;
+        };
+
+        let words: Vec<&str> = directive.split_whitespace().collect();
+        let in_module = module.is_some();
+        builder = match words.as_slice() {
+            ["package", path] => {
+                let Some((import_space, name)) = path.split_once('/') else {
+                    bail!("line {line_number}: package path must be <import space>/<name>");
+                };
+                if import_space.is_empty() || name.is_empty() {
+                    bail!("line {line_number}: package path must be <import space>/<name>");
+                }
+                builder = flush_module(builder, &mut module);
+                in_package = true;
+                builder.package(path)
+            }
+            ["module", name] if in_package => {
+                builder = flush_module(builder, &mut module);
+                module = Some(String::new());
+                builder.module(name, "")
+            }
+            ["main", name] if in_package => builder.main_module(name),
+            ["alias", alias, import_space] if in_package => {
+                builder.import_space_alias(alias, import_space)
+            }
+            ["private"] if in_module => {
+                builder.visibility(crate::package2::Visibility::Package)
+            }
+            ["no-prelude"] if in_module => builder.no_prelude(),
+            ["import", import_space, module_alias] if in_module && is_relative(import_space) => {
+                builder.import(import_space, "", module_alias)
+            }
+            ["import", import_space, package_alias, module_alias] if in_module => {
+                builder.import(import_space, package_alias, module_alias)
+            }
+            ["prelude", import_space, module_alias] if is_relative(import_space) => {
+                builder.prelude(import_space, "", module_alias)
+            }
+            ["prelude", import_space, package_alias, module_alias] => {
+                builder.prelude(import_space, package_alias, module_alias)
+            }
+            ["override", import_space, overrides] => {
+                builder.import_space_override(import_space, overrides)
+            }
+            ["package" | "module" | "main" | "alias" | "private" | "no-prelude" | "import" | "prelude" | "override", ..] => {
+                bail!("line {line_number}: misplaced or malformed directive `{}`", line.trim_end());
+            }
+            _ => bail!("line {line_number}: unknown directive `{}`", line.trim_end()),
+        };
+    }
+
+    Ok(flush_module(builder, &mut module))
+}
+
+// Give the finished module its text.
+fn flush_module(builder: WorldBuilder, module: &mut Option<String>) -> WorldBuilder {
+    match module.take() {
+        Some(text) => builder.text(&text),
+        None => builder,
+    }
+}
+
+fn is_relative(import_space: &str) -> bool {
+    matches!(import_space, "pkg" | "self" | "super")
+}
+
+#[test]
+fn test_parse_world_fixture() {
+    use crate::package2::Visibility;
+
+    let ref db = crate::Database::default();
+    let fixture = WorldFixture::new(db, S("\
+// A comment.
+
+//@ prelude sys core prelude
+//@ package sys/core
+//@ module prelude
+//@ no-prelude
+//@ module detail
+//@ private
+fn detail
+//@ package local/app
+//@ main main
+//@ alias sys vendor
+//@ module main
+//@ import sys core core
+//@ import self util
+import module sys/core
+
+//@ module util
+"));
+    let world = fixture_world(db, fixture).X();
+    let map = world.package_world_map(db).map(db);
+    let core = map["sys"]["core"];
+    let app = map["local"]["app"];
+    assert_eq!(core.modules(db)["detail"].visibility(db), Visibility::Package);
+    assert_eq!(core.modules(db)["detail"].text(db).text(db), "fn detail\n");
+    assert!(core.modules(db)["prelude"].no_prelude(db));
+    assert_eq!(app.main_module(db).as_deref(), Some("main"));
+    assert_eq!(app.import_space_aliases(db)["sys"], "vendor");
+    assert_eq!(app.modules(db)["main"].text(db).text(db), "import module sys/core\n\n");
+    assert_eq!(app.modules(db)["util"].text(db).text(db), "");
+
+    let demands = world.import_demand_map(db);
+    assert_eq!(demands.map(db)[&app.modules(db)["main"]], vec![
+        (S("sys"), S("core"), S("core")),
+        (S("self"), S(""), S("util")),
+    ]);
+    assert_eq!(demands.prelude(db), &vec![(S("sys"), S("core"), S("prelude"))]);
+
+    let error = |text: &str| parse_world_fixture(text).err().X().to_string();
+    assert_eq!(error("text\n"), "line 1: text before the first module");
+    assert_eq!(error("//@ module a\n"), "line 1: misplaced or malformed directive `//@ module a`");
+    assert_eq!(error("//@ package sys/a\n//@ import sys a\n"), "line 2: misplaced or malformed directive `//@ import sys a`");
+    assert_eq!(error("//@ package core\n"), "line 1: package path must be <import space>/<name>");
+    assert_eq!(error("//@ frobnicate\n"), "line 1: unknown directive `//@ frobnicate`");
+}
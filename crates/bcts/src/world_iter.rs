@@ -0,0 +1,182 @@
+//! Iteration over every module of a package world.
+//!
+//! Both resolvers hold a world as packages by import space and name,
+//! each with its modules by name.
+//! `PackageWorldIter` flattens that into a `PackageWorldRecord` per module,
+//! lazily, from either end.
+
+use rmx::prelude::*;
+use rmx::std::collections::BTreeMap;
+use rmx::std::collections::btree_map;
+use rmx::std::iter::Flatten;
+
+/// A package of a world, `package::Package` or `package2::Package`.
+pub trait WorldPackage: Copy {
+    type Module: Copy;
+
+    fn world_modules<'db>(self, db: &'db dyn crate::Db) -> &'db BTreeMap<String, Self::Module>;
+}
+
+impl WorldPackage for crate::package::Package {
+    type Module = crate::package::PackageModule;
+
+    fn world_modules<'db>(self, db: &'db dyn crate::Db) -> &'db BTreeMap<String, Self::Module> {
+        self.modules(db)
+    }
+}
+
+impl WorldPackage for crate::package2::Package {
+    type Module = crate::package2::PackageModule;
+
+    fn world_modules<'db>(self, db: &'db dyn crate::Db) -> &'db BTreeMap<String, Self::Module> {
+        self.modules(db)
+    }
+}
+
+#[derive(Copy, Clone)]
+pub struct PackageWorldRecord<'db, P: WorldPackage> {
+    pub import_space: &'db str,
+    pub package_name: &'db str,
+    pub package: P,
+    pub package_module: P::Module,
+}
+
+/// Iterator over every module in a world.
+///
+/// Created by each resolver's `PackageWorldMap::flatten_iter`.
+#[derive(Clone)]
+pub struct PackageWorldIter<'db, P: WorldPackage> {
+    inner: Flatten<ImportSpaceIter<'db, P>>,
+}
+
+impl<'db, P: WorldPackage> PackageWorldIter<'db, P> {
+    pub fn new(
+        db: &'db dyn crate::Db,
+        import_spaces: &'db BTreeMap<String, BTreeMap<String, P>>,
+    ) -> PackageWorldIter<'db, P> {
+        PackageWorldIter {
+            inner: ImportSpaceIter {
+                db,
+                import_spaces: import_spaces.iter(),
+            }.flatten(),
+        }
+    }
+}
+
+impl<'db, P: WorldPackage> Iterator for PackageWorldIter<'db, P> {
+    type Item = PackageWorldRecord<'db, P>;
+
+    fn next(&mut self) -> Option<PackageWorldRecord<'db, P>> {
+        self.inner.next()
+    }
+}
+
+impl<'db, P: WorldPackage> DoubleEndedIterator for PackageWorldIter<'db, P> {
+    fn next_back(&mut self) -> Option<PackageWorldRecord<'db, P>> {
+        self.inner.next_back()
+    }
+}
+
+// Yields the packages of each import space.
+#[derive(Clone)]
+struct ImportSpaceIter<'db, P> {
+    db: &'db dyn crate::Db,
+    import_spaces: btree_map::Iter<'db, String, BTreeMap<String, P>>,
+}
+
+impl<'db, P: WorldPackage> ImportSpaceIter<'db, P> {
+    fn packages(
+        &self,
+        (import_space, packages): (&'db String, &'db BTreeMap<String, P>),
+    ) -> Flatten<PackageIter<'db, P>> {
+        PackageIter {
+            db: self.db,
+            import_space,
+            packages: packages.iter(),
+        }.flatten()
+    }
+}
+
+impl<'db, P: WorldPackage> Iterator for ImportSpaceIter<'db, P> {
+    type Item = Flatten<PackageIter<'db, P>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.import_spaces.next().map(|entry| self.packages(entry))
+    }
+}
+
+impl<'db, P: WorldPackage> DoubleEndedIterator for ImportSpaceIter<'db, P> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.import_spaces.next_back().map(|entry| self.packages(entry))
+    }
+}
+
+// Yields the modules of each package in one import space.
+#[derive(Clone)]
+struct PackageIter<'db, P> {
+    db: &'db dyn crate::Db,
+    import_space: &'db str,
+    packages: btree_map::Iter<'db, String, P>,
+}
+
+impl<'db, P: WorldPackage> PackageIter<'db, P> {
+    fn modules(
+        &self,
+        (package_name, package): (&'db String, &'db P),
+    ) -> PackageModuleIter<'db, P> {
+        PackageModuleIter {
+            import_space: self.import_space,
+            package_name,
+            package: *package,
+            modules: package.world_modules(self.db).iter(),
+        }
+    }
+}
+
+impl<'db, P: WorldPackage> Iterator for PackageIter<'db, P> {
+    type Item = PackageModuleIter<'db, P>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.packages.next().map(|entry| self.modules(entry))
+    }
+}
+
+impl<'db, P: WorldPackage> DoubleEndedIterator for PackageIter<'db, P> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.packages.next_back().map(|entry| self.modules(entry))
+    }
+}
+
+// Yields the records of one package.
+#[derive(Clone)]
+struct PackageModuleIter<'db, P: WorldPackage> {
+    import_space: &'db str,
+    package_name: &'db str,
+    package: P,
+    modules: btree_map::Iter<'db, String, P::Module>,
+}
+
+impl<'db, P: WorldPackage> PackageModuleIter<'db, P> {
+    fn record(&self, package_module: &P::Module) -> PackageWorldRecord<'db, P> {
+        PackageWorldRecord {
+            import_space: self.import_space,
+            package_name: self.package_name,
+            package: self.package,
+            package_module: *package_module,
+        }
+    }
+}
+
+impl<'db, P: WorldPackage> Iterator for PackageModuleIter<'db, P> {
+    type Item = PackageWorldRecord<'db, P>;
+
+    fn next(&mut self) -> Option<PackageWorldRecord<'db, P>> {
+        self.modules.next().map(|(_, package_module)| self.record(package_module))
+    }
+}
+
+impl<'db, P: WorldPackage> DoubleEndedIterator for PackageModuleIter<'db, P> {
+    fn next_back(&mut self) -> Option<PackageWorldRecord<'db, P>> {
+        self.modules.next_back().map(|(_, package_module)| self.record(package_module))
+    }
+}
@@ -0,0 +1,62 @@
+//! Formats every `.bct` file in the test corpus, checking that
+//! formatting is idempotent and preserves the brace tree.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use bcts::project_config::FormatOptions;
+
+fn corpus() -> Vec<PathBuf> {
+    let tests_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut paths = vec![];
+    for dir in ["lang", "snapshots"] {
+        for entry in fs::read_dir(tests_dir.join(dir)).expect("read corpus dir") {
+            let path = entry.expect("dir entry").path();
+            if path.extension().is_some_and(|extension| extension == "bct") {
+                paths.push(path);
+            }
+        }
+    }
+    paths.sort();
+    paths
+}
+
+#[test]
+fn format_is_idempotent() {
+    let paths = corpus();
+    assert!(!paths.is_empty());
+
+    let mut failures = vec![];
+    let options = [
+        FormatOptions::default(),
+        FormatOptions { indent_width: 2, ..FormatOptions::default() },
+        FormatOptions { max_width: 20, wrap_comments: true, ..FormatOptions::default() },
+    ];
+    for options in options {
+        for path in &paths {
+            let db = bcts::Database::default();
+            let text = fs::read_to_string(path).expect("read corpus file");
+            let source = bcts::input::Source::new(&db, text);
+            let once = bcts::format::format_source(&db, source, &options);
+            let formatted = bcts::input::Source::new(&db, once.clone());
+            let twice = bcts::format::format_source(&db, formatted, &options);
+
+            if twice != once {
+                failures.push(format!(
+                    "{} (indent_width {}): not idempotent\n--- once\n{once}--- twice\n{twice}",
+                    path.display(), options.indent_width,
+                ));
+            }
+            if !bcts::tree_eq::trees_equal_modulo_trivia(&db, source, formatted) {
+                failures.push(format!(
+                    "{} (indent_width {}): formatting changed the brace tree\n{once}",
+                    path.display(), options.indent_width,
+                ));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("{} format failure(s):\n\n{}", failures.len(), failures.join("\n\n"));
+    }
+}
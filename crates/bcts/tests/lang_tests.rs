@@ -0,0 +1,35 @@
+//! Runs the `bcts test` blocks in `tests/lang/*.bct`.
+//!
+//! See `bcts::test_runner` for the test block format.
+
+use std::fs;
+use std::path::Path;
+
+#[test]
+fn lang_tests() {
+    let lang_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/lang");
+    let mut paths: Vec<_> = fs::read_dir(&lang_dir).expect("read tests/lang")
+        .map(|entry| entry.expect("dir entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bct"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let mut failures = vec![];
+    for path in paths {
+        let text = fs::read_to_string(&path).expect("read test file");
+        let outcomes = bcts::test_runner::run_test_file(&text)
+            .unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+        for outcome in outcomes.into_iter().filter(|outcome| !outcome.passed()) {
+            failures.push(format!(
+                "{}:{} {}\n{}",
+                path.display(), outcome.line, outcome.name,
+                outcome.failures.join("\n"),
+            ));
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("{} lang test(s) failed:\n\n{}", failures.len(), failures.join("\n\n"));
+    }
+}
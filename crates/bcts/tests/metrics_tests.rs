@@ -0,0 +1,60 @@
+//! Metrics are global to the process,
+//! so they are tested in their own test binary.
+
+use std::sync::{Arc, Mutex};
+
+use bcts::diagnostics::Code;
+use bcts::metrics::{Metrics, set_metrics};
+
+#[derive(Default)]
+struct Recorded {
+    queries: Vec<&'static str>,
+    tokens: usize,
+    diagnostics: Vec<Code>,
+}
+
+#[derive(Default)]
+struct TestMetrics(Mutex<Recorded>);
+
+impl Metrics for TestMetrics {
+    fn query_executed(&self, query: &'static str) {
+        self.0.lock().unwrap().queries.push(query);
+    }
+
+    fn tokens_lexed(&self, count: usize) {
+        let mut recorded = self.0.lock().unwrap();
+        recorded.tokens = recorded.tokens.checked_add(count).unwrap();
+    }
+
+    fn diagnostic_emitted(&self, code: Code) {
+        self.0.lock().unwrap().diagnostics.push(code);
+    }
+}
+
+#[test]
+fn metrics_hooks() {
+    let metrics = Arc::new(TestMetrics::default());
+    set_metrics(Some(metrics.clone()));
+
+    let db = bcts::Database::default();
+    let source = bcts::input::Source::new(&db, "(a b".to_string());
+    let bracer = bcts::bracer::basic_bracer(&db, source);
+    bcts::diagnostics::bracer_diagnostics(&db, bracer);
+
+    {
+        let recorded = metrics.0.lock().unwrap();
+        assert_eq!(recorded.queries, vec!["source_map", "lex", "bracer"]);
+        assert_eq!(recorded.tokens, 4);
+        assert_eq!(recorded.diagnostics, vec![Code::UnclosedBrace]);
+    }
+
+    // Cached queries report nothing.
+    let chunk = bcts::source_map::basic_source_map(&db, source);
+    bcts::bracer::bracer(&db, bcts::lexer::lex_chunk(&db, chunk));
+    assert_eq!(metrics.0.lock().unwrap().queries.len(), 3);
+
+    set_metrics(None);
+    let source = bcts::input::Source::new(&db, "x".to_string());
+    bcts::source_map::basic_source_map(&db, source);
+    assert_eq!(metrics.0.lock().unwrap().queries.len(), 3);
+}
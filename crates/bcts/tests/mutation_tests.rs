@@ -0,0 +1,71 @@
+//! Panic-regression tests over mutated sources.
+//!
+//! Every source in `tests/snapshots` is broken with each
+//! `bcts::mutate::Mutation` for a range of seeds,
+//! and the broken sources are run through the passes.
+//! A panic is reported with the mutation and seed that made it,
+//! to be added to `bracer_panic_tests.rs` once fixed.
+
+use std::fs;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::Path;
+
+/// Seeds tried per source and mutation.
+const SEEDS: u64 = 32;
+
+/// Run `text` through the passes, returning the panic message if any.
+fn run_passes(text: &str) -> Result<(), String> {
+    let result = catch_unwind(AssertUnwindSafe(|| {
+        let db = bcts::Database::default();
+        let source = bcts::input::Source::new(&db, text.to_string());
+        let bracer = bcts::bracer::basic_bracer(&db, source);
+        let _tokens: Vec<_> = bracer.iter(&db).collect();
+        for line in bracer.iter(&db).lines() {
+            let _tokens: Vec<_> = line.collect();
+        }
+        bcts::diagnostics::bracer_diagnostics(&db, bracer);
+        let edits = bcts::fixes::bracer_fixes(&db, bracer);
+        bcts::edit::apply_edits(text, &edits).expect("bracer fixes overlap");
+        let options = bcts::project_config::FormatOptions::default();
+        bcts::format::format_source(&db, source, &options);
+    }));
+
+    result.map_err(|e| {
+        if let Some(s) = e.downcast_ref::<&str>() {
+            s.to_string()
+        } else if let Some(s) = e.downcast_ref::<String>() {
+            s.clone()
+        } else {
+            "unknown panic".to_string()
+        }
+    })
+}
+
+#[test]
+fn test_mutated_snapshots() {
+    let snapshot_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots");
+    let mut paths: Vec<_> = fs::read_dir(&snapshot_dir).expect("read tests/snapshots")
+        .map(|entry| entry.expect("dir entry").path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "bct"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty());
+
+    let db = bcts::Database::default();
+    let mut failures = vec![];
+    for path in paths {
+        let text = fs::read_to_string(&path).expect("read source");
+        for mutant in bcts::mutate::mutants(&db, &text, 0..SEEDS) {
+            if let Err(message) = run_passes(&mutant.text) {
+                failures.push(format!(
+                    "{} seed {} {}: {message}\n{:?}",
+                    path.display(), mutant.seed, mutant.mutation.name(), mutant.text,
+                ));
+            }
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("{} mutant(s) panicked:\n\n{}", failures.len(), failures.join("\n\n"));
+    }
+}
@@ -0,0 +1,92 @@
+//! Golden tests for package resolution.
+//!
+//! Each `tests/resolver/<name>.world` fixture, in the format of
+//! `bcts::world_fixture`, is resolved and its `dump::resolve_dump`
+//! is compared against `<name>.snap`.
+//!
+//! To accept new or changed output run
+//!
+//!     UPDATE_EXPECT=1 cargo test --test resolver_tests
+//!
+//! and review the changes to the `.snap` files.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn fixture_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/resolver")
+}
+
+fn update_expect() -> bool {
+    std::env::var_os("UPDATE_EXPECT").is_some()
+}
+
+fn render(fixture_path: &Path, fixture_text: &str) -> String {
+    let db = bcts::Database::default();
+    let fixture = bcts::world_fixture::WorldFixture::new(&db, fixture_text.to_string());
+    let world = match bcts::world_fixture::fixture_world(&db, fixture) {
+        Ok(world) => world,
+        Err(e) => panic!("{}: {e}", fixture_path.display()),
+    };
+    let package_world_map = world.package_world_map(&db);
+    let graph = bcts::package_resolve2::package_world_graph(
+        &db,
+        package_world_map,
+        world.import_demand_map(&db),
+    );
+    bcts::dump::resolve_dump(&db, package_world_map, graph)
+}
+
+/// Compare `actual` against the golden file, or write it when updating.
+///
+/// Returns a description of the mismatch, if any.
+fn check_snapshot(snap_path: &Path, actual: &str) -> Option<String> {
+    if update_expect() {
+        fs::write(snap_path, actual).expect("write snapshot");
+        return None;
+    }
+
+    let expected = match fs::read_to_string(snap_path) {
+        Ok(expected) => expected,
+        Err(_) => {
+            return Some(format!(
+                "{}: missing snapshot, run with UPDATE_EXPECT=1 to create it\n{actual}",
+                snap_path.display(),
+            ));
+        }
+    };
+
+    if expected == actual {
+        return None;
+    }
+
+    Some(format!(
+        "{}: snapshot mismatch\n--- expected\n{expected}--- actual\n{actual}",
+        snap_path.display(),
+    ))
+}
+
+#[test]
+fn resolver_snapshots() {
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(fixture_dir())
+        .expect("fixture dir")
+        .map(|entry| entry.expect("dir entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "world"))
+        .collect();
+    fixtures.sort();
+    assert!(!fixtures.is_empty());
+
+    let mut failures = vec![];
+    for fixture_path in &fixtures {
+        let fixture_text = fs::read_to_string(fixture_path).expect("read fixture");
+        let actual = render(fixture_path, &fixture_text);
+        let snap_path = fixture_path.with_extension("snap");
+        if let Some(failure) = check_snapshot(&snap_path, &actual) {
+            failures.push(failure);
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("{} snapshot(s) failed\n\n{}", failures.len(), failures.join("\n"));
+    }
+}
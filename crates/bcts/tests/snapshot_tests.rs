@@ -0,0 +1,119 @@
+//! Golden tests for the lexer, bracer and lines passes.
+//!
+//! Each `tests/snapshots/<name>.bct` source is run through the
+//! pipeline and its dumps are compared against `<name>.snap`.
+//! A `<name>.toml` beside the source is its project config,
+//! for the sections the source map reads.
+//!
+//! To accept new or changed output run
+//!
+//!     UPDATE_EXPECT=1 cargo test --test snapshot_tests
+//!
+//! and review the changes to the `.snap` files.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+fn snapshot_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/snapshots")
+}
+
+fn update_expect() -> bool {
+    std::env::var_os("UPDATE_EXPECT").is_some()
+}
+
+fn render(source_text: &str, config_text: Option<&str>) -> String {
+    let db = bcts::Database::default();
+    let config_file = match config_text {
+        Some(config_text) => bcts::project_config::ConfigFile::parse(config_text).expect("parse config"),
+        None => bcts::project_config::ConfigFile::default(),
+    };
+    let project_config = bcts::project_config::ProjectConfig::from_file(&db, config_file);
+    let source_map_config = bcts::project_config::project_source_map_config(&db, project_config);
+    let source = bcts::input::Source::new(&db, source_text.to_string());
+    let chunk = bcts::source_map::source_map(&db, source, source_map_config);
+    let chunk_lex = bcts::lexer::lex_chunk(&db, chunk);
+    let bracer = bcts::bracer::bracer(&db, chunk_lex);
+
+    let sections = [
+        ("lex", bcts::dump::lex_dump(&db, chunk_lex)),
+        ("tree", bcts::dump::tree_dump(&db, bracer)),
+        ("lines", bcts::dump::lines_dump(&db, bracer)),
+        ("diagnostics", bcts::dump::diagnostics_dump(&db, bracer)),
+    ];
+
+    let mut buf = String::new();
+    for (name, dump) in sections {
+        buf.push_str(&format!("--- {name}\n"));
+        buf.push_str(&dump);
+    }
+    buf
+}
+
+/// Compare `actual` against the golden file, or write it when updating.
+///
+/// Returns a description of the mismatch, if any.
+fn check_snapshot(snap_path: &Path, actual: &str) -> Option<String> {
+    if update_expect() {
+        fs::write(snap_path, actual).expect("write snapshot");
+        return None;
+    }
+
+    let expected = match fs::read_to_string(snap_path) {
+        Ok(expected) => expected,
+        Err(_) => {
+            return Some(format!(
+                "{}: missing snapshot, run with UPDATE_EXPECT=1 to create it\n{actual}",
+                snap_path.display(),
+            ));
+        }
+    };
+
+    if expected == actual {
+        return None;
+    }
+
+    let mut msg = format!("{}: snapshot mismatch\n", snap_path.display());
+    let expected_lines: Vec<_> = expected.lines().collect();
+    let actual_lines: Vec<_> = actual.lines().collect();
+    let len = expected_lines.len().max(actual_lines.len());
+    for i in 0..len {
+        let e = expected_lines.get(i);
+        let a = actual_lines.get(i);
+        if e != a {
+            msg.push_str(&format!(
+                "  line {}:\n    expected: {}\n    actual:   {}\n",
+                i.checked_add(1).expect("line number"),
+                e.unwrap_or(&"<none>"),
+                a.unwrap_or(&"<none>"),
+            ));
+        }
+    }
+    Some(msg)
+}
+
+#[test]
+fn snapshots() {
+    let mut sources: Vec<PathBuf> = fs::read_dir(snapshot_dir())
+        .expect("snapshot dir")
+        .map(|entry| entry.expect("dir entry").path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bct"))
+        .collect();
+    sources.sort();
+    assert!(!sources.is_empty());
+
+    let mut failures = vec![];
+    for source_path in &sources {
+        let source_text = fs::read_to_string(source_path).expect("read source");
+        let config_text = fs::read_to_string(source_path.with_extension("toml")).ok();
+        let actual = render(&source_text, config_text.as_deref());
+        let snap_path = source_path.with_extension("snap");
+        if let Some(failure) = check_snapshot(&snap_path, &actual) {
+            failures.push(failure);
+        }
+    }
+
+    if !failures.is_empty() {
+        panic!("{} snapshot(s) failed\n\n{}", failures.len(), failures.join("\n"));
+    }
+}